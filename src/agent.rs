@@ -0,0 +1,219 @@
+//! Scans spread across machines: agents hash their own files and send them
+//! to a coordinator, which finds the duplicates among all of them.
+//!
+//! An agent indexes its directories as `index create` does and streams the
+//! [index](crate::index), one `(path, size, hash)` record per line, over a
+//! TCP connection to the coordinator, then shuts down its side of the
+//! connection. The coordinator answers with one line, `ok` or `error: `
+//! and the reason, once it has read and checked the whole index. When
+//! every agent expected has sent its index, the coordinator merges them,
+//! with any directories of its own, into one report, in which the files of
+//! each agent are labelled with its host name.
+//!
+//! There is no authentication, so the coordinator should only listen where
+//! every agent is trusted. No file contents cross the network, only their
+//! hashes.
+
+use crate::cache::algo_name;
+use crate::index::{parse_index, Index};
+use crate::{is_cancelled, HashAlgo};
+use log::{debug, info, warn};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+/// How long an agent may pause while sending its index, and the
+/// coordinator while checking it, before the other gives up.
+const READ_TIMEOUT: Duration = Duration::from_mins(1);
+/// How often the coordinator checks for an interruption while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Sends `index` to the coordinator listening at `coordinator`, returning
+/// once it has accepted it.
+///
+/// # Errors
+/// Returns an error if the coordinator cannot be reached, the connection
+/// fails, or the coordinator refuses the index, in which case the error
+/// holds its reason.
+pub fn send_index(coordinator: impl ToSocketAddrs, index: &Index) -> io::Result<()> {
+    let stream = TcpStream::connect(coordinator)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut writer = BufWriter::new(&stream);
+    index.write_to(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    stream.shutdown(Shutdown::Write)?;
+    let mut answer = String::new();
+    BufReader::new(&stream).read_line(&mut answer)?;
+    match answer.trim_end() {
+        "ok" => Ok(()),
+        "" => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the coordinator closed the connection",
+        )),
+        answer => Err(io::Error::other(format!(
+            "the coordinator refused the index: {}",
+            answer.strip_prefix("error: ").unwrap_or(answer)
+        ))),
+    }
+}
+
+/// Waits on `listener` for `agents` indexes hashed with `hash_algo`,
+/// calling `received` with each one accepted, and returns them.
+///
+/// Agents are answered one at a time. An index that cannot be read, or
+/// was hashed with another algorithm, is refused and does not count. If
+/// `cancel` is set while waiting, the indexes received so far are returned.
+///
+/// # Errors
+/// Returns an error if accepting connections fails.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::agent::{receive_indexes, send_index};
+/// use duplicate_file_finder::index::create_index;
+/// use duplicate_file_finder::{HashAlgo, ScanOptions};
+/// use std::net::TcpListener;
+/// use tempfile::tempdir;
+///
+/// let dir = tempdir().unwrap();
+/// std::fs::write(dir.path().join("a.txt"), b"hello").unwrap();
+/// let index = create_index(&[dir.path().to_path_buf()], &ScanOptions::new().quiet(true)).unwrap();
+///
+/// let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+/// let addr = listener.local_addr().unwrap();
+/// let agent = std::thread::spawn(move || send_index(addr, &index));
+/// let indexes = receive_indexes(&listener, 1, HashAlgo::Sha256, None, |_| {}).unwrap();
+/// agent.join().unwrap().unwrap();
+/// assert_eq!(indexes[0].entries.len(), 1);
+/// ```
+pub fn receive_indexes(
+    listener: &TcpListener,
+    agents: usize,
+    hash_algo: HashAlgo,
+    cancel: Option<&AtomicBool>,
+    mut received: impl FnMut(&Index),
+) -> io::Result<Vec<Index>> {
+    // Polled, so an interruption is noticed between connections.
+    listener.set_nonblocking(true)?;
+    let mut indexes = Vec::with_capacity(agents);
+    while indexes.len() < agents && !is_cancelled(cancel) {
+        let (stream, peer) = match listener.accept() {
+            Ok(accepted) => accepted,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        debug!("Agent connected from {peer}");
+        match receive_index(&stream, hash_algo) {
+            Ok(index) => {
+                info!(
+                    "Received index of {} files from {} ({peer})",
+                    index.entries.len(),
+                    index.host
+                );
+                answer(&stream, "ok");
+                received(&index);
+                indexes.push(index);
+            }
+            Err(e) => {
+                warn!("Refused index from {peer}: {e}");
+                answer(&stream, &format!("error: {e}"));
+            }
+        }
+    }
+    listener.set_nonblocking(false)?;
+    Ok(indexes)
+}
+
+/// Reads and checks the index an agent sends on `stream`.
+fn receive_index(mut stream: &TcpStream, hash_algo: HashAlgo) -> io::Result<Index> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut text = String::new();
+    stream
+        .read_to_string(&mut text)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::InvalidData => io::Error::new(e.kind(), "not an index"),
+            _ => e,
+        })?;
+    let index = parse_index(&text)?;
+    if index.hash_algo != hash_algo {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "hashed with {}, but the coordinator uses {}",
+                algo_name(index.hash_algo),
+                algo_name(hash_algo)
+            ),
+        ));
+    }
+    Ok(index)
+}
+
+/// Writes the coordinator's one-line answer to an agent, which may have
+/// gone already.
+fn answer(mut stream: &TcpStream, line: &str) {
+    if let Err(e) = writeln!(stream, "{line}") {
+        debug!("Failed to answer an agent: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexEntry;
+    use chrono::Local;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_refuses_indexes_it_cannot_merge() {
+        let index = |host: &str, hash_algo| Index {
+            host: host.to_string(),
+            created: Local::now(),
+            hash_algo,
+            roots: vec![PathBuf::from("/data")],
+            entries: vec![IndexEntry {
+                path: PathBuf::from("/data/a.txt"),
+                size: 5,
+                hash: "ab".to_string(),
+            }],
+            errors: Vec::new(),
+            interrupted: false,
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let addr = listener.local_addr().expect("address");
+        let agents = std::thread::spawn(move || {
+            let refused = send_index(addr, &index("old", HashAlgo::Md5));
+            let mut garbage = TcpStream::connect(addr).expect("connect");
+            garbage.write_all(b"not an index\n").expect("write");
+            garbage.shutdown(Shutdown::Write).expect("shut down");
+            let mut answer = String::new();
+            garbage.read_to_string(&mut answer).expect("read answer");
+            (
+                refused,
+                answer,
+                send_index(addr, &index("nas", HashAlgo::Sha256)),
+            )
+        });
+        let mut hosts = Vec::new();
+        let indexes = receive_indexes(&listener, 1, HashAlgo::Sha256, None, |index| {
+            hosts.push(index.host.clone());
+        })
+        .expect("receive");
+        let (refused, answer, accepted) = agents.join().expect("agents");
+        let refused = refused.expect_err("other algorithm");
+        assert!(
+            refused.to_string().contains("coordinator uses"),
+            "{refused}"
+        );
+        assert_eq!(answer, "error: not an index\n");
+        accepted.expect("accepted");
+        assert_eq!(hosts, ["nas"]);
+        assert_eq!(indexes[0].entries[0].path, PathBuf::from("/data/a.txt"));
+    }
+}
@@ -0,0 +1,512 @@
+//! Reading the files inside zip, tar and gzip-compressed tar archives, for
+//! [`ScanOptions::scan_archives`](crate::ScanOptions::scan_archives).
+//!
+//! A file inside an archive is named by the archive's path, then `!/`, then
+//! its path within the archive, as in `photos.zip!/2019/beach.jpg`. Only
+//! regular files are read: directories, links and nested archives are not
+//! descended into. Zip entries must be stored or deflated, and not
+//! encrypted.
+
+use crate::inflate::Inflate;
+use crate::HashAlgo;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// What separates an archive's path from the path of a file inside it.
+pub(crate) const SEPARATOR: &str = "!/";
+
+/// How a file inside an archive is stored.
+enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+fn format(path: &Path) -> Option<Format> {
+    let is =
+        |ext: Option<&OsStr>, name: &str| ext.is_some_and(|ext| ext.eq_ignore_ascii_case(name));
+    let ext = path.extension();
+    if is(ext, "zip") {
+        Some(Format::Zip)
+    } else if is(ext, "tar") {
+        Some(Format::Tar)
+    } else if is(ext, "tgz")
+        || (is(ext, "gz") && is(path.file_stem().map(Path::new)?.extension(), "tar"))
+    {
+        Some(Format::TarGz)
+    } else {
+        None
+    }
+}
+
+/// Returns `true` if `path` names a zip, tar or `.tar.gz` archive.
+pub(crate) fn is_archive(path: &Path) -> bool {
+    format(path).is_some()
+}
+
+fn invalid(format: &str, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid {format} archive: {message}"),
+    )
+}
+
+/// Returns the path naming the file at `inner` inside `archive`.
+fn entry_path(archive: &Path, inner: &str) -> PathBuf {
+    let mut path = OsString::from(archive);
+    path.push(SEPARATOR);
+    path.push(inner.trim_start_matches("./").trim_start_matches('/'));
+    PathBuf::from(path)
+}
+
+/// Hashes every file inside the archive at `path` with `algo`, passing the
+/// path of each, with its size and hash or the error that kept it from
+/// being read, to `on_entry`.
+///
+/// # Errors
+/// Returns an error if the archive cannot be read, or if a tar archive is
+/// cut short; the files before the fault have been passed on by then.
+pub(crate) fn hash_entries(
+    path: &Path,
+    algo: HashAlgo,
+    on_entry: &mut dyn FnMut(PathBuf, io::Result<(u64, String)>),
+) -> io::Result<()> {
+    let file = File::open(path)?;
+    match format(path) {
+        Some(Format::Zip) => zip_entries(BufReader::new(file), path, algo, on_entry),
+        Some(Format::Tar) => tar_entries(BufReader::new(file), path, algo, on_entry),
+        Some(Format::TarGz) => tar_entries(Inflate::gzip(file)?, path, algo, on_entry),
+        None => Ok(()),
+    }
+}
+
+/// Hashes the next `len` bytes of `reader`.
+fn hash_exactly(reader: &mut impl Read, len: u64, algo: HashAlgo) -> io::Result<String> {
+    let mut hasher = algo.hasher();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut left = len;
+    while left > 0 {
+        let n = usize::try_from(left).map_or(buffer.len(), |left| left.min(buffer.len()));
+        reader.read_exact(&mut buffer[..n])?;
+        hasher.update(&buffer[..n]);
+        left -= n as u64;
+    }
+    Ok(hasher.finish_hex())
+}
+
+/// Reads and discards the next `len` bytes of `reader`.
+fn skip(reader: &mut impl Read, len: u64) -> io::Result<()> {
+    if io::copy(&mut reader.take(len), &mut io::sink())? < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+/// Reads the next tar block, returning `false` at the end of the stream.
+fn read_block(reader: &mut impl Read, block: &mut [u8; 512]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < block.len() {
+        match reader.read(&mut block[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// Reads a number from a tar header field: octal digits, or big-endian
+/// binary after a set high bit for sizes that octal cannot hold.
+fn tar_number(field: &[u8]) -> io::Result<u64> {
+    if field.first().is_some_and(|&b| b & 0x80 != 0) {
+        return Ok(field[1..]
+            .iter()
+            .fold(u64::from(field[0] & 0x7F), |n, &b| n << 8 | u64::from(b)));
+    }
+    let digits = String::from_utf8_lossy(field);
+    let digits = digits.trim_matches(|c: char| c == '\0' || c == ' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| invalid("tar", "bad number in header"))
+}
+
+/// Returns the text of a NUL-terminated tar header field.
+fn tar_text(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn tar_entries(
+    mut reader: impl Read,
+    archive: &Path,
+    algo: HashAlgo,
+    on_entry: &mut dyn FnMut(PathBuf, io::Result<(u64, String)>),
+) -> io::Result<()> {
+    let mut header = [0; 512];
+    // A long name or size given by an entry of its own for the next file.
+    let (mut long_name, mut long_size) = (None, None);
+    while read_block(&mut reader, &mut header)? {
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        // The checksum is the sum of the header's bytes, counting its own
+        // field as spaces.
+        let sum: u64 = header
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| {
+                if (148..156).contains(&i) {
+                    32
+                } else {
+                    u64::from(b)
+                }
+            })
+            .sum();
+        if tar_number(&header[148..156])? != sum {
+            return Err(invalid("tar", "header checksum does not match"));
+        }
+        let size = match long_size.take() {
+            Some(size) => size,
+            None => tar_number(&header[124..136])?,
+        };
+        let padding = (512 - size % 512) % 512;
+        match header[156] {
+            b'0' | 0 | b'7' => {
+                let name = long_name.take().unwrap_or_else(|| {
+                    let name = tar_text(&header[..100]);
+                    let prefix = tar_text(&header[345..500]);
+                    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                        format!("{prefix}/{name}")
+                    } else {
+                        name
+                    }
+                });
+                let hash = hash_exactly(&mut reader, size, algo)?;
+                on_entry(entry_path(archive, &name), Ok((size, hash)));
+            }
+            kind @ (b'L' | b'x') => {
+                let mut data = Vec::new();
+                (&mut reader).take(size).read_to_end(&mut data)?;
+                if (data.len() as u64) < size {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                if kind == b'L' {
+                    long_name = Some(tar_text(&data));
+                } else {
+                    for (key, value) in pax_records(&data) {
+                        match key {
+                            "path" => long_name = Some(value.to_owned()),
+                            "size" => long_size = value.parse().ok(),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            _ => {
+                long_name = None;
+                skip(&mut reader, size)?;
+            }
+        }
+        skip(&mut reader, padding)?;
+    }
+    Ok(())
+}
+
+/// Splits a pax extended header into its `key=value` records, each written
+/// as `<length> <key>=<value>\n`.
+fn pax_records(data: &[u8]) -> Vec<(&str, &str)> {
+    let mut records = Vec::new();
+    let mut rest = data;
+    while let Some(space) = rest.iter().position(|&b| b == b' ') {
+        let Some(len) = std::str::from_utf8(&rest[..space])
+            .ok()
+            .and_then(|len| len.parse::<usize>().ok())
+            .filter(|&len| len > space + 1 && len <= rest.len())
+        else {
+            break;
+        };
+        let record = &rest[space + 1..len - 1];
+        if let Some((key, value)) = std::str::from_utf8(record)
+            .ok()
+            .and_then(|record| record.split_once('='))
+        {
+            records.push((key, value));
+        }
+        rest = &rest[len..];
+    }
+    records
+}
+
+/// Reads a little-endian number of `N` bytes at `at` in `bytes`.
+fn le<const N: usize>(bytes: &[u8], at: usize) -> io::Result<u64> {
+    let field = bytes
+        .get(at..at + N)
+        .ok_or_else(|| invalid("zip", "record cut short"))?;
+    Ok(field.iter().rev().fold(0, |n, &b| n << 8 | u64::from(b)))
+}
+
+/// A file listed in a zip archive's central directory.
+struct ZipEntry {
+    name: String,
+    flags: u64,
+    method: u64,
+    compressed: u64,
+    size: u64,
+    offset: u64,
+}
+
+fn zip_entries<R: Read + Seek>(
+    mut reader: R,
+    archive: &Path,
+    algo: HashAlgo,
+    on_entry: &mut dyn FnMut(PathBuf, io::Result<(u64, String)>),
+) -> io::Result<()> {
+    for entry in zip_directory(&mut reader)? {
+        if entry.name.ends_with('/') {
+            continue;
+        }
+        let path = entry_path(archive, &entry.name);
+        let hashed = if entry.flags & 1 != 0 {
+            Err(invalid("zip", "entry is encrypted"))
+        } else {
+            hash_zip_entry(&mut reader, &entry, algo)
+        };
+        on_entry(path, hashed.map(|hash| (entry.size, hash)));
+    }
+    Ok(())
+}
+
+/// Reads the central directory of the zip archive in `reader`.
+fn zip_directory<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<ZipEntry>> {
+    // The end of central directory record closes the archive, followed by
+    // a comment of up to 65535 bytes.
+    let len = reader.seek(SeekFrom::End(0))?;
+    let tail_len = len.min(22 + 0xFFFF);
+    reader.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = Vec::new();
+    reader.read_to_end(&mut tail)?;
+    let end = tail
+        .windows(4)
+        .rposition(|w| w == b"PK\x05\x06")
+        .ok_or_else(|| invalid("zip", "no end of central directory"))?;
+    let mut count = le::<2>(&tail, end + 10)?;
+    let mut size = le::<4>(&tail, end + 12)?;
+    let mut offset = le::<4>(&tail, end + 16)?;
+    if count == 0xFFFF || size == 0xFFFF_FFFF || offset == 0xFFFF_FFFF {
+        // A Zip64 archive, whose true values follow a locator just before.
+        let locator = end
+            .checked_sub(20)
+            .filter(|&at| &tail[at..at + 4] == b"PK\x06\x07")
+            .ok_or_else(|| invalid("zip", "no Zip64 end of central directory"))?;
+        reader.seek(SeekFrom::Start(le::<8>(&tail, locator + 8)?))?;
+        let mut record = [0; 56];
+        reader.read_exact(&mut record)?;
+        if &record[..4] != b"PK\x06\x06" {
+            return Err(invalid("zip", "no Zip64 end of central directory"));
+        }
+        count = le::<8>(&record, 32)?;
+        size = le::<8>(&record, 40)?;
+        offset = le::<8>(&record, 48)?;
+    }
+    if offset.saturating_add(size) > len {
+        return Err(invalid("zip", "central directory is out of bounds"));
+    }
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut directory = Vec::new();
+    reader.take(size).read_to_end(&mut directory)?;
+
+    let mut entries = Vec::new();
+    let mut at = 0;
+    for _ in 0..count {
+        if directory.get(at..at + 4) != Some(b"PK\x01\x02") {
+            return Err(invalid("zip", "bad central directory entry"));
+        }
+        let name_len = usize::try_from(le::<2>(&directory, at + 28)?).unwrap_or_default();
+        let extra_len = usize::try_from(le::<2>(&directory, at + 30)?).unwrap_or_default();
+        let comment_len = usize::try_from(le::<2>(&directory, at + 32)?).unwrap_or_default();
+        let name = directory
+            .get(at + 46..at + 46 + name_len)
+            .ok_or_else(|| invalid("zip", "record cut short"))?;
+        let mut entry = ZipEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            flags: le::<2>(&directory, at + 8)?,
+            method: le::<2>(&directory, at + 10)?,
+            compressed: le::<4>(&directory, at + 20)?,
+            size: le::<4>(&directory, at + 24)?,
+            offset: le::<4>(&directory, at + 42)?,
+        };
+        let extra_start = at + 46 + name_len;
+        let extra = directory
+            .get(extra_start..extra_start + extra_len)
+            .ok_or_else(|| invalid("zip", "record cut short"))?;
+        read_zip64_extra(extra, &mut entry)?;
+        entries.push(entry);
+        at = extra_start + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+/// Replaces the sizes and offset of `entry` that did not fit in 32 bits
+/// with those of its Zip64 extra field, which holds only those, in order.
+fn read_zip64_extra(mut extra: &[u8], entry: &mut ZipEntry) -> io::Result<()> {
+    while extra.len() >= 4 {
+        let id = le::<2>(extra, 0)?;
+        let len = usize::try_from(le::<2>(extra, 2)?).unwrap_or_default();
+        let data = extra
+            .get(4..4 + len)
+            .ok_or_else(|| invalid("zip", "record cut short"))?;
+        if id == 1 {
+            let mut at = 0;
+            for field in [&mut entry.size, &mut entry.compressed, &mut entry.offset] {
+                if *field == 0xFFFF_FFFF {
+                    *field = le::<8>(data, at)?;
+                    at += 8;
+                }
+            }
+        }
+        extra = &extra[4 + len..];
+    }
+    Ok(())
+}
+
+fn hash_zip_entry<R: Read + Seek>(
+    reader: &mut R,
+    entry: &ZipEntry,
+    algo: HashAlgo,
+) -> io::Result<String> {
+    reader.seek(SeekFrom::Start(entry.offset))?;
+    let mut header = [0; 30];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != b"PK\x03\x04" {
+        return Err(invalid("zip", "bad local header"));
+    }
+    let skipped = le::<2>(&header, 26)? + le::<2>(&header, 28)?;
+    reader.seek(SeekFrom::Current(
+        i64::try_from(skipped).unwrap_or_default(),
+    ))?;
+    let mut data = reader.take(entry.compressed);
+    match entry.method {
+        0 => hash_exactly(&mut data, entry.size, algo),
+        8 => hash_exactly(&mut Inflate::new(data), entry.size, algo),
+        method => Err(invalid(
+            "zip",
+            &format!("compression method {method} is not supported"),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn hash(data: &[u8]) -> String {
+        let mut hasher = HashAlgo::Sha256.hasher();
+        hasher.update(data);
+        hasher.finish_hex()
+    }
+
+    fn entries(
+        read: impl FnOnce(&mut dyn FnMut(PathBuf, io::Result<(u64, String)>)) -> io::Result<()>,
+    ) -> Vec<(PathBuf, u64, String)> {
+        let mut found = Vec::new();
+        read(&mut |path, hashed| {
+            let (size, hash) = hashed.expect("readable entry");
+            found.push((path, size, hash));
+        })
+        .expect("readable archive");
+        found
+    }
+
+    #[test]
+    fn test_tar_entries() {
+        // A ustar header for `name`, with its checksum filled in.
+        let header = |name: &str, kind: u8, size: usize| {
+            let mut header = [0u8; 512];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[124..135].copy_from_slice(format!("{size:011o}").as_bytes());
+            header[156] = kind;
+            header[257..263].copy_from_slice(b"ustar\0");
+            header[148..156].fill(b' ');
+            let sum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+            header[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+            header
+        };
+        let mut tar = Vec::new();
+        tar.extend_from_slice(&header("./docs/", b'5', 0));
+        tar.extend_from_slice(&header("./docs/a.txt", b'0', 5));
+        tar.extend_from_slice(b"hello");
+        tar.resize(tar.len() + 507, 0);
+        let long = "x".repeat(150);
+        tar.extend_from_slice(&header("././@LongLink", b'L', long.len() + 1));
+        tar.extend_from_slice(long.as_bytes());
+        tar.resize(tar.len() + 512 - long.len(), 0);
+        tar.extend_from_slice(&header("truncated-name", b'0', 0));
+        tar.resize(tar.len() + 1024, 0);
+
+        let archive = Path::new("backup.tar");
+        let found = entries(|on_entry| tar_entries(&tar[..], archive, HashAlgo::Sha256, on_entry));
+        assert_eq!(
+            found,
+            [
+                (PathBuf::from("backup.tar!/docs/a.txt"), 5, hash(b"hello")),
+                (PathBuf::from(format!("backup.tar!/{long}")), 0, hash(b"")),
+            ]
+        );
+
+        tar[600] ^= 1;
+        let result = tar_entries(&tar[..], archive, HashAlgo::Sha256, &mut |_, _| {});
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_zip_entries() {
+        // zipfile with a stored `a.txt` holding "hello" and a deflated
+        // `dir/b.txt` holding "hello hello hello hello\n".
+        let zip: &[u8] = &[
+            0x50, 0x4B, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x00,
+            0x86, 0xA6, 0x10, 0x36, 0x05, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x05, 0x00,
+            0x00, 0x00, 0x61, 0x2E, 0x74, 0x78, 0x74, 0x68, 0x65, 0x6C, 0x6C, 0x6F, 0x50, 0x4B,
+            0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x21, 0x00, 0x00, 0x88,
+            0x59, 0x0B, 0x0B, 0x00, 0x00, 0x00, 0x18, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00,
+            0x64, 0x69, 0x72, 0x2F, 0x62, 0x2E, 0x74, 0x78, 0x74, 0xCB, 0x48, 0xCD, 0xC9, 0xC9,
+            0x57, 0xC8, 0x40, 0x27, 0xB9, 0x00, 0x50, 0x4B, 0x01, 0x02, 0x14, 0x03, 0x14, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x00, 0x86, 0xA6, 0x10, 0x36, 0x05, 0x00,
+            0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x80, 0x01, 0x00, 0x00, 0x00, 0x00, 0x61, 0x2E, 0x74, 0x78,
+            0x74, 0x50, 0x4B, 0x01, 0x02, 0x14, 0x03, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00,
+            0x00, 0x21, 0x00, 0x00, 0x88, 0x59, 0x0B, 0x0B, 0x00, 0x00, 0x00, 0x18, 0x00, 0x00,
+            0x00, 0x09, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80,
+            0x01, 0x28, 0x00, 0x00, 0x00, 0x64, 0x69, 0x72, 0x2F, 0x62, 0x2E, 0x74, 0x78, 0x74,
+            0x50, 0x4B, 0x05, 0x06, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02, 0x00, 0x6A, 0x00,
+            0x00, 0x00, 0x5A, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let archive = Path::new("old.zip");
+        let found =
+            entries(|on_entry| zip_entries(Cursor::new(zip), archive, HashAlgo::Sha256, on_entry));
+        assert_eq!(
+            found,
+            [
+                (PathBuf::from("old.zip!/a.txt"), 5, hash(b"hello")),
+                (
+                    PathBuf::from("old.zip!/dir/b.txt"),
+                    24,
+                    hash(b"hello hello hello hello\n")
+                ),
+            ]
+        );
+
+        let result = zip_entries(
+            Cursor::new(&zip[..zip.len() - 22]),
+            archive,
+            HashAlgo::Sha256,
+            &mut |_, _| {},
+        );
+        assert!(result.is_err());
+    }
+}
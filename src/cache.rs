@@ -0,0 +1,313 @@
+//! An on-disk cache of full-content hashes for fast incremental rescans.
+//!
+//! Each entry records a file's path, size, modification time, the hash
+//! algorithm used and the resulting digest. A cached digest is only reused
+//! while the size and modification time still match, so editing a file
+//! always causes it to be hashed again.
+//!
+//! The cache is stored as JSON with a `version` field. A file written by an
+//! incompatible version, or one that cannot be parsed, is ignored and
+//! rebuilt rather than treated as an error.
+
+use crate::json::{self, Value};
+use crate::{full_hash_with, HashAlgo};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{self, Metadata};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::UNIX_EPOCH;
+
+/// Version of the on-disk format. Bump it whenever the layout changes.
+pub const CACHE_VERSION: u64 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CacheEntry {
+    size: u64,
+    /// Modification time as seconds and nanoseconds since the Unix epoch.
+    mtime: (u64, u32),
+    hash_algo: HashAlgo,
+    hash: String,
+}
+
+/// Full-content hashes remembered between runs.
+///
+/// The cache can be shared by the parallel hashing stage, so every method
+/// takes `&self`.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::cache::HashCache;
+/// use tempfile::tempdir;
+///
+/// let dir = tempdir().unwrap();
+/// let cache_file = dir.path().join("hashes.json");
+/// let cache = HashCache::load(&cache_file).unwrap();
+/// assert!(cache.is_empty());
+/// cache.save(&cache_file).unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct HashCache {
+    entries: Mutex<HashMap<PathBuf, CacheEntry>>,
+    hits: AtomicUsize,
+}
+
+impl HashCache {
+    /// Reads a cache file, returning an empty cache if it does not exist.
+    ///
+    /// Files with a different [`CACHE_VERSION`] or invalid contents are
+    /// discarded with a warning.
+    ///
+    /// # Errors
+    /// Returns any I/O error other than the file being missing.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        let entries = match parse_entries(&text) {
+            Ok(entries) => entries,
+            Err(reason) => {
+                warn!(
+                    "Ignoring hash cache {}: {reason}; it will be rebuilt",
+                    path.display()
+                );
+                HashMap::new()
+            }
+        };
+        info!(
+            "Loaded {} cached hashes from {}",
+            entries.len(),
+            path.display()
+        );
+        Ok(Self {
+            entries: Mutex::new(entries),
+            hits: AtomicUsize::new(0),
+        })
+    }
+
+    /// Writes the cache to `path`, dropping entries for files that no longer exist.
+    ///
+    /// The file is written under a temporary name and renamed into place, so
+    /// an interrupted save never leaves a truncated cache behind.
+    ///
+    /// # Errors
+    /// Returns any I/O error raised while writing the file.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut entries = self.lock();
+        entries.retain(|file, _| file.exists());
+
+        let mut sorted: Vec<(&str, &CacheEntry)> = entries
+            .iter()
+            .filter_map(|(file, entry)| file.to_str().map(|p| (p, entry)))
+            .collect();
+        sorted.sort_by_key(|(p, _)| *p);
+
+        let mut text = format!("{{\"version\":{CACHE_VERSION},\"entries\":[");
+        for (i, (file, entry)) in sorted.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            let _ = write!(
+                text,
+                "{separator}\n{{\"path\":{},\"size\":{},\"mtime_secs\":{},\"mtime_nanos\":{},\"algo\":{},\"hash\":{}}}",
+                json::quote(file),
+                entry.size,
+                entry.mtime.0,
+                entry.mtime.1,
+                json::quote(algo_name(entry.hash_algo)),
+                json::quote(&entry.hash),
+            );
+        }
+        text.push_str("\n]}\n");
+
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temp, path)?;
+        info!("Saved {} cached hashes to {}", sorted.len(), path.display());
+        Ok(())
+    }
+
+    /// Returns the number of cached hashes.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns `true` if no hashes are cached.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns how many hashes were served from the cache instead of computed.
+    #[must_use]
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns the full hash of `path`, reusing a cached digest if the file is unchanged.
+    ///
+    /// The number of bytes hashed is passed to `on_read` as the file is read,
+    /// which never happens for a cached digest.
+    pub(crate) fn full_hash(
+        &self,
+        path: &Path,
+        hash_algo: HashAlgo,
+        on_read: &dyn Fn(u64),
+    ) -> io::Result<String> {
+        let meta = fs::metadata(path)?;
+        let Some(mtime) = mtime_key(&meta) else {
+            return full_hash_with(path, hash_algo, on_read);
+        };
+
+        if let Some(entry) = self.lock().get(path) {
+            if entry.size == meta.len() && entry.mtime == mtime && entry.hash_algo == hash_algo {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(entry.hash.clone());
+            }
+        }
+
+        let hash = full_hash_with(path, hash_algo, on_read)?;
+        self.lock().insert(
+            path.to_path_buf(),
+            CacheEntry {
+                size: meta.len(),
+                mtime,
+                hash_algo,
+                hash: hash.clone(),
+            },
+        );
+        Ok(hash)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, CacheEntry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Returns the modification time used to detect changed files, if available.
+fn mtime_key(meta: &Metadata) -> Option<(u64, u32)> {
+    let since_epoch = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((since_epoch.as_secs(), since_epoch.subsec_nanos()))
+}
+
+pub(crate) fn algo_name(hash_algo: HashAlgo) -> &'static str {
+    match hash_algo {
+        HashAlgo::Sha256 => "sha256",
+        HashAlgo::Blake3 => "blake3",
+        HashAlgo::Xxh3 => "xxh3",
+        HashAlgo::Md5 => "md5",
+    }
+}
+
+pub(crate) fn parse_algo(name: &str) -> Option<HashAlgo> {
+    match name {
+        "sha256" => Some(HashAlgo::Sha256),
+        "blake3" => Some(HashAlgo::Blake3),
+        "xxh3" => Some(HashAlgo::Xxh3),
+        "md5" => Some(HashAlgo::Md5),
+        _ => None,
+    }
+}
+
+fn parse_entries(text: &str) -> Result<HashMap<PathBuf, CacheEntry>, String> {
+    let doc = json::parse(text)?;
+    match doc.get("version").and_then(Value::as_u64) {
+        Some(CACHE_VERSION) => {}
+        Some(other) => return Err(format!("unsupported version {other}")),
+        None => return Err("missing version".to_string()),
+    }
+
+    let items = doc
+        .get("entries")
+        .and_then(Value::as_array)
+        .ok_or("missing entries")?;
+    let mut entries = HashMap::with_capacity(items.len());
+    for item in items {
+        let field = |name: &str| item.get(name).ok_or(format!("entry without {name}"));
+        let number = |name: &str| field(name)?.as_u64().ok_or(format!("invalid {name}"));
+        let string = |name: &str| field(name)?.as_str().ok_or(format!("invalid {name}"));
+
+        let nanos = u32::try_from(number("mtime_nanos")?).map_err(|e| e.to_string())?;
+        let entry = CacheEntry {
+            size: number("size")?,
+            mtime: (number("mtime_secs")?, nanos),
+            hash_algo: parse_algo(string("algo")?).ok_or("unknown algo")?,
+            hash: string("hash")?.to_string(),
+        };
+        entries.insert(PathBuf::from(string("path")?), entry);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_cache_round_trip_and_prune() {
+        let dir = tempdir().expect("create temp dir");
+        let cache_file = dir.path().join("cache.json");
+        let kept = dir.path().join("kept, \"quoted\".txt");
+        let removed = dir.path().join("removed.txt");
+        fs::write(&kept, "content").expect("write file");
+        fs::write(&removed, "content").expect("write file");
+
+        let cache = HashCache::load(&cache_file).expect("load cache");
+        let hash = cache
+            .full_hash(&kept, HashAlgo::Sha256, &|_| {})
+            .expect("hash file");
+        cache
+            .full_hash(&removed, HashAlgo::Sha256, &|_| {})
+            .expect("hash file");
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.hits(), 0);
+        fs::remove_file(&removed).expect("remove file");
+        cache.save(&cache_file).expect("save cache");
+
+        let reloaded = HashCache::load(&cache_file).expect("load cache");
+        assert_eq!(reloaded.len(), 1);
+        assert_eq!(
+            reloaded
+                .full_hash(&kept, HashAlgo::Sha256, &|_| {})
+                .expect("hash file"),
+            hash
+        );
+        assert_eq!(reloaded.hits(), 1);
+
+        // A different algorithm or changed contents must not reuse the digest.
+        reloaded
+            .full_hash(&kept, HashAlgo::Blake3, &|_| {})
+            .expect("hash file");
+        assert_eq!(reloaded.hits(), 1);
+        fs::write(&kept, "changed content").expect("write file");
+        assert_ne!(
+            reloaded
+                .full_hash(&kept, HashAlgo::Blake3, &|_| {})
+                .expect("hash file"),
+            hash
+        );
+        assert_eq!(reloaded.hits(), 1);
+    }
+
+    #[test]
+    fn test_incompatible_cache_is_ignored() {
+        let dir = tempdir().expect("create temp dir");
+        let cache_file = dir.path().join("cache.json");
+
+        fs::write(&cache_file, r#"{"version":999,"entries":[]}"#).expect("write file");
+        assert!(HashCache::load(&cache_file).expect("load cache").is_empty());
+
+        fs::write(&cache_file, "not json").expect("write file");
+        assert!(HashCache::load(&cache_file).expect("load cache").is_empty());
+    }
+}
@@ -0,0 +1,482 @@
+//! Detection of files that share much of their contents without being
+//! duplicates, such as two snapshots of a virtual machine disk or a log file
+//! and a later copy it has grown into.
+//!
+//! Each file is split into chunks of about [`AVERAGE_CHUNK`] bytes with
+//! `FastCDC`, which cuts wherever a rolling hash of the last few bytes meets a
+//! condition. Because the cuts depend on the content rather than on
+//! offsets, an insertion or deletion only changes the chunks around it, and
+//! the rest of two such files split into the same chunks. Each chunk is
+//! hashed, and two files share the chunks whose hashes they have in common.
+
+use crate::{
+    build_pool, collect_files, format_size, in_pool, into_errors, is_cancelled, record_error,
+    Console, GlobError, ScanConfig, ScanOptions, Stage,
+};
+use chrono::Local;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use twox_hash::XxHash3_64;
+
+/// The percentage of the smaller file two files must share by default to be
+/// reported.
+pub const DEFAULT_MIN_SHARED: u32 = 50;
+
+/// The size files are split into on average, which is also the smallest
+/// file analysed.
+pub const AVERAGE_CHUNK: u64 = 64 * 1024;
+
+/// No chunk but the last of a file is shorter.
+const MIN_CHUNK: usize = 16 * 1024;
+/// No chunk is longer.
+const MAX_CHUNK: usize = 256 * 1024;
+
+/// A cut is made once the top bits of the rolling hash are all zero: more
+/// of them before the average size, so chunks cluster around it.
+const MASK_SMALL: u64 = !0 << (64 - 18);
+const MASK_LARGE: u64 = !0 << (64 - 14);
+
+/// A random number for every byte value, mixed into the rolling hash.
+const GEAR: [u64; 256] = {
+    let mut gear = [0; 256];
+    let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+    let mut i = 0;
+    while i < 256 {
+        // SplitMix64.
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut x = state;
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        gear[i] = x ^ (x >> 31);
+        i += 1;
+    }
+    gear
+};
+
+/// A file that is part of a pair sharing contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkedFile {
+    pub path: PathBuf,
+    /// The size of the file in bytes.
+    pub size: u64,
+}
+
+/// Two files that share much of their contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialDuplicate {
+    /// The two files, in path order.
+    pub files: [ChunkedFile; 2],
+    /// How many bytes of chunks both files hold.
+    pub shared_bytes: u64,
+    /// The shared bytes as a percentage of the smaller file, rounded down.
+    pub shared: u32,
+}
+
+/// The result of a search for partial duplicates.
+#[derive(Debug)]
+pub struct PartialDuplicates {
+    /// The directories searched.
+    pub roots: Vec<PathBuf>,
+    /// The percentage of the smaller file two files had to share to be
+    /// reported.
+    pub min_shared: u32,
+    /// How many files were split into chunks.
+    pub files: usize,
+    /// The total size of those files.
+    pub total_bytes: u64,
+    /// The total size of their distinct chunks: what storing them would
+    /// take if every repeated chunk were stored once.
+    pub unique_bytes: u64,
+    /// The pairs sharing enough, sharing the most bytes first, then by path.
+    pub pairs: Vec<PartialDuplicate>,
+    /// Files that could not be read, with the error raised.
+    pub errors: Vec<(PathBuf, io::Error)>,
+    /// Whether the search was cancelled before every file was read.
+    pub interrupted: bool,
+}
+
+impl PartialDuplicates {
+    /// Returns an estimate of the bytes that deduplicating the files by
+    /// chunk, as some filesystems and backup tools do, would save.
+    #[must_use]
+    pub fn shareable_bytes(&self) -> u64 {
+        self.total_bytes - self.unique_bytes
+    }
+}
+
+/// Finds pairs of files in `dirs` whose shared chunks make up at least
+/// `min_shared` percent of the smaller file.
+///
+/// Files are chosen as in any scan, by every filter of `options` that
+/// applies to the walk and by size. Files smaller than [`AVERAGE_CHUNK`]
+/// are left out, as they are a single chunk that only an exact copy
+/// shares. Every file is read in full.
+///
+/// # Errors
+/// Returns an error if any pattern in `options` is invalid.
+pub fn find_partial_duplicates(
+    dirs: &[PathBuf],
+    options: &ScanOptions,
+    min_shared: u32,
+) -> Result<PartialDuplicates, GlobError> {
+    let config = ScanConfig::new(options)?;
+    let console = Console::new(config.quiet, config.progress_json, config.progress);
+    let errors = Mutex::new(Vec::new());
+    let files: Vec<_> = collect_files(dirs, &config.walk, config.cancel, &errors, &mut Vec::new())
+        .into_iter()
+        .filter(|file| {
+            let size = file.meta.len();
+            size >= AVERAGE_CHUNK
+                && config.min_size.is_none_or(|min| size >= min)
+                && config.max_size.is_none_or(|max| size <= max)
+        })
+        .collect();
+    console.status(&format!(
+        "{} files identified across {} directories",
+        files.len(),
+        dirs.len()
+    ));
+
+    let progress = console.bar(files.len() as u64, Stage::ChunkHash, "Chunking files");
+    let pool = build_pool(config.threads);
+    let chunked: Vec<(ChunkedFile, Vec<(u64, u32)>)> = in_pool(pool.as_ref(), || {
+        files
+            .into_par_iter()
+            .filter_map(|file| {
+                if is_cancelled(config.cancel) {
+                    return None;
+                }
+                let chunks = config.read(&file.path, || chunk_file(&file.path));
+                config.pace(file.meta.len());
+                progress.inc(1);
+                match chunks {
+                    Ok((size, chunks)) => Some((
+                        ChunkedFile {
+                            path: file.path,
+                            size,
+                        },
+                        chunks,
+                    )),
+                    Err(e) => {
+                        record_error(&errors, &file.path, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    });
+    progress.finish_with_message("Files chunked");
+
+    let (files, chunks): (Vec<_>, Vec<_>) = chunked.into_iter().unzip();
+    let total_bytes = files.iter().map(|file| file.size).sum();
+    let (unique_bytes, pairs) = shared_pairs(&files, &chunks, min_shared);
+    console.complete(pairs.len());
+    Ok(PartialDuplicates {
+        roots: dirs.to_vec(),
+        min_shared,
+        files: files.len(),
+        total_bytes,
+        unique_bytes,
+        pairs,
+        errors: into_errors(errors),
+        interrupted: is_cancelled(config.cancel),
+    })
+}
+
+/// Returns where the first chunk of `data` ends: at the first cut point,
+/// or at the end of `data` if it holds no more than a chunk.
+fn cut_point(data: &[u8]) -> usize {
+    if data.len() <= MIN_CHUNK {
+        return data.len();
+    }
+    let end = data.len().min(MAX_CHUNK);
+    let average = usize::try_from(AVERAGE_CHUNK).unwrap_or(end).min(end);
+    let mut hash: u64 = 0;
+    for (i, &byte) in data.iter().enumerate().take(end).skip(MIN_CHUNK) {
+        hash = (hash << 1).wrapping_add(GEAR[usize::from(byte)]);
+        let mask = if i < average { MASK_SMALL } else { MASK_LARGE };
+        if hash & mask == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+/// Splits everything `reader` yields into chunks, passing each to
+/// `on_chunk`.
+fn split(mut reader: impl Read, on_chunk: &mut dyn FnMut(&[u8])) -> io::Result<()> {
+    let mut buffer = Vec::with_capacity(2 * MAX_CHUNK);
+    let mut start = 0;
+    let mut at_end = false;
+    loop {
+        if !at_end && buffer.len() - start < MAX_CHUNK {
+            // Move what is left to the front and top the buffer up.
+            buffer.drain(..start);
+            start = 0;
+            let len = buffer.len();
+            buffer.resize(2 * MAX_CHUNK, 0);
+            let mut filled = len;
+            while filled < buffer.len() {
+                match reader.read(&mut buffer[filled..]) {
+                    Ok(0) => {
+                        at_end = true;
+                        break;
+                    }
+                    Ok(n) => filled += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            buffer.truncate(filled);
+        }
+        if start == buffer.len() {
+            return Ok(());
+        }
+        let end = start + cut_point(&buffer[start..]);
+        on_chunk(&buffer[start..end]);
+        start = end;
+    }
+}
+
+/// Returns the size of the file at `path` as read, and the hash and length
+/// of each of its distinct chunks.
+fn chunk_file(path: &Path) -> io::Result<(u64, Vec<(u64, u32)>)> {
+    let mut size = 0;
+    let mut chunks = Vec::new();
+    split(File::open(path)?, &mut |chunk| {
+        size += chunk.len() as u64;
+        let len = u32::try_from(chunk.len()).unwrap_or(u32::MAX);
+        chunks.push((XxHash3_64::oneshot(chunk), len));
+    })?;
+    chunks.sort_unstable();
+    chunks.dedup();
+    Ok((size, chunks))
+}
+
+/// Returns the total length of the distinct chunks of `files`, and the
+/// pairs whose shared chunks make up at least `min_shared` percent of the
+/// smaller file.
+fn shared_pairs(
+    files: &[ChunkedFile],
+    chunks: &[Vec<(u64, u32)>],
+    min_shared: u32,
+) -> (u64, Vec<PartialDuplicate>) {
+    let mut holders: HashMap<(u64, u32), Vec<usize>> = HashMap::new();
+    for (i, chunks) in chunks.iter().enumerate() {
+        for &chunk in chunks {
+            holders.entry(chunk).or_default().push(i);
+        }
+    }
+    let unique_bytes = holders.keys().map(|&(_, len)| u64::from(len)).sum();
+    let mut shared: HashMap<(usize, usize), u64> = HashMap::new();
+    for ((_, len), holders) in &holders {
+        for (n, &a) in holders.iter().enumerate() {
+            for &b in &holders[n + 1..] {
+                *shared.entry((a, b)).or_default() += u64::from(*len);
+            }
+        }
+    }
+
+    let mut pairs: Vec<PartialDuplicate> = shared
+        .into_iter()
+        .filter_map(|((a, b), shared_bytes)| {
+            let smaller = files[a].size.min(files[b].size).max(1);
+            let shared = u32::try_from((shared_bytes * 100 / smaller).min(100)).unwrap_or(100);
+            if shared < min_shared {
+                return None;
+            }
+            let mut pair = [files[a].clone(), files[b].clone()];
+            pair.sort_by(|x, y| x.path.cmp(&y.path));
+            Some(PartialDuplicate {
+                files: pair,
+                shared_bytes,
+                shared,
+            })
+        })
+        .collect();
+    pairs.sort_by(|a, b| {
+        b.shared_bytes
+            .cmp(&a.shared_bytes)
+            .then_with(|| a.files[0].path.cmp(&b.files[0].path))
+            .then_with(|| a.files[1].path.cmp(&b.files[1].path))
+    });
+    (unique_bytes, pairs)
+}
+
+/// Writes the pairs of partial duplicates as plain text, with the bytes
+/// that deduplicating every chunk would save.
+///
+/// # Errors
+/// Returns any error raised by `writer`.
+pub fn write_partial_duplicates_to<W: Write>(
+    writer: &mut W,
+    partial: &PartialDuplicates,
+    start_time: &str,
+) -> io::Result<()> {
+    let username = whoami::username();
+    let end_time = Local::now().format("%Y%m%d %H:%M:%S").to_string();
+
+    writeln!(writer, "Partial Duplicates Report")?;
+    writeln!(writer, "Generated by: {username}")?;
+    writeln!(writer, "Start Time: {start_time}")?;
+    writeln!(writer, "End Time: {end_time}")?;
+    if partial.roots.len() == 1 {
+        writeln!(writer, "Base Directory: {}", partial.roots[0].display())?;
+    } else {
+        writeln!(writer, "Base Directories:")?;
+        for dir in &partial.roots {
+            writeln!(writer, " - {}", dir.display())?;
+        }
+    }
+    writeln!(writer)?;
+
+    if partial.interrupted {
+        writeln!(
+            writer,
+            "Scan Interrupted: the results below are incomplete."
+        )?;
+    }
+    writeln!(
+        writer,
+        "Files Analysed: {} (split into chunks of about {}; listed when sharing at least {}% \
+         of the smaller file)",
+        partial.files,
+        format_size(AVERAGE_CHUNK),
+        partial.min_shared
+    )?;
+    writeln!(writer, "Total Size: {}", format_size(partial.total_bytes))?;
+    let percent = partial
+        .shareable_bytes()
+        .saturating_mul(100)
+        .checked_div(partial.total_bytes)
+        .unwrap_or(0);
+    writeln!(
+        writer,
+        "Shareable By Chunk Deduplication: {} ({percent}%)",
+        format_size(partial.shareable_bytes())
+    )?;
+    writeln!(writer, "Pairs Found: {}", partial.pairs.len())?;
+    if !partial.errors.is_empty() {
+        writeln!(
+            writer,
+            "Files Skipped Due To Errors: {}",
+            partial.errors.len()
+        )?;
+    }
+    writeln!(writer)?;
+
+    for pair in &partial.pairs {
+        writeln!(
+            writer,
+            "{} shared ({}% of the smaller file):",
+            format_size(pair.shared_bytes),
+            pair.shared
+        )?;
+        for file in &pair.files {
+            writeln!(
+                writer,
+                "{} ({})",
+                file.path.display(),
+                format_size(file.size)
+            )?;
+        }
+        writeln!(writer)?;
+    }
+
+    if !partial.errors.is_empty() {
+        writeln!(writer, "Skipped Files:")?;
+        for (path, error) in &partial.errors {
+            writeln!(writer, "{}: {error}", path.display())?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns `len` bytes that look random, from `seed`.
+    fn noise(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state.to_le_bytes()[0]
+            })
+            .collect()
+    }
+
+    fn chunks_of(data: &[u8]) -> Vec<(u64, u32)> {
+        let mut chunks = Vec::new();
+        split(data, &mut |chunk| {
+            let len = u32::try_from(chunk.len()).expect("short chunk");
+            chunks.push((XxHash3_64::oneshot(chunk), len));
+        })
+        .expect("in-memory read");
+        chunks
+    }
+
+    #[test]
+    fn test_split_cuts_by_content() {
+        let data = noise(7, 4 * 1024 * 1024);
+        let chunks = chunks_of(&data);
+        let total: u64 = chunks.iter().map(|&(_, len)| u64::from(len)).sum();
+        assert_eq!(total, data.len() as u64);
+        assert!(chunks[..chunks.len() - 1]
+            .iter()
+            .all(|&(_, len)| (MIN_CHUNK..=MAX_CHUNK).contains(&(len as usize))));
+        let average = data.len() / chunks.len();
+        assert!((32 * 1024..128 * 1024).contains(&average), "{average}");
+
+        // Inserting bytes near the start only changes the chunks around them.
+        let mut shifted = noise(9, 1000);
+        shifted.extend_from_slice(&data);
+        let shifted = chunks_of(&shifted);
+        let common = chunks
+            .iter()
+            .filter(|chunk| shifted.contains(chunk))
+            .count();
+        assert!(common + 2 >= chunks.len(), "{common} of {}", chunks.len());
+    }
+
+    #[test]
+    fn test_shared_pairs() {
+        let log = noise(1, 1024 * 1024);
+        let mut grown = log.clone();
+        grown.extend(noise(2, 1024 * 1024));
+        let file = |name: &str, data: &[u8]| ChunkedFile {
+            path: PathBuf::from(name),
+            size: data.len() as u64,
+        };
+        let unrelated = noise(3, 512 * 1024);
+        let files = [
+            file("new.log", &grown),
+            file("old.log", &log),
+            file("other.bin", &unrelated),
+        ];
+        let mut chunks: Vec<_> = [&grown, &log, &unrelated]
+            .iter()
+            .map(|data| chunks_of(data))
+            .collect();
+        for chunks in &mut chunks {
+            chunks.sort_unstable();
+            chunks.dedup();
+        }
+
+        let (unique_bytes, pairs) = shared_pairs(&files, &chunks, 90);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].files[0].path, PathBuf::from("new.log"));
+        assert!(pairs[0].shared >= 90);
+        let expected = grown.len() + unrelated.len();
+        assert!(unique_bytes >= expected as u64);
+        assert!(unique_bytes - (expected as u64) < MAX_CHUNK as u64 * 2);
+    }
+}
@@ -0,0 +1,519 @@
+//! Comparison of source directories against reference directories.
+//!
+//! Rather than listing every duplicate, a comparison answers whether each
+//! file in the source directories also exists, with the same contents, in a
+//! reference directory: the question to ask before deleting an old backup
+//! folder whose files should all have been copied elsewhere. Both sets of
+//! directories go through one ordinary scan; a source file has a copy if it
+//! is in a duplicate group with a file of a reference directory, or is a hard
+//! link to one.
+//!
+//! A source file without a copy that matches a reference file in name, size
+//! and modification time was most likely copied there and has since changed
+//! on one side without being modified, so the pair is also reported as
+//! possibly corrupted.
+
+use crate::index::{copies_in_indexes, Index};
+use crate::{
+    collect_files, find_duplicates_with_options, format_size, into_errors, is_cancelled,
+    record_error, GlobError, ScanConfig, ScanOptions,
+};
+use chrono::Local;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Which source files have a copy in the reference directories.
+#[derive(Debug)]
+pub struct Comparison {
+    /// The source directories, in the order they were given.
+    pub sources: Vec<PathBuf>,
+    /// The reference directories, in the order they were given.
+    pub references: Vec<PathBuf>,
+    /// Source files with a copy in a reference directory, each paired with
+    /// one such copy, sorted by path.
+    pub present: Vec<(PathBuf, PathBuf)>,
+    /// Source files with no copy in any reference directory, sorted by path.
+    pub missing: Vec<PathBuf>,
+    /// Total size in bytes of the files in `missing`.
+    pub missing_bytes: u64,
+    /// Files in `missing` whose contents differ from a reference file with
+    /// the same name, size and modification time to the second, each paired
+    /// with one such file, sorted by path. One of the two has probably been
+    /// corrupted.
+    pub corrupted: Vec<(PathBuf, PathBuf)>,
+    /// Paths that could not be read, with the error raised. Source files
+    /// among them are in neither list.
+    pub errors: Vec<(PathBuf, io::Error)>,
+    /// Whether the scan was cancelled before every file was examined, in
+    /// which case some files in `missing` may have a copy after all.
+    pub interrupted: bool,
+}
+
+/// Finds which files in `sources` also exist in `references`.
+///
+/// A reference directory may lie inside a source directory, or the other
+/// way round: each file belongs to the innermost directory given that
+/// contains it, so the files of a reference directory inside a source are
+/// not source files. Files outside the size limits of `options` take part
+/// in neither list. A directory given as both source and reference counts as
+/// a source.
+///
+/// # Errors
+/// Returns an error if any pattern in `options` is invalid.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::{compare_dirs, ScanOptions};
+/// use std::fs;
+/// use tempfile::tempdir;
+///
+/// let (old, new) = (tempdir().unwrap(), tempdir().unwrap());
+/// fs::write(old.path().join("a.txt"), b"kept").unwrap();
+/// fs::write(old.path().join("b.txt"), b"only here").unwrap();
+/// fs::write(new.path().join("copy of a.txt"), b"kept").unwrap();
+///
+/// let options = ScanOptions::new().quiet(true);
+/// let comparison = compare_dirs(
+///     &[old.path().to_path_buf()],
+///     &[new.path().to_path_buf()],
+///     &options,
+/// )
+/// .unwrap();
+/// assert_eq!(comparison.present.len(), 1);
+/// assert_eq!(comparison.missing, [old.path().join("b.txt")]);
+/// ```
+pub fn compare_dirs(
+    sources: &[PathBuf],
+    references: &[PathBuf],
+    options: &ScanOptions,
+) -> Result<Comparison, GlobError> {
+    let config = ScanConfig::new(options)?;
+    let dirs: Vec<PathBuf> = sources.iter().chain(references).cloned().collect();
+    let report = find_duplicates_with_options(&dirs, options)?;
+    let roots = Roots::new(sources, references);
+
+    let mut copies: HashMap<PathBuf, PathBuf> = HashMap::new();
+    for group in &report.groups {
+        let Some(copy) = group.paths.iter().find(|path| roots.is_reference(path)) else {
+            continue;
+        };
+        for path in group.paths.iter().filter(|path| roots.is_source(path)) {
+            copies.insert(path.clone(), copy.clone());
+        }
+    }
+    for (link, kept) in &report.hard_links {
+        for (path, other) in [(link, kept), (kept, link)] {
+            if roots.is_source(path) && roots.is_reference(other) {
+                copies.insert(path.clone(), other.clone());
+            }
+        }
+    }
+
+    // The scan only reports files with duplicates, so the source files are
+    // listed again to find those without a copy. Their errors were already
+    // recorded by the scan.
+    let errors = Mutex::new(Vec::new());
+    let files = collect_files(
+        sources,
+        &config.walk,
+        config.cancel,
+        &errors,
+        &mut Vec::new(),
+    );
+    let failed: HashSet<PathBuf> = report.errors.iter().map(|(path, _)| path.clone()).collect();
+    let mut comparison = Comparison {
+        sources: sources.to_vec(),
+        references: references.to_vec(),
+        present: Vec::new(),
+        missing: Vec::new(),
+        missing_bytes: 0,
+        corrupted: Vec::new(),
+        errors: report.errors,
+        interrupted: report.interrupted,
+    };
+    for file in files {
+        let size = file.meta.len();
+        if !roots.is_source(&file.path)
+            || config.min_size.is_some_and(|min| size < min)
+            || config.max_size.is_some_and(|max| size > max)
+            || failed.contains(&file.path)
+        {
+            continue;
+        }
+        if let Some(copy) = copies.remove(&file.path) {
+            comparison.present.push((file.path, copy));
+        } else {
+            comparison.missing_bytes += size;
+            comparison.missing.push(file.path);
+        }
+    }
+    comparison.present.sort();
+    comparison.missing.sort();
+    if !comparison.missing.is_empty() {
+        comparison.corrupted = corrupted(&comparison.missing, references, &config, &roots);
+    }
+    Ok(comparison)
+}
+
+/// Finds which files in `sources` also exist in `references` or in
+/// `indexes`, as [`compare_dirs`] does.
+///
+/// Each source file without a copy in `references` that shares its size
+/// with an entry of an index is hashed with that index's algorithm, and has
+/// a copy if the hashes match. Such copies are paired with their
+/// [`Index::label`], and the roots of each index are added to the
+/// comparison's references the same way. An index is never checked for
+/// possibly corrupted files.
+///
+/// # Errors
+/// Returns an error if any pattern in `options` is invalid.
+pub fn compare_with_indexes(
+    sources: &[PathBuf],
+    references: &[PathBuf],
+    indexes: &[Index],
+    options: &ScanOptions,
+) -> Result<Comparison, GlobError> {
+    let mut comparison = compare_dirs(sources, references, options)?;
+    if indexes.is_empty() || comparison.missing.is_empty() {
+        return Ok(comparison);
+    }
+    let config = ScanConfig::new(options)?;
+    let errors = Mutex::new(Vec::new());
+    let missing: Vec<(u64, PathBuf)> = std::mem::take(&mut comparison.missing)
+        .into_iter()
+        .filter_map(|path| match std::fs::metadata(&path) {
+            Ok(meta) => Some((meta.len(), path)),
+            Err(e) => {
+                record_error(&errors, &path, e);
+                None
+            }
+        })
+        .collect();
+    let mut copies = copies_in_indexes(&missing, indexes, &config, &errors);
+    let errors = into_errors(errors);
+    let failed: HashSet<&Path> = errors.iter().map(|(path, _)| path.as_path()).collect();
+    comparison.missing_bytes = 0;
+    for (size, path) in missing {
+        if let Some(copy) = copies.remove(&path) {
+            comparison.present.push((path, copy));
+        } else if !failed.contains(path.as_path()) {
+            comparison.missing_bytes += size;
+            comparison.missing.push(path);
+        }
+    }
+    comparison.present.sort();
+    comparison
+        .corrupted
+        .retain(|(path, _)| comparison.missing.binary_search(path).is_ok());
+    comparison.errors.extend(errors);
+    for index in indexes {
+        comparison
+            .references
+            .extend(index.roots.iter().map(|root| index.label(root)));
+    }
+    comparison.interrupted |= is_cancelled(config.cancel);
+    Ok(comparison)
+}
+
+/// Identifies a file by what copying it preserves: its name, its size and
+/// its modification time to the second, which is as precise as some
+/// filesystems and copy tools keep it.
+type Signature = (OsString, u64, u64);
+
+fn signature(path: &Path, size: u64, modified: Option<SystemTime>) -> Option<Signature> {
+    let seconds = modified?.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some((path.file_name()?.to_os_string(), size, seconds))
+}
+
+/// Pairs each of the `missing` source files with a reference file of the
+/// same [`Signature`], whose contents must differ as it is not a copy.
+fn corrupted(
+    missing: &[PathBuf],
+    references: &[PathBuf],
+    config: &ScanConfig,
+    roots: &Roots,
+) -> Vec<(PathBuf, PathBuf)> {
+    let errors = Mutex::new(Vec::new());
+    let mut by_signature: HashMap<Signature, PathBuf> = HashMap::new();
+    for file in collect_files(
+        references,
+        &config.walk,
+        config.cancel,
+        &errors,
+        &mut Vec::new(),
+    ) {
+        if !roots.is_reference(&file.path) {
+            continue;
+        }
+        if let Some(key) = signature(&file.path, file.meta.len(), file.meta.modified().ok()) {
+            by_signature.entry(key).or_insert(file.path);
+        }
+    }
+    missing
+        .iter()
+        .filter_map(|path| {
+            let meta = std::fs::metadata(path).ok()?;
+            let key = signature(path, meta.len(), meta.modified().ok())?;
+            Some((path.clone(), by_signature.get(&key)?.clone()))
+        })
+        .collect()
+}
+
+/// The source and reference directories of a comparison, made absolute so
+/// that paths can be matched against them however they were written.
+struct Roots {
+    /// Each directory, with `true` for references.
+    dirs: Vec<(PathBuf, bool)>,
+}
+
+impl Roots {
+    fn new(sources: &[PathBuf], references: &[PathBuf]) -> Self {
+        let absolute = |dir: &PathBuf| std::path::absolute(dir).unwrap_or_else(|_| dir.clone());
+        let dirs = sources
+            .iter()
+            .map(|dir| (absolute(dir), false))
+            .chain(references.iter().map(|dir| (absolute(dir), true)))
+            .collect();
+        Self { dirs }
+    }
+
+    /// Returns whether the innermost directory containing `path` is a
+    /// reference, or `None` if no directory contains it.
+    fn classify(&self, path: &Path) -> Option<bool> {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        // Reversed so that a directory given as both counts as a source.
+        self.dirs
+            .iter()
+            .rev()
+            .filter(|(dir, _)| path.starts_with(dir))
+            .max_by_key(|(dir, _)| dir.components().count())
+            .map(|&(_, reference)| reference)
+    }
+
+    fn is_source(&self, path: &Path) -> bool {
+        self.classify(path) == Some(false)
+    }
+
+    fn is_reference(&self, path: &Path) -> bool {
+        self.classify(path) == Some(true)
+    }
+}
+
+/// Writes a comparison as plain text: the source files that may be
+/// corrupted first, then those without a copy, as those are the ones that
+/// would be lost, then those with one.
+///
+/// # Errors
+/// Returns any error raised by `writer`.
+pub fn write_comparison_to<W: Write>(
+    writer: &mut W,
+    comparison: &Comparison,
+    start_time: &str,
+) -> io::Result<()> {
+    let username = whoami::username();
+    let end_time = Local::now().format("%Y%m%d %H:%M:%S").to_string();
+
+    writeln!(writer, "Directory Comparison Report")?;
+    writeln!(writer, "Generated by: {username}")?;
+    writeln!(writer, "Start Time: {start_time}")?;
+    writeln!(writer, "End Time: {end_time}")?;
+    for (label, dirs) in [
+        ("Source", &comparison.sources),
+        ("Reference", &comparison.references),
+    ] {
+        if dirs.len() == 1 {
+            writeln!(writer, "{label} Directory: {}", dirs[0].display())?;
+        } else {
+            writeln!(writer, "{label} Directories:")?;
+            for dir in dirs {
+                writeln!(writer, " - {}", dir.display())?;
+            }
+        }
+    }
+    writeln!(writer)?;
+
+    if comparison.interrupted {
+        writeln!(
+            writer,
+            "Scan Interrupted: some files listed without a copy may have one."
+        )?;
+    }
+    writeln!(writer, "Files With A Copy: {}", comparison.present.len())?;
+    writeln!(
+        writer,
+        "Files Without A Copy: {} ({})",
+        comparison.missing.len(),
+        format_size(comparison.missing_bytes)
+    )?;
+    if !comparison.corrupted.is_empty() {
+        writeln!(writer, "Possibly Corrupted: {}", comparison.corrupted.len())?;
+    }
+    if !comparison.errors.is_empty() {
+        writeln!(
+            writer,
+            "Files Skipped Due To Errors: {}",
+            comparison.errors.len()
+        )?;
+    }
+    writeln!(writer)?;
+
+    if !comparison.corrupted.is_empty() {
+        writeln!(
+            writer,
+            "Possibly Corrupted (same name, size and modification time, different contents):"
+        )?;
+        for (path, other) in &comparison.corrupted {
+            writeln!(writer, "{} <> {}", path.display(), other.display())?;
+        }
+        writeln!(writer)?;
+    }
+    if !comparison.missing.is_empty() {
+        writeln!(writer, "Files Without A Copy:")?;
+        for path in &comparison.missing {
+            writeln!(writer, "{}", path.display())?;
+        }
+        writeln!(writer)?;
+    }
+    if !comparison.present.is_empty() {
+        writeln!(writer, "Files With A Copy:")?;
+        for (path, copy) in &comparison.present {
+            writeln!(writer, "{} => {}", path.display(), copy.display())?;
+        }
+        writeln!(writer)?;
+    }
+    if !comparison.errors.is_empty() {
+        writeln!(writer, "Skipped Files:")?;
+        for (path, error) in &comparison.errors {
+            writeln!(writer, "{}: {error}", path.display())?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_compare_dirs() {
+        let root = tempdir().expect("create temp dir");
+        let (source, reference) = (root.path().join("old"), root.path().join("new"));
+        let nested = source.join("archive");
+        fs::create_dir_all(&nested).expect("create dir");
+        fs::create_dir(&reference).expect("create dir");
+        fs::write(source.join("copied.txt"), "copied").expect("write file");
+        fs::write(reference.join("renamed.txt"), "copied").expect("write file");
+        // Copies within the sources alone do not count.
+        fs::write(source.join("twice.txt"), "twice").expect("write file");
+        fs::write(source.join("twice again.txt"), "twice").expect("write file");
+        // A reference inside a source holds reference files only.
+        fs::write(nested.join("kept.txt"), "nested").expect("write file");
+        fs::write(source.join("also kept.txt"), "nested").expect("write file");
+
+        let options = ScanOptions::new().quiet(true);
+        let comparison = compare_dirs(
+            std::slice::from_ref(&source),
+            &[reference.clone(), nested.clone()],
+            &options,
+        )
+        .expect("valid globs");
+        assert_eq!(
+            comparison.present,
+            [
+                (source.join("also kept.txt"), nested.join("kept.txt")),
+                (source.join("copied.txt"), reference.join("renamed.txt")),
+            ]
+        );
+        assert_eq!(
+            comparison.missing,
+            [source.join("twice again.txt"), source.join("twice.txt")]
+        );
+        assert_eq!(comparison.missing_bytes, 10);
+
+        let mut text = Vec::new();
+        write_comparison_to(&mut text, &comparison, "start").expect("write report");
+        let text = String::from_utf8(text).expect("UTF-8");
+        assert!(text.contains("Files Without A Copy: 2 (10 bytes)\n"));
+        assert!(text.contains(&format!(
+            "{} => {}",
+            source.join("copied.txt").display(),
+            reference.join("renamed.txt").display()
+        )));
+    }
+
+    #[test]
+    fn test_flags_possibly_corrupted_copies() {
+        let root = tempdir().expect("create temp dir");
+        let (source, reference) = (root.path().join("drive"), root.path().join("mirror"));
+        fs::create_dir(&source).expect("create dir");
+        fs::create_dir(&reference).expect("create dir");
+        let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        for (dir, photo, notes) in [
+            (&source, "photo one", "draft"),
+            (&reference, "photo 0ne", "final"),
+        ] {
+            fs::write(dir.join("photo.jpg"), photo).expect("write file");
+            fs::File::options()
+                .write(true)
+                .open(dir.join("photo.jpg"))
+                .and_then(|file| file.set_modified(time))
+                .expect("set modification time");
+            // Edited since: the times differ.
+            fs::write(dir.join("notes.txt"), notes).expect("write file");
+        }
+        fs::File::options()
+            .write(true)
+            .open(reference.join("notes.txt"))
+            .and_then(|file| file.set_modified(time))
+            .expect("set modification time");
+
+        let options = ScanOptions::new().quiet(true);
+        let comparison = compare_dirs(
+            std::slice::from_ref(&source),
+            std::slice::from_ref(&reference),
+            &options,
+        )
+        .expect("valid globs");
+        assert_eq!(comparison.missing.len(), 2);
+        assert_eq!(
+            comparison.corrupted,
+            [(source.join("photo.jpg"), reference.join("photo.jpg"))]
+        );
+
+        let mut text = Vec::new();
+        write_comparison_to(&mut text, &comparison, "start").expect("write report");
+        let text = String::from_utf8(text).expect("UTF-8");
+        assert!(text.contains("Possibly Corrupted: 1\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hard_links_count_as_copies() {
+        let root = tempdir().expect("create temp dir");
+        let (source, reference) = (root.path().join("old"), root.path().join("new"));
+        fs::create_dir(&source).expect("create dir");
+        fs::create_dir(&reference).expect("create dir");
+        fs::write(source.join("a.txt"), "linked").expect("write file");
+        fs::hard_link(source.join("a.txt"), reference.join("a.txt")).expect("hard link");
+
+        let options = ScanOptions::new().quiet(true);
+        let comparison = compare_dirs(
+            std::slice::from_ref(&source),
+            std::slice::from_ref(&reference),
+            &options,
+        )
+        .expect("valid globs");
+        assert_eq!(
+            comparison.present,
+            [(source.join("a.txt"), reference.join("a.txt"))]
+        );
+        assert!(comparison.missing.is_empty());
+    }
+}
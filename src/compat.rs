@@ -0,0 +1,173 @@
+//! Reports in the formats of other duplicate finders, so scripts and GUIs
+//! written for `fdupes` or `rmlint` can read this crate's results unchanged.
+
+use crate::json::quote;
+use crate::DuplicateReport;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Writes the report as `fdupes` prints it: one path per line, with a blank
+/// line after every group.
+///
+/// Paths are written as their raw bytes, as `fdupes` does, so a path that
+/// contains a line break cannot be told apart from two paths.
+///
+/// # Errors
+/// Returns any I/O error raised by `writer`.
+///
+/// # Example
+/// ```
+/// use std::path::PathBuf;
+/// use duplicate_file_finder::{write_fdupes_to, DuplicateGroup, DuplicateReport};
+///
+/// let report = DuplicateReport::from_groups(vec![DuplicateGroup {
+///     hash: String::from("somehash"),
+///     size: 1024,
+///     paths: vec![PathBuf::from("/tmp/b.txt"), PathBuf::from("/tmp/a.txt")],
+/// }]);
+/// let mut buffer = Vec::new();
+/// write_fdupes_to(&mut buffer, &report).unwrap();
+/// assert_eq!(buffer, b"/tmp/a.txt\n/tmp/b.txt\n\n");
+/// ```
+pub fn write_fdupes_to<W: Write>(writer: &mut W, report: &DuplicateReport) -> io::Result<()> {
+    for group in &report.groups {
+        for path in &group.paths {
+            writer.write_all(path.as_os_str().as_encoded_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Writes the report as the JSON array `rmlint` produces with `-o json`.
+///
+/// The array holds a header object, one `duplicate_file` object per file in
+/// report order, and a footer object with the totals. The first path of each
+/// group is marked `is_original`, matching `--keep first`. Each file's
+/// `depth` is its traversal depth below the scanned directory that holds it,
+/// 1 for a file directly inside. Members `rmlint` fills from information
+/// this crate does not collect, such as `disk_id` and the header's `args`,
+/// are left out, and `checksum` is the group's hash as written in the other
+/// reports.
+///
+/// # Errors
+/// Returns any I/O error raised by `writer`.
+///
+/// # Example
+/// ```
+/// use std::path::PathBuf;
+/// use duplicate_file_finder::{write_rmlint_to, DuplicateGroup, DuplicateReport};
+///
+/// let report = DuplicateReport::from_groups(vec![DuplicateGroup {
+///     hash: String::from("somehash"),
+///     size: 1024,
+///     paths: vec![PathBuf::from("/tmp/a.txt"), PathBuf::from("/tmp/b.txt")],
+/// }]);
+/// let mut buffer = Vec::new();
+/// write_rmlint_to(&mut buffer, &report).unwrap();
+/// let json = String::from_utf8(buffer).unwrap();
+/// assert!(json.contains("\"type\": \"duplicate_file\""));
+/// assert!(json.contains("\"duplicate_sets\": 1"));
+/// ```
+pub fn write_rmlint_to<W: Write>(writer: &mut W, report: &DuplicateReport) -> io::Result<()> {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    writeln!(writer, "[")?;
+    writeln!(
+        writer,
+        "{{\"description\": \"rmlint json-dump of lint files\", \"cwd\": {}, \"progress\": 0}},",
+        quote(&cwd.to_string_lossy())
+    )?;
+
+    let mut id: u64 = 0;
+    for group in &report.groups {
+        for (index, path) in group.paths.iter().enumerate() {
+            id += 1;
+            let metadata = report.metadata.get(path);
+            let mtime = metadata
+                .and_then(|m| m.modified)
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or_else(|| "null".to_string(), |d| d.as_secs().to_string());
+            let inode = metadata
+                .and_then(|m| m.inode)
+                .map_or_else(|| "null".to_string(), |inode| inode.to_string());
+            writeln!(
+                writer,
+                "{{\"id\": {id}, \"type\": \"duplicate_file\", \"progress\": 100, \
+                 \"checksum\": {}, \"path\": {}, \"size\": {}, \"depth\": {}, \
+                 \"inode\": {inode}, \"is_original\": {}, \"mtime\": {mtime}}},",
+                quote(&group.hash),
+                quote(&path.to_string_lossy()),
+                group.size,
+                depth(path, &report.roots),
+                index == 0
+            )?;
+        }
+    }
+
+    writeln!(
+        writer,
+        "{{\"aborted\": {}, \"progress\": 100, \"duplicates\": {}, \"duplicate_sets\": {}, \
+         \"total_lint_size\": {}}}",
+        report.interrupted,
+        report.redundant_files(),
+        report.total_groups,
+        report.total_reclaimable_bytes
+    )?;
+    writeln!(writer, "]")
+}
+
+/// Counts the components of `path` below the first of `roots` holding it,
+/// or below the filesystem root when none does.
+fn depth(path: &Path, roots: &[PathBuf]) -> usize {
+    let relative = roots
+        .iter()
+        .find_map(|root| path.strip_prefix(root).ok())
+        .unwrap_or(path);
+    relative
+        .components()
+        .filter(|c| matches!(c, std::path::Component::Normal(_)))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::{self, Value};
+    use crate::DuplicateGroup;
+
+    #[test]
+    fn test_write_rmlint_parses() {
+        let mut report = DuplicateReport::from_groups(vec![DuplicateGroup {
+            hash: "abc".to_string(),
+            size: 10,
+            paths: vec![
+                PathBuf::from("/scan/a.txt"),
+                PathBuf::from("/scan/nested/dir/b.txt"),
+                PathBuf::from("/elsewhere/c.txt"),
+            ],
+        }]);
+        report.roots = vec![PathBuf::from("/scan")];
+
+        let mut buffer = Vec::new();
+        write_rmlint_to(&mut buffer, &report).expect("write");
+        let doc = json::parse(std::str::from_utf8(&buffer).expect("UTF-8")).expect("valid JSON");
+        let items = doc.as_array().expect("array");
+
+        assert_eq!(items.len(), 5);
+        let depths: Vec<u64> = items[1..4]
+            .iter()
+            .filter_map(|item| item.get("depth").and_then(Value::as_u64))
+            .collect();
+        // Paths are sorted, so the file outside the scanned directory is first.
+        assert_eq!(depths, [2, 1, 3]);
+        assert_eq!(items[1].get("is_original"), Some(&Value::Bool(true)));
+        assert_eq!(items[2].get("is_original"), Some(&Value::Bool(false)));
+        assert_eq!(items[4].get("duplicates").and_then(Value::as_u64), Some(2));
+        assert_eq!(
+            items[4].get("total_lint_size").and_then(Value::as_u64),
+            Some(20)
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Reading compressed files, for
+//! [`ScanOptions::decompress`](crate::ScanOptions::decompress).
+//!
+//! Gzip files are decompressed here. Bzip2, xz and Zstandard files are
+//! decompressed by the `bzip2`, `xz` and `zstd` commands, which must be on
+//! the `PATH` for those files to be compared.
+
+use crate::inflate::Inflate;
+use crate::HashAlgo;
+use log::debug;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// How a compressed file is decompressed.
+#[derive(Clone, Copy)]
+enum Decoder {
+    Gzip,
+    /// By running this command with `-dc`.
+    Command(&'static str),
+}
+
+/// The extensions of compressed files, without the dot, and how each is
+/// decompressed.
+const FORMATS: [(&str, Decoder); 4] = [
+    ("gz", Decoder::Gzip),
+    ("bz2", Decoder::Command("bzip2")),
+    ("xz", Decoder::Command("xz")),
+    ("zst", Decoder::Command("zstd")),
+];
+
+fn decoder(path: &Path) -> Option<Decoder> {
+    let ext = path.extension().and_then(OsStr::to_str)?;
+    FORMATS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(ext))
+        .map(|&(_, decoder)| decoder)
+}
+
+/// Returns `true` if `path` names a gzip, bzip2, xz or Zstandard file.
+pub(crate) fn is_compressed(path: &Path) -> bool {
+    decoder(path).is_some()
+}
+
+/// Returns the size and `algo` hash of the decompressed contents of the
+/// compressed file at `path`.
+///
+/// Every member of a gzip file made of several is read, and each is checked
+/// against its stored checksum and length.
+///
+/// # Errors
+/// Returns an error if the file cannot be read or is not validly
+/// compressed, if a gzip checksum does not match, or if the command that
+/// decompresses it fails.
+pub(crate) fn hash_decompressed(path: &Path, algo: HashAlgo) -> io::Result<(u64, String)> {
+    let tool = match decoder(path) {
+        Some(Decoder::Gzip) => {
+            let file = BufReader::new(File::open(path)?);
+            return hash_reader(Inflate::gzip(file)?, algo);
+        }
+        Some(Decoder::Command(tool)) => tool,
+        None => return Err(io::ErrorKind::InvalidInput.into()),
+    };
+
+    debug!("Running {tool} -dc {}", path.display());
+    let mut child = Command::new(tool)
+        .arg("-dc")
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot run {tool}: {e}")))?;
+    let hashed = match child.stdout.take() {
+        Some(stdout) => hash_reader(stdout, algo),
+        None => Err(io::ErrorKind::BrokenPipe.into()),
+    };
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().next().unwrap_or("no details");
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{tool} failed: {reason}"),
+        ));
+    }
+    hashed
+}
+
+/// Hashes everything `reader` yields, returning how many bytes it was and
+/// their hash.
+pub(crate) fn hash_reader(mut reader: impl Read, algo: HashAlgo) -> io::Result<(u64, String)> {
+    let mut hasher = algo.hasher();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => {
+                hasher.update(&buffer[..n]);
+                size += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok((size, hasher.finish_hex()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_compressed() {
+        assert!(is_compressed(Path::new("logs/app.log.GZ")));
+        assert!(is_compressed(Path::new("dump.sql.zst")));
+        assert!(is_compressed(Path::new("backup.tar.xz")));
+        assert!(!is_compressed(Path::new("notes.txt")));
+        assert!(!is_compressed(Path::new("gz")));
+        assert!(hash_decompressed(Path::new("notes.txt"), HashAlgo::Sha256).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! The web page served at `/` by [`serve`](crate::server::serve).
+//!
+//! Like the HTML report, the page is self-contained: its style and script
+//! are inline, so the server needs no files beside the binary. All it knows
+//! comes from the API, which the script polls while a scan runs.
+
+/// The dashboard, a single page that starts scans, shows their progress,
+/// lists the groups of the selected scan with thumbnails of images, and
+/// deletes, trashes or hard links the files ticked.
+pub(crate) const PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Duplicate File Finder</title>
+<style>
+body{font-family:sans-serif;margin:2em;color:#222}
+fieldset{margin-bottom:1.5em}
+label{display:block;margin:.3em 0}
+textarea{width:100%;font-family:monospace}
+table{border-collapse:collapse;margin-bottom:1.5em;width:100%}
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:left}
+th{background:#eee}
+tr.done td{color:#888;text-decoration:line-through}
+progress{width:20em}
+img{max-width:96px;max-height:96px;vertical-align:middle;margin-right:8px}
+.error{color:#a00;font-weight:bold}
+#actions{position:sticky;top:0;background:#fff;padding:.5em 0;border-bottom:1px solid #ccc}
+</style>
+</head>
+<body>
+<h1>Duplicate File Finder</h1>
+<form id="start">
+<fieldset>
+<legend>New scan</legend>
+<label>Directories, one per line<br><textarea name="directories" rows="3" required></textarea></label>
+<label>Exclude, one glob per line<br><textarea name="exclude" rows="2"></textarea></label>
+<label>Minimum size in bytes <input name="min_size" type="number" min="0"></label>
+<button>Scan</button> <span id="start-error" class="error"></span>
+</fieldset>
+</form>
+<h2>Scans</h2>
+<table><thead><tr><th>Scan</th><th>Directories</th><th>State</th><th></th></tr></thead>
+<tbody id="scans"></tbody></table>
+<div id="results" hidden>
+<h2 id="results-title"></h2>
+<div id="actions">
+<span id="selected">0 files selected</span>
+<button data-action="delete">Delete selected</button>
+<button data-action="trash">Move selected to trash</button>
+<button data-action="hardlink">Hard link selected</button>
+<span id="action-result"></span>
+</div>
+<div id="groups"></div>
+</div>
+<script>
+var shown = null, timer = null;
+var images = /\.(png|jpe?g|gif|webp|bmp|avif)$/i;
+
+function api(method, path, body) {
+  var init = {method: method, headers: {'Content-Type': 'application/json'}};
+  if (body !== undefined) init.body = JSON.stringify(body);
+  return fetch(path, init).then(function (response) {
+    return response.json().then(function (json) {
+      if (!response.ok) throw new Error(json.error || response.statusText);
+      return json;
+    });
+  });
+}
+
+function el(tag, text) {
+  var node = document.createElement(tag);
+  if (text !== undefined) node.textContent = text;
+  return node;
+}
+
+function size(bytes) {
+  var units = ['B', 'KB', 'MB', 'GB', 'TB'], i = 0;
+  while (bytes >= 1024 && i < units.length - 1) { bytes /= 1024; i++; }
+  return (i ? bytes.toFixed(2) : bytes) + ' ' + units[i];
+}
+
+function lines(text) {
+  return text.split('\n').map(function (line) { return line.trim(); }).filter(Boolean);
+}
+
+document.getElementById('start').addEventListener('submit', function (event) {
+  event.preventDefault();
+  var form = event.target, body = {
+    directories: lines(form.directories.value),
+    exclude: lines(form.exclude.value)
+  };
+  if (form.min_size.value) body.min_size = Number(form.min_size.value);
+  var error = document.getElementById('start-error');
+  error.textContent = '';
+  api('POST', '/scans', body).then(refresh, function (e) { error.textContent = e.message; });
+});
+
+function refresh() {
+  clearTimeout(timer);
+  api('GET', '/scans').then(function (scans) {
+    var rows = document.getElementById('scans');
+    rows.replaceChildren();
+    scans.slice().reverse().forEach(function (scan) {
+      var row = el('tr'), state = el('td'), open = el('td');
+      row.appendChild(el('td', '#' + scan.id));
+      row.appendChild(el('td', scan.directories.join(', ')));
+      if (scan.state === 'running') {
+        var bar = el('progress');
+        bar.max = scan.total || 1;
+        bar.value = scan.done;
+        state.append((scan.stage || 'starting') + ' ', bar);
+        var cancel = el('button', 'Cancel');
+        cancel.onclick = function () { api('DELETE', '/scans/' + scan.id).then(refresh); };
+        open.appendChild(cancel);
+      } else {
+        state.textContent = scan.state + ': ' + scan.groups + ' groups, ' +
+          size(scan.reclaimable_bytes) + ' reclaimable';
+        var view = el('button', 'Show');
+        view.onclick = function () { show(scan.id); };
+        open.appendChild(view);
+      }
+      row.append(state, open);
+      rows.appendChild(row);
+    });
+    if (scans.some(function (scan) { return scan.state === 'running'; })) {
+      timer = setTimeout(refresh, 1000);
+    }
+  });
+}
+
+function show(id) {
+  api('GET', '/scans/' + id + '/report').then(function (report) {
+    shown = id;
+    document.getElementById('results').hidden = false;
+    document.getElementById('results-title').textContent = 'Scan #' + id + ': ' +
+      report.total_groups + ' groups, ' + size(report.total_reclaimable_bytes) + ' reclaimable';
+    document.getElementById('action-result').textContent = '';
+    var groups = document.getElementById('groups');
+    groups.replaceChildren();
+    report.groups.forEach(function (group, i) {
+      var table = el('table'), head = el('tr');
+      head.appendChild(el('th', ''));
+      head.appendChild(el('th', 'Group ' + (i + 1) + ': ' + group.files.length + ' files of ' +
+        size(group.size)));
+      head.appendChild(el('th', 'Modified'));
+      table.appendChild(head);
+      group.files.forEach(function (file) {
+        var row = el('tr'), tick = el('input'), name = el('td');
+        tick.type = 'checkbox';
+        tick.value = file.path;
+        tick.onchange = count;
+        row.appendChild(el('td')).appendChild(tick);
+        if (images.test(file.path)) {
+          var thumbnail = el('img');
+          thumbnail.loading = 'lazy';
+          thumbnail.alt = '';
+          thumbnail.src = '/scans/' + id + '/preview?path=' + encodeURIComponent(file.path);
+          name.appendChild(thumbnail);
+        }
+        name.append(file.path);
+        row.appendChild(name);
+        row.appendChild(el('td', file.modified === null ? 'unknown' :
+          new Date(file.modified * 1000).toLocaleString()));
+        table.appendChild(row);
+      });
+      groups.appendChild(table);
+    });
+    count();
+  }, function (e) { document.getElementById('action-result').textContent = e.message; });
+}
+
+function ticked() {
+  return Array.from(document.querySelectorAll('#groups input:checked:not(:disabled)'));
+}
+
+function count() {
+  document.getElementById('selected').textContent = ticked().length + ' files selected';
+}
+
+document.querySelectorAll('#actions button').forEach(function (button) {
+  button.addEventListener('click', function () {
+    var boxes = ticked(), result = document.getElementById('action-result');
+    var body = {action: button.dataset.action, files: boxes.map(function (box) { return box.value; })};
+    if (!body.files.length) { result.textContent = 'Tick the files to act on first.'; return; }
+    api('POST', '/scans/' + shown + '/actions', body).then(function (plan) {
+      if (!confirm(button.textContent + ': ' + plan.files.length + ' files, ' +
+          size(plan.bytes) + '. Continue?')) return;
+      body.confirm = true;
+      return api('POST', '/scans/' + shown + '/actions', body).then(function (outcome) {
+        var changed = {};
+        outcome.files.forEach(function (file) { changed[file.path] = true; });
+        boxes.forEach(function (box) {
+          if (!changed[box.value]) return;
+          box.checked = false;
+          box.disabled = true;
+          box.closest('tr').className = 'done';
+        });
+        result.textContent = outcome.files.length + ' files changed, ' + size(outcome.bytes) +
+          ' reclaimed' + (outcome.failed.length ? ', ' + outcome.failed.length + ' failed' : '') + '.';
+        count();
+      });
+    }).catch(function (e) { result.textContent = e.message; });
+  });
+});
+
+refresh();
+</script>
+</body>
+</html>
+"#;
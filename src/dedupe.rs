@@ -0,0 +1,1263 @@
+//! Actions that reclaim space from confirmed duplicates: replacing them with
+//! hard or symbolic links or reflinked clones, deleting all but one copy or
+//! moving the others to the trash, or listing the redundant copies for
+//! another tool to remove.
+//!
+//! Every action defaults to a dry run: pass `dry_run = false` only when the
+//! user has explicitly asked for files to be changed.
+
+use crate::{format_size, trash, DuplicateReport};
+use log::{info, warn};
+use std::fs::{self, Metadata};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The modification time and permissions a duplicate had before it was
+/// changed, so that a restore can give them back to the copy it recreates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileAttributes {
+    /// When the file was last modified, if the platform reports it.
+    pub modified: Option<SystemTime>,
+    /// Whether the file was read-only.
+    pub readonly: bool,
+    /// The Unix permission bits, or `None` on other platforms.
+    pub mode: Option<u32>,
+}
+
+impl FileAttributes {
+    /// Reads the attributes of `path`, or `None` if it cannot be read.
+    #[must_use]
+    pub fn read(path: &Path) -> Option<Self> {
+        fs::metadata(path).ok().map(|meta| Self::of(&meta))
+    }
+
+    fn of(meta: &Metadata) -> Self {
+        Self {
+            modified: meta.modified().ok(),
+            readonly: meta.permissions().readonly(),
+            mode: mode(meta),
+        }
+    }
+}
+
+#[cfg(unix)]
+#[allow(clippy::unnecessary_wraps)]
+fn mode(meta: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Some(meta.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn mode(_meta: &Metadata) -> Option<u32> {
+    None
+}
+
+/// A duplicate that was (or, in a dry run, would be) replaced by a link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkedFile {
+    /// The duplicate whose contents were replaced.
+    pub path: PathBuf,
+    /// The canonical copy the duplicate now links to.
+    pub target: PathBuf,
+    /// Bytes freed by replacing this file.
+    pub size: u64,
+    /// What the file's attributes were before it was replaced.
+    pub attributes: Option<FileAttributes>,
+}
+
+/// The outcome of [`hardlink_duplicates`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HardlinkSummary {
+    /// Whether the run only reported what it would do.
+    pub dry_run: bool,
+    /// Files replaced by hard links.
+    pub linked: Vec<LinkedFile>,
+    /// Files left untouched, with the reason they were skipped.
+    pub skipped: Vec<(PathBuf, String)>,
+    /// Total bytes freed by the links.
+    pub reclaimed_bytes: u64,
+}
+
+impl HardlinkSummary {
+    /// Writes a human-readable section describing the links to `writer`.
+    ///
+    /// # Errors
+    /// Returns any I/O error raised by the writer.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_links(
+            writer,
+            "Hard Links",
+            self.dry_run,
+            &self.linked,
+            &self.skipped,
+            self.reclaimed_bytes,
+        )
+    }
+}
+
+/// The outcome of [`symlink_duplicates`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymlinkSummary {
+    /// Whether the run only reported what it would do.
+    pub dry_run: bool,
+    /// Files replaced by symbolic links; each `target` is an absolute path.
+    pub linked: Vec<LinkedFile>,
+    /// Files left untouched, with the reason they were skipped.
+    pub skipped: Vec<(PathBuf, String)>,
+    /// Total bytes freed by the links.
+    pub reclaimed_bytes: u64,
+}
+
+impl SymlinkSummary {
+    /// Writes a human-readable section describing the links to `writer`.
+    ///
+    /// # Errors
+    /// Returns any I/O error raised by the writer.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_links(
+            writer,
+            "Symbolic Links",
+            self.dry_run,
+            &self.linked,
+            &self.skipped,
+            self.reclaimed_bytes,
+        )
+    }
+}
+
+/// The outcome of [`reflink_duplicates`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReflinkSummary {
+    /// Whether the run only reported what it would do.
+    pub dry_run: bool,
+    /// Files replaced by clones sharing the canonical copy's extents.
+    pub linked: Vec<LinkedFile>,
+    /// Files left untouched, with the reason they were skipped.
+    pub skipped: Vec<(PathBuf, String)>,
+    /// Total bytes freed by the clones.
+    pub reclaimed_bytes: u64,
+}
+
+impl ReflinkSummary {
+    /// Writes a human-readable section describing the clones to `writer`.
+    ///
+    /// # Errors
+    /// Returns any I/O error raised by the writer.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_links(
+            writer,
+            "Reflinks",
+            self.dry_run,
+            &self.linked,
+            &self.skipped,
+            self.reclaimed_bytes,
+        )
+    }
+}
+
+/// Writes the report section shared by the link and reflink passes.
+fn write_links<W: Write>(
+    writer: &mut W,
+    title: &str,
+    dry_run: bool,
+    linked: &[LinkedFile],
+    skipped: &[(PathBuf, String)],
+    reclaimed_bytes: u64,
+) -> io::Result<()> {
+    if dry_run {
+        writeln!(writer, "{title} (dry run, no files changed):")?;
+    } else {
+        writeln!(writer, "{title}:")?;
+    }
+    for file in linked {
+        writeln!(
+            writer,
+            "{} -> {}",
+            file.path.display(),
+            file.target.display()
+        )?;
+    }
+    for (path, reason) in skipped {
+        writeln!(writer, "Skipped {}: {reason}", path.display())?;
+    }
+    let label = if dry_run {
+        "Space That Would Be Reclaimed"
+    } else {
+        "Space Reclaimed"
+    };
+    writeln!(writer, "{label}: {}", format_size(reclaimed_bytes))?;
+    writeln!(writer)
+}
+
+/// Replaces every duplicate with a hard link to the first file in its group.
+///
+/// Before linking, each duplicate is checked to be on the same filesystem as
+/// the canonical copy. The replacement is atomic: a link is created under a
+/// temporary name in the duplicate's directory and then renamed over it, so a
+/// failure never leaves the original path missing. Files that are already
+/// links to the canonical copy are skipped. On platforms where the device
+/// cannot be compared, every file is skipped with a warning.
+///
+/// # Arguments
+/// * `report` - The confirmed duplicate groups to act on.
+/// * `dry_run` - When `true`, nothing is changed and the summary describes what
+///   would happen.
+///
+/// # Returns
+/// A [`HardlinkSummary`] listing linked and skipped files and the bytes freed.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::dedupe::hardlink_duplicates;
+/// use duplicate_file_finder::find_duplicates_report;
+/// use tempfile::tempdir;
+///
+/// fn demo() -> std::io::Result<()> {
+///     let dir = tempdir()?;
+///     std::fs::write(dir.path().join("a.txt"), b"same")?;
+///     std::fs::write(dir.path().join("b.txt"), b"same")?;
+///     let report = find_duplicates_report(dir.path());
+///     let summary = hardlink_duplicates(&report, true);
+///     assert_eq!(summary.linked.len(), 1);
+///     Ok(())
+/// }
+/// ```
+#[must_use]
+pub fn hardlink_duplicates(report: &DuplicateReport, dry_run: bool) -> HardlinkSummary {
+    let mut summary = HardlinkSummary {
+        dry_run,
+        ..HardlinkSummary::default()
+    };
+
+    for group in &report.groups {
+        let Some((canonical, others)) = group.paths.split_first() else {
+            continue;
+        };
+        let canonical_meta = match fs::metadata(canonical) {
+            Ok(meta) => meta,
+            Err(e) => {
+                warn!("Cannot read {}: {e}", canonical.display());
+                for path in others {
+                    summary
+                        .skipped
+                        .push((path.clone(), format!("canonical copy unreadable: {e}")));
+                }
+                continue;
+            }
+        };
+
+        for path in others {
+            if let Err(reason) = check_linkable(&canonical_meta, path) {
+                warn!("Skipping {}: {reason}", path.display());
+                summary.skipped.push((path.clone(), reason));
+                continue;
+            }
+            let attributes = FileAttributes::read(path);
+            if !dry_run {
+                if let Err(e) = replace_with_link(canonical, path) {
+                    warn!("Failed to link {}: {e}", path.display());
+                    summary.skipped.push((path.clone(), e.to_string()));
+                    continue;
+                }
+                info!("Linked {} -> {}", path.display(), canonical.display());
+            }
+            summary.reclaimed_bytes += group.size;
+            summary.linked.push(LinkedFile {
+                path: path.clone(),
+                target: canonical.clone(),
+                size: group.size,
+                attributes,
+            });
+        }
+    }
+
+    summary
+}
+
+/// Replaces every duplicate with a symbolic link to the first file in its group.
+///
+/// Unlike hard links, symbolic links can point across filesystems. Each link
+/// holds the absolute, canonical path of the kept copy, so it stays valid
+/// wherever it is read from. The replacement is atomic: the link is created
+/// under a temporary name in the duplicate's directory and then renamed over
+/// it. A group is left untouched if its first file is itself a symbolic link,
+/// since linking to it would build a chain that breaks when the link does.
+/// Duplicates that are already symbolic links are skipped. On platforms
+/// without symbolic links every file is skipped.
+///
+/// On Windows, creating symbolic links requires Developer Mode or an
+/// elevated prompt; files that cannot be linked are reported as skipped.
+///
+/// # Arguments
+/// * `report` - The confirmed duplicate groups to act on.
+/// * `dry_run` - When `true`, nothing is changed and the summary describes what
+///   would happen.
+///
+/// # Returns
+/// A [`SymlinkSummary`] listing linked and skipped files and the bytes freed.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::dedupe::symlink_duplicates;
+/// use duplicate_file_finder::find_duplicates_report;
+/// use tempfile::tempdir;
+///
+/// fn demo() -> std::io::Result<()> {
+///     let dir = tempdir()?;
+///     std::fs::write(dir.path().join("a.txt"), b"same")?;
+///     std::fs::write(dir.path().join("b.txt"), b"same")?;
+///     let report = find_duplicates_report(dir.path());
+///     let summary = symlink_duplicates(&report, true);
+///     assert_eq!(summary.linked.len(), 1);
+///     Ok(())
+/// }
+/// ```
+#[must_use]
+pub fn symlink_duplicates(report: &DuplicateReport, dry_run: bool) -> SymlinkSummary {
+    let mut summary = SymlinkSummary {
+        dry_run,
+        ..SymlinkSummary::default()
+    };
+
+    for group in &report.groups {
+        let Some((canonical, others)) = group.paths.split_first() else {
+            continue;
+        };
+        let target = match link_target(canonical) {
+            Ok(target) => target,
+            Err(reason) => {
+                warn!("Not linking to {}: {reason}", canonical.display());
+                for path in others {
+                    summary
+                        .skipped
+                        .push((path.clone(), format!("canonical copy {reason}")));
+                }
+                continue;
+            }
+        };
+
+        for path in others {
+            let attributes = match fs::symlink_metadata(path) {
+                Ok(meta) if meta.file_type().is_symlink() => {
+                    let reason = "already a symbolic link".to_string();
+                    warn!("Skipping {}: {reason}", path.display());
+                    summary.skipped.push((path.clone(), reason));
+                    continue;
+                }
+                Ok(meta) => FileAttributes::of(&meta),
+                Err(e) => {
+                    warn!("Skipping {}: {e}", path.display());
+                    summary.skipped.push((path.clone(), e.to_string()));
+                    continue;
+                }
+            };
+            if !dry_run {
+                if let Err(e) = replace_with_symlink(&target, path) {
+                    warn!("Failed to link {}: {e}", path.display());
+                    summary.skipped.push((path.clone(), e.to_string()));
+                    continue;
+                }
+                info!("Linked {} -> {}", path.display(), target.display());
+            }
+            summary.reclaimed_bytes += group.size;
+            summary.linked.push(LinkedFile {
+                path: path.clone(),
+                target: target.clone(),
+                size: group.size,
+                attributes: Some(attributes),
+            });
+        }
+    }
+
+    summary
+}
+
+/// Replaces every duplicate with a reflink clone of the first file in its group.
+///
+/// A clone shares the canonical copy's data blocks until either file is
+/// written to, so the space is freed while both paths stay independent files:
+/// editing one never changes the other, unlike a hard link. The clone is made
+/// under a temporary name in the duplicate's directory, given the duplicate's
+/// permissions and modification time, and renamed over it.
+///
+/// Cloning needs a filesystem with shared extents, such as Btrfs or XFS, and
+/// is currently implemented on Linux only. A dry run still clones each file to
+/// a temporary name and removes it again, so that files on filesystems without
+/// reflink support are reported as skipped rather than as would-be clones.
+/// Duplicates on another filesystem than the canonical copy, or already hard
+/// linked to it, are skipped.
+///
+/// # Arguments
+/// * `report` - The confirmed duplicate groups to act on.
+/// * `dry_run` - When `true`, no duplicate is replaced and the summary
+///   describes what would happen.
+///
+/// # Returns
+/// A [`ReflinkSummary`] listing cloned and skipped files and the bytes freed.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::dedupe::reflink_duplicates;
+/// use duplicate_file_finder::find_duplicates_report;
+/// use tempfile::tempdir;
+///
+/// fn demo() -> std::io::Result<()> {
+///     let dir = tempdir()?;
+///     std::fs::write(dir.path().join("a.txt"), b"same")?;
+///     std::fs::write(dir.path().join("b.txt"), b"same")?;
+///     let report = find_duplicates_report(dir.path());
+///     let summary = reflink_duplicates(&report, true);
+///     // Filesystems without reflinks, such as ext4, skip the file instead.
+///     assert_eq!(summary.linked.len() + summary.skipped.len(), 1);
+///     Ok(())
+/// }
+/// ```
+#[must_use]
+pub fn reflink_duplicates(report: &DuplicateReport, dry_run: bool) -> ReflinkSummary {
+    let mut summary = ReflinkSummary {
+        dry_run,
+        ..ReflinkSummary::default()
+    };
+
+    for group in &report.groups {
+        let Some((canonical, others)) = group.paths.split_first() else {
+            continue;
+        };
+        let canonical_meta = match fs::metadata(canonical) {
+            Ok(meta) => meta,
+            Err(e) => {
+                warn!("Cannot read {}: {e}", canonical.display());
+                for path in others {
+                    summary
+                        .skipped
+                        .push((path.clone(), format!("canonical copy unreadable: {e}")));
+                }
+                continue;
+            }
+        };
+
+        for path in others {
+            if let Err(reason) = check_reflinkable(&canonical_meta, path) {
+                warn!("Skipping {}: {reason}", path.display());
+                summary.skipped.push((path.clone(), reason));
+                continue;
+            }
+            let attributes = FileAttributes::read(path);
+            if let Err(e) = replace_with_clone(canonical, path, dry_run) {
+                warn!("Failed to clone {}: {e}", path.display());
+                summary.skipped.push((path.clone(), e.to_string()));
+                continue;
+            }
+            if !dry_run {
+                info!("Cloned {} -> {}", path.display(), canonical.display());
+            }
+            summary.reclaimed_bytes += group.size;
+            summary.linked.push(LinkedFile {
+                path: path.clone(),
+                target: canonical.clone(),
+                size: group.size,
+                attributes,
+            });
+        }
+    }
+
+    summary
+}
+
+/// Returns the absolute path a symbolic link to `canonical` should hold, or
+/// why no link should point at it.
+fn link_target(canonical: &Path) -> Result<PathBuf, String> {
+    let meta = fs::symlink_metadata(canonical).map_err(|e| format!("unreadable: {e}"))?;
+    if meta.file_type().is_symlink() {
+        return Err("is itself a symbolic link".to_string());
+    }
+    fs::canonicalize(canonical).map_err(|e| format!("unreadable: {e}"))
+}
+
+/// How [`delete_duplicates`], [`trash_duplicates`] and [`write_print0_to`]
+/// choose the one file to keep in each group.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum KeepStrategy {
+    /// Keep the first path listed in the group.
+    #[default]
+    First,
+    /// Keep the file with the earliest modification time.
+    Oldest,
+    /// Keep the file with the latest modification time.
+    Newest,
+    /// Keep the file with the fewest path components.
+    ShortestPath,
+    /// Keep the file under whichever scanned directory was listed first, so
+    /// copies in later directories are the ones removed.
+    FirstListedDir,
+}
+
+/// A duplicate that was (or, in a dry run, would be) deleted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeletedFile {
+    /// The duplicate that was removed.
+    pub path: PathBuf,
+    /// The copy kept in its place.
+    pub kept: PathBuf,
+    /// Bytes freed by removing this file.
+    pub size: u64,
+    /// Where [`trash_duplicates`] moved the file; `None` after a deletion or
+    /// a dry run.
+    pub trashed_to: Option<PathBuf>,
+    /// What the file's attributes were before it was removed, read by
+    /// [`remove_selected`] and the passes that remove duplicates; any value
+    /// passed in is ignored.
+    pub attributes: Option<FileAttributes>,
+}
+
+/// The outcome of [`delete_duplicates`] or [`trash_duplicates`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeleteSummary {
+    /// Whether the run only reported what it would do.
+    pub dry_run: bool,
+    /// Whether files were moved to the trash rather than deleted outright.
+    pub trashed: bool,
+    /// Files removed.
+    pub deleted: Vec<DeletedFile>,
+    /// Files that could not be removed, with the reason.
+    pub failed: Vec<(PathBuf, String)>,
+    /// Total bytes freed by the deletions.
+    pub freed_bytes: u64,
+}
+
+impl DeleteSummary {
+    /// Writes a human-readable section describing the deletions to `writer`.
+    ///
+    /// # Errors
+    /// Returns any I/O error raised by the writer.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let title = if self.trashed {
+            "Trashed Files"
+        } else {
+            "Deleted Files"
+        };
+        if self.dry_run {
+            writeln!(writer, "{title} (dry run, no files changed):")?;
+        } else {
+            writeln!(writer, "{title}:")?;
+        }
+        for file in &self.deleted {
+            writeln!(
+                writer,
+                "{} (kept {})",
+                file.path.display(),
+                file.kept.display()
+            )?;
+        }
+        for (path, reason) in &self.failed {
+            writeln!(writer, "Failed {}: {reason}", path.display())?;
+        }
+        let label = match (self.dry_run, self.trashed) {
+            (true, false) => "Space That Would Be Freed",
+            (false, false) => "Space Freed",
+            (_, true) => "Space Freed Once the Trash Is Emptied",
+        };
+        writeln!(writer, "{label}: {}", format_size(self.freed_bytes))?;
+        writeln!(writer)
+    }
+}
+
+/// Deletes every duplicate except one file per group, chosen by `keep`.
+///
+/// A group is left untouched if the kept file can no longer be found, or if
+/// `keep` needs modification times that cannot be read. A failed deletion is
+/// logged and recorded in the summary, and the remaining files are still
+/// processed.
+///
+/// # Arguments
+/// * `report` - The confirmed duplicate groups to act on.
+/// * `keep` - Rule selecting the file to keep in each group.
+/// * `dry_run` - When `true`, nothing is deleted and the summary describes
+///   what would happen.
+///
+/// # Returns
+/// A [`DeleteSummary`] listing deleted and failed files and the bytes freed.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::dedupe::{delete_duplicates, KeepStrategy};
+/// use duplicate_file_finder::find_duplicates_report;
+/// use tempfile::tempdir;
+///
+/// fn demo() -> std::io::Result<()> {
+///     let dir = tempdir()?;
+///     std::fs::write(dir.path().join("a.txt"), b"same")?;
+///     std::fs::create_dir(dir.path().join("nested"))?;
+///     std::fs::write(dir.path().join("nested").join("b.txt"), b"same")?;
+///     let report = find_duplicates_report(dir.path());
+///     let summary = delete_duplicates(&report, KeepStrategy::ShortestPath, true);
+///     assert!(summary.deleted[0].path.ends_with("nested/b.txt"));
+///     Ok(())
+/// }
+/// ```
+#[must_use]
+pub fn delete_duplicates(
+    report: &DuplicateReport,
+    keep: KeepStrategy,
+    dry_run: bool,
+) -> DeleteSummary {
+    remove_duplicates(report, keep, dry_run, false)
+}
+
+/// Moves every duplicate except one file per group, chosen by `keep`, to the
+/// trash, where it can be restored from the file manager.
+///
+/// This behaves like [`delete_duplicates`], but files are only moved. On
+/// Linux and other Unix desktops the freedesktop.org trash is used: files on
+/// the home filesystem go to `~/.local/share/Trash`, and files on other
+/// filesystems to a `.Trash-$UID` directory at the top of that filesystem.
+/// On macOS files go to `~/.Trash`. On other platforms every file fails.
+/// The space is only freed once the trash is emptied.
+///
+/// # Arguments
+/// * `report` - The confirmed duplicate groups to act on.
+/// * `keep` - Rule selecting the file to keep in each group.
+/// * `dry_run` - When `true`, nothing is moved and the summary describes
+///   what would happen.
+///
+/// # Returns
+/// A [`DeleteSummary`] with `trashed` set, listing moved and failed files.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::dedupe::{trash_duplicates, KeepStrategy};
+/// use duplicate_file_finder::find_duplicates_report;
+/// use tempfile::tempdir;
+///
+/// fn demo() -> std::io::Result<()> {
+///     let dir = tempdir()?;
+///     std::fs::write(dir.path().join("a.txt"), b"same")?;
+///     std::fs::write(dir.path().join("b.txt"), b"same")?;
+///     let report = find_duplicates_report(dir.path());
+///     let summary = trash_duplicates(&report, KeepStrategy::First, true);
+///     assert!(summary.deleted[0].path.ends_with("b.txt"));
+///     Ok(())
+/// }
+/// ```
+#[must_use]
+pub fn trash_duplicates(
+    report: &DuplicateReport,
+    keep: KeepStrategy,
+    dry_run: bool,
+) -> DeleteSummary {
+    remove_duplicates(report, keep, dry_run, true)
+}
+
+/// Removes every duplicate but the kept copy, deleting it or moving it to the trash.
+fn remove_duplicates(
+    report: &DuplicateReport,
+    keep: KeepStrategy,
+    dry_run: bool,
+    trashed: bool,
+) -> DeleteSummary {
+    let mut summary = DeleteSummary {
+        dry_run,
+        trashed,
+        ..DeleteSummary::default()
+    };
+    let mut plan = Vec::new();
+
+    for group in &report.groups {
+        let kept = match choose_kept(&group.paths, &report.roots, keep) {
+            Ok(Some(kept)) => kept,
+            Ok(None) => continue,
+            Err((path, e)) => {
+                warn!("Cannot read {}: {e}", path.display());
+                for other in &group.paths {
+                    summary
+                        .failed
+                        .push((other.clone(), format!("cannot choose a copy to keep: {e}")));
+                }
+                continue;
+            }
+        };
+        if let Err(e) = fs::metadata(kept) {
+            warn!("Kept copy {} is unreadable: {e}", kept.display());
+            for other in group.paths.iter().filter(|p| *p != kept) {
+                summary
+                    .failed
+                    .push((other.clone(), format!("kept copy unreadable: {e}")));
+            }
+            continue;
+        }
+
+        plan.extend(
+            group
+                .paths
+                .iter()
+                .filter(|p| *p != kept)
+                .map(|path| DeletedFile {
+                    path: path.clone(),
+                    kept: kept.clone(),
+                    size: group.size,
+                    trashed_to: None,
+                    attributes: None,
+                }),
+        );
+    }
+
+    remove_files(&mut summary, plan);
+    summary
+}
+
+/// Deletes, or moves to the trash, exactly the listed files, for callers
+/// that chose the copies to remove themselves, such as an interactive prompt.
+///
+/// Each file's `kept` copy is checked to still exist first, and the file is
+/// skipped as failed if it does not, so a stale selection never removes the
+/// last copy. The `trashed_to` field of the input is ignored.
+///
+/// # Arguments
+/// * `files` - The copies to remove, each naming the duplicate it leaves behind.
+/// * `trash` - When `true`, files are moved to the trash as by [`trash_duplicates`].
+/// * `dry_run` - When `true`, nothing is removed and the summary describes
+///   what would happen.
+///
+/// # Returns
+/// A [`DeleteSummary`] listing removed and failed files and the bytes freed.
+#[must_use]
+pub fn remove_selected(files: &[DeletedFile], trash: bool, dry_run: bool) -> DeleteSummary {
+    let mut summary = DeleteSummary {
+        dry_run,
+        trashed: trash,
+        ..DeleteSummary::default()
+    };
+    let mut plan = Vec::with_capacity(files.len());
+    for file in files {
+        match fs::metadata(&file.kept) {
+            Ok(_) => plan.push(file.clone()),
+            Err(e) => {
+                warn!("Kept copy {} is unreadable: {e}", file.kept.display());
+                summary
+                    .failed
+                    .push((file.path.clone(), format!("kept copy unreadable: {e}")));
+            }
+        }
+    }
+    remove_files(&mut summary, plan);
+    summary
+}
+
+/// Removes every planned file as `summary` asks, recording the outcome.
+fn remove_files(summary: &mut DeleteSummary, plan: Vec<DeletedFile>) {
+    type Remove = fn(&Path) -> io::Result<Option<PathBuf>>;
+    let (remove, verb, done): (Remove, _, _) = if summary.trashed {
+        (
+            |path| trash::move_to_trash(path).map(Some),
+            "trash",
+            "Trashed",
+        )
+    } else {
+        (
+            |path| fs::remove_file(path).map(|()| None),
+            "delete",
+            "Deleted",
+        )
+    };
+
+    for mut file in plan {
+        file.trashed_to = None;
+        file.attributes = FileAttributes::read(&file.path);
+        if !summary.dry_run {
+            match remove(&file.path) {
+                Ok(moved) => file.trashed_to = moved,
+                Err(e) => {
+                    warn!("Failed to {verb} {}: {e}", file.path.display());
+                    summary.failed.push((file.path, e.to_string()));
+                    continue;
+                }
+            }
+            info!(
+                "{done} {} (kept {})",
+                file.path.display(),
+                file.kept.display()
+            );
+        }
+        summary.freed_bytes += file.size;
+        summary.deleted.push(file);
+    }
+}
+
+/// Writes every duplicate except the one chosen by `keep` in each group,
+/// separated by NUL bytes, for tools such as `xargs -0`.
+///
+/// Nothing else is written: no header, sizes or newlines, and a NUL follows
+/// every path including the last. Piping the output to `rm` therefore leaves
+/// exactly one copy of each file. Paths are written as raw bytes, so names
+/// containing spaces, newlines or invalid UTF-8 arrive intact.
+///
+/// A group is left out entirely if its kept copy cannot be chosen or read, so
+/// a failure never causes every copy to be listed.
+///
+/// # Errors
+/// Returns any I/O error raised by the writer.
+pub fn write_print0_to<W: Write>(
+    writer: &mut W,
+    report: &DuplicateReport,
+    keep: KeepStrategy,
+) -> io::Result<()> {
+    print0(writer, report, keep, None)
+}
+
+/// Like [`write_print0_to`], but writes `separator` as a NUL-terminated
+/// record of its own between groups, so a consumer can tell where each group
+/// ends. With an empty separator every boundary is marked by two NULs in a
+/// row.
+///
+/// # Errors
+/// Returns any I/O error raised by the writer.
+pub fn write_print0_grouped_to<W: Write>(
+    writer: &mut W,
+    report: &DuplicateReport,
+    keep: KeepStrategy,
+    separator: &[u8],
+) -> io::Result<()> {
+    print0(writer, report, keep, Some(separator))
+}
+
+fn print0<W: Write>(
+    writer: &mut W,
+    report: &DuplicateReport,
+    keep: KeepStrategy,
+    separator: Option<&[u8]>,
+) -> io::Result<()> {
+    let mut first = true;
+    for group in &report.groups {
+        let kept = match choose_kept(&group.paths, &report.roots, keep) {
+            Ok(Some(kept)) => kept,
+            Ok(None) => continue,
+            Err((path, e)) => {
+                warn!("Cannot read {}: {e}; leaving its group out", path.display());
+                continue;
+            }
+        };
+        if let Err(e) = fs::metadata(kept) {
+            warn!(
+                "Kept copy {} is unreadable: {e}; leaving its group out",
+                kept.display()
+            );
+            continue;
+        }
+        if let Some(separator) = separator.filter(|_| !first) {
+            writer.write_all(separator)?;
+            writer.write_all(b"\0")?;
+        }
+        first = false;
+        for path in group.paths.iter().filter(|p| *p != kept) {
+            writer.write_all(path.as_os_str().as_encoded_bytes())?;
+            writer.write_all(b"\0")?;
+        }
+    }
+    Ok(())
+}
+
+/// Picks the path to keep, or returns the path whose metadata could not be read.
+///
+/// `roots` are the scanned directories, used by [`KeepStrategy::FirstListedDir`].
+fn choose_kept<'a>(
+    paths: &'a [PathBuf],
+    roots: &[PathBuf],
+    keep: KeepStrategy,
+) -> Result<Option<&'a PathBuf>, (&'a PathBuf, io::Error)> {
+    let modified = |path| {
+        fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .map_err(|e| (path, e))
+    };
+    Ok(match keep {
+        KeepStrategy::First => paths.first(),
+        KeepStrategy::ShortestPath => paths.iter().min_by_key(|p| p.components().count()),
+        // Paths outside every root (hand-built reports) rank last.
+        KeepStrategy::FirstListedDir => paths.iter().min_by_key(|p| {
+            roots
+                .iter()
+                .position(|root| p.starts_with(root))
+                .unwrap_or(usize::MAX)
+        }),
+        KeepStrategy::Oldest | KeepStrategy::Newest => {
+            let mut times = Vec::with_capacity(paths.len());
+            for path in paths {
+                times.push((modified(path)?, path));
+            }
+            // Ties keep the earliest listed path, like `First`.
+            if keep == KeepStrategy::Oldest {
+                times.iter().min_by_key(|(time, _)| *time).map(|(_, p)| *p)
+            } else {
+                times
+                    .iter()
+                    .rev()
+                    .max_by_key(|(time, _)| *time)
+                    .map(|(_, p)| *p)
+            }
+        }
+    })
+}
+
+/// Checks that `path` can be replaced by a hard link to the canonical file.
+#[cfg(unix)]
+fn check_linkable(canonical: &Metadata, path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = fs::metadata(path).map_err(|e| e.to_string())?;
+    if meta.dev() != canonical.dev() {
+        return Err("on a different filesystem than the canonical copy".to_string());
+    }
+    if meta.ino() == canonical.ino() {
+        return Err("already a hard link to the canonical copy".to_string());
+    }
+    Ok(())
+}
+
+/// Checks that `path` can be replaced by a hard link to the canonical file.
+#[cfg(not(unix))]
+fn check_linkable(_canonical: &Metadata, _path: &Path) -> Result<(), String> {
+    Err("hard linking is only supported on Unix platforms".to_string())
+}
+
+/// Atomically replaces `path` with a hard link to `canonical`.
+fn replace_with_link(canonical: &Path, path: &Path) -> io::Result<()> {
+    let temp = temp_sibling(path);
+    fs::hard_link(canonical, &temp)?;
+    if let Err(e) = fs::rename(&temp, path) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Atomically replaces `path` with a symbolic link to `target`.
+fn replace_with_symlink(target: &Path, path: &Path) -> io::Result<()> {
+    let temp = temp_sibling(path);
+    create_symlink(target, &temp)?;
+    if let Err(e) = fs::rename(&temp, path) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn create_symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symbolic links are not supported on this platform",
+    ))
+}
+
+/// Checks that `path` can be replaced by a clone of the canonical file.
+#[cfg(target_os = "linux")]
+fn check_reflinkable(canonical: &Metadata, path: &Path) -> Result<(), String> {
+    check_linkable(canonical, path)
+}
+
+/// Checks that `path` can be replaced by a clone of the canonical file.
+#[cfg(not(target_os = "linux"))]
+fn check_reflinkable(_canonical: &Metadata, _path: &Path) -> Result<(), String> {
+    Err("reflinks are only supported on Linux".to_string())
+}
+
+/// Clones `canonical` to a temporary name next to `path` and renames the
+/// clone over `path`, or only removes it again in a dry run.
+fn replace_with_clone(canonical: &Path, path: &Path, dry_run: bool) -> io::Result<()> {
+    let meta = fs::metadata(path)?;
+    let temp = temp_sibling(path);
+    let cloned = clone_file(canonical, &temp).and_then(|clone| {
+        clone.set_permissions(meta.permissions())?;
+        clone.set_modified(meta.modified()?)
+    });
+    if dry_run || cloned.is_err() {
+        let _ = fs::remove_file(&temp);
+        return cloned;
+    }
+    if let Err(e) = fs::rename(&temp, path) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Creates `dest` as a clone sharing the extents of `source`.
+#[cfg(target_os = "linux")]
+fn clone_file(source: &Path, dest: &Path) -> io::Result<fs::File> {
+    use std::os::unix::io::AsRawFd;
+
+    let source = fs::File::open(source)?;
+    let dest = fs::File::options()
+        .write(true)
+        .create_new(true)
+        .open(dest)?;
+    // SAFETY: both descriptors are open for the duration of the call and
+    // FICLONE takes the source descriptor as its argument.
+    if unsafe { libc::ioctl(dest.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } != 0 {
+        let e = io::Error::last_os_error();
+        return Err(match e.raw_os_error() {
+            Some(libc::EOPNOTSUPP | libc::EINVAL | libc::ENOTTY | libc::EXDEV) => io::Error::new(
+                io::ErrorKind::Unsupported,
+                "filesystem does not support reflinks",
+            ),
+            _ => e,
+        });
+    }
+    Ok(dest)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn clone_file(_source: &Path, _dest: &Path) -> io::Result<fs::File> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reflinks are only supported on Linux",
+    ))
+}
+
+/// Returns a hidden temporary path next to `path`.
+fn temp_sibling(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{name}.dff-{}.tmp", std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{find_duplicates_report, ScanOptions};
+    use std::fs::File;
+    use tempfile::tempdir;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hardlink_duplicates() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempdir().expect("create temp dir");
+        let file1 = dir.path().join("file1.txt");
+        let file2 = dir.path().join("file2.txt");
+        fs::write(&file1, "Duplicate content").expect("write file");
+        fs::write(&file2, "Duplicate content").expect("write file");
+        let report = find_duplicates_report(dir.path());
+
+        let dry = hardlink_duplicates(&report, true);
+        assert_eq!(dry.linked.len(), 1);
+        assert_eq!(dry.reclaimed_bytes, 17);
+        let ino = |p: &Path| fs::metadata(p).expect("metadata").ino();
+        assert_ne!(ino(&file1), ino(&file2));
+
+        let done = hardlink_duplicates(&report, false);
+        assert_eq!(done.linked.len(), 1);
+        assert_eq!(done.reclaimed_bytes, 17);
+        assert_eq!(ino(&file1), ino(&file2));
+        assert_eq!(
+            fs::read_to_string(&file2).expect("read"),
+            "Duplicate content"
+        );
+
+        let again = hardlink_duplicates(&report, false);
+        assert!(again.linked.is_empty());
+        assert_eq!(again.skipped.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_duplicates() {
+        let dir = tempdir().expect("create temp dir");
+        let file1 = dir.path().join("file1.txt");
+        let file2 = dir.path().join("file2.txt");
+        fs::write(&file1, "Duplicate content").expect("write file");
+        fs::write(&file2, "Duplicate content").expect("write file");
+        let report = find_duplicates_report(dir.path());
+        let target = fs::canonicalize(&report.groups[0].paths[0]).expect("canonicalize");
+        let is_link = |p: &Path| {
+            fs::symlink_metadata(p)
+                .expect("metadata")
+                .file_type()
+                .is_symlink()
+        };
+
+        let dry = symlink_duplicates(&report, true);
+        assert_eq!(dry.linked.len(), 1);
+        assert_eq!(dry.reclaimed_bytes, 17);
+        assert!(!is_link(&file1) && !is_link(&file2));
+
+        let done = symlink_duplicates(&report, false);
+        assert_eq!(done.linked.len(), 1);
+        let linked = &done.linked[0].path;
+        assert!(is_link(linked));
+        assert_eq!(fs::read_link(linked).expect("read link"), target);
+        assert!(target.is_absolute());
+        assert_eq!(
+            fs::read_to_string(linked).expect("read"),
+            "Duplicate content"
+        );
+
+        let again = symlink_duplicates(&report, false);
+        assert!(again.linked.is_empty());
+        assert_eq!(again.skipped[0].1, "already a symbolic link");
+
+        // Linking to a symlink would leave a chain that breaks with it.
+        // Built by hand, since `from_groups` would put the link second.
+        let reversed = DuplicateReport {
+            groups: vec![crate::DuplicateGroup {
+                hash: "h".to_string(),
+                size: 17,
+                paths: vec![linked.clone(), target.clone()],
+            }],
+            ..DuplicateReport::default()
+        };
+        let refused = symlink_duplicates(&reversed, false);
+        assert!(refused.linked.is_empty());
+        assert_eq!(
+            refused.skipped,
+            [(
+                target.clone(),
+                "canonical copy is itself a symbolic link".to_string()
+            )]
+        );
+        assert!(!is_link(&target));
+    }
+
+    // Windows does not allow newlines in file names.
+    #[cfg(unix)]
+    #[test]
+    fn test_write_print0_skips_kept_copy() {
+        let dir = tempdir().expect("create temp dir");
+        let short = dir.path().join("a b.txt");
+        let long = dir.path().join("line\nbreak").join("copy.txt");
+        fs::create_dir(long.parent().expect("parent")).expect("create dir");
+        fs::write(&short, "Duplicate content").expect("write file");
+        fs::write(&long, "Duplicate content").expect("write file");
+        let report = find_duplicates_report(dir.path());
+
+        let mut buffer = Vec::new();
+        write_print0_to(&mut buffer, &report, KeepStrategy::ShortestPath).expect("write");
+        let mut expected = long.as_os_str().as_encoded_bytes().to_vec();
+        expected.push(0);
+        assert_eq!(buffer, expected);
+
+        // A group whose kept copy is missing is left out rather than listed in full.
+        fs::remove_file(&short).expect("remove file");
+        let mut buffer = Vec::new();
+        write_print0_to(&mut buffer, &report, KeepStrategy::ShortestPath).expect("write");
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_write_print0_grouped_separates_groups() {
+        let dir = tempdir().expect("create temp dir");
+        for name in ["a1", "a2", "b1", "b2", "b3"] {
+            let content = if name.starts_with('a') {
+                "first"
+            } else {
+                "second!"
+            };
+            fs::write(dir.path().join(name), content).expect("write file");
+        }
+        let report = find_duplicates_report(dir.path());
+
+        let mut buffer = Vec::new();
+        write_print0_grouped_to(&mut buffer, &report, KeepStrategy::First, b"--").expect("write");
+        let records: Vec<&[u8]> = buffer[..buffer.len() - 1].split(|b| *b == 0).collect();
+        // The larger group comes first, and the separator only sits between groups.
+        assert_eq!(records.len(), 4);
+        assert!(records[0].ends_with(b"b2") && records[1].ends_with(b"b3"));
+        assert_eq!(records[2], b"--");
+        assert!(records[3].ends_with(b"a2"));
+    }
+
+    #[test]
+    fn test_delete_duplicates() {
+        let dir = tempdir().expect("create temp dir");
+        let nested = dir.path().join("nested");
+        fs::create_dir(&nested).expect("create dir");
+        let older = nested.join("older.txt");
+        let newer = dir.path().join("newer.txt");
+        fs::write(&older, "Duplicate content").expect("write file");
+        fs::write(&newer, "Duplicate content").expect("write file");
+        let past = std::time::SystemTime::now() - std::time::Duration::from_hours(1);
+        File::options()
+            .write(true)
+            .open(&older)
+            .and_then(|f| f.set_modified(past))
+            .expect("set mtime");
+        let report = find_duplicates_report(dir.path());
+
+        let keep_newest = delete_duplicates(&report, KeepStrategy::Newest, true);
+        assert_eq!(keep_newest.deleted.len(), 1);
+        assert_eq!(keep_newest.deleted[0].path, older);
+        let keep_short = delete_duplicates(&report, KeepStrategy::ShortestPath, true);
+        assert_eq!(keep_short.deleted[0].path, older);
+        assert!(older.exists() && newer.exists());
+
+        let done = delete_duplicates(&report, KeepStrategy::Oldest, false);
+        assert_eq!(done.deleted[0].path, newer);
+        assert_eq!(done.freed_bytes, 17);
+        assert!(older.exists());
+        assert!(!newer.exists());
+
+        // The deleted copy is gone, so running again reports a failure.
+        let again = delete_duplicates(&report, KeepStrategy::First, false);
+        assert!(again.deleted.is_empty());
+        assert_eq!(again.failed.len(), 1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_reflink_duplicates() {
+        let dir = tempdir().expect("create temp dir");
+        let file1 = dir.path().join("file1.txt");
+        let file2 = dir.path().join("file2.txt");
+        fs::write(&file1, "Duplicate content").expect("write file");
+        fs::write(&file2, "Duplicate content").expect("write file");
+        let report = find_duplicates_report(dir.path());
+
+        let preview = reflink_duplicates(&report, true);
+        let summary = reflink_duplicates(&report, false);
+        assert_eq!(preview.linked, summary.linked);
+        if summary.linked.is_empty() {
+            // ext4 and tmpfs cannot share extents; the file is skipped untouched.
+            assert_eq!(summary.skipped[0].1, "filesystem does not support reflinks");
+        } else {
+            assert_eq!(summary.reclaimed_bytes, 17);
+        }
+        assert_eq!(fs::read(&file2).expect("read file"), b"Duplicate content");
+        // No temporary clone is left behind either way.
+        assert_eq!(fs::read_dir(dir.path()).expect("read dir").count(), 2);
+    }
+
+    #[test]
+    fn test_delete_keeps_first_listed_dir() {
+        let dir = tempdir().expect("create temp dir");
+        let originals = dir.path().join("zz originals");
+        let backup = dir.path().join("backup");
+        for root in [&originals, &backup] {
+            fs::create_dir(root).expect("create dir");
+            fs::write(root.join("photo.jpg"), "Duplicate content").expect("write file");
+        }
+        let report = ScanOptions::new()
+            .quiet(true)
+            .run(&[originals.clone(), backup.clone()])
+            .expect("no globs");
+
+        let summary = delete_duplicates(&report, KeepStrategy::FirstListedDir, true);
+        assert_eq!(summary.deleted.len(), 1);
+        assert_eq!(summary.deleted[0].path, backup.join("photo.jpg"));
+        assert_eq!(summary.deleted[0].kept, originals.join("photo.jpg"));
+    }
+}
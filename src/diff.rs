@@ -0,0 +1,251 @@
+//! Comparing two duplicate reports, for the `diff` subcommand and the
+//! summary printed after each rescan with `--watch`.
+//!
+//! Groups are matched by the files they share rather than by hash, so
+//! reports made with different hash algorithms still compare. A group of
+//! the newer report sharing no file with any group of the older one is new,
+//! a group of the older report sharing none with the newer one is resolved,
+//! and a newer group whose files differ from the older group it shares one
+//! with has changed.
+
+use crate::json_report::read_output_json;
+use crate::sqlite::read_sqlite;
+use crate::{format_size, format_size_change, DuplicateGroup, DuplicateReport};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// What changed between two reports, as returned by [`diff_reports`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReportDiff {
+    /// Groups of the newer report sharing no file with the older one.
+    pub added: Vec<DuplicateGroup>,
+    /// Groups of the older report sharing no file with the newer one.
+    pub resolved: Vec<DuplicateGroup>,
+    /// Groups of the newer report whose files differ from the older group
+    /// they share a file with.
+    pub changed: Vec<ChangedGroup>,
+    /// The reclaimable bytes of the older report.
+    pub old_reclaimable_bytes: u64,
+    /// The reclaimable bytes of the newer report.
+    pub new_reclaimable_bytes: u64,
+}
+
+/// A group whose files changed between two reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedGroup {
+    /// The group as the newer report has it.
+    pub group: DuplicateGroup,
+    /// Its files that the older group did not have.
+    pub added: Vec<PathBuf>,
+    /// The files of the older group that it no longer has.
+    pub removed: Vec<PathBuf>,
+}
+
+impl ReportDiff {
+    /// Returns `true` if the two reports hold the same groups.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.resolved.is_empty() && self.changed.is_empty()
+    }
+
+    /// Writes a human-readable description of the changes to `writer`.
+    ///
+    /// # Errors
+    /// Returns any I/O error raised by the writer.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "New duplicate groups: {}", self.added.len())?;
+        for group in &self.added {
+            write_group(writer, group)?;
+        }
+        writeln!(writer, "Resolved groups: {}", self.resolved.len())?;
+        for group in &self.resolved {
+            write_group(writer, group)?;
+        }
+        writeln!(writer, "Changed groups: {}", self.changed.len())?;
+        for changed in &self.changed {
+            write_group(writer, &changed.group)?;
+            for path in &changed.added {
+                writeln!(writer, "    + {}", path.display())?;
+            }
+            for path in &changed.removed {
+                writeln!(writer, "    - {}", path.display())?;
+            }
+        }
+        writeln!(
+            writer,
+            "Reclaimable space: {} -> {} ({})",
+            format_size(self.old_reclaimable_bytes),
+            format_size(self.new_reclaimable_bytes),
+            format_size_change(self.old_reclaimable_bytes, self.new_reclaimable_bytes)
+        )
+    }
+}
+
+fn write_group<W: Write>(writer: &mut W, group: &DuplicateGroup) -> io::Result<()> {
+    writeln!(
+        writer,
+        "  {} files of {}:",
+        group.paths.len(),
+        format_size(group.size)
+    )?;
+    for path in &group.paths {
+        writeln!(writer, "    {}", path.display())?;
+    }
+    Ok(())
+}
+
+/// Compares the groups of `old` with those of `new`.
+///
+/// # Example
+/// ```
+/// use std::path::PathBuf;
+/// use duplicate_file_finder::diff::diff_reports;
+/// use duplicate_file_finder::{DuplicateGroup, DuplicateReport};
+///
+/// let group = |paths: &[&str]| DuplicateGroup {
+///     hash: String::from("somehash"),
+///     size: 1024,
+///     paths: paths.iter().map(PathBuf::from).collect(),
+/// };
+/// let old = DuplicateReport::from_groups(vec![group(&["/a", "/b"]), group(&["/c", "/d"])]);
+/// let new = DuplicateReport::from_groups(vec![group(&["/a", "/b", "/e"])]);
+/// let diff = diff_reports(&old, &new);
+/// assert!(diff.added.is_empty());
+/// assert_eq!(diff.resolved.len(), 1);
+/// assert_eq!(diff.changed[0].added, [PathBuf::from("/e")]);
+/// ```
+#[must_use]
+pub fn diff_reports(old: &DuplicateReport, new: &DuplicateReport) -> ReportDiff {
+    let group_of: HashMap<&Path, &DuplicateGroup> = old
+        .groups
+        .iter()
+        .flat_map(|group| group.paths.iter().map(move |path| (path.as_path(), group)))
+        .collect();
+    let mut diff = ReportDiff {
+        old_reclaimable_bytes: old.total_reclaimable_bytes,
+        new_reclaimable_bytes: new.total_reclaimable_bytes,
+        ..ReportDiff::default()
+    };
+    for group in &new.groups {
+        match group
+            .paths
+            .iter()
+            .find_map(|path| group_of.get(path.as_path()))
+        {
+            None => diff.added.push(group.clone()),
+            Some(before) if before.paths != group.paths => diff.changed.push(ChangedGroup {
+                group: group.clone(),
+                added: missing_from(&group.paths, &before.paths),
+                removed: missing_from(&before.paths, &group.paths),
+            }),
+            Some(_) => {}
+        }
+    }
+    let remaining: HashSet<&Path> = new
+        .groups
+        .iter()
+        .flat_map(|group| group.paths.iter().map(PathBuf::as_path))
+        .collect();
+    diff.resolved = old
+        .groups
+        .iter()
+        .filter(|group| {
+            !group
+                .paths
+                .iter()
+                .any(|path| remaining.contains(path.as_path()))
+        })
+        .cloned()
+        .collect();
+    diff
+}
+
+/// Returns the paths of `paths` that are not in `others`.
+fn missing_from(paths: &[PathBuf], others: &[PathBuf]) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .filter(|path| !others.contains(path))
+        .cloned()
+        .collect()
+}
+
+/// Reads a report saved with `--format json` or `--format sqlite`, telling
+/// them apart by their contents.
+///
+/// The groups, their files, the scanned directories, the skipped files and
+/// the totals are read; the metadata of the files is not.
+///
+/// # Errors
+/// Returns any I/O error raised while reading `path`, or an error of kind
+/// [`io::ErrorKind::InvalidData`] if it is neither kind of report.
+pub fn read_report(path: &Path) -> io::Result<DuplicateReport> {
+    let data = fs::read(path)?;
+    if data.starts_with(b"SQLite format 3\0") {
+        return read_sqlite(&data);
+    }
+    let text = String::from_utf8(data).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a JSON or SQLite duplicate report",
+        )
+    })?;
+    read_output_json(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{write_output_json, write_sqlite_to};
+    use std::fs::File;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_read_report_round_trips_both_formats() {
+        // Enough files and a path long enough to span several pages of the
+        // database, including overflow pages.
+        let mut paths: Vec<PathBuf> = (0..500)
+            .map(|i| PathBuf::from(format!("/data/copies/file-{i:04}.bin")))
+            .collect();
+        paths.push(PathBuf::from(format!("/{}", "x".repeat(10_000))));
+        let mut report = DuplicateReport::from_groups(vec![
+            DuplicateGroup {
+                hash: "abc".to_string(),
+                size: 10,
+                paths,
+            },
+            DuplicateGroup {
+                hash: "def".to_string(),
+                size: 70_000,
+                paths: vec![PathBuf::from("/data/\"a\""), PathBuf::from("/data/b")],
+            },
+        ]);
+        report
+            .errors
+            .push((PathBuf::from("/locked"), io::Error::other("denied")));
+        let roots = [PathBuf::from("/data")];
+
+        let dir = tempdir().expect("create temp dir");
+        let json = dir.path().join("report.json");
+        let sqlite = dir.path().join("report.db");
+        let mut file = File::create(&json).expect("create report");
+        write_output_json(&mut file, &report, "start", &roots).expect("write JSON");
+        let mut file = File::create(&sqlite).expect("create report");
+        write_sqlite_to(&mut file, &report, "start", &roots).expect("write database");
+
+        for path in [json, sqlite] {
+            let read = read_report(&path).expect("read report");
+            assert_eq!(read.groups, report.groups, "{}", path.display());
+            assert_eq!(read.total_reclaimable_bytes, report.total_reclaimable_bytes);
+            assert_eq!(read.roots, roots);
+            assert_eq!(read.errors[0].1.to_string(), "denied");
+            assert!(diff_reports(&report, &read).is_empty());
+        }
+
+        let garbage = dir.path().join("garbage.txt");
+        fs::write(&garbage, "not a report").expect("write file");
+        let error = read_report(&garbage).expect_err("not a report");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
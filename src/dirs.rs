@@ -0,0 +1,279 @@
+//! Detection of whole directory trees that are exact copies of each other.
+//!
+//! This runs after file-level duplicates are known. A directory's signature
+//! combines the names of its entries with the content hash of every file and
+//! the signature of every subdirectory, so two directories match only when
+//! every file in both trees is identical. Any file that was not part of a
+//! duplicate group (a unique file, or one skipped by the scan) rules its
+//! directory and all of its ancestors out.
+
+use crate::{format_size, DuplicateReport};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// A set of directories whose whole trees have identical contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateDirGroup {
+    /// The identical directories, sorted by path.
+    pub dirs: Vec<PathBuf>,
+    /// Total size in bytes of the files in one copy of the tree.
+    pub size: u64,
+    /// Number of files in one copy of the tree.
+    pub file_count: usize,
+}
+
+/// The computed signature of a directory tree.
+#[derive(Debug, Clone)]
+struct Tree {
+    digest: [u8; 32],
+    size: u64,
+    file_count: usize,
+}
+
+/// Finds directories whose entire contents are duplicated elsewhere.
+///
+/// Only the outermost matching directories are reported: when two trees are
+/// identical, their matching subdirectories are listed separately only if
+/// some other copy of them exists outside those trees.
+/// Directories that contain no files are ignored. Groups are ordered by size,
+/// largest first.
+///
+/// # Arguments
+/// * `report` - File-level duplicates from a completed scan.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::{find_duplicate_dirs, find_duplicates_report};
+/// use tempfile::tempdir;
+/// use std::fs;
+///
+/// fn demo() -> std::io::Result<()> {
+///     let root = tempdir()?;
+///     for copy in ["a", "b"] {
+///         fs::create_dir(root.path().join(copy))?;
+///         fs::write(root.path().join(copy).join("photo.jpg"), b"pixels")?;
+///     }
+///     let report = find_duplicates_report(root.path());
+///     let dirs = find_duplicate_dirs(&report);
+///     assert_eq!(dirs[0].dirs.len(), 2);
+///     Ok(())
+/// }
+/// ```
+#[must_use]
+pub fn find_duplicate_dirs(report: &DuplicateReport) -> Vec<DuplicateDirGroup> {
+    let hashes: HashMap<&Path, &str> = report
+        .groups
+        .iter()
+        .flat_map(|group| {
+            group
+                .paths
+                .iter()
+                .map(|p| (p.as_path(), group.hash.as_str()))
+        })
+        .collect();
+
+    let mut trees: HashMap<PathBuf, Option<Tree>> = HashMap::new();
+    let mut pending: Vec<PathBuf> = hashes
+        .keys()
+        .filter_map(|p| p.parent().map(Path::to_path_buf))
+        .collect();
+    // Climb one level at a time while directories keep matching.
+    while let Some(dir) = pending.pop() {
+        if trees.contains_key(&dir) {
+            continue;
+        }
+        if signature(&dir, &hashes, &mut trees).is_some() {
+            if let Some(parent) = dir.parent() {
+                pending.push(parent.to_path_buf());
+            }
+        }
+    }
+
+    let mut by_digest: HashMap<[u8; 32], (Tree, Vec<PathBuf>)> = HashMap::new();
+    for (dir, tree) in trees {
+        if let Some(tree) = tree.filter(|t| t.file_count > 0) {
+            by_digest
+                .entry(tree.digest)
+                .or_insert_with(|| (tree, Vec::new()))
+                .1
+                .push(dir);
+        }
+    }
+    let duplicated: HashSet<PathBuf> = by_digest
+        .values()
+        .filter(|(_, dirs)| dirs.len() > 1)
+        .flat_map(|(_, dirs)| dirs.iter().cloned())
+        .collect();
+
+    let mut groups: Vec<DuplicateDirGroup> = by_digest
+        .into_values()
+        .filter(|(_, dirs)| dirs.len() > 1)
+        // A group nested inside larger identical trees adds nothing new.
+        .filter(|(_, dirs)| {
+            !dirs
+                .iter()
+                .all(|d| d.parent().is_some_and(|p| duplicated.contains(p)))
+        })
+        .map(|(tree, mut dirs)| {
+            dirs.sort();
+            DuplicateDirGroup {
+                dirs,
+                size: tree.size,
+                file_count: tree.file_count,
+            }
+        })
+        .collect();
+    groups.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.dirs.cmp(&b.dirs)));
+    groups
+}
+
+/// Computes the signature of `dir`, or `None` if any file below it has no
+/// known duplicate or the directory cannot be read.
+fn signature(
+    dir: &Path,
+    hashes: &HashMap<&Path, &str>,
+    trees: &mut HashMap<PathBuf, Option<Tree>>,
+) -> Option<Tree> {
+    if let Some(tree) = trees.get(dir) {
+        return tree.clone();
+    }
+    let tree = compute_signature(dir, hashes, trees);
+    trees.insert(dir.to_path_buf(), tree.clone());
+    tree
+}
+
+fn compute_signature(
+    dir: &Path,
+    hashes: &HashMap<&Path, &str>,
+    trees: &mut HashMap<PathBuf, Option<Tree>>,
+) -> Option<Tree> {
+    let mut files = Vec::new();
+    let mut subdirs = Vec::new();
+    for entry in fs::read_dir(dir).ok()? {
+        let entry = entry.ok()?;
+        let file_type = entry.file_type().ok()?;
+        if file_type.is_dir() {
+            subdirs.push(entry.path());
+        } else if file_type.is_file() {
+            files.push(entry.path());
+        } else {
+            // Symlinks and special files cannot be compared safely.
+            return None;
+        }
+    }
+
+    // Check files before descending, so a unique file stops the climb cheaply.
+    let mut entries = Vec::with_capacity(files.len() + subdirs.len());
+    let mut size = 0;
+    let mut file_count = 0;
+    for file in files {
+        let hash = hashes.get(file.as_path())?;
+        size += fs::metadata(&file).ok()?.len();
+        file_count += 1;
+        entries.push((file, b'F', hash.as_bytes().to_vec()));
+    }
+    for subdir in subdirs {
+        let tree = signature(&subdir, hashes, trees)?;
+        size += tree.size;
+        file_count += tree.file_count;
+        entries.push((subdir, b'D', tree.digest.to_vec()));
+    }
+
+    entries.sort_by(|a, b| a.0.file_name().cmp(&b.0.file_name()));
+    let mut sha = Sha256::new();
+    for (path, kind, digest) in &entries {
+        let name = path.file_name().unwrap_or_default();
+        sha.update([*kind]);
+        sha.update(name.as_encoded_bytes());
+        sha.update([0]);
+        sha.update(digest);
+    }
+    Some(Tree {
+        digest: sha.finalize().into(),
+        size,
+        file_count,
+    })
+}
+
+/// Writes a report section listing duplicate directory trees.
+///
+/// # Errors
+/// Returns any I/O error raised by the writer.
+pub fn write_dir_groups_to<W: Write>(
+    writer: &mut W,
+    groups: &[DuplicateDirGroup],
+) -> io::Result<()> {
+    writeln!(writer, "Duplicate Directories:")?;
+    if groups.is_empty() {
+        writeln!(writer, "None found.")?;
+        writeln!(writer)?;
+    }
+    for group in groups {
+        writeln!(
+            writer,
+            "Size: {} ({} files)",
+            format_size(group.size),
+            group.file_count
+        )?;
+        for dir in &group.dirs {
+            writeln!(writer, "{}", dir.display())?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::find_duplicates_report;
+    use tempfile::tempdir;
+
+    fn write_tree(root: &Path, files: &[(&str, &str)]) {
+        for (name, contents) in files {
+            let path = root.join(name);
+            fs::create_dir_all(path.parent().expect("parent")).expect("create dir");
+            fs::write(path, contents).expect("write file");
+        }
+    }
+
+    #[test]
+    fn test_find_duplicate_dirs() {
+        let root = tempdir().expect("create temp dir");
+        let tree = [
+            ("a.txt", "alpha"),
+            ("sub/b.txt", "beta"),
+            ("sub/c.txt", "gamma"),
+        ];
+        write_tree(&root.path().join("copy1"), &tree);
+        write_tree(&root.path().join("copy2"), &tree);
+        // Shares every file with the copies but also holds a unique one.
+        write_tree(&root.path().join("partial"), &tree);
+        write_tree(&root.path().join("partial"), &[("extra.txt", "unique")]);
+
+        let report = find_duplicates_report(root.path());
+        let groups = find_duplicate_dirs(&report);
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(
+            groups[0].dirs,
+            [root.path().join("copy1"), root.path().join("copy2")]
+        );
+        assert_eq!(groups[0].file_count, 3);
+        assert_eq!(groups[0].size, 14);
+        // `partial/sub` is itself a complete copy, so the subdirectories are
+        // reported even though `partial` as a whole is not.
+        assert_eq!(groups[1].dirs.len(), 3);
+        assert!(groups[1]
+            .dirs
+            .contains(&root.path().join("partial").join("sub")));
+
+        let mut text = Vec::new();
+        write_dir_groups_to(&mut text, &groups).expect("write to buffer");
+        let text = String::from_utf8(text).expect("valid UTF-8");
+        assert!(text.starts_with("Duplicate Directories:\nSize: 14 bytes (3 files)\n"));
+    }
+}
@@ -0,0 +1,177 @@
+//! Which storage devices are spinning disks, so that reads from them can be
+//! made one at a time.
+//!
+//! Reading many files at once from a hard disk sends its head seeking back
+//! and forth between them, which can be far slower than reading the same
+//! files one after another. Solid-state drives are the opposite: they are
+//! fastest with many reads in flight. On Linux the kernel reports whether the
+//! block device behind a file is rotational; elsewhere, and for devices the
+//! kernel says nothing about, such as network mounts, reads stay parallel.
+//!
+//! The device of a file is taken from the metadata the walk already read,
+//! so that locking it costs no further `stat`.
+
+use log::debug;
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// The device identifier of a file, as reported by `stat`.
+type DeviceId = u64;
+
+/// One lock per spinning disk met during a scan, held while reading a file
+/// from that disk.
+#[derive(Debug, Default)]
+pub(crate) struct DiskLocks {
+    /// The lock of each device seen so far, or `None` for devices read in
+    /// parallel.
+    devices: Mutex<HashMap<DeviceId, Option<Arc<Mutex<()>>>>>,
+    /// The device of the files found by the walk, by the directory holding
+    /// them, and by their own path for the few, such as followed links,
+    /// that are on another device than the first file found beside them.
+    found: Mutex<HashMap<PathBuf, DeviceId>>,
+}
+
+impl DiskLocks {
+    /// Records the device of `path`, a file found by the walk, from its
+    /// metadata `meta`.
+    pub(crate) fn found(&self, path: &Path, meta: &Metadata) {
+        let (Some(dir), Some(device)) = (path.parent(), device_in(meta)) else {
+            return;
+        };
+        let mut found = self.found.lock().unwrap_or_else(PoisonError::into_inner);
+        match found.get(dir) {
+            Some(&known) if known == device => {}
+            Some(_) => {
+                found.insert(path.to_path_buf(), device);
+            }
+            None => {
+                found.insert(dir.to_path_buf(), device);
+            }
+        }
+    }
+
+    /// Runs `read`, which reads `path`, while no other file on the same
+    /// spinning disk is being read.
+    pub(crate) fn read<R>(&self, path: &Path, read: impl FnOnce() -> R) -> R {
+        match self.lock_for(path) {
+            Some(lock) => {
+                let _guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+                read()
+            }
+            None => read(),
+        }
+    }
+
+    /// Returns the lock of the disk holding `path`, or `None` if the file
+    /// can be read in parallel with others.
+    fn lock_for(&self, path: &Path) -> Option<Arc<Mutex<()>>> {
+        let device = self.device_found(path).or_else(|| device_of(path))?;
+        let mut devices = self.devices.lock().unwrap_or_else(PoisonError::into_inner);
+        devices
+            .entry(device)
+            .or_insert_with(|| {
+                is_rotational(device).then(|| {
+                    debug!("Reading files on device {device:#x} one at a time");
+                    Arc::new(Mutex::new(()))
+                })
+            })
+            .clone()
+    }
+
+    /// Returns the device recorded by [`DiskLocks::found`] for `path`.
+    fn device_found(&self, path: &Path) -> Option<DeviceId> {
+        let found = self.found.lock().unwrap_or_else(PoisonError::into_inner);
+        found
+            .get(path)
+            .or_else(|| found.get(path.parent()?))
+            .copied()
+    }
+}
+
+/// Reads the device of a file the walk did not find, such as one named by
+/// an index.
+fn device_of(path: &Path) -> Option<DeviceId> {
+    std::fs::metadata(path).ok().as_ref().and_then(device_in)
+}
+
+#[cfg(unix)]
+#[allow(clippy::unnecessary_wraps)]
+fn device_in(meta: &Metadata) -> Option<DeviceId> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(meta.dev())
+}
+
+#[cfg(not(unix))]
+fn device_in(_meta: &Metadata) -> Option<DeviceId> {
+    None
+}
+
+/// Returns `true` if the kernel reports the block device as rotational.
+#[cfg(target_os = "linux")]
+fn is_rotational(device: DeviceId) -> bool {
+    let dir = std::path::PathBuf::from(format!(
+        "/sys/dev/block/{}:{}",
+        libc::major(device),
+        libc::minor(device)
+    ));
+    // A partition has no queue of its own; it shares the whole disk's.
+    [
+        dir.join("queue/rotational"),
+        dir.join("../queue/rotational"),
+    ]
+    .iter()
+    .find_map(|path| std::fs::read_to_string(path).ok())
+    .is_some_and(|flag| flag.trim() == "1")
+}
+
+/// Rotational media cannot be detected; every device is read in parallel.
+#[cfg(not(target_os = "linux"))]
+fn is_rotational(_device: DeviceId) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_disk_locks_are_shared_per_device() {
+        let dir = tempdir().expect("create temp dir");
+        let first = dir.path().join("a.txt");
+        let second = dir.path().join("b.txt");
+        fs::write(&first, "a").expect("write file");
+        fs::write(&second, "b").expect("write file");
+
+        let locks = DiskLocks::default();
+        match (locks.lock_for(&first), locks.lock_for(&second)) {
+            (Some(a), Some(b)) => assert!(Arc::ptr_eq(&a, &b)),
+            (None, None) => {}
+            _ => panic!("files in one directory are on one device"),
+        }
+        assert!(locks.lock_for(&dir.path().join("missing")).is_none());
+        assert_eq!(locks.read(&first, || 7), 7);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_devices_found_by_the_walk_are_not_read_again() {
+        let dir = tempdir().expect("create temp dir");
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "a").expect("write file");
+        let meta = fs::metadata(&file).expect("metadata");
+
+        let locks = DiskLocks::default();
+        locks.found(&file, &meta);
+        fs::remove_file(&file).expect("remove file");
+        // Another file of the directory, never found, shares its device.
+        let other = dir.path().join("b.txt");
+        assert_eq!(locks.device_found(&file), device_in(&meta));
+        assert_eq!(locks.device_found(&other), device_in(&meta));
+        assert_eq!(locks.device_found(Path::new("/elsewhere/c.txt")), None);
+    }
+}
@@ -0,0 +1,380 @@
+//! Minimal glob matching used to exclude paths from a scan.
+//!
+//! Patterns are matched against paths relative to the scanned root, using `/`
+//! as the separator on every platform. The supported syntax is:
+//!
+//! * `?` matches any single character.
+//! * `*` matches any sequence of characters, including `/`.
+//! * `**` as a whole path component matches zero or more directories, so
+//!   `**/target/**` matches everything below any `target` directory.
+//! * `[abc]`, `[a-z]` and `[!a-z]` match a single character from (or not
+//!   from) a set.
+//! * `\` escapes the following character.
+
+use std::error::Error;
+use std::fmt;
+use std::path::{Component, Path};
+
+use crate::RegexError;
+
+/// An error produced when a glob pattern cannot be parsed.
+///
+/// Scans also return it for a regular expression given to
+/// [`ScanOptions::include_regex`](crate::ScanOptions::include_regex) or
+/// [`ScanOptions::exclude_regex`](crate::ScanOptions::exclude_regex) that
+/// cannot be parsed, wrapping the [`RegexError`], which
+/// [`GlobError::regex`] returns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobError {
+    kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Kind {
+    Glob {
+        pattern: String,
+        reason: &'static str,
+    },
+    Regex(RegexError),
+}
+
+impl GlobError {
+    /// Returns the pattern that failed to parse.
+    #[must_use]
+    pub fn pattern(&self) -> &str {
+        match &self.kind {
+            Kind::Glob { pattern, .. } => pattern,
+            Kind::Regex(err) => err.pattern(),
+        }
+    }
+
+    /// Returns the error in a regular expression, or `None` if a glob
+    /// pattern failed to parse.
+    #[must_use]
+    pub fn regex(&self) -> Option<&RegexError> {
+        match &self.kind {
+            Kind::Glob { .. } => None,
+            Kind::Regex(err) => Some(err),
+        }
+    }
+}
+
+impl From<RegexError> for GlobError {
+    fn from(err: RegexError) -> Self {
+        Self {
+            kind: Kind::Regex(err),
+        }
+    }
+}
+
+impl fmt::Display for GlobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            Kind::Glob { pattern, reason } => write!(f, "invalid glob '{pattern}': {reason}"),
+            Kind::Regex(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for GlobError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.regex().map(|err| err as &(dyn Error + 'static))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(char),
+    AnyChar,
+    ZeroOrMore,
+    /// `?` where it cannot match `/`, as in ignore files.
+    AnyCharInComponent,
+    /// `*` where it cannot match `/`, as in ignore files.
+    ZeroOrMoreInComponent,
+    /// `**/` at the start of a pattern: empty, or anything ending in `/`.
+    RecursivePrefix,
+    /// `/**/` in the middle of a pattern: `/`, or `/` + anything + `/`.
+    RecursiveZeroOrMore,
+    /// `/**` at the end of a pattern: `/` followed by anything.
+    RecursiveSuffix,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// A single compiled glob pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Glob {
+    tokens: Vec<Token>,
+}
+
+impl Glob {
+    fn new(pattern: &str) -> Result<Self, GlobError> {
+        Self::parse(pattern, false)
+    }
+
+    /// Compiles a pattern from an ignore file, where `*` and `?` never
+    /// match `/` and only `**` crosses directories.
+    pub(crate) fn new_in_components(pattern: &str) -> Result<Self, GlobError> {
+        Self::parse(pattern, true)
+    }
+
+    fn parse(pattern: &str, in_components: bool) -> Result<Self, GlobError> {
+        let err = |reason| GlobError {
+            kind: Kind::Glob {
+                pattern: pattern.to_string(),
+                reason,
+            },
+        };
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            match chars[i] {
+                '?' if in_components => tokens.push(Token::AnyCharInComponent),
+                '?' => tokens.push(Token::AnyChar),
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    let at_start = i == 0;
+                    let after_sep = i > 0 && chars[i - 1] == '/';
+                    let at_end = i + 2 == chars.len();
+                    let before_sep = chars.get(i + 2) == Some(&'/');
+                    if !(at_start || after_sep) || !(at_end || before_sep) {
+                        return Err(err("`**` must form a whole path component"));
+                    }
+                    if at_start && before_sep {
+                        tokens.push(Token::RecursivePrefix);
+                        i += 3;
+                        continue;
+                    }
+                    if after_sep && before_sep {
+                        tokens.pop();
+                        tokens.push(Token::RecursiveZeroOrMore);
+                        i += 3;
+                        continue;
+                    }
+                    if after_sep && at_end {
+                        tokens.pop();
+                        tokens.push(Token::RecursiveSuffix);
+                    } else {
+                        // A lone `**` matches everything, even in ignore files.
+                        tokens.push(Token::ZeroOrMore);
+                    }
+                    i += 2;
+                    continue;
+                }
+                '*' if in_components => tokens.push(Token::ZeroOrMoreInComponent),
+                '*' => tokens.push(Token::ZeroOrMore),
+                '[' => {
+                    let (token, next) = parse_class(&chars, i + 1).map_err(err)?;
+                    tokens.push(token);
+                    i = next;
+                    continue;
+                }
+                '\\' => {
+                    let escaped = chars.get(i + 1).ok_or_else(|| err("dangling escape"))?;
+                    tokens.push(Token::Literal(*escaped));
+                    i += 2;
+                    continue;
+                }
+                c => tokens.push(Token::Literal(c)),
+            }
+            i += 1;
+        }
+
+        Ok(Self { tokens })
+    }
+
+    fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        match_tokens(&self.tokens, &chars)
+    }
+
+    /// Returns `true` if the pattern matches the given relative path.
+    pub(crate) fn is_path_match(&self, path: &Path) -> bool {
+        self.is_match(&to_glob_text(path))
+    }
+}
+
+/// Parses a character class starting just after the opening `[`.
+///
+/// Returns the token and the index just past the closing `]`.
+fn parse_class(chars: &[char], start: usize) -> Result<(Token, usize), &'static str> {
+    let mut i = start;
+    let negated = matches!(chars.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let c = *chars.get(i).ok_or("unclosed character class")?;
+        if c == ']' && !first {
+            return Ok((Token::Class { negated, ranges }, i + 1));
+        }
+        first = false;
+        if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|&e| e != ']') {
+            let end = chars[i + 2];
+            if end < c {
+                return Err("invalid character range");
+            }
+            ranges.push((c, end));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+}
+
+fn match_tokens(tokens: &[Token], text: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return text.is_empty();
+    };
+
+    match token {
+        Token::Literal(c) => text.first() == Some(c) && match_tokens(rest, &text[1..]),
+        Token::AnyChar => !text.is_empty() && match_tokens(rest, &text[1..]),
+        Token::Class { negated, ranges } => text.first().is_some_and(|c| {
+            let in_class = ranges.iter().any(|(lo, hi)| (lo..=hi).contains(&c));
+            in_class != *negated && match_tokens(rest, &text[1..])
+        }),
+        Token::AnyCharInComponent => {
+            text.first().is_some_and(|&c| c != '/') && match_tokens(rest, &text[1..])
+        }
+        Token::ZeroOrMore => (0..=text.len()).any(|n| match_tokens(rest, &text[n..])),
+        Token::ZeroOrMoreInComponent => {
+            let end = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+            (0..=end).any(|n| match_tokens(rest, &text[n..]))
+        }
+        Token::RecursivePrefix => {
+            match_tokens(rest, text)
+                || (0..text.len())
+                    .filter(|&n| text[n] == '/')
+                    .any(|n| match_tokens(rest, &text[n + 1..]))
+        }
+        Token::RecursiveZeroOrMore => {
+            text.first() == Some(&'/')
+                && (0..text.len())
+                    .filter(|&n| text[n] == '/')
+                    .any(|n| match_tokens(rest, &text[n + 1..]))
+        }
+        Token::RecursiveSuffix => text.first() == Some(&'/'),
+    }
+}
+
+/// A collection of glob patterns that matches a path if any pattern does.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::GlobSet;
+/// use std::path::Path;
+///
+/// let set = GlobSet::new(&["**/target/**".to_string(), "*.tmp".to_string()]).unwrap();
+/// assert!(set.is_match(Path::new("crate/target/debug/app")));
+/// assert!(set.is_match(Path::new("notes/draft.tmp")));
+/// assert!(!set.is_match(Path::new("src/main.rs")));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GlobSet {
+    globs: Vec<Glob>,
+}
+
+impl GlobSet {
+    /// Compiles every pattern into a single set.
+    ///
+    /// # Errors
+    /// Returns a [`GlobError`] naming the first pattern that is not a valid glob.
+    pub fn new(patterns: &[String]) -> Result<Self, GlobError> {
+        let globs = patterns
+            .iter()
+            .map(|p| Glob::new(p))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { globs })
+    }
+
+    /// Returns `true` if the set contains no patterns.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.globs.is_empty()
+    }
+
+    /// Returns `true` if any pattern matches the given relative path.
+    #[must_use]
+    pub fn is_match(&self, path: &Path) -> bool {
+        if self.globs.is_empty() {
+            return false;
+        }
+        let text = to_glob_text(path);
+        self.globs.iter().any(|g| g.is_match(&text))
+    }
+
+    /// Returns `true` if a directory and everything below it is excluded.
+    ///
+    /// This also matches patterns such as `**/target/**` that only describe
+    /// the directory's contents, which lets the walker prune the directory.
+    #[must_use]
+    pub fn is_dir_match(&self, path: &Path) -> bool {
+        if self.globs.is_empty() {
+            return false;
+        }
+        let text = to_glob_text(path);
+        let with_sep = format!("{text}/");
+        self.globs
+            .iter()
+            .any(|g| g.is_match(&text) || g.is_match(&with_sep))
+    }
+}
+
+/// Joins the normal components of a path with `/`.
+fn to_glob_text(path: &Path) -> String {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(patterns: &[&str]) -> GlobSet {
+        let patterns: Vec<String> = patterns.iter().map(ToString::to_string).collect();
+        GlobSet::new(&patterns).expect("valid globs")
+    }
+
+    #[test]
+    fn test_wildcards() {
+        let globs = set(&["*.tmp", "file?.txt", "[a-c]x"]);
+        assert!(globs.is_match(Path::new("a.tmp")));
+        assert!(globs.is_match(Path::new("dir/sub/a.tmp")));
+        assert!(globs.is_match(Path::new("file1.txt")));
+        assert!(!globs.is_match(Path::new("file10.txt")));
+        assert!(globs.is_match(Path::new("bx")));
+        assert!(!globs.is_match(Path::new("dx")));
+    }
+
+    #[test]
+    fn test_recursive_wildcards() {
+        let globs = set(&["**/target/**", "docs/**/*.md"]);
+        assert!(globs.is_match(Path::new("target/debug/app")));
+        assert!(globs.is_match(Path::new("a/b/target/x")));
+        assert!(!globs.is_match(Path::new("targets/x")));
+        assert!(globs.is_dir_match(Path::new("a/target")));
+        assert!(globs.is_match(Path::new("docs/readme.md")));
+        assert!(globs.is_match(Path::new("docs/a/b/readme.md")));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        for bad in ["[abc", "a**", "**b/c", "trailing\\", "[z-a]"] {
+            let err = GlobSet::new(&[bad.to_string()]).expect_err(bad);
+            assert_eq!(err.pattern(), bad);
+        }
+    }
+}
@@ -0,0 +1,384 @@
+//! A history of completed scans, and the trend report printed by the
+//! `history` subcommand.
+//!
+//! When a history is kept, every completed scan for duplicates appends one
+//! line to it: a JSON object with the time, the directories scanned, how
+//! many files were compared and how much was read, what was found, and the
+//! same figures for each directory. Runs over the same set of directories can then be listed
+//! side by side, showing whether duplication grows or shrinks over time.
+
+use crate::json::{self, Value};
+use crate::{format_size, format_size_change, DuplicateReport};
+use chrono::{DateTime, Local};
+use log::warn;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Version of the history line format. Bump it whenever the layout changes.
+pub const HISTORY_VERSION: u64 = 1;
+
+/// The figures recorded for one completed scan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    /// When the scan finished.
+    pub time: DateTime<Local>,
+    /// The directories scanned, made absolute so runs started from
+    /// different working directories can be compared.
+    pub directories: Vec<PathBuf>,
+    /// Files found to compare.
+    pub files_scanned: u64,
+    /// Bytes read to hash and compare them.
+    pub bytes_hashed: u64,
+    /// Groups of identical files found.
+    pub duplicate_groups: usize,
+    /// Files in those groups, including the copy each would keep.
+    pub duplicate_files: usize,
+    /// Bytes that keeping one file of each group would free.
+    pub reclaimable_bytes: u64,
+    /// The same figures for each directory scanned, in the same order.
+    pub per_directory: Vec<DirectoryStats>,
+}
+
+/// What one of the directories of a [`Run`] held.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryStats {
+    /// The directory, made absolute.
+    pub directory: PathBuf,
+    /// Files below it in a group of duplicates.
+    pub duplicate_files: usize,
+    /// The sizes of those of its files that keeping the first file of each
+    /// group would remove, so the figures of every directory add up to
+    /// those of the run.
+    pub reclaimable_bytes: u64,
+}
+
+impl Run {
+    /// Returns the figures of `report`, timestamped now.
+    #[must_use]
+    pub fn from_report(report: &DuplicateReport) -> Self {
+        let mut per_directory: Vec<DirectoryStats> = report
+            .roots
+            .iter()
+            .map(|root| DirectoryStats {
+                directory: absolute(root),
+                duplicate_files: 0,
+                reclaimable_bytes: 0,
+            })
+            .collect();
+        for group in &report.groups {
+            for (i, path) in group.paths.iter().enumerate() {
+                // Count each file once, under the first root that holds it.
+                let Some(stats) = report
+                    .roots
+                    .iter()
+                    .position(|root| path.starts_with(root))
+                    .map(|index| &mut per_directory[index])
+                else {
+                    continue;
+                };
+                stats.duplicate_files += 1;
+                if i > 0 {
+                    stats.reclaimable_bytes += group.size;
+                }
+            }
+        }
+        Self {
+            time: Local::now(),
+            directories: report.roots.iter().map(|root| absolute(root)).collect(),
+            files_scanned: report.files_scanned,
+            bytes_hashed: report.bytes_hashed,
+            duplicate_groups: report.total_groups,
+            duplicate_files: report.groups.iter().map(|group| group.paths.len()).sum(),
+            reclaimable_bytes: report.total_reclaimable_bytes,
+            per_directory,
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let directories: Vec<String> = self
+            .directories
+            .iter()
+            .map(|dir| json::quote(&dir.to_string_lossy()))
+            .collect();
+        let per_directory: Vec<String> = self
+            .per_directory
+            .iter()
+            .map(|stats| {
+                format!(
+                    "{{\"directory\":{},\"duplicate_files\":{},\"reclaimable_bytes\":{}}}",
+                    json::quote(&stats.directory.to_string_lossy()),
+                    stats.duplicate_files,
+                    stats.reclaimable_bytes
+                )
+            })
+            .collect();
+        format!(
+            "{{\"version\":{HISTORY_VERSION},\"time\":{},\"directories\":[{}],\
+             \"files_scanned\":{},\"bytes_hashed\":{},\"duplicate_groups\":{},\
+             \"duplicate_files\":{},\"reclaimable_bytes\":{},\"per_directory\":[{}]}}\n",
+            json::quote(&self.time.to_rfc3339()),
+            directories.join(","),
+            self.files_scanned,
+            self.bytes_hashed,
+            self.duplicate_groups,
+            self.duplicate_files,
+            self.reclaimable_bytes,
+            per_directory.join(",")
+        )
+    }
+
+    /// The key runs are grouped by: their directories, in order.
+    fn key(&self) -> String {
+        let names: Vec<String> = self
+            .directories
+            .iter()
+            .map(|dir| dir.display().to_string())
+            .collect();
+        names.join(", ")
+    }
+}
+
+/// Appends `run` to the history at `history`, creating it if needed.
+///
+/// # Errors
+/// Returns any I/O error raised while writing the history.
+pub fn record(history: &Path, run: &Run) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(history)?;
+    file.write_all(run.to_json().as_bytes())
+}
+
+/// Reads every run recorded in the history at `history`, oldest first. A
+/// history that does not exist yet holds no runs.
+///
+/// Lines that cannot be parsed, or that were written by another
+/// [`HISTORY_VERSION`], are skipped with a warning.
+///
+/// # Errors
+/// Returns any I/O error raised while reading the history.
+pub fn load(history: &Path) -> io::Result<Vec<Run>> {
+    let text = match fs::read_to_string(history) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut runs = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_line(line) {
+            Ok(run) => runs.push(run),
+            Err(reason) => warn!(
+                "Skipping line {} of {}: {reason}",
+                number + 1,
+                history.display()
+            ),
+        }
+    }
+    Ok(runs)
+}
+
+/// Writes the trend of every set of directories in `runs` to `writer`: a
+/// table of the latest `last` runs, with the change in reclaimable space
+/// from each to the next, then, when several directories were scanned
+/// together, how each of them changed over those runs.
+///
+/// # Errors
+/// Returns any I/O error raised by the writer.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::history::{write_trends_to, Run};
+/// use duplicate_file_finder::DuplicateReport;
+///
+/// let run = Run::from_report(&DuplicateReport::default());
+/// let mut out = Vec::new();
+/// write_trends_to(&[run.clone(), run], 10, &mut out).unwrap();
+/// assert!(String::from_utf8(out).unwrap().contains("(2 runs)"));
+/// ```
+pub fn write_trends_to<W: Write>(runs: &[Run], last: usize, writer: &mut W) -> io::Result<()> {
+    if runs.is_empty() {
+        return writeln!(writer, "No scans recorded yet.");
+    }
+    let mut sets: BTreeMap<String, Vec<&Run>> = BTreeMap::new();
+    for run in runs {
+        sets.entry(run.key()).or_default().push(run);
+    }
+    for (i, (key, runs)) in sets.iter().enumerate() {
+        if i > 0 {
+            writeln!(writer)?;
+        }
+        let shown = &runs[runs.len().saturating_sub(last.max(1))..];
+        writeln!(writer, "{key} ({} runs)", runs.len())?;
+        writeln!(
+            writer,
+            "{:<16}  {:>10}  {:>8}  {:>10}  {:>12}  {:>12}",
+            "Date", "Files", "Groups", "Duplicates", "Reclaimable", "Change"
+        )?;
+        let mut previous = runs.len().checked_sub(shown.len() + 1).map(|i| runs[i]);
+        for run in shown {
+            let change = previous.map_or_else(String::new, |before| {
+                format_size_change(before.reclaimable_bytes, run.reclaimable_bytes)
+            });
+            let row = format!(
+                "{:<16}  {:>10}  {:>8}  {:>10}  {:>12}  {:>12}",
+                run.time.format("%Y-%m-%d %H:%M"),
+                run.files_scanned,
+                run.duplicate_groups,
+                run.duplicate_files,
+                format_size(run.reclaimable_bytes),
+                change
+            );
+            writeln!(writer, "{}", row.trim_end())?;
+            previous = Some(run);
+        }
+        let (first, latest) = (shown[0], shown[shown.len() - 1]);
+        if latest.per_directory.len() > 1 {
+            writeln!(
+                writer,
+                "Reclaimable by directory, {} to {}:",
+                first.time.format("%Y-%m-%d"),
+                latest.time.format("%Y-%m-%d")
+            )?;
+            for stats in &latest.per_directory {
+                let before = first
+                    .per_directory
+                    .iter()
+                    .find(|old| old.directory == stats.directory)
+                    .map_or(0, |old| old.reclaimable_bytes);
+                writeln!(
+                    writer,
+                    "  {}: {} in {} duplicates ({})",
+                    stats.directory.display(),
+                    format_size(stats.reclaimable_bytes),
+                    stats.duplicate_files,
+                    format_size_change(before, stats.reclaimable_bytes)
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn parse_line(line: &str) -> Result<Run, String> {
+    let doc = json::parse(line)?;
+    match doc.get("version").and_then(Value::as_u64) {
+        Some(HISTORY_VERSION) => {}
+        Some(other) => return Err(format!("unsupported version {other}")),
+        None => return Err("missing version".to_string()),
+    }
+    let number = |item: &Value, name: &str| {
+        item.get(name)
+            .and_then(Value::as_u64)
+            .ok_or(format!("missing {name}"))
+    };
+    let count = |item: &Value, name: &str| {
+        number(item, name).and_then(|n| usize::try_from(n).map_err(|e| e.to_string()))
+    };
+    let time = doc
+        .get("time")
+        .and_then(Value::as_str)
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .ok_or("missing time")?
+        .with_timezone(&Local);
+    let directories = doc
+        .get("directories")
+        .and_then(Value::as_array)
+        .ok_or("missing directories")?
+        .iter()
+        .map(|dir| dir.as_str().map(PathBuf::from).ok_or("invalid directory"))
+        .collect::<Result<_, _>>()?;
+    let mut per_directory = Vec::new();
+    for item in doc
+        .get("per_directory")
+        .and_then(Value::as_array)
+        .ok_or("missing per_directory")?
+    {
+        per_directory.push(DirectoryStats {
+            directory: item
+                .get("directory")
+                .and_then(Value::as_str)
+                .map(PathBuf::from)
+                .ok_or("missing directory")?,
+            duplicate_files: count(item, "duplicate_files")?,
+            reclaimable_bytes: number(item, "reclaimable_bytes")?,
+        });
+    }
+    Ok(Run {
+        time,
+        directories,
+        files_scanned: number(&doc, "files_scanned")?,
+        bytes_hashed: number(&doc, "bytes_hashed")?,
+        duplicate_groups: count(&doc, "duplicate_groups")?,
+        duplicate_files: count(&doc, "duplicate_files")?,
+        reclaimable_bytes: number(&doc, "reclaimable_bytes")?,
+        per_directory,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DuplicateGroup;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_history_round_trip_and_trends() {
+        let dir = tempdir().expect("create temp dir");
+        let root_a = dir.path().join("a");
+        let root_b = dir.path().join("b");
+        let group = |size, paths: &[&PathBuf]| DuplicateGroup {
+            hash: String::from("somehash"),
+            size,
+            paths: paths.iter().map(|path| path.join("file")).collect(),
+        };
+        let mut report = DuplicateReport::from_groups(vec![
+            group(100, &[&root_a, &root_a, &root_b]),
+            group(50, &[&root_b, &root_b]),
+        ]);
+        report.roots = vec![root_a.clone(), root_b.clone()];
+        report.files_scanned = 12;
+        let first = Run::from_report(&report);
+        assert_eq!(first.reclaimable_bytes, 250);
+        assert_eq!(first.per_directory[0].duplicate_files, 2);
+        assert_eq!(first.per_directory[0].reclaimable_bytes, 100);
+        assert_eq!(first.per_directory[1].duplicate_files, 3);
+        assert_eq!(first.per_directory[1].reclaimable_bytes, 150);
+
+        report = DuplicateReport::from_groups(vec![group(100, &[&root_a, &root_b])]);
+        report.roots = vec![root_a, root_b];
+        let second = Run::from_report(&report);
+
+        let history = dir.path().join("history.jsonl");
+        record(&history, &first).expect("record run");
+        fs::write(
+            &history,
+            fs::read_to_string(&history).expect("read history") + "not json\n",
+        )
+        .expect("append garbage");
+        record(&history, &second).expect("record run");
+        let runs = load(&history).expect("load history");
+        // Times are kept to the second's fraction, so the runs match exactly.
+        assert_eq!(runs, vec![first, second]);
+
+        let mut out = Vec::new();
+        write_trends_to(&runs, 10, &mut out).expect("write trends");
+        let text = String::from_utf8(out).expect("utf-8");
+        assert!(text.contains("(2 runs)"), "{text}");
+        assert!(text.contains("-150 bytes"), "{text}");
+        assert!(
+            text.contains("b: 100 bytes in 1 duplicates (-50 bytes)"),
+            "{text}"
+        );
+
+        assert!(load(&dir.path().join("missing.jsonl"))
+            .expect("missing history")
+            .is_empty());
+    }
+}
@@ -0,0 +1,254 @@
+//! A self-contained HTML version of the report for sharing with others.
+//!
+//! The page has no external stylesheets or scripts, so it still renders when
+//! sent as an attachment. Every path is linked with a `file://` URL so it can
+//! be opened straight from the browser, and images are previewed from the
+//! same URL; previews of files the reader cannot reach simply do not load.
+//! The small inline script only adds sorting, and the report reads the same
+//! in mail clients that block it.
+
+use crate::{format_size, DuplicateReport, FileMetadata};
+use chrono::{DateTime, Local};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:1.5em;width:100%}\
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:left}\
+th{background:#eee}\
+.warning{color:#a00;font-weight:bold}\
+summary{cursor:pointer;font-weight:bold;margin-bottom:.5em}\
+th{cursor:pointer}\
+img{max-width:96px;max-height:96px;vertical-align:middle;margin-right:8px}";
+
+/// Sorts a table by the clicked column, using a cell's `data-sort` value when
+/// it has one; clicking again reverses the order.
+const SCRIPT: &str = "document.querySelectorAll('th').forEach(function(th){\
+th.addEventListener('click',function(){\
+var body=th.closest('table').tBodies[0],col=th.cellIndex,asc=th.dataset.order!=='asc';\
+th.dataset.order=asc?'asc':'desc';\
+var key=function(row){var cell=row.cells[col];return cell.dataset.sort||cell.textContent;};\
+Array.from(body.rows).sort(function(a,b){\
+return (asc?1:-1)*key(a).localeCompare(key(b),undefined,{numeric:true});\
+}).forEach(function(row){body.appendChild(row);});});});";
+
+/// Extensions of the files previewed with a thumbnail.
+const IMAGE_EXTENSIONS: [&str; 8] = ["png", "jpg", "jpeg", "gif", "webp", "bmp", "svg", "avif"];
+
+/// Writes the report as a single HTML page.
+///
+/// A summary with the number of groups and the reclaimable space comes first,
+/// followed by one collapsible table per group in the report's order, which
+/// is descending by file size. Each table lists the files with their
+/// modification time and read-only flag, and sorts by any column when its
+/// heading is clicked. Images get a thumbnail. Paths are escaped, so names
+/// containing `<` or `&` display as written.
+///
+/// # Errors
+/// Returns any I/O error raised by `writer`.
+///
+/// # Example
+/// ```
+/// use std::path::PathBuf;
+/// use duplicate_file_finder::{write_html_to, DuplicateGroup, DuplicateReport};
+///
+/// let report = DuplicateReport::from_groups(vec![DuplicateGroup {
+///     hash: String::from("somehash"),
+///     size: 1024,
+///     paths: vec![PathBuf::from("/tmp/a&b.txt"), PathBuf::from("/tmp/c.txt")],
+/// }]);
+/// let mut buffer = Vec::new();
+/// write_html_to(&mut buffer, &report).unwrap();
+/// let html = String::from_utf8(buffer).unwrap();
+/// assert!(html.contains("a%26b.txt\">/tmp/a&amp;b.txt</a>"));
+/// ```
+pub fn write_html_to<W: Write>(writer: &mut W, report: &DuplicateReport) -> io::Result<()> {
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(writer, "<html lang=\"en\">")?;
+    writeln!(writer, "<head>")?;
+    writeln!(writer, "<meta charset=\"utf-8\">")?;
+    writeln!(writer, "<title>Duplicate File Finder Report</title>")?;
+    writeln!(writer, "<style>{STYLE}</style>")?;
+    writeln!(writer, "</head>")?;
+    writeln!(writer, "<body>")?;
+    writeln!(writer, "<h1>Duplicate File Finder Report</h1>")?;
+
+    writeln!(writer, "<h2>Summary</h2>")?;
+    if report.interrupted {
+        writeln!(
+            writer,
+            "<p class=\"warning\">Scan interrupted: the results below are incomplete.</p>"
+        )?;
+    }
+    writeln!(writer, "<ul>")?;
+    writeln!(writer, "<li>Duplicate groups: {}</li>", report.total_groups)?;
+    writeln!(
+        writer,
+        "<li>Total potential space savings: {}</li>",
+        format_size(report.total_reclaimable_bytes)
+    )?;
+    if !report.errors.is_empty() {
+        writeln!(
+            writer,
+            "<li>Files skipped due to errors: {}</li>",
+            report.errors.len()
+        )?;
+    }
+    writeln!(writer, "</ul>")?;
+
+    for (index, group) in report.groups.iter().enumerate() {
+        writeln!(writer, "<details open>")?;
+        writeln!(
+            writer,
+            "<summary>Group {} &middot; {} &middot; {} files</summary>",
+            index + 1,
+            format_size(group.size),
+            group.paths.len()
+        )?;
+        writeln!(writer, "<table>")?;
+        writeln!(
+            writer,
+            "<thead><tr><th>File</th><th>Modified</th><th>Read-only</th></tr></thead>"
+        )?;
+        writeln!(writer, "<tbody>")?;
+        for path in &group.paths {
+            write_file_row(writer, path, report.metadata.get(path))?;
+        }
+        writeln!(writer, "</tbody>")?;
+        writeln!(writer, "</table>")?;
+        writeln!(writer, "</details>")?;
+    }
+
+    writeln!(writer, "<script>{SCRIPT}</script>")?;
+    writeln!(writer, "</body>")?;
+    writeln!(writer, "</html>")
+}
+
+/// Writes one table row for `path`, with a thumbnail if it is an image.
+fn write_file_row<W: Write>(
+    writer: &mut W,
+    path: &Path,
+    metadata: Option<&FileMetadata>,
+) -> io::Result<()> {
+    let url = escape(&file_url(path));
+    let is_image = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+    let thumbnail = if is_image {
+        format!("<img src=\"{url}\" alt=\"\" loading=\"lazy\">")
+    } else {
+        String::new()
+    };
+    let modified = metadata.and_then(|m| m.modified);
+    let (sort_key, shown) = modified.map_or_else(
+        || (String::new(), "unknown".to_string()),
+        |time| {
+            let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            let shown = DateTime::<Local>::from(time)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string();
+            (secs.to_string(), shown)
+        },
+    );
+    let readonly = match metadata {
+        Some(m) if m.readonly => "yes",
+        Some(_) => "no",
+        None => "unknown",
+    };
+    writeln!(
+        writer,
+        "<tr><td>{thumbnail}<a href=\"{url}\">{}</a></td>\
+         <td data-sort=\"{sort_key}\">{shown}</td><td>{readonly}</td></tr>",
+        escape(&path.to_string_lossy())
+    )
+}
+
+/// Escapes text for use in HTML content and quoted attribute values.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Builds a percent-encoded `file://` URL for `path`, made absolute if possible.
+fn file_url(path: &Path) -> String {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let text = absolute.to_string_lossy();
+    let mut url = String::from("file://");
+    // Windows paths such as `C:\dir` become `file:///C:/dir`.
+    if !text.starts_with('/') {
+        url.push('/');
+    }
+    for byte in text.bytes() {
+        match byte {
+            b'\\' if cfg!(windows) => url.push('/'),
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                url.push(char::from(byte));
+            }
+            _ => {
+                let _ = write!(url, "%{byte:02X}");
+            }
+        }
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DuplicateGroup;
+    use std::path::PathBuf;
+
+    // The expected URLs assume Unix-style absolute paths.
+    #[cfg(unix)]
+    #[test]
+    fn test_write_html_escapes_paths() {
+        let report = DuplicateReport::from_groups(vec![
+            DuplicateGroup {
+                hash: "small".to_string(),
+                size: 10,
+                paths: vec![
+                    PathBuf::from("/data/<b>bold</b> & \"quoted\".txt"),
+                    PathBuf::from("/data/plain.txt"),
+                ],
+            },
+            DuplicateGroup {
+                hash: "large".to_string(),
+                size: 2048,
+                paths: vec![PathBuf::from("/data/c.bin"), PathBuf::from("/data/d.bin")],
+            },
+        ]);
+
+        let mut buffer = Vec::new();
+        write_html_to(&mut buffer, &report).expect("write to buffer");
+        let html = String::from_utf8(buffer).expect("valid UTF-8");
+
+        assert!(html.starts_with("<!DOCTYPE html>\n"));
+        assert!(html.contains("<li>Duplicate groups: 2</li>"));
+        assert!(html.contains("<li>Total potential space savings: 2.01 KB</li>"));
+        assert!(!html.contains("<b>bold"));
+        assert!(!html.contains("<link") && !html.contains("<script src"));
+        assert!(html.contains(
+            "<a href=\"file:///data/%3Cb%3Ebold%3C/b%3E%20%26%20%22quoted%22.txt\">\
+             /data/&lt;b&gt;bold&lt;/b&gt; &amp; &quot;quoted&quot;.txt</a>"
+        ));
+        assert_eq!(html.matches("<details open>").count(), 2);
+        assert!(html.contains("<th>Modified</th>"));
+        assert!(!html.contains("<img"));
+        let large = html.find("Group 1 &middot; 2.00 KB");
+        let small = html.find("Group 2 &middot; 10 bytes");
+        assert!(large.expect("large group") < small.expect("small group"));
+    }
+}
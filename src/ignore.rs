@@ -0,0 +1,264 @@
+//! Ignore files in the syntax of `.gitignore`, which leave paths out of a
+//! scan without listing them on the command line.
+//!
+//! Each line of an ignore file is a pattern applied to paths below the
+//! directory that holds the file:
+//!
+//! * Blank lines and lines starting with `#` are skipped; trailing spaces
+//!   are dropped unless escaped with `\`.
+//! * A pattern without a `/`, such as `*.tmp`, matches a name at any depth.
+//!   One with a `/` at the start or in the middle, such as `/build` or
+//!   `docs/*.pdf`, is relative to the ignore file's directory.
+//! * A trailing `/` makes the pattern match directories only.
+//! * A leading `!` includes again a path that an earlier pattern ignored,
+//!   unless one of its parent directories is ignored.
+//! * `*` and `?` never match `/`; `**` matches any number of directories.
+//!
+//! When several patterns match a path the last one wins, and files in
+//! deeper directories win over those above them.
+//!
+//! When a scan respects git's rules, `.gitignore` files and the
+//! `.git/info/exclude` file of a repository are read the same way, and
+//! `.git` directories themselves are skipped.
+
+use crate::glob::Glob;
+use crate::GlobError;
+use log::warn;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The ignore file read in every scanned directory.
+const DUPEIGNORE: &str = ".dupeignore";
+
+/// The ignore files git reads in a directory, lowest precedence first.
+const GIT_IGNORE_FILES: [&str; 2] = [".git/info/exclude", ".gitignore"];
+
+/// Where the ignore rules of a scan come from.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct IgnoreConfig {
+    /// File names read in every directory of the walk, lowest precedence
+    /// first.
+    names: Vec<&'static str>,
+    /// Files given explicitly, whose patterns are relative to each scanned
+    /// directory.
+    files: Vec<PathBuf>,
+    /// Whether `.git` directories are skipped.
+    git: bool,
+}
+
+impl IgnoreConfig {
+    /// Reads `.dupeignore` files and `files`, and git's ignore files too
+    /// when `git` is set.
+    pub(crate) fn new(files: Vec<PathBuf>, git: bool) -> Self {
+        let mut names = Vec::new();
+        if git {
+            names.extend(GIT_IGNORE_FILES);
+        }
+        names.push(DUPEIGNORE);
+        Self { names, files, git }
+    }
+}
+
+/// The rules of one ignore file.
+#[derive(Debug, Clone)]
+struct IgnoreFile {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+    /// Whether the pattern is matched against the whole relative path
+    /// rather than only the file name.
+    anchored: bool,
+}
+
+impl IgnoreFile {
+    /// Reads the ignore file at `path`, returning `None` if there is none.
+    fn load(path: &Path) -> Option<Self> {
+        match std::fs::read(path) {
+            Ok(bytes) => Some(Self::parse(&String::from_utf8_lossy(&bytes), path)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                warn!("Cannot read ignore file {}: {e}", path.display());
+                None
+            }
+        }
+    }
+
+    /// Parses the lines of an ignore file, skipping invalid patterns as git
+    /// does.
+    fn parse(text: &str, source: &Path) -> Self {
+        let rules = text
+            .lines()
+            .filter_map(|line| {
+                Rule::parse(line).unwrap_or_else(|e| {
+                    warn!("Skipping pattern in {}: {e}", source.display());
+                    None
+                })
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Returns whether the last rule matching `rel` ignores it, or `None`
+    /// if no rule matches.
+    fn decision(&self, rel: &Path, is_dir: bool) -> Option<bool> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(rel, is_dir))
+            .map(|rule| !rule.negated)
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Result<Option<Self>, GlobError> {
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let line = trim_trailing_spaces(line);
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let anchored = pattern.contains('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        if pattern.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            glob: Glob::new_in_components(pattern)?,
+            negated,
+            dir_only,
+            anchored,
+        }))
+    }
+
+    fn matches(&self, rel: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            self.glob.is_path_match(rel)
+        } else {
+            rel.file_name()
+                .is_some_and(|name| self.glob.is_path_match(Path::new(name)))
+        }
+    }
+}
+
+/// Drops trailing spaces, keeping one escaped with a backslash.
+fn trim_trailing_spaces(line: &str) -> &str {
+    let trimmed = line.trim_end_matches(' ');
+    if trimmed.len() < line.len() && trimmed.ends_with('\\') {
+        &line[..=trimmed.len()]
+    } else {
+        trimmed
+    }
+}
+
+/// The ignore rules met while walking one scanned directory.
+///
+/// Ignore files are read the first time the walk looks inside the
+/// directory holding them, so directories that are pruned are never
+/// searched for one.
+#[derive(Debug)]
+pub(crate) struct Ignores<'a> {
+    config: &'a IgnoreConfig,
+    root: &'a Path,
+    /// The explicitly given files, which apply below the root.
+    given: Vec<IgnoreFile>,
+    /// The files found in each directory visited so far, keyed by the
+    /// directory's path relative to the root.
+    found: HashMap<PathBuf, Vec<IgnoreFile>>,
+}
+
+impl<'a> Ignores<'a> {
+    pub(crate) fn new(config: &'a IgnoreConfig, root: &'a Path) -> Self {
+        let given = config
+            .files
+            .iter()
+            .filter_map(|path| IgnoreFile::load(path))
+            .collect();
+        Self {
+            config,
+            root,
+            given,
+            found: HashMap::new(),
+        }
+    }
+
+    /// Returns `true` if the rules ignore a walked entry. The scanned root
+    /// itself is never ignored.
+    pub(crate) fn is_ignored(&mut self, entry: &walkdir::DirEntry) -> bool {
+        if entry.depth() == 0 || (self.given.is_empty() && self.config.names.is_empty()) {
+            return false;
+        }
+        let rel = entry.path().strip_prefix(self.root).unwrap_or(entry.path());
+        let is_dir = entry.file_type().is_dir();
+        if self.config.git && is_dir && entry.file_name() == ".git" {
+            return true;
+        }
+
+        let mut ignored = None;
+        for file in &self.given {
+            ignored = file.decision(rel, is_dir).or(ignored);
+        }
+        let mut dir = PathBuf::new();
+        let parents = rel.parent().into_iter().flat_map(Path::components);
+        for component in std::iter::once(None).chain(parents.map(Some)) {
+            if let Some(component) = component {
+                dir.push(component);
+            }
+            if !self.found.contains_key(&dir) {
+                let files = self
+                    .config
+                    .names
+                    .iter()
+                    .filter_map(|name| IgnoreFile::load(&self.root.join(&dir).join(name)))
+                    .collect();
+                self.found.insert(dir.clone(), files);
+            }
+            let below = rel.strip_prefix(&dir).unwrap_or(rel);
+            for file in &self.found[&dir] {
+                ignored = file.decision(below, is_dir).or(ignored);
+            }
+        }
+        ignored == Some(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(text: &str) -> IgnoreFile {
+        IgnoreFile::parse(text, Path::new(".dupeignore"))
+    }
+
+    #[test]
+    fn test_ignore_file_rules() {
+        let rules = file("# comment\n\n*.tmp\n/build/\ndocs/*.pdf\n!keep.tmp\ntrailing\\ \n");
+        let ignored = |path: &str, is_dir| rules.decision(Path::new(path), is_dir);
+
+        assert_eq!(ignored("a/b/c.tmp", false), Some(true));
+        assert_eq!(ignored("a/keep.tmp", false), Some(false));
+        assert_eq!(ignored("build", true), Some(true));
+        // Directory-only and anchored patterns.
+        assert_eq!(ignored("build", false), None);
+        assert_eq!(ignored("src/build", true), None);
+        assert_eq!(ignored("docs/manual.pdf", false), Some(true));
+        // `*` does not cross directories.
+        assert_eq!(ignored("docs/old/manual.pdf", false), None);
+        assert_eq!(ignored("trailing ", false), Some(true));
+        assert_eq!(ignored("trailing", false), None);
+    }
+}
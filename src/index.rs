@@ -0,0 +1,551 @@
+//! Scan indexes: the path, size and full hash of every file below some
+//! directories, saved by `index create` so duplicates between two machines
+//! can be found without copying any data between them.
+//!
+//! An index is a text file of JSON lines. The first describes the index:
+//! the machine it was made on, when, with which [`HashAlgo`] and of which
+//! directories. Every other line is one file. An index is used in place of
+//! a directory: the files of this machine that share a size with one of its
+//! entries are hashed with its algorithm, and those with the same hash are
+//! duplicates of the entry. Files of an index are reported as the host
+//! name, a colon and their path on that host, such as `nas:/data/a.jpg`;
+//! they cannot be opened by that path.
+
+use crate::cache::{algo_name, parse_algo};
+use crate::json::{self, Value};
+use crate::{
+    build_pool, collect_files, find_duplicates_with_options, full_hash_with, in_pool, into_errors,
+    is_cancelled, record_error, Console, DuplicateGroup, DuplicateReport, GlobError, HashAlgo,
+    Progress, ReadProgress, ScanConfig, ScanOptions, Stage,
+};
+use chrono::{DateTime, Local};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+/// Version of the index format. Bump it whenever the layout changes.
+pub const INDEX_VERSION: u64 = 1;
+
+/// The `format` of the first line of every index, telling it apart from
+/// other JSON lines files.
+const INDEX_FORMAT: &str = "duplicate_file_finder index";
+
+/// The files below some directories of one machine, with their hashes.
+#[derive(Debug)]
+pub struct Index {
+    /// The name of the machine the index was made on.
+    pub host: String,
+    /// When the index was made.
+    pub created: DateTime<Local>,
+    /// The algorithm the hashes of the entries were computed with.
+    pub hash_algo: HashAlgo,
+    /// The directories indexed, made absolute.
+    pub roots: Vec<PathBuf>,
+    /// Every file indexed, sorted by path.
+    pub entries: Vec<IndexEntry>,
+    /// Paths that could not be read while making the index, with the error
+    /// raised. They have no entry. An index read from a file has none.
+    pub errors: Vec<(PathBuf, io::Error)>,
+    /// Whether the index was cancelled before every file was hashed.
+    pub interrupted: bool,
+}
+
+/// One file of an [`Index`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// The file's absolute path on the machine indexed.
+    pub path: PathBuf,
+    /// Its size in bytes.
+    pub size: u64,
+    /// The lowercase hex digest of its contents.
+    pub hash: String,
+}
+
+impl Index {
+    /// Returns how `path`, a path on the indexed machine, is reported: the
+    /// host name, a colon and the path, or the path alone if the index has
+    /// no host, as for [objects in S3](crate::s3).
+    #[must_use]
+    pub fn label(&self, path: &Path) -> PathBuf {
+        if self.host.is_empty() {
+            return path.to_path_buf();
+        }
+        PathBuf::from(format!("{}:{}", self.host, path.display()))
+    }
+
+    /// Writes the index to `writer` in the format [`read_index`] reads.
+    ///
+    /// # Errors
+    /// Returns any I/O error raised by the writer.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let roots: Vec<String> = self
+            .roots
+            .iter()
+            .map(|root| json::quote(&root.to_string_lossy()))
+            .collect();
+        writeln!(
+            writer,
+            "{{\"format\":{},\"version\":{INDEX_VERSION},\"host\":{},\"created\":{},\
+             \"hash_algo\":{},\"roots\":[{}]}}",
+            json::quote(INDEX_FORMAT),
+            json::quote(&self.host),
+            json::quote(&self.created.to_rfc3339()),
+            json::quote(algo_name(self.hash_algo)),
+            roots.join(",")
+        )?;
+        for entry in &self.entries {
+            writeln!(
+                writer,
+                "{{\"path\":{},\"size\":{},\"hash\":{}}}",
+                json::quote(&entry.path.to_string_lossy()),
+                entry.size,
+                json::quote(&entry.hash)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Hashes every file in `dirs` into an index of this machine, with the
+/// patterns, size limits, [`HashAlgo`] and cache of `options`.
+///
+/// # Errors
+/// Returns an error if any pattern in `options` is invalid.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::index::create_index;
+/// use duplicate_file_finder::ScanOptions;
+/// use std::fs;
+/// use tempfile::tempdir;
+///
+/// let dir = tempdir().unwrap();
+/// fs::write(dir.path().join("a.txt"), b"indexed").unwrap();
+/// let index = create_index(&[dir.path().to_path_buf()], &ScanOptions::new().quiet(true)).unwrap();
+/// assert_eq!(index.entries.len(), 1);
+/// assert_eq!(index.entries[0].size, 7);
+/// ```
+pub fn create_index(dirs: &[PathBuf], options: &ScanOptions) -> Result<Index, GlobError> {
+    let config = ScanConfig::new(options)?;
+    let console = Console::new(config.quiet, config.progress_json, config.progress);
+    let errors = Mutex::new(Vec::new());
+    let files: Vec<(u64, PathBuf)> =
+        collect_files(dirs, &config.walk, config.cancel, &errors, &mut Vec::new())
+            .into_iter()
+            .map(|file| (file.meta.len(), absolute(&file.path)))
+            .filter(|&(size, _)| {
+                config.min_size.is_none_or(|min| size >= min)
+                    && config.max_size.is_none_or(|max| size <= max)
+            })
+            .collect();
+    console.status(&format!(
+        "{} files identified across {} directories",
+        files.len(),
+        dirs.len()
+    ));
+
+    let mut entries = hash_files(files, config.hash_algo, &config, &console, &errors);
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    console.complete(0);
+    Ok(Index {
+        host: whoami::fallible::hostname().unwrap_or_else(|_| "localhost".to_string()),
+        created: Local::now(),
+        hash_algo: config.hash_algo,
+        roots: dirs.iter().map(|dir| absolute(dir)).collect(),
+        entries,
+        errors: into_errors(errors),
+        interrupted: is_cancelled(config.cancel),
+    })
+}
+
+/// Writes `index` to the file at `path`, replacing it.
+///
+/// # Errors
+/// Returns any I/O error raised while writing the file.
+pub fn write_index(path: &Path, index: &Index) -> io::Result<()> {
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+    index.write_to(&mut writer)?;
+    writer.flush()
+}
+
+/// Reads an index written by [`Index::write_to`].
+///
+/// # Errors
+/// Returns any I/O error raised while reading `path`, or an error of kind
+/// [`io::ErrorKind::InvalidData`] if it is not an index of this version.
+pub fn read_index(path: &Path) -> io::Result<Index> {
+    let text = fs::read_to_string(path).map_err(|e| match e.kind() {
+        io::ErrorKind::InvalidData => invalid("not an index".to_string()),
+        _ => e,
+    })?;
+    parse_index(&text)
+}
+
+/// Parses the text of an index, as [`read_index`] does.
+pub(crate) fn parse_index(text: &str) -> io::Result<Index> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let header = lines
+        .next()
+        .and_then(|(_, line)| json::parse(line).ok())
+        .filter(|doc| doc.get("format").and_then(Value::as_str) == Some(INDEX_FORMAT))
+        .ok_or_else(|| invalid("not an index".to_string()))?;
+    match header.get("version").and_then(Value::as_u64) {
+        Some(INDEX_VERSION) => {}
+        Some(other) => return Err(invalid(format!("unsupported index version {other}"))),
+        None => return Err(invalid("missing version".to_string())),
+    }
+    let string = |doc: &Value, name: &str| {
+        doc.get(name)
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or(format!("missing {name}"))
+    };
+    let parse_header = || -> Result<Index, String> {
+        let created = string(&header, "created")?;
+        let hash_algo = string(&header, "hash_algo")?;
+        Ok(Index {
+            host: string(&header, "host")?,
+            created: DateTime::parse_from_rfc3339(&created)
+                .map_err(|e| format!("invalid created: {e}"))?
+                .with_timezone(&Local),
+            hash_algo: parse_algo(&hash_algo)
+                .ok_or(format!("unknown hash algorithm {hash_algo}"))?,
+            roots: header
+                .get("roots")
+                .and_then(Value::as_array)
+                .ok_or("missing roots")?
+                .iter()
+                .map(|root| root.as_str().map(PathBuf::from).ok_or("invalid root"))
+                .collect::<Result<_, _>>()?,
+            entries: Vec::new(),
+            errors: Vec::new(),
+            interrupted: false,
+        })
+    };
+    let mut index = parse_header().map_err(|reason| invalid(format!("line 1: {reason}")))?;
+    for (number, line) in lines {
+        let entry = json::parse(line).and_then(|doc| {
+            Ok(IndexEntry {
+                path: PathBuf::from(string(&doc, "path")?),
+                size: doc
+                    .get("size")
+                    .and_then(Value::as_u64)
+                    .ok_or("missing size")?,
+                hash: string(&doc, "hash")?,
+            })
+        });
+        index
+            .entries
+            .push(entry.map_err(|reason| invalid(format!("line {}: {reason}", number + 1)))?);
+    }
+    Ok(index)
+}
+
+fn invalid(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Scans `dirs` for duplicates as [`find_duplicates_with_options`] does,
+/// and also finds the files of `indexes` that are duplicates of each other
+/// or of a file in `dirs`.
+///
+/// Files of an index are reported by their [`Index::label`], and the roots
+/// of each index are added to the report's roots the same way. With no
+/// directories, only the indexes are compared.
+///
+/// # Errors
+/// Returns an error if any pattern in `options` is invalid.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::index::{create_index, find_duplicates_with_indexes};
+/// use duplicate_file_finder::ScanOptions;
+/// use std::fs;
+/// use tempfile::tempdir;
+///
+/// let (here, there) = (tempdir().unwrap(), tempdir().unwrap());
+/// fs::write(here.path().join("a.txt"), b"same").unwrap();
+/// fs::write(there.path().join("b.txt"), b"same").unwrap();
+/// let options = ScanOptions::new().quiet(true);
+/// let index = create_index(&[there.path().to_path_buf()], &options).unwrap();
+///
+/// let report =
+///     find_duplicates_with_indexes(&[here.path().to_path_buf()], &[index], &options).unwrap();
+/// assert_eq!(report.groups[0].paths.len(), 2);
+/// ```
+pub fn find_duplicates_with_indexes(
+    dirs: &[PathBuf],
+    indexes: &[Index],
+    options: &ScanOptions,
+) -> Result<DuplicateReport, GlobError> {
+    if indexes.is_empty() {
+        return find_duplicates_with_options(dirs, options);
+    }
+    let config = ScanConfig::new(options)?;
+    let local = if dirs.is_empty() {
+        DuplicateReport::default()
+    } else {
+        find_duplicates_with_options(dirs, options)?
+    };
+    let console = Console::new(config.quiet, config.progress_json, config.progress);
+    let errors = Mutex::new(Vec::new());
+    let files = if dirs.is_empty() {
+        Vec::new()
+    } else {
+        local_files(dirs, &config, &errors)
+    };
+
+    let mut by_hash: HashMap<(HashAlgo, String, u64), Vec<PathBuf>> = HashMap::new();
+    let mut key_of: HashMap<PathBuf, (HashAlgo, String, u64)> = HashMap::new();
+    for (hash_algo, entry) in hash_matching(&files, indexes, &config, &console, &errors) {
+        let key = (hash_algo, entry.hash, entry.size);
+        key_of.insert(entry.path.clone(), key.clone());
+        by_hash.entry(key).or_default().push(entry.path);
+    }
+    for index in indexes {
+        for entry in &index.entries {
+            if config.min_size.is_none_or(|min| entry.size >= min)
+                && config.max_size.is_none_or(|max| entry.size <= max)
+            {
+                by_hash
+                    .entry((index.hash_algo, entry.hash.clone(), entry.size))
+                    .or_default()
+                    .push(index.label(&entry.path));
+            }
+        }
+    }
+
+    // A local group with a file hashed against an index joins that file's
+    // group, so its copies are listed once, with those of the index.
+    let mut groups = Vec::new();
+    for group in local.groups {
+        let Some(paths) = group
+            .paths
+            .iter()
+            .find_map(|path| key_of.get(path))
+            .and_then(|key| by_hash.get_mut(key))
+        else {
+            groups.push(group);
+            continue;
+        };
+        let known: HashSet<PathBuf> = paths.iter().cloned().collect();
+        paths.extend(group.paths.into_iter().filter(|path| !known.contains(path)));
+    }
+    for ((_, hash, size), paths) in by_hash {
+        if paths.len() > 1 {
+            groups.extend(
+                config
+                    .name_match
+                    .split(DuplicateGroup { hash, size, paths }),
+            );
+        }
+    }
+
+    let mut report = DuplicateReport::from_groups(groups);
+    report.errors = local.errors;
+    report.errors.extend(into_errors(errors));
+    report.hard_links = local.hard_links;
+    report.metadata = local.metadata;
+    report.roots = dirs.to_vec();
+    for index in indexes {
+        report
+            .roots
+            .extend(index.roots.iter().map(|root| index.label(root)));
+    }
+    report.interrupted = local.interrupted || is_cancelled(config.cancel);
+    report.files_scanned = local.files_scanned
+        + indexes
+            .iter()
+            .map(|index| index.entries.len() as u64)
+            .sum::<u64>();
+    report.bytes_hashed = local.bytes_hashed + config.bytes_read.load(Ordering::Relaxed);
+    Ok(report)
+}
+
+/// Finds which of `files`, the files this machine holds, are in
+/// `indexes`, pairing each with the [`Index::label`] of one of its copies
+/// there. Files whose size no entry shares are not read.
+///
+/// Files that could not be read are recorded in `errors`.
+pub(crate) fn copies_in_indexes(
+    files: &[(u64, PathBuf)],
+    indexes: &[Index],
+    config: &ScanConfig,
+    errors: &Mutex<Vec<(PathBuf, io::Error)>>,
+) -> HashMap<PathBuf, PathBuf> {
+    let console = Console::new(config.quiet, config.progress_json, config.progress);
+    let mut copies: HashMap<(HashAlgo, &str, u64), PathBuf> = HashMap::new();
+    for index in indexes {
+        for entry in &index.entries {
+            copies
+                .entry((index.hash_algo, &entry.hash, entry.size))
+                .or_insert_with(|| index.label(&entry.path));
+        }
+    }
+    hash_matching(files, indexes, config, &console, errors)
+        .into_iter()
+        .filter_map(|(hash_algo, entry)| {
+            let copy = copies.get(&(hash_algo, entry.hash.as_str(), entry.size))?;
+            Some((entry.path, copy.clone()))
+        })
+        .collect()
+}
+
+/// Lists the files in `dirs`, with their sizes, within the size limits of
+/// `config`.
+fn local_files(
+    dirs: &[PathBuf],
+    config: &ScanConfig,
+    errors: &Mutex<Vec<(PathBuf, io::Error)>>,
+) -> Vec<(u64, PathBuf)> {
+    collect_files(dirs, &config.walk, config.cancel, errors, &mut Vec::new())
+        .into_iter()
+        .map(|file| (file.meta.len(), file.path))
+        .filter(|&(size, _)| {
+            config.min_size.is_none_or(|min| size >= min)
+                && config.max_size.is_none_or(|max| size <= max)
+        })
+        .collect()
+}
+
+/// Hashes each of `files` that shares its size with an entry of one of
+/// `indexes`, once with the algorithm of every such index, returning the
+/// hashes with the algorithm used.
+fn hash_matching(
+    files: &[(u64, PathBuf)],
+    indexes: &[Index],
+    config: &ScanConfig,
+    console: &Console,
+    errors: &Mutex<Vec<(PathBuf, io::Error)>>,
+) -> Vec<(HashAlgo, IndexEntry)> {
+    let mut sizes: HashMap<HashAlgo, HashSet<u64>> = HashMap::new();
+    for index in indexes {
+        let algo_sizes = sizes.entry(index.hash_algo).or_default();
+        algo_sizes.extend(index.entries.iter().map(|entry| entry.size));
+    }
+    let mut hashed = Vec::new();
+    for (hash_algo, sizes) in sizes {
+        let matching: Vec<(u64, PathBuf)> = files
+            .iter()
+            .filter(|(size, _)| sizes.contains(size))
+            .cloned()
+            .collect();
+        if !matching.is_empty() {
+            let entries = hash_files(matching, hash_algo, config, console, errors);
+            hashed.extend(entries.into_iter().map(|entry| (hash_algo, entry)));
+        }
+    }
+    hashed
+}
+
+/// Hashes the whole of each of `files` with `hash_algo`, through the cache
+/// of `config` if it has one, stopping early once the scan is cancelled.
+fn hash_files(
+    files: Vec<(u64, PathBuf)>,
+    hash_algo: HashAlgo,
+    config: &ScanConfig,
+    console: &Console,
+    errors: &Mutex<Vec<(PathBuf, io::Error)>>,
+) -> Vec<IndexEntry> {
+    let total_bytes = files
+        .iter()
+        .map(|&(size, _)| size)
+        .fold(0, u64::saturating_add);
+    let progress = console.byte_bar(total_bytes, Stage::FullHash, "Computing full hashes...");
+    let pool = build_pool(config.threads);
+    let io_pool = build_pool(config.io_threads);
+    let entries = in_pool(io_pool.as_ref().or(pool.as_ref()), || {
+        files
+            .into_par_iter()
+            .filter_map(|(size, path)| {
+                if is_cancelled(config.cancel) {
+                    return None;
+                }
+                match hash_file(&path, size, hash_algo, config, &progress) {
+                    Ok(hash) => Some(IndexEntry { path, size, hash }),
+                    Err(e) => {
+                        record_error(errors, &path, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    });
+    progress.finish_with_message("Full hashes computed.");
+    entries
+}
+
+fn hash_file(
+    path: &Path,
+    size: u64,
+    hash_algo: HashAlgo,
+    config: &ScanConfig,
+    progress: &Progress,
+) -> io::Result<String> {
+    let read = ReadProgress::new(progress, size);
+    let on_read = |bytes| {
+        config.pace(bytes);
+        read.advance(bytes);
+    };
+    let hash = config.read(path, || match config.cache {
+        Some(cache) => cache.full_hash(path, hash_algo, &on_read),
+        None => full_hash_with(path, hash_algo, &on_read),
+    });
+    read.finish();
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_index_round_trip_and_matching() {
+        let (here, there) = (
+            tempdir().expect("create temp dir"),
+            tempdir().expect("create temp dir"),
+        );
+        fs::write(here.path().join("a.txt"), b"shared").expect("write file");
+        fs::write(here.path().join("b.txt"), b"shared").expect("write file");
+        fs::write(here.path().join("c.txt"), b"only here").expect("write file");
+        fs::write(there.path().join("copy.txt"), b"shared").expect("write file");
+        fs::write(there.path().join("other.txt"), b"sharer").expect("write file");
+        let options = ScanOptions::new().quiet(true).hash_algo(HashAlgo::Xxh3);
+        let index = create_index(&[there.path().to_path_buf()], &options).expect("create index");
+
+        let path = here.path().join("index.dff");
+        write_index(&path, &index).expect("write index");
+        let read = read_index(&path).expect("read index");
+        assert_eq!(read.entries, index.entries);
+        assert_eq!(read.roots, index.roots);
+        assert_eq!(read.host, index.host);
+        assert_eq!(read.hash_algo, HashAlgo::Xxh3);
+
+        // Local files are hashed with the index's algorithm, whatever the
+        // scan's, and join the local group of their copies.
+        let options = ScanOptions::new().quiet(true).skip_files([path.clone()]);
+        let report = find_duplicates_with_indexes(&[here.path().to_path_buf()], &[read], &options)
+            .expect("scan");
+        assert_eq!(report.groups.len(), 1);
+        let copy = index.label(&there.path().join("copy.txt"));
+        assert_eq!(report.groups[0].paths.len(), 3);
+        assert!(report.groups[0].paths.contains(&copy));
+        assert_eq!(report.roots[1], index.label(&index.roots[0]));
+
+        fs::write(&path, "{\"format\":\"something else\"}\n").expect("write file");
+        let error = read_index(&path).expect_err("not an index");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
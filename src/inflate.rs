@@ -0,0 +1,619 @@
+//! A streaming DEFLATE decoder (RFC 1951), with the zlib wrapper (RFC 1950)
+//! used by PNG and the gzip wrapper (RFC 1952) of `.tar.gz` archives.
+//!
+//! Output is decoded a little at a time as it is read, keeping only the last
+//! 32 KiB that back-references can reach, so streams of any length decode in
+//! constant memory.
+
+use std::io::{self, Read};
+use std::sync::OnceLock;
+
+/// How far back a back-reference can reach.
+const WINDOW: usize = 32 * 1024;
+/// The longest code in any DEFLATE Huffman table.
+const MAX_BITS: u32 = 15;
+
+/// The shortest length of each length code from 257.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+/// Extra bits following each length code.
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// The shortest distance of each distance code.
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+/// Extra bits following each distance code.
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order in which the lengths of the code length code are stored.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid deflate data: {message}"),
+    )
+}
+
+/// A canonical Huffman code, decoded with one table lookup.
+struct Huffman {
+    /// Indexed by the next `MAX_BITS` bits of input, each entry holds the
+    /// symbol shifted left by four and the length of its code, or zero if no
+    /// code matches.
+    table: Vec<u16>,
+}
+
+impl Huffman {
+    /// Builds the code with the given length for each symbol, where zero
+    /// means the symbol is unused. Incomplete codes are allowed, as DEFLATE
+    /// uses them for distance codes with a single symbol.
+    fn new(lengths: &[u8]) -> io::Result<Self> {
+        let mut count = [0u16; MAX_BITS as usize + 1];
+        for &len in lengths {
+            count[usize::from(len)] += 1;
+        }
+        count[0] = 0;
+        let mut left = 1i32;
+        for &n in &count[1..] {
+            left = (left << 1) - i32::from(n);
+            if left < 0 {
+                return Err(invalid("over-subscribed Huffman code"));
+            }
+        }
+
+        let mut next = [0u16; MAX_BITS as usize + 1];
+        let mut code = 0;
+        for len in 1..next.len() {
+            code = (code + count[len - 1]) << 1;
+            next[len] = code;
+        }
+        let mut table = vec![0; 1 << MAX_BITS];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len == 0 {
+                continue;
+            }
+            let code = next[usize::from(len)];
+            next[usize::from(len)] += 1;
+            // Codes are packed starting from their most significant bit,
+            // while the table is indexed by bits in the order they arrive.
+            let reversed = code.reverse_bits() >> (16 - u32::from(len));
+            let entry = u16::try_from(symbol << 4).map_err(|_| invalid("too many symbols"))?
+                | u16::from(len);
+            for index in (usize::from(reversed)..table.len()).step_by(1 << len) {
+                table[index] = entry;
+            }
+        }
+        Ok(Self { table })
+    }
+}
+
+/// The literal/length and distance codes of blocks compressed with the
+/// fixed codes of the specification.
+fn fixed_codes() -> &'static (Huffman, Huffman) {
+    static CODES: OnceLock<(Huffman, Huffman)> = OnceLock::new();
+    CODES.get_or_init(|| {
+        let mut lengths = [0u8; 288];
+        lengths[..144].fill(8);
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        lengths[280..].fill(8);
+        let literals = Huffman::new(&lengths).expect("the fixed code is valid");
+        let distances = Huffman::new(&[5; 30]).expect("the fixed code is valid");
+        (literals, distances)
+    })
+}
+
+/// The CRC-32 of each byte value, for the checksum of gzip members.
+fn crc_table() -> &'static [u32; 256] {
+    static TABLE: OnceLock<[u32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0; 256];
+        for (byte, entry) in (0u32..).zip(table.iter_mut()) {
+            *entry = (0..8).fold(byte, |crc, _| {
+                if crc & 1 == 1 {
+                    0xEDB8_8320 ^ (crc >> 1)
+                } else {
+                    crc >> 1
+                }
+            });
+        }
+        table
+    })
+}
+
+/// The checksum and length of the output of the gzip member being decoded,
+/// checked against its trailer.
+#[derive(Default)]
+struct Member {
+    crc: u32,
+    size: u32,
+}
+
+impl Member {
+    fn update(&mut self, bytes: &[u8]) {
+        let table = crc_table();
+        let crc = bytes.iter().fold(!self.crc, |crc, &byte| {
+            table[usize::from(u8::try_from(crc & 0xFF).unwrap_or_default() ^ byte)] ^ (crc >> 8)
+        });
+        self.crc = !crc;
+        // The trailer holds the length modulo 2^32.
+        self.size = self
+            .size
+            .wrapping_add(u32::try_from(bytes.len()).unwrap_or(u32::MAX));
+    }
+}
+
+/// Reads a stream a bit at a time, least significant bit first.
+struct Bits<R> {
+    reader: R,
+    buffer: Box<[u8]>,
+    start: usize,
+    end: usize,
+    value: u64,
+    count: u32,
+}
+
+impl<R: Read> Bits<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: vec![0; 16 * 1024].into_boxed_slice(),
+            start: 0,
+            end: 0,
+            value: 0,
+            count: 0,
+        }
+    }
+
+    /// Tops up the bits held to at least 57, or as many as are left.
+    fn refill(&mut self) -> io::Result<()> {
+        while self.count <= 56 {
+            if self.start == self.end {
+                self.start = 0;
+                self.end = loop {
+                    match self.reader.read(&mut self.buffer) {
+                        Ok(n) => break n,
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        Err(e) => return Err(e),
+                    }
+                };
+                if self.end == 0 {
+                    return Ok(());
+                }
+            }
+            self.value |= u64::from(self.buffer[self.start]) << self.count;
+            self.start += 1;
+            self.count += 8;
+        }
+        Ok(())
+    }
+
+    /// Takes the next `n` bits, at most 32, as a number.
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        if self.count < n {
+            self.refill()?;
+            if self.count < n {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        let bits = u32::try_from(self.value & ((1 << n) - 1)).unwrap_or_default();
+        self.value >>= n;
+        self.count -= n;
+        Ok(bits)
+    }
+
+    /// Decodes the next symbol of `code`.
+    fn decode(&mut self, code: &Huffman) -> io::Result<usize> {
+        if self.count < MAX_BITS {
+            self.refill()?;
+        }
+        let index = usize::try_from(self.value & ((1 << MAX_BITS) - 1)).unwrap_or_default();
+        let entry = code.table[index];
+        let len = u32::from(entry & 0xF);
+        if entry == 0 {
+            return Err(invalid("unknown Huffman code"));
+        }
+        if len > self.count {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.value >>= len;
+        self.count -= len;
+        Ok(usize::from(entry >> 4))
+    }
+
+    /// Skips to the next byte boundary.
+    fn align(&mut self) {
+        let partial = self.count % 8;
+        self.value >>= partial;
+        self.count -= partial;
+    }
+
+    /// Returns whether every byte of the stream has been taken.
+    fn at_end(&mut self) -> io::Result<bool> {
+        if self.count == 0 {
+            self.refill()?;
+        }
+        Ok(self.count == 0)
+    }
+}
+
+/// What the decoder expects next.
+enum State {
+    /// The header of a block, or the end of the stream after the last one.
+    Header,
+    /// The bytes left in a stored block.
+    Stored(usize),
+    /// The codes of a block compressed with the fixed codes.
+    Fixed,
+    /// The codes of a block compressed with codes of its own.
+    Dynamic(Box<(Huffman, Huffman)>),
+    /// Nothing: the last block has ended.
+    Done,
+}
+
+/// Decompresses a raw DEFLATE stream read from `R`.
+pub(crate) struct Inflate<R> {
+    bits: Bits<R>,
+    state: State,
+    last_block: bool,
+    /// Recent output: the history that back-references can reach, followed
+    /// by output not yet read.
+    output: Vec<u8>,
+    /// Where the output not yet read starts.
+    read: usize,
+    /// The checks of the current member of a gzip stream, or `None` for
+    /// other streams and once the last member has been checked.
+    member: Option<Member>,
+}
+
+impl<R: Read> Inflate<R> {
+    /// Decompresses the raw DEFLATE stream that `reader` yields.
+    pub(crate) fn new(reader: R) -> Self {
+        Self {
+            bits: Bits::new(reader),
+            state: State::Header,
+            last_block: false,
+            output: Vec::with_capacity(3 * WINDOW),
+            read: 0,
+            member: None,
+        }
+    }
+
+    /// Decompresses a zlib stream: a DEFLATE stream between a two-byte
+    /// header and a checksum. The checksum is not checked.
+    pub(crate) fn zlib(reader: R) -> io::Result<Self> {
+        let mut inflate = Self::new(reader);
+        let method = inflate.bits.bits(8)?;
+        let flags = inflate.bits.bits(8)?;
+        if method & 0xF != 8 || (method << 8 | flags) % 31 != 0 {
+            return Err(invalid("not a zlib stream"));
+        }
+        if flags & 0x20 != 0 {
+            return Err(invalid("zlib preset dictionaries are not supported"));
+        }
+        Ok(inflate)
+    }
+
+    /// Decompresses a gzip stream: one or more members, as `cat a.gz b.gz`
+    /// or `pigz` make, each a DEFLATE stream after a header that may hold a
+    /// file name and comment and before a trailer with the CRC-32 and length
+    /// of its output. Reading fails if a trailer does not match what was
+    /// decoded, or if anything but another member follows one.
+    pub(crate) fn gzip(reader: R) -> io::Result<Self> {
+        let mut inflate = Self::new(reader);
+        read_gzip_header(&mut inflate.bits)?;
+        inflate.member = Some(Member::default());
+        Ok(inflate)
+    }
+
+    /// Checks the trailer of the gzip member that has just ended and starts
+    /// on the next one, returning `false` at the end of the stream.
+    fn next_member(&mut self) -> io::Result<bool> {
+        let Some(member) = self.member.take() else {
+            return Ok(false);
+        };
+        self.bits.align();
+        if self.bits.bits(32)? != member.crc {
+            return Err(invalid("gzip checksum does not match"));
+        }
+        if self.bits.bits(32)? != member.size {
+            return Err(invalid("gzip length does not match"));
+        }
+        if self.bits.at_end()? {
+            return Ok(false);
+        }
+        read_gzip_header(&mut self.bits)
+            .map_err(|_| invalid("unexpected data after a gzip member"))?;
+        self.member = Some(Member::default());
+        self.state = State::Header;
+        self.last_block = false;
+        // Back-references cannot reach into an earlier member.
+        self.output.clear();
+        self.read = 0;
+        Ok(true)
+    }
+
+    /// Decodes more output, up to about a window's worth.
+    fn step(&mut self) -> io::Result<()> {
+        match std::mem::replace(&mut self.state, State::Header) {
+            State::Header if self.last_block => self.state = State::Done,
+            State::Header => self.read_header()?,
+            State::Stored(left) => {
+                let n = left.min(WINDOW);
+                for _ in 0..n {
+                    let byte = self.bits.bits(8)?;
+                    self.output.push(u8::try_from(byte).unwrap_or_default());
+                }
+                if left > n {
+                    self.state = State::Stored(left - n);
+                }
+            }
+            State::Fixed => {
+                let (literals, distances) = fixed_codes();
+                if !self.decode_codes(literals, distances)? {
+                    self.state = State::Fixed;
+                }
+            }
+            State::Dynamic(codes) => {
+                if !self.decode_codes(&codes.0, &codes.1)? {
+                    self.state = State::Dynamic(codes);
+                }
+            }
+            State::Done => self.state = State::Done,
+        }
+        Ok(())
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        self.last_block = self.bits.bits(1)? == 1;
+        self.state = match self.bits.bits(2)? {
+            0 => {
+                self.bits.align();
+                let len = self.bits.bits(16)?;
+                if self.bits.bits(16)? != !len & 0xFFFF {
+                    return Err(invalid("stored block length does not match"));
+                }
+                State::Stored(usize::try_from(len).unwrap_or_default())
+            }
+            1 => State::Fixed,
+            2 => State::Dynamic(Box::new(self.read_codes()?)),
+            _ => return Err(invalid("reserved block type")),
+        };
+        Ok(())
+    }
+
+    /// Reads the codes at the start of a block with codes of its own.
+    fn read_codes(&mut self) -> io::Result<(Huffman, Huffman)> {
+        let literals = usize::try_from(self.bits.bits(5)? + 257).unwrap_or_default();
+        let distances = usize::try_from(self.bits.bits(5)? + 1).unwrap_or_default();
+        let code_lengths = self.bits.bits(4)? + 4;
+        let mut lengths = [0u8; 19];
+        for &symbol in &CODE_LENGTH_ORDER[..usize::try_from(code_lengths).unwrap_or_default()] {
+            lengths[symbol] = u8::try_from(self.bits.bits(3)?).unwrap_or_default();
+        }
+        let code_length_code = Huffman::new(&lengths)?;
+
+        let mut lengths = vec![0u8; literals + distances];
+        let mut i = 0;
+        while i < lengths.len() {
+            let symbol = self.bits.decode(&code_length_code)?;
+            let (len, repeat) = match symbol {
+                0..=15 => (u8::try_from(symbol).unwrap_or_default(), 1),
+                16 if i > 0 => (lengths[i - 1], 3 + self.bits.bits(2)?),
+                16 => return Err(invalid("repeated length with no previous length")),
+                17 => (0, 3 + self.bits.bits(3)?),
+                _ => (0, 11 + self.bits.bits(7)?),
+            };
+            let end = i + usize::try_from(repeat).unwrap_or_default();
+            if end > lengths.len() {
+                return Err(invalid("too many code lengths"));
+            }
+            lengths[i..end].fill(len);
+            i = end;
+        }
+        if lengths[256] == 0 {
+            return Err(invalid("no end-of-block code"));
+        }
+        Ok((
+            Huffman::new(&lengths[..literals])?,
+            Huffman::new(&lengths[literals..])?,
+        ))
+    }
+
+    /// Decodes literals and back-references until about a window's worth of
+    /// output is ready, returning `true` at the end of the block.
+    fn decode_codes(&mut self, literals: &Huffman, distances: &Huffman) -> io::Result<bool> {
+        let target = self.output.len() + WINDOW;
+        while self.output.len() < target {
+            let symbol = self.bits.decode(literals)?;
+            match symbol {
+                0..=255 => self.output.push(u8::try_from(symbol).unwrap_or_default()),
+                256 => return Ok(true),
+                257..=285 => {
+                    let index = symbol - 257;
+                    let len = usize::from(LENGTH_BASE[index])
+                        + usize::try_from(self.bits.bits(u32::from(LENGTH_EXTRA[index]))?)
+                            .unwrap_or_default();
+                    let index = self.bits.decode(distances)?;
+                    if index >= DIST_BASE.len() {
+                        return Err(invalid("unknown distance code"));
+                    }
+                    let distance = usize::from(DIST_BASE[index])
+                        + usize::try_from(self.bits.bits(u32::from(DIST_EXTRA[index]))?)
+                            .unwrap_or_default();
+                    if distance > self.output.len() {
+                        return Err(invalid("distance reaches before the start"));
+                    }
+                    // The source may overlap the bytes being written, which
+                    // repeats them.
+                    let start = self.output.len() - distance;
+                    for i in start..start + len {
+                        let byte = self.output[i];
+                        self.output.push(byte);
+                    }
+                }
+                _ => return Err(invalid("unknown length code")),
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Reads the header of a gzip member, which may hold a file name and
+/// comment, up to its DEFLATE stream.
+fn read_gzip_header<R: Read>(bits: &mut Bits<R>) -> io::Result<()> {
+    if bits.bits(16)? != 0x8B1F || bits.bits(8)? != 8 {
+        return Err(invalid("not a gzip stream"));
+    }
+    let flags = bits.bits(8)?;
+    // The modification time, extra flags and operating system.
+    for _ in 0..6 {
+        bits.bits(8)?;
+    }
+    if flags & 0x04 != 0 {
+        for _ in 0..bits.bits(16)? {
+            bits.bits(8)?;
+        }
+    }
+    // The file name and the comment, each ending with a zero byte.
+    for field in [0x08, 0x10] {
+        if flags & field != 0 {
+            while bits.bits(8)? != 0 {}
+        }
+    }
+    if flags & 0x02 != 0 {
+        bits.bits(16)?;
+    }
+    Ok(())
+}
+
+impl<R: Read> Read for Inflate<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.read == self.output.len() {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            if matches!(self.state, State::Done) {
+                if self.next_member()? {
+                    continue;
+                }
+                return Ok(0);
+            }
+            // Everything has been read, so only the history is kept.
+            if self.output.len() > 2 * WINDOW {
+                self.output.drain(..self.output.len() - WINDOW);
+                self.read = self.output.len();
+            }
+            self.step()?;
+        }
+        let n = buf.len().min(self.output.len() - self.read);
+        buf[..n].copy_from_slice(&self.output[self.read..self.read + n]);
+        self.read += n;
+        if let Some(member) = &mut self.member {
+            member.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut output = Vec::new();
+        Inflate::zlib(data)?.read_to_end(&mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn test_inflate_block_types() {
+        // zlib.compress(b"hello hello hello hello\n"), using the fixed codes.
+        let fixed = [
+            0x78, 0x9C, 0xCB, 0x48, 0xCD, 0xC9, 0xC9, 0x57, 0xC8, 0x40, 0x27, 0xB9, 0x00, 0x70,
+            0xBE, 0x08, 0xBB,
+        ];
+        assert_eq!(
+            inflate(&fixed).expect("valid stream"),
+            b"hello hello hello hello\n"
+        );
+
+        // zlib.compress(b"abc", 0), a stored block.
+        let stored = [
+            0x78, 0x01, 0x01, 0x03, 0x00, 0xFC, 0xFF, 0x61, 0x62, 0x63, 0x02, 0x4D, 0x01, 0x27,
+        ];
+        assert_eq!(inflate(&stored).expect("valid stream"), b"abc");
+
+        // The squares modulo 97 of 0 to 59, written out, at level 9 with
+        // codes of the block's own.
+        let dynamic = [
+            0x78, 0xDA, 0x0D, 0x8B, 0xC1, 0x11, 0x00, 0x30, 0x08, 0xC2, 0x56, 0xAA, 0x02, 0x8A,
+            0xFB, 0x2F, 0x56, 0x8E, 0x4F, 0x8E, 0xC0, 0x2B, 0x5E, 0x4D, 0x0B, 0xC3, 0x1B, 0xBA,
+            0xD0, 0xE4, 0x6E, 0x37, 0x52, 0x9E, 0x80, 0x7D, 0x15, 0x19, 0x95, 0x19, 0x71, 0xD4,
+            0xF3, 0xA8, 0xD7, 0x16, 0xBB, 0x7D, 0x53, 0x50, 0x95, 0x67, 0xF2, 0x45, 0x07, 0x0E,
+            0xD6, 0xDE, 0x6A, 0x91, 0x84, 0xAC, 0xC3, 0x54, 0x1C, 0x33, 0xF2, 0x7C, 0xFB, 0x7B,
+            0x16, 0xEE,
+        ];
+        let squares: String = (0..60).map(|i| (i * i % 97).to_string()).collect();
+        assert_eq!(inflate(&dynamic).expect("valid stream"), squares.as_bytes());
+
+        assert!(inflate(&fixed[..10]).is_err());
+        assert!(inflate(&[0x78, 0x9C, 0xFF]).is_err());
+    }
+
+    #[test]
+    fn test_gunzip_skips_header_fields() {
+        // gzip.GzipFile(filename="note.txt", mtime=0) holding b"hello gzip\n".
+        let named = [
+            0x1F, 0x8B, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xFF, 0x6E, 0x6F, 0x74, 0x65,
+            0x2E, 0x74, 0x78, 0x74, 0x00, 0xCB, 0x48, 0xCD, 0xC9, 0xC9, 0x57, 0x48, 0xAF, 0xCA,
+            0x2C, 0xE0, 0x02, 0x00, 0x39, 0x7C, 0x63, 0x56, 0x0B, 0x00, 0x00, 0x00,
+        ];
+        let mut output = Vec::new();
+        Inflate::gzip(&named[..])
+            .expect("gzip header")
+            .read_to_end(&mut output)
+            .expect("valid stream");
+        assert_eq!(output, b"hello gzip\n");
+        assert!(Inflate::gzip(&named[1..]).is_err());
+    }
+
+    #[test]
+    fn test_gunzip_reads_every_member_and_checks_trailers() {
+        // gzip.compress(b"hello gzip\n", mtime=0), twice over, as
+        // `cat a.gz a.gz` makes.
+        let member = [
+            0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xCB, 0x48, 0xCD, 0xC9,
+            0xC9, 0x57, 0x48, 0xAF, 0xCA, 0x2C, 0xE0, 0x02, 0x00, 0x39, 0x7C, 0x63, 0x56, 0x0B,
+            0x00, 0x00, 0x00,
+        ];
+        let gunzip = |data: &[u8]| {
+            let mut output = Vec::new();
+            Inflate::gzip(data)?
+                .read_to_end(&mut output)
+                .map(|_| output)
+        };
+        let twice = [member, member].concat();
+        assert_eq!(
+            gunzip(&twice).expect("valid stream"),
+            b"hello gzip\nhello gzip\n"
+        );
+
+        let mut corrupt = member;
+        corrupt[23] ^= 1;
+        let error = gunzip(&corrupt).expect_err("checksum differs");
+        assert!(error.to_string().contains("checksum"), "{error}");
+        let mut garbage = member.to_vec();
+        garbage.extend_from_slice(b"junk");
+        assert!(gunzip(&garbage).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+//! Asking which copies to keep in each group, for `--interactive`.
+//!
+//! Every group is listed with numbered entries, in the style of `fdupes -d`,
+//! and the answer decides which files survive. Nothing is removed here: the
+//! chosen files are returned for `remove_selected` to delete or trash, so a
+//! dry run still changes nothing. At least one copy of every group is always
+//! kept.
+
+use duplicate_file_finder::dedupe::DeletedFile;
+use duplicate_file_finder::{format_size, DuplicateGroup, DuplicateReport};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+const HELP: &str = "\
+  1 3   keep files 1 and 3, remove the others
+  a     keep all files in this group
+  d 2   keep none in the folder of file 2, keep the rest
+  D 2   the same, and for every remaining group with copies elsewhere
+  q     keep all files in this and every remaining group";
+
+/// One answer to the prompt.
+#[derive(Debug, PartialEq, Eq)]
+enum Answer {
+    /// Keep these entries, numbered from zero.
+    Keep(Vec<usize>),
+    KeepAll,
+    /// Remove every copy in the folder of this entry; `remember` applies the
+    /// same rule to the remaining groups.
+    DropFolder {
+        entry: usize,
+        remember: bool,
+    },
+    Quit,
+}
+
+/// Prompts for every group in `report` on `output`, reading answers from
+/// `input`, and returns the files to remove.
+///
+/// Input ending early keeps every file in the groups not yet answered.
+pub(crate) fn choose_removals<R: BufRead, W: Write>(
+    report: &DuplicateReport,
+    mut input: R,
+    mut output: W,
+) -> io::Result<Vec<DeletedFile>> {
+    let mut removals = Vec::new();
+    let mut dropped_folders: Vec<PathBuf> = Vec::new();
+    let total = report.groups.len();
+
+    for (index, group) in report.groups.iter().enumerate() {
+        let auto: Vec<usize> = (0..group.paths.len())
+            .filter(|&i| {
+                !dropped_folders
+                    .iter()
+                    .any(|dir| in_folder(&group.paths[i], dir))
+            })
+            .collect();
+        if auto.len() < group.paths.len() && !auto.is_empty() {
+            writeln!(
+                output,
+                "\n[{}/{total}] Removing {} of {} copies, in remembered folders.",
+                index + 1,
+                group.paths.len() - auto.len(),
+                group.paths.len()
+            )?;
+            removals.extend(removals_for(group, &auto));
+            continue;
+        }
+
+        write_group(&mut output, group, index, total)?;
+        let keep = loop {
+            write!(
+                output,
+                "Keep which files? (1-{}, a, d N, D N, q, ? for help): ",
+                group.paths.len()
+            )?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(output)?;
+                return Ok(removals);
+            }
+            match parse_answer(&line, group.paths.len()) {
+                Ok(Answer::Keep(keep)) => break keep,
+                Ok(Answer::KeepAll) => break (0..group.paths.len()).collect(),
+                Ok(Answer::Quit) => return Ok(removals),
+                Ok(Answer::DropFolder { entry, remember }) => {
+                    let Some(folder) = group.paths[entry].parent() else {
+                        writeln!(output, "File {} has no folder.", entry + 1)?;
+                        continue;
+                    };
+                    let keep: Vec<usize> = (0..group.paths.len())
+                        .filter(|&i| !in_folder(&group.paths[i], folder))
+                        .collect();
+                    if keep.is_empty() {
+                        writeln!(
+                            output,
+                            "Every copy is in {}; keep at least one.",
+                            folder.display()
+                        )?;
+                        continue;
+                    }
+                    if remember {
+                        dropped_folders.push(folder.to_path_buf());
+                    }
+                    break keep;
+                }
+                Err(message) => writeln!(output, "{message}\n{HELP}")?,
+            }
+        };
+        removals.extend(removals_for(group, &keep));
+    }
+    Ok(removals)
+}
+
+fn write_group<W: Write>(
+    output: &mut W,
+    group: &DuplicateGroup,
+    index: usize,
+    total: usize,
+) -> io::Result<()> {
+    writeln!(
+        output,
+        "\n[{}/{total}] {} copies of {}:",
+        index + 1,
+        group.paths.len(),
+        format_size(group.size)
+    )?;
+    for (i, path) in group.paths.iter().enumerate() {
+        writeln!(output, "  {}) {}", i + 1, path.display())?;
+    }
+    Ok(())
+}
+
+/// Whether `path` sits directly in `folder`.
+fn in_folder(path: &Path, folder: &Path) -> bool {
+    path.parent() == Some(folder)
+}
+
+/// Lists every entry of `group` not in `keep` as a removal, pointing at the
+/// first kept entry.
+fn removals_for<'a>(
+    group: &'a DuplicateGroup,
+    keep: &'a [usize],
+) -> impl Iterator<Item = DeletedFile> + 'a {
+    let kept = &group.paths[keep[0]];
+    group
+        .paths
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !keep.contains(i))
+        .map(move |(_, path)| DeletedFile {
+            path: path.clone(),
+            kept: kept.clone(),
+            size: group.size,
+            trashed_to: None,
+            attributes: None,
+        })
+}
+
+/// Parses one line typed at the prompt for a group of `count` files.
+fn parse_answer(line: &str, count: usize) -> Result<Answer, String> {
+    let entry = |text: &str| match text.trim().parse::<usize>() {
+        Ok(n) if (1..=count).contains(&n) => Ok(n - 1),
+        _ => Err(format!(
+            "'{}' is not a file number from 1 to {count}.",
+            text.trim()
+        )),
+    };
+    let line = line.trim();
+    match line {
+        "" | "?" => return Err("Choose the files to keep:".to_string()),
+        "a" => return Ok(Answer::KeepAll),
+        "q" => return Ok(Answer::Quit),
+        _ => {}
+    }
+    if let Some(rest) = line.strip_prefix('d') {
+        return Ok(Answer::DropFolder {
+            entry: entry(rest)?,
+            remember: false,
+        });
+    }
+    if let Some(rest) = line.strip_prefix('D') {
+        return Ok(Answer::DropFolder {
+            entry: entry(rest)?,
+            remember: true,
+        });
+    }
+    let mut keep = Vec::new();
+    for word in line.split(|c: char| c == ',' || c.is_whitespace()) {
+        if word.is_empty() {
+            continue;
+        }
+        let index = entry(word)?;
+        if !keep.contains(&index) {
+            keep.push(index);
+        }
+    }
+    if keep.is_empty() {
+        return Err("Choose the files to keep:".to_string());
+    }
+    Ok(Answer::Keep(keep))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(paths: &[&str]) -> DuplicateGroup {
+        DuplicateGroup {
+            size: 10,
+            hash: "hash".to_string(),
+            paths: paths.iter().map(PathBuf::from).collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_answer() {
+        assert_eq!(parse_answer("2, 3\n", 3), Ok(Answer::Keep(vec![1, 2])));
+        assert_eq!(parse_answer("a", 3), Ok(Answer::KeepAll));
+        assert_eq!(
+            parse_answer("D 1", 3),
+            Ok(Answer::DropFolder {
+                entry: 0,
+                remember: true
+            })
+        );
+        assert!(parse_answer("4", 3).is_err());
+        assert!(parse_answer("", 3).is_err());
+        assert!(parse_answer(" , ", 3).is_err());
+    }
+
+    #[test]
+    fn test_choose_removals() {
+        let report = DuplicateReport::from_groups(vec![
+            group(&["/backup/a", "/photos/a"]),
+            group(&["/backup/b", "/photos/b", "/photos/c"]),
+            group(&["/backup/c", "/backup/d"]),
+            group(&["/x/e", "/y/e"]),
+        ]);
+        // Drop /backup for every group; the second group is then answered
+        // automatically, the third has nowhere else to keep a copy and is
+        // asked again, and the last is left alone by quitting.
+        let input = b"D 1\nd 1\n2\nq\n";
+        let mut output = Vec::new();
+        let removals = choose_removals(&report, &input[..], &mut output).expect("prompt");
+
+        let removed: Vec<_> = removals.iter().map(|f| f.path.to_str().unwrap()).collect();
+        assert_eq!(removed, ["/backup/a", "/backup/b", "/backup/c"]);
+        assert_eq!(removals[1].kept, PathBuf::from("/photos/b"));
+        let output = String::from_utf8(output).expect("UTF-8");
+        assert!(output.contains("[2/4] Removing 1 of 3 copies, in remembered folders."));
+        assert!(output.contains("Every copy is in /backup; keep at least one."));
+    }
+}
@@ -0,0 +1,556 @@
+//! Decoding of the average brightness of each 8×8 block of a JPEG image.
+//!
+//! A JPEG image is stored as blocks of 8×8 pixels, and the first coefficient
+//! of each block, its DC coefficient, is the block's average brightness. An
+//! image of those coefficients is an eighth the size of the original, which
+//! is plenty for comparing images by what they show, and decoding it skips
+//! the costly inverse transform. Sequential and progressive images with
+//! Huffman coding are supported.
+//!
+//! The segments that hold the image itself can also be picked out from those
+//! holding metadata, to compare files by their image data alone.
+
+use std::io;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid JPEG image: {message}"),
+    )
+}
+
+/// Returns `true` if `data` starts like a JPEG image.
+pub(crate) fn is_jpeg(data: &[u8]) -> bool {
+    data.starts_with(&[0xFF, 0xD8, 0xFF])
+}
+
+/// The brightness of each block of an image, from its DC coefficients.
+pub(crate) struct Blocks {
+    /// The size of the image in pixels.
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    /// How many blocks there are across and down.
+    pub(crate) cols: usize,
+    pub(crate) rows: usize,
+    /// Each block's DC coefficient, row by row. They are not scaled by the
+    /// quantization table, which changes their range but not their order.
+    pub(crate) dc: Vec<i32>,
+}
+
+/// A component of the image, such as brightness or a colour difference.
+#[derive(Clone, Copy)]
+struct Component {
+    id: u8,
+    /// How many blocks across and down the component has in each unit of
+    /// blocks of all components, the MCU.
+    h: usize,
+    v: usize,
+}
+
+/// A Huffman table, decoded as in Annex F of the specification.
+#[derive(Clone)]
+struct Huffman {
+    /// The largest code of each length, or -1 if there is none.
+    max_code: [i32; 17],
+    /// The smallest code of each length minus the index of its value.
+    offset: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl Huffman {
+    fn new(counts: &[u8], values: &[u8]) -> Self {
+        let mut max_code = [-1; 17];
+        let mut offset = [0; 17];
+        let (mut code, mut index) = (0i32, 0i32);
+        for len in 1..=16 {
+            let count = i32::from(counts[len - 1]);
+            offset[len] = code - index;
+            code += count;
+            index += count;
+            if count > 0 {
+                max_code[len] = code - 1;
+            }
+            code <<= 1;
+        }
+        Self {
+            max_code,
+            offset,
+            values: values.to_vec(),
+        }
+    }
+}
+
+/// Reads entropy-coded data a bit at a time, most significant bit first,
+/// removing the zero byte stuffed after each 0xFF.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    value: u32,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self {
+            data,
+            pos,
+            value: 0,
+            count: 0,
+        }
+    }
+
+    fn bit(&mut self) -> io::Result<u32> {
+        if self.count == 0 {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| invalid("image data ends early"))?;
+            if byte == 0xFF {
+                match self.data.get(self.pos + 1) {
+                    Some(0) => self.pos += 2,
+                    // A marker: the data has ended, so ones are read, as
+                    // decoders conventionally do.
+                    _ => return Ok(1),
+                }
+            } else {
+                self.pos += 1;
+            }
+            self.value = u32::from(byte);
+            self.count = 8;
+        }
+        self.count -= 1;
+        Ok((self.value >> self.count) & 1)
+    }
+
+    fn bits(&mut self, n: u8) -> io::Result<u32> {
+        let mut value = 0;
+        for _ in 0..n {
+            value = value << 1 | self.bit()?;
+        }
+        Ok(value)
+    }
+
+    fn decode(&mut self, table: &Huffman) -> io::Result<u8> {
+        let mut code = 0i32;
+        for len in 1..=16 {
+            code = code << 1 | i32::try_from(self.bit()?).unwrap_or_default();
+            if code <= table.max_code[len] {
+                let index = usize::try_from(code - table.offset[len]).unwrap_or(usize::MAX);
+                return table
+                    .values
+                    .get(index)
+                    .copied()
+                    .ok_or_else(|| invalid("bad Huffman table"));
+            }
+        }
+        Err(invalid("unknown Huffman code"))
+    }
+
+    /// Reads a coefficient of `size` bits, which encode negative numbers
+    /// as their complement.
+    fn signed(&mut self, size: u8) -> io::Result<i32> {
+        if size == 0 {
+            return Ok(0);
+        }
+        if size > 16 {
+            return Err(invalid("coefficient too large"));
+        }
+        let value = i32::try_from(self.bits(size)?).unwrap_or_default();
+        Ok(if value < 1 << (size - 1) {
+            value - (1 << size) + 1
+        } else {
+            value
+        })
+    }
+
+    /// Skips the restart marker expected at this point, if there is one.
+    fn restart(&mut self) {
+        self.count = 0;
+        if self.data.get(self.pos) == Some(&0xFF)
+            && self
+                .data
+                .get(self.pos + 1)
+                .is_some_and(|m| (0xD0..=0xD7).contains(m))
+        {
+            self.pos += 2;
+        }
+    }
+}
+
+/// The frame, and the tables that the scans refer to.
+#[derive(Default)]
+struct Decoder {
+    width: u32,
+    height: u32,
+    progressive: bool,
+    components: Vec<Component>,
+    dc_tables: [Option<Huffman>; 4],
+    ac_tables: [Option<Huffman>; 4],
+    restart_interval: usize,
+}
+
+/// Decodes the DC coefficients of the brightness component of the JPEG
+/// image in `data`, or returns `None` if the image is coded in a way that is
+/// not supported: arithmetic coding, lossless, or more than 8 bits a sample.
+pub(crate) fn decode_blocks(data: &[u8]) -> io::Result<Option<Blocks>> {
+    if !is_jpeg(data) {
+        return Err(invalid("no start of image"));
+    }
+    let mut decoder = Decoder::default();
+    let mut pos = 2;
+    loop {
+        // Markers may be preceded by any number of fill bytes.
+        while data.get(pos) == Some(&0xFF) && data.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        let (Some(&0xFF), Some(&marker)) = (data.get(pos), data.get(pos + 1)) else {
+            return Err(invalid("missing marker"));
+        };
+        if marker == 0xD9 {
+            return Err(invalid("no image data"));
+        }
+        if matches!(marker, 0x01 | 0xD0..=0xD8) {
+            pos += 2;
+            continue;
+        }
+        let len = data
+            .get(pos + 2..pos + 4)
+            .map(|len| usize::from(u16::from_be_bytes([len[0], len[1]])))
+            .filter(|&len| len >= 2)
+            .ok_or_else(|| invalid("truncated segment"))?;
+        let segment = data
+            .get(pos + 4..pos + 2 + len)
+            .ok_or_else(|| invalid("truncated segment"))?;
+        pos += 2 + len;
+        match marker {
+            0xC0..=0xC2 => {
+                if segment.first() != Some(&8) {
+                    return Ok(None);
+                }
+                decoder.read_frame(segment, marker == 0xC2)?;
+            }
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return Ok(None),
+            0xC4 => decoder.read_tables(segment)?,
+            0xDD => {
+                let &[high, low] = segment else {
+                    return Err(invalid("bad restart interval"));
+                };
+                decoder.restart_interval = usize::from(u16::from_be_bytes([high, low]));
+            }
+            0xDA => {
+                if let Some(blocks) = decoder.read_scan(segment, data, &mut pos)? {
+                    return Ok(Some(blocks));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Hands `on_part` every part of the JPEG image in `data` except its
+/// metadata: the application segments, which hold EXIF, XMP, IPTC and colour
+/// profiles, the comments, and anything after the end of the image. The
+/// Adobe segment is kept, as it says how the colours were transformed.
+pub(crate) fn for_each_content_part(data: &[u8], on_part: &mut dyn FnMut(&[u8])) -> io::Result<()> {
+    if !is_jpeg(data) {
+        return Err(invalid("no start of image"));
+    }
+    on_part(&data[..2]);
+    let mut pos = 2;
+    while pos < data.len() {
+        while data.get(pos) == Some(&0xFF) && data.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        let (Some(&0xFF), Some(&marker)) = (data.get(pos), data.get(pos + 1)) else {
+            return Err(invalid("missing marker"));
+        };
+        if marker == 0xD9 {
+            on_part(&data[pos..pos + 2]);
+            break;
+        }
+        if matches!(marker, 0x01 | 0xD0..=0xD8) {
+            on_part(&data[pos..pos + 2]);
+            pos += 2;
+            continue;
+        }
+        let len = data
+            .get(pos + 2..pos + 4)
+            .map(|len| usize::from(u16::from_be_bytes([len[0], len[1]])))
+            .filter(|&len| len >= 2)
+            .ok_or_else(|| invalid("truncated segment"))?;
+        let mut end = pos + 2 + len;
+        if end > data.len() {
+            return Err(invalid("truncated segment"));
+        }
+        if marker == 0xDA {
+            end = skip_entropy_data(data, end);
+        }
+        let metadata = matches!(marker, 0xE0..=0xED | 0xEF | 0xFE);
+        if !metadata {
+            on_part(&data[pos..end]);
+        }
+        pos = end;
+    }
+    Ok(())
+}
+
+impl Decoder {
+    fn read_frame(&mut self, segment: &[u8], progressive: bool) -> io::Result<()> {
+        let [_, h0, h1, w0, w1, count, ref rest @ ..] = *segment else {
+            return Err(invalid("truncated frame"));
+        };
+        self.height = u32::from(u16::from_be_bytes([h0, h1]));
+        self.width = u32::from(u16::from_be_bytes([w0, w1]));
+        self.progressive = progressive;
+        if self.width == 0 || self.height == 0 {
+            return Err(invalid("empty image"));
+        }
+        self.components = rest
+            .chunks_exact(3)
+            .take(usize::from(count))
+            .map(|c| Component {
+                id: c[0],
+                h: usize::from(c[1] >> 4),
+                v: usize::from(c[1] & 0xF),
+            })
+            .collect();
+        if self.components.len() != usize::from(count)
+            || self
+                .components
+                .iter()
+                .any(|c| !(1..=4).contains(&c.h) || !(1..=4).contains(&c.v))
+        {
+            return Err(invalid("bad components"));
+        }
+        Ok(())
+    }
+
+    fn read_tables(&mut self, mut segment: &[u8]) -> io::Result<()> {
+        while let [class_and_id, ref rest @ ..] = *segment {
+            let counts = rest.get(..16).ok_or_else(|| invalid("truncated table"))?;
+            let total = counts
+                .iter()
+                .map(|&count| usize::from(count))
+                .sum::<usize>();
+            let values = rest
+                .get(16..16 + total)
+                .ok_or_else(|| invalid("truncated table"))?;
+            let table = Some(Huffman::new(counts, values));
+            let id = usize::from(class_and_id & 3);
+            if class_and_id >> 4 == 0 {
+                self.dc_tables[id] = table;
+            } else {
+                self.ac_tables[id] = table;
+            }
+            segment = &rest[16 + total..];
+        }
+        Ok(())
+    }
+
+    /// Reads a scan starting at `pos`, moving `pos` past its data. Returns
+    /// the blocks if the scan holds the DC coefficients of the brightness
+    /// component, which is the first one.
+    fn read_scan(
+        &self,
+        segment: &[u8],
+        data: &[u8],
+        pos: &mut usize,
+    ) -> io::Result<Option<Blocks>> {
+        let Some(luma) = self.components.first() else {
+            return Err(invalid("scan before frame"));
+        };
+        let count = usize::from(*segment.first().unwrap_or(&0));
+        let selectors = segment
+            .get(1..1 + 2 * count)
+            .ok_or_else(|| invalid("truncated scan"))?;
+        let &[start, _, approximation] = segment
+            .get(1 + 2 * count..)
+            .ok_or_else(|| invalid("truncated scan"))?
+        else {
+            return Err(invalid("truncated scan"));
+        };
+        let has_luma = selectors.chunks_exact(2).any(|s| s[0] == luma.id);
+        // Only the first scan of the DC coefficients is needed; later ones
+        // of a progressive image add precision the comparison does not use.
+        if !has_luma || start != 0 || approximation >> 4 != 0 {
+            *pos = skip_entropy_data(data, *pos);
+            return Ok(None);
+        }
+
+        let mut scan = Vec::new();
+        for selector in selectors.chunks_exact(2) {
+            let index = self
+                .components
+                .iter()
+                .position(|c| c.id == selector[0])
+                .ok_or_else(|| invalid("unknown component"))?;
+            let dc = self.dc_tables[usize::from(selector[1] >> 4)]
+                .as_ref()
+                .ok_or_else(|| invalid("missing table"))?;
+            let ac = if self.progressive {
+                None
+            } else {
+                Some(
+                    self.ac_tables[usize::from(selector[1] & 3)]
+                        .as_ref()
+                        .ok_or_else(|| invalid("missing table"))?,
+                )
+            };
+            scan.push((index, dc, ac));
+        }
+        let shift = approximation & 0xF;
+
+        let h_max = self.components.iter().map(|c| c.h).max().unwrap_or(1);
+        let v_max = self.components.iter().map(|c| c.v).max().unwrap_or(1);
+        let width = usize::try_from(self.width).unwrap_or_default();
+        let height = usize::try_from(self.height).unwrap_or_default();
+        // Blocks of the brightness component that cover the image.
+        let cols = (width * luma.h).div_ceil(h_max).div_ceil(8);
+        let rows = (height * luma.v).div_ceil(v_max).div_ceil(8);
+        let mut blocks = Blocks {
+            width: self.width,
+            height: self.height,
+            cols,
+            rows,
+            dc: vec![0; cols * rows],
+        };
+        let mut store = |x: usize, y: usize, value: i32| {
+            if x < cols && y < rows {
+                blocks.dc[y * cols + x] = value;
+            }
+        };
+
+        let mut bits = Bits::new(data, *pos);
+        let mut predictions = vec![0i32; self.components.len()];
+        // A scan of one component has no MCUs of several blocks: it goes
+        // block by block over just the blocks that cover the image.
+        let (units_across, units_down) = if scan.len() == 1 {
+            (cols, rows)
+        } else {
+            (width.div_ceil(8 * h_max), height.div_ceil(8 * v_max))
+        };
+        for unit in 0..units_across * units_down {
+            if self.restart_interval > 0 && unit > 0 && unit % self.restart_interval == 0 {
+                bits.restart();
+                predictions.fill(0);
+            }
+            let (unit_x, unit_y) = (unit % units_across, unit / units_across);
+            for &(index, dc, ac) in &scan {
+                let component = self.components[index];
+                let (h, v) = if scan.len() == 1 {
+                    (1, 1)
+                } else {
+                    (component.h, component.v)
+                };
+                for block in 0..h * v {
+                    let size = bits.decode(dc)?;
+                    predictions[index] += bits.signed(size)?;
+                    if let Some(ac) = ac {
+                        skip_ac(&mut bits, ac)?;
+                    }
+                    if index == 0 {
+                        let x = unit_x * h + block % h;
+                        let y = unit_y * v + block / h;
+                        store(x, y, predictions[0] << shift);
+                    }
+                }
+            }
+        }
+        Ok(Some(blocks))
+    }
+}
+
+/// Skips the 63 AC coefficients of a block of a sequential image.
+fn skip_ac(bits: &mut Bits, table: &Huffman) -> io::Result<()> {
+    let mut k = 1;
+    while k < 64 {
+        let symbol = bits.decode(table)?;
+        let (run, size) = (symbol >> 4, symbol & 0xF);
+        if size == 0 {
+            if run != 15 {
+                break;
+            }
+            k += 16;
+        } else {
+            k += usize::from(run) + 1;
+            bits.bits(size)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns where the entropy-coded data starting at `pos` ends: at the next
+/// marker other than a restart marker.
+fn skip_entropy_data(data: &[u8], mut pos: usize) -> usize {
+    while pos + 1 < data.len() {
+        if data[pos] == 0xFF && !matches!(data[pos + 1], 0x00 | 0xD0..=0xD7 | 0xFF) {
+            return pos;
+        }
+        pos += 1;
+    }
+    data.len()
+}
+
+/// Encodes a baseline grey JPEG image for tests that has only the average
+/// of each block, using a code of four bits for each DC size and one bit
+/// for the end of each block.
+#[cfg(test)]
+pub(crate) fn encode_dc_only(
+    width: usize,
+    height: usize,
+    pixel: impl Fn(usize, usize) -> u8,
+) -> Vec<u8> {
+    let mut jpeg = vec![0xFF, 0xD8];
+    let mut segment = |marker: u8, body: &[u8]| {
+        jpeg.extend([0xFF, marker]);
+        jpeg.extend(u16::try_from(body.len() + 2).expect("small").to_be_bytes());
+        jpeg.extend_from_slice(body);
+    };
+    let mut tables = vec![0x00];
+    tables.extend([0, 0, 0, 12, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    tables.extend(0..12);
+    tables.push(0x10);
+    tables.extend([1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00]);
+    let mut frame = vec![8];
+    frame.extend(u16::try_from(height).expect("small").to_be_bytes());
+    frame.extend(u16::try_from(width).expect("small").to_be_bytes());
+    frame.extend([1, 1, 0x11, 0]);
+    segment(0xC0, &frame);
+    segment(0xC4, &tables);
+    segment(0xDA, &[1, 1, 0x00, 0, 63, 0]);
+
+    let mut bits: Vec<bool> = Vec::new();
+    let mut put = |value: u32, len: u32| bits.extend((0..len).rev().map(|i| value >> i & 1 == 1));
+    let mut previous = 0;
+    for row in 0..height.div_ceil(8) {
+        for col in 0..width.div_ceil(8) {
+            let mut sum = 0;
+            for y in row * 8..row * 8 + 8 {
+                for x in col * 8..col * 8 + 8 {
+                    sum += i32::from(pixel(x.min(width - 1), y.min(height - 1))) - 128;
+                }
+            }
+            // With a quantizer of one, DC is eight times the average.
+            let dc = sum / 8;
+            let diff = dc - previous;
+            previous = dc;
+            let size = 32 - diff.unsigned_abs().leading_zeros();
+            put(size, 4);
+            let extra = if diff < 0 { diff - 1 } else { diff };
+            put(extra.cast_unsigned() & ((1 << size) - 1), size);
+            put(0, 1);
+        }
+    }
+    while !bits.len().is_multiple_of(8) {
+        bits.push(true);
+    }
+    for byte in bits.chunks(8) {
+        let byte = byte.iter().fold(0u8, |acc, &bit| acc << 1 | u8::from(bit));
+        jpeg.push(byte);
+        if byte == 0xFF {
+            jpeg.push(0);
+        }
+    }
+    jpeg.extend([0xFF, 0xD9]);
+    jpeg
+}
@@ -0,0 +1,301 @@
+//! A machine-readable JSON version of the report for other tools.
+//!
+//! The document is a single object holding the scan metadata, the totals,
+//! every group with its hash, size and files, and the paths that were
+//! skipped. Paths that are not valid UTF-8 are written lossily, as in the
+//! CSV report.
+
+use crate::json::{self, quote, Value};
+use crate::{DuplicateGroup, DuplicateReport, FileMetadata};
+use chrono::Local;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+/// The `version` written at the top of every JSON report, raised whenever
+/// the layout changes incompatibly.
+pub const JSON_REPORT_VERSION: u32 = 1;
+
+/// Writes the report as a JSON document.
+///
+/// The top-level object has these members:
+///
+/// * `version` – [`JSON_REPORT_VERSION`].
+/// * `generated_by`, `start_time`, `end_time` and `base_dirs` – the same
+///   header the text report starts with.
+/// * `interrupted`, `total_groups` and `total_reclaimable_bytes` – the
+///   report's totals.
+/// * `groups` – one object per group in report order, with its `hash`,
+///   `size` in bytes, `reclaimable_bytes` and `files`. Each file has a
+///   `path`, its `modified` time in seconds since the Unix epoch, whether it
+///   is `readonly`, and its `inode`; metadata that was not captured is
+///   `null`.
+/// * `errors` – the skipped files, each with a `path` and an `error`.
+///
+/// # Errors
+/// Returns any I/O error raised by `writer`.
+///
+/// # Example
+/// ```
+/// use std::path::PathBuf;
+/// use duplicate_file_finder::{write_output_json, DuplicateGroup, DuplicateReport};
+///
+/// let report = DuplicateReport::from_groups(vec![DuplicateGroup {
+///     hash: String::from("somehash"),
+///     size: 1024,
+///     paths: vec![PathBuf::from("/tmp/a.txt"), PathBuf::from("/tmp/b.txt")],
+/// }]);
+/// let mut buffer = Vec::new();
+/// write_output_json(&mut buffer, &report, "20240101 00:00:00", &[PathBuf::from("/tmp")])
+///     .unwrap();
+/// let json = String::from_utf8(buffer).unwrap();
+/// assert!(json.contains("\"total_reclaimable_bytes\": 1024"));
+/// assert!(json.contains("\"hash\": \"somehash\""));
+/// ```
+pub fn write_output_json<W: Write>(
+    writer: &mut W,
+    report: &DuplicateReport,
+    start_time: &str,
+    base_dirs: &[PathBuf],
+) -> io::Result<()> {
+    let end_time = Local::now().format("%Y%m%d %H:%M:%S").to_string();
+
+    writeln!(writer, "{{")?;
+    writeln!(writer, "  \"version\": {JSON_REPORT_VERSION},")?;
+    writeln!(
+        writer,
+        "  \"generated_by\": {},",
+        quote(&whoami::username())
+    )?;
+    writeln!(writer, "  \"start_time\": {},", quote(start_time))?;
+    writeln!(writer, "  \"end_time\": {},", quote(&end_time))?;
+    let dirs: Vec<String> = base_dirs
+        .iter()
+        .map(|dir| quote(&dir.to_string_lossy()))
+        .collect();
+    writeln!(writer, "  \"base_dirs\": [{}],", dirs.join(", "))?;
+    writeln!(writer, "  \"interrupted\": {},", report.interrupted)?;
+    writeln!(writer, "  \"total_groups\": {},", report.total_groups)?;
+    writeln!(
+        writer,
+        "  \"total_reclaimable_bytes\": {},",
+        report.total_reclaimable_bytes
+    )?;
+
+    write!(writer, "  \"groups\": [")?;
+    for (index, group) in report.groups.iter().enumerate() {
+        let separator = if index == 0 { "" } else { "," };
+        writeln!(writer, "{separator}")?;
+        writeln!(writer, "    {{")?;
+        writeln!(writer, "      \"hash\": {},", quote(&group.hash))?;
+        writeln!(writer, "      \"size\": {},", group.size)?;
+        writeln!(
+            writer,
+            "      \"reclaimable_bytes\": {},",
+            group.reclaimable_bytes()
+        )?;
+        write!(writer, "      \"files\": [")?;
+        for (i, path) in group.paths.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(writer, "{separator}\n        ")?;
+            write_file_entry(writer, &path.to_string_lossy(), report.metadata.get(path))?;
+        }
+        writeln!(writer, "\n      ]")?;
+        write!(writer, "    }}")?;
+    }
+    if report.groups.is_empty() {
+        writeln!(writer, "],")?;
+    } else {
+        writeln!(writer, "\n  ],")?;
+    }
+
+    write!(writer, "  \"errors\": [")?;
+    for (index, (path, error)) in report.errors.iter().enumerate() {
+        let separator = if index == 0 { "" } else { "," };
+        write!(
+            writer,
+            "{separator}\n    {{\"path\": {}, \"error\": {}}}",
+            quote(&path.to_string_lossy()),
+            quote(&error.to_string())
+        )?;
+    }
+    if report.errors.is_empty() {
+        writeln!(writer, "]")?;
+    } else {
+        writeln!(writer, "\n  ]")?;
+    }
+    writeln!(writer, "}}")
+}
+
+/// Reads a report written by [`write_output_json`].
+///
+/// The groups, their files, the scanned directories, the skipped files and
+/// the totals are read; the metadata of the files is not.
+///
+/// # Errors
+/// Returns an error of kind [`io::ErrorKind::InvalidData`] if `text` is not
+/// a JSON report of version [`JSON_REPORT_VERSION`].
+pub(crate) fn read_output_json(text: &str) -> io::Result<DuplicateReport> {
+    let doc = json::parse(text).map_err(|e| invalid(&e))?;
+    match doc.get("version").and_then(Value::as_u64) {
+        Some(version) if version == u64::from(JSON_REPORT_VERSION) => {}
+        Some(other) => return Err(invalid(&format!("unsupported version {other}"))),
+        None => return Err(invalid("missing version")),
+    }
+    let mut groups = Vec::new();
+    for group in array(&doc, "groups")? {
+        let paths = array(group, "files")?
+            .iter()
+            .map(|file| string(file, "path").map(PathBuf::from))
+            .collect::<io::Result<_>>()?;
+        groups.push(DuplicateGroup {
+            hash: string(group, "hash")?.to_string(),
+            size: group
+                .get("size")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| invalid("missing size"))?,
+            paths,
+        });
+    }
+    let mut report = DuplicateReport::from_groups(groups);
+    report.interrupted = doc
+        .get("interrupted")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    if let Some(total) = doc.get("total_reclaimable_bytes").and_then(Value::as_u64) {
+        report.total_reclaimable_bytes = total;
+    }
+    report.roots = array(&doc, "base_dirs")?
+        .iter()
+        .filter_map(Value::as_str)
+        .map(PathBuf::from)
+        .collect();
+    for error in array(&doc, "errors")? {
+        report.errors.push((
+            PathBuf::from(string(error, "path")?),
+            io::Error::other(string(error, "error")?),
+        ));
+    }
+    Ok(report)
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("not a duplicate report: {what}"),
+    )
+}
+
+fn array<'v>(value: &'v Value, name: &str) -> io::Result<&'v [Value]> {
+    value
+        .get(name)
+        .and_then(Value::as_array)
+        .ok_or_else(|| invalid(&format!("missing {name}")))
+}
+
+fn string<'v>(value: &'v Value, name: &str) -> io::Result<&'v str> {
+    value
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid(&format!("missing {name}")))
+}
+
+fn write_file_entry<W: Write>(
+    writer: &mut W,
+    path: &str,
+    metadata: Option<&FileMetadata>,
+) -> io::Result<()> {
+    let null = || "null".to_string();
+    let modified = metadata
+        .and_then(|m| m.modified)
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or_else(null, |d| d.as_secs().to_string());
+    let readonly = metadata.map_or_else(null, |m| m.readonly.to_string());
+    let inode = metadata
+        .and_then(|m| m.inode)
+        .map_or_else(null, |inode| inode.to_string());
+    write!(
+        writer,
+        "{{\"path\": {}, \"modified\": {modified}, \"readonly\": {readonly}, \"inode\": {inode}}}",
+        quote(path)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::{self, Value};
+    use crate::DuplicateGroup;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_write_output_json_parses() {
+        let mut report = DuplicateReport::from_groups(vec![
+            DuplicateGroup {
+                hash: "abc".to_string(),
+                size: 10,
+                paths: vec![
+                    PathBuf::from("/a/\"quoted\".txt"),
+                    PathBuf::from("/b/x.txt"),
+                ],
+            },
+            DuplicateGroup {
+                hash: "def".to_string(),
+                size: 4,
+                paths: vec![
+                    PathBuf::from("/c/1"),
+                    PathBuf::from("/c/2"),
+                    PathBuf::from("/c/3"),
+                ],
+            },
+        ]);
+        report.metadata.insert(
+            PathBuf::from("/b/x.txt"),
+            FileMetadata {
+                modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+                readonly: true,
+                inode: Some(42),
+            },
+        );
+        report.errors.push((
+            PathBuf::from("/locked"),
+            io::Error::new(io::ErrorKind::PermissionDenied, "denied"),
+        ));
+
+        let mut buffer = Vec::new();
+        write_output_json(&mut buffer, &report, "start", &[PathBuf::from("/")]).expect("write");
+        let doc = json::parse(std::str::from_utf8(&buffer).expect("UTF-8")).expect("valid JSON");
+
+        assert_eq!(doc.get("version").and_then(Value::as_u64), Some(1));
+        assert_eq!(doc.get("total_groups").and_then(Value::as_u64), Some(2));
+        assert_eq!(
+            doc.get("total_reclaimable_bytes").and_then(Value::as_u64),
+            Some(18)
+        );
+        let groups = doc.get("groups").and_then(Value::as_array).expect("groups");
+        assert_eq!(groups.len(), 2);
+        assert_eq!(
+            groups[1].get("reclaimable_bytes").and_then(Value::as_u64),
+            Some(8)
+        );
+        let files = groups[0]
+            .get("files")
+            .and_then(Value::as_array)
+            .expect("files");
+        assert_eq!(
+            files[0].get("path").and_then(Value::as_str),
+            Some("/a/\"quoted\".txt")
+        );
+        assert_eq!(files[0].get("inode"), Some(&Value::Null));
+        assert_eq!(
+            files[1].get("modified").and_then(Value::as_u64),
+            Some(1_700_000_000)
+        );
+        assert_eq!(files[1].get("readonly"), Some(&Value::Bool(true)));
+        let errors = doc.get("errors").and_then(Value::as_array).expect("errors");
+        assert_eq!(
+            errors[0].get("error").and_then(Value::as_str),
+            Some("denied")
+        );
+    }
+}
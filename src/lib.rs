@@ -1,23 +1,101 @@
 #![warn(clippy::pedantic)]
 
-use chrono::Local;
+pub mod agent;
+mod archive;
+mod blake3;
+pub mod cache;
+pub mod chunks;
+pub mod compare;
+mod compat;
+mod compressed;
+mod dashboard;
+pub mod dedupe;
+pub mod diff;
+pub mod dirs;
+mod disks;
+mod glob;
+pub mod history;
+mod html;
+mod ignore;
+pub mod index;
+mod inflate;
+mod jpeg;
+mod json;
+mod json_report;
+mod md5;
+mod media;
+pub mod metrics;
+#[cfg(unix)]
+mod mmap;
+pub mod names;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod notify;
+mod options;
+mod png;
+pub mod progress;
+mod regex;
+pub mod remote;
+pub mod s3;
+pub mod schedule;
+pub mod server;
+pub mod similar;
+mod spill;
+mod sqlite;
+pub mod texts;
+mod throttle;
+mod trash;
+pub mod undo;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+pub mod videos;
+pub mod watch;
+
+pub use cache::HashCache;
+pub use compare::{compare_dirs, Comparison};
+pub use compat::{write_fdupes_to, write_rmlint_to};
+pub use dirs::{find_duplicate_dirs, DuplicateDirGroup};
+pub use glob::{GlobError, GlobSet};
+pub use html::write_html_to;
+pub use json_report::{write_output_json, JSON_REPORT_VERSION};
+pub use options::{OutputOptions, ScanOptions};
+pub use progress::{ProgressObserver, Stage};
+pub use regex::RegexError;
+pub use sqlite::write_sqlite_to;
+
+use chrono::{DateTime, Local};
+use disks::DiskLocks;
 use fern::Dispatch;
+use ignore::{IgnoreConfig, Ignores};
 use indicatif::{ProgressBar, ProgressStyle};
-use log::info;
+use log::{debug, info, warn};
+use progress::Observer;
 use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use regex::Regex;
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::fs::{self, File};
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::fs::File;
 use std::hash::Hasher;
-use std::io::{BufReader, BufWriter, Read, Write};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use twox_hash::XxHash64;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex, PoisonError};
+use std::time::SystemTime;
+use throttle::Throttle;
+use twox_hash::{XxHash3_64, XxHash64};
 use walkdir::WalkDir;
 
+/// The file [`setup_logger`] writes to, in the current directory.
+pub const LOG_FILE: &str = "duplicate_finder.log";
+
 /// Initializes logging for the library and command line tool.
 ///
-/// The logger records messages to a file called `duplicate_finder.log` and
+/// The logger records messages to a file called [`LOG_FILE`] and
 /// formats each entry with a timestamp and log level. Call this once near the
 /// start of your program before emitting any log messages.
 ///
@@ -46,11 +124,888 @@ pub fn setup_logger() -> Result<(), fern::InitError> {
             ));
         })
         .level(log::LevelFilter::Info)
-        .chain(fern::log_file("duplicate_finder.log")?)
+        .chain(fern::log_file(LOG_FILE)?)
         .apply()?;
     Ok(())
 }
 
+/// The algorithm used to confirm duplicates in the full-hash stage.
+///
+/// Digests are always reported as lowercase hex strings. SHA-256 is the
+/// default so existing reports stay reproducible; BLAKE3 is considerably
+/// faster and equally suitable for detecting duplicates. XXH3 and MD5 trade
+/// collision resistance for speed: they are fine for finding accidental
+/// copies, but files crafted to collide would be reported as duplicates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum HashAlgo {
+    /// SHA-256, producing a 64-character digest.
+    #[default]
+    Sha256,
+    /// BLAKE3, producing a 64-character digest.
+    Blake3,
+    /// XXH3, a non-cryptographic hash producing a 16-character digest.
+    Xxh3,
+    /// MD5, producing a 32-character digest that matches `md5sum`.
+    Md5,
+}
+
+impl HashAlgo {
+    /// Returns a fresh hasher for this algorithm.
+    ///
+    /// # Example
+    /// ```
+    /// use duplicate_file_finder::HashAlgo;
+    ///
+    /// let mut hasher = HashAlgo::Md5.hasher();
+    /// hasher.update(b"abc");
+    /// assert_eq!(hasher.finish_hex(), "900150983cd24fb0d6963f7d28e17f72");
+    /// ```
+    #[must_use]
+    pub fn hasher(self) -> Box<dyn ContentHasher> {
+        match self {
+            Self::Sha256 => Box::new(Sha256::new()),
+            Self::Blake3 => Box::new(blake3::Hasher::new()),
+            Self::Xxh3 => Box::new(XxHash3_64::new()),
+            Self::Md5 => Box::new(md5::Hasher::new()),
+        }
+    }
+}
+
+/// Incrementally hashes file contents for the full-hash stage.
+///
+/// Each [`HashAlgo`] is backed by an implementation of this trait, so the
+/// stage reads a file the same way whichever algorithm confirms it.
+pub trait ContentHasher {
+    /// Feeds the next bytes of the file.
+    fn update(&mut self, bytes: &[u8]);
+
+    /// Consumes the hasher, returning the digest as a lowercase hex string.
+    fn finish_hex(self: Box<Self>) -> String;
+}
+
+impl ContentHasher for Sha256 {
+    fn update(&mut self, bytes: &[u8]) {
+        Digest::update(self, bytes);
+    }
+
+    fn finish_hex(self: Box<Self>) -> String {
+        format!("{:x}", self.finalize())
+    }
+}
+
+impl ContentHasher for blake3::Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        blake3::Hasher::update(self, bytes);
+    }
+
+    fn finish_hex(self: Box<Self>) -> String {
+        to_hex(&self.finalize())
+    }
+}
+
+impl ContentHasher for XxHash3_64 {
+    fn update(&mut self, bytes: &[u8]) {
+        self.write(bytes);
+    }
+
+    fn finish_hex(self: Box<Self>) -> String {
+        format!("{:016x}", self.finish())
+    }
+}
+
+impl ContentHasher for md5::Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        md5::Hasher::update(self, bytes);
+    }
+
+    fn finish_hex(self: Box<Self>) -> String {
+        to_hex(&self.finalize())
+    }
+}
+
+/// Formats a digest as lowercase hex.
+fn to_hex(digest: &[u8]) -> String {
+    digest
+        .iter()
+        .fold(String::with_capacity(digest.len() * 2), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Which parts of a file the quick-hash stage samples.
+///
+/// Sampling the tail as well as the head separates files that share a common
+/// header. Large media files can share long headers and trailers too, so the
+/// middle can be sampled as well, at the cost of one more seek per file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum QuickHashSample {
+    /// The first and last blocks of each file.
+    #[default]
+    FirstLast,
+    /// The first, middle and last blocks of each file.
+    FirstMiddleLast,
+}
+
+impl QuickHashSample {
+    /// Returns how many blocks of a file are sampled.
+    fn blocks(self) -> u64 {
+        match self {
+            Self::FirstLast => 2,
+            Self::FirstMiddleLast => 3,
+        }
+    }
+}
+
+/// The layout used when writing a report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// The human-readable report written by [`write_output_to`].
+    #[default]
+    Text,
+    /// One row per file, written by [`write_csv_to`].
+    Csv,
+    /// A self-contained web page, written by [`write_html_to`].
+    Html,
+    /// A machine-readable document, written by [`write_output_json`].
+    Json,
+    /// A database of tables for SQL queries, written by [`write_sqlite_to`].
+    Sqlite,
+    /// Blank-line separated groups as `fdupes` prints them, written by
+    /// [`write_fdupes_to`].
+    Fdupes,
+    /// The JSON written by `rmlint -o json`, written by [`write_rmlint_to`].
+    Rmlint,
+}
+
+/// Whether files with identical content must also share a file name to be
+/// reported as duplicates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum NameMatch {
+    /// Report identical files regardless of their names.
+    #[default]
+    Any,
+    /// Only report identical files whose file names are equal.
+    Exact,
+    /// Only report identical files whose file names are equal ignoring case.
+    IgnoreCase,
+}
+
+impl NameMatch {
+    /// Returns the name key that files must share under this rule.
+    fn key(self, path: &Path) -> OsString {
+        let name = path.file_name().unwrap_or_default();
+        match self {
+            NameMatch::Any | NameMatch::Exact => name.to_os_string(),
+            // Names that are not valid UTF-8 can only match exactly.
+            NameMatch::IgnoreCase => name
+                .to_str()
+                .map_or_else(|| name.to_os_string(), |n| n.to_lowercase().into()),
+        }
+    }
+
+    /// Splits a confirmed group into the subgroups whose files share a name.
+    ///
+    /// Subgroups with a single file are dropped. Each keeps the group's hash.
+    fn split(self, group: DuplicateGroup) -> Vec<DuplicateGroup> {
+        if self == NameMatch::Any {
+            return vec![group];
+        }
+        let mut by_name: HashMap<OsString, Vec<PathBuf>> = HashMap::new();
+        for path in group.paths {
+            by_name.entry(self.key(&path)).or_default().push(path);
+        }
+        by_name
+            .into_values()
+            .filter(|paths| paths.len() > 1)
+            .map(|paths| DuplicateGroup {
+                hash: group.hash.clone(),
+                size: group.size,
+                paths,
+            })
+            .collect()
+    }
+}
+
+/// A class of media selected by its file extensions, to scan only the kind
+/// of content that matters without listing every extension as a glob.
+///
+/// Extensions are compared ignoring case, so `IMG_0001.JPG` is an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum FileType {
+    /// Photos and pictures, including camera raw formats.
+    Images,
+    /// Video clips and films.
+    Videos,
+    /// Music and other sound recordings.
+    Audio,
+    /// Text, office and e-book documents.
+    Documents,
+    /// Compressed archives and disk images.
+    Archives,
+}
+
+impl FileType {
+    /// Returns the lowercase extensions, without the dot, of this class.
+    ///
+    /// # Example
+    /// ```
+    /// use duplicate_file_finder::FileType;
+    ///
+    /// assert!(FileType::Images.extensions().contains(&"jpg"));
+    /// ```
+    #[must_use]
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            FileType::Images => &[
+                "jpg", "jpeg", "jpe", "png", "gif", "bmp", "tif", "tiff", "webp", "heic", "heif",
+                "avif", "svg", "ico", "psd", "raw", "dng", "cr2", "cr3", "nef", "arw", "orf",
+                "rw2", "raf", "srw", "pef",
+            ],
+            FileType::Videos => &[
+                "mp4", "m4v", "mov", "avi", "mkv", "webm", "wmv", "flv", "mpg", "mpeg", "m2ts",
+                "mts", "ts", "3gp", "vob", "ogv",
+            ],
+            FileType::Audio => &[
+                "mp3", "flac", "wav", "aac", "m4a", "ogg", "oga", "opus", "wma", "aiff", "aif",
+                "alac", "ape", "mid", "midi",
+            ],
+            FileType::Documents => &[
+                "pdf", "doc", "docx", "odt", "rtf", "txt", "md", "xls", "xlsx", "ods", "csv",
+                "ppt", "pptx", "odp", "epub", "mobi", "djvu", "pages", "numbers", "key",
+            ],
+            FileType::Archives => &[
+                "zip", "tar", "gz", "tgz", "bz2", "tbz2", "xz", "txz", "zst", "7z", "rar", "iso",
+                "dmg", "cab", "lz", "lzma",
+            ],
+        }
+    }
+
+    /// Returns `true` if `path` has one of this class's extensions.
+    fn matches(self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                self.extensions()
+                    .iter()
+                    .any(|known| known.eq_ignore_ascii_case(ext))
+            })
+    }
+}
+
+/// Bytes read from each end of a file by the quick-hash stage by default.
+pub const DEFAULT_QUICK_HASH_BYTES: u64 = 8 * 1024;
+
+/// Pairs of files up to this size are compared byte for byte instead of
+/// hashed, as are larger pairs when [`ScanOptions::compare_pairs`] is set.
+const BYTE_COMPARE_MAX_BYTES: u64 = 64 * 1024;
+
+/// What the directory walk visits in every scanned directory.
+#[derive(Debug, Default)]
+struct Walk {
+    /// Paths to skip; matching directories are not descended into.
+    exclude: GlobSet,
+    /// When not empty, only files matching one of these are collected.
+    include: GlobSet,
+    /// Directories pruned whatever their contents.
+    exclude_dirs: ExcludedDirs,
+    /// When not empty, only files whose full path matches one of these are
+    /// collected.
+    include_regex: Vec<Regex>,
+    /// Files whose full path matches any of these are skipped.
+    exclude_regex: Vec<Regex>,
+    /// When not empty, only files of one of these types are collected.
+    file_types: Vec<FileType>,
+    /// Ignore files whose rules leave paths out of the walk.
+    ignore: IgnoreConfig,
+    /// Whether hidden files and directories are left out.
+    skip_hidden: bool,
+    follow_symlinks: bool,
+    /// Whether the walk stays on the filesystem of each scanned directory.
+    one_file_system: bool,
+    /// Files never collected however they are reached, such as the tool's
+    /// own report and log.
+    skip_files: HashSet<FileId>,
+    max_depth: Option<usize>,
+}
+
+impl Walk {
+    /// Returns `true` if the regular expressions let the file at `full`, an
+    /// absolute path, be collected.
+    fn regex_allows(&self, full: &Path) -> bool {
+        let text = full.to_string_lossy();
+        (self.include_regex.is_empty() || self.include_regex.iter().any(|r| r.is_match(&text)))
+            && !self.exclude_regex.iter().any(|r| r.is_match(&text))
+    }
+}
+
+/// Directories left out of the walk, given as paths or as globs.
+#[derive(Debug, Default)]
+struct ExcludedDirs {
+    /// Patterns without a `/`, matched against the directory's own name.
+    names: GlobSet,
+    /// Patterns with a `/`, matched against the path relative to the
+    /// scanned directory.
+    paths: GlobSet,
+    /// Existing directories named by path, recognised however they are reached.
+    ids: HashSet<FileId>,
+}
+
+impl ExcludedDirs {
+    /// Sorts `patterns` into the three kinds. A pattern containing a path
+    /// separator that names an existing directory is taken as that
+    /// directory; any other pattern is a glob.
+    fn new(patterns: &[String]) -> Result<Self, GlobError> {
+        let mut names = Vec::new();
+        let mut paths = Vec::new();
+        let mut ids = HashSet::new();
+        for pattern in patterns {
+            let pattern = pattern.trim_end_matches('/');
+            let is_path = pattern.contains(['/', std::path::MAIN_SEPARATOR]);
+            if is_path && Path::new(pattern).is_dir() {
+                if let Ok(id) = file_id(Path::new(pattern)) {
+                    ids.insert(id);
+                    continue;
+                }
+            }
+            if is_path {
+                paths.push(pattern.to_string());
+            } else {
+                names.push(pattern.to_string());
+            }
+        }
+        Ok(Self {
+            names: GlobSet::new(&names)?,
+            paths: GlobSet::new(&paths)?,
+            ids,
+        })
+    }
+
+    fn is_match(&self, rel: &Path, entry: &walkdir::DirEntry) -> bool {
+        self.names.is_match(Path::new(entry.file_name()))
+            || self.paths.is_match(rel)
+            || (!self.ids.is_empty()
+                && file_id(entry.path()).is_ok_and(|id| self.ids.contains(&id)))
+    }
+}
+
+/// Settings shared by every stage of a scan.
+#[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)]
+struct ScanConfig<'a> {
+    walk: Walk,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    hash_algo: HashAlgo,
+    quick_hash_bytes: u64,
+    quick_hash_sample: QuickHashSample,
+    paranoid: bool,
+    compare_pairs: bool,
+    ignore_image_metadata: bool,
+    audio_content: bool,
+    scan_archives: bool,
+    decompress: bool,
+    low_memory: bool,
+    threads: usize,
+    io_threads: usize,
+    /// Serialises reads from each spinning disk, unless they may run in
+    /// parallel.
+    disks: Option<DiskLocks>,
+    /// Paces reads while hashing, if their rate is limited.
+    throttle: Option<Throttle>,
+    name_match: NameMatch,
+    cache: Option<&'a HashCache>,
+    quiet: bool,
+    progress_json: bool,
+    progress: Option<Observer<'a>>,
+    cancel: Option<&'a AtomicBool>,
+    /// How many files the walk found.
+    files_found: AtomicU64,
+    /// How many bytes have been read from files.
+    bytes_read: AtomicU64,
+}
+
+impl<'a> ScanConfig<'a> {
+    /// Compiles the glob patterns of `options` into a scan configuration.
+    fn new(options: &ScanOptions<'a>) -> Result<Self, GlobError> {
+        Ok(Self {
+            walk: Walk {
+                exclude: GlobSet::new(&options.exclude)?,
+                include: GlobSet::new(&options.include)?,
+                exclude_dirs: ExcludedDirs::new(&options.exclude_dirs)?,
+                include_regex: compile_regexes(&options.include_regex)?,
+                exclude_regex: compile_regexes(&options.exclude_regex)?,
+                file_types: options.file_types.clone(),
+                ignore: IgnoreConfig::new(options.ignore_files.clone(), options.respect_gitignore),
+                skip_hidden: options.skip_hidden,
+                follow_symlinks: options.follow_symlinks,
+                one_file_system: options.one_file_system,
+                // Files that do not exist yet cannot be met during the walk.
+                skip_files: options
+                    .skip_files
+                    .iter()
+                    .filter_map(|path| file_id(path).ok())
+                    .collect(),
+                max_depth: options.max_depth,
+            },
+            min_size: options.min_size,
+            max_size: options.max_size,
+            hash_algo: options.hash_algo,
+            quick_hash_bytes: options.quick_hash_bytes,
+            quick_hash_sample: options.quick_hash_sample,
+            paranoid: options.paranoid,
+            compare_pairs: options.compare_pairs,
+            ignore_image_metadata: options.ignore_image_metadata,
+            audio_content: options.audio_content,
+            scan_archives: options.scan_archives,
+            decompress: options.decompress,
+            low_memory: options.low_memory,
+            threads: options.threads,
+            io_threads: options.io_threads,
+            disks: options.serialize_hdd.then(DiskLocks::default),
+            throttle: (options.max_read_rate > 0).then(|| Throttle::new(options.max_read_rate)),
+            name_match: options.name_match,
+            cache: options.cache,
+            quiet: options.quiet,
+            progress_json: options.progress_json,
+            progress: options.progress,
+            cancel: options.cancel,
+            files_found: AtomicU64::new(0),
+            bytes_read: AtomicU64::new(0),
+        })
+    }
+}
+
+impl ScanConfig<'_> {
+    /// Runs `read`, which reads `path`, under the lock of its spinning disk
+    /// if reads from spinning disks are serialised.
+    fn read<R>(&self, path: &Path, read: impl FnOnce() -> R) -> R {
+        match &self.disks {
+            Some(disks) => disks.read(path, read),
+            None => read(),
+        }
+    }
+
+    /// Notes the device of `file`, found by the walk, for [`ScanConfig::read`].
+    fn found(&self, file: &FoundFile) {
+        if let Some(disks) = &self.disks {
+            disks.found(&file.path, &file.meta);
+        }
+    }
+
+    /// Returns whether `path` is compared by what it holds rather than byte
+    /// for byte: an image with [`ScanOptions::ignore_image_metadata`], or an
+    /// audio file with [`ScanOptions::audio_content`].
+    fn by_content(&self, path: &Path) -> bool {
+        (self.ignore_image_metadata && media::is_image(path))
+            || (self.audio_content && media::is_audio(path))
+    }
+
+    /// Returns whether `path` is compared by its decompressed contents, with
+    /// [`ScanOptions::decompress`].
+    fn decompresses(&self, path: &Path) -> bool {
+        self.decompress && compressed::is_compressed(path)
+    }
+
+    /// Accounts for `bytes` just read, waiting if reads are running ahead of
+    /// the read rate limit.
+    fn pace(&self, bytes: u64) {
+        self.bytes_read.fetch_add(bytes, Ordering::Relaxed);
+        if let Some(throttle) = &self.throttle {
+            throttle.consume(bytes);
+        }
+    }
+}
+
+fn compile_regexes(patterns: &[String]) -> Result<Vec<Regex>, RegexError> {
+    patterns.iter().map(|pattern| Regex::new(pattern)).collect()
+}
+
+impl Default for ScanConfig<'_> {
+    fn default() -> Self {
+        Self::new(&ScanOptions::default()).expect("no glob patterns to compile")
+    }
+}
+
+/// Returns `true` once the caller has asked for the scan to stop.
+fn is_cancelled(cancel: Option<&AtomicBool>) -> bool {
+    cancel.is_some_and(|flag| flag.load(Ordering::Relaxed))
+}
+
+/// Progress bars and status lines shown on the terminal during a scan.
+///
+/// In quiet mode every bar is hidden and status lines are only logged. With
+/// JSON progress, bars are replaced by events on stderr; see [`JsonProgress`].
+/// With an observer, bars are hidden and everything goes to the observer.
+struct Console<'a> {
+    style: ProgressStyle,
+    /// The style of bars counting bytes, with throughput and time left.
+    byte_style: ProgressStyle,
+    quiet: bool,
+    json: bool,
+    observer: Option<&'a dyn ProgressObserver>,
+}
+
+impl<'a> Console<'a> {
+    fn new(quiet: bool, json: bool, observer: Option<Observer<'a>>) -> Self {
+        let style = |counts: &str| {
+            ProgressStyle::with_template(&format!(
+                "[{{elapsed_precise}}] [{{bar:40.cyan/blue}}] {counts} {{msg}}"
+            ))
+            .unwrap_or_else(|_| ProgressStyle::default_bar())
+            .progress_chars("█>-")
+        };
+        Self {
+            style: style("{pos}/{len}"),
+            byte_style: style("{bytes}/{total_bytes} ({bytes_per_sec}, {eta} left)"),
+            quiet,
+            json,
+            observer: observer.map(|o| o.0),
+        }
+    }
+
+    /// Creates the progress display for a stage, hidden in quiet mode.
+    fn bar(&self, len: u64, stage: Stage, message: &'static str) -> Progress<'a> {
+        self.styled_bar(len, stage, message, &self.style)
+    }
+
+    /// Creates the progress display for a stage whose work is counted in
+    /// bytes, showing the throughput and an estimate of the time left.
+    fn byte_bar(&self, len: u64, stage: Stage, message: &'static str) -> Progress<'a> {
+        self.styled_bar(len, stage, message, &self.byte_style)
+    }
+
+    fn styled_bar(
+        &self,
+        len: u64,
+        stage: Stage,
+        message: &'static str,
+        style: &ProgressStyle,
+    ) -> Progress<'a> {
+        let bar = if self.quiet || self.json || self.observer.is_some() {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new(len)
+        };
+        bar.set_style(style.clone());
+        bar.set_message(message);
+        let json = self.json.then(|| JsonProgress::start(stage.as_str(), len));
+        let observer = self.observer.map(|observer| {
+            observer.stage_started(stage, len);
+            StageObserver {
+                observer,
+                stage,
+                total: len,
+                done: Mutex::new(0),
+            }
+        });
+        Progress {
+            bar,
+            json,
+            observer,
+        }
+    }
+
+    /// Emits the final JSON event once every group has been delivered.
+    fn complete(&self, groups: usize) {
+        if self.json {
+            emit_json(&format!("{{\"event\":\"complete\",\"groups\":{groups}}}"));
+        }
+        if let Some(observer) = self.observer {
+            observer.finished(groups);
+        }
+    }
+
+    /// Logs a status line and hands it to the observer or, unless quiet,
+    /// prints it to stdout.
+    fn status(&self, message: &str) {
+        info!("{message}");
+        if let Some(observer) = self.observer {
+            observer.message(message);
+        } else if !self.quiet {
+            println!("{message}");
+        }
+    }
+}
+
+/// Progress of one stage, shown as a bar, reported as JSON lines or passed
+/// to an observer.
+struct Progress<'a> {
+    bar: ProgressBar,
+    json: Option<JsonProgress>,
+    observer: Option<StageObserver<'a>>,
+}
+
+impl Progress<'_> {
+    fn inc(&self, delta: u64) {
+        self.bar.inc(delta);
+        if let Some(json) = &self.json {
+            json.advance(delta);
+        }
+        if let Some(stage) = &self.observer {
+            stage.advance(delta);
+        }
+    }
+
+    fn finish_with_message(&self, message: &'static str) {
+        self.bar.finish_with_message(message);
+        if let Some(json) = &self.json {
+            json.finish();
+        }
+        if let Some(stage) = &self.observer {
+            stage.observer.stage_finished(stage.stage);
+        }
+    }
+}
+
+/// Forwards one stage's progress to a [`ProgressObserver`].
+struct StageObserver<'a> {
+    observer: &'a dyn ProgressObserver,
+    stage: Stage,
+    total: u64,
+    /// Work done so far; held while calling the observer so calls never overlap.
+    done: Mutex<u64>,
+}
+
+impl StageObserver<'_> {
+    fn advance(&self, delta: u64) {
+        let mut done = self.done.lock().unwrap_or_else(PoisonError::into_inner);
+        *done += delta;
+        self.observer.progress(self.stage, *done, self.total);
+    }
+}
+
+/// Writes `{"stage":...,"done":...,"total":...}` events to stderr.
+///
+/// An event is written when the stage starts, each time another hundredth of
+/// the work is done, and when it finishes, so large scans do not flood the
+/// reader. `done` never decreases within a stage.
+struct JsonProgress {
+    stage: &'static str,
+    total: u64,
+    step: u64,
+    /// Work done so far and the value last reported.
+    state: Mutex<(u64, u64)>,
+}
+
+impl JsonProgress {
+    fn start(stage: &'static str, total: u64) -> Self {
+        let progress = Self {
+            stage,
+            total,
+            step: (total / 100).max(1),
+            state: Mutex::new((0, 0)),
+        };
+        progress.emit(0);
+        progress
+    }
+
+    fn advance(&self, delta: u64) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.0 += delta;
+        if state.0 / self.step != state.1 / self.step {
+            state.1 = state.0;
+            self.emit(state.0);
+        }
+    }
+
+    fn finish(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.1 != state.0 {
+            state.1 = state.0;
+            self.emit(state.0);
+        }
+    }
+
+    fn emit(&self, done: u64) {
+        emit_json(&format!(
+            "{{\"stage\":\"{}\",\"done\":{done},\"total\":{}}}",
+            self.stage, self.total
+        ));
+    }
+}
+
+/// Writes one JSON event line to stderr, ignoring a closed stream.
+fn emit_json(line: &str) {
+    let _ = writeln!(io::stderr().lock(), "{line}");
+}
+
+/// A set of files that share identical content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    /// Lowercase hex digest shared by every file in the group, computed with
+    /// the scan's [`HashAlgo`].
+    ///
+    /// A group of two small files, or of any two files with
+    /// [`ScanOptions::compare_pairs`], is confirmed by comparing their bytes
+    /// directly, and the digest is computed from the bytes read while doing
+    /// so, so it is the same one a hashed group would carry.
+    pub hash: String,
+    /// Size in bytes of each file in the group.
+    ///
+    /// Files compared by what they hold, with
+    /// [`ScanOptions::ignore_image_metadata`] or
+    /// [`ScanOptions::audio_content`], may differ in size; their group has
+    /// the size of its smallest file. A group holding files compressed on
+    /// disk, with [`ScanOptions::decompress`], has their decompressed size.
+    pub size: u64,
+    /// Paths of the files with this content.
+    ///
+    /// With [`ScanOptions::scan_archives`], a file inside an archive has
+    /// the archive's path, `!/` and its path inside, such as
+    /// `backup.zip!/docs/report.pdf`; it cannot be opened by that path.
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    /// Returns the number of bytes that could be freed by keeping a single copy.
+    #[must_use]
+    pub fn reclaimable_bytes(&self) -> u64 {
+        self.size * (self.paths.len().saturating_sub(1) as u64)
+    }
+}
+
+/// File details recorded when a duplicate is confirmed, for the verbose report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMetadata {
+    /// Last modification time, if the platform provides it.
+    pub modified: Option<SystemTime>,
+    /// Whether the file's permissions mark it read-only.
+    pub readonly: bool,
+    /// The file's inode number on Unix; `None` elsewhere.
+    ///
+    /// Every path in a report is a distinct file, so no two entries share
+    /// an inode on the same device.
+    pub inode: Option<u64>,
+}
+
+impl FileMetadata {
+    fn read(path: &Path) -> io::Result<Self> {
+        let meta = std::fs::metadata(path)?;
+        Ok(Self {
+            modified: meta.modified().ok(),
+            readonly: meta.permissions().readonly(),
+            inode: inode(&meta),
+        })
+    }
+}
+
+#[cfg(unix)]
+#[allow(clippy::unnecessary_wraps)]
+fn inode(meta: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(meta.ino())
+}
+
+#[cfg(not(unix))]
+fn inode(_meta: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// The outcome of a duplicate scan.
+///
+/// Groups are ordered by file size, largest first, and groups of equal size
+/// by their first path. The paths within each group are sorted, so scanning
+/// the same files always produces the same report, whatever order the
+/// parallel stages finished in.
+#[derive(Debug, Default)]
+pub struct DuplicateReport {
+    /// Every confirmed group of duplicate files.
+    pub groups: Vec<DuplicateGroup>,
+    /// Number of duplicate groups found.
+    pub total_groups: usize,
+    /// Bytes that could be freed by keeping one file from each group.
+    pub total_reclaimable_bytes: u64,
+    /// Paths that could not be read during the scan, with the error raised.
+    ///
+    /// These files were skipped and are not part of any group.
+    pub errors: Vec<(PathBuf, io::Error)>,
+    /// Whether the scan was cancelled before every file was examined.
+    ///
+    /// The groups of an interrupted scan are still genuine duplicates, but
+    /// other duplicates may have been missed.
+    pub interrupted: bool,
+    /// Metadata of every duplicate path, captured as its group was confirmed.
+    ///
+    /// Paths whose metadata could not be read are absent.
+    pub metadata: HashMap<PathBuf, FileMetadata>,
+    /// The directories that were scanned, in the order they were given.
+    pub roots: Vec<PathBuf>,
+    /// Paths left out because they are hard links to a file already found,
+    /// each paired with the path that was kept.
+    ///
+    /// Hard links already share their data, so they are not duplicates:
+    /// removing one frees no space.
+    pub hard_links: Vec<(PathBuf, PathBuf)>,
+    /// How many files the scan found to compare, before filtering by size.
+    pub files_scanned: u64,
+    /// How many bytes the scan read to hash and compare files.
+    pub bytes_hashed: u64,
+}
+
+/// Orders groups by size descending, then by first path, then by hash.
+///
+/// Groups never share a path, so the hash only matters for hand-built reports.
+fn report_order(a: &DuplicateGroup, b: &DuplicateGroup) -> std::cmp::Ordering {
+    b.size
+        .cmp(&a.size)
+        .then_with(|| a.paths.iter().min().cmp(&b.paths.iter().min()))
+        .then_with(|| a.hash.cmp(&b.hash))
+}
+
+impl DuplicateReport {
+    /// Builds a report from a list of groups, sorting them into report order
+    /// and computing the aggregate totals.
+    #[must_use]
+    pub fn from_groups(mut groups: Vec<DuplicateGroup>) -> Self {
+        for group in &mut groups {
+            group.paths.sort();
+        }
+        groups.sort_by(report_order);
+        let total_reclaimable_bytes = groups.iter().map(DuplicateGroup::reclaimable_bytes).sum();
+        Self {
+            total_groups: groups.len(),
+            total_reclaimable_bytes,
+            groups,
+            errors: Vec::new(),
+            interrupted: false,
+            metadata: HashMap::new(),
+            roots: Vec::new(),
+            hard_links: Vec::new(),
+            files_scanned: 0,
+            bytes_hashed: 0,
+        }
+    }
+
+    /// Returns `true` if no duplicates were found.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Returns the number of files that could be removed by keeping one copy per group.
+    #[must_use]
+    pub fn redundant_files(&self) -> usize {
+        self.groups
+            .iter()
+            .map(|group| group.paths.len().saturating_sub(1))
+            .sum()
+    }
+
+    /// Converts the report into a map from content hash to duplicate paths.
+    ///
+    /// Groups split by [`NameMatch`] share a hash, so their paths are merged.
+    #[must_use]
+    pub fn into_map(self) -> HashMap<String, Vec<PathBuf>> {
+        let mut map: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for group in self.groups {
+            map.entry(group.hash).or_default().extend(group.paths);
+        }
+        map
+    }
+}
+
 /// Recursively scans the provided directory for duplicate files.
 ///
 /// Files are grouped by size, then by a quick non-cryptographic hash and
@@ -79,14 +1034,46 @@ pub fn setup_logger() -> Result<(), fern::InitError> {
 /// ```
 #[must_use]
 pub fn find_duplicates(dir: &Path) -> HashMap<String, Vec<PathBuf>> {
-    find_duplicates_in_dirs(&[dir.to_path_buf()])
+    find_duplicates_report(dir).into_map()
+}
+
+/// Recursively scans the provided directory and returns a structured report.
+///
+/// This behaves like [`find_duplicates`] but keeps the size of each group so
+/// callers do not need to query the filesystem again.
+///
+/// # Arguments
+/// * `dir` - The root path to scan for duplicate files.
+///
+/// # Returns
+/// A [`DuplicateReport`] describing every duplicate group found.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::find_duplicates_report;
+/// use tempfile::tempdir;
+///
+/// fn check() -> std::io::Result<()> {
+///     let dir = tempdir()?;
+///     std::fs::write(dir.path().join("a.txt"), b"same")?;
+///     std::fs::write(dir.path().join("b.txt"), b"same")?;
+///     let report = find_duplicates_report(dir.path());
+///     assert_eq!(report.total_groups, 1);
+///     assert_eq!(report.total_reclaimable_bytes, 4);
+///     Ok(())
+/// }
+/// ```
+#[must_use]
+pub fn find_duplicates_report(dir: &Path) -> DuplicateReport {
+    scan(&[dir.to_path_buf()], &ScanConfig::default())
 }
 
 /// Recursively scans the given directories for duplicate files.
 ///
-/// Files are grouped by size and a quick hash of their first 8 KiB before
-/// verifying equality with a full SHA‑256 hash. Only paths that match at every
-/// stage are returned.
+/// Files are grouped by size and a quick hash of their first and last
+/// [`DEFAULT_QUICK_HASH_BYTES`] before verifying equality with a full
+/// SHA‑256 hash. Only paths that match at every stage are returned. Use
+/// [`find_duplicates_with_options`] to change any of these settings.
 ///
 /// # Arguments
 /// * `dirs` - The directories to search for duplicates.
@@ -116,173 +1103,1873 @@ pub fn find_duplicates(dir: &Path) -> HashMap<String, Vec<PathBuf>> {
 #[allow(clippy::module_name_repetitions)]
 #[must_use]
 pub fn find_duplicates_in_dirs(dirs: &[PathBuf]) -> HashMap<String, Vec<PathBuf>> {
-    let style =
-        ProgressStyle::with_template("[{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}")
-            .unwrap_or_else(|_| ProgressStyle::default_bar())
-            .progress_chars("█>-");
-
-    let files = collect_files(dirs);
-    info!(
-        "{} files identified across {} directories",
-        files.len(),
-        dirs.len()
-    );
-    println!(
-        "{} files identified across {} directories",
-        files.len(),
-        dirs.len()
-    );
-
-    let by_size = group_by_size(&files, &style);
-    let by_quick_hash = group_by_quick_hash(by_size, &style);
-    let duplicates = group_by_full_hash(by_quick_hash, &style);
-
-    info!("{} duplicate files identified.", duplicates.len());
-    duplicates
+    find_duplicates_in_dirs_report(dirs).into_map()
 }
 
-/// Walks all provided directories and returns a flat list of file paths.
+/// Recursively scans the given directories and returns a structured report.
+///
+/// This behaves like [`find_duplicates_in_dirs`] but keeps the size of each
+/// group, which is computed once during full-hash grouping. It scans with
+/// the default [`ScanOptions`]; use [`find_duplicates_with_options`] to
+/// change them.
 ///
 /// # Arguments
-/// * `dirs` - Directories to traverse recursively.
+/// * `dirs` - The directories to search for duplicates.
 ///
 /// # Returns
-/// A vector containing the full paths of every file found.
+/// A [`DuplicateReport`] describing every duplicate group found.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::find_duplicates_in_dirs_report;
+/// use tempfile::tempdir;
+/// use std::fs;
+///
+/// fn demo() -> std::io::Result<()> {
+///     let d1 = tempdir()?;
+///     let d2 = tempdir()?;
+///     fs::write(d1.path().join("a.txt"), b"same")?;
+///     fs::write(d2.path().join("b.txt"), b"same")?;
+///     let report =
+///         find_duplicates_in_dirs_report(&[d1.path().to_path_buf(), d2.path().to_path_buf()]);
+///     assert_eq!(report.groups[0].paths.len(), 2);
+///     Ok(())
+/// }
+/// ```
 #[must_use]
-fn collect_files(dirs: &[PathBuf]) -> Vec<PathBuf> {
-    dirs.iter()
-        .flat_map(|dir| {
-            WalkDir::new(dir)
-                .into_iter()
-                .filter_map(Result::ok)
-                .filter(|entry| entry.path().is_file())
-                .map(|entry| entry.path().to_path_buf())
-                .collect::<Vec<_>>()
-        })
-        .collect()
+pub fn find_duplicates_in_dirs_report(dirs: &[PathBuf]) -> DuplicateReport {
+    // The default options hold no patterns, so they cannot be invalid.
+    find_duplicates_with_options(dirs, &ScanOptions::default()).unwrap_or_default()
 }
 
-/// Groups files by size and returns a map keyed by file length.
+/// Recursively scans the given directories with the settings in `options`.
+///
+/// This is the most flexible entry point: every setting has a default, so
+/// callers only state what they change. See [`ScanOptions`] for the
+/// available settings.
 ///
 /// # Arguments
-/// * `files` - List of file paths to examine.
-/// * `style` - Progress bar style shared across stages.
+/// * `dirs` - The directories to search for duplicates.
+/// * `options` - How to scan them.
 ///
 /// # Returns
-/// A map from file size to the files with that length.
-fn group_by_size(files: &[PathBuf], style: &ProgressStyle) -> HashMap<u64, Vec<PathBuf>> {
-    let progress = ProgressBar::new(files.len() as u64);
-    progress.set_style(style.clone());
-    progress.set_message("Indexing files by size...");
-
-    let size_entries: Vec<(u64, PathBuf)> = files
-        .par_iter()
-        .filter_map(|file| {
-            let size = file.metadata().ok()?.len();
-            progress.inc(1);
-            Some((size, file.clone()))
-        })
+/// A [`DuplicateReport`] describing every duplicate group found.
+///
+/// # Errors
+/// Returns a [`GlobError`] if any of the glob patterns or regular expressions
+/// in `options` is invalid.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::{find_duplicates_with_options, ScanOptions};
+/// use tempfile::tempdir;
+/// use std::fs;
+///
+/// fn demo() -> Result<(), Box<dyn std::error::Error>> {
+///     let dir = tempdir()?;
+///     fs::write(dir.path().join("a.txt"), b"same")?;
+///     fs::write(dir.path().join("b.txt"), b"same")?;
+///     fs::write(dir.path().join("c.tmp"), b"same")?;
+///     let options = ScanOptions::new().exclude(["*.tmp"]).quiet(true);
+///     let report = find_duplicates_with_options(&[dir.path().to_path_buf()], &options)?;
+///     assert_eq!(report.groups[0].paths.len(), 2);
+///     Ok(())
+/// }
+/// # demo().unwrap();
+/// ```
+pub fn find_duplicates_with_options(
+    dirs: &[PathBuf],
+    options: &ScanOptions,
+) -> Result<DuplicateReport, GlobError> {
+    Ok(scan(dirs, &ScanConfig::new(options)?))
+}
+
+/// Brings `previous`, a report of `dirs` made with the same `options`, up
+/// to date after the paths in `changed` were created, modified, moved or
+/// deleted, as a [`Watcher`](watch::Watcher) reports them.
+///
+/// The directories are walked again, which reads no file contents, but only
+/// the files that share a size with a changed file, or with a group of
+/// `previous` that held one, are hashed; the other groups of `previous` are
+/// kept as they were. Pass a [`ScanOptions::cache`] so that the files among
+/// them that did not change are not read in full again. A changed directory
+/// stands for every file below it.
+///
+/// Scans that compare images or audio by what they hold, look inside
+/// archives or compressed files, or use [`ScanOptions::low_memory`] are
+/// not grouped by size alone, so they are run again in full instead.
+///
+/// # Errors
+/// Returns a [`GlobError`] if any of the glob patterns or regular expressions
+/// in `options` is invalid.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::{find_duplicates_with_options, update_duplicates, ScanOptions};
+/// use tempfile::tempdir;
+/// use std::fs;
+///
+/// fn demo() -> Result<(), Box<dyn std::error::Error>> {
+///     let dir = tempdir()?;
+///     fs::write(dir.path().join("a.txt"), b"same")?;
+///     fs::write(dir.path().join("b.txt"), b"same")?;
+///     let dirs = [dir.path().to_path_buf()];
+///     let options = ScanOptions::new().quiet(true);
+///     let report = find_duplicates_with_options(&dirs, &options)?;
+///
+///     fs::write(dir.path().join("c.txt"), b"same")?;
+///     let changed = [dir.path().join("c.txt")];
+///     let report = update_duplicates(&dirs, &report, &changed, &options)?;
+///     assert_eq!(report.groups[0].paths.len(), 3);
+///     Ok(())
+/// }
+/// # demo().unwrap();
+/// ```
+pub fn update_duplicates(
+    dirs: &[PathBuf],
+    previous: &DuplicateReport,
+    changed: &[PathBuf],
+    options: &ScanOptions,
+) -> Result<DuplicateReport, GlobError> {
+    let config = ScanConfig::new(options)?;
+    if config.ignore_image_metadata
+        || config.audio_content
+        || config.scan_archives
+        || config.decompress
+        || config.low_memory
+    {
+        return Ok(scan(dirs, &config));
+    }
+    Ok(update(dirs, previous, changed, &config))
+}
+
+/// Scans the given directories and passes each duplicate group to a callback
+/// as soon as it is confirmed.
+///
+/// Unlike [`find_duplicates_in_dirs_report`], confirmed groups are never
+/// collected into a single structure, so results can be written to a file or
+/// database incrementally while a large scan is still running. The callback is
+/// always invoked on the calling thread, so it does not need to be `Send` or
+/// `Sync`. Groups arrive in no particular order.
+///
+/// # Arguments
+/// * `dirs` - The directories to search for duplicates.
+/// * `on_group` - Called once for every confirmed duplicate group.
+///
+/// # Returns
+/// The paths that could not be read during the scan, with the error raised.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::find_duplicates_streaming;
+/// use tempfile::tempdir;
+/// use std::fs;
+///
+/// fn demo() -> std::io::Result<()> {
+///     let dir = tempdir()?;
+///     fs::write(dir.path().join("a.txt"), b"same")?;
+///     fs::write(dir.path().join("b.txt"), b"same")?;
+///     let mut count = 0;
+///     let errors = find_duplicates_streaming(&[dir.path().to_path_buf()], |group| {
+///         assert_eq!(group.paths.len(), 2);
+///         count += 1;
+///     });
+///     assert_eq!(count, 1);
+///     assert!(errors.is_empty());
+///     Ok(())
+/// }
+/// ```
+pub fn find_duplicates_streaming(
+    dirs: &[PathBuf],
+    mut on_group: impl FnMut(DuplicateGroup),
+) -> Vec<(PathBuf, io::Error)> {
+    scan_streaming(dirs, &ScanConfig::default(), &mut on_group, &mut Vec::new())
+}
+
+/// Starts a scan on a background thread and returns an iterator over the
+/// duplicate groups as they are confirmed.
+///
+/// This is the pull-based form of [`find_duplicates_streaming`]: a frontend
+/// can show results while a large scan is still running, from any thread and
+/// without handing over a callback. Groups arrive in no particular order. The
+/// options must not borrow anything short-lived, since the scan outlives this
+/// call; a scan whose iterator is dropped early still runs to the end, so
+/// pass a flag to [`ScanOptions::cancel`] to stop it.
+///
+/// # Errors
+/// Returns a [`GlobError`] if any of the glob patterns or regular expressions
+/// in `options` is invalid.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::{find_duplicates_iter, ScanOptions};
+/// use tempfile::tempdir;
+/// use std::fs;
+///
+/// fn demo() -> Result<(), Box<dyn std::error::Error>> {
+///     let dir = tempdir()?;
+///     fs::write(dir.path().join("a.txt"), b"same")?;
+///     fs::write(dir.path().join("b.txt"), b"same")?;
+///     let mut groups = find_duplicates_iter(vec![dir.path().to_path_buf()], &ScanOptions::new().quiet(true))?;
+///     for group in &mut groups {
+///         println!("{} copies of {} bytes", group.paths.len(), group.size);
+///     }
+///     assert!(groups.into_errors().is_empty());
+///     Ok(())
+/// }
+/// # demo().unwrap();
+/// ```
+pub fn find_duplicates_iter(
+    dirs: Vec<PathBuf>,
+    options: &ScanOptions<'static>,
+) -> Result<DuplicateGroups, GlobError> {
+    let config = ScanConfig::new(options)?;
+    let (sender, receiver) = mpsc::channel();
+    let scan = std::thread::spawn(move || {
+        scan_streaming(
+            &dirs,
+            &config,
+            &mut |group| {
+                // A closed receiver just means nobody is reading any more.
+                let _ = sender.send(group);
+            },
+            &mut Vec::new(),
+        )
+    });
+    Ok(DuplicateGroups {
+        receiver,
+        scan: Some(scan),
+    })
+}
+
+/// Duplicate groups confirmed by a background scan; see [`find_duplicates_iter`].
+///
+/// Iteration blocks until the next group is confirmed and ends when the scan
+/// has finished.
+#[derive(Debug)]
+pub struct DuplicateGroups {
+    receiver: mpsc::Receiver<DuplicateGroup>,
+    scan: Option<std::thread::JoinHandle<Vec<(PathBuf, io::Error)>>>,
+}
+
+impl Iterator for DuplicateGroups {
+    type Item = DuplicateGroup;
+
+    fn next(&mut self) -> Option<DuplicateGroup> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl DuplicateGroups {
+    /// Waits for the scan to finish and returns the paths that could not be
+    /// read, with the error raised. Groups not yet taken are discarded.
+    #[must_use]
+    pub fn into_errors(mut self) -> Vec<(PathBuf, io::Error)> {
+        match self.scan.take().map(std::thread::JoinHandle::join) {
+            Some(Ok(errors)) => errors,
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Runs the size, quick hash and full hash stages over the given directories.
+fn scan(dirs: &[PathBuf], config: &ScanConfig) -> DuplicateReport {
+    let mut groups = Vec::new();
+    let mut metadata = HashMap::new();
+    let mut hard_links = Vec::new();
+    let errors = scan_streaming(
+        dirs,
+        config,
+        &mut |group| {
+            for path in &group.paths {
+                match FileMetadata::read(path) {
+                    Ok(meta) => {
+                        metadata.insert(path.clone(), meta);
+                    }
+                    Err(e) => debug!("No metadata for {}: {e}", path.display()),
+                }
+            }
+            groups.push(group);
+        },
+        &mut hard_links,
+    );
+    let mut report = DuplicateReport::from_groups(groups);
+    report.errors = errors;
+    hard_links.sort();
+    report.hard_links = hard_links;
+    report.metadata = metadata;
+    report.roots = dirs.to_vec();
+    report.interrupted = is_cancelled(config.cancel);
+    report.files_scanned = config.files_found.load(Ordering::Relaxed);
+    report.bytes_hashed = config.bytes_read.load(Ordering::Relaxed);
+
+    if report.interrupted {
+        warn!("Scan interrupted; results are incomplete.");
+    }
+    info!("{} duplicate files identified.", report.total_groups);
+    report
+}
+
+/// Updates `previous` for the paths in `changed`, as described for
+/// [`update_duplicates`].
+fn update(
+    dirs: &[PathBuf],
+    previous: &DuplicateReport,
+    changed: &[PathBuf],
+    config: &ScanConfig,
+) -> DuplicateReport {
+    let console = Console::new(config.quiet, config.progress_json, config.progress);
+    let errors = Mutex::new(Vec::new());
+    let pool = build_pool(config.threads);
+    let io_pool = build_pool(config.io_threads);
+    let stages = Stages {
+        console: &console,
+        config,
+        pool: io_pool.as_ref().or(pool.as_ref()),
+        errors: &errors,
+    };
+    let mut hard_links = Vec::new();
+    let files = collect_files(dirs, &config.walk, config.cancel, &errors, &mut hard_links);
+    for file in &files {
+        config.found(file);
+    }
+    config
+        .files_found
+        .store(files.len() as u64, Ordering::Relaxed);
+
+    // Changes are reported by absolute path, however the directories were given.
+    let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let changed: Vec<PathBuf> = changed.iter().map(|path| absolute(path)).collect();
+    let touched = |path: &Path| {
+        let path = absolute(path);
+        changed.iter().any(|changed| path.starts_with(changed))
+    };
+    let files: Vec<(u64, PathBuf)> = files
+        .into_iter()
+        .map(|file| (file.meta.len(), file.path))
+        .collect();
+    let sizes: HashSet<u64> = previous
+        .groups
+        .iter()
+        .filter(|group| group.paths.iter().any(|path| touched(path)))
+        .map(|group| group.size)
+        .chain(
+            files
+                .iter()
+                .filter(|(_, path)| touched(path))
+                .map(|&(size, _)| size),
+        )
+        .collect();
+    let files: Vec<(u64, PathBuf)> = files
+        .into_iter()
+        .filter(|(size, _)| sizes.contains(size))
+        .collect();
+    console.status(&format!(
+        "{} files share a size with a changed file",
+        files.len()
+    ));
+
+    let mut groups: Vec<DuplicateGroup> = previous
+        .groups
+        .iter()
+        .filter(|group| !sizes.contains(&group.size))
+        .cloned()
+        .collect();
+    let mut metadata: HashMap<PathBuf, FileMetadata> = groups
+        .iter()
+        .flat_map(|group| &group.paths)
+        .filter_map(|path| Some((path.clone(), *previous.metadata.get(path)?)))
+        .collect();
+    let by_size = group_by_size(files, &console, config.min_size, config.max_size);
+    let found = stages.hash(by_size, &mut |group| {
+        for path in &group.paths {
+            match FileMetadata::read(path) {
+                Ok(meta) => {
+                    metadata.insert(path.clone(), meta);
+                }
+                Err(e) => debug!("No metadata for {}: {e}", path.display()),
+            }
+        }
+        groups.push(group);
+    });
+    console.complete(found);
+
+    let mut report = DuplicateReport::from_groups(groups);
+    report.errors = into_errors(errors);
+    hard_links.sort();
+    report.hard_links = hard_links;
+    report.metadata = metadata;
+    report.roots = dirs.to_vec();
+    report.interrupted = is_cancelled(config.cancel);
+    report.files_scanned = config.files_found.load(Ordering::Relaxed);
+    report.bytes_hashed = config.bytes_read.load(Ordering::Relaxed);
+    info!(
+        "{} duplicate files identified after {} changes.",
+        report.total_groups,
+        changed.len()
+    );
+    report
+}
+
+/// Runs every stage and hands each confirmed group to `on_group` as soon as
+/// it is found, returning the files that could not be read. Hard links left
+/// out of the scan are added to `hard_links`.
+fn scan_streaming(
+    dirs: &[PathBuf],
+    config: &ScanConfig,
+    on_group: &mut dyn FnMut(DuplicateGroup),
+    hard_links: &mut Vec<(PathBuf, PathBuf)>,
+) -> Vec<(PathBuf, io::Error)> {
+    let console = Console::new(config.quiet, config.progress_json, config.progress);
+
+    let errors = Mutex::new(Vec::new());
+    let pool = build_pool(config.threads);
+    let pool = pool.as_ref();
+    // The stages that read file contents can be bounded separately, to limit
+    // disk pressure without slowing down the rest of the scan.
+    let io_pool = build_pool(config.io_threads);
+    let stages = Stages {
+        console: &console,
+        config,
+        pool: io_pool.as_ref().or(pool),
+        errors: &errors,
+    };
+    if config.low_memory {
+        let groups = scan_spilled(dirs, &stages, on_group, hard_links);
+        console.complete(groups);
+        return into_errors(errors);
+    }
+
+    let files = collect_files(dirs, &config.walk, config.cancel, &errors, hard_links);
+    for file in &files {
+        config.found(file);
+    }
+    config
+        .files_found
+        .store(files.len() as u64, Ordering::Relaxed);
+    if files.is_empty() {
+        console.status("No files found to scan.");
+        console.complete(0);
+        return into_errors(errors);
+    }
+    console.status(&format!(
+        "{} files identified across {} directories",
+        files.len(),
+        dirs.len()
+    ));
+
+    let (media, files): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|file| config.by_content(&file.path));
+    let (compressed, files): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|file| config.decompresses(&file.path));
+    let files: Vec<_> = files
+        .into_iter()
+        .map(|file| (file.meta.len(), file.path))
+        .collect();
+    let mut entries = stages.hash_archives(&files);
+    stages.hash_decompressed(compressed, &mut entries);
+    // Files the size of a file inside an archive, or of a decompressed
+    // file, are compared with it by their full hash, skipping the earlier
+    // stages.
+    let (matching, files): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|(size, _)| entries.contains_key(size));
+    let by_size = group_by_size(files, &console, config.min_size, config.max_size);
+    let mut groups = stages.hash(by_size, on_group);
+    groups += stages.hash_archived(matching, entries, on_group);
+    groups += stages.hash_media(media, on_group);
+    console.complete(groups);
+    into_errors(errors)
+}
+
+/// The stages of a scan that follow grouping by size, and what they share.
+struct Stages<'s, 'a> {
+    console: &'s Console<'a>,
+    config: &'s ScanConfig<'a>,
+    /// Thread pool to read files in, or `None` for the global pool.
+    pool: Option<&'s ThreadPool>,
+    errors: &'s Mutex<Vec<(PathBuf, io::Error)>>,
+}
+
+impl Stages<'_, '_> {
+    /// Hashes the files of every size group, handing each confirmed group to
+    /// `on_group`, and returns how many groups were found.
+    fn hash(
+        &self,
+        by_size: HashMap<u64, Vec<PathBuf>>,
+        on_group: &mut dyn FnMut(DuplicateGroup),
+    ) -> usize {
+        let Self {
+            console,
+            config,
+            pool: io_pool,
+            errors,
+        } = *self;
+        if by_size.is_empty() {
+            // Only files sharing a size can be duplicates, so hashing is pointless.
+            return 0;
+        }
+        let by_quick_hash = in_pool(io_pool, || {
+            group_by_quick_hash(by_size, console, config, errors)
+        });
+        let mut groups = 0;
+        group_by_full_hash(
+            by_quick_hash,
+            console,
+            config,
+            io_pool,
+            errors,
+            &mut |group| {
+                for group in config.name_match.split(group) {
+                    groups += 1;
+                    on_group(group);
+                }
+            },
+        );
+        groups
+    }
+
+    /// Groups images and audio files by a hash of what they hold, for
+    /// [`ScanOptions::ignore_image_metadata`] and
+    /// [`ScanOptions::audio_content`], handing each group to `on_group`, and
+    /// returns how many groups were found.
+    ///
+    /// Files that differ only in metadata differ in size, so rather than
+    /// going through the stages keyed by size, every file is read and
+    /// hashed. Files that cannot be decoded are hashed byte for byte.
+    fn hash_media(&self, files: Vec<FoundFile>, on_group: &mut dyn FnMut(DuplicateGroup)) -> usize {
+        let Self {
+            console,
+            config,
+            pool: io_pool,
+            errors,
+        } = *self;
+        let files: Vec<(u64, PathBuf)> = files
+            .into_iter()
+            .map(|file| (file.meta.len(), file.path))
+            .filter(|&(size, _)| {
+                config.min_size.is_none_or(|min| size >= min)
+                    && config.max_size.is_none_or(|max| size <= max)
+            })
+            .collect();
+        if files.len() < 2 {
+            return 0;
+        }
+        let progress = console.bar(
+            files.len() as u64,
+            Stage::MediaHash,
+            "Hashing media contents...",
+        );
+        let hashed: Vec<(String, u64, PathBuf)> = in_pool(io_pool, || {
+            files
+                .into_par_iter()
+                .filter_map(|(size, path)| {
+                    if is_cancelled(config.cancel) {
+                        return None;
+                    }
+                    let data = config.read(&path, || std::fs::read(&path));
+                    progress.inc(1);
+                    let data = match data {
+                        Ok(data) => data,
+                        Err(e) => {
+                            record_error(errors, &path, e);
+                            return None;
+                        }
+                    };
+                    config.pace(data.len() as u64);
+                    let hash = media::content_hash(&data, config.hash_algo)
+                        .unwrap_or_else(|e| {
+                            debug!("Comparing {} byte for byte: {e}", path.display());
+                            None
+                        })
+                        .unwrap_or_else(|| {
+                            let mut hasher = config.hash_algo.hasher();
+                            hasher.update(&data);
+                            hasher.finish_hex()
+                        });
+                    Some((hash, size, path))
+                })
+                .collect()
+        });
+        progress.finish_with_message("Media contents hashed.");
+
+        let mut by_hash: HashMap<String, Vec<(u64, PathBuf)>> = HashMap::new();
+        for (hash, size, path) in hashed {
+            by_hash.entry(hash).or_default().push((size, path));
+        }
+        let mut groups = 0;
+        for (hash, files) in by_hash.into_iter().filter(|(_, files)| files.len() > 1) {
+            // Only the smallest copy's size is sure to be freed by each removal.
+            let size = files
+                .iter()
+                .map(|&(size, _)| size)
+                .min()
+                .unwrap_or_default();
+            let paths = files.into_iter().map(|(_, path)| path).collect();
+            for group in config
+                .name_match
+                .split(DuplicateGroup { hash, size, paths })
+            {
+                groups += 1;
+                on_group(group);
+            }
+        }
+        groups
+    }
+
+    /// Hashes every file inside the archives among `files`, for
+    /// [`ScanOptions::scan_archives`], returning their hashes and paths by
+    /// size. Only sizes within the scan's limits are kept.
+    fn hash_archives(&self, files: &[(u64, PathBuf)]) -> HashMap<u64, Vec<(String, PathBuf)>> {
+        let Self {
+            console,
+            config,
+            pool: io_pool,
+            errors,
+        } = *self;
+        let mut by_size: HashMap<u64, Vec<(String, PathBuf)>> = HashMap::new();
+        if !config.scan_archives {
+            return by_size;
+        }
+        let archives: Vec<&(u64, PathBuf)> = files
+            .iter()
+            .filter(|(_, path)| archive::is_archive(path))
+            .collect();
+        if archives.is_empty() {
+            return by_size;
+        }
+        let progress = console.bar(
+            archives.len() as u64,
+            Stage::ArchiveHash,
+            "Reading archives...",
+        );
+        let entries: Vec<Vec<(u64, String, PathBuf)>> = in_pool(io_pool, || {
+            archives
+                .into_par_iter()
+                .map(|(size, path)| {
+                    let mut entries = Vec::new();
+                    if is_cancelled(config.cancel) {
+                        return entries;
+                    }
+                    let read = config.read(path, || {
+                        archive::hash_entries(path, config.hash_algo, &mut |entry, hashed| {
+                            match hashed {
+                                Ok((size, hash)) => entries.push((size, hash, entry)),
+                                Err(e) => record_error(errors, &entry, e),
+                            }
+                        })
+                    });
+                    if let Err(e) = read {
+                        record_error(errors, path, e);
+                    }
+                    config.pace(*size);
+                    progress.inc(1);
+                    entries
+                })
+                .collect()
+        });
+        progress.finish_with_message("Archives read.");
+
+        for (size, hash, path) in entries.into_iter().flatten() {
+            if config.min_size.is_none_or(|min| size >= min)
+                && config.max_size.is_none_or(|max| size <= max)
+            {
+                by_size.entry(size).or_default().push((hash, path));
+            }
+        }
+        by_size
+    }
+
+    /// Hashes the decompressed contents of `files`, for
+    /// [`ScanOptions::decompress`], adding their hashes and paths to
+    /// `entries` under their decompressed size if it is within the scan's
+    /// limits.
+    fn hash_decompressed(
+        &self,
+        files: Vec<FoundFile>,
+        entries: &mut HashMap<u64, Vec<(String, PathBuf)>>,
+    ) {
+        let Self {
+            console,
+            config,
+            pool: io_pool,
+            errors,
+        } = *self;
+        if files.is_empty() {
+            return;
+        }
+        let progress = console.bar(
+            files.len() as u64,
+            Stage::Decompress,
+            "Decompressing files...",
+        );
+        let hashed: Vec<(u64, String, PathBuf)> = in_pool(io_pool, || {
+            files
+                .into_par_iter()
+                .filter_map(|file| {
+                    if is_cancelled(config.cancel) {
+                        return None;
+                    }
+                    let hashed = config.read(&file.path, || {
+                        compressed::hash_decompressed(&file.path, config.hash_algo)
+                    });
+                    config.pace(file.meta.len());
+                    progress.inc(1);
+                    match hashed {
+                        Ok((size, hash)) => Some((size, hash, file.path)),
+                        Err(e) => {
+                            record_error(errors, &file.path, e);
+                            None
+                        }
+                    }
+                })
+                .collect()
+        });
+        progress.finish_with_message("Files decompressed.");
+
+        for (size, hash, path) in hashed {
+            if config.min_size.is_none_or(|min| size >= min)
+                && config.max_size.is_none_or(|max| size <= max)
+            {
+                entries.entry(size).or_default().push((hash, path));
+            }
+        }
+    }
+
+    /// Groups the files inside archives and the decompressed files in
+    /// `entries`, by size, with each other and with `files`, the other files
+    /// of the same sizes, handing each group to `on_group`, and returns how
+    /// many groups were found.
+    fn hash_archived(
+        &self,
+        files: Vec<(u64, PathBuf)>,
+        entries: HashMap<u64, Vec<(String, PathBuf)>>,
+        on_group: &mut dyn FnMut(DuplicateGroup),
+    ) -> usize {
+        let Self {
+            console,
+            config,
+            pool: io_pool,
+            errors,
+        } = *self;
+        let mut by_hash: HashMap<(u64, String), Vec<PathBuf>> = HashMap::new();
+        for (size, entries) in entries {
+            for (hash, path) in entries {
+                by_hash.entry((size, hash)).or_default().push(path);
+            }
+        }
+        if !files.is_empty() {
+            let progress = console.bar(
+                files.len() as u64,
+                Stage::ArchiveHash,
+                "Hashing files of matching sizes...",
+            );
+            let hashed: Vec<(u64, String, PathBuf)> = in_pool(io_pool, || {
+                files
+                    .into_par_iter()
+                    .filter_map(|(size, path)| {
+                        if is_cancelled(config.cancel) {
+                            return None;
+                        }
+                        let hash = config.read(&path, || full_hash(&path, config.hash_algo));
+                        config.pace(size);
+                        progress.inc(1);
+                        match hash {
+                            Ok(hash) => Some((size, hash, path)),
+                            Err(e) => {
+                                record_error(errors, &path, e);
+                                None
+                            }
+                        }
+                    })
+                    .collect()
+            });
+            progress.finish_with_message("Files hashed.");
+            for (size, hash, path) in hashed {
+                by_hash.entry((size, hash)).or_default().push(path);
+            }
+        }
+
+        let mut groups = 0;
+        for ((size, hash), paths) in by_hash.into_iter().filter(|(_, paths)| paths.len() > 1) {
+            for group in config
+                .name_match
+                .split(DuplicateGroup { hash, size, paths })
+            {
+                groups += 1;
+                on_group(group);
+            }
+        }
+        groups
+    }
+}
+
+/// Runs a low-memory scan, returning how many groups were found.
+///
+/// Rather than being held in memory, every file found is written to a
+/// temporary [`spill::Spill`] and read back in batches, each holding every
+/// file of some sizes. Each batch is grouped by size and hashed before the
+/// next is read, so only one batch's paths are in memory at a time. Files
+/// reached by several paths are recognised once their size is known to be
+/// shared, at the cost of reading their metadata a second time.
+fn scan_spilled(
+    dirs: &[PathBuf],
+    stages: &Stages,
+    on_group: &mut dyn FnMut(DuplicateGroup),
+    hard_links: &mut Vec<(PathBuf, PathBuf)>,
+) -> usize {
+    let Stages {
+        console,
+        config,
+        errors,
+        ..
+    } = *stages;
+    let mut spill = match spill::Spill::new() {
+        Ok(spill) => spill,
+        Err(e) => {
+            record_error(errors, &std::env::temp_dir(), e);
+            return 0;
+        }
+    };
+    let mut failed = None;
+    // Files compared by what they hold are not grouped by size, so they are
+    // kept in memory and searched before the batches.
+    let mut media = Collected::default();
+    let mut compressed = Collected::default();
+    // Archives are also kept, to be read before the batches.
+    let mut archives = Vec::new();
+    walk_files(
+        dirs,
+        &config.walk,
+        config.cancel,
+        errors,
+        &mut |file, id| {
+            config.found(&file);
+            if config.by_content(&file.path) {
+                media.add(file, id, hard_links);
+            } else if config.decompresses(&file.path) {
+                compressed.add(file, id, hard_links);
+            } else if failed.is_none() {
+                failed = spill.push(file.meta.len(), &file.path).err();
+                if config.scan_archives && archive::is_archive(&file.path) {
+                    archives.push((file.meta.len(), file.path));
+                }
+            }
+        },
+    );
+    if let Some(e) = failed {
+        record_error(errors, spill.path(), e);
+        return 0;
+    }
+    let found = spill.len() + (media.files.len() + compressed.files.len()) as u64;
+    config.files_found.store(found, Ordering::Relaxed);
+    if found == 0 {
+        console.status("No files found to scan.");
+        return 0;
+    }
+    console.status(&format!(
+        "{found} files identified across {} directories",
+        dirs.len()
+    ));
+    let mut groups = stages.hash_media(media.files, on_group);
+    let mut entries = stages.hash_archives(&archives);
+    stages.hash_decompressed(compressed.files, &mut entries);
+    if spill.len() == 0 {
+        return groups + stages.hash_archived(Vec::new(), entries, on_group);
+    }
+
+    let path = spill.path().to_path_buf();
+    let batches = match spill.into_batches(spill::BATCH_FILES) {
+        Ok(batches) => batches,
+        Err(e) => {
+            record_error(errors, &path, e);
+            return groups + stages.hash_archived(Vec::new(), entries, on_group);
+        }
+    };
+    let count = batches.len();
+    for (index, batch) in batches.enumerate() {
+        if is_cancelled(config.cancel) {
+            break;
+        }
+        if count > 1 {
+            console.status(&format!("Searching batch {} of {count}", index + 1));
+        }
+        let batch = match batch {
+            Ok(batch) => batch,
+            Err(e) => {
+                record_error(errors, &path, e);
+                continue;
+            }
+        };
+        let (matching, batch): (Vec<_>, Vec<_>) = batch
+            .into_iter()
+            .partition(|(size, _)| entries.contains_key(size));
+        if !matching.is_empty() {
+            groups += hash_spilled_archived(stages, matching, &mut entries, on_group, hard_links);
+        }
+        let mut by_size = group_by_size(batch, console, config.min_size, config.max_size);
+        drop_repeated_files(&mut by_size, errors, hard_links);
+        groups += stages.hash(by_size, on_group);
+    }
+    // Files inside archives, or decompressed, whose size no other file has.
+    groups + stages.hash_archived(Vec::new(), entries, on_group)
+}
+
+/// Groups `files`, of a batch of a low-memory scan, with the files of
+/// `entries` they share a size with, which are taken out of `entries`, and
+/// returns how many groups were found.
+fn hash_spilled_archived(
+    stages: &Stages,
+    files: Vec<(u64, PathBuf)>,
+    entries: &mut HashMap<u64, Vec<(String, PathBuf)>>,
+    on_group: &mut dyn FnMut(DuplicateGroup),
+    hard_links: &mut Vec<(PathBuf, PathBuf)>,
+) -> usize {
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for (size, path) in files {
+        by_size.entry(size).or_default().push(path);
+    }
+    drop_repeated_paths(&mut by_size, stages.errors, hard_links);
+    let sizes: HashMap<_, _> = by_size
+        .keys()
+        .filter_map(|size| entries.remove_entry(size))
         .collect();
+    let files = by_size
+        .into_iter()
+        .flat_map(|(size, paths)| paths.into_iter().map(move |path| (size, path)))
+        .collect();
+    stages.hash_archived(files, sizes, on_group)
+}
+
+/// Keeps one path to each file in `by_size`, as [`collect_files`] does for
+/// a whole scan, and drops the sizes no longer shared.
+fn drop_repeated_files(
+    by_size: &mut HashMap<u64, Vec<PathBuf>>,
+    errors: &Mutex<Vec<(PathBuf, io::Error)>>,
+    hard_links: &mut Vec<(PathBuf, PathBuf)>,
+) {
+    drop_repeated_paths(by_size, errors, hard_links);
+    by_size.retain(|_, paths| paths.len() > 1);
+}
+
+/// Keeps one path to each file in `by_size`, as [`collect_files`] does for
+/// a whole scan.
+fn drop_repeated_paths(
+    by_size: &mut HashMap<u64, Vec<PathBuf>>,
+    errors: &Mutex<Vec<(PathBuf, io::Error)>>,
+    hard_links: &mut Vec<(PathBuf, PathBuf)>,
+) {
+    for paths in by_size.values_mut() {
+        let mut collected = Collected::default();
+        for path in std::mem::take(paths) {
+            match file_id(&path) {
+                Ok(id) => collected.add(path, id, hard_links),
+                Err(e) => record_error(errors, &path, e),
+            }
+        }
+        *paths = collected.files;
+    }
+}
+
+/// Unwraps the errors collected by a scan, logging how many files were skipped.
+fn into_errors(errors: Mutex<Vec<(PathBuf, io::Error)>>) -> Vec<(PathBuf, io::Error)> {
+    let errors = errors.into_inner().unwrap_or_else(PoisonError::into_inner);
+    if !errors.is_empty() {
+        warn!("{} files skipped due to errors.", errors.len());
+    }
+    errors
+}
+
+/// Builds a dedicated thread pool when the scan is limited to `threads` workers.
+///
+/// Returns `None` for `0` or if the pool cannot be created, in which case the
+/// global rayon pool is used.
+fn build_pool(threads: usize) -> Option<ThreadPool> {
+    if threads == 0 {
+        return None;
+    }
+    match ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => Some(pool),
+        Err(e) => {
+            warn!("Cannot create a pool of {threads} threads, using the default: {e}");
+            None
+        }
+    }
+}
+
+/// Runs `op` inside `pool`, or directly on the global pool when there is none.
+fn in_pool<R: Send>(pool: Option<&ThreadPool>, op: impl FnOnce() -> R + Send) -> R {
+    match pool {
+        Some(pool) => pool.install(op),
+        None => op(),
+    }
+}
+
+/// Records a file that could not be read and logs the reason.
+fn record_error(errors: &Mutex<Vec<(PathBuf, io::Error)>>, path: &Path, error: io::Error) {
+    warn!("Skipping {}: {error}", path.display());
+    errors
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push((path.to_path_buf(), error));
+}
+
+/// Walks all provided directories and returns a flat list of file paths.
+///
+/// Entries matching `walk.exclude` are skipped, and excluded directories,
+/// including those in `walk.exclude_dirs`, are pruned so their contents are
+/// never visited. When `walk.include` is not
+/// empty, only files matching it are returned. Entries that cannot be read
+/// are recorded in `errors`.
+///
+/// Each underlying file is returned only once, however many paths lead to
+/// it: a directory listed twice, a directory nested inside another one, a
+/// symlink and its target, or several hard links to the same data all yield
+/// the first path seen. Hard links left out this way are added to
+/// `hard_links` with the path that was kept. When `walk.follow_symlinks` is set, symlinked
+/// directories are descended into as well. Symlink loops are skipped rather
+/// than followed forever.
+///
+/// # Arguments
+/// * `dirs` - Directories to traverse recursively.
+/// * `walk` - Patterns, matched against paths relative to each directory,
+///   and traversal limits.
+/// * `cancel` - Stops the walk early once set.
+/// * `errors` - Collects paths that could not be traversed.
+///
+/// # Returns
+/// Every file found, with the metadata read while walking so later stages
+/// need not read it again.
+#[must_use]
+fn collect_files(
+    dirs: &[PathBuf],
+    walk: &Walk,
+    cancel: Option<&AtomicBool>,
+    errors: &Mutex<Vec<(PathBuf, io::Error)>>,
+    hard_links: &mut Vec<(PathBuf, PathBuf)>,
+) -> Vec<FoundFile> {
+    let mut collected = Collected::default();
+    walk_files(dirs, walk, cancel, errors, &mut |file, id| {
+        collected.add(file, id, hard_links);
+    });
+    collected.files
+}
+
+/// Walks `dirs` as [`collect_files`] does, handing every file found to
+/// `on_file` with its identity, without leaving out files already found
+/// through another path.
+fn walk_files(
+    dirs: &[PathBuf],
+    walk: &Walk,
+    cancel: Option<&AtomicBool>,
+    errors: &Mutex<Vec<(PathBuf, io::Error)>>,
+    on_file: &mut dyn FnMut(FoundFile, FileId),
+) {
+    let mut roots = HashSet::new();
+    for dir in dirs {
+        // Walking the same directory twice would only find files already seen.
+        if let Ok(root) = std::fs::canonicalize(dir) {
+            if !roots.insert(root) {
+                debug!("Skipping {}: already scanned", dir.display());
+                continue;
+            }
+        }
+        // Regular expressions see absolute paths, however the directory was given.
+        let uses_regex = !walk.include_regex.is_empty() || !walk.exclude_regex.is_empty();
+        let absolute_root = if uses_regex {
+            std::path::absolute(dir).unwrap_or_else(|_| dir.clone())
+        } else {
+            PathBuf::new()
+        };
+        let mut walker = WalkDir::new(dir)
+            .follow_links(walk.follow_symlinks)
+            .same_file_system(walk.one_file_system);
+        if let Some(depth) = walk.max_depth {
+            walker = walker.max_depth(depth);
+        }
+        let mut ignores = Ignores::new(&walk.ignore, dir);
+        let walker = walker
+            .into_iter()
+            .filter_entry(|entry| !is_excluded(dir, entry, walk) && !ignores.is_ignored(entry));
+        for entry in walker {
+            if is_cancelled(cancel) {
+                return;
+            }
+            match entry {
+                // Without following links, a link to a file is not the file.
+                Ok(entry)
+                    if entry.depth() > 0 && entry.path_is_symlink() && !walk.follow_symlinks =>
+                {
+                    debug!("Skipping {}: symbolic link", entry.path().display());
+                }
+                // The type read with the directory listing rules out
+                // directories without another system call.
+                Ok(entry) if entry.file_type().is_dir() => {}
+                Ok(entry)
+                    if !(is_included(dir, &entry, &walk.include)
+                        && is_of_type(entry.path(), &walk.file_types)) =>
+                {
+                    debug!("Skipping {}: not included", entry.path().display());
+                }
+                Ok(entry)
+                    if uses_regex
+                        && !walk.regex_allows(
+                            &absolute_root
+                                .join(entry.path().strip_prefix(dir).unwrap_or(entry.path())),
+                        ) =>
+                {
+                    debug!(
+                        "Skipping {}: filtered by regular expression",
+                        entry.path().display()
+                    );
+                }
+                // Links are only met here when followed, and then the
+                // metadata is that of their target.
+                Ok(entry) => match entry.metadata() {
+                    Ok(meta) if meta.is_file() => match file_id_of(entry.path(), &meta) {
+                        Ok(id) if walk.skip_files.contains(&id) => {
+                            debug!("Skipping {}: excluded output file", entry.path().display());
+                        }
+                        Ok(id) => {
+                            let file = FoundFile {
+                                path: entry.into_path(),
+                                meta,
+                            };
+                            on_file(file, id);
+                        }
+                        Err(e) => record_error(errors, entry.path(), e),
+                    },
+                    // Sockets, pipes and devices, or files removed since the
+                    // directory was listed.
+                    Ok(_) | Err(_) => {}
+                },
+                Err(e) if e.loop_ancestor().is_some() => {
+                    info!(
+                        "Skipping symlink loop at {}",
+                        e.path().unwrap_or(dir).display()
+                    );
+                }
+                Err(e) => {
+                    let path = e.path().unwrap_or(dir).to_path_buf();
+                    let error = e.into_io_error().unwrap_or_else(|| {
+                        io::Error::other("filesystem loop detected during traversal")
+                    });
+                    record_error(errors, &path, error);
+                }
+            }
+        }
+    }
+}
+
+/// A file found by [`collect_files`].
+#[derive(Debug)]
+struct FoundFile {
+    path: PathBuf,
+    /// The metadata of the file, read once during the walk.
+    meta: std::fs::Metadata,
+}
+
+impl AsRef<Path> for FoundFile {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// The files found by [`collect_files`], each underlying file once.
+struct Collected<T = FoundFile> {
+    files: Vec<T>,
+    /// The index in `files` of the path kept for each file.
+    seen: HashMap<FileId, usize>,
+}
+
+impl<T> Default for Collected<T> {
+    fn default() -> Self {
+        Self {
+            files: Vec::new(),
+            seen: HashMap::new(),
+        }
+    }
+}
+
+impl<T: AsRef<Path>> Collected<T> {
+    /// Adds `file` unless the file it leads to was already found, recording
+    /// it in `hard_links` if it is a hard link to the path that was kept.
+    fn add(&mut self, file: T, id: FileId, hard_links: &mut Vec<(PathBuf, PathBuf)>) {
+        let Some(&index) = self.seen.get(&id) else {
+            self.seen.insert(id, self.files.len());
+            self.files.push(file);
+            return;
+        };
+        let path = file.as_ref().to_path_buf();
+        let kept = self.files[index].as_ref();
+        if is_hard_link(kept, &path) {
+            debug!(
+                "Skipping {}: hard link to {}",
+                path.display(),
+                kept.display()
+            );
+            hard_links.push((path, kept.to_path_buf()));
+        } else {
+            debug!("Skipping {}: already seen via another path", path.display());
+        }
+    }
+}
+
+/// Identifies the file a path resolves to, so several paths to it can be recognised.
+#[cfg(unix)]
+type FileId = (u64, u64);
+
+/// Identifies the file a path resolves to, so several paths to it can be recognised.
+#[cfg(windows)]
+type FileId = (u32, u64);
+
+/// Identifies the file a path resolves to, so several paths to it can be recognised.
+#[cfg(not(any(unix, windows)))]
+type FileId = PathBuf;
+
+/// Returns the device and inode number of the file `path` points to.
+#[cfg(unix)]
+fn file_id(path: &Path) -> io::Result<FileId> {
+    file_id_of(path, &std::fs::metadata(path)?)
+}
+
+/// Returns the device and inode number of a file from its metadata.
+#[cfg(unix)]
+#[allow(clippy::unnecessary_wraps)]
+fn file_id_of(_path: &Path, meta: &std::fs::Metadata) -> io::Result<FileId> {
+    use std::os::unix::fs::MetadataExt;
+
+    Ok((meta.dev(), meta.ino()))
+}
+
+/// Returns the identity of the file `path` points to, which its metadata
+/// does not carry on this platform.
+#[cfg(not(unix))]
+fn file_id_of(path: &Path, _meta: &std::fs::Metadata) -> io::Result<FileId> {
+    file_id(path)
+}
+
+/// Returns the volume serial number and file index of the file `path`
+/// points to, which every hard link to it shares.
+#[cfg(windows)]
+fn file_id(path: &Path) -> io::Result<FileId> {
+    use std::ffi::c_void;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+
+    /// `BY_HANDLE_FILE_INFORMATION`, with each `FILETIME` as two words.
+    /// Only a few fields are read, but the system fills them all.
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct FileInformation {
+        file_attributes: u32,
+        creation_time: [u32; 2],
+        last_access_time: [u32; 2],
+        last_write_time: [u32; 2],
+        volume_serial_number: u32,
+        file_size_high: u32,
+        file_size_low: u32,
+        number_of_links: u32,
+        file_index_high: u32,
+        file_index_low: u32,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetFileInformationByHandle(file: *mut c_void, information: *mut FileInformation) -> i32;
+    }
+
+    // Lets directories be opened too; no access is needed to query the index.
+    const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+    let file = std::fs::OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)?;
+    let mut information = std::mem::MaybeUninit::<FileInformation>::uninit();
+    // SAFETY: the handle stays open for the call and `information` points to
+    // a buffer with the layout the function fills.
+    if unsafe { GetFileInformationByHandle(file.as_raw_handle(), information.as_mut_ptr()) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the call succeeded, so every field has been written.
+    let information = unsafe { information.assume_init() };
+    let index =
+        u64::from(information.file_index_high) << 32 | u64::from(information.file_index_low);
+    Ok((information.volume_serial_number, index))
+}
+
+/// Returns the canonical path of the file `path` points to.
+#[cfg(not(any(unix, windows)))]
+fn file_id(path: &Path) -> io::Result<FileId> {
+    std::fs::canonicalize(path)
+}
+
+/// Returns `true` if two paths to the same file are distinct hard links
+/// rather than one path reached twice, through a symlink or an overlapping
+/// directory.
+fn is_hard_link(kept: &Path, other: &Path) -> bool {
+    match (std::fs::canonicalize(kept), std::fs::canonicalize(other)) {
+        (Ok(kept), Ok(other)) => kept != other,
+        _ => false,
+    }
+}
+
+/// Returns `true` if a walked entry matches any exclude pattern, is a
+/// directory excluded by `walk.exclude_dirs`, or is hidden when hidden
+/// entries are skipped.
+///
+/// The scanned root itself is never excluded.
+fn is_excluded(root: &Path, entry: &walkdir::DirEntry, walk: &Walk) -> bool {
+    if entry.depth() == 0 {
+        return false;
+    }
+    if walk.skip_hidden && is_hidden(entry) {
+        return true;
+    }
+    let Ok(rel) = entry.path().strip_prefix(root) else {
+        return false;
+    };
+    if entry.file_type().is_dir() {
+        walk.exclude.is_dir_match(rel) || walk.exclude_dirs.is_match(rel, entry)
+    } else {
+        walk.exclude.is_match(rel)
+    }
+}
+
+/// Returns `true` if a walked entry's name starts with a dot.
+#[cfg(not(windows))]
+fn is_hidden(entry: &walkdir::DirEntry) -> bool {
+    entry.file_name().as_encoded_bytes().starts_with(b".")
+}
+
+/// Returns `true` if a walked entry's name starts with a dot, or it has the
+/// hidden or system attribute.
+#[cfg(windows)]
+fn is_hidden(entry: &walkdir::DirEntry) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    const FILE_ATTRIBUTE_SYSTEM: u32 = 0x4;
+    entry.file_name().as_encoded_bytes().starts_with(b".")
+        || entry.metadata().is_ok_and(|meta| {
+            meta.file_attributes() & (FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM) != 0
+        })
+}
+
+/// Returns `true` if a walked file matches an include pattern, or if there
+/// are none.
+fn is_included(root: &Path, entry: &walkdir::DirEntry, include: &GlobSet) -> bool {
+    if include.is_empty() {
+        return true;
+    }
+    let rel = entry.path().strip_prefix(root).unwrap_or(entry.path());
+    include.is_match(rel)
+}
+
+/// Returns `true` if no file types are selected or `path` is of one of them.
+fn is_of_type(path: &Path, file_types: &[FileType]) -> bool {
+    file_types.is_empty() || file_types.iter().any(|t| t.matches(path))
+}
+
+/// Groups files by size and returns a map keyed by file length.
+///
+/// Files outside the inclusive `min_size..=max_size` range are dropped here so
+/// they are never hashed, as are files whose size no other file shares.
+///
+/// The sizes come from the metadata read during the walk, so no file is
+/// examined again.
+///
+/// # Arguments
+/// * `files` - The size and path of every file found.
+/// * `console` - Progress output shared across stages.
+/// * `min_size` - Smallest size in bytes to keep, if any.
+/// * `max_size` - Largest size in bytes to keep, if any.
+///
+/// # Returns
+/// A map from file size to the two or more files with that length.
+fn group_by_size(
+    files: Vec<(u64, PathBuf)>,
+    console: &Console,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+) -> HashMap<u64, Vec<PathBuf>> {
+    let progress = console.bar(files.len() as u64, Stage::Size, "Indexing files by size...");
 
     let mut size_map: HashMap<u64, Vec<PathBuf>> = HashMap::new();
-    for (size, path) in size_entries {
-        size_map.entry(size).or_default().push(path);
+    for (size, path) in files {
+        progress.inc(1);
+        if min_size.is_none_or(|min| size >= min) && max_size.is_none_or(|max| size <= max) {
+            size_map.entry(size).or_default().push(path);
+        }
     }
 
     progress.finish_with_message("File sizes indexed.");
-    info!("{} file sizes identified.", size_map.len());
-    println!("{} file sizes identified.", size_map.len());
+    size_map.retain(|_, paths| paths.len() > 1);
+    console.status(&format!(
+        "{} file sizes shared by more than one file.",
+        size_map.len()
+    ));
     size_map
 }
 
-/// Filters size groups using a quick hash of the first 8 KiB of each file.
+/// Filters size groups using a quick hash of a few blocks of each file.
 ///
 /// # Arguments
 /// * `size_map` - Files grouped by size from [`group_by_size`].
-/// * `style` - Progress bar style shared across stages.
+/// * `console` - Progress output shared across stages.
+/// * `config` - Supplies how many bytes of which blocks are sampled, how
+///   reads are scheduled and the cancellation flag, which stops hashing
+///   further files once set.
+/// * `errors` - Collects files that could not be read.
 ///
 /// # Returns
-/// A map from quick hash to files sharing that hash.
+/// A map from file size and quick hash to files sharing both.
 fn group_by_quick_hash(
     size_map: HashMap<u64, Vec<PathBuf>>,
-    style: &ProgressStyle,
-) -> HashMap<u64, Vec<PathBuf>> {
-    let progress = ProgressBar::new(size_map.len() as u64);
-    progress.set_style(style.clone());
-    progress.set_message("Computing quick hashes...");
+    console: &Console,
+    config: &ScanConfig,
+    errors: &Mutex<Vec<(PathBuf, io::Error)>>,
+) -> HashMap<(u64, u64), Vec<PathBuf>> {
+    let progress = console.bar(
+        size_map.len() as u64,
+        Stage::QuickHash,
+        "Computing quick hashes...",
+    );
 
-    let potential_dupes: HashMap<u64, Vec<PathBuf>> = size_map
+    let potential_dupes: HashMap<(u64, u64), Vec<PathBuf>> = size_map
         .into_par_iter()
-        .filter(|(_, files)| files.len() > 1)
-        .flat_map_iter(|(_, files)| {
+        .flat_map_iter(|(size, files)| {
             let mut quick_hash_map: HashMap<u64, Vec<PathBuf>> = HashMap::new();
-            for file in files {
-                if let Some(qh) = quick_hash(&file) {
-                    quick_hash_map.entry(qh).or_default().push(file);
+            let hashed = quick_hash_group(files, size, config);
+            for (file, hash) in hashed {
+                match hash {
+                    Ok(qh) => quick_hash_map.entry(qh).or_default().push(file),
+                    Err(e) => record_error(errors, &file, e),
                 }
             }
             progress.inc(1);
             quick_hash_map
                 .into_iter()
                 .filter(|(_, group)| group.len() > 1)
+                .map(|(qh, group)| ((size, qh), group))
                 .collect::<Vec<_>>()
         })
         .collect();
 
     progress.finish_with_message("Quick hashes complete.");
-    info!("{} unique quick hashes identified.", potential_dupes.len());
-    println!("{} unique quick hashes identified.", potential_dupes.len());
+    console.status(&format!(
+        "{} unique quick hashes identified.",
+        potential_dupes.len()
+    ));
     potential_dupes
 }
 
-/// Performs full SHA‑256 hashing on candidates and groups confirmed duplicates.
+/// Quick-hashes the files of one size group, stopping early once the scan
+/// is cancelled. With the `io-uring` feature the reads of the whole group are
+/// batched.
+fn quick_hash_group(
+    files: Vec<PathBuf>,
+    size: u64,
+    config: &ScanConfig,
+) -> Vec<(PathBuf, io::Result<u64>)> {
+    let (sample_bytes, sample) = (config.quick_hash_bytes, config.quick_hash_sample);
+    let sampled: u64 = quick_hash_ranges(size, sample_bytes, sample)
+        .iter()
+        .map(|&(_, bytes)| bytes)
+        .sum();
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    if let Some(first) = files.first().filter(|_| !is_cancelled(config.cancel)) {
+        let batch = || quick_hashes_batched(&files, size, sample_bytes, sample);
+        if let Some(hashes) = config.read(first, batch) {
+            config.pace(sampled * files.len() as u64);
+            return files.into_iter().zip(hashes).collect();
+        }
+    }
+
+    let mut hashed = Vec::with_capacity(files.len());
+    for file in files {
+        if is_cancelled(config.cancel) {
+            break;
+        }
+        let hash = config.read(&file, || quick_hash(&file, sample_bytes, sample));
+        config.pace(sampled);
+        hashed.push((file, hash));
+    }
+    hashed
+}
+
+/// Performs full-content hashing on candidates and groups confirmed duplicates.
+///
+/// Groups are hashed in parallel, but each confirmed group is sent back and
+/// handed to `on_group` on the calling thread, so the callback needs to be
+/// neither `Send` nor `Sync`.
+///
+/// A group of exactly two files no larger than [`BYTE_COMPARE_MAX_BYTES`] is
+/// confirmed by reading both and comparing their contents, which is cheaper
+/// than hashing them and exact by construction. With `config.compare_pairs`,
+/// larger pairs are compared too, reading them side by side so that files
+/// that differ are only read up to their first difference. Either way, the
+/// contents of an identical pair are hashed as they are read, so its group
+/// carries the same full hash as a hashed one.
 ///
 /// # Arguments
 /// * `potential_dupes` - Files that matched in [`group_by_quick_hash`].
-/// * `style` - Progress bar style shared across stages.
-///
-/// # Returns
-/// A map from SHA-256 hash to all files with identical content.
+/// * `console` - Progress output shared across stages.
+/// * `config` - Supplies the hash algorithm, the optional hash cache, whether
+///   hashed groups are verified byte for byte, and the cancellation flag. Once
+///   cancelled, no further files are hashed but groups already confirmed are
+///   still delivered.
+/// * `pool` - Thread pool to hash in, or `None` for the global pool.
+/// * `errors` - Collects files that could not be read.
+/// * `on_group` - Receives every group of files with identical content.
 fn group_by_full_hash(
-    potential_dupes: HashMap<u64, Vec<PathBuf>>,
-    style: &ProgressStyle,
-) -> HashMap<String, Vec<PathBuf>> {
-    let total_files = potential_dupes.values().map(Vec::len).sum::<usize>() as u64;
-    let progress = ProgressBar::new(total_files);
-    progress.set_style(style.clone());
-    progress.set_message("Computing full hashes...");
-
-    let duplicates: HashMap<String, Vec<PathBuf>> = potential_dupes
-        .into_par_iter()
-        .flat_map_iter(|(_qh, files)| {
-            let mut hash_map: HashMap<String, Vec<PathBuf>> = HashMap::new();
-            for file in files {
-                if let Some(fh) = full_hash(&file) {
-                    hash_map.entry(fh).or_default().push(file);
-                }
-                progress.inc(1);
-            }
-            hash_map
-                .into_iter()
-                .filter(|(_, g)| g.len() > 1)
-                .collect::<Vec<_>>()
+    potential_dupes: HashMap<(u64, u64), Vec<PathBuf>>,
+    console: &Console,
+    config: &ScanConfig,
+    pool: Option<&ThreadPool>,
+    errors: &Mutex<Vec<(PathBuf, io::Error)>>,
+    on_group: &mut dyn FnMut(DuplicateGroup),
+) {
+    let hash_file = |file: &Path, on_read: &dyn Fn(u64)| {
+        config.read(file, || match config.cache {
+            Some(cache) => cache.full_hash(file, config.hash_algo, on_read),
+            None => full_hash_with(file, config.hash_algo, on_read),
         })
-        .collect();
+    };
+    // Counting bytes rather than files keeps the bar moving, and the time
+    // left meaningful, when a few large files make up most of the work.
+    let total_bytes = potential_dupes
+        .iter()
+        .map(|((size, _), files)| size.saturating_mul(files.len() as u64))
+        .fold(0, u64::saturating_add);
+    let progress = console.byte_bar(total_bytes, Stage::FullHash, "Computing full hashes...");
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::scope(|scope| {
+        let progress = &progress;
+        scope.spawn(move || {
+            in_pool(pool, || {
+                potential_dupes.into_par_iter().for_each_with(
+                    sender,
+                    |sender, ((size, _), files)| {
+                        let compared = size <= BYTE_COMPARE_MAX_BYTES || config.compare_pairs;
+                        if compared && files.len() == 2 {
+                            if is_cancelled(config.cancel) {
+                                return;
+                            }
+                            let hash = config.read(&files[0], || {
+                                pair_hash(&files, size, config, progress, errors)
+                            });
+                            if let Some(hash) = hash {
+                                let _ = sender.send(DuplicateGroup {
+                                    hash,
+                                    size,
+                                    paths: files,
+                                });
+                            }
+                            return;
+                        }
+
+                        let mut hash_map: HashMap<String, Vec<PathBuf>> = HashMap::new();
+                        for (file, hash) in
+                            full_hash_group(files, size, config, &hash_file, progress)
+                        {
+                            match hash {
+                                Ok(fh) => hash_map.entry(fh).or_default().push(file),
+                                Err(e) => record_error(errors, &file, e),
+                            }
+                        }
+                        for (hash, paths) in hash_map.into_iter().filter(|(_, g)| g.len() > 1) {
+                            let sets = if config.paranoid && !is_cancelled(config.cancel) {
+                                split_by_contents(&hash, paths, &|bytes| config.pace(bytes), errors)
+                            } else {
+                                vec![paths]
+                            };
+                            for paths in sets {
+                                // The receiver outlives every sender, so sending cannot fail.
+                                let _ = sender.send(DuplicateGroup {
+                                    hash: hash.clone(),
+                                    size,
+                                    paths,
+                                });
+                            }
+                        }
+                    },
+                );
+            });
+        });
+
+        for group in receiver {
+            on_group(group);
+        }
+    });
 
     progress.finish_with_message("Full hashes computed.");
-    duplicates
+}
+
+/// Compares the two files of a pair of `size`-byte files, returning the
+/// full hash of their contents if they are identical and advancing
+/// `progress` by both files.
+///
+/// Small files are read whole; larger ones side by side, up to their first
+/// difference. A file that cannot be read is recorded in `errors` and the
+/// pair is treated as different.
+fn pair_hash(
+    files: &[PathBuf],
+    size: u64,
+    config: &ScanConfig,
+    progress: &Progress,
+    errors: &Mutex<Vec<(PathBuf, io::Error)>>,
+) -> Option<String> {
+    let read = ReadProgress::new(progress, 2 * size);
+    let mut hasher = config.hash_algo.hasher();
+    let identical = if size <= BYTE_COMPARE_MAX_BYTES {
+        let contents = identical_contents(&files[0], &files[1], errors);
+        config.pace(2 * size);
+        contents.map(|bytes| hasher.update(&bytes)).is_some()
+    } else {
+        let on_read = |bytes| {
+            config.pace(bytes);
+            read.advance(bytes);
+        };
+        contents_equal(&files[0], &files[1], &on_read, &mut |bytes| {
+            hasher.update(bytes);
+        })
+        .unwrap_or_else(|(path, e)| {
+            record_error(errors, &path, e);
+            false
+        })
+    };
+    read.finish();
+    identical.then(|| hasher.finish_hex())
+}
+
+/// Reads two files in full, returning their contents if they are identical.
+///
+/// A file that cannot be read is recorded in `errors` and the pair is treated
+/// as different.
+fn identical_contents(
+    first: &Path,
+    second: &Path,
+    errors: &Mutex<Vec<(PathBuf, io::Error)>>,
+) -> Option<Vec<u8>> {
+    let read = |path: &Path| {
+        std::fs::read(path)
+            .map_err(|e| record_error(errors, path, e))
+            .ok()
+    };
+    match (read(first), read(second)) {
+        (Some(a), Some(b)) if a == b => Some(a),
+        _ => None,
+    }
+}
+
+/// Hashes a file, passing the number of bytes read to the callback as it goes.
+type HashFile<'a> = dyn Fn(&Path, &dyn Fn(u64)) -> io::Result<String> + 'a;
+
+/// Full-hashes the files of one quick-hash group with `hash_file`, stopping
+/// early once the scan is cancelled. With the `io-uring` feature, groups of
+/// small files are read in batches instead unless a hash cache is in use.
+///
+/// `progress` advances by `size` for each file, in steps of
+/// [`PROGRESS_STEP_BYTES`] while it is read.
+fn full_hash_group(
+    files: Vec<PathBuf>,
+    size: u64,
+    config: &ScanConfig,
+    hash_file: &HashFile,
+    progress: &Progress,
+) -> Vec<(PathBuf, io::Result<String>)> {
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    if let Some(first) = files.first().filter(|_| {
+        config.cache.is_none() && size <= URING_FULL_HASH_MAX_BYTES && !is_cancelled(config.cancel)
+    }) {
+        let batch = || full_hashes_batched(&files, size, config.hash_algo);
+        if let Some(hashes) = config.read(first, batch) {
+            config.pace(size * files.len() as u64);
+            progress.inc(size * files.len() as u64);
+            return files.into_iter().zip(hashes).collect();
+        }
+    }
+    #[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+    let _ = size;
+
+    let mut hashed = Vec::with_capacity(files.len());
+    for file in files {
+        if is_cancelled(config.cancel) {
+            break;
+        }
+        let read = ReadProgress::new(progress, size);
+        let on_read = |bytes| {
+            config.pace(bytes);
+            read.advance(bytes);
+        };
+        let hash = hash_file(&file, &on_read);
+        read.finish();
+        hashed.push((file, hash));
+    }
+    hashed
+}
+
+/// Advances the full-hash progress as the files making up `share` bytes of
+/// it are read, in steps of [`PROGRESS_STEP_BYTES`].
+struct ReadProgress<'p, 'a> {
+    progress: &'p Progress<'a>,
+    share: u64,
+    /// Bytes read but not reported yet.
+    pending: Cell<u64>,
+    /// Bytes reported so far.
+    reported: Cell<u64>,
+}
+
+impl<'p, 'a> ReadProgress<'p, 'a> {
+    fn new(progress: &'p Progress<'a>, share: u64) -> Self {
+        Self {
+            progress,
+            share,
+            pending: Cell::new(0),
+            reported: Cell::new(0),
+        }
+    }
+
+    fn advance(&self, bytes: u64) {
+        self.pending.set(self.pending.get() + bytes);
+        if self.pending.get() >= PROGRESS_STEP_BYTES {
+            // A file that grew since it was sized must not overshoot the total.
+            let step = self
+                .pending
+                .replace(0)
+                .min(self.share - self.reported.get());
+            self.reported.set(self.reported.get() + step);
+            self.progress.inc(step);
+        }
+    }
+
+    /// Reports the rest of the share, as cached, unreadable and shrunken
+    /// files and files compared up to a difference count in full too.
+    fn finish(&self) {
+        if self.reported.get() < self.share {
+            self.progress.inc(self.share - self.reported.get());
+        }
+    }
+}
+
+/// Splits files sharing the full hash `hash` into sets of at least two whose
+/// contents are identical byte for byte, for scans that do not trust hash
+/// equality alone.
+///
+/// Files that cannot be read are recorded in `errors` and left out. The
+/// number of bytes read is passed to `on_read` as they are compared.
+fn split_by_contents(
+    hash: &str,
+    files: Vec<PathBuf>,
+    on_read: &dyn Fn(u64),
+    errors: &Mutex<Vec<(PathBuf, io::Error)>>,
+) -> Vec<Vec<PathBuf>> {
+    let mut sets = Vec::new();
+    let mut differ = false;
+    let mut pending = files;
+    while pending.len() > 1 {
+        let mut candidates = pending.into_iter();
+        let mut same: Vec<PathBuf> = candidates.next().into_iter().collect();
+        pending = Vec::new();
+        for file in candidates.by_ref() {
+            match contents_equal(&same[0], &file, on_read, &mut |_| {}) {
+                Ok(true) => same.push(file),
+                Ok(false) => {
+                    differ = true;
+                    pending.push(file);
+                }
+                Err((failed, e)) => {
+                    let reference_failed = failed == same[0];
+                    record_error(errors, &failed, e);
+                    if reference_failed {
+                        // Compare the rest again against a file that can be read.
+                        pending.extend(same.drain(1..));
+                        same.clear();
+                        pending.push(file);
+                        break;
+                    }
+                }
+            }
+        }
+        pending.extend(candidates);
+        if same.len() > 1 {
+            sets.push(same);
+        }
+    }
+    if differ {
+        warn!("Files with full hash {hash} differ when compared byte for byte");
+    }
+    sets
+}
+
+/// Reads two files side by side and reports whether their contents are
+/// identical, stopping at the first difference.
+///
+/// An error is returned along with the path of the file that failed. The
+/// number of bytes read is passed to `on_read` as they are compared, and the
+/// bytes of `first` to `on_same` once they have matched.
+fn contents_equal(
+    first: &Path,
+    second: &Path,
+    on_read: &dyn Fn(u64),
+    on_same: &mut dyn FnMut(&[u8]),
+) -> Result<bool, (PathBuf, io::Error)> {
+    let open = |path: &Path| File::open(path).map_err(|e| (path.to_path_buf(), e));
+    let mut readers = [(first, open(first)?), (second, open(second)?)];
+    let mut buffers = [
+        vec![0; FULL_HASH_BUFFER_SIZE],
+        vec![0; FULL_HASH_BUFFER_SIZE],
+    ];
+    loop {
+        let mut lens = [0; 2];
+        for ((path, file), (buffer, len)) in readers
+            .iter_mut()
+            .zip(buffers.iter_mut().zip(lens.iter_mut()))
+        {
+            *len = read_full(file, buffer).map_err(|e| (path.to_path_buf(), e))?;
+        }
+        on_read((lens[0] + lens[1]) as u64);
+        if buffers[0][..lens[0]] != buffers[1][..lens[1]] {
+            return Ok(false);
+        }
+        on_same(&buffers[0][..lens[0]]);
+        if lens[0] == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Fills `buffer` from `reader`, returning fewer bytes only at the end of the
+/// input.
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
 }
 
 /// Writes a report of duplicate files to a specified output file, including metadata such as
 /// the user who generated the report, the start and end time, and the base directory scanned.
 ///
-/// The duplicate file entries are sorted in descending order by file size. Each group of duplicates
-/// is listed with its size followed by the full paths to the duplicate files.
+/// The size of each group is read from its first file. Use
+/// [`write_output_report`] to write a [`DuplicateReport`], which already
+/// holds the sizes, or to change the layout.
 ///
 /// # Arguments
 ///
-/// * `duplicates` - A map where each key is a SHA-256 hash and the value is a list of file paths
-///   that share that hash (i.e., files with the same content).
+/// * `duplicates` - A hashmap where the key is a file hash and the value is a vector of file paths
+///   that share that hash.
 /// * `output_file` - The path to the output file where the report should be written.
 /// * `start_time` - A string representing the start time of the operation (usually formatted as `YYYYMMDD HH:MM:SS`).
 /// * `base_dirs` - The directory or directories searched for duplicates. Each will be
@@ -303,7 +2990,7 @@ fn group_by_full_hash(
 /// ```
 /// use std::collections::HashMap;
 /// use std::path::PathBuf;
-/// use duplicate_file_finder::{write_output};
+/// use duplicate_file_finder::write_output;
 /// fn example_usage() -> Result<(), Box<dyn std::error::Error>> {
 ///     let mut duplicates = HashMap::new();
 ///     duplicates.insert(
@@ -315,32 +3002,146 @@ fn group_by_full_hash(
 ///     let output_file = "duplicates.txt";
 ///     let base_dirs = &[PathBuf::from("/tmp")];
 ///
-///     write_output(duplicates, output_file, start_time, base_dirs)?;
-///     Ok(())
-/// }
+///     write_output(duplicates, output_file, start_time, base_dirs)?;
+///     Ok(())
+/// }
+/// ```
+///
+pub fn write_output<S: ::std::hash::BuildHasher>(
+    duplicates: HashMap<String, Vec<PathBuf>, S>,
+    output_file: impl AsRef<Path>,
+    start_time: &str,
+    base_dirs: &[PathBuf],
+) -> Result<(), Box<dyn Error>> {
+    let groups = duplicates
+        .into_iter()
+        .map(|(hash, paths)| DuplicateGroup {
+            size: paths
+                .first()
+                .and_then(|path| std::fs::metadata(path).ok())
+                .map_or(0, |meta| meta.len()),
+            hash,
+            paths,
+        })
+        .collect();
+    write_output_report(
+        &DuplicateReport::from_groups(groups),
+        output_file,
+        start_time,
+        base_dirs,
+        &OutputOptions::default(),
+    )
+}
+
+/// Writes `report` to the file at `output_file`, laid out as `options`
+/// says.
+///
+/// This opens `output_file` and delegates the formatting to [`write_output_to`].
+///
+/// # Arguments
+///
+/// * `report` - The duplicate groups to write, as returned by [`find_duplicates_with_options`].
+/// * `output_file` - The path to the output file where the report should be written. Any
+///   path is accepted, including ones that are not valid UTF-8.
+/// * `start_time` - A string representing the start time of the operation (usually formatted as `YYYYMMDD HH:MM:SS`).
+/// * `base_dirs` - The directory or directories searched for duplicates. Each will be
+///   listed in the report header.
+/// * `options` - How to lay out the report; see [`OutputOptions`].
+///
+/// # Errors
+///
+/// This function will return an error if the output file cannot be created, or if any I/O operation
+/// (e.g., writing to the file) fails.
+///
+/// # Example
+///
+/// ```
+/// use std::path::PathBuf;
+/// use duplicate_file_finder::{write_output_report, DuplicateGroup, DuplicateReport, OutputOptions};
+/// fn example_usage() -> Result<(), Box<dyn std::error::Error>> {
+///     let report = DuplicateReport::from_groups(vec![DuplicateGroup {
+///         hash: String::from("somehash"),
+///         size: 1024,
+///         paths: vec![PathBuf::from("/tmp/file1.txt"), PathBuf::from("/tmp/file2.txt")],
+///     }]);
+///
+///     let base_dirs = &[PathBuf::from("/tmp")];
+///     let options = OutputOptions::new().verbose(true);
+///     write_output_report(&report, "duplicates.txt", "20250707 15:00:00", base_dirs, &options)?;
+///     Ok(())
+/// }
+/// ```
+pub fn write_output_report(
+    report: &DuplicateReport,
+    output_file: impl AsRef<Path>,
+    start_time: &str,
+    base_dirs: &[PathBuf],
+    options: &OutputOptions,
+) -> Result<(), Box<dyn Error>> {
+    let output_file = output_file.as_ref();
+    let file = File::create(output_file)?;
+    let mut writer = BufWriter::new(file);
+    write_output_to(&mut writer, report, start_time, base_dirs, options)?;
+    writer.flush()?;
+
+    info!("Duplicate files saved to {}", output_file.display());
+    Ok(())
+}
+
+/// Writes a report of duplicate files to any [`Write`] target, such as stdout or an
+/// in-memory buffer.
+///
+/// The header lists the user who generated the report, the start and end time, and the
+/// base directories scanned. The duplicate file entries are listed in the report's order,
+/// which is descending by file size. Each group of duplicates is listed with its size
+/// followed by the full paths to the duplicate files. Files that could not be read
+/// are listed last under `Skipped Files:`, each with the error that stopped it.
+///
+/// In verbose mode each path is followed by an indented line with the file's
+/// modification time, size and read-only state, taken from
+/// [`DuplicateReport::metadata`]. Files without recorded metadata show
+/// `<metadata unavailable>` instead.
+///
+/// # Arguments
+///
+/// * `writer` - Destination for the report text.
+/// * `report` - The duplicate groups to write, as returned by [`find_duplicates_with_options`].
+/// * `start_time` - A string representing the start time of the operation.
+/// * `base_dirs` - The directory or directories searched for duplicates.
+/// * `options` - How to lay out the report; see [`OutputOptions`].
+///
+/// # Errors
+///
+/// Returns any I/O error raised by `writer`.
+///
+/// # Example
+///
 /// ```
+/// use std::path::PathBuf;
+/// use duplicate_file_finder::{write_output_to, DuplicateGroup, DuplicateReport, OutputOptions};
 ///
-pub fn write_output<S: ::std::hash::BuildHasher>(
-    duplicates: HashMap<String, Vec<PathBuf>, S>,
-    output_file: &str,
+/// let report = DuplicateReport::from_groups(vec![DuplicateGroup {
+///     hash: String::from("somehash"),
+///     size: 1024,
+///     paths: vec![PathBuf::from("/tmp/file1.txt"), PathBuf::from("/tmp/file2.txt")],
+/// }]);
+/// let mut buffer = Vec::new();
+/// let base_dirs = [PathBuf::from("/tmp")];
+/// write_output_to(&mut buffer, &report, "20250707 15:00:00", &base_dirs, &OutputOptions::new())
+///     .unwrap();
+/// let text = String::from_utf8(buffer).unwrap();
+/// assert!(text.contains("Size: 1.00 KB"));
+/// ```
+pub fn write_output_to<W: Write>(
+    writer: &mut W,
+    report: &DuplicateReport,
     start_time: &str,
     base_dirs: &[PathBuf],
-) -> Result<(), Box<dyn Error>> {
-    let mut entries: Vec<(u64, Vec<PathBuf>)> = duplicates
-        .into_values()
-        .map(|paths| {
-            let size = fs::metadata(&paths[0]).map(|m| m.len()).unwrap_or(0);
-            (size, paths)
-        })
-        .collect();
-    entries.sort_by(|a, b| b.0.cmp(&a.0)); // Sort by file size descending
-
+    options: &OutputOptions,
+) -> io::Result<()> {
     let username = whoami::username();
     let end_time = Local::now().format("%Y%m%d %H:%M:%S").to_string();
 
-    let file = File::create(output_file)?;
-    let mut writer = BufWriter::new(file);
-
     // Write header
     writeln!(writer, "Duplicate File Finder Report")?;
     writeln!(writer, "Generated by: {username}")?;
@@ -356,32 +3157,248 @@ pub fn write_output<S: ::std::hash::BuildHasher>(
     }
     writeln!(writer)?;
 
-    // Calculate potential space savings
-    let total_savings: u64 = entries
-        .iter()
-        .map(|(size, paths)| size * (paths.len().saturating_sub(1) as u64))
-        .sum();
-
+    if report.interrupted {
+        writeln!(
+            writer,
+            "Scan Interrupted: the results below are incomplete."
+        )?;
+    }
     writeln!(
         writer,
         "Total Potential Space Savings: {}",
-        format_size(total_savings)
+        format_size(report.total_reclaimable_bytes)
     )?;
+    if !report.errors.is_empty() {
+        writeln!(
+            writer,
+            "Files Skipped Due To Errors: {}",
+            report.errors.len()
+        )?;
+    }
+    if !report.hard_links.is_empty() {
+        writeln!(
+            writer,
+            "Hard Links Already Deduplicated: {}",
+            report.hard_links.len()
+        )?;
+    }
     writeln!(writer)?;
 
-    // Write duplicate files
-    for (size, paths) in entries {
-        writeln!(writer, "Size: {}", format_size(size))?;
-        for path in paths {
+    // Groups are already in report order: size descending, then first path.
+    for group in &report.groups {
+        writeln!(writer, "Size: {}", format_size(group.size))?;
+        for path in &group.paths {
             writeln!(writer, "{}", path.display())?;
+            if options.verbose {
+                write_metadata_line(writer, report.metadata.get(path), group.size)?;
+            }
+        }
+        writeln!(writer)?;
+    }
+
+    if !report.errors.is_empty() {
+        writeln!(writer, "Skipped Files:")?;
+        for (path, error) in &report.errors {
+            writeln!(writer, "{}: {error}", path.display())?;
+        }
+        writeln!(writer)?;
+    }
+    if !report.hard_links.is_empty() {
+        writeln!(writer, "Hard Links Already Sharing Storage:")?;
+        for (path, kept) in &report.hard_links {
+            writeln!(writer, "{} => {}", path.display(), kept.display())?;
         }
         writeln!(writer)?;
     }
+    Ok(())
+}
+
+/// Writes the indented metadata line shown under a path in verbose reports.
+fn write_metadata_line<W: Write>(
+    writer: &mut W,
+    metadata: Option<&FileMetadata>,
+    size: u64,
+) -> io::Result<()> {
+    let Some(metadata) = metadata else {
+        return writeln!(writer, "    <metadata unavailable>");
+    };
+    let modified = metadata.modified.map_or_else(
+        || "unknown".to_string(),
+        |time| {
+            DateTime::<Local>::from(time)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        },
+    );
+    let readonly = if metadata.readonly { "yes" } else { "no" };
+    writeln!(
+        writer,
+        "    Modified: {modified} | Size: {} | Read-only: {readonly}",
+        format_size(size)
+    )
+}
+
+/// Number of groups listed by [`write_summary_to`].
+const SUMMARY_TOP_GROUPS: usize = 10;
+
+/// Writes only the aggregate figures of a report, without listing every file.
+///
+/// The summary gives the number of duplicate groups, the number of redundant
+/// files and the space that could be reclaimed, followed by the ten groups
+/// that waste the most space. Each of those is shown with its first path, so
+/// a quick look tells whether a full cleanup is worth it.
+///
+/// # Errors
+///
+/// Returns any I/O error raised by `writer`.
+///
+/// # Example
+///
+/// ```
+/// use std::path::PathBuf;
+/// use duplicate_file_finder::{write_summary_to, DuplicateGroup, DuplicateReport};
+///
+/// let report = DuplicateReport::from_groups(vec![DuplicateGroup {
+///     hash: String::from("somehash"),
+///     size: 1024,
+///     paths: vec![PathBuf::from("/tmp/a.txt"), PathBuf::from("/tmp/b.txt")],
+/// }]);
+/// let mut buffer = Vec::new();
+/// write_summary_to(&mut buffer, &report).unwrap();
+/// let text = String::from_utf8(buffer).unwrap();
+/// assert!(text.contains("Redundant Files: 1\n"));
+/// assert!(text.contains("1. 1.00 KB in 2 files of 1.00 KB: /tmp/a.txt\n"));
+/// ```
+pub fn write_summary_to<W: Write>(writer: &mut W, report: &DuplicateReport) -> io::Result<()> {
+    writeln!(writer, "Duplicate File Finder Summary")?;
+    if report.interrupted {
+        writeln!(
+            writer,
+            "Scan Interrupted: the figures below are incomplete."
+        )?;
+    }
+    writeln!(writer, "Duplicate Groups: {}", report.total_groups)?;
+    writeln!(writer, "Redundant Files: {}", report.redundant_files())?;
+    writeln!(
+        writer,
+        "Total Potential Space Savings: {}",
+        format_size(report.total_reclaimable_bytes)
+    )?;
+    if !report.errors.is_empty() {
+        writeln!(
+            writer,
+            "Files Skipped Due To Errors: {}",
+            report.errors.len()
+        )?;
+    }
+    if !report.hard_links.is_empty() {
+        writeln!(
+            writer,
+            "Hard Links Already Deduplicated: {}",
+            report.hard_links.len()
+        )?;
+    }
+    writeln!(writer)?;
+
+    if report.is_empty() {
+        return Ok(());
+    }
+    // Stable, so groups wasting the same space stay in report order.
+    let mut largest: Vec<&DuplicateGroup> = report.groups.iter().collect();
+    largest.sort_by_key(|group| std::cmp::Reverse(group.reclaimable_bytes()));
+    writeln!(writer, "Largest Groups By Wasted Space:")?;
+    for (index, group) in largest.into_iter().take(SUMMARY_TOP_GROUPS).enumerate() {
+        writeln!(
+            writer,
+            "{}. {} in {} files of {}: {}",
+            index + 1,
+            format_size(group.reclaimable_bytes()),
+            group.paths.len(),
+            format_size(group.size),
+            group
+                .paths
+                .first()
+                .map_or_else(String::new, |p| p.display().to_string())
+        )?;
+    }
+    writeln!(writer)
+}
+
+/// Writes a report as CSV with one row per file, for use in spreadsheets.
+///
+/// The columns are `group_id,hash,size_bytes,path,mtime`. Every file in the
+/// same duplicate set shares a `group_id`, numbered from 1. `mtime` is the
+/// local modification time as `YYYY-MM-DD HH:MM:SS`, which spreadsheets read
+/// as a date, and is empty when it was not captured. Groups are ordered by
+/// descending size (ties broken by first path, then hash) and the paths
+/// within a group are sorted, so the same scan always produces the same file. Fields containing
+/// commas, quotes or line breaks are quoted as described in RFC 4180.
+///
+/// # Errors
+///
+/// Returns any I/O error raised by `writer`.
+///
+/// # Example
+///
+/// ```
+/// use std::path::PathBuf;
+/// use duplicate_file_finder::{write_csv_to, DuplicateGroup, DuplicateReport};
+///
+/// let report = DuplicateReport::from_groups(vec![DuplicateGroup {
+///     hash: String::from("somehash"),
+///     size: 1024,
+///     paths: vec![PathBuf::from("/tmp/b.txt"), PathBuf::from("/tmp/a,1.txt")],
+/// }]);
+/// let mut buffer = Vec::new();
+/// write_csv_to(&mut buffer, &report).unwrap();
+/// assert_eq!(
+///     String::from_utf8(buffer).unwrap(),
+///     "group_id,hash,size_bytes,path,mtime\n\
+///      1,somehash,1024,\"/tmp/a,1.txt\",\n\
+///      1,somehash,1024,/tmp/b.txt,\n"
+/// );
+/// ```
+pub fn write_csv_to<W: Write>(writer: &mut W, report: &DuplicateReport) -> io::Result<()> {
+    let mut groups: Vec<&DuplicateGroup> = report.groups.iter().collect();
+    groups.sort_by(|a, b| report_order(a, b));
 
-    info!("Duplicate files saved to {output_file}");
+    writeln!(writer, "group_id,hash,size_bytes,path,mtime")?;
+    for (index, group) in groups.into_iter().enumerate() {
+        let mut paths: Vec<&PathBuf> = group.paths.iter().collect();
+        paths.sort();
+        for path in paths {
+            let mtime = report
+                .metadata
+                .get(path)
+                .and_then(|metadata| metadata.modified)
+                .map(|time| {
+                    DateTime::<Local>::from(time)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string()
+                })
+                .unwrap_or_default();
+            writeln!(
+                writer,
+                "{},{},{},{},{mtime}",
+                index + 1,
+                csv_field(&group.hash),
+                group.size,
+                csv_field(&path.to_string_lossy())
+            )?;
+        }
+    }
     Ok(())
 }
 
+/// Quotes a CSV field if it contains a delimiter, quote or line break.
+fn csv_field(value: &str) -> std::borrow::Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\"")).into()
+    } else {
+        value.into()
+    }
+}
+
 /// Converts a file size in bytes to a human-readable string (e.g., "1.43 MB").
 ///
 /// # Arguments
@@ -390,9 +3407,15 @@ pub fn write_output<S: ::std::hash::BuildHasher>(
 /// # Returns
 /// A `String` representing the size in the most appropriate unit (bytes, KB, MB, GB, or TB).
 ///
+/// # Example
+/// ```
+/// use duplicate_file_finder::format_size;
+///
+/// assert_eq!(format_size(1536), "1.50 KB");
+/// ```
 #[must_use]
 #[allow(clippy::cast_precision_loss)]
-fn format_size(size: u64) -> String {
+pub fn format_size(size: u64) -> String {
     const KB: u64 = 1024;
     const MB: u64 = KB * 1024;
     const GB: u64 = MB * 1024;
@@ -411,86 +3434,795 @@ fn format_size(size: u64) -> String {
     }
 }
 
-/// Computes a fast, non-cryptographic hash for a file based on its first 8 KB.
+/// Describes the change from `before` to `after` bytes, such as `-1.50 MB`.
+pub(crate) fn format_size_change(before: u64, after: u64) -> String {
+    match after.cmp(&before) {
+        std::cmp::Ordering::Greater => format!("+{}", format_size(after - before)),
+        std::cmp::Ordering::Less => format!("-{}", format_size(before - after)),
+        std::cmp::Ordering::Equal => "no change".to_string(),
+    }
+}
+
+/// An error returned by [`parse_size`] for malformed size strings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSizeError {
+    input: String,
+}
+
+impl std::fmt::Display for ParseSizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid size '{}': expected a number with an optional unit (B, KB, MB, GB, TB)",
+            self.input
+        )
+    }
+}
+
+impl Error for ParseSizeError {}
+
+/// Parses a human-readable size such as `"4KB"`, `"1.5 GB"` or `"512"` into bytes.
+///
+/// Units are case-insensitive and use the same 1024-based multiples as the
+/// report. A bare number is interpreted as bytes, and `K`, `KB` and `KiB`
+/// are all accepted.
+///
+/// # Errors
+/// Returns a [`ParseSizeError`] if the number or unit cannot be parsed.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::parse_size;
+///
+/// assert_eq!(parse_size("4KB").unwrap(), 4096);
+/// assert_eq!(parse_size("2gb").unwrap(), 2 * 1024 * 1024 * 1024);
+/// assert!(parse_size("ten").is_err());
+/// ```
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+pub fn parse_size(input: &str) -> Result<u64, ParseSizeError> {
+    let err = || ParseSizeError {
+        input: input.to_string(),
+    };
+    let trimmed = input.trim();
+    let split = trimmed
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+        "t" | "tb" | "tib" => 1024 * 1024 * 1024 * 1024,
+        _ => return Err(err()),
+    };
+
+    if let Ok(whole) = number.parse::<u64>() {
+        return whole.checked_mul(multiplier).ok_or_else(err);
+    }
+    let value: f64 = number.parse().map_err(|_| err())?;
+    let bytes = value * multiplier as f64;
+    if !bytes.is_finite() || bytes >= u64::MAX as f64 {
+        return Err(err());
+    }
+    Ok(bytes.round() as u64)
+}
+
+/// Computes a fast, non-cryptographic hash for a file based on its first and
+/// last `sample_bytes`, optionally the `sample_bytes` in its middle, and its
+/// length.
 ///
-/// Used for quickly eliminating obviously different files.
+/// Used for quickly eliminating obviously different files. Sampling the tail
+/// as well as the head separates files that share a common header, such as
+/// images or documents from the same program. Files no larger than all
+/// samples together are hashed in full.
 ///
 /// # Arguments
 /// * `file_path` - Path to the file to hash.
+/// * `sample_bytes` - Bytes to read from each sampled block of the file.
+/// * `sample` - Which blocks of the file are sampled.
 ///
 /// # Returns
-/// An `Option<u64>` containing the hash value, or `None` if the file couldn't be read.
+/// The hash value, or the I/O error raised while reading the file.
 ///
-const QUICK_HASH_SIZE: usize = 8 * 1024;
+fn quick_hash(file_path: &Path, sample_bytes: u64, sample: QuickHashSample) -> io::Result<u64> {
+    let mut file = File::open(file_path)?;
+    let len = file.metadata()?.len();
+    let mut buffer = Vec::new();
+    for (offset, bytes) in quick_hash_ranges(len, sample_bytes, sample) {
+        if offset != 0 {
+            file.seek(SeekFrom::Start(offset))?;
+        }
+        (&mut file).take(bytes).read_to_end(&mut buffer)?;
+    }
+    Ok(quick_hash_of(&buffer, len))
+}
 
-#[must_use]
-fn quick_hash(file_path: &Path) -> Option<u64> {
+/// Returns the offset and length of each block of a `len`-byte file that the
+/// quick hash reads.
+fn quick_hash_ranges(len: u64, sample_bytes: u64, sample: QuickHashSample) -> Vec<(u64, u64)> {
+    if len <= sample_bytes.saturating_mul(sample.blocks()) {
+        return vec![(0, len)];
+    }
+    let mut ranges = vec![(0, sample_bytes)];
+    if sample == QuickHashSample::FirstMiddleLast {
+        ranges.push(((len - sample_bytes) / 2, sample_bytes));
+    }
+    ranges.push((len - sample_bytes, sample_bytes));
+    ranges
+}
+
+/// Combines the sampled blocks of a `len`-byte file into its quick hash.
+fn quick_hash_of(samples: &[u8], len: u64) -> u64 {
     let mut hasher = XxHash64::with_seed(0);
-    let file = File::open(file_path).ok()?;
-    let mut reader = BufReader::new(file);
-    let mut buffer = [0; QUICK_HASH_SIZE];
-    let bytes_read = reader.read(&mut buffer).ok()?;
+    hasher.write(samples);
+    hasher.write_u64(len);
+    hasher.finish()
+}
+
+/// Bytes read by one batch of `io_uring` requests.
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+const URING_BATCH_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Files up to this size are read whole through `io_uring` for their full
+/// hash; larger ones are better served by a memory map or long reads.
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+const URING_FULL_HASH_MAX_BYTES: u64 = 1024 * 1024;
+
+/// Computes the [`quick_hash`] of files of `size` bytes with batched reads,
+/// or returns `None` if `io_uring` cannot be used.
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+fn quick_hashes_batched(
+    files: &[PathBuf],
+    size: u64,
+    sample_bytes: u64,
+    sample: QuickHashSample,
+) -> Option<Vec<io::Result<u64>>> {
+    use std::os::fd::AsRawFd;
+
+    let ranges = quick_hash_ranges(size, sample_bytes, sample);
+    let per_file = ranges.iter().map(|&(_, bytes)| bytes).sum::<u64>().max(1);
+    let batch_files = usize::try_from(URING_BATCH_BYTES / per_file).map_or(1, |n| n.max(1));
+    let mut hashes = Vec::with_capacity(files.len());
+    for chunk in files.chunks(batch_files) {
+        let opened: Vec<io::Result<File>> = chunk.iter().map(File::open).collect();
+        let requests: Vec<uring::ReadRequest> = opened
+            .iter()
+            .flatten()
+            .flat_map(|file| {
+                ranges.iter().map(|&(offset, bytes)| uring::ReadRequest {
+                    fd: file.as_raw_fd(),
+                    offset,
+                    len: usize::try_from(bytes).unwrap_or(usize::MAX),
+                })
+            })
+            .collect();
+        let mut reads = uring::with_ring(|ring| ring.read_batch(&requests))?.into_iter();
+        for file in opened {
+            hashes.push(file.and_then(|_| {
+                let samples: Vec<io::Result<Vec<u8>>> = reads.by_ref().take(ranges.len()).collect();
+                let mut buffer = Vec::new();
+                for sample in samples {
+                    buffer.extend(sample?);
+                }
+                Ok(quick_hash_of(&buffer, size))
+            }));
+        }
+    }
+    Some(hashes)
+}
+
+/// Computes the [`full_hash`] of files of `size` bytes with batched reads of
+/// their whole contents, or returns `None` if `io_uring` cannot be used.
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+fn full_hashes_batched(
+    files: &[PathBuf],
+    size: u64,
+    hash_algo: HashAlgo,
+) -> Option<Vec<io::Result<String>>> {
+    use std::os::fd::AsRawFd;
+
+    // One byte more than expected reveals a file that grew since it was sized.
+    let len = usize::try_from(size).ok()?.checked_add(1)?;
+    let batch_files = usize::try_from(URING_BATCH_BYTES)
+        .map_or(1, |bytes| bytes / len)
+        .max(1);
+    let mut hashes = Vec::with_capacity(files.len());
+    for chunk in files.chunks(batch_files) {
+        let opened: Vec<io::Result<File>> = chunk.iter().map(File::open).collect();
+        let requests: Vec<uring::ReadRequest> = opened
+            .iter()
+            .flatten()
+            .map(|file| uring::ReadRequest {
+                fd: file.as_raw_fd(),
+                offset: 0,
+                len,
+            })
+            .collect();
+        let mut reads = uring::with_ring(|ring| ring.read_batch(&requests))?.into_iter();
+        for (path, file) in chunk.iter().zip(opened) {
+            hashes.push(file.and_then(|_| {
+                let contents = reads
+                    .next()
+                    .unwrap_or_else(|| Err(io::Error::other("read was not performed")))?;
+                if contents.len() == len {
+                    return full_hash(path, hash_algo);
+                }
+                let mut digest = hash_algo.hasher();
+                digest.update(&contents);
+                Ok(digest.finish_hex())
+            }));
+        }
+    }
+    Some(hashes)
+}
+
+const FULL_HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+/// How many bytes of a file are hashed between updates of the full-hash
+/// progress, so that large files do not stall it while observers are not
+/// called for every buffer read.
+const PROGRESS_STEP_BYTES: u64 = 1024 * 1024;
+
+/// Files at least this large are memory-mapped instead of read in chunks.
+///
+/// Below this the buffered loop is as fast, and mapping costs a few extra
+/// system calls per file.
+#[cfg(unix)]
+const MMAP_MIN_BYTES: u64 = 16 * 1024 * 1024;
+
+/// How much of a memory-mapped file is hashed at a time.
+#[cfg(unix)]
+const MAPPED_CHUNK_BYTES: usize = 8 * 1024 * 1024;
+
+/// Computes a full hash of a file's contents with the chosen algorithm.
+///
+/// Used in the final step of duplicate detection to confirm file identity.
+/// Files of [`MMAP_MIN_BYTES`] or more are memory-mapped on Unix and hashed
+/// in one pass; smaller files, and any file that cannot be mapped, are read
+/// through a buffer.
+///
+/// # Arguments
+/// * `file_path` - Path to the file to hash.
+/// * `hash_algo` - Algorithm to hash with.
+///
+/// # Returns
+/// The lowercase hex representation of the digest, or the I/O error raised
+/// while reading the file.
+fn full_hash(file_path: &Path, hash_algo: HashAlgo) -> io::Result<String> {
+    full_hash_with(file_path, hash_algo, &|_| {})
+}
+
+/// Computes a full hash like [`full_hash`], passing the number of bytes
+/// hashed to `on_read` as the file is read.
+pub(crate) fn full_hash_with(
+    file_path: &Path,
+    hash_algo: HashAlgo,
+    on_read: &dyn Fn(u64),
+) -> io::Result<String> {
+    let file = File::open(file_path)?;
+    let mut hasher = hash_algo.hasher();
+    let mut update = |bytes: &[u8]| {
+        hasher.update(bytes);
+        on_read(bytes.len() as u64);
+    };
+
+    if !hash_mapped(&file, file_path, &mut update) {
+        let mut reader = BufReader::new(file);
+        let mut buffer = vec![0; FULL_HASH_BUFFER_SIZE];
+        loop {
+            let bytes_read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            update(&buffer[..bytes_read]);
+        }
+    }
+
+    Ok(hasher.finish_hex())
+}
+
+/// Hashes `file` through a memory map if it is large enough, returning
+/// `false` if the caller should read it instead.
+#[cfg(unix)]
+fn hash_mapped(file: &File, path: &Path, update: &mut impl FnMut(&[u8])) -> bool {
+    let len = match file.metadata() {
+        Ok(meta) if meta.len() >= MMAP_MIN_BYTES => meta.len(),
+        _ => return false,
+    };
+    match mmap::Mmap::map(file, len) {
+        Ok(map) => {
+            // Hashing the map in pieces lets progress be reported as it goes.
+            for chunk in map.as_slice().chunks(MAPPED_CHUNK_BYTES) {
+                update(chunk);
+            }
+            true
+        }
+        Err(e) => {
+            debug!("Reading {} instead of mapping it: {e}", path.display());
+            false
+        }
+    }
+}
 
-    hasher.write(&buffer[..bytes_read]);
-    Some(hasher.finish())
+/// Memory maps are only used on Unix; everything else is read.
+#[cfg(not(unix))]
+fn hash_mapped(_file: &File, _path: &Path, _update: &mut impl FnMut(&[u8])) -> bool {
+    false
 }
 
-/// Computes a full SHA-256 hash of a file's contents.
-///
-/// Used in the final step of duplicate detection to confirm file identity.
-///
-/// # Arguments
-/// * `file_path` - Path to the file to hash.
-///
-/// # Returns
-/// An `Option<String>` with the lowercase hex representation of the SHA-256 hash,
-/// or `None` if the file could not be read.
-///
-const FULL_HASH_BUFFER_SIZE: usize = 64 * 1024;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(500), "500 bytes");
+        assert_eq!(format_size(1500), "1.46 KB");
+        assert_eq!(format_size(1_500_000), "1.43 MB");
+        assert_eq!(format_size(1_500_000_000), "1.40 GB");
+        assert_eq!(format_size(1_500_000_000_000), "1.36 TB");
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("0").expect("valid"), 0);
+        assert_eq!(parse_size("512B").expect("valid"), 512);
+        assert_eq!(parse_size("4KB").expect("valid"), 4096);
+        assert_eq!(parse_size("4 kib").expect("valid"), 4096);
+        assert_eq!(parse_size("1.5M").expect("valid"), 1_572_864);
+        assert_eq!(parse_size("2GB").expect("valid"), 2_147_483_648);
+        assert!(parse_size("").is_err());
+        assert!(parse_size("12 parsecs").is_err());
+        assert!(parse_size("99999999999TB").is_err());
+    }
+
+    #[test]
+    fn test_find_duplicates_with_size_limits() {
+        let dir = tempdir().expect("create temp dir");
+        fs::write(dir.path().join("empty1.txt"), "").expect("write file");
+        fs::write(dir.path().join("empty2.txt"), "").expect("write file");
+        fs::write(dir.path().join("small1.txt"), "abcd").expect("write file");
+        fs::write(dir.path().join("small2.txt"), "abcd").expect("write file");
+        fs::write(dir.path().join("large1.txt"), "abcdefgh").expect("write file");
+        fs::write(dir.path().join("large2.txt"), "abcdefgh").expect("write file");
+
+        let dirs = [dir.path().to_path_buf()];
+        let all = find_duplicates_with_options(&dirs, &ScanOptions::new().quiet(true))
+            .expect("no patterns");
+        assert_eq!(all.total_groups, 3);
+
+        let bounded = find_duplicates_with_options(
+            &dirs,
+            &ScanOptions::new().min_size(4).max_size(4).quiet(true),
+        )
+        .expect("no patterns");
+        assert_eq!(bounded.total_groups, 1);
+        assert_eq!(bounded.groups[0].size, 4);
+
+        let non_empty =
+            find_duplicates_with_options(&dirs, &ScanOptions::new().min_size(1).quiet(true))
+                .expect("no patterns");
+        assert_eq!(non_empty.total_groups, 2);
+        assert!(non_empty.groups.iter().all(|g| g.size > 0));
+    }
+
+    #[test]
+    fn test_quick_hash() {
+        let dir = tempdir().expect("create temp dir");
+        let file_path = dir.path().join("test_file.txt");
+        let mut file = File::create(&file_path).expect("create file");
+        writeln!(file, "Hello, world!").expect("write file");
+
+        let sample = QuickHashSample::default();
+        let hash = quick_hash(&file_path, DEFAULT_QUICK_HASH_BYTES, sample);
+        assert!(hash.is_ok());
+        let missing = dir.path().join("missing.txt");
+        assert!(quick_hash(&missing, DEFAULT_QUICK_HASH_BYTES, sample).is_err());
+    }
+
+    #[test]
+    fn test_quick_hash_samples_tail() {
+        let dir = tempdir().expect("create temp dir");
+        let header = vec![b'h'; 64];
+        let mut first = header.clone();
+        first.extend_from_slice(&[b'x'; 64]);
+        let mut second = header;
+        second.extend_from_slice(&[b'y'; 64]);
+        let file1 = dir.path().join("file1.bin");
+        let file2 = dir.path().join("file2.bin");
+        fs::write(&file1, &first).expect("write file");
+        fs::write(&file2, &second).expect("write file");
+
+        // Same 16-byte head and different tails, with a window smaller than the file.
+        let sample = QuickHashSample::FirstLast;
+        let hash1 = quick_hash(&file1, 16, sample).expect("hash file");
+        let hash2 = quick_hash(&file2, 16, sample).expect("hash file");
+        assert_ne!(hash1, hash2);
+
+        // Small files are hashed in full regardless of layout.
+        assert_eq!(
+            quick_hash(&file1, 1024, sample).expect("hash file"),
+            quick_hash(&file1, 64, sample).expect("hash file")
+        );
+    }
+
+    #[test]
+    fn test_quick_hash_samples_middle() {
+        let dir = tempdir().expect("create temp dir");
+        let mut first = vec![b'e'; 96];
+        let mut second = first.clone();
+        first[48] = b'x';
+        second[48] = b'y';
+        let file1 = dir.path().join("file1.bin");
+        let file2 = dir.path().join("file2.bin");
+        fs::write(&file1, &first).expect("write file");
+        fs::write(&file2, &second).expect("write file");
+
+        // Same ends and different middles.
+        let hash = |path, sample| quick_hash(path, 16, sample).expect("hash file");
+        let sample = QuickHashSample::FirstLast;
+        assert_eq!(hash(&file1, sample), hash(&file2, sample));
+        let sample = QuickHashSample::FirstMiddleLast;
+        assert_ne!(hash(&file1, sample), hash(&file2, sample));
+    }
+
+    #[test]
+    fn test_group_by_size_uses_walk_metadata() {
+        let dir = tempdir().expect("create temp dir");
+        fs::write(dir.path().join("a.txt"), "same").expect("write file");
+        fs::write(dir.path().join("b.txt"), "same").expect("write file");
+        fs::write(dir.path().join("c.txt"), "other contents").expect("write file");
+        let errors = Mutex::new(Vec::new());
+        let files = collect_files(
+            &[dir.path().to_path_buf()],
+            &Walk::default(),
+            None,
+            &errors,
+            &mut Vec::new(),
+        );
+        // Files are not read again, so removing one does not change the groups.
+        fs::remove_file(dir.path().join("b.txt")).expect("remove file");
+
+        let files = files.into_iter().map(|file| (file.meta.len(), file.path));
+        let by_size = group_by_size(
+            files.collect(),
+            &Console::new(true, false, None),
+            None,
+            None,
+        );
+        assert_eq!(by_size.len(), 1);
+        assert_eq!(by_size[&4].len(), 2);
+        assert!(errors.into_inner().expect("lock errors").is_empty());
+    }
+
+    #[test]
+    fn test_max_depth_limits_recursion() {
+        let dir = tempdir().expect("create temp dir");
+        let nested = dir.path().join("level1").join("level2");
+        fs::create_dir_all(&nested).expect("create dir");
+        for path in [
+            dir.path().join("top.txt"),
+            dir.path().join("level1").join("middle.txt"),
+            nested.join("bottom.txt"),
+        ] {
+            fs::write(path, "same").expect("write file");
+        }
+        let scan = |max_depth| {
+            let errors = Mutex::new(Vec::new());
+            let walk = Walk {
+                max_depth,
+                ..Walk::default()
+            };
+            let files = collect_files(
+                &[dir.path().to_path_buf()],
+                &walk,
+                None,
+                &errors,
+                &mut Vec::new(),
+            );
+            let mut paths: Vec<_> = files.into_iter().map(|file| file.path).collect();
+            paths.sort();
+            paths
+        };
+
+        assert!(scan(Some(0)).is_empty());
+        assert_eq!(scan(Some(1)), [dir.path().join("top.txt")]);
+        assert_eq!(scan(Some(2)).len(), 2);
+        assert!(!scan(Some(2)).contains(&nested.join("bottom.txt")));
+        assert_eq!(scan(None).len(), 3);
+    }
+
+    #[test]
+    fn test_find_duplicates_with_options() {
+        let dir = tempdir().expect("create temp dir");
+        fs::write(dir.path().join("a.txt"), "same").expect("write file");
+        fs::write(dir.path().join("b.txt"), "same").expect("write file");
+        fs::write(dir.path().join("c.log"), "same").expect("write file");
+        fs::write(dir.path().join("big1.bin"), "larger contents").expect("write file");
+        fs::write(dir.path().join("big2.bin"), "larger contents").expect("write file");
+        let dirs = [dir.path().to_path_buf()];
+
+        let options = ScanOptions::new()
+            .exclude(["*.log"])
+            .max_size(4)
+            .quiet(true);
+        let report = find_duplicates_with_options(&dirs, &options).expect("valid patterns");
+        assert_eq!(report.groups.len(), 1);
+        let mut paths = report.groups[0].paths.clone();
+        paths.sort();
+        assert_eq!(paths, [dir.path().join("a.txt"), dir.path().join("b.txt")]);
+        let run = options.run(&dirs).expect("valid patterns");
+        assert_eq!(run.groups, report.groups);
+
+        // The simple form scans with the defaults, so nothing is left out.
+        let defaults = find_duplicates_in_dirs_report(&dirs);
+        assert_eq!(defaults.total_groups, 2);
+        assert_eq!(defaults.groups[1].paths.len(), 3);
+
+        let invalid = ScanOptions::new().exclude(["[oops"]).quiet(true);
+        assert!(find_duplicates_with_options(&dirs, &invalid).is_err());
+    }
+
+    #[test]
+    fn test_update_rehashes_only_sizes_touched_by_changes() {
+        let dir = tempdir().expect("create temp dir");
+        let write = |name: &str, contents: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, contents).expect("write file");
+            path
+        };
+        let (kept_a, removed) = (write("a.txt", "four"), write("b.txt", "four"));
+        let longer = [write("c.txt", "longer"), write("d.txt", "longer")];
+        let dirs = [dir.path().to_path_buf()];
+        let cache = HashCache::default();
+        let options = ScanOptions::new().cache(&cache).quiet(true);
+        let first = find_duplicates_with_options(&dirs, &options).expect("valid patterns");
+        assert_eq!(first.groups.len(), 2);
+
+        fs::remove_file(&removed).expect("remove file");
+        let added = write("e.txt", "four");
+        // Left alone, since nothing of its size changed.
+        let kept = first
+            .groups
+            .iter()
+            .find(|group| group.paths == longer)
+            .expect("group")
+            .clone();
+        let report =
+            update_duplicates(&dirs, &first, &[removed, added.clone()], &options).expect("update");
+        assert_eq!(report.groups.len(), 2);
+        let small = report.groups.iter().find(|g| g.size == 4).expect("group");
+        let mut paths = small.paths.clone();
+        paths.sort();
+        assert_eq!(paths, [kept_a, added]);
+        assert!(report.groups.contains(&kept));
+        // Only the files of the changed size were read.
+        assert!(report.bytes_hashed < first.bytes_hashed);
+    }
+
+    #[test]
+    fn test_compare_pairs_skips_full_hash() {
+        let dir = tempdir().expect("create temp dir");
+        let contents =
+            vec![1; 3 * usize::try_from(BYTE_COMPARE_MAX_BYTES).expect("fits in memory")];
+        fs::write(dir.path().join("a.bin"), &contents).expect("write file");
+        fs::write(dir.path().join("b.bin"), &contents).expect("write file");
+        // Same size and quick hash, but a difference in the middle.
+        let mut other = contents.clone();
+        other[contents.len() / 2] = 2;
+        fs::write(dir.path().join("c.txt"), &other[1..]).expect("write file");
+        fs::write(dir.path().join("d.txt"), &contents[1..]).expect("write file");
+        let dirs = [dir.path().to_path_buf()];
+
+        let scan = |compare| {
+            let options = ScanOptions::new().compare_pairs(compare).quiet(true);
+            find_duplicates_with_options(&dirs, &options).expect("valid globs")
+        };
+        let (hashed, compared) = (scan(false), scan(true));
+        assert_eq!(hashed.groups.len(), 1);
+        assert_eq!(compared.groups.len(), 1);
+        assert_eq!(compared.groups[0].paths.len(), 2);
+        // The pair is hashed as it is compared, so the digest is the same.
+        assert_eq!(compared.groups[0].hash, hashed.groups[0].hash);
+    }
+
+    #[test]
+    fn test_full_hash_progress_counts_bytes_within_files() {
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<(u64, u64)>>);
+
+        impl ProgressObserver for Recorder {
+            fn progress(&self, stage: Stage, done: u64, total: u64) {
+                if stage == Stage::FullHash {
+                    self.0.lock().expect("lock").push((done, total));
+                }
+            }
+        }
+
+        let dir = tempdir().expect("create temp dir");
+        let contents = vec![7; usize::try_from(3 * PROGRESS_STEP_BYTES).expect("fits in memory")];
+        fs::write(dir.path().join("a.bin"), &contents).expect("write file");
+        fs::write(dir.path().join("b.bin"), &contents).expect("write file");
+        let recorder = Recorder::default();
+        let report = ScanOptions::new()
+            .progress(&recorder)
+            .run(&[dir.path().to_path_buf()])
+            .expect("valid globs");
+        assert_eq!(report.total_groups, 1);
+
+        let updates = recorder.0.into_inner().expect("lock");
+        let total = 2 * contents.len() as u64;
+        assert!(updates.len() > 2, "{updates:?}");
+        assert!(updates.iter().all(|&(_, t)| t == total));
+        assert!(updates.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(updates.last(), Some(&(total, total)));
+    }
+
+    #[test]
+    fn test_progress_observer_receives_every_stage() {
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<String>>);
+
+        impl ProgressObserver for Recorder {
+            fn stage_started(&self, stage: Stage, total: u64) {
+                self.push(format!("start {} {total}", stage.as_str()));
+            }
+            fn progress(&self, stage: Stage, done: u64, total: u64) {
+                self.push(format!("{} {done}/{total}", stage.as_str()));
+            }
+            fn stage_finished(&self, stage: Stage) {
+                self.push(format!("finish {}", stage.as_str()));
+            }
+            fn message(&self, text: &str) {
+                self.push(format!("message {text}"));
+            }
+            fn finished(&self, groups: usize) {
+                self.push(format!("done {groups}"));
+            }
+        }
+
+        impl Recorder {
+            fn push(&self, event: String) {
+                self.0.lock().expect("lock").push(event);
+            }
+        }
+
+        let dir = tempdir().expect("create temp dir");
+        fs::write(dir.path().join("a.txt"), "same").expect("write file");
+        fs::write(dir.path().join("b.txt"), "same").expect("write file");
+        fs::write(dir.path().join("c.txt"), "other contents").expect("write file");
+        let recorder = Recorder::default();
+        let report = ScanOptions::new()
+            .progress(&recorder)
+            .run(&[dir.path().to_path_buf()])
+            .expect("valid globs");
+        assert_eq!(report.total_groups, 1);
 
-#[must_use]
-#[allow(clippy::large_stack_arrays)]
-fn full_hash(file_path: &Path) -> Option<String> {
-    let file = File::open(file_path).ok()?;
-    let mut reader = BufReader::new(file);
-    let mut hasher = Sha256::new();
-    let mut buffer = [0; FULL_HASH_BUFFER_SIZE];
-
-    while let Ok(bytes_read) = reader.read(&mut buffer) {
-        if bytes_read == 0 {
-            break;
+        let events = recorder.0.into_inner().expect("lock");
+        assert!(events[0].starts_with("message 3 files identified"));
+        for expected in [
+            "start size 3",
+            "size 3/3",
+            "finish size",
+            "start quick_hash 1",
+            "quick_hash 1/1",
+            "finish quick_hash",
+            "start full_hash 8",
+            "full_hash 8/8",
+            "finish full_hash",
+        ] {
+            assert!(
+                events.iter().any(|e| e == expected),
+                "{expected} in {events:?}"
+            );
         }
-        hasher.update(&buffer[..bytes_read]);
+        assert_eq!(events.last().map(String::as_str), Some("done 1"));
     }
 
-    Some(format!("{:x}", hasher.finalize()))
-}
+    #[test]
+    fn test_name_match_filters_groups() {
+        let dir = tempdir().expect("create temp dir");
+        fs::create_dir(dir.path().join("old")).expect("create dir");
+        fs::write(dir.path().join("report.pdf"), "same").expect("write file");
+        fs::write(dir.path().join("old").join("REPORT.pdf"), "same").expect("write file");
+        fs::write(dir.path().join("old").join("report.pdf"), "same").expect("write file");
+        let scan = |name_match| {
+            find_duplicates_with_options(
+                &[dir.path().to_path_buf()],
+                &ScanOptions::new().name_match(name_match).quiet(true),
+            )
+            .expect("no patterns")
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs::{self, File};
-    use std::io::Write;
-    use tempfile::tempdir;
+        assert_eq!(scan(NameMatch::Any).groups[0].paths.len(), 3);
+        let exact = scan(NameMatch::Exact);
+        assert_eq!(exact.groups.len(), 1);
+        let mut paths = exact.groups[0].paths.clone();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                dir.path().join("old").join("report.pdf"),
+                dir.path().join("report.pdf")
+            ]
+        );
+        assert_eq!(exact.total_reclaimable_bytes, 4);
+        assert_eq!(scan(NameMatch::IgnoreCase).groups[0].paths.len(), 3);
+    }
 
     #[test]
-    fn test_format_size() {
-        assert_eq!(format_size(500), "500 bytes");
-        assert_eq!(format_size(1500), "1.46 KB");
-        assert_eq!(format_size(1_500_000), "1.43 MB");
-        assert_eq!(format_size(1_500_000_000), "1.40 GB");
-        assert_eq!(format_size(1_500_000_000_000), "1.36 TB");
+    fn test_small_pairs_are_compared_byte_for_byte() {
+        let dir = tempdir().expect("create temp dir");
+        // One-byte samples make every pair share a quick hash, so only the
+        // byte comparison can tell them apart.
+        fs::write(dir.path().join("same1.txt"), "a1b").expect("write file");
+        fs::write(dir.path().join("same2.txt"), "a1b").expect("write file");
+        fs::write(dir.path().join("diff1.txt"), "a2bb").expect("write file");
+        fs::write(dir.path().join("diff2.txt"), "a3bb").expect("write file");
+
+        let report = find_duplicates_with_options(
+            &[dir.path().to_path_buf()],
+            &ScanOptions::new().quick_hash_bytes(1).quiet(true),
+        )
+        .expect("valid patterns");
+
+        assert_eq!(report.groups.len(), 1);
+        let group = &report.groups[0];
+        assert_eq!(group.size, 3);
+        let mut names: Vec<_> = group.paths.iter().map(|p| p.file_name()).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [Some("same1.txt".as_ref()), Some("same2.txt".as_ref())]
+        );
+        let hash = full_hash(&dir.path().join("same1.txt"), HashAlgo::Sha256).expect("hash");
+        assert_eq!(group.hash, hash);
+        assert!(report.errors.is_empty());
     }
 
     #[test]
-    fn test_quick_hash() {
+    fn test_split_by_contents_separates_files_that_differ() {
         let dir = tempdir().expect("create temp dir");
-        let file_path = dir.path().join("test_file.txt");
-        let mut file = File::create(&file_path).expect("create file");
-        writeln!(file, "Hello, world!").expect("write file");
+        let write = |name: &str, contents: &[u8]| {
+            let path = dir.path().join(name);
+            fs::write(&path, contents).expect("write file");
+            path
+        };
+        // Longer than one read buffer, differing only at the very end.
+        let mut long = vec![7u8; FULL_HASH_BUFFER_SIZE + 10];
+        let a = write("a.bin", &long);
+        let b = write("b.bin", &long);
+        *long.last_mut().expect("not empty") = 8;
+        let c = write("c.bin", &long);
+        let d = write("d.bin", &long);
+        let unique = write("unique.bin", b"short");
+        let missing = dir.path().join("missing.bin");
 
-        let hash = quick_hash(&file_path);
-        assert!(hash.is_some());
+        let errors = Mutex::new(Vec::new());
+        let files = vec![
+            missing.clone(),
+            a.clone(),
+            c.clone(),
+            unique,
+            b.clone(),
+            d.clone(),
+        ];
+        let sets = split_by_contents("0000", files, &|_| {}, &errors);
+        assert_eq!(sets, vec![vec![a, b], vec![c, d]]);
+        let errors = errors.into_inner().expect("no poisoning");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, missing);
     }
 
     #[test]
@@ -500,14 +4232,70 @@ mod tests {
         let mut file = File::create(&file_path).expect("create file");
         writeln!(file, "Hello, world!").expect("write file");
 
-        let hash = full_hash(&file_path);
-        assert!(hash.is_some());
+        let hash = full_hash(&file_path, HashAlgo::Sha256);
+        assert!(hash.is_ok());
         assert_eq!(
             hash.expect("hash exists"),
             "d9014c4624844aa5bac314773d6b689ad467fa4e1d1a50a1b8a99d5a95f72ff5"
         ); // Precomputed SHA-256 of "Hello, world!\n"
     }
 
+    #[test]
+    fn test_full_hash_algorithms_are_stable() {
+        let dir = tempdir().expect("create temp dir");
+        let file1 = dir.path().join("file1.bin");
+        let file2 = dir.path().join("file2.bin");
+        fs::write(&file1, b"abc").expect("write file");
+        fs::write(&file2, b"abc").expect("write file");
+
+        let expected = [
+            (
+                HashAlgo::Sha256,
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                HashAlgo::Blake3,
+                "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+            ),
+            (HashAlgo::Xxh3, "78af5f94892f3950"),
+            (HashAlgo::Md5, "900150983cd24fb0d6963f7d28e17f72"),
+        ];
+        for (algo, digest) in expected {
+            assert_eq!(full_hash(&file1, algo).expect("hash"), digest);
+            assert_eq!(
+                full_hash(&file1, algo).expect("hash"),
+                full_hash(&file2, algo).expect("hash")
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_full_hash_of_mapped_file_matches_buffered_read() {
+        let dir = tempdir().expect("create temp dir");
+        let path = dir.path().join("large.bin");
+        let len = usize::try_from(MMAP_MIN_BYTES).expect("fits in usize") + 3;
+        let contents: Vec<u8> = (0..=250).cycle().take(len).collect();
+        fs::write(&path, &contents).expect("write file");
+
+        assert_eq!(
+            full_hash(&path, HashAlgo::Sha256).expect("hash"),
+            format!("{:x}", Sha256::digest(&contents))
+        );
+        let mut blake3 = blake3::Hasher::new();
+        for chunk in contents.chunks(FULL_HASH_BUFFER_SIZE) {
+            blake3.update(chunk);
+        }
+        let expected = blake3
+            .finalize()
+            .iter()
+            .fold(String::new(), |mut hex, byte| {
+                let _ = write!(hex, "{byte:02x}");
+                hex
+            });
+        assert_eq!(full_hash(&path, HashAlgo::Blake3).expect("hash"), expected);
+    }
+
     #[test]
     fn test_find_duplicates() {
         let dir = tempdir().expect("create temp dir");
@@ -551,6 +4339,748 @@ mod tests {
         assert!(group.contains(&file2));
     }
 
+    #[test]
+    fn test_find_duplicates_with_options_uses_hash_algo() {
+        let dir1 = tempdir().expect("create temp dir");
+        let dir2 = tempdir().expect("create temp dir");
+        let file1 = dir1.path().join("file1.txt");
+        let file2 = dir2.path().join("file2.txt");
+        fs::write(&file1, "Duplicate content").expect("write file");
+        fs::write(&file2, "Duplicate content").expect("write file");
+        fs::write(dir2.path().join("unique.txt"), "Unique content").expect("write file");
+
+        let report = find_duplicates_with_options(
+            &[dir1.path().to_path_buf(), dir2.path().to_path_buf()],
+            &ScanOptions::new().hash_algo(HashAlgo::Blake3).quiet(true),
+        )
+        .expect("no patterns");
+        assert_eq!(report.groups.len(), 1);
+        let group = &report.groups[0];
+        assert!(group.paths.contains(&file1) && group.paths.contains(&file2));
+        assert_eq!(
+            group.hash,
+            full_hash(&file1, HashAlgo::Blake3).expect("hash")
+        );
+    }
+
+    #[test]
+    fn test_find_duplicates_with_include() {
+        let dir = tempdir().expect("create temp dir");
+        let photos = dir.path().join("photos");
+        fs::create_dir(&photos).expect("create dir");
+        for name in ["a.jpg", "b.JPG", "c.txt"] {
+            fs::write(dir.path().join(name), "Duplicate content").expect("write file");
+        }
+        fs::write(photos.join("d.jpg"), "Duplicate content").expect("write file");
+        fs::write(photos.join("e.jpg"), "Duplicate content").expect("write file");
+
+        let report = ScanOptions::new()
+            .include(["*.jpg"])
+            .exclude(["photos/e.jpg"])
+            .quiet(true)
+            .run(&[dir.path().to_path_buf()])
+            .expect("valid globs");
+        // Included files are found in subdirectories too, and exclude wins.
+        assert_eq!(
+            report.groups[0].paths,
+            [dir.path().join("a.jpg"), photos.join("d.jpg")]
+        );
+
+        let invalid = ScanOptions::new().include(["[oops"]).quiet(true);
+        assert!(invalid.run(&[dir.path().to_path_buf()]).is_err());
+    }
+
+    #[test]
+    fn test_find_duplicates_with_excluded_dirs() {
+        let dir = tempdir().expect("create temp dir");
+        let deps = dir.path().join("app").join("node_modules");
+        let backup = dir.path().join("backup");
+        let kept = dir.path().join("app").join("src");
+        for sub in [&deps, &backup, &kept] {
+            fs::create_dir_all(sub).expect("create dir");
+            fs::write(sub.join("lib.js"), "Duplicate content").expect("write file");
+        }
+        fs::write(dir.path().join("lib.js"), "Duplicate content").expect("write file");
+
+        // The backup is named by an absolute path, the dependencies by name.
+        let report = ScanOptions::new()
+            .exclude_dirs(["node_modules".to_string(), backup.display().to_string()])
+            .quiet(true)
+            .run(&[dir.path().to_path_buf()])
+            .expect("valid globs");
+        assert_eq!(
+            report.groups[0].paths,
+            [kept.join("lib.js"), dir.path().join("lib.js")]
+        );
+    }
+    #[test]
+    fn test_find_duplicates_with_regex() {
+        let dir = tempdir().expect("create temp dir");
+        for sub in ["2023-07-14", "2023-07-15", "misc"] {
+            fs::create_dir(dir.path().join(sub)).expect("create dir");
+            fs::write(dir.path().join(sub).join("img.jpg"), "Duplicate content")
+                .expect("write file");
+            fs::write(dir.path().join(sub).join("img.bak"), "Duplicate content")
+                .expect("write file");
+        }
+
+        // Separators differ between platforms, so the pattern accepts both.
+        let report = ScanOptions::new()
+            .include_regex([r"[/\\]\d{4}-\d{2}-\d{2}[/\\]"])
+            .exclude_regex([r"\.bak$"])
+            .quiet(true)
+            .run(&[dir.path().to_path_buf()])
+            .expect("valid regexes");
+        assert_eq!(
+            report.groups[0].paths,
+            [
+                dir.path().join("2023-07-14").join("img.jpg"),
+                dir.path().join("2023-07-15").join("img.jpg"),
+            ]
+        );
+
+        let invalid = ScanOptions::new().exclude_regex(["(oops"]).quiet(true);
+        let err = invalid
+            .run(&[dir.path().to_path_buf()])
+            .expect_err("invalid regex");
+        assert_eq!(err.pattern(), "(oops");
+        assert_eq!(err.regex().map(RegexError::pattern), Some("(oops"));
+        assert!(err
+            .to_string()
+            .starts_with("invalid regular expression '(oops'"));
+    }
+
+    #[test]
+    fn test_find_duplicates_with_ignore_files() {
+        let dir = tempdir().expect("create temp dir");
+        let build = dir.path().join("build");
+        let docs = dir.path().join("docs");
+        for sub in [&build, &docs] {
+            fs::create_dir(sub).expect("create dir");
+        }
+        for path in [
+            build.join("out.bin"),
+            docs.join("a.tmp"),
+            docs.join("keep.tmp"),
+            docs.join("b.txt"),
+            dir.path().join("c.txt"),
+            dir.path().join("d.log"),
+        ] {
+            fs::write(path, "Duplicate content").expect("write file");
+        }
+        fs::write(dir.path().join(".dupeignore"), "/build/\n*.tmp\n").expect("write file");
+        // A nested file overrides the one above it.
+        fs::write(docs.join(".dupeignore"), "!keep.tmp\n").expect("write file");
+        let given = dir.path().join("extra-ignore");
+        fs::write(&given, "*.log\nextra-ignore\n").expect("write file");
+
+        let report = ScanOptions::new()
+            .ignore_files([&given])
+            .quiet(true)
+            .run(&[dir.path().to_path_buf()])
+            .expect("no patterns to compile");
+        assert_eq!(
+            report.groups[0].paths,
+            [
+                dir.path().join("c.txt"),
+                docs.join("b.txt"),
+                docs.join("keep.tmp"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_duplicates_respecting_gitignore() {
+        let dir = tempdir().expect("create temp dir");
+        let repo = dir.path().join("repo");
+        let git_info = repo.join(".git").join("info");
+        let target = repo.join("target");
+        for sub in [&git_info, &target] {
+            fs::create_dir_all(sub).expect("create dir");
+        }
+        for path in [
+            repo.join(".git").join("object"),
+            target.join("app"),
+            repo.join("notes.local"),
+            repo.join("a.rs"),
+            repo.join("b.rs"),
+        ] {
+            fs::write(path, "Duplicate content").expect("write file");
+        }
+        fs::write(repo.join(".gitignore"), "target/\n").expect("write file");
+        fs::write(git_info.join("exclude"), "*.local\n").expect("write file");
+
+        let roots = [dir.path().to_path_buf()];
+        let all = ScanOptions::new().quiet(true).run(&roots).expect("scan");
+        assert_eq!(all.groups[0].paths.len(), 5);
+
+        let report = ScanOptions::new()
+            .respect_gitignore(true)
+            .quiet(true)
+            .run(&roots)
+            .expect("scan");
+        assert_eq!(
+            report.groups[0].paths,
+            [repo.join("a.rs"), repo.join("b.rs")]
+        );
+    }
+
+    #[test]
+    fn test_find_duplicates_with_exclude() {
+        let dir = tempdir().expect("create temp dir");
+        let target = dir.path().join("target");
+        fs::create_dir(&target).expect("create dir");
+
+        let kept1 = dir.path().join("a.txt");
+        let kept2 = dir.path().join("b.txt");
+        fs::write(&kept1, "Duplicate content").expect("write file");
+        fs::write(&kept2, "Duplicate content").expect("write file");
+        fs::write(dir.path().join("c.tmp"), "Duplicate content").expect("write file");
+        fs::write(target.join("d.txt"), "Duplicate content").expect("write file");
+
+        let duplicates = find_duplicates_with_options(
+            &[dir.path().to_path_buf()],
+            &ScanOptions::new()
+                .exclude(["**/target/**", "*.tmp"])
+                .quiet(true),
+        )
+        .map(DuplicateReport::into_map)
+        .expect("valid globs");
+        let group = duplicates.values().next().expect("duplicates");
+        assert_eq!(group.len(), 2);
+        assert!(group.contains(&kept1));
+        assert!(group.contains(&kept2));
+
+        let invalid = find_duplicates_with_options(
+            &[dir.path().to_path_buf()],
+            &ScanOptions::new().exclude(["["]).quiet(true),
+        )
+        .map(DuplicateReport::into_map);
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_find_duplicates_report() {
+        let dir = tempdir().expect("create temp dir");
+
+        let small1 = dir.path().join("small1.txt");
+        let small2 = dir.path().join("small2.txt");
+        let large1 = dir.path().join("large1.txt");
+        let large2 = dir.path().join("large2.txt");
+        let large3 = dir.path().join("large3.txt");
+
+        fs::write(&small1, "abc").expect("write file");
+        fs::write(&small2, "abc").expect("write file");
+        fs::write(&large1, "abcdefghij").expect("write file");
+        fs::write(&large2, "abcdefghij").expect("write file");
+        fs::write(&large3, "abcdefghij").expect("write file");
+
+        let report = find_duplicates_report(dir.path());
+        assert_eq!(report.total_groups, 2);
+        assert_eq!(report.total_reclaimable_bytes, 3 + 2 * 10);
+        assert_eq!(report.groups[0].size, 10);
+        assert_eq!(report.groups[0].paths.len(), 3);
+        assert_eq!(report.groups[1].size, 3);
+        assert_eq!(report.groups[1].paths.len(), 2);
+    }
+
+    #[test]
+    fn test_find_duplicates_streaming() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let dir = tempdir().expect("create temp dir");
+        fs::write(dir.path().join("a1.txt"), "first").expect("write file");
+        fs::write(dir.path().join("a2.txt"), "first").expect("write file");
+        fs::write(dir.path().join("b1.txt"), "second group").expect("write file");
+        fs::write(dir.path().join("b2.txt"), "second group").expect("write file");
+        fs::write(dir.path().join("unique.txt"), "unique").expect("write file");
+
+        // `Rc` is neither `Send` nor `Sync`, so this only compiles if the
+        // callback stays on the calling thread.
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&seen);
+        let errors = find_duplicates_streaming(&[dir.path().to_path_buf()], move |group| {
+            sink.borrow_mut().push(group);
+        });
+        assert!(errors.is_empty());
+
+        let mut streamed = seen.take();
+        streamed.sort_by_key(|group| group.size);
+        let report = find_duplicates_report(dir.path());
+        let mut collected = report.groups;
+        collected.sort_by_key(|group| group.size);
+        assert_eq!(streamed.len(), 2);
+        assert_eq!(streamed.len(), collected.len());
+        for (a, b) in streamed.iter().zip(&collected) {
+            assert_eq!(a.hash, b.hash);
+            assert_eq!(a.size, b.size);
+        }
+    }
+
+    #[test]
+    fn test_find_duplicates_iter() {
+        let dir = tempdir().expect("create temp dir");
+        fs::write(dir.path().join("a.txt"), "same").expect("write file");
+        fs::write(dir.path().join("b.txt"), "same").expect("write file");
+        fs::write(dir.path().join("c.bin"), "other").expect("write file");
+        fs::write(dir.path().join("d.bin"), "other").expect("write file");
+
+        let mut groups = find_duplicates_iter(
+            vec![dir.path().to_path_buf()],
+            &ScanOptions::new().quiet(true),
+        )
+        .expect("valid globs");
+        let mut sizes: Vec<u64> = groups.by_ref().map(|group| group.size).collect();
+        sizes.sort_unstable();
+        assert_eq!(sizes, [4, 5]);
+        assert!(groups.into_errors().is_empty());
+
+        let invalid = ScanOptions::new().exclude(["[oops"]);
+        assert!(find_duplicates_iter(vec![dir.path().to_path_buf()], &invalid).is_err());
+    }
+
+    #[test]
+    fn test_unreadable_paths_are_reported() {
+        let dir = tempdir().expect("create temp dir");
+        let missing = dir.path().join("does_not_exist");
+
+        let report = find_duplicates_report(&missing);
+        assert!(report.is_empty());
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].0, missing);
+        assert_eq!(report.errors[0].1.kind(), io::ErrorKind::NotFound);
+
+        let mut buffer = Vec::new();
+        write_output_to(
+            &mut buffer,
+            &report,
+            "start",
+            &[dir.path().to_path_buf()],
+            &OutputOptions::new(),
+        )
+        .expect("write to buffer");
+        let text = String::from_utf8(buffer).expect("valid UTF-8");
+        assert!(text.contains("Files Skipped Due To Errors: 1\n"));
+        assert!(text.contains(&format!("Skipped Files:\n{}: ", missing.display())));
+
+        let errors = Mutex::new(Vec::new());
+        let size_map = HashMap::from([(4, vec![missing.clone(), missing.clone()])]);
+        let quick = group_by_quick_hash(
+            size_map,
+            &Console::new(true, false, None),
+            &ScanConfig::default(),
+            &errors,
+        );
+        assert!(quick.is_empty());
+        assert_eq!(errors.into_inner().expect("lock").len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempdir().expect("create temp dir");
+        let real = dir.path().join("real");
+        fs::create_dir(&real).expect("create dir");
+        fs::write(real.join("a.txt"), "same").expect("write file");
+        fs::write(real.join("b.txt"), "same").expect("write file");
+        let outside = tempdir().expect("create temp dir");
+        symlink(&real, dir.path().join("linked")).expect("create symlink");
+        symlink(outside.path(), dir.path().join("outside")).expect("create symlink");
+        fs::write(outside.path().join("c.txt"), "same").expect("write file");
+        // A link back to an ancestor must not loop forever.
+        symlink(dir.path(), real.join("loop")).expect("create symlink");
+        // A link to a file is only followed along with links to directories.
+        symlink(outside.path().join("c.txt"), dir.path().join("c-link.txt"))
+            .expect("create symlink");
+
+        let scan = |follow_symlinks| {
+            find_duplicates_with_options(
+                &[dir.path().to_path_buf()],
+                &ScanOptions::new()
+                    .follow_symlinks(follow_symlinks)
+                    .quiet(true),
+            )
+            .expect("valid globs")
+        };
+
+        let unfollowed = scan(false);
+        assert_eq!(unfollowed.groups[0].paths.len(), 2);
+
+        // Each file is reachable through two paths but counts once.
+        let followed = scan(true);
+        assert_eq!(followed.total_groups, 1);
+        assert_eq!(followed.groups[0].paths.len(), 3);
+        assert!(followed.errors.is_empty());
+    }
+
+    #[test]
+    fn test_overlapping_dirs_scan_each_file_once() {
+        let dir = tempdir().expect("create temp dir");
+        let sub = dir.path().join("sub");
+        fs::create_dir(&sub).expect("create dir");
+        fs::write(dir.path().join("a.txt"), "same").expect("write file");
+        fs::write(sub.join("b.txt"), "same").expect("write file");
+        fs::write(sub.join("unique.txt"), "different").expect("write file");
+        let scan = |dirs: &[PathBuf]| {
+            let options = ScanOptions::new().quiet(true);
+            find_duplicates_with_options(dirs, &options).expect("valid globs")
+        };
+
+        let single = scan(&[dir.path().to_path_buf()]);
+        assert_eq!(single.total_groups, 1);
+        assert_eq!(single.groups[0].paths.len(), 2);
+
+        for dirs in [
+            vec![dir.path().to_path_buf(), dir.path().to_path_buf()],
+            vec![dir.path().to_path_buf(), sub.clone()],
+            vec![sub.clone(), dir.path().join(".")],
+        ] {
+            let report = scan(&dirs);
+            assert_eq!(report.total_groups, single.total_groups, "{dirs:?}");
+            assert_eq!(report.groups[0].paths.len(), 2, "{dirs:?}");
+            assert_eq!(
+                report.total_reclaimable_bytes, single.total_reclaimable_bytes,
+                "{dirs:?}"
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hard_links_are_not_duplicates_of_themselves() {
+        let dir = tempdir().expect("create temp dir");
+        fs::write(dir.path().join("a.txt"), "same").expect("write file");
+        fs::hard_link(dir.path().join("a.txt"), dir.path().join("b.txt")).expect("hard link");
+
+        let report = find_duplicates_report(dir.path());
+        assert!(report.is_empty());
+        assert_eq!(report.hard_links.len(), 1);
+
+        let mut text = Vec::new();
+        write_output_to(
+            &mut text,
+            &report,
+            "start",
+            &[dir.path().to_path_buf()],
+            &OutputOptions::new(),
+        )
+        .expect("write report");
+        let text = String::from_utf8(text).expect("UTF-8");
+        assert!(text.contains("Hard Links Already Deduplicated: 1"));
+        assert!(text.contains(" => "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_low_memory_scan_matches_normal_scan() {
+        let dir = tempdir().expect("create temp dir");
+        fs::write(dir.path().join("a.txt"), "same").expect("write file");
+        fs::write(dir.path().join("b.txt"), "same").expect("write file");
+        fs::hard_link(dir.path().join("a.txt"), dir.path().join("c.txt")).expect("hard link");
+        fs::write(dir.path().join("d.txt"), "different").expect("write file");
+        fs::write(dir.path().join("e.txt"), "other").expect("write file");
+        let dirs = [dir.path().to_path_buf(), dir.path().join(".")];
+
+        let scan = |low_memory| {
+            let options = ScanOptions::new().low_memory(low_memory).quiet(true);
+            let mut report = find_duplicates_with_options(&dirs, &options).expect("valid globs");
+            for group in &mut report.groups {
+                group.paths.sort();
+            }
+            report
+        };
+        let (normal, low_memory) = (scan(false), scan(true));
+        assert_eq!(low_memory.groups, normal.groups);
+        assert_eq!(low_memory.groups.len(), 1);
+        assert_eq!(low_memory.groups[0].paths.len(), 2);
+        assert_eq!(low_memory.hard_links.len(), 1);
+    }
+
+    #[test]
+    fn test_thread_limit() {
+        let pool = build_pool(2).expect("build pool");
+        assert_eq!(in_pool(Some(&pool), rayon::current_num_threads), 2);
+        assert!(build_pool(0).is_none());
+
+        let dir = tempdir().expect("create temp dir");
+        fs::write(dir.path().join("a.txt"), "same").expect("write file");
+        fs::write(dir.path().join("b.txt"), "same").expect("write file");
+        let dirs = [dir.path().to_path_buf()];
+        let report =
+            find_duplicates_with_options(&dirs, &ScanOptions::new().threads(1).quiet(true))
+                .expect("valid globs");
+        assert_eq!(report.total_groups, 1);
+
+        let report = ScanOptions::new()
+            .threads(1)
+            .io_threads(2)
+            .quiet(true)
+            .run(&dirs)
+            .expect("valid globs");
+        assert_eq!(report.total_groups, 1);
+    }
+
+    #[test]
+    fn test_cancelled_scan_stops_hashing() {
+        let dir = tempdir().expect("create temp dir");
+        let file1 = dir.path().join("file1.txt");
+        let file2 = dir.path().join("file2.txt");
+        fs::write(&file1, "Duplicate content").expect("write file");
+        fs::write(&file2, "Duplicate content").expect("write file");
+
+        let cancel = AtomicBool::new(true);
+        let dirs = [dir.path().to_path_buf()];
+        let report =
+            find_duplicates_with_options(&dirs, &ScanOptions::new().quiet(true).cancel(&cancel))
+                .expect("valid globs");
+        assert!(report.interrupted);
+        assert!(report.is_empty());
+
+        let errors = Mutex::new(Vec::new());
+        let candidates = HashMap::from([((17, 0), vec![file1, file2])]);
+        let mut groups = Vec::new();
+        let config = ScanConfig {
+            cancel: Some(&cancel),
+            ..ScanConfig::default()
+        };
+        group_by_full_hash(
+            candidates,
+            &Console::new(true, false, None),
+            &config,
+            None,
+            &errors,
+            &mut |group| groups.push(group),
+        );
+        assert!(groups.is_empty());
+
+        let mut buffer = Vec::new();
+        write_output_to(
+            &mut buffer,
+            &report,
+            "20250101 12:00:00",
+            &dirs,
+            &OutputOptions::new(),
+        )
+        .expect("write to buffer");
+        let output = String::from_utf8(buffer).expect("valid UTF-8");
+        assert!(output.contains("Scan Interrupted"));
+    }
+
+    #[test]
+    fn test_write_output_to_buffer() {
+        let report = DuplicateReport::from_groups(vec![
+            DuplicateGroup {
+                hash: "small".to_string(),
+                size: 10,
+                paths: vec![PathBuf::from("/data/a.txt"), PathBuf::from("/data/b.txt")],
+            },
+            DuplicateGroup {
+                hash: "large".to_string(),
+                size: 2048,
+                paths: vec![PathBuf::from("/data/c.bin"), PathBuf::from("/data/d.bin")],
+            },
+        ]);
+
+        let mut buffer = Vec::new();
+        write_output_to(
+            &mut buffer,
+            &report,
+            "20250101 12:00:00",
+            &[PathBuf::from("/data")],
+            &OutputOptions::new(),
+        )
+        .expect("write to buffer");
+        let output = String::from_utf8(buffer).expect("valid UTF-8");
+
+        assert!(output.starts_with("Duplicate File Finder Report\n"));
+        assert!(output.contains("Base Directory: /data\n"));
+        assert!(output.contains("Total Potential Space Savings: 2.01 KB\n"));
+        let large = output.find("Size: 2.00 KB\n/data/c.bin\n/data/d.bin\n");
+        let small = output.find("Size: 10 bytes\n/data/a.txt\n/data/b.txt\n");
+        assert!(large.expect("large group") < small.expect("small group"));
+    }
+
+    #[test]
+    fn test_write_output_verbose_metadata() {
+        let dir = tempdir().expect("create temp dir");
+        let file1 = dir.path().join("file1.txt");
+        let file2 = dir.path().join("file2.txt");
+        fs::write(&file1, "Duplicate content").expect("write file");
+        fs::write(&file2, "Duplicate content").expect("write file");
+        let mut permissions = fs::metadata(&file2).expect("read metadata").permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&file2, permissions).expect("set permissions");
+
+        let mut report = find_duplicates_report(dir.path());
+        assert_eq!(report.metadata.len(), 2);
+        assert!(report.metadata[&file2].readonly);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let ino = fs::metadata(&file2).expect("read metadata").ino();
+            assert_eq!(report.metadata[&file2].inode, Some(ino));
+            assert_ne!(report.metadata[&file1].inode, Some(ino));
+        }
+        // Simulate a file whose metadata could not be read during the scan.
+        report.metadata.remove(&file1);
+
+        let mut buffer = Vec::new();
+        write_output_to(
+            &mut buffer,
+            &report,
+            "20250101 12:00:00",
+            &[],
+            &OutputOptions::new().verbose(true),
+        )
+        .expect("write to buffer");
+        let output = String::from_utf8(buffer).expect("valid UTF-8");
+        assert!(output.contains(&format!(
+            "{}\n    <metadata unavailable>\n",
+            file1.display()
+        )));
+        let line = output
+            .lines()
+            .skip_while(|line| *line != file2.to_str().expect("valid UTF-8"))
+            .nth(1)
+            .expect("metadata line");
+        assert!(line.starts_with("    Modified: "));
+        assert!(line.ends_with(" | Size: 17 bytes | Read-only: yes"));
+
+        let mut compact = Vec::new();
+        write_output_to(
+            &mut compact,
+            &report,
+            "20250101 12:00:00",
+            &[],
+            &OutputOptions::new(),
+        )
+        .expect("write to buffer");
+        assert!(!String::from_utf8(compact)
+            .expect("valid UTF-8")
+            .contains("Modified:"));
+    }
+
+    /// Splits CSV text into records, undoing RFC 4180 quoting.
+    fn parse_csv(text: &str) -> Vec<Vec<String>> {
+        let mut records = Vec::new();
+        let mut record = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, quoted) {
+                ('"', true) if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                ('"', _) => quoted = !quoted,
+                (',', false) => record.push(std::mem::take(&mut field)),
+                ('\n', false) => {
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+                _ => field.push(c),
+            }
+        }
+        records
+    }
+
+    #[test]
+    fn test_write_summary_lists_largest_groups() {
+        // A few large files waste less space than many copies of a small one.
+        let mut groups: Vec<DuplicateGroup> = (0..12)
+            .map(|i| DuplicateGroup {
+                hash: format!("hash{i}"),
+                size: 1000 + i,
+                paths: vec![
+                    PathBuf::from(format!("/data/{i}/a")),
+                    PathBuf::from(format!("/data/{i}/b")),
+                ],
+            })
+            .collect();
+        groups.push(DuplicateGroup {
+            hash: "many".to_string(),
+            size: 600,
+            paths: (0..5)
+                .map(|i| PathBuf::from(format!("/small/{i}")))
+                .collect(),
+        });
+        let report = DuplicateReport::from_groups(groups);
+
+        let mut buffer = Vec::new();
+        write_summary_to(&mut buffer, &report).expect("write to buffer");
+        let text = String::from_utf8(buffer).expect("valid UTF-8");
+
+        assert!(text.starts_with(
+            "Duplicate File Finder Summary\n\
+             Duplicate Groups: 13\n\
+             Redundant Files: 16\n"
+        ));
+        assert!(text.contains("1. 2.34 KB in 5 files of 600 bytes: /small/0\n"));
+        assert!(text.contains("2. 1011 bytes in 2 files of 1011 bytes: /data/11/a\n"));
+        assert!(text.contains("10. "));
+        assert!(!text.contains("11. "));
+        assert!(!text.contains("/data/0/a"));
+        assert!(!text.contains("/data/0/b") && !text.contains("/small/1"));
+    }
+
+    #[test]
+    fn test_write_csv_round_trip() {
+        let mut report = DuplicateReport::from_groups(vec![
+            DuplicateGroup {
+                hash: "small".to_string(),
+                size: 10,
+                paths: vec![
+                    PathBuf::from("/data/b.txt"),
+                    PathBuf::from("/data/say \"hi\", ok.txt"),
+                ],
+            },
+            DuplicateGroup {
+                hash: "large".to_string(),
+                size: 2048,
+                paths: vec![
+                    PathBuf::from("/data/z.bin"),
+                    PathBuf::from("/data/y.bin"),
+                    PathBuf::from("/data/x.bin"),
+                ],
+            },
+        ]);
+        let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        report.metadata.insert(
+            PathBuf::from("/data/x.bin"),
+            FileMetadata {
+                modified: Some(modified),
+                readonly: false,
+                inode: None,
+            },
+        );
+
+        let mut buffer = Vec::new();
+        write_csv_to(&mut buffer, &report).expect("write to buffer");
+        let records = parse_csv(&String::from_utf8(buffer).expect("valid UTF-8"));
+
+        assert_eq!(
+            records[0],
+            ["group_id", "hash", "size_bytes", "path", "mtime"]
+        );
+        let rows = &records[1..];
+        assert_eq!(rows.len(), 5);
+        let ids: std::collections::BTreeSet<&str> = rows.iter().map(|r| r[0].as_str()).collect();
+        assert_eq!(ids.len(), 2);
+        let mtime = DateTime::<Local>::from(modified)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        assert_eq!(rows[0], ["1", "large", "2048", "/data/x.bin", &mtime]);
+        assert_eq!(rows[2][3], "/data/z.bin");
+        assert_eq!(
+            rows[4],
+            ["2", "small", "10", "/data/say \"hi\", ok.txt", ""]
+        );
+    }
+
     #[test]
     fn test_write_output() {
         let dir = tempdir().expect("create temp dir");
@@ -565,16 +5095,45 @@ mod tests {
         duplicates.insert("dummy_hash".to_string(), vec![file1.clone(), file2.clone()]);
 
         let output_file = dir.path().join("output.txt");
-        let _res = write_output(
+        write_output(
             duplicates,
-            output_file.to_str().expect("valid UTF-8 path"),
+            output_file.to_str().expect("valid UTF-8"),
             "20250101 12:00:00",
             &[dir.path().to_path_buf()],
-        );
+        )
+        .expect("write report");
 
         let output = fs::read_to_string(&output_file).expect("read file");
         assert!(output.contains("Duplicate File Finder Report"));
+        // The size of the group is read from its files.
+        assert!(output.contains("Size: 17 bytes\n"));
         assert!(output.contains(file1.to_str().expect("valid UTF-8")));
         assert!(output.contains(file2.to_str().expect("valid UTF-8")));
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_output_non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempdir().expect("create temp dir");
+        let mut duplicates = HashMap::new();
+        duplicates.insert(
+            "dummy_hash".to_string(),
+            vec![dir.path().join("a.txt"), dir.path().join("b.txt")],
+        );
+
+        let output_file = dir.path().join(OsStr::from_bytes(b"report-\xff.txt"));
+        write_output(
+            duplicates,
+            &output_file,
+            "20250101 12:00:00",
+            &[dir.path().to_path_buf()],
+        )
+        .expect("write report");
+
+        let output = fs::read_to_string(&output_file).expect("read file");
+        assert!(output.contains("Duplicate File Finder Report"));
+    }
 }
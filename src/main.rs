@@ -1,102 +1,2659 @@
 #![warn(clippy::pedantic)]
 
 use chrono::Local;
-use clap::{ArgGroup, Parser};
-use duplicate_file_finder::{find_duplicates, find_duplicates_in_dirs, setup_logger, write_output};
-use log::{error, info};
-use std::path::PathBuf;
+use clap::{ArgGroup, Parser, Subcommand};
+use duplicate_file_finder::agent::{receive_indexes, send_index};
+use duplicate_file_finder::chunks::{
+    find_partial_duplicates, write_partial_duplicates_to, DEFAULT_MIN_SHARED,
+};
+use duplicate_file_finder::compare::{compare_dirs, compare_with_indexes, write_comparison_to};
+use duplicate_file_finder::dedupe::{
+    delete_duplicates, hardlink_duplicates, reflink_duplicates, remove_selected,
+    symlink_duplicates, trash_duplicates, write_print0_grouped_to, write_print0_to, DeleteSummary,
+    HardlinkSummary, KeepStrategy, SymlinkSummary,
+};
+use duplicate_file_finder::diff::{diff_reports, read_report};
+use duplicate_file_finder::dirs::write_dir_groups_to;
+use duplicate_file_finder::history::{self, Run};
+use duplicate_file_finder::index::{
+    create_index, find_duplicates_with_indexes, read_index, write_index, Index,
+};
+use duplicate_file_finder::metrics::Metrics;
+use duplicate_file_finder::names::{find_name_collisions, write_name_collisions_to};
+use duplicate_file_finder::notify::{self, SmtpSettings};
+use duplicate_file_finder::remote::{is_remote, RemoteRoot, DEFAULT_REMOTE_COMMAND};
+use duplicate_file_finder::s3::{index_buckets, is_s3, S3Root, S3Settings};
+use duplicate_file_finder::schedule::Schedule;
+use duplicate_file_finder::server;
+use duplicate_file_finder::similar::{
+    find_similar_images, write_similar_images_to, DEFAULT_MAX_DISTANCE,
+};
+use duplicate_file_finder::texts::{
+    find_similar_texts, write_similar_texts_to, DEFAULT_MIN_SIMILARITY,
+};
+use duplicate_file_finder::undo::{self, UndoAction, UndoEntry, DEFAULT_UNDO_LOG};
+use duplicate_file_finder::videos::{self, find_similar_videos, write_similar_videos_to};
+use duplicate_file_finder::watch::{self, Watcher};
+use duplicate_file_finder::{
+    find_duplicate_dirs, find_duplicates_with_options, format_size, parse_size, setup_logger,
+    update_duplicates, write_csv_to, write_fdupes_to, write_html_to, write_output_json,
+    write_output_report, write_output_to, write_rmlint_to, write_sqlite_to, write_summary_to,
+    DuplicateReport, FileType, GlobError, GlobSet, HashAlgo, HashCache, NameMatch, OutputOptions,
+    QuickHashSample, ReportFormat, ScanOptions, LOG_FILE,
+};
+use log::{error, info, warn};
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, IsTerminal, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+mod interactive;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const DEFAULT_REPORT_FILENAME: &str = "duplicate_file_report.txt";
+const DEFAULT_CSV_REPORT_FILENAME: &str = "duplicate_file_report.csv";
+const DEFAULT_HTML_REPORT_FILENAME: &str = "duplicate_file_report.html";
+const DEFAULT_JSON_REPORT_FILENAME: &str = "duplicate_file_report.json";
+const DEFAULT_SQLITE_REPORT_FILENAME: &str = "duplicate_file_report.sqlite";
+const DEFAULT_FDUPES_REPORT_FILENAME: &str = "duplicate_file_report_fdupes.txt";
+const DEFAULT_RMLINT_REPORT_FILENAME: &str = "duplicate_file_report_rmlint.json";
+const DEFAULT_COMPARISON_FILENAME: &str = "comparison_report.txt";
+const DEFAULT_SIMILAR_IMAGES_FILENAME: &str = "similar_images_report.txt";
+const DEFAULT_SIMILAR_VIDEOS_FILENAME: &str = "similar_videos_report.txt";
+const DEFAULT_SIMILAR_TEXTS_FILENAME: &str = "similar_texts_report.txt";
+const DEFAULT_PARTIAL_DUPLICATES_FILENAME: &str = "partial_duplicates_report.txt";
+const DEFAULT_NAME_COLLISIONS_FILENAME: &str = "name_collisions_report.txt";
+const DEFAULT_INDEX_FILENAME: &str = "duplicate_file_index.dff";
+/// Passing this as `--output` writes the report to stdout.
+const STDOUT_OUTPUT: &str = "-";
+/// Exit status of `verify-backup` when some source files have no copy in the backup.
+const BACKUP_INCOMPLETE_EXIT_CODE: i32 = 2;
+/// Exit status used after Ctrl-C, following the shell's 128 + SIGINT convention.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+/// Where the full hashes of a scan without --cache are checkpointed, so an
+/// interrupted scan can be continued with --resume.
+const CHECKPOINT_FILE: &str = "duplicate_finder.checkpoint.json";
+/// How often the full hashes computed so far are checkpointed.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_mins(1);
+
+/// Set by the Ctrl-C handler to stop the scan early.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
 
+// Each flag is an independent command-line switch.
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Parser)]
 #[command(
     author,
     version = VERSION,
     about = "Scans the specified directory recursively for duplicate files.",
-    group = ArgGroup::new("input").args(["directory", "directories"])
+    args_conflicts_with_subcommands = true,
+    group = ArgGroup::new("input").args(["directory", "directories"]),
+    group = ArgGroup::new("action").args(["hardlink", "symlink", "reflink", "delete", "trash"]),
+    group = ArgGroup::new("keeps_one").args(["delete", "trash", "print0"]),
+    group = ArgGroup::new("removes").args(["delete", "trash"]),
+    group = ArgGroup::new("by_name").args(["same_name_only", "name_collisions"]).multiple(true),
+    group = ArgGroup::new("repeats").args(["watch", "schedule"])
 )]
 struct Cli {
     /// Directory to scan for duplicates
     #[arg(group = "input")]
     directory: Option<PathBuf>,
 
-    /// One or more directories to scan for duplicates
+    /// One or more directories to scan for duplicates, indexes made by
+    /// `index create` on other machines, sftp://[user@]host[:port]/path
+    /// directories on machines reached with ssh, or `s3://bucket/prefix`
+    /// objects in S3-compatible storage
     #[arg(short = 'd', long = "directories", value_name = "DIR", num_args = 1.., group = "input")]
     directories: Option<Vec<PathBuf>>,
 
-    /// Output file or directory for the report
+    /// Instead of listing duplicates, report which scanned files already have
+    /// a copy in DIR and which do not
+    ///
+    /// Repeat to compare against several directories. DIR may also be an
+    /// index made by `index create`, an sftp:// directory or an s3://
+    /// bucket. The report is
+    /// written to `comparison_report.txt` unless --output is given.
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["format", "print0", "summary", "duplicate_dirs", "action"]
+    )]
+    against: Vec<PathBuf>,
+
+    /// Command that runs this tool on the machines of sftp:// directories,
+    /// which index their own files
+    #[arg(long, value_name = "CMD", default_value = DEFAULT_REMOTE_COMMAND)]
+    remote_command: String,
+
+    /// Coordinate a scan across machines: wait on ADDR for the indexes
+    /// that `agent` sends from each of them, then report the duplicates
+    /// among all of them and any directories given
+    ///
+    /// There is no authentication, so only listen where every agent is
+    /// trusted.
+    #[arg(long, value_name = "ADDR", conflicts_with = "against")]
+    coordinate: Option<SocketAddr>,
+
+    /// How many agents --coordinate waits for
+    #[arg(long, value_name = "N", default_value_t = 1, requires = "coordinate")]
+    agents: usize,
+
+    /// Instead of listing duplicates, group PNG and JPEG images that look
+    /// alike, such as resized or re-encoded copies
+    ///
+    /// The report is written to `similar_images_report.txt` unless --output
+    /// is given.
+    #[arg(
+        long,
+        conflicts_with_all = ["format", "print0", "summary", "duplicate_dirs", "action", "against"]
+    )]
+    images_similar: bool,
+
+    /// How many of the 64 bits of their perceptual hashes two images may
+    /// differ in to count as similar
+    #[arg(
+        long,
+        value_name = "BITS",
+        default_value_t = DEFAULT_MAX_DISTANCE,
+        value_parser = clap::value_parser!(u32).range(..=64),
+        requires = "images_similar"
+    )]
+    image_distance: u32,
+
+    /// Instead of listing duplicates, group videos that show the same footage,
+    /// such as re-encoded copies (experimental; needs `ffmpeg` and `ffprobe`)
+    ///
+    /// The report is written to `similar_videos_report.txt` unless --output
+    /// is given.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "format", "print0", "summary", "duplicate_dirs", "action", "against", "images_similar",
+        ]
+    )]
+    videos_similar: bool,
+
+    /// How many of the 64 bits of their hashes the sampled frames of two
+    /// videos may differ in on average to count as similar
+    #[arg(
+        long,
+        value_name = "BITS",
+        default_value_t = videos::DEFAULT_MAX_DISTANCE,
+        value_parser = clap::value_parser!(u32).range(..=64),
+        requires = "videos_similar"
+    )]
+    video_distance: u32,
+
+    /// Instead of listing duplicates, list pairs of text files that are
+    /// mostly the same, such as copies of a config file that have drifted
+    /// apart
+    ///
+    /// The report is written to `similar_texts_report.txt` unless --output
+    /// is given.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "format", "print0", "summary", "duplicate_dirs", "action", "against", "images_similar",
+            "videos_similar",
+        ]
+    )]
+    text_similar: bool,
+
+    /// The percentage of their three-word sequences two text files must
+    /// share to be listed
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value_t = DEFAULT_MIN_SIMILARITY,
+        value_parser = clap::value_parser!(u32).range(1..=100),
+        requires = "text_similar"
+    )]
+    text_similarity: u32,
+
+    /// Instead of listing duplicates, list pairs of files that share much of
+    /// their contents, such as disk image snapshots or a log file and a copy
+    /// it has grown into, and estimate what deduplicating them by chunk
+    /// would save
+    ///
+    /// The report is written to `partial_duplicates_report.txt` unless
+    /// --output is given.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "format", "print0", "summary", "duplicate_dirs", "action", "against", "images_similar",
+            "videos_similar", "text_similar",
+        ]
+    )]
+    partial: bool,
+
+    /// The percentage of the smaller of two files that must be made of
+    /// chunks the other holds too for the pair to be listed
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value_t = DEFAULT_MIN_SHARED,
+        value_parser = clap::value_parser!(u32).range(1..=100),
+        requires = "partial"
+    )]
+    min_shared: u32,
+
+    /// Instead of listing duplicates, list file names shared by files with
+    /// different contents, such as copies of a document edited separately
+    ///
+    /// The report is written to `name_collisions_report.txt` unless --output
+    /// is given.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "format", "print0", "summary", "duplicate_dirs", "action", "against", "images_similar",
+            "videos_similar", "text_similar", "partial", "same_name_only",
+        ]
+    )]
+    name_collisions: bool,
+
+    /// Output file or directory for the report, or `-` for stdout
     #[arg(short, long, value_name = "FILE")]
     output: Option<PathBuf>,
+
+    /// Report layout
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
+
+    /// Glob pattern for paths to skip, relative to each scanned directory (repeatable)
+    #[arg(short, long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Glob pattern for files to scan, relative to each scanned directory;
+    /// other files are skipped (repeatable)
+    #[arg(short, long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Directory to leave out entirely, as a path or as a glob such as
+    /// `node_modules` that matches a directory name anywhere (repeatable)
+    #[arg(long, value_name = "DIR")]
+    exclude_dir: Vec<String>,
+
+    /// Regular expression a file's absolute path must match to be scanned,
+    /// for selections globs cannot express (repeatable)
+    #[arg(long, value_name = "REGEX")]
+    include_regex: Vec<String>,
+
+    /// Regular expression for absolute file paths to skip (repeatable)
+    #[arg(long, value_name = "REGEX")]
+    exclude_regex: Vec<String>,
+
+    /// Scan only files with an extension of this kind (repeatable)
+    #[arg(long = "type", value_enum, value_name = "TYPE")]
+    file_type: Vec<FileType>,
+
+    /// Ignore file in `.gitignore` syntax whose patterns are relative to each
+    /// scanned directory; `.dupeignore` files are always read (repeatable)
+    #[arg(long, value_name = "FILE")]
+    ignore_file: Vec<PathBuf>,
+
+    /// Skip paths ignored by `.gitignore` files and `.git/info/exclude`, and
+    /// `.git` directories
+    #[arg(long)]
+    respect_gitignore: bool,
+
+    /// Ignore files smaller than this size (e.g. 4KB; use 1 to skip empty files)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    min_size: Option<u64>,
+
+    /// Ignore files larger than this size (e.g. 2GB)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_size: Option<u64>,
+
+    /// Hide progress bars and status messages (implied when stdout is not a terminal)
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Write progress to stderr as JSON lines instead of drawing progress bars
+    #[arg(long)]
+    progress_json: bool,
+
+    /// List each file's modification time, size and read-only state in the text report
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Hash algorithm used to confirm duplicates; xxh3 and md5 are faster but not collision resistant
+    #[arg(long, visible_alias = "hash", value_enum, default_value_t = HashAlgo::Sha256)]
+    hash_algo: HashAlgo,
+
+    /// Bytes sampled from each block of a file by the quick hash (e.g. 64KB)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "8KB")]
+    quick_hash_bytes: u64,
+
+    /// Blocks of each file sampled by the quick hash; first-middle-last also reads the middle
+    #[arg(long, value_enum, default_value_t = QuickHashSample::FirstLast)]
+    quick_hash_sample: QuickHashSample,
+
+    /// Compare files byte for byte after hashing, before reporting or changing them
+    #[arg(long)]
+    paranoid: bool,
+
+    /// Compare groups of two files byte for byte instead of hashing them, whatever their size
+    #[arg(long)]
+    compare_pairs: bool,
+
+    /// Compare PNG and JPEG images by their pixels or image data, so copies that differ only in
+    /// metadata such as EXIF or XMP are duplicates
+    #[arg(long, conflicts_with_all = ["images_similar", "videos_similar", "text_similar", "partial"])]
+    ignore_image_metadata: bool,
+
+    /// Compare MP3, FLAC and M4A files by their audio alone, so copies with different tags or
+    /// artwork are duplicates
+    #[arg(long, conflicts_with_all = ["images_similar", "videos_similar", "text_similar", "partial"])]
+    audio_content: bool,
+
+    /// Also compare the files inside zip, tar and tar.gz archives, reported as
+    /// `archive.zip!/inner/path`
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "action", "duplicate_dirs", "against", "images_similar", "videos_similar",
+            "text_similar", "partial",
+        ]
+    )]
+    scan_archives: bool,
+
+    /// Compare .gz, .bz2, .xz and .zst files by their decompressed contents, so `log.txt.gz`
+    /// matches `log.txt`
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "action", "duplicate_dirs", "against", "images_similar", "videos_similar",
+            "text_similar", "partial",
+        ]
+    )]
+    decompress: bool,
+
+    /// Keep the list of files found in a temporary file instead of in memory, for huge scans
+    #[arg(long)]
+    low_memory: bool,
+
+    /// Maximum number of worker threads (0 uses one per core)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    threads: usize,
+
+    /// Maximum number of threads reading files for hashing (0 uses --threads)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    io_threads: usize,
+
+    /// Read several files at once from spinning disks too, instead of one at a time
+    #[arg(long)]
+    parallel_hdd: bool,
+
+    /// Limit reading while hashing to this many megabytes per second (0 for no limit)
+    #[arg(long, value_name = "MBPS", default_value_t = 0)]
+    max_read_mbps: u64,
+
+    /// Skip dotfiles, and files with the hidden or system attribute on Windows
+    #[arg(long, overrides_with = "include_hidden")]
+    skip_hidden: bool,
+
+    /// Scan hidden files too, the default; overrides an earlier --skip-hidden
+    #[arg(long, overrides_with = "skip_hidden")]
+    include_hidden: bool,
+
+    /// Follow symbolic links to directories and files, counting each underlying file once
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Do not descend into directories on other filesystems, such as mounts
+    #[arg(short = 'x', long)]
+    one_file_system: bool,
+
+    /// Descend at most N levels: 0 is the directory itself, 1 its immediate children
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Only report identical files that also share a file name
+    #[arg(long)]
+    same_name_only: bool,
+
+    /// Compare file names case-insensitively with --same-name-only or
+    /// --name-collisions
+    #[arg(long, requires = "by_name")]
+    ignore_case: bool,
+
+    /// Reuse full hashes of unchanged files from this cache file, updating it afterwards
+    #[arg(long, value_name = "FILE", global = true)]
+    cache: Option<PathBuf>,
+
+    /// Reuse the full hashes checkpointed by an interrupted scan instead of starting over
+    #[arg(long, global = true)]
+    resume: bool,
+
+    /// Also report directory trees whose entire contents are duplicated
+    #[arg(long = "dirs")]
+    duplicate_dirs: bool,
+
+    /// Replace duplicates with hard links to one copy (dry run unless --confirm is given)
+    #[arg(long)]
+    hardlink: bool,
+
+    /// Replace duplicates with symbolic links to one copy, across filesystems
+    /// (dry run unless --confirm is given)
+    #[arg(long)]
+    symlink: bool,
+
+    /// Replace duplicates with reflink clones that share one copy's data but
+    /// stay independent files (Linux on Btrfs or XFS; dry run unless --confirm
+    /// is given)
+    #[arg(long)]
+    reflink: bool,
+
+    /// Delete all but one copy of each duplicate (dry run unless --confirm is given)
+    #[arg(long)]
+    delete: bool,
+
+    /// Move all but one copy of each duplicate to the trash, where they can
+    /// be restored (dry run unless --confirm is given)
+    #[arg(long)]
+    trash: bool,
+
+    /// Print only the redundant copies to stdout, NUL-separated, for `xargs -0`
+    ///
+    /// One copy of each group, chosen by --keep, is left out so that piping to
+    /// `rm` never deletes every copy. No report file is written.
+    #[arg(
+        long,
+        conflicts_with_all = ["output", "format", "verbose", "duplicate_dirs", "action"]
+    )]
+    print0: bool,
+
+    /// With --print0, write SEP as a record of its own between groups; an
+    /// empty SEP marks each boundary with two NULs in a row
+    #[arg(long, value_name = "SEP", requires = "print0")]
+    group_separator: Option<String>,
+
+    /// Print only totals and the ten most wasteful groups instead of every file
+    ///
+    /// The summary goes to stdout, or to the file given with --output.
+    #[arg(
+        long,
+        conflicts_with_all = ["format", "verbose", "print0", "duplicate_dirs", "action"]
+    )]
+    summary: bool,
+
+    /// After the scan, keep watching the directories and scan again whenever
+    /// files are created, modified or deleted, rewriting the report each
+    /// time, until interrupted with Ctrl-C. Changes are noticed as they
+    /// happen on Linux only; other systems walk the directories again
+    /// every five seconds
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "action", "print0", "against", "images_similar", "videos_similar", "text_similar",
+            "partial", "name_collisions",
+        ]
+    )]
+    watch: bool,
+
+    /// Instead of scanning once, stay running and scan at the times given
+    /// by a cron-style schedule such as "0 3 * * 0" (03:00 every Sunday),
+    /// rewriting the report and running any actions each time, until
+    /// interrupted with Ctrl-C
+    #[arg(
+        long,
+        value_name = "CRON",
+        value_parser = Schedule::parse,
+        conflicts_with_all = [
+            "watch", "interactive", "print0", "against", "images_similar", "videos_similar",
+            "text_similar", "partial", "name_collisions",
+        ]
+    )]
+    schedule: Option<Schedule>,
+
+    /// POST a JSON summary of the results to URL when the scan finishes,
+    /// and after every rescan with --watch (uses curl)
+    #[arg(long, value_name = "URL")]
+    notify_webhook: Option<String>,
+
+    /// Email the report to ADDR when the scan finishes, through the SMTP
+    /// server set in --smtp-config or the `DFF_SMTP_*` environment variables
+    /// (uses curl; repeat or separate with commas for several recipients)
+    #[arg(
+        long,
+        value_name = "ADDR",
+        value_delimiter = ',',
+        conflicts_with_all = ["print0", "watch"]
+    )]
+    email_to: Vec<String>,
+
+    /// File of `key = value` SMTP settings: url, from, user and password
+    #[arg(long, value_name = "FILE", requires = "email_to")]
+    smtp_config: Option<PathBuf>,
+
+    /// While watching or running on a schedule, serve Prometheus metrics of
+    /// the scans at /metrics on ADDR
+    #[arg(long, value_name = "ADDR", requires = "repeats")]
+    metrics: Option<SocketAddr>,
+
+    /// Ask which copies to keep in each group before --delete or --trash
+    /// removes the others, instead of applying --keep
+    #[arg(long, requires = "removes", conflicts_with = "keep")]
+    interactive: bool,
+
+    /// Which copy --delete, --trash or --print0 keeps in each group
+    #[arg(long, value_enum, default_value_t = KeepStrategy::First, requires = "keeps_one")]
+    keep: KeepStrategy,
+
+    /// Actually modify files when using --hardlink, --symlink, --reflink, --delete or --trash
+    #[arg(long, requires = "action")]
+    confirm: bool,
+
+    /// Journal of confirmed --delete, --trash, --hardlink and --symlink runs, read by `restore`
+    #[arg(long, value_name = "FILE", global = true, default_value = DEFAULT_UNDO_LOG)]
+    undo_log: PathBuf,
+
+    /// Record the totals of every completed scan in this file, read by
+    /// `history`; no history is kept without it
+    #[arg(long, value_name = "FILE", global = true)]
+    history: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Undo the most recent --delete, --trash, --hardlink or --symlink run
+    /// recorded in the undo log (dry run unless --confirm is given)
+    Restore {
+        /// Actually restore the files
+        #[arg(long)]
+        confirm: bool,
+    },
+    /// List the files in SOURCE whose contents are missing from BACKUP, to
+    /// confirm a copy is complete before deleting the original
+    ///
+    /// Files count as backed up wherever they are in BACKUP and whatever
+    /// their name. The exit status is 2 if any file is missing.
+    VerifyBackup {
+        /// The original files
+        source: PathBuf,
+        /// Where they should have been copied to
+        backup: PathBuf,
+    },
+    /// Show the duplicate groups that are new, resolved or changed since a
+    /// report saved with --format json or --format sqlite
+    ///
+    /// Without NEW, the directories of OLD are scanned again and compared
+    /// instead, with the scan options given after `--`, which should be
+    /// those OLD was made with: `diff old.json -- --exclude '*.tmp'`.
+    Diff {
+        /// The earlier report
+        old: PathBuf,
+        /// A later report to compare it with
+        new: Option<PathBuf>,
+        /// Options for scanning the directories of OLD again
+        #[arg(last = true, value_name = "SCAN OPTIONS", conflicts_with = "new")]
+        scan_options: Vec<OsString>,
+    },
+    /// Manage indexes of the files of this machine, which take the place of
+    /// a directory to find duplicates on another machine without copying
+    /// any data between them
+    Index {
+        #[command(subcommand)]
+        command: IndexCommand,
+    },
+    /// Hash every file below DIR and send its path, size and hash to the
+    /// machine coordinating a scan with --coordinate
+    Agent {
+        /// The address the coordinator listens on
+        #[arg(long, value_name = "ADDR")]
+        coordinator: String,
+        /// The directories to hash
+        #[arg(value_name = "DIR", required = true)]
+        dirs: Vec<PathBuf>,
+        /// Hash algorithm, which must be the coordinator's
+        #[arg(long, visible_alias = "hash", value_enum, default_value_t = HashAlgo::Sha256)]
+        hash_algo: HashAlgo,
+    },
+    /// Show how the duplicates found by past scans have changed, from the
+    /// totals each completed scan recorded in the --history file
+    History {
+        /// How many of the latest runs of each set of directories to list
+        #[arg(long, value_name = "N", default_value_t = 10)]
+        last: usize,
+    },
+    /// Run a local HTTP server that starts scans, reports their progress
+    /// and results as JSON, and acts on the duplicates found
+    ///
+    /// Open the address in a browser for a dashboard doing the same.
+    ///
+    /// There is no authentication: anyone who can connect can delete
+    /// files, so only listen on addresses every client is trusted on.
+    Serve {
+        /// The address and port to listen on
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:7878")]
+        listen: SocketAddr,
+    },
+}
+
+#[derive(Subcommand)]
+enum IndexCommand {
+    /// Hash every file below DIR and save its path, size and hash
+    ///
+    /// Pass the index instead of a directory, to scan or to --against, on
+    /// another machine to find the duplicates there of the files indexed.
+    Create {
+        /// The directories to index
+        #[arg(value_name = "DIR", required = true)]
+        dirs: Vec<PathBuf>,
+        /// File to save the index to
+        #[arg(short, long, value_name = "FILE", default_value = DEFAULT_INDEX_FILENAME)]
+        output: PathBuf,
+        /// Hash algorithm of the index; files compared with it are hashed
+        /// with the same one
+        #[arg(long, visible_alias = "hash", value_enum, default_value_t = HashAlgo::Sha256)]
+        hash_algo: HashAlgo,
+    },
 }
 
 fn main() {
     setup_logger().expect("Failed to initialize logger");
 
     let cli = Cli::parse();
+    if let Some(Command::Restore { confirm }) = cli.command {
+        run_restore(&cli.undo_log, confirm);
+        return;
+    }
+    if let Some(Command::History { last }) = cli.command {
+        run_history(cli.history.as_deref(), last);
+        return;
+    }
+    if let Some(Command::Serve { listen }) = cli.command {
+        run_serve(listen, &cli.undo_log);
+        return;
+    }
+    install_interrupt_handler();
+    if let Some(command) = &cli.command {
+        run_command(&cli, command);
+        return;
+    }
+    let to_stdout = cli.print0
+        || (cli.summary && cli.output.is_none())
+        || cli.output.as_deref() == Some(Path::new(STDOUT_OUTPUT));
+    // Status lines would interleave with the report when it goes to stdout.
+    let quiet = cli.quiet || to_stdout || !std::io::stdout().is_terminal();
 
-    let dirs: Vec<PathBuf> = if let Some(multi) = cli.directories.clone() {
-        multi
-    } else if let Some(dir) = cli.directory.clone() {
-        vec![dir]
-    } else {
-        vec![std::env::current_dir().expect("cannot determine current directory")]
-    };
+    let dirs = scan_dirs(&cli);
 
+    let default_filename = default_filename(&cli);
     let mut output_file = cli
         .output
-        .unwrap_or_else(|| PathBuf::from(DEFAULT_REPORT_FILENAME));
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(default_filename));
 
-    if output_file.is_dir() {
-        output_file = output_file.join(DEFAULT_REPORT_FILENAME);
+    if !to_stdout && output_file.is_dir() {
+        output_file = output_file.join(default_filename);
     }
-    for d in &dirs {
-        if !d.exists() || !d.is_dir() {
-            eprintln!("Error: '{}' is not a valid directory", d.display());
-            error!("Invalid directory: {}", d.display());
+    validate_args(&cli, &dirs);
+    let (dirs, mut indexes, buckets) = read_indexes(&cli, &dirs, quiet);
+
+    let start_time = Local::now().format("%Y%m%d %H:%M:%S").to_string();
+
+    announce_scan(&cli, &dirs, quiet);
+    for index in &indexes {
+        announce_index(index, quiet);
+    }
+    if let Some(addr) = cli.coordinate {
+        indexes.extend(receive_from_agents(&cli, addr, quiet));
+    }
+    if !to_stdout {
+        status(
+            quiet,
+            &format!("Output will be saved to: {}", output_file.display()),
+        );
+    }
+
+    let cache = load_hashes(&cli, quiet);
+    let target = (!to_stdout).then_some(output_file.as_path());
+    if run_search(&cli, &dirs, target, &cache, &start_time, quiet) {
+        return;
+    }
+    // Listen before scanning, so a busy address is reported straight away
+    // and the first scan is counted.
+    let metrics = cli.metrics.map(serve_metrics);
+    if let Some(schedule) = &cli.schedule {
+        run_schedule(
+            &cli,
+            schedule,
+            &dirs,
+            target,
+            &cache,
+            metrics.as_deref(),
+            quiet,
+        );
+        return;
+    }
+    let started = Instant::now();
+    let report = scan(&cli, target, &cache, quiet, |options| {
+        let bucket_index = bucket_index(&buckets, &dirs, options);
+        let indexes: Vec<Index> = indexes.into_iter().chain(bucket_index).collect();
+        find_duplicates_with_indexes(&dirs, &indexes, options)
+    });
+    if let Some(metrics) = &metrics {
+        metrics.record(&report, started.elapsed());
+    }
+    deliver(&cli, &report, &dirs, target, &cache, &start_time, quiet);
+    if report.interrupted {
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+    if cli.watch {
+        run_watch(
+            &cli,
+            &dirs,
+            target,
+            &cache,
+            report,
+            metrics.as_deref(),
+            quiet,
+        );
+    }
+}
+
+/// Saves the hashes of a finished scan and writes its report to
+/// `output_file`, or stdout when `None`, then runs the actions asked for.
+fn deliver(
+    cli: &Cli,
+    report: &DuplicateReport,
+    dirs: &[PathBuf],
+    output_file: Option<&Path>,
+    cache: &HashCache,
+    start_time: &str,
+    quiet: bool,
+) {
+    if !report.errors.is_empty() {
+        eprintln!(
+            "{} files skipped due to errors; see {LOG_FILE} for details.",
+            report.errors.len()
+        );
+    }
+    if report.interrupted {
+        eprintln!("Scan interrupted; writing the duplicates confirmed so far.");
+    }
+    save_hashes(cli, cache, report.interrupted);
+    // A comparison with an index is no scan of this machine to follow.
+    if !report.interrupted && !reads_indexes(cli) {
+        save_history(cli, report);
+    }
+
+    if cli.summary {
+        save_summary(report, output_file, quiet);
+    } else if report.is_empty() {
+        status(quiet, "No duplicate files found.");
+    } else if cli.print0 {
+        print_paths0(report, cli.keep, cli.group_separator.as_deref());
+    } else {
+        match save_report(report, cli, output_file, start_time, dirs) {
+            Ok(()) => match output_file {
+                Some(path) => status(
+                    quiet,
+                    &format!("Duplicate file report saved to {}", path.display()),
+                ),
+                None => info!("Duplicate file report written to stdout"),
+            },
+            Err(e) => {
+                if output_file.is_none() {
+                    exit_if_pipe_closed(&*e);
+                }
+                eprintln!("Error writing output: {e}");
+                error!("Failed to write output: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let wrote_report = cli.summary || !(report.is_empty() || cli.print0);
+    if let Some(url) = &cli.notify_webhook {
+        notify_webhook(url, report, output_file.filter(|_| wrote_report));
+    }
+    if !cli.email_to.is_empty() {
+        email_report(cli, report, output_file.filter(|_| wrote_report));
+    }
+    if wrote_report && !cli.summary && !report.interrupted {
+        run_actions(cli, report, output_file, quiet);
+    }
+}
+
+/// Appends the totals of `report` to the history if one is kept, warning
+/// rather than failing if that does not work.
+fn save_history(cli: &Cli, report: &DuplicateReport) {
+    let Some(path) = &cli.history else {
+        return;
+    };
+    if let Err(e) = history::record(path, &Run::from_report(report)) {
+        eprintln!(
+            "Warning: could not record the scan in {}: {e}",
+            path.display()
+        );
+        warn!("Failed to record the scan in {}: {e}", path.display());
+    }
+}
+
+/// Emails `report` to the recipients of `--email-to`, warning rather than
+/// failing if that does not work.
+fn email_report(cli: &Cli, report: &DuplicateReport, report_path: Option<&Path>) {
+    let to = cli.email_to.join(", ");
+    let sent = SmtpSettings::load(cli.smtp_config.as_deref())
+        .and_then(|settings| notify::email_report(&settings, &cli.email_to, report, report_path));
+    match sent {
+        Ok(()) => info!("Emailed the report to {to}"),
+        Err(e) => {
+            eprintln!("Warning: could not email the report to {to}: {e}");
+            warn!("Failed to email the report to {to}: {e}");
+        }
+    }
+}
+
+/// Posts the summary of `report` to the webhook at `url`, warning rather
+/// than failing if that does not work.
+fn notify_webhook(url: &str, report: &DuplicateReport, report_path: Option<&Path>) {
+    // A relative path means little to whoever receives the notification.
+    let report_path = report_path.map(|path| std::path::absolute(path).unwrap_or(path.into()));
+    match notify::post_json(url, &notify::summary_json(report, report_path.as_deref())) {
+        Ok(()) => info!("Notified {url}"),
+        Err(e) => {
+            eprintln!("Warning: could not notify {url}: {e}");
+            warn!("Failed to notify {url}: {e}");
+        }
+    }
+}
+
+/// Updates the duplicates of `dirs` whenever files change below them for
+/// `--watch`, starting from those of the first scan, until Ctrl-C. Only
+/// files sharing a size with a changed one are hashed again, and the full
+/// hashes of those that did not change come from `cache`.
+fn run_watch(
+    cli: &Cli,
+    dirs: &[PathBuf],
+    output_file: Option<&Path>,
+    cache: &HashCache,
+    mut previous: DuplicateReport,
+    metrics: Option<&Metrics>,
+    quiet: bool,
+) {
+    let mut ignored = own_files(cli, output_file);
+    let mut temp = checkpoint_path(cli).as_os_str().to_owned();
+    temp.push(".tmp");
+    ignored.push(temp.into());
+    let mut watcher = match Watcher::new(dirs, ignored) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("Error: cannot watch for changes: {e}");
+            error!("Cannot watch for changes: {e}");
             std::process::exit(1);
         }
+    };
+    status(quiet, "Watching for changes; press Ctrl-C to stop.");
+    loop {
+        let changed = match watcher.wait(watch::DEFAULT_SETTLE, &INTERRUPTED) {
+            Ok(changed) if changed.is_empty() => return,
+            Ok(changed) => changed,
+            Err(e) => {
+                eprintln!("Error: cannot watch for changes: {e}");
+                error!("Cannot watch for changes: {e}");
+                std::process::exit(1);
+            }
+        };
+        info!(
+            "{} paths changed, first {}",
+            changed.len(),
+            changed[0].display()
+        );
+        status(
+            quiet,
+            &format!(
+                "{} paths changed; updating the duplicates...",
+                changed.len()
+            ),
+        );
+
+        let start_time = Local::now().format("%Y%m%d %H:%M:%S").to_string();
+        let started = Instant::now();
+        let report = scan(cli, output_file, cache, quiet, |options| {
+            update_duplicates(dirs, &previous, &changed, options)
+        });
+        if let Some(metrics) = metrics {
+            metrics.record(&report, started.elapsed());
+        }
+        deliver(cli, &report, dirs, output_file, cache, &start_time, quiet);
+        let diff = diff_reports(&previous, &report);
+        let (added, changed, resolved) =
+            (diff.added.len(), diff.changed.len(), diff.resolved.len());
+        info!("Rescan: {added} new duplicate groups, {changed} changed, {resolved} resolved");
+        status(
+            quiet,
+            &format!(
+                "{added} new duplicate groups, {changed} changed, {resolved} no longer \
+                 duplicated; watching for changes..."
+            ),
+        );
+        if report.interrupted {
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+        previous = report;
     }
+}
 
-    let start_time = Local::now().format("%Y%m%d %H:%M:%S").to_string();
+/// Scans `dirs` at every time `schedule` gives for `--schedule`, until
+/// Ctrl-C. Interrupting a scan exits as an interrupted scan does, while
+/// interrupting the wait between scans exits successfully.
+fn run_schedule(
+    cli: &Cli,
+    schedule: &Schedule,
+    dirs: &[PathBuf],
+    output_file: Option<&Path>,
+    cache: &HashCache,
+    metrics: Option<&Metrics>,
+    quiet: bool,
+) {
+    loop {
+        let Some(next) = schedule.next_after(Local::now()) else {
+            eprintln!("Error: the schedule never runs");
+            error!("The schedule never runs");
+            std::process::exit(1);
+        };
+        let when = next.format("%Y-%m-%d %H:%M");
+        info!("Next scheduled scan at {when}");
+        status(
+            quiet,
+            &format!("Next scan at {when}; press Ctrl-C to stop."),
+        );
+        // Sleep in short steps, checking the clock rather than counting,
+        // so a suspended machine or a clock change does not delay the scan.
+        loop {
+            if INTERRUPTED.load(Ordering::Relaxed) {
+                return;
+            }
+            let left = (next - Local::now()).to_std().unwrap_or_default();
+            if left.is_zero() {
+                break;
+            }
+            std::thread::sleep(left.min(Duration::from_secs(1)));
+        }
+
+        let start_time = Local::now().format("%Y%m%d %H:%M:%S").to_string();
+        let started = Instant::now();
+        let report = scan(cli, output_file, cache, quiet, |options| {
+            find_duplicates_with_options(dirs, options)
+        });
+        if let Some(metrics) = metrics {
+            metrics.record(&report, started.elapsed());
+        }
+        deliver(cli, &report, dirs, output_file, cache, &start_time, quiet);
+        if report.interrupted {
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+        info!(
+            "Scheduled scan found {} duplicate groups",
+            report.total_groups
+        );
+    }
+}
+
+/// Runs the comparison or similarity search asked for instead of a search
+/// for duplicates, if any, returning whether one ran.
+fn run_search(
+    cli: &Cli,
+    dirs: &[PathBuf],
+    target: Option<&Path>,
+    cache: &HashCache,
+    start_time: &str,
+    quiet: bool,
+) -> bool {
+    if !cli.against.is_empty() {
+        run_compare(cli, dirs, target, cache, start_time, quiet);
+    } else if cli.name_collisions {
+        run_name_collisions(cli, dirs, target, cache, start_time, quiet);
+    } else if cli.partial {
+        run_partial(cli, dirs, target, cache, start_time, quiet);
+    } else if cli.text_similar {
+        run_similar_texts(cli, dirs, target, cache, start_time, quiet);
+    } else if cli.videos_similar {
+        run_similar_videos(cli, dirs, target, cache, start_time, quiet);
+    } else if cli.images_similar {
+        run_similar(cli, dirs, target, cache, start_time, quiet);
+    } else {
+        return false;
+    }
+    true
+}
+
+/// Makes the first Ctrl-C stop the scan gracefully instead of killing the process.
+///
+/// The default handler is restored once the flag is set, so a second Ctrl-C
+/// exits immediately.
+#[cfg(unix)]
+fn install_interrupt_handler() {
+    extern "C" fn on_sigint(_signal: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+        // SAFETY: `signal` is async-signal-safe and SIG_DFL is a valid disposition.
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+        }
+    }
+
+    let handler = on_sigint as extern "C" fn(libc::c_int);
+    // SAFETY: the handler only touches an atomic and calls `signal`, both of
+    // which are async-signal-safe.
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+/// Makes the first Ctrl-C stop the scan gracefully instead of killing the process.
+///
+/// A second Ctrl-C is passed on to the default handler, which exits
+/// immediately, as do Ctrl-Break and closing the console.
+#[cfg(windows)]
+fn install_interrupt_handler() {
+    const CTRL_C_EVENT: u32 = 0;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleCtrlHandler(
+            handler: Option<unsafe extern "system" fn(u32) -> i32>,
+            add: i32,
+        ) -> i32;
+    }
+
+    // Windows runs the handler on a thread of its own, so touching the
+    // atomic is all it needs to do.
+    unsafe extern "system" fn on_ctrl_c(event: u32) -> i32 {
+        i32::from(event == CTRL_C_EVENT && !INTERRUPTED.swap(true, Ordering::Relaxed))
+    }
+
+    // SAFETY: `on_ctrl_c` matches the `PHANDLER_ROUTINE` signature and stays
+    // valid for the life of the process.
+    if unsafe { SetConsoleCtrlHandler(Some(on_ctrl_c), 1) } == 0 {
+        error!(
+            "Failed to install the Ctrl-C handler: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+/// Ctrl-C keeps its default behaviour on platforms without console signals.
+#[cfg(not(any(unix, windows)))]
+fn install_interrupt_handler() {
+    let _ = &INTERRUPTED;
+    let _ = Ordering::Relaxed;
+}
+
+/// Returns the directories to scan: those given, or else the current one.
+fn scan_dirs(cli: &Cli) -> Vec<PathBuf> {
+    if let Some(multi) = cli.directories.clone() {
+        multi
+    } else if let Some(dir) = cli.directory.clone() {
+        vec![dir]
+    } else if cli.coordinate.is_some() {
+        // The coordinator need not scan any files of its own.
+        Vec::new()
+    } else {
+        vec![std::env::current_dir().expect("cannot determine current directory")]
+    }
+}
 
+/// Logs and prints which directories are about to be scanned.
+fn announce_scan(cli: &Cli, dirs: &[PathBuf], quiet: bool) {
     if dirs.len() == 1 {
-        println!("Scanning directory: {}", dirs[0].display());
         info!("Starting duplicate file detection in {}", dirs[0].display());
-    } else {
-        println!("Scanning {} directories", dirs.len());
+        status(quiet, &format!("Scanning directory: {}", dirs[0].display()));
+    } else if !dirs.is_empty() {
         info!(
             "Starting duplicate file detection across {} directories",
             dirs.len()
         );
+        status(quiet, &format!("Scanning {} directories", dirs.len()));
+    }
+    for dir in &cli.against {
+        status(quiet, &format!("Comparing against: {}", dir.display()));
     }
-    println!("Output will be saved to: {}", output_file.display());
+}
 
-    let duplicates = if dirs.len() == 1 {
-        find_duplicates(&dirs[0])
-    } else {
-        find_duplicates_in_dirs(&dirs)
-    };
+/// Logs and prints which index is compared with the files scanned.
+fn announce_index(index: &Index, quiet: bool) {
+    status(
+        quiet,
+        &format!(
+            "Comparing with the index of {} files on {}, made {}",
+            index.entries.len(),
+            index.host,
+            index.created.format("%Y-%m-%d %H:%M")
+        ),
+    );
+}
 
-    if duplicates.is_empty() {
-        println!("No duplicate files found.");
-        info!("No duplicate files found.");
-    } else {
-        match write_output(
-            duplicates,
-            output_file.to_str().expect("valid UTF-8 path"),
-            &start_time,
-            &dirs,
-        ) {
-            Ok(()) => {
-                println!("Duplicate file report saved to {}", output_file.display());
-                info!("Duplicate file report saved to {}", output_file.display());
+/// Returns whether any of the inputs given to scan or compare against is a
+/// file, which is read as an index, a remote directory, which is indexed
+/// by its machine, or objects in S3, which are indexed as they download,
+/// or agents send indexes to this machine.
+fn reads_indexes(cli: &Cli) -> bool {
+    cli.coordinate.is_some()
+        || scan_dirs(cli)
+            .iter()
+            .chain(&cli.against)
+            .any(|path| path.is_file() || is_remote(path) || is_s3(path))
+}
+
+/// Splits `inputs` into the directories to scan, the indexes read from the
+/// files among them or made by the machines of remote directories, and the
+/// S3 roots, exiting if an index cannot be read or made.
+fn read_indexes(
+    cli: &Cli,
+    inputs: &[PathBuf],
+    quiet: bool,
+) -> (Vec<PathBuf>, Vec<Index>, Vec<S3Root>) {
+    let (buckets, inputs): (Vec<&PathBuf>, Vec<&PathBuf>) =
+        inputs.iter().partition(|path| is_s3(path));
+    // Roots were checked by validate_args.
+    let buckets = buckets
+        .into_iter()
+        .filter_map(|path| S3Root::parse(&path.to_string_lossy()).ok())
+        .collect();
+    let (files, dirs): (Vec<&PathBuf>, Vec<&PathBuf>) = inputs
+        .into_iter()
+        .partition(|path| path.is_file() || is_remote(path));
+    let indexes = files
+        .into_iter()
+        .map(|path| {
+            let index = if is_remote(path) {
+                // Roots were checked by validate_args.
+                let root = RemoteRoot::parse(&path.to_string_lossy()).unwrap_or_else(|e| {
+                    eprintln!("Error: {e}");
+                    std::process::exit(1);
+                });
+                status(quiet, &format!("Indexing {root} over ssh..."));
+                root.index(&cli.remote_command, cli.hash_algo)
+            } else {
+                read_index(path)
+            };
+            index.unwrap_or_else(|e| {
+                let what = if is_remote(path) {
+                    "indexing"
+                } else {
+                    "reading index"
+                };
+                eprintln!("Error {what} {}: {e}", path.display());
+                error!("Failed {what} {}: {e}", path.display());
+                std::process::exit(1);
+            })
+        })
+        .collect();
+    (dirs.into_iter().cloned().collect(), indexes, buckets)
+}
+
+/// Indexes the objects of `buckets` that may be duplicates of each other or
+/// of a file in `dirs`, if any buckets are given, exiting if they cannot be
+/// listed.
+fn bucket_index(buckets: &[S3Root], dirs: &[PathBuf], options: &ScanOptions) -> Option<Index> {
+    if buckets.is_empty() {
+        return None;
+    }
+    let index = S3Settings::from_env()
+        .and_then(|settings| index_buckets(buckets, dirs, &settings, options))
+        .unwrap_or_else(|e| {
+            eprintln!("Error: {e}");
+            error!("Failed to index S3 objects: {e}");
+            std::process::exit(1);
+        });
+    if !index.errors.is_empty() {
+        eprintln!(
+            "{} objects skipped due to errors; see {LOG_FILE} for details.",
+            index.errors.len()
+        );
+    }
+    Some(index)
+}
+
+/// Returns the options given that cannot work on the files of an index,
+/// which are not on this machine.
+fn index_conflicts(cli: &Cli) -> Option<&'static str> {
+    let conflicts = [
+        (
+            cli.hardlink || cli.symlink || cli.reflink || cli.delete || cli.trash,
+            "--hardlink, --symlink, --reflink, --delete and --trash",
+        ),
+        (cli.interactive, "--interactive"),
+        (cli.watch, "--watch"),
+        (cli.schedule.is_some(), "--schedule"),
+        (cli.duplicate_dirs, "--dirs"),
+        (
+            cli.images_similar
+                || cli.videos_similar
+                || cli.text_similar
+                || cli.partial
+                || cli.name_collisions,
+            "searches for similar files, partial duplicates and name collisions",
+        ),
+    ];
+    conflicts
+        .into_iter()
+        .find_map(|(given, options)| given.then_some(options))
+}
+
+/// Logs a status message and, unless quiet, prints it to stdout.
+fn status(quiet: bool, message: &str) {
+    info!("{message}");
+    if !quiet {
+        println!("{message}");
+    }
+}
+
+/// Exits with an error if any directory or option value is invalid.
+fn validate_args(cli: &Cli, dirs: &[PathBuf]) {
+    for d in dirs.iter().chain(&cli.against) {
+        if is_remote(d) {
+            if let Err(e) = RemoteRoot::parse(&d.to_string_lossy()) {
+                eprintln!("Error: {e}");
+                error!("Invalid remote directory: {e}");
+                std::process::exit(1);
             }
-            Err(e) => {
-                eprintln!("Error writing output: {e}");
-                error!("Failed to write output: {e}");
+            continue;
+        }
+        if is_s3(d) {
+            if let Err(e) = S3Root::parse(&d.to_string_lossy()) {
+                eprintln!("Error: {e}");
+                error!("Invalid S3 location: {e}");
+                std::process::exit(1);
+            }
+            // Credentials are checked now rather than after a long scan.
+            if let Err(e) = S3Settings::from_env() {
+                eprintln!("Error: cannot read from S3: {e}");
+                error!("Invalid S3 settings: {e}");
                 std::process::exit(1);
             }
+            continue;
+        }
+        // Files are read as indexes.
+        if !d.is_dir() && !d.is_file() {
+            eprintln!("Error: '{}' is not a valid directory", d.display());
+            error!("Invalid directory: {}", d.display());
+            std::process::exit(1);
+        }
+    }
+
+    if reads_indexes(cli) {
+        if let Some(options) = index_conflicts(cli) {
+            eprintln!("Error: {options} cannot be used with an index");
+            error!("Options incompatible with an index: {options}");
+            std::process::exit(1);
+        }
+        if !cli.against.is_empty() && dirs.iter().any(|d| d.is_file() || is_remote(d) || is_s3(d)) {
+            eprintln!("Error: only the directories given to --against may be indexes");
+            error!("Index given as the source of a comparison");
+            std::process::exit(1);
+        }
+    }
+
+    for reference in &cli.against {
+        let same = |d: &PathBuf| d.canonicalize().ok() == reference.canonicalize().ok();
+        if dirs.iter().any(same) {
+            eprintln!(
+                "Error: '{}' is both scanned and compared against",
+                reference.display()
+            );
+            error!("Directory compared against itself: {}", reference.display());
+            std::process::exit(1);
+        }
+    }
+
+    if let (Some(min), Some(max)) = (cli.min_size, cli.max_size) {
+        if min > max {
+            eprintln!("Error: --min-size ({min} bytes) is larger than --max-size ({max} bytes)");
+            error!("Invalid size range: {min} > {max}");
+            std::process::exit(1);
+        }
+    }
+
+    if !cli.email_to.is_empty() {
+        // Settings are checked now rather than after a long scan.
+        if let Err(e) = SmtpSettings::load(cli.smtp_config.as_deref()) {
+            eprintln!("Error: cannot email the report: {e}");
+            error!("Invalid SMTP settings: {e}");
+            std::process::exit(1);
+        }
+    }
+
+    for patterns in [&cli.exclude, &cli.include, &cli.exclude_dir] {
+        if let Err(e) = GlobSet::new(patterns) {
+            eprintln!("Error: {e}");
+            error!("Invalid glob pattern: {e}");
+            std::process::exit(1);
+        }
+    }
+
+    for path in &cli.ignore_file {
+        if let Err(e) = File::open(path) {
+            eprintln!("Error: cannot read ignore file {}: {e}", path.display());
+            error!("Cannot read ignore file {}: {e}", path.display());
+            std::process::exit(1);
         }
     }
+
+    let regexes = ScanOptions::new()
+        .include_regex(cli.include_regex.iter().cloned())
+        .exclude_regex(cli.exclude_regex.iter().cloned());
+    if let Err(e) = regexes.validate() {
+        eprintln!("Error: {e}");
+        error!("Invalid regular expression: {e}");
+        std::process::exit(1);
+    }
+}
+
+/// Returns the file the report is written to when no name is given.
+fn default_filename(cli: &Cli) -> &'static str {
+    if !cli.against.is_empty() {
+        return DEFAULT_COMPARISON_FILENAME;
+    }
+    if cli.images_similar {
+        return DEFAULT_SIMILAR_IMAGES_FILENAME;
+    }
+    if cli.videos_similar {
+        return DEFAULT_SIMILAR_VIDEOS_FILENAME;
+    }
+    if cli.text_similar {
+        return DEFAULT_SIMILAR_TEXTS_FILENAME;
+    }
+    if cli.partial {
+        return DEFAULT_PARTIAL_DUPLICATES_FILENAME;
+    }
+    if cli.name_collisions {
+        return DEFAULT_NAME_COLLISIONS_FILENAME;
+    }
+    match cli.format {
+        ReportFormat::Text => DEFAULT_REPORT_FILENAME,
+        ReportFormat::Csv => DEFAULT_CSV_REPORT_FILENAME,
+        ReportFormat::Html => DEFAULT_HTML_REPORT_FILENAME,
+        ReportFormat::Json => DEFAULT_JSON_REPORT_FILENAME,
+        ReportFormat::Sqlite => DEFAULT_SQLITE_REPORT_FILENAME,
+        ReportFormat::Fdupes => DEFAULT_FDUPES_REPORT_FILENAME,
+        ReportFormat::Rmlint => DEFAULT_RMLINT_REPORT_FILENAME,
+    }
+}
+
+/// Returns the files this tool writes: its log, undo log, history, hash
+/// checkpoint or cache, and `output_file`.
+fn own_files(cli: &Cli, output_file: Option<&Path>) -> Vec<PathBuf> {
+    [
+        Some(Path::new(LOG_FILE)),
+        Some(cli.undo_log.as_path()),
+        cli.history.as_deref(),
+        output_file,
+        Some(checkpoint_path(cli)),
+    ]
+    .into_iter()
+    .flatten()
+    .map(Path::to_path_buf)
+    .collect()
+}
+
+/// Runs `run`, a scan or comparison, with the options given on the command
+/// line, exiting if a pattern is invalid.
+///
+/// The files this tool writes are left out, so a report or log from an
+/// earlier run inside a scanned directory never shows up as a duplicate.
+/// The full hashes in `cache` are checkpointed every
+/// [`CHECKPOINT_INTERVAL`] while the scan runs.
+fn scan<T>(
+    cli: &Cli,
+    output_file: Option<&Path>,
+    cache: &HashCache,
+    quiet: bool,
+    run: impl FnOnce(&ScanOptions) -> Result<T, GlobError>,
+) -> T {
+    let checkpoint = checkpoint_path(cli);
+    let options = ScanOptions::new()
+        .exclude(cli.exclude.iter().cloned())
+        .include(cli.include.iter().cloned())
+        .exclude_dirs(cli.exclude_dir.iter().cloned())
+        .include_regex(cli.include_regex.iter().cloned())
+        .exclude_regex(cli.exclude_regex.iter().cloned())
+        .file_types(cli.file_type.iter().copied())
+        .ignore_files(cli.ignore_file.iter().cloned())
+        .respect_gitignore(cli.respect_gitignore)
+        .min_size(cli.min_size)
+        .max_size(cli.max_size)
+        .hash_algo(cli.hash_algo)
+        .quick_hash_bytes(cli.quick_hash_bytes)
+        .quick_hash_sample(cli.quick_hash_sample)
+        .paranoid(cli.paranoid)
+        .compare_pairs(cli.compare_pairs)
+        .ignore_image_metadata(cli.ignore_image_metadata)
+        .audio_content(cli.audio_content)
+        .scan_archives(cli.scan_archives)
+        .decompress(cli.decompress)
+        .low_memory(cli.low_memory)
+        .max_read_rate(cli.max_read_mbps.saturating_mul(1024 * 1024))
+        .threads(cli.threads)
+        .io_threads(cli.io_threads)
+        .serialize_hdd(!cli.parallel_hdd)
+        .skip_hidden(cli.skip_hidden)
+        .follow_symlinks(cli.follow_symlinks)
+        .one_file_system(cli.one_file_system)
+        .skip_files(own_files(cli, output_file))
+        .max_depth(cli.max_depth)
+        .name_match(name_match(cli))
+        .cache(cache)
+        .quiet(quiet)
+        .progress_json(cli.progress_json)
+        .cancel(&INTERRUPTED);
+    let (done, finished) = mpsc::channel::<()>();
+    std::thread::scope(|scope| {
+        scope.spawn(move || {
+            while finished.recv_timeout(CHECKPOINT_INTERVAL) == Err(RecvTimeoutError::Timeout) {
+                if let Err(e) = cache.save(checkpoint) {
+                    error!(
+                        "Failed to checkpoint hashes to {}: {e}",
+                        checkpoint.display()
+                    );
+                }
+            }
+        });
+        let result = run(&options);
+        drop(done);
+        match result {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("Error: {e}");
+                error!("Invalid pattern: {e}");
+                std::process::exit(1);
+            }
+        }
+    })
+}
+
+/// Writes the report of `--against`: which files in `dirs` have a copy in
+/// the reference directories. Exits if it cannot be written.
+fn run_compare(
+    cli: &Cli,
+    dirs: &[PathBuf],
+    output_file: Option<&Path>,
+    cache: &HashCache,
+    start_time: &str,
+    quiet: bool,
+) {
+    let (references, indexes, buckets) = read_indexes(cli, &cli.against, quiet);
+    let comparison = scan(cli, output_file, cache, quiet, |options| {
+        let bucket_index = bucket_index(&buckets, dirs, options);
+        let indexes: Vec<Index> = indexes.into_iter().chain(bucket_index).collect();
+        compare_with_indexes(dirs, &references, &indexes, options)
+    });
+    info!(
+        "Comparison: {} files with a copy, {} without",
+        comparison.present.len(),
+        comparison.missing.len()
+    );
+    if !comparison.errors.is_empty() {
+        eprintln!(
+            "{} files skipped due to errors; see {LOG_FILE} for details.",
+            comparison.errors.len()
+        );
+    }
+    if comparison.interrupted {
+        eprintln!("Scan interrupted; some files listed without a copy may have one.");
+    }
+    save_hashes(cli, cache, comparison.interrupted);
+
+    save_text_report(output_file, "Comparison report", quiet, |mut w| {
+        write_comparison_to(&mut w, &comparison, start_time)
+    });
+    if comparison.interrupted {
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+}
+
+/// Writes the report of `--images-similar`: groups of images in `dirs` that
+/// look alike. Exits if it cannot be written.
+fn run_similar(
+    cli: &Cli,
+    dirs: &[PathBuf],
+    output_file: Option<&Path>,
+    cache: &HashCache,
+    start_time: &str,
+    quiet: bool,
+) {
+    // Image hashes are not cached, but the cache is still checkpointed.
+    let similar = scan(cli, output_file, cache, quiet, |options| {
+        find_similar_images(dirs, options, cli.image_distance)
+    });
+    info!(
+        "Similar images: {} groups among {} images",
+        similar.groups.len(),
+        similar.images
+    );
+    if !similar.errors.is_empty() {
+        eprintln!(
+            "{} images skipped due to errors; see {LOG_FILE} for details.",
+            similar.errors.len()
+        );
+    }
+    if similar.interrupted {
+        eprintln!("Scan interrupted; writing the similar images found so far.");
+    }
+    save_hashes(cli, cache, similar.interrupted);
+
+    save_text_report(output_file, "Similar images report", quiet, |mut w| {
+        write_similar_images_to(&mut w, &similar, start_time)
+    });
+    if similar.interrupted {
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+}
+
+/// Writes the report of `--videos-similar`: groups of videos in `dirs` that
+/// show the same footage. Exits if `ffmpeg` or `ffprobe` is missing or the
+/// report cannot be written.
+fn run_similar_videos(
+    cli: &Cli,
+    dirs: &[PathBuf],
+    output_file: Option<&Path>,
+    cache: &HashCache,
+    start_time: &str,
+    quiet: bool,
+) {
+    if !videos::tools_available() {
+        eprintln!("Error: --videos-similar needs the ffmpeg and ffprobe commands on the PATH.");
+        error!("ffmpeg or ffprobe not found; cannot compare videos");
+        std::process::exit(1);
+    }
+    let similar = scan(cli, output_file, cache, quiet, |options| {
+        find_similar_videos(dirs, options, cli.video_distance)
+    });
+    info!(
+        "Similar videos: {} groups among {} videos",
+        similar.groups.len(),
+        similar.videos
+    );
+    if !similar.errors.is_empty() {
+        eprintln!(
+            "{} videos skipped due to errors; see {LOG_FILE} for details.",
+            similar.errors.len()
+        );
+    }
+    if similar.interrupted {
+        eprintln!("Scan interrupted; writing the similar videos found so far.");
+    }
+    save_hashes(cli, cache, similar.interrupted);
+
+    save_text_report(output_file, "Similar videos report", quiet, |mut w| {
+        write_similar_videos_to(&mut w, &similar, start_time)
+    });
+    if similar.interrupted {
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+}
+
+/// Writes the report of `--text-similar`: pairs of text files in `dirs`
+/// that are mostly the same. Exits if it cannot be written.
+fn run_similar_texts(
+    cli: &Cli,
+    dirs: &[PathBuf],
+    output_file: Option<&Path>,
+    cache: &HashCache,
+    start_time: &str,
+    quiet: bool,
+) {
+    let similar = scan(cli, output_file, cache, quiet, |options| {
+        find_similar_texts(dirs, options, cli.text_similarity)
+    });
+    info!(
+        "Similar text files: {} pairs among {} files",
+        similar.pairs.len(),
+        similar.files
+    );
+    if !similar.errors.is_empty() {
+        eprintln!(
+            "{} files skipped due to errors; see {LOG_FILE} for details.",
+            similar.errors.len()
+        );
+    }
+    if similar.interrupted {
+        eprintln!("Scan interrupted; writing the similar text files found so far.");
+    }
+    save_hashes(cli, cache, similar.interrupted);
+
+    save_text_report(output_file, "Similar text files report", quiet, |mut w| {
+        write_similar_texts_to(&mut w, &similar, start_time)
+    });
+    if similar.interrupted {
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+}
+
+/// Writes the report of `--name-collisions`: file names in `dirs` shared by
+/// files with different contents. Exits if it cannot be written.
+fn run_name_collisions(
+    cli: &Cli,
+    dirs: &[PathBuf],
+    output_file: Option<&Path>,
+    cache: &HashCache,
+    start_time: &str,
+    quiet: bool,
+) {
+    let collisions = scan(cli, output_file, cache, quiet, |options| {
+        find_name_collisions(dirs, options)
+    });
+    info!(
+        "Name collisions: {} names among {} files",
+        collisions.collisions.len(),
+        collisions.files
+    );
+    if !collisions.errors.is_empty() {
+        eprintln!(
+            "{} files skipped due to errors; see {LOG_FILE} for details.",
+            collisions.errors.len()
+        );
+    }
+    if collisions.interrupted {
+        eprintln!("Scan interrupted; writing the name collisions found so far.");
+    }
+    save_hashes(cli, cache, collisions.interrupted);
+
+    save_text_report(output_file, "Name collisions report", quiet, |mut w| {
+        write_name_collisions_to(&mut w, &collisions, start_time)
+    });
+    if collisions.interrupted {
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+}
+
+/// Writes the report of `--partial`: pairs of files in `dirs` that share
+/// much of their contents. Exits if it cannot be written.
+fn run_partial(
+    cli: &Cli,
+    dirs: &[PathBuf],
+    output_file: Option<&Path>,
+    cache: &HashCache,
+    start_time: &str,
+    quiet: bool,
+) {
+    let partial = scan(cli, output_file, cache, quiet, |options| {
+        find_partial_duplicates(dirs, options, cli.min_shared)
+    });
+    info!(
+        "Partial duplicates: {} pairs among {} files, {} shareable",
+        partial.pairs.len(),
+        partial.files,
+        format_size(partial.shareable_bytes())
+    );
+    if !partial.errors.is_empty() {
+        eprintln!(
+            "{} files skipped due to errors; see {LOG_FILE} for details.",
+            partial.errors.len()
+        );
+    }
+    if partial.interrupted {
+        eprintln!("Scan interrupted; writing the partial duplicates found so far.");
+    }
+    save_hashes(cli, cache, partial.interrupted);
+
+    save_text_report(output_file, "Partial duplicates report", quiet, |mut w| {
+        write_partial_duplicates_to(&mut w, &partial, start_time)
+    });
+    if partial.interrupted {
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+}
+
+/// Writes a plain-text report, named `name` in the status line, to
+/// `output_file`, or stdout when `None`, exiting if it cannot be written.
+fn save_text_report(
+    output_file: Option<&Path>,
+    name: &str,
+    quiet: bool,
+    write: impl Fn(&mut dyn Write) -> std::io::Result<()>,
+) {
+    let written = if let Some(path) = output_file {
+        write_report_file(path, |mut w| write(&mut w))
+    } else {
+        let mut stdout = std::io::stdout().lock();
+        write(&mut stdout)
+            .and_then(|()| stdout.flush())
+            .map_err(Into::into)
+    };
+    match written {
+        Ok(()) => {
+            if let Some(path) = output_file {
+                status(quiet, &format!("{name} saved to {}", path.display()));
+            }
+        }
+        Err(e) => {
+            if output_file.is_none() {
+                exit_if_pipe_closed(&*e);
+            }
+            eprintln!("Error writing output: {e}");
+            error!("Failed to write output: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Returns where full hashes are kept between runs: the --cache file if one
+/// is given, or else the checkpoint of an interrupted scan.
+fn checkpoint_path(cli: &Cli) -> &Path {
+    cli.cache
+        .as_deref()
+        .unwrap_or_else(|| Path::new(CHECKPOINT_FILE))
+}
+
+/// Loads the full hashes a scan starts from: those in the --cache file, or
+/// with --resume those checkpointed by an interrupted scan.
+fn load_hashes(cli: &Cli, quiet: bool) -> HashCache {
+    if cli.cache.is_none() && !cli.resume {
+        return HashCache::default();
+    }
+    let cache = load_cache(checkpoint_path(cli));
+    if cli.cache.is_none() {
+        status(
+            quiet,
+            &format!("Resuming with {} checkpointed hashes", cache.len()),
+        );
+    }
+    cache
+}
+
+/// Saves the full hashes for the next run. Without --cache they are only
+/// kept when the scan was interrupted, and a completed scan removes the
+/// checkpoint.
+fn save_hashes(cli: &Cli, cache: &HashCache, interrupted: bool) {
+    let checkpoint = checkpoint_path(cli);
+    if cli.cache.is_some() {
+        save_cache(cache, checkpoint);
+    } else if interrupted {
+        save_cache(cache, checkpoint);
+        eprintln!("Run again with --resume to reuse the hashes computed so far.");
+    } else {
+        if cli.resume {
+            info!("{} full hashes reused from the checkpoint", cache.hits());
+        }
+        if let Err(e) = std::fs::remove_file(checkpoint) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                error!("Failed to remove checkpoint {}: {e}", checkpoint.display());
+            }
+        }
+    }
+}
+
+/// Maps the layout flags onto the text report's [`OutputOptions`].
+fn text_options(cli: &Cli) -> OutputOptions {
+    OutputOptions::new().verbose(cli.verbose)
+}
+
+/// Maps the file name flags onto the scan's [`NameMatch`] rule.
+fn name_match(cli: &Cli) -> NameMatch {
+    match (cli.same_name_only || cli.name_collisions, cli.ignore_case) {
+        (false, _) => NameMatch::Any,
+        (true, false) => NameMatch::Exact,
+        (true, true) => NameMatch::IgnoreCase,
+    }
+}
+
+/// Loads the hash cache, exiting with an error if the file cannot be read.
+fn load_cache(path: &Path) -> HashCache {
+    match HashCache::load(path) {
+        Ok(cache) => cache,
+        Err(e) => {
+            eprintln!("Error reading cache {}: {e}", path.display());
+            error!("Failed to read cache {}: {e}", path.display());
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Saves the hash cache; a failure is reported but does not affect the scan results.
+fn save_cache(cache: &HashCache, path: &Path) {
+    info!("{} full hashes reused from the cache", cache.hits());
+    if let Err(e) = cache.save(path) {
+        eprintln!("Error saving cache {}: {e}", path.display());
+        error!("Failed to save cache {}: {e}", path.display());
+    }
+}
+
+/// Writes the report in the chosen format to `output_file`, or to stdout when `None`.
+fn save_report(
+    report: &DuplicateReport,
+    cli: &Cli,
+    output_file: Option<&Path>,
+    start_time: &str,
+    dirs: &[PathBuf],
+) -> Result<(), Box<dyn Error>> {
+    match (cli.format, output_file) {
+        (ReportFormat::Text, Some(path)) => {
+            write_output_report(report, path, start_time, dirs, &text_options(cli))
+        }
+        (ReportFormat::Csv, Some(path)) => write_report_file(path, |w| write_csv_to(w, report)),
+        (ReportFormat::Html, Some(path)) => write_report_file(path, |w| write_html_to(w, report)),
+        (ReportFormat::Json, Some(path)) => {
+            write_report_file(path, |w| write_output_json(w, report, start_time, dirs))
+        }
+        (ReportFormat::Sqlite, Some(path)) => {
+            write_report_file(path, |w| write_sqlite_to(w, report, start_time, dirs))
+        }
+        (ReportFormat::Fdupes, Some(path)) => {
+            write_report_file(path, |w| write_fdupes_to(w, report))
+        }
+        (ReportFormat::Rmlint, Some(path)) => {
+            write_report_file(path, |w| write_rmlint_to(w, report))
+        }
+        (format, None) => {
+            let mut stdout = std::io::stdout().lock();
+            match format {
+                ReportFormat::Text => {
+                    write_output_to(&mut stdout, report, start_time, dirs, &text_options(cli))?;
+                }
+                ReportFormat::Csv => write_csv_to(&mut stdout, report)?,
+                ReportFormat::Html => write_html_to(&mut stdout, report)?,
+                ReportFormat::Json => write_output_json(&mut stdout, report, start_time, dirs)?,
+                ReportFormat::Sqlite => {
+                    // The database is laid out with seeks, which stdout cannot do.
+                    let mut database = std::io::Cursor::new(Vec::new());
+                    write_sqlite_to(&mut database, report, start_time, dirs)?;
+                    stdout.write_all(database.get_ref())?;
+                }
+                ReportFormat::Fdupes => write_fdupes_to(&mut stdout, report)?,
+                ReportFormat::Rmlint => write_rmlint_to(&mut stdout, report)?,
+            }
+            stdout.flush()?;
+            Ok(())
+        }
+    }
+}
+
+/// Writes the `--summary` block to `output_file`, or stdout when `None`,
+/// exiting if it cannot be written.
+fn save_summary(report: &DuplicateReport, output_file: Option<&Path>, quiet: bool) {
+    info!(
+        "Summary: {} duplicate groups, {} redundant files, {} bytes reclaimable",
+        report.total_groups,
+        report.redundant_files(),
+        report.total_reclaimable_bytes
+    );
+    let written = if let Some(path) = output_file {
+        write_report_file(path, |w| write_summary_to(w, report))
+    } else {
+        let mut stdout = std::io::stdout().lock();
+        write_summary_to(&mut stdout, report)
+            .and_then(|()| stdout.flush())
+            .map_err(Into::into)
+    };
+    match written {
+        Ok(()) => {
+            if let Some(path) = output_file {
+                status(quiet, &format!("Summary saved to {}", path.display()));
+            }
+        }
+        Err(e) => {
+            if output_file.is_none() {
+                exit_if_pipe_closed(&*e);
+            }
+            eprintln!("Error writing summary: {e}");
+            error!("Failed to write summary: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Exits quietly if `e` means the program reading stdout has closed it, as
+/// after `| head` or quitting `less`, since that is not a failure.
+fn exit_if_pipe_closed(e: &(dyn Error + 'static)) {
+    let closed = e
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe);
+    if closed {
+        info!("Stdout was closed before the output was complete");
+        std::process::exit(0);
+    }
+}
+
+/// Creates `path` and fills it using `write`.
+fn write_report_file(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> std::io::Result<()>,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(&mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the redundant copies to stdout for `--print0`, exiting if stdout fails.
+fn print_paths0(report: &DuplicateReport, keep: KeepStrategy, separator: Option<&str>) {
+    let mut stdout = std::io::stdout().lock();
+    let written = match separator {
+        Some(separator) => write_print0_grouped_to(&mut stdout, report, keep, separator.as_bytes()),
+        None => write_print0_to(&mut stdout, report, keep),
+    };
+    if let Err(e) = written.and_then(|()| stdout.flush()) {
+        exit_if_pipe_closed(&e);
+        eprintln!("Error writing paths: {e}");
+        error!("Failed to write paths: {e}");
+        std::process::exit(1);
+    }
+    info!("Redundant duplicate paths written to stdout (keep: {keep:?})");
+}
+
+/// Appends an action summary to the report, exiting if it cannot be written.
+///
+/// When `output_file` is `None` the report went to stdout, so the summary does
+/// too. The other formats have no room for the summary, so it goes to stderr
+/// instead.
+fn append_summary(
+    output_file: Option<&Path>,
+    format: ReportFormat,
+    what: &str,
+    write: impl FnOnce(&mut dyn Write) -> std::io::Result<()>,
+) {
+    let appended = match (format, output_file) {
+        (ReportFormat::Text, Some(path)) => OpenOptions::new()
+            .append(true)
+            .open(path)
+            .and_then(|mut file| write(&mut file)),
+        (ReportFormat::Text, None) => write(&mut std::io::stdout().lock()),
+        _ => write(&mut std::io::stderr().lock()),
+    };
+    if let Err(e) = appended {
+        eprintln!("Error writing {what} summary: {e}");
+        error!("Failed to write {what} summary: {e}");
+        std::process::exit(1);
+    }
+}
+
+/// Runs the follow-up passes requested on the command line after the report is written.
+fn run_actions(cli: &Cli, report: &DuplicateReport, output_file: Option<&Path>, quiet: bool) {
+    // Must run before any action below changes the files on disk.
+    if cli.duplicate_dirs {
+        run_dirs(report, output_file, cli.format);
+    }
+    if cli.hardlink {
+        let summary = run_hardlink(report, output_file, cli.format, cli.confirm, quiet);
+        if !summary.dry_run {
+            save_undo(
+                cli,
+                &undo::link_entries(UndoAction::Hardlink, &summary.linked, cli.hash_algo),
+            );
+        }
+    }
+    if cli.symlink {
+        let summary = run_symlink(report, output_file, cli.format, cli.confirm, quiet);
+        if !summary.dry_run {
+            save_undo(
+                cli,
+                &undo::link_entries(UndoAction::Symlink, &summary.linked, cli.hash_algo),
+            );
+        }
+    }
+    if cli.reflink {
+        run_reflink(report, output_file, cli.format, cli.confirm, quiet);
+    }
+    if cli.delete || cli.trash {
+        let summary = run_delete(
+            report,
+            output_file,
+            cli.format,
+            (!cli.interactive).then_some(cli.keep),
+            cli.trash,
+            cli.confirm,
+            quiet,
+        );
+        if !summary.dry_run {
+            save_undo(cli, &undo::delete_entries(&summary, cli.hash_algo));
+        }
+    }
+}
+
+/// Appends the files an action changed to the undo log, so `restore` can
+/// put them back. A failure is reported but does not undo the action.
+fn save_undo(cli: &Cli, entries: &[UndoEntry]) {
+    let journal = &cli.undo_log;
+    if let Err(e) = undo::record(journal, entries) {
+        eprintln!(
+            "Error writing undo log {}: {e}; these changes cannot be restored automatically.",
+            journal.display()
+        );
+        error!("Failed to write undo log {}: {e}", journal.display());
+    }
+}
+
+/// Serves metrics at `addr` on a thread of their own, exiting if the
+/// address cannot be listened on.
+fn serve_metrics(addr: SocketAddr) -> Arc<Metrics> {
+    let listener = match TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Error listening on {addr}: {e}");
+            error!("Failed to listen on {addr}: {e}");
+            std::process::exit(1);
+        }
+    };
+    let addr = listener.local_addr().unwrap_or(addr);
+    info!("Serving metrics on {addr}");
+    let metrics = Arc::new(Metrics::new());
+    let served = Arc::clone(&metrics);
+    std::thread::spawn(move || {
+        if let Err(e) = server::serve_metrics(&listener, served) {
+            error!("Failed to serve metrics on {addr}: {e}");
+        }
+    });
+    metrics
+}
+
+/// Serves the API until the process is stopped.
+fn run_serve(addr: SocketAddr, journal: &Path) {
+    let listener = match TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Error listening on {addr}: {e}");
+            error!("Failed to listen on {addr}: {e}");
+            std::process::exit(1);
+        }
+    };
+    // Print the bound address, which tells callers passing port 0 which
+    // port they were given.
+    let addr = listener.local_addr().unwrap_or(addr);
+    println!("Listening on http://{addr}");
+    info!("Serving the API on {addr}");
+    if let Err(e) = server::serve(&listener, journal) {
+        eprintln!("Error serving on {addr}: {e}");
+        error!("Failed to serve on {addr}: {e}");
+        std::process::exit(1);
+    }
+}
+
+/// Prints the trends recorded in the history file for `history`.
+fn run_history(path: Option<&Path>, last: usize) {
+    let Some(path) = path else {
+        eprintln!("Error: give the history file with --history");
+        error!("No history file given");
+        std::process::exit(1);
+    };
+    let runs = match history::load(path) {
+        Ok(runs) => runs,
+        Err(e) => {
+            eprintln!("Error reading history {}: {e}", path.display());
+            error!("Failed to read history {}: {e}", path.display());
+            std::process::exit(1);
+        }
+    };
+    let mut stdout = std::io::stdout().lock();
+    if let Err(e) = history::write_trends_to(&runs, last, &mut stdout) {
+        exit_if_pipe_closed(&e);
+        error!("Failed to write history: {e}");
+    }
+}
+
+/// Undoes the most recent run in the undo log and prints what was restored.
+fn run_restore(journal: &Path, confirm: bool) {
+    let summary = match undo::restore_last(journal, !confirm) {
+        Ok(summary) => summary,
+        Err(e) => {
+            eprintln!("Error reading undo log {}: {e}", journal.display());
+            error!("Failed to restore from {}: {e}", journal.display());
+            std::process::exit(1);
+        }
+    };
+    let mut stdout = std::io::stdout().lock();
+    if let Err(e) = summary.write_to(&mut stdout) {
+        error!("Failed to write restore summary: {e}");
+    }
+    if summary.dry_run && summary.operation.is_some() {
+        println!(
+            "Dry run: {} files would be restored; re-run with --confirm to apply.",
+            summary.restored.len()
+        );
+    }
+    info!(
+        "Restore of {:?} (dry run: {}): {} restored, {} failed",
+        summary.operation,
+        summary.dry_run,
+        summary.restored.len(),
+        summary.failed.len()
+    );
+    if !summary.failed.is_empty() {
+        eprintln!(
+            "{} files could not be restored; see above for details.",
+            summary.failed.len()
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Compares the report at `old` with the one at `new`, or with a fresh scan
+/// of its directories, for `diff`.
+fn run_diff(cli: &Cli, old: &Path, new: Option<&Path>, scan_options: &[OsString]) {
+    let read = |path: &Path| match read_report(path) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Error reading report {}: {e}", path.display());
+            error!("Failed to read report {}: {e}", path.display());
+            std::process::exit(1);
+        }
+    };
+    let before = read(old);
+    let after = new.map_or_else(|| rescan(cli, old, &before, scan_options), read);
+
+    let diff = diff_reports(&before, &after);
+    info!(
+        "Diff of {}: {} new groups, {} resolved, {} changed",
+        old.display(),
+        diff.added.len(),
+        diff.resolved.len(),
+        diff.changed.len()
+    );
+    let mut stdout = std::io::stdout().lock();
+    if let Err(e) = diff.write_to(&mut stdout) {
+        exit_if_pipe_closed(&e);
+        error!("Failed to write diff: {e}");
+    }
+}
+
+/// Scans the directories of `before`, the report read from `old`, again
+/// for `diff`, with the options in `scan_options` parsed as they would be
+/// for a scan.
+fn rescan(
+    cli: &Cli,
+    old: &Path,
+    before: &DuplicateReport,
+    scan_options: &[OsString],
+) -> DuplicateReport {
+    let program = std::env::args_os().next().unwrap_or_default();
+    let parsed = Cli::try_parse_from(std::iter::once(program).chain(scan_options.iter().cloned()));
+    let mut options = match parsed {
+        Ok(options)
+            if options.command.is_none()
+                && options.directory.is_none()
+                && options.directories.is_none() =>
+        {
+            options
+        }
+        Ok(_) => {
+            eprintln!("Error: the options after -- may not name directories or a subcommand");
+            error!("Diff scan options named directories or a subcommand");
+            std::process::exit(1);
+        }
+        Err(e) => e.exit(),
+    };
+    // The hash cache options may also be given before `diff`.
+    options.cache = options.cache.or_else(|| cli.cache.clone());
+    options.resume |= cli.resume;
+    let cli = &options;
+    let dirs = &before.roots;
+    if dirs.is_empty() {
+        eprintln!("Error: {} names no directories to scan", old.display());
+        error!("Report {} names no directories", old.display());
+        std::process::exit(1);
+    }
+    if let Some(dir) = dirs.iter().find(|dir| !dir.is_dir()) {
+        eprintln!("Error: '{}' is not a valid directory", dir.display());
+        error!("Invalid directory: {}", dir.display());
+        std::process::exit(1);
+    }
+    let quiet = !std::io::stdout().is_terminal();
+    announce_scan(cli, dirs, quiet);
+    let cache = load_hashes(cli, quiet);
+    let report = scan(cli, None, &cache, quiet, |options| {
+        find_duplicates_with_options(dirs, options)
+    });
+    save_hashes(cli, &cache, report.interrupted);
+    if report.interrupted {
+        eprintln!("Scan interrupted; nothing compared.");
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+    report
+}
+
+/// Runs an `index` subcommand.
+fn run_index(cli: &Cli, command: &IndexCommand) {
+    match command {
+        IndexCommand::Create {
+            dirs,
+            output,
+            hash_algo,
+        } => run_index_create(cli, dirs, output, *hash_algo),
+    }
+}
+
+/// Hashes the files in `dirs` into an index saved to `output`, for
+/// `index create`.
+fn run_index_create(cli: &Cli, dirs: &[PathBuf], output: &Path, hash_algo: HashAlgo) {
+    // The index is written to stdout when another machine asks for it.
+    let to_stdout = output == Path::new(STDOUT_OUTPUT);
+    let quiet = to_stdout || !std::io::stdout().is_terminal();
+    let target = (!to_stdout).then_some(output);
+    let index = index_dirs(cli, dirs, target, hash_algo, quiet, "written");
+    if to_stdout {
+        let mut stdout = BufWriter::new(std::io::stdout().lock());
+        if let Err(e) = index.write_to(&mut stdout).and_then(|()| stdout.flush()) {
+            exit_if_pipe_closed(&e);
+            eprintln!("Error writing index: {e}");
+            error!("Failed to write index: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Err(e) = write_index(output, &index) {
+        eprintln!("Error writing index {}: {e}", output.display());
+        error!("Failed to write index {}: {e}", output.display());
+        std::process::exit(1);
+    }
+    status(
+        quiet,
+        &format!(
+            "Index of {} files saved to {}",
+            index.entries.len(),
+            output.display()
+        ),
+    );
+}
+
+/// Hashes the files in `dirs` into an index with `hash_algo`, leaving out
+/// `output_file`, exiting if a directory is invalid or the scan is
+/// interrupted, when the index is not `what_for`.
+fn index_dirs(
+    cli: &Cli,
+    dirs: &[PathBuf],
+    output_file: Option<&Path>,
+    hash_algo: HashAlgo,
+    quiet: bool,
+    what_for: &str,
+) -> Index {
+    if let Some(dir) = dirs.iter().find(|dir| !dir.is_dir()) {
+        eprintln!("Error: '{}' is not a valid directory", dir.display());
+        error!("Invalid directory: {}", dir.display());
+        std::process::exit(1);
+    }
+    announce_scan(cli, dirs, quiet);
+    let cache = load_hashes(cli, quiet);
+    let index = scan(cli, output_file, &cache, quiet, |options| {
+        create_index(dirs, &options.clone().hash_algo(hash_algo))
+    });
+    save_hashes(cli, &cache, index.interrupted);
+    if !index.errors.is_empty() {
+        eprintln!(
+            "{} files skipped due to errors; see {LOG_FILE} for details.",
+            index.errors.len()
+        );
+    }
+    if index.interrupted {
+        eprintln!("Scan interrupted; no index {what_for}.");
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+    index
+}
+
+/// Runs the subcommands that scan, which may be interrupted.
+fn run_command(cli: &Cli, command: &Command) {
+    match command {
+        Command::VerifyBackup { source, backup } => run_verify_backup(cli, source, backup),
+        Command::Diff {
+            old,
+            new,
+            scan_options,
+        } => run_diff(cli, old, new.as_deref(), scan_options),
+        Command::Index { command } => run_index(cli, command),
+        Command::Agent {
+            coordinator,
+            dirs,
+            hash_algo,
+        } => run_agent(cli, coordinator, dirs, *hash_algo),
+        // Handled before the interrupt handler is installed.
+        Command::Restore { .. } | Command::History { .. } | Command::Serve { .. } => {}
+    }
+}
+
+/// Hashes the files in `dirs` and sends them to the coordinator at
+/// `coordinator`, for `agent`.
+fn run_agent(cli: &Cli, coordinator: &str, dirs: &[PathBuf], hash_algo: HashAlgo) {
+    let quiet = cli.quiet || !std::io::stdout().is_terminal();
+    let index = index_dirs(cli, dirs, None, hash_algo, quiet, "sent");
+    status(
+        quiet,
+        &format!("Sending {} files to {coordinator}...", index.entries.len()),
+    );
+    if let Err(e) = send_index(coordinator, &index) {
+        eprintln!("Error sending index to {coordinator}: {e}");
+        error!("Failed to send index to {coordinator}: {e}");
+        std::process::exit(1);
+    }
+    status(quiet, "Index accepted by the coordinator.");
+}
+
+/// Waits on `addr` for the indexes of `--agents` agents, exiting if it
+/// cannot listen there.
+fn receive_from_agents(cli: &Cli, addr: SocketAddr, quiet: bool) -> Vec<Index> {
+    let listener = TcpListener::bind(addr).unwrap_or_else(|e| {
+        eprintln!("Error listening on {addr}: {e}");
+        error!("Failed to listen on {addr}: {e}");
+        std::process::exit(1);
+    });
+    // The bound address tells agents which port a request for port 0 got.
+    let addr = listener.local_addr().unwrap_or(addr);
+    status(
+        quiet,
+        &format!("Waiting for {} agents on {addr}...", cli.agents),
+    );
+    let mut done = 0;
+    let indexes = receive_indexes(
+        &listener,
+        cli.agents,
+        cli.hash_algo,
+        Some(&INTERRUPTED),
+        |index| {
+            done += 1;
+            status(
+                quiet,
+                &format!(
+                    "Received {} files from {} ({done} of {} agents)",
+                    index.entries.len(),
+                    index.host,
+                    cli.agents
+                ),
+            );
+        },
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("Error receiving indexes on {addr}: {e}");
+        error!("Failed to receive indexes on {addr}: {e}");
+        std::process::exit(1);
+    });
+    if indexes.len() < cli.agents {
+        eprintln!(
+            "Interrupted with {} of {} agents received; reporting those.",
+            indexes.len(),
+            cli.agents
+        );
+    }
+    indexes
+}
+
+/// Lists the files in `source` with no copy in `backup` for `verify-backup`,
+/// exiting with [`BACKUP_INCOMPLETE_EXIT_CODE`] if there are any.
+fn run_verify_backup(cli: &Cli, source: &Path, backup: &Path) {
+    for dir in [source, backup] {
+        if !dir.is_dir() {
+            eprintln!("Error: '{}' is not a valid directory", dir.display());
+            error!("Invalid directory: {}", dir.display());
+            std::process::exit(1);
+        }
+    }
+    if source.canonicalize().ok() == backup.canonicalize().ok() {
+        eprintln!("Error: the source and backup are the same directory");
+        error!("Backup verified against itself: {}", source.display());
+        std::process::exit(1);
+    }
+    let quiet = !std::io::stdout().is_terminal();
+    info!(
+        "Verifying the backup of {} in {}",
+        source.display(),
+        backup.display()
+    );
+
+    let cache = load_hashes(cli, quiet);
+    let (sources, backups) = ([source.to_path_buf()], [backup.to_path_buf()]);
+    let comparison = scan(cli, None, &cache, quiet, |options| {
+        compare_dirs(&sources, &backups, options)
+    });
+    save_hashes(cli, &cache, comparison.interrupted);
+
+    let mut stdout = std::io::stdout().lock();
+    for path in &comparison.missing {
+        if let Err(e) = writeln!(stdout, "{}", path.display()) {
+            exit_if_pipe_closed(&e);
+            error!("Failed to write missing file: {e}");
+        }
+    }
+    if let Err(e) = stdout.flush() {
+        exit_if_pipe_closed(&e);
+    }
+    drop(stdout);
+
+    if !comparison.errors.is_empty() {
+        eprintln!(
+            "{} files could not be checked; see {LOG_FILE} for details.",
+            comparison.errors.len()
+        );
+    }
+    if comparison.interrupted {
+        eprintln!("Scan interrupted; the backup was not fully checked.");
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+    let checked = comparison.present.len() + comparison.missing.len();
+    if !comparison.missing.is_empty() {
+        eprintln!(
+            "{} of {checked} files ({}) are missing from the backup.",
+            comparison.missing.len(),
+            format_size(comparison.missing_bytes)
+        );
+    }
+    for (path, copy) in &comparison.corrupted {
+        eprintln!(
+            "Possibly corrupted: {} differs from {}, which has the same name, size and \
+             modification time.",
+            path.display(),
+            copy.display()
+        );
+    }
+    // Files that could not be read may be missing too.
+    if !comparison.missing.is_empty() || !comparison.errors.is_empty() {
+        std::process::exit(BACKUP_INCOMPLETE_EXIT_CODE);
+    }
+    status(
+        quiet,
+        &format!("Backup complete: all {checked} files have a copy."),
+    );
+}
+
+/// Finds duplicate directory trees and appends them to the report.
+fn run_dirs(report: &DuplicateReport, output_file: Option<&Path>, format: ReportFormat) {
+    let groups = find_duplicate_dirs(report);
+    info!("{} duplicate directory groups found", groups.len());
+    append_summary(output_file, format, "duplicate directory", |mut w| {
+        write_dir_groups_to(&mut w, &groups)
+    });
+}
+
+/// Replaces duplicates with hard links and appends the outcome to the report.
+fn run_hardlink(
+    report: &DuplicateReport,
+    output_file: Option<&Path>,
+    format: ReportFormat,
+    confirm: bool,
+    quiet: bool,
+) -> HardlinkSummary {
+    let summary = hardlink_duplicates(report, !confirm);
+    append_summary(output_file, format, "hard link", |mut w| {
+        summary.write_to(&mut w)
+    });
+    if summary.dry_run {
+        status(
+            quiet,
+            &format!(
+                "Dry run: {} files would be hard linked; re-run with --confirm to apply.",
+                summary.linked.len()
+            ),
+        );
+    } else {
+        status(
+            quiet,
+            &format!("{} files replaced with hard links.", summary.linked.len()),
+        );
+    }
+    info!(
+        "Hard link pass (dry run: {}): {} linked, {} skipped, {} bytes reclaimed",
+        summary.dry_run,
+        summary.linked.len(),
+        summary.skipped.len(),
+        summary.reclaimed_bytes
+    );
+    if !summary.skipped.is_empty() {
+        eprintln!(
+            "{} files skipped; see the report for details.",
+            summary.skipped.len()
+        );
+    }
+    summary
+}
+
+/// Replaces duplicates with symbolic links and appends the outcome to the report.
+fn run_symlink(
+    report: &DuplicateReport,
+    output_file: Option<&Path>,
+    format: ReportFormat,
+    confirm: bool,
+    quiet: bool,
+) -> SymlinkSummary {
+    let summary = symlink_duplicates(report, !confirm);
+    append_summary(output_file, format, "symbolic link", |mut w| {
+        summary.write_to(&mut w)
+    });
+    if summary.dry_run {
+        status(
+            quiet,
+            &format!(
+                "Dry run: {} files would be replaced with symbolic links; re-run with --confirm to apply.",
+                summary.linked.len()
+            ),
+        );
+    } else {
+        status(
+            quiet,
+            &format!(
+                "{} files replaced with symbolic links.",
+                summary.linked.len()
+            ),
+        );
+    }
+    info!(
+        "Symbolic link pass (dry run: {}): {} linked, {} skipped, {} bytes reclaimed",
+        summary.dry_run,
+        summary.linked.len(),
+        summary.skipped.len(),
+        summary.reclaimed_bytes
+    );
+    if !summary.skipped.is_empty() {
+        eprintln!(
+            "{} files skipped; see the report for details.",
+            summary.skipped.len()
+        );
+    }
+    summary
+}
+
+/// Replaces duplicates with reflink clones and appends the outcome to the report.
+fn run_reflink(
+    report: &DuplicateReport,
+    output_file: Option<&Path>,
+    format: ReportFormat,
+    confirm: bool,
+    quiet: bool,
+) {
+    let summary = reflink_duplicates(report, !confirm);
+    append_summary(output_file, format, "reflink", |mut w| {
+        summary.write_to(&mut w)
+    });
+    if summary.dry_run {
+        status(
+            quiet,
+            &format!(
+                "Dry run: {} files would be replaced with reflinks; re-run with --confirm to apply.",
+                summary.linked.len()
+            ),
+        );
+    } else {
+        status(
+            quiet,
+            &format!("{} files replaced with reflinks.", summary.linked.len()),
+        );
+    }
+    info!(
+        "Reflink pass (dry run: {}): {} cloned, {} skipped, {} bytes reclaimed",
+        summary.dry_run,
+        summary.linked.len(),
+        summary.skipped.len(),
+        summary.reclaimed_bytes
+    );
+    if !summary.skipped.is_empty() {
+        eprintln!(
+            "{} files skipped; see the report for details.",
+            summary.skipped.len()
+        );
+    }
+}
+
+/// Deletes redundant copies, or moves them to the trash, and appends the
+/// outcome to the report.
+///
+/// With no `keep` rule the user is asked which copies to keep in each group.
+fn run_delete(
+    report: &DuplicateReport,
+    output_file: Option<&Path>,
+    format: ReportFormat,
+    keep: Option<KeepStrategy>,
+    trash: bool,
+    confirm: bool,
+    quiet: bool,
+) -> DeleteSummary {
+    let summary = match keep {
+        Some(keep) if trash => trash_duplicates(report, keep, !confirm),
+        Some(keep) => delete_duplicates(report, keep, !confirm),
+        None => {
+            let chosen = interactive::choose_removals(
+                report,
+                std::io::stdin().lock(),
+                std::io::stdout().lock(),
+            );
+            match chosen {
+                Ok(files) => remove_selected(&files, trash, !confirm),
+                Err(e) => {
+                    eprintln!("Error reading answers: {e}");
+                    error!("Interactive prompt failed: {e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+    };
+    let done = if trash {
+        "moved to the trash"
+    } else {
+        "deleted"
+    };
+    append_summary(output_file, format, "deletion", |mut w| {
+        summary.write_to(&mut w)
+    });
+    if summary.dry_run {
+        status(
+            quiet,
+            &format!(
+                "Dry run: {} files would be {done}; re-run with --confirm to apply.",
+                summary.deleted.len()
+            ),
+        );
+    } else {
+        status(quiet, &format!("{} files {done}.", summary.deleted.len()));
+    }
+    info!(
+        "Delete pass (dry run: {}, trash: {trash}, keep: {keep:?}): {} removed, {} failed, {} bytes freed",
+        summary.dry_run,
+        summary.deleted.len(),
+        summary.failed.len(),
+        summary.freed_bytes
+    );
+    if !summary.failed.is_empty() {
+        eprintln!(
+            "{} files could not be removed; see the report for details.",
+            summary.failed.len()
+        );
+    }
+    summary
 }
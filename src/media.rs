@@ -0,0 +1,286 @@
+//! Hashing of what media files hold, leaving out their metadata.
+//!
+//! Exporting or syncing a photo often rewrites its metadata, its EXIF
+//! timestamps, XMP ratings or text chunks, while leaving the image itself
+//! untouched, and music players and taggers do the same to the tags and
+//! artwork of songs, so the copies differ byte for byte. Hashing only the
+//! parts of the file that decide what it shows or plays finds them anyway.
+
+use crate::{jpeg, png, HashAlgo};
+use std::io;
+use std::path::Path;
+
+/// The extensions, without the dot, of the images that can be compared by
+/// what they show.
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "jpe", "jfif"];
+
+/// The extensions, without the dot, of the audio files that can be compared
+/// by what they play.
+const AUDIO_EXTENSIONS: [&str; 3] = ["mp3", "flac", "m4a"];
+
+fn invalid(format: &str, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid {format} file: {message}"),
+    )
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            extensions
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(ext))
+        })
+}
+
+/// Returns `true` if `path` has the extension of a PNG or JPEG image.
+pub(crate) fn is_image(path: &Path) -> bool {
+    has_extension(path, &IMAGE_EXTENSIONS)
+}
+
+/// Returns `true` if `path` has the extension of an MP3, FLAC or M4A file.
+pub(crate) fn is_audio(path: &Path) -> bool {
+    has_extension(path, &AUDIO_EXTENSIONS)
+}
+
+/// Hashes what the image or audio file in `data` holds with `hash_algo`, or
+/// returns `None` if it is in none of the formats understood.
+///
+/// - A PNG image is hashed by its decoded pixels, so it matches however it
+///   was compressed; a JPEG image by its coded image data, so it matches
+///   only copies that were not re-encoded.
+/// - An MP3 file is hashed by what lies between its ID3, APE and Lyrics3
+///   tags, a FLAC file by its stream information and audio frames, leaving
+///   out Vorbis comments, pictures, padding and the seek table, and an M4A
+///   file by its media data alone, leaving out the tags and artwork of its
+///   movie box.
+pub(crate) fn content_hash(data: &[u8], hash_algo: HashAlgo) -> io::Result<Option<String>> {
+    let mut hasher = hash_algo.hasher();
+    let mut update = |bytes: &[u8]| hasher.update(bytes);
+    if png::is_png(data) {
+        update(b"png\0");
+        png::Png::parse(data)?.for_each_content_part(&mut update)?;
+    } else if jpeg::is_jpeg(data) {
+        update(b"jpeg\0");
+        jpeg::for_each_content_part(data, &mut update)?;
+    } else if data.starts_with(b"fLaC") || skip_id3v2(data).starts_with(b"fLaC") {
+        update(b"flac\0");
+        flac_content(skip_id3v2(data), &mut update)?;
+    } else if data.get(4..8) == Some(b"ftyp") {
+        update(b"m4a\0");
+        mp4_content(data, &mut update)?;
+    } else if data.starts_with(b"ID3") || is_mpeg_frame(data) {
+        update(b"mp3\0");
+        update(mp3_content(data));
+    } else {
+        return Ok(None);
+    }
+    Ok(Some(hasher.finish_hex()))
+}
+
+/// Returns `data` after the ID3 tags at its start, if it has any.
+fn skip_id3v2(mut data: &[u8]) -> &[u8] {
+    // Some writers add a new tag in front of an old one.
+    while let [b'I', b'D', b'3', _, _, flags, s0, s1, s2, s3, ..] = *data {
+        // The size is in seven-bit bytes, so that it never holds a frame sync.
+        let size = [s0, s1, s2, s3]
+            .iter()
+            .fold(0usize, |size, &byte| size << 7 | usize::from(byte & 0x7F));
+        let footer = if flags & 0x10 == 0 { 0 } else { 10 };
+        let Some(rest) = data.get(10 + size + footer..) else {
+            return &[];
+        };
+        data = rest;
+    }
+    data
+}
+
+/// Returns `true` if `data` starts with the header of an MPEG audio frame.
+fn is_mpeg_frame(data: &[u8]) -> bool {
+    matches!(data, [0xFF, second, ..] if second & 0xE0 == 0xE0)
+}
+
+/// Returns the audio frames of the MP3 file in `data`, without the tags at
+/// either end.
+fn mp3_content(data: &[u8]) -> &[u8] {
+    let mut data = skip_id3v2(data);
+    loop {
+        let len = data.len();
+        if len >= 128 && data[len - 128..].starts_with(b"TAG") {
+            data = &data[..len - 128];
+        } else if len >= 32 && data[len - 32..].starts_with(b"APETAGEX") {
+            let footer = &data[len - 32..];
+            let size = u32::from_le_bytes([footer[12], footer[13], footer[14], footer[15]]);
+            let flags = u32::from_le_bytes([footer[20], footer[21], footer[22], footer[23]]);
+            let header = if flags & 0x8000_0000 == 0 { 0 } else { 32 };
+            let size = usize::try_from(size).map_or(len, |size| size + header);
+            data = &data[..len.saturating_sub(size)];
+        } else if len >= 15 && data[len - 9..] == *b"LYRICS200" {
+            let size = std::str::from_utf8(&data[len - 15..len - 9])
+                .ok()
+                .and_then(|size| size.parse::<usize>().ok());
+            let Some(size) = size else {
+                return data;
+            };
+            data = &data[..len.saturating_sub(size + 15)];
+        } else {
+            return data;
+        }
+    }
+}
+
+/// Hands `on_part` the stream information and audio frames of the FLAC
+/// stream in `data`, leaving out every other metadata block.
+fn flac_content(data: &[u8], on_part: &mut dyn FnMut(&[u8])) -> io::Result<()> {
+    let mut pos = 4;
+    loop {
+        let header = data
+            .get(pos..pos + 4)
+            .ok_or_else(|| invalid("FLAC", "truncated metadata"))?;
+        let len =
+            usize::from(header[1]) << 16 | usize::from(header[2]) << 8 | usize::from(header[3]);
+        let body = data
+            .get(pos + 4..pos + 4 + len)
+            .ok_or_else(|| invalid("FLAC", "truncated metadata"))?;
+        // Block type 0 is the stream information, such as the sample rate
+        // and the MD5 of the decoded audio.
+        let kind = header[0] & 0x7F;
+        if kind == 0 {
+            on_part(body);
+        }
+        pos += 4 + len;
+        if header[0] & 0x80 != 0 {
+            break;
+        }
+    }
+    on_part(&data[pos..]);
+    Ok(())
+}
+
+/// Hands `on_part` the contents of every media data box of the MP4 file in
+/// `data`, where its audio is kept.
+fn mp4_content(data: &[u8], on_part: &mut dyn FnMut(&[u8])) -> io::Result<()> {
+    let mut pos = 0;
+    let mut found = false;
+    while pos < data.len() {
+        let header = data
+            .get(pos..pos + 8)
+            .ok_or_else(|| invalid("MP4", "truncated box"))?;
+        let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let (start, size) = match size {
+            // The box runs to the end of the file.
+            0 => (pos + 8, data.len() - pos),
+            // The size follows the type, in 64 bits.
+            1 => {
+                let large = data
+                    .get(pos + 8..pos + 16)
+                    .ok_or_else(|| invalid("MP4", "truncated box"))?;
+                let large = u64::from_be_bytes(large.try_into().unwrap_or_default());
+                (pos + 16, usize::try_from(large).unwrap_or(usize::MAX))
+            }
+            size => (pos + 8, usize::try_from(size).unwrap_or(usize::MAX)),
+        };
+        let end = pos
+            .checked_add(size)
+            .filter(|&end| end >= start && end <= data.len())
+            .ok_or_else(|| invalid("MP4", "bad box size"))?;
+        if &header[4..8] == b"mdat" {
+            on_part(&data[start..end]);
+            found = true;
+        }
+        pos = end;
+    }
+    if !found {
+        return Err(invalid("MP4", "no media data"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(x: usize, y: usize) -> u8 {
+        u8::try_from((x * 7 + y * 13) % 256).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_image_content_hash_ignores_metadata() {
+        let hash = |data: &[u8]| {
+            content_hash(data, HashAlgo::Xxh3)
+                .expect("valid image")
+                .expect("known format")
+        };
+
+        let png = png::encode(20, 10, false, pixel);
+        // A text chunk after the 33 bytes of signature and header.
+        let mut tagged = png[..33].to_vec();
+        tagged.extend(b"\0\0\0\x05tEXtA\0bcd\0\0\0\0");
+        tagged.extend_from_slice(&png[33..]);
+        assert_eq!(hash(&png), hash(&tagged));
+        let changed = png::encode(20, 10, false, |x, y| pixel(x, y) ^ u8::from(x == 3));
+        assert_ne!(hash(&png), hash(&changed));
+
+        let jpeg = jpeg::encode_dc_only(16, 16, pixel);
+        let mut tagged = jpeg[..2].to_vec();
+        tagged.extend(b"\xFF\xE1\0\x08Exif\0\0");
+        tagged.extend_from_slice(&jpeg[2..jpeg.len() - 2]);
+        tagged.extend(b"\xFF\xFE\0\x04hi\xFF\xD9trailing bytes");
+        assert_eq!(hash(&jpeg), hash(&tagged));
+        let changed = jpeg::encode_dc_only(16, 16, |x, y| pixel(x, y) / 2);
+        assert_ne!(hash(&jpeg), hash(&changed));
+
+        assert!(content_hash(b"GIF89a", HashAlgo::Xxh3)
+            .expect("not decoded")
+            .is_none());
+    }
+
+    #[test]
+    fn test_content_hash_ignores_audio_tags() {
+        let hash = |data: &[u8]| {
+            content_hash(data, HashAlgo::Xxh3)
+                .expect("valid file")
+                .expect("known format")
+        };
+        let frames = b"\xFF\xFB\x90\x64frame one\xFF\xFB\x90\x64frame two";
+
+        let mut tagged = b"ID3\x03\0\0\0\0\0\x05TIT2\0".to_vec();
+        tagged.extend_from_slice(frames);
+        let mut ape = b"APETAGEX\xD0\x07\0\0".to_vec();
+        ape.extend(36u32.to_le_bytes());
+        ape.extend([0; 16]);
+        tagged.extend(b"four");
+        tagged.extend(&ape);
+        let mut id3v1 = b"TAG".to_vec();
+        id3v1.resize(128, b' ');
+        tagged.extend(&id3v1);
+        assert_eq!(hash(frames), hash(&tagged));
+        assert_ne!(hash(frames), hash(&frames[..20]));
+
+        let streaminfo = [7; 34];
+        let mut flac = b"fLaC\x80\0\0\x22".to_vec();
+        flac.extend(streaminfo);
+        flac.extend(b"audio frames");
+        let mut tagged = b"fLaC\0\0\0\x22".to_vec();
+        tagged.extend(streaminfo);
+        tagged.extend(b"\x04\0\0\x05title\x86\0\0\x03png");
+        tagged.extend(b"audio frames");
+        assert_eq!(hash(&flac), hash(&tagged));
+        let mut changed = flac.clone();
+        *changed.last_mut().expect("frames") ^= 1;
+        assert_ne!(hash(&flac), hash(&changed));
+
+        let ftyp = b"\0\0\0\x0CftypM4A ";
+        let mdat = b"\0\0\0\x0Dmdatsound";
+        let mut m4a = ftyp.to_vec();
+        m4a.extend(b"\0\0\0\x0Cmoovudta");
+        m4a.extend(mdat);
+        let mut tagged = ftyp.to_vec();
+        tagged.extend(mdat);
+        tagged.extend(b"\0\0\0\x10moovcovrart!");
+        assert_eq!(hash(&m4a), hash(&tagged));
+        assert!(content_hash(b"\0\0\0\x0CftypM4A \0\0\0\x08free", HashAlgo::Xxh3).is_err());
+    }
+}
@@ -0,0 +1,230 @@
+//! Scan metrics in the Prometheus text format, served at `/metrics` by the
+//! `serve` subcommand and by `--watch` with `--metrics`.
+//!
+//! Counters add up every scan finished since the process started. The
+//! gauges describing what was found hold the result of the latest scan of
+//! each set of directories, labelled with those directories, so their
+//! history shows how duplication grows or shrinks over time.
+
+use crate::DuplicateReport;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The prefix of every metric name.
+const PREFIX: &str = "duplicate_file_finder";
+/// The upper bounds, in seconds, of the scan duration histogram buckets.
+const DURATION_BUCKETS: [f64; 8] = [1.0, 5.0, 15.0, 60.0, 300.0, 900.0, 3600.0, 14400.0];
+
+/// Metrics collected from every scan recorded.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    totals: Mutex<Totals>,
+}
+
+#[derive(Debug, Default)]
+struct Totals {
+    scans: u64,
+    interrupted: u64,
+    files_scanned: u64,
+    bytes_hashed: u64,
+    /// How many scans took at most each of [`DURATION_BUCKETS`].
+    buckets: [u64; DURATION_BUCKETS.len()],
+    duration_seconds: f64,
+    /// The latest result for each set of directories, keyed by their label.
+    latest: BTreeMap<String, Latest>,
+}
+
+/// The name and help text of a gauge, and how to read its value.
+type Gauge = (&'static str, &'static str, fn(&Latest) -> String);
+
+/// What the latest scan of a set of directories found.
+#[derive(Debug)]
+struct Latest {
+    groups: usize,
+    files: usize,
+    reclaimable_bytes: u64,
+    finished: f64,
+}
+
+impl Metrics {
+    /// Returns metrics that have recorded no scans yet.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a scan that produced `report` and took `duration`.
+    ///
+    /// An interrupted scan counts towards the totals, but leaves the gauges
+    /// describing what its directories hold unchanged, since it may have
+    /// missed duplicates.
+    pub fn record(&self, report: &DuplicateReport, duration: Duration) {
+        let mut totals = self.totals.lock().unwrap_or_else(PoisonError::into_inner);
+        totals.scans += 1;
+        totals.files_scanned += report.files_scanned;
+        totals.bytes_hashed += report.bytes_hashed;
+        let seconds = duration.as_secs_f64();
+        totals.duration_seconds += seconds;
+        for (count, bound) in totals.buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if seconds <= bound {
+                *count += 1;
+            }
+        }
+        if report.interrupted {
+            totals.interrupted += 1;
+            return;
+        }
+        let finished = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        totals.latest.insert(
+            label(&report.roots),
+            Latest {
+                groups: report.total_groups,
+                files: report.groups.iter().map(|group| group.paths.len()).sum(),
+                reclaimable_bytes: report.total_reclaimable_bytes,
+                finished,
+            },
+        );
+    }
+
+    /// Returns the metrics in the Prometheus text exposition format.
+    #[must_use]
+    pub fn render(&self) -> String {
+        let totals = self.totals.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut out = String::new();
+        let counters = [
+            ("scans_total", "Scans finished.", totals.scans),
+            (
+                "scans_interrupted_total",
+                "Scans cancelled before they finished.",
+                totals.interrupted,
+            ),
+            (
+                "files_scanned_total",
+                "Files found to compare by every scan.",
+                totals.files_scanned,
+            ),
+            (
+                "bytes_hashed_total",
+                "Bytes read to hash and compare files.",
+                totals.bytes_hashed,
+            ),
+        ];
+        for (name, help, value) in counters {
+            family(&mut out, name, "counter", help);
+            let _ = writeln!(out, "{PREFIX}_{name} {value}");
+        }
+
+        let name = "scan_duration_seconds";
+        family(&mut out, name, "histogram", "How long scans took.");
+        for (count, bound) in totals.buckets.iter().zip(DURATION_BUCKETS) {
+            let _ = writeln!(out, "{PREFIX}_{name}_bucket{{le=\"{bound}\"}} {count}");
+        }
+        let _ = writeln!(
+            out,
+            "{PREFIX}_{name}_bucket{{le=\"+Inf\"}} {}",
+            totals.scans
+        );
+        let _ = writeln!(out, "{PREFIX}_{name}_sum {}", totals.duration_seconds);
+        let _ = writeln!(out, "{PREFIX}_{name}_count {}", totals.scans);
+
+        let gauges: [Gauge; 4] = [
+            (
+                "duplicate_groups",
+                "Groups of identical files found by the latest scan of each set of directories.",
+                |latest| latest.groups.to_string(),
+            ),
+            (
+                "duplicate_files",
+                "Files in those groups, including the copy each would keep.",
+                |latest| latest.files.to_string(),
+            ),
+            (
+                "reclaimable_bytes",
+                "Bytes that keeping one file of each of those groups would free.",
+                |latest| latest.reclaimable_bytes.to_string(),
+            ),
+            (
+                "last_scan_timestamp_seconds",
+                "When the latest scan of each set of directories finished.",
+                |latest| latest.finished.to_string(),
+            ),
+        ];
+        for (name, help, value) in gauges {
+            family(&mut out, name, "gauge", help);
+            for (dirs, latest) in &totals.latest {
+                let _ = writeln!(
+                    out,
+                    "{PREFIX}_{name}{{directories=\"{dirs}\"}} {}",
+                    value(latest)
+                );
+            }
+        }
+        out
+    }
+}
+
+/// Writes the `HELP` and `TYPE` lines that precede the samples of a metric.
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {PREFIX}_{name} {help}");
+    let _ = writeln!(out, "# TYPE {PREFIX}_{name} {kind}");
+}
+
+/// Returns the label value naming `roots`: the directories joined by
+/// commas, escaped as the exposition format requires.
+fn label(roots: &[PathBuf]) -> String {
+    let joined: Vec<String> = roots
+        .iter()
+        .map(|root| root.to_string_lossy().into_owned())
+        .collect();
+    joined
+        .join(",")
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DuplicateGroup;
+
+    #[test]
+    fn test_render_metrics() {
+        let metrics = Metrics::new();
+        let mut report = DuplicateReport::from_groups(vec![DuplicateGroup {
+            hash: String::from("somehash"),
+            size: 100,
+            paths: vec![PathBuf::from("/data/a"), PathBuf::from("/data/b")],
+        }]);
+        report.roots = vec![PathBuf::from("/data"), PathBuf::from("/my \"files\"")];
+        report.files_scanned = 10;
+        report.bytes_hashed = 300;
+        metrics.record(&report, Duration::from_secs(2));
+        report.interrupted = true;
+        metrics.record(&report, Duration::from_secs(20));
+
+        let text = metrics.render();
+        assert!(text.starts_with(
+            "# HELP duplicate_file_finder_scans_total Scans finished.\n\
+             # TYPE duplicate_file_finder_scans_total counter\n\
+             duplicate_file_finder_scans_total 2\n"
+        ));
+        assert!(text.contains("duplicate_file_finder_scans_interrupted_total 1\n"));
+        assert!(text.contains("duplicate_file_finder_files_scanned_total 20\n"));
+        assert!(text.contains("duplicate_file_finder_bytes_hashed_total 600\n"));
+        assert!(text.contains("duplicate_file_finder_scan_duration_seconds_bucket{le=\"1\"} 0\n"));
+        assert!(text.contains("duplicate_file_finder_scan_duration_seconds_bucket{le=\"5\"} 1\n"));
+        assert!(text.contains("duplicate_file_finder_scan_duration_seconds_bucket{le=\"60\"} 2\n"));
+        assert!(text.contains("duplicate_file_finder_scan_duration_seconds_sum 22\n"));
+        assert!(text.contains(
+            "duplicate_file_finder_reclaimable_bytes{directories=\"/data,/my \\\"files\\\"\"} 100\n"
+        ));
+        assert!(text.contains("duplicate_file_finder_duplicate_files{directories="));
+    }
+}
@@ -0,0 +1,141 @@
+//! Read-only memory maps used to hash large files without a read loop.
+//!
+//! Mapping a file lets the hasher consume it in a single pass, with no copy
+//! through a user-space buffer and no per-chunk system calls. The trade-off is
+//! that if another process truncates the file while it is mapped, touching
+//! the vanished pages raises `SIGBUS` and ends the process; Rust cannot turn
+//! that into an error. [`Mmap::map`] therefore refuses files on network and
+//! FUSE filesystems on Linux, where files are most likely to change under us,
+//! and callers fall back to buffered reads whenever mapping is refused or
+//! fails. Local files that are truncated mid-scan remain a (rare) risk.
+
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::ptr::NonNull;
+
+/// Filesystem types whose files can change or disappear behind the kernel's back.
+#[cfg(target_os = "linux")]
+const VOLATILE_FILESYSTEMS: [u32; 5] = [
+    0x6969,      // NFS
+    0x517B,      // SMB
+    0xFF53_4D42, // CIFS
+    0xFE53_4D42, // SMB2
+    0x6573_5546, // FUSE (sshfs, rclone, ...)
+];
+
+/// A read-only, private mapping of a whole file, unmapped on drop.
+pub struct Mmap {
+    ptr: NonNull<libc::c_void>,
+    len: usize,
+}
+
+impl Mmap {
+    /// Maps the first `len` bytes of `file`, which must not be empty.
+    ///
+    /// # Errors
+    /// Returns an error if the file is on a volatile filesystem, if `len` is
+    /// zero or does not fit in memory, or if the kernel refuses the mapping.
+    pub fn map(file: &File, len: u64) -> io::Result<Self> {
+        let len = usize::try_from(len)
+            .ok()
+            .filter(|&len| len > 0)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "cannot map this length"))?;
+        check_filesystem(file)?;
+
+        // SAFETY: a null hint lets the kernel pick the address, the descriptor
+        // is open for reading, and the result is checked before use.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let ptr = NonNull::new(ptr).ok_or_else(|| io::Error::other("mmap returned null"))?;
+        advise_sequential(ptr, len);
+        Ok(Self { ptr, len })
+    }
+
+    /// Returns the mapped bytes.
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: the mapping covers `len` readable bytes and lives as long as `self`.
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr().cast::<u8>(), self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        // SAFETY: `ptr` and `len` describe a mapping created by `map` that has
+        // not been unmapped yet.
+        unsafe {
+            libc::munmap(self.ptr.as_ptr(), self.len);
+        }
+    }
+}
+
+/// Refuses files on filesystems listed in [`VOLATILE_FILESYSTEMS`].
+#[cfg(target_os = "linux")]
+fn check_filesystem(file: &File) -> io::Result<()> {
+    let mut stat = std::mem::MaybeUninit::<libc::statfs>::uninit();
+    // SAFETY: `stat` is large enough for the result and only read on success.
+    let stat = unsafe {
+        if libc::fstatfs(file.as_raw_fd(), stat.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stat.assume_init()
+    };
+    // Magic numbers are 32-bit even where `f_type` is wider.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let kind = stat.f_type as u32;
+    if VOLATILE_FILESYSTEMS.contains(&kind) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "not mapping a file on a network or FUSE filesystem",
+        ));
+    }
+    Ok(())
+}
+
+/// Filesystem types cannot be queried portably; every file may be mapped.
+#[cfg(not(target_os = "linux"))]
+fn check_filesystem(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+/// Asks the kernel to read ahead aggressively, since the hasher reads front to back.
+#[cfg(target_os = "linux")]
+fn advise_sequential(ptr: NonNull<libc::c_void>, len: usize) {
+    // SAFETY: the range is exactly the mapping just created; the advice is
+    // only a hint, so failure is harmless and ignored.
+    unsafe {
+        libc::madvise(ptr.as_ptr(), len, libc::MADV_SEQUENTIAL);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn advise_sequential(_ptr: NonNull<libc::c_void>, _len: usize) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_map_reads_file_contents() {
+        let dir = tempdir().expect("create temp dir");
+        let path = dir.path().join("data.bin");
+        std::fs::write(&path, b"mapped contents").expect("write file");
+        let file = File::open(&path).expect("open file");
+
+        let map = Mmap::map(&file, 15).expect("map file");
+        assert_eq!(map.as_slice(), b"mapped contents");
+        assert!(Mmap::map(&file, 0).is_err());
+    }
+}
@@ -0,0 +1,343 @@
+//! Detection of files that share a name but not their contents.
+//!
+//! This is the inverse of the usual search: instead of identical contents
+//! stored under different names, it finds one name holding different
+//! contents in different folders, as happens when copies of a document are
+//! edited separately and drift apart. Only files that share both a name and
+//! a size need to be hashed to tell their versions apart.
+
+use crate::{
+    build_pool, collect_files, format_size, full_hash_with, in_pool, into_errors, is_cancelled,
+    record_error, Console, GlobError, NameMatch, ScanConfig, ScanOptions, Stage,
+};
+use chrono::{DateTime, Local};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Files sharing a name that hold the same contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameVersion {
+    /// The size of each file in bytes.
+    pub size: u64,
+    /// When the most recently modified of the files was modified, if known.
+    pub modified: Option<SystemTime>,
+    /// The files, sorted by path.
+    pub paths: Vec<PathBuf>,
+}
+
+/// A file name shared by files with different contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameCollision {
+    /// The name, as the first of its files by path spells it.
+    pub name: String,
+    /// The distinct contents stored under the name, most recently modified
+    /// first.
+    pub versions: Vec<NameVersion>,
+}
+
+/// The result of a search for name collisions.
+#[derive(Debug)]
+pub struct NameCollisions {
+    /// The directories searched.
+    pub roots: Vec<PathBuf>,
+    /// How many files were examined.
+    pub files: usize,
+    /// The names held by files with different contents, sorted by name.
+    pub collisions: Vec<NameCollision>,
+    /// Files that could not be read, with the error raised.
+    pub errors: Vec<(PathBuf, io::Error)>,
+    /// Whether the search was cancelled before every file was hashed, in
+    /// which case some names may be missing.
+    pub interrupted: bool,
+}
+
+/// Finds the file names in `dirs` shared by files with different contents.
+///
+/// Files are chosen as in any scan, by every filter of `options` that
+/// applies to the walk and by size. Names are compared exactly unless
+/// [`ScanOptions::name_match`] is [`NameMatch::IgnoreCase`]. Names whose
+/// files are all identical are left out.
+///
+/// # Errors
+/// Returns an error if any pattern in `options` is invalid.
+pub fn find_name_collisions(
+    dirs: &[PathBuf],
+    options: &ScanOptions,
+) -> Result<NameCollisions, GlobError> {
+    let config = ScanConfig::new(options)?;
+    let console = Console::new(config.quiet, config.progress_json, config.progress);
+    let errors = Mutex::new(Vec::new());
+    let rule = match config.name_match {
+        NameMatch::IgnoreCase => NameMatch::IgnoreCase,
+        NameMatch::Any | NameMatch::Exact => NameMatch::Exact,
+    };
+    let files: Vec<_> = collect_files(dirs, &config.walk, config.cancel, &errors, &mut Vec::new())
+        .into_iter()
+        .filter(|file| {
+            let size = file.meta.len();
+            config.min_size.is_none_or(|min| size >= min)
+                && config.max_size.is_none_or(|max| size <= max)
+        })
+        .collect();
+    let examined = files.len();
+    console.status(&format!(
+        "{examined} files identified across {} directories",
+        dirs.len()
+    ));
+
+    let mut by_name: HashMap<OsString, Vec<(PathBuf, u64, Option<SystemTime>)>> = HashMap::new();
+    for file in files {
+        let entry = (file.path, file.meta.len(), file.meta.modified().ok());
+        by_name.entry(rule.key(&entry.0)).or_default().push(entry);
+    }
+    by_name.retain(|_, files| files.len() > 1);
+
+    // Files whose name and size are both shared must be hashed to tell
+    // whether they differ; a size of its own is a version of its own.
+    let mut counts: HashMap<(&OsString, u64), usize> = HashMap::new();
+    for (name, files) in &by_name {
+        for (_, size, _) in files {
+            *counts.entry((name, *size)).or_default() += 1;
+        }
+    }
+    let to_hash: Vec<(&Path, u64)> = by_name
+        .iter()
+        .flat_map(|(name, files)| {
+            let counts = &counts;
+            files
+                .iter()
+                .filter(move |(_, size, _)| counts[&(name, *size)] > 1)
+                .map(|(path, size, _)| (path.as_path(), *size))
+        })
+        .collect();
+    let total_bytes = to_hash.iter().map(|&(_, size)| size).sum();
+    let progress = console.byte_bar(
+        total_bytes,
+        Stage::FullHash,
+        "Hashing files sharing a name and size...",
+    );
+    let pool = build_pool(config.threads);
+    let hashes: HashMap<&Path, String> = in_pool(pool.as_ref(), || {
+        to_hash
+            .into_par_iter()
+            .filter_map(|(path, size)| {
+                if is_cancelled(config.cancel) {
+                    return None;
+                }
+                let on_read = |bytes| progress.inc(bytes);
+                let hash = config.read(path, || match config.cache {
+                    Some(cache) => cache.full_hash(path, config.hash_algo, &on_read),
+                    None => full_hash_with(path, config.hash_algo, &on_read),
+                });
+                config.pace(size);
+                match hash {
+                    Ok(hash) => Some((path, hash)),
+                    Err(e) => {
+                        record_error(&errors, path, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    });
+    progress.finish_with_message("Files hashed");
+
+    let mut collisions: Vec<NameCollision> = by_name
+        .iter()
+        .filter_map(|(name, files)| collision(files, &hashes, |size| counts[&(name, size)] > 1))
+        .collect();
+    collisions.sort_by(|a, b| a.name.cmp(&b.name));
+    console.complete(collisions.len());
+
+    Ok(NameCollisions {
+        roots: dirs.to_vec(),
+        files: examined,
+        collisions,
+        errors: into_errors(errors),
+        interrupted: is_cancelled(config.cancel),
+    })
+}
+
+/// Sorts `files`, which share a name, into their versions, returning
+/// `None` if they all hold the same contents. Files of a size for which
+/// `needs_hash` is `true` are told apart by their hash in `hashes`, and left
+/// out if they have none.
+fn collision(
+    files: &[(PathBuf, u64, Option<SystemTime>)],
+    hashes: &HashMap<&Path, String>,
+    needs_hash: impl Fn(u64) -> bool,
+) -> Option<NameCollision> {
+    let mut versions: HashMap<(u64, Option<&String>), NameVersion> = HashMap::new();
+    for (path, size, modified) in files {
+        let hash = hashes.get(path.as_path());
+        if needs_hash(*size) && hash.is_none() {
+            // Unreadable, or left unhashed by a cancelled search.
+            continue;
+        }
+        let version = versions.entry((*size, hash)).or_insert(NameVersion {
+            size: *size,
+            modified: None,
+            paths: Vec::new(),
+        });
+        version.modified = version.modified.max(*modified);
+        version.paths.push(path.clone());
+    }
+    if versions.len() < 2 {
+        return None;
+    }
+    let mut versions: Vec<NameVersion> = versions.into_values().collect();
+    for version in &mut versions {
+        version.paths.sort();
+    }
+    versions.sort_by(|a, b| {
+        b.modified
+            .cmp(&a.modified)
+            .then_with(|| a.paths.cmp(&b.paths))
+    });
+    let first = versions.iter().map(|version| &version.paths[0]).min()?;
+    let name = first.file_name().unwrap_or_default();
+    Some(NameCollision {
+        name: name.to_string_lossy().into_owned(),
+        versions,
+    })
+}
+
+/// Writes the names shared by files with different contents as plain text,
+/// each with its versions, most recently modified first.
+///
+/// # Errors
+/// Returns any error raised by `writer`.
+pub fn write_name_collisions_to<W: Write>(
+    writer: &mut W,
+    collisions: &NameCollisions,
+    start_time: &str,
+) -> io::Result<()> {
+    let username = whoami::username();
+    let end_time = Local::now().format("%Y%m%d %H:%M:%S").to_string();
+
+    writeln!(writer, "Name Collisions Report")?;
+    writeln!(writer, "Generated by: {username}")?;
+    writeln!(writer, "Start Time: {start_time}")?;
+    writeln!(writer, "End Time: {end_time}")?;
+    if collisions.roots.len() == 1 {
+        writeln!(writer, "Base Directory: {}", collisions.roots[0].display())?;
+    } else {
+        writeln!(writer, "Base Directories:")?;
+        for dir in &collisions.roots {
+            writeln!(writer, " - {}", dir.display())?;
+        }
+    }
+    writeln!(writer)?;
+
+    if collisions.interrupted {
+        writeln!(
+            writer,
+            "Scan Interrupted: the results below are incomplete."
+        )?;
+    }
+    writeln!(writer, "Files Examined: {}", collisions.files)?;
+    writeln!(
+        writer,
+        "Names With Differing Contents: {}",
+        collisions.collisions.len()
+    )?;
+    if !collisions.errors.is_empty() {
+        writeln!(
+            writer,
+            "Files Skipped Due To Errors: {}",
+            collisions.errors.len()
+        )?;
+    }
+    writeln!(writer)?;
+
+    for collision in &collisions.collisions {
+        writeln!(
+            writer,
+            "{} ({} versions):",
+            collision.name,
+            collision.versions.len()
+        )?;
+        for (i, version) in collision.versions.iter().enumerate() {
+            let modified = version.modified.map_or_else(
+                || "unknown".to_string(),
+                |time| {
+                    DateTime::<Local>::from(time)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string()
+                },
+            );
+            writeln!(
+                writer,
+                "  Version {} ({}, modified {modified}):",
+                i + 1,
+                format_size(version.size)
+            )?;
+            for path in &version.paths {
+                writeln!(writer, "    {}", path.display())?;
+            }
+        }
+        writeln!(writer)?;
+    }
+
+    if !collisions.errors.is_empty() {
+        writeln!(writer, "Skipped Files:")?;
+        for (path, error) in &collisions.errors {
+            writeln!(writer, "{}: {error}", path.display())?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_name_collisions() {
+        let dir = tempdir().expect("create temp dir");
+        for sub in ["a", "b", "c", "d"] {
+            fs::create_dir(dir.path().join(sub)).expect("create dir");
+        }
+        fs::write(dir.path().join("a/notes.txt"), b"first draft").expect("write file");
+        fs::write(dir.path().join("b/notes.txt"), b"first draft").expect("write file");
+        fs::write(dir.path().join("c/notes.txt"), b"final draft").expect("write file");
+        fs::write(dir.path().join("d/NOTES.txt"), b"a longer final draft").expect("write file");
+        fs::write(dir.path().join("a/same.txt"), b"same").expect("write file");
+        fs::write(dir.path().join("b/same.txt"), b"same").expect("write file");
+
+        let dirs = [dir.path().to_path_buf()];
+        let options = ScanOptions::new().quiet(true);
+        let found = find_name_collisions(&dirs, &options).expect("valid options");
+        assert_eq!(found.files, 6);
+        assert_eq!(found.collisions.len(), 1);
+        let collision = &found.collisions[0];
+        assert_eq!(collision.name, "notes.txt");
+        assert_eq!(collision.versions.len(), 2);
+        let shared = collision
+            .versions
+            .iter()
+            .find(|version| version.paths.len() == 2)
+            .expect("the two first drafts");
+        assert_eq!(
+            shared.paths,
+            [
+                dir.path().join("a/notes.txt"),
+                dir.path().join("b/notes.txt")
+            ]
+        );
+
+        let options = options.name_match(NameMatch::IgnoreCase);
+        let found = find_name_collisions(&dirs, &options).expect("valid options");
+        assert_eq!(found.collisions.len(), 1);
+        assert_eq!(found.collisions[0].name, "notes.txt");
+        assert_eq!(found.collisions[0].versions.len(), 3);
+    }
+}
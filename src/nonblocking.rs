@@ -0,0 +1,138 @@
+//! A scan that can be awaited from async code, enabled by the `async` feature.
+//!
+//! Hashing is blocking file I/O and CPU work, so running it on an async
+//! runtime's worker threads would stall every other task. [`find_duplicates_async`]
+//! instead runs the scan on a dedicated thread, with its hashing pool sized by
+//! [`ScanOptions::threads`], and returns a future that completes with the
+//! report. The future only uses `std::task`, so it works with any executor.
+
+use crate::{find_duplicates_with_options, DuplicateReport, GlobError, ScanOptions};
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+/// The result slot shared between the scan thread and the future.
+#[derive(Default)]
+struct Shared {
+    result: Option<Result<DuplicateReport, GlobError>>,
+    waker: Option<Waker>,
+}
+
+/// A scan running on its own thread; see [`find_duplicates_async`].
+pub struct ScanFuture {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Future for ScanFuture {
+    type Output = Result<DuplicateReport, GlobError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(result) = shared.result.take() {
+            Poll::Ready(result)
+        } else {
+            shared.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// Scans `dirs` without blocking the calling async runtime.
+///
+/// The scan starts immediately on a new thread, so it makes progress even
+/// before the future is first polled. Pass [`ScanOptions::threads`] to bound
+/// how many files are hashed at once; the default uses one thread per core.
+/// Dropping the future does not stop the scan; use [`ScanOptions::cancel`]
+/// for that.
+///
+/// # Example
+/// ```no_run
+/// use duplicate_file_finder::nonblocking::find_duplicates_async;
+/// use duplicate_file_finder::ScanOptions;
+/// use std::path::PathBuf;
+///
+/// async fn handler() -> Result<usize, Box<dyn std::error::Error>> {
+///     let options = ScanOptions::new().threads(2).quiet(true);
+///     let report = find_duplicates_async(vec![PathBuf::from("/srv/uploads")], &options).await?;
+///     Ok(report.total_groups)
+/// }
+/// ```
+#[must_use]
+pub fn find_duplicates_async(dirs: Vec<PathBuf>, options: &ScanOptions<'static>) -> ScanFuture {
+    let shared = Arc::new(Mutex::new(Shared::default()));
+    let options = options.clone();
+    let thread_shared = Arc::clone(&shared);
+    std::thread::spawn(move || {
+        let result = find_duplicates_with_options(&dirs, &options);
+        let mut shared = thread_shared.lock().unwrap_or_else(PoisonError::into_inner);
+        shared.result = Some(result);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    });
+    ScanFuture { shared }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::task::Wake;
+    use std::thread::Thread;
+    use tempfile::tempdir;
+
+    /// Wakes the test thread, which parks between polls.
+    struct ThreadWaker {
+        thread: Thread,
+        woken: AtomicBool,
+    }
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.woken.store(true, Ordering::Release);
+            self.thread.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let waker = Arc::new(ThreadWaker {
+            thread: std::thread::current(),
+            woken: AtomicBool::new(false),
+        });
+        let context_waker = Waker::from(Arc::clone(&waker));
+        let mut cx = Context::from_waker(&context_waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            while !waker.woken.swap(false, Ordering::Acquire) {
+                std::thread::park();
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_duplicates_async() {
+        let dir = tempdir().expect("create temp dir");
+        std::fs::write(dir.path().join("a.txt"), "same").expect("write file");
+        std::fs::write(dir.path().join("b.txt"), "same").expect("write file");
+        let options = ScanOptions::new().threads(1).quiet(true);
+
+        let report = block_on(find_duplicates_async(
+            vec![dir.path().to_path_buf()],
+            &options,
+        ))
+        .expect("valid globs");
+        assert_eq!(report.total_groups, 1);
+
+        let invalid = ScanOptions::new().exclude(["[oops"]);
+        assert!(block_on(find_duplicates_async(
+            vec![dir.path().to_path_buf()],
+            &invalid
+        ))
+        .is_err());
+    }
+}
@@ -0,0 +1,572 @@
+//! Notifications sent when a scan finishes: webhooks for `--notify-webhook`
+//! and email for `--email-to`.
+//!
+//! Both are sent by the `curl` command, which must be on the `PATH`, so
+//! `https` URLs and SMTP over TLS work without a TLS library in this crate.
+//! The webhook summary is JSON whose `text` and `content` members carry a
+//! one-line message, which is what Slack and Discord webhooks display;
+//! automation can read the other members instead. Email carries the same
+//! message with the report attached.
+
+use crate::json::quote;
+use crate::{format_size, DuplicateReport};
+use chrono::Local;
+use log::debug;
+use std::fmt::Write as _;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How long a webhook may take to answer, in seconds.
+const TIMEOUT_SECS: u32 = 30;
+/// How long sending an email may take, in seconds.
+const MAIL_TIMEOUT_SECS: u32 = 120;
+/// The environment variables overriding each SMTP setting, with the key
+/// setting it in a configuration file.
+const SMTP_VARIABLES: [(&str, &str); 4] = [
+    ("url", "DFF_SMTP_URL"),
+    ("from", "DFF_SMTP_FROM"),
+    ("user", "DFF_SMTP_USER"),
+    ("password", "DFF_SMTP_PASSWORD"),
+];
+
+/// Returns the JSON summary of `report` posted to webhooks.
+///
+/// Besides `text` and `content`, the object has `event`, which is always
+/// `"scan_complete"`, `directories`, `interrupted`, `duplicate_groups`,
+/// `duplicate_files`, `reclaimable_bytes`, `skipped_files` and `report`,
+/// the path of the report written, or `null` if none was.
+#[must_use]
+pub fn summary_json(report: &DuplicateReport, report_path: Option<&Path>) -> String {
+    let files: usize = report.groups.iter().map(|group| group.paths.len()).sum();
+    let message = summary_message(report, report_path);
+    let dirs: Vec<String> = report
+        .roots
+        .iter()
+        .map(|dir| quote(&dir.to_string_lossy()))
+        .collect();
+    let report_path =
+        report_path.map_or_else(|| "null".to_string(), |path| quote(&path.to_string_lossy()));
+    format!(
+        "{{\"event\":\"scan_complete\",\"text\":{message},\"content\":{message},\
+         \"directories\":[{}],\"interrupted\":{},\"duplicate_groups\":{},\
+         \"duplicate_files\":{files},\"reclaimable_bytes\":{},\"skipped_files\":{},\
+         \"report\":{report_path}}}",
+        dirs.join(","),
+        report.interrupted,
+        report.total_groups,
+        report.total_reclaimable_bytes,
+        report.errors.len(),
+        message = quote(&message)
+    )
+}
+
+/// Returns the one-line message describing `report`, naming the report
+/// written, if any.
+fn summary_message(report: &DuplicateReport, report_path: Option<&Path>) -> String {
+    let mut message = format!(
+        "Duplicate scan finished: {} duplicate groups, {} reclaimable",
+        report.total_groups,
+        format_size(report.total_reclaimable_bytes)
+    );
+    if report.interrupted {
+        message.push_str(" (interrupted; results are incomplete)");
+    }
+    if let Some(path) = report_path {
+        let _ = write!(message, ". Report: {}", path.display());
+    }
+    message
+}
+
+/// Posts `body` as JSON to `url`.
+///
+/// # Errors
+/// Returns an error if `curl` cannot be run, or if the request fails or is
+/// answered with an error status.
+pub fn post_json(url: &str, body: &str) -> io::Result<()> {
+    debug!("Posting {} bytes to {url}", body.len());
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--max-time"])
+        .arg(TIMEOUT_SECS.to_string())
+        .args(["--header", "Content-Type: application/json"])
+        .args(["--data-binary", "@-", "--output"])
+        .arg(if cfg!(windows) { "NUL" } else { "/dev/null" })
+        .arg("--")
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot run curl: {e}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(body.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().next().unwrap_or("no details");
+        return Err(io::Error::other(format!("curl failed: {reason}")));
+    }
+    Ok(())
+}
+
+/// How to reach the SMTP server that email is sent through.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SmtpSettings {
+    /// The server, as `smtps://host:465` for TLS from the start or
+    /// `smtp://host:587` to upgrade the connection with STARTTLS.
+    pub url: String,
+    /// The sender, as `someone@example.com` or `Name <someone@example.com>`.
+    pub from: String,
+    /// The user name to log in with, if the server requires one.
+    pub user: Option<String>,
+    /// The password to log in with.
+    pub password: Option<String>,
+}
+
+impl SmtpSettings {
+    /// Reads the settings from the configuration file at `config`, if
+    /// given, with each overridden by its environment variable:
+    /// `DFF_SMTP_URL`, `DFF_SMTP_FROM`, `DFF_SMTP_USER` and
+    /// `DFF_SMTP_PASSWORD`.
+    ///
+    /// The file holds one `key = value` line per setting, with the keys
+    /// `url`, `from`, `user` and `password`. Blank lines and lines
+    /// starting with `#` are ignored.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or holds an unknown
+    /// key, or if the URL or sender is not set anywhere.
+    pub fn load(config: Option<&Path>) -> io::Result<Self> {
+        let text = match config {
+            Some(path) => fs::read_to_string(path).map_err(|e| {
+                io::Error::new(e.kind(), format!("cannot read {}: {e}", path.display()))
+            })?,
+            None => String::new(),
+        };
+        Self::parse(&text, |name| std::env::var(name).ok())
+    }
+
+    /// Parses a configuration file's `text`, overriding its settings with
+    /// the variables `env` returns.
+    fn parse(text: &str, env: impl Fn(&str) -> Option<String>) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut settings = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("line {}: expected key = value", number + 1)))?;
+            settings
+                .set(key.trim(), value.trim().to_string())
+                .map_err(|()| {
+                    invalid(format!(
+                        "line {}: unknown setting {}",
+                        number + 1,
+                        key.trim()
+                    ))
+                })?;
+        }
+        for (key, variable) in SMTP_VARIABLES {
+            if let Some(value) = env(variable) {
+                settings
+                    .set(key, value)
+                    .map_err(|()| invalid(key.to_string()))?;
+            }
+        }
+        if settings.url.is_empty() {
+            return Err(invalid(
+                "no SMTP server set; set url in the configuration file or DFF_SMTP_URL".to_string(),
+            ));
+        }
+        if settings.from.is_empty() {
+            return Err(invalid(
+                "no sender set; set from in the configuration file or DFF_SMTP_FROM".to_string(),
+            ));
+        }
+        Ok(settings)
+    }
+
+    fn set(&mut self, key: &str, value: String) -> Result<(), ()> {
+        match key {
+            "url" => self.url = value,
+            "from" => self.from = value,
+            "user" => self.user = Some(value),
+            "password" => self.password = Some(value),
+            _ => return Err(()),
+        }
+        Ok(())
+    }
+}
+
+/// Emails the summary of `report` to `to`, attaching the report written
+/// at `report_path`, if any.
+///
+/// The connection must be encrypted when logging in; without a user it is
+/// encrypted if the server offers it.
+///
+/// # Errors
+/// Returns an error if an address holds a line break, if the report cannot
+/// be read, if `curl` cannot be run, or if the server refuses the message.
+pub fn email_report(
+    settings: &SmtpSettings,
+    to: &[String],
+    report: &DuplicateReport,
+    report_path: Option<&Path>,
+) -> io::Result<()> {
+    // A line break would end the header, or the SMTP command, and start
+    // another.
+    if let Some(address) = std::iter::once(&settings.from)
+        .chain(to)
+        .find(|address| address.contains(['\r', '\n']))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("email address {address:?} holds a line break"),
+        ));
+    }
+    let attachment = match report_path {
+        Some(path) => Some((path, fs::read(path)?)),
+        None => None,
+    };
+    let dirs: Vec<String> = report
+        .roots
+        .iter()
+        .map(|dir| dir.display().to_string())
+        .collect();
+    let subject = format!(
+        "Duplicate files in {}: {} groups, {} reclaimable",
+        dirs.join(", "),
+        report.total_groups,
+        format_size(report.total_reclaimable_bytes)
+    );
+    let message = email_message(
+        &settings.from,
+        to,
+        &subject,
+        &summary_message(report, report_path),
+        attachment
+            .as_ref()
+            .map(|(path, data)| (*path, data.as_slice())),
+    );
+    send_email(settings, to, &message)
+}
+
+/// Builds the email, encoding the text and attachment as base64 so they
+/// pass through any server.
+fn email_message(
+    from: &str,
+    to: &[String],
+    subject: &str,
+    text: &str,
+    attachment: Option<(&Path, &[u8])>,
+) -> String {
+    let boundary = format!("duplicate-file-finder-{}", unique());
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "From: {from}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\n\
+         Message-ID: <{}@duplicate-file-finder>\r\nMIME-Version: 1.0\r\n\
+         Content-Type: multipart/mixed; boundary=\"{boundary}\"\r\n\r\n",
+        to.join(", "),
+        encode_header(subject),
+        Local::now().to_rfc2822(),
+        unique()
+    );
+    let _ = write!(
+        out,
+        "--{boundary}\r\nContent-Type: text/plain; charset=utf-8\r\n\
+         Content-Transfer-Encoding: base64\r\n\r\n{}",
+        base64_lines(format!("{text}\n").as_bytes())
+    );
+    if let Some((path, data)) = attachment {
+        // Names outside ASCII would need encoding that not every client
+        // reads, and the report's own name matters little.
+        let name: String = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_graphic() && c != '"' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let _ = write!(
+            out,
+            "--{boundary}\r\nContent-Type: {}; name=\"{name}\"\r\n\
+             Content-Disposition: attachment; filename=\"{name}\"\r\n\
+             Content-Transfer-Encoding: base64\r\n\r\n{}",
+            content_type(path),
+            base64_lines(data)
+        );
+    }
+    let _ = write!(out, "--{boundary}--\r\n");
+    out
+}
+
+/// Returns the MIME type a report at `path` is attached as.
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("csv") => "text/csv; charset=utf-8",
+        Some("html") => "text/html; charset=utf-8",
+        Some("json") => "application/json",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Encodes a header value holding text outside ASCII as RFC 2047 requires.
+/// Line breaks, which directory names may hold, become spaces, so the value
+/// cannot end the header and start another.
+fn encode_header(value: &str) -> String {
+    let value = value.replace(['\r', '\n'], " ");
+    if value.is_ascii() {
+        value
+    } else {
+        format!("=?utf-8?B?{}?=", base64(value.as_bytes()))
+    }
+}
+
+/// Returns a value that differs between calls, for boundaries and message
+/// ids.
+fn unique() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    format!("{nanos}.{}", std::process::id())
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Encodes `data` as base64 in lines of 76 characters, each ended by CRLF.
+fn base64_lines(data: &[u8]) -> String {
+    let encoded = base64(data);
+    let mut out = String::with_capacity(encoded.len() + encoded.len() / 38 + 2);
+    // The encoding is ASCII, so any byte offset is a boundary.
+    for line in encoded.as_bytes().chunks(76) {
+        out.push_str(std::str::from_utf8(line).unwrap_or_default());
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// Sends `message` to `to` through the server in `settings`.
+///
+/// The settings are handed to `curl` as a configuration on its stdin, so
+/// the password never appears in its command line, and the message is
+/// uploaded from a temporary file.
+fn send_email(settings: &SmtpSettings, to: &[String], message: &str) -> io::Result<()> {
+    let path = std::env::temp_dir().join(format!("duplicate_file_finder-{}.eml", unique()));
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    let sent = file
+        .write_all(message.as_bytes())
+        .and_then(|()| run_curl_smtp(settings, to, &path));
+    drop(file);
+    if let Err(e) = fs::remove_file(&path) {
+        debug!("Failed to remove {}: {e}", path.display());
+    }
+    sent
+}
+
+fn run_curl_smtp(settings: &SmtpSettings, to: &[String], message: &Path) -> io::Result<()> {
+    // The envelope takes the bare address of a sender given with a name.
+    let sender = match settings.from.rsplit_once('<') {
+        Some((_, rest)) => rest.trim_end_matches('>'),
+        None => settings.from.as_str(),
+    };
+    // curl takes the path of the URL as the name to greet the server with,
+    // and gives one without a path the name of the uploaded file instead.
+    let url = settings.url.trim_end_matches('/');
+    let has_path = url
+        .split_once("://")
+        .is_some_and(|(_, rest)| rest.contains('/'));
+    let url = if has_path {
+        url.to_string()
+    } else {
+        let host = whoami::fallible::hostname().unwrap_or_else(|_| "localhost".to_string());
+        format!("{url}/{host}")
+    };
+    let mut config = format!(
+        "url = {}\nmail-from = {}\nupload-file = {}\nmax-time = {MAIL_TIMEOUT_SECS}\n",
+        curl_quote(&url),
+        curl_quote(sender),
+        curl_quote(&message.to_string_lossy())
+    );
+    for recipient in to {
+        let _ = writeln!(config, "mail-rcpt = {}", curl_quote(recipient));
+    }
+    match &settings.user {
+        Some(user) => {
+            let password = settings.password.as_deref().unwrap_or("");
+            let _ = writeln!(
+                config,
+                "user = {}",
+                curl_quote(&format!("{user}:{password}"))
+            );
+            config.push_str("ssl-reqd\n");
+        }
+        None => config.push_str("ssl\n"),
+    }
+
+    debug!(
+        "Sending email to {} through {}",
+        to.join(", "),
+        settings.url
+    );
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot run curl: {e}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(config.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().next().unwrap_or("no details");
+        return Err(io::Error::other(format!("curl failed: {reason}")));
+    }
+    Ok(())
+}
+
+/// Quotes `value` for a `curl` configuration file.
+pub(crate) fn curl_quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+    format!("\"{escaped}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+    use crate::DuplicateGroup;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_smtp_settings() {
+        let config =
+            "# Mail relay\nurl = smtp://mail.example:587\nfrom = Backups <b@example.com>\n\
+                      user = backups\n";
+        let env = |name: &str| (name == "DFF_SMTP_PASSWORD").then(|| "hunter2".to_string());
+        let settings = SmtpSettings::parse(config, env).expect("valid settings");
+        assert_eq!(
+            settings,
+            SmtpSettings {
+                url: "smtp://mail.example:587".to_string(),
+                from: "Backups <b@example.com>".to_string(),
+                user: Some("backups".to_string()),
+                password: Some("hunter2".to_string()),
+            }
+        );
+        assert!(SmtpSettings::parse("url = smtp://mail.example\n", |_| None).is_err());
+        assert!(SmtpSettings::parse("port = 25\n", |_| None).is_err());
+    }
+
+    #[test]
+    fn test_email_message() {
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+        let message = email_message(
+            "a@example.com",
+            &["b@example.com".to_string(), "c@example.com".to_string()],
+            "Résumé",
+            "hello",
+            Some((Path::new("/tmp/report.csv"), b"a,b\n")),
+        );
+        assert!(message.starts_with("From: a@example.com\r\nTo: b@example.com, c@example.com\r\n"));
+        assert!(message.contains("Subject: =?utf-8?B?UsOpc3Vtw6k=?=\r\n"));
+        assert!(message.contains("Content-Type: text/csv; charset=utf-8; name=\"report.csv\""));
+        assert!(message.contains("\r\n\r\naGVsbG8K\r\n"));
+        assert!(message.contains("\r\n\r\nYSxiCg==\r\n"));
+        assert!(message.ends_with("--\r\n"));
+
+        let message = email_message(
+            "a@example.com",
+            &["b@example.com".to_string()],
+            "Duplicate files in /tmp/x\r\nBcc: c@example.com",
+            "hello",
+            None,
+        );
+        assert!(message.contains("Subject: Duplicate files in /tmp/x  Bcc: c@example.com\r\n"));
+        let settings =
+            SmtpSettings::parse("url = smtp://mail.example\nfrom = a@example.com\n", |_| {
+                None
+            })
+            .expect("valid settings");
+        let to = ["b@example.com\r\nBcc: c@example.com".to_string()];
+        let error = email_report(&settings, &to, &DuplicateReport::default(), None)
+            .expect_err("line break in address");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_summary_json() {
+        let mut report = DuplicateReport::from_groups(vec![DuplicateGroup {
+            hash: String::from("somehash"),
+            size: 2048,
+            paths: vec![PathBuf::from("/data/a"), PathBuf::from("/data/b")],
+        }]);
+        report.roots = vec![PathBuf::from("/data")];
+        let body = summary_json(&report, Some(Path::new("/tmp/report.txt")));
+        let value = json::parse(&body).expect("valid JSON");
+        assert_eq!(
+            value.get("text").and_then(json::Value::as_str),
+            Some("Duplicate scan finished: 1 duplicate groups, 2.00 KB reclaimable. Report: /tmp/report.txt")
+        );
+        assert_eq!(
+            value.get("duplicate_files").and_then(json::Value::as_u64),
+            Some(2)
+        );
+        assert_eq!(
+            value.get("reclaimable_bytes").and_then(json::Value::as_u64),
+            Some(2048)
+        );
+        assert_eq!(
+            value.get("report").and_then(json::Value::as_str),
+            Some("/tmp/report.txt")
+        );
+
+        let body = summary_json(&report, None);
+        assert!(body.ends_with("\"report\":null}"));
+    }
+}
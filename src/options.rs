@@ -0,0 +1,553 @@
+//! Builder-style configuration for [`find_duplicates_with_options`].
+//!
+//! [`find_duplicates_with_options`]: crate::find_duplicates_with_options
+
+use crate::progress::Observer;
+use crate::{
+    find_duplicates_with_options, DuplicateReport, FileType, GlobError, HashAlgo, HashCache,
+    NameMatch, ProgressObserver, QuickHashSample, DEFAULT_QUICK_HASH_BYTES,
+};
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+
+/// Everything that controls a scan, with defaults matching [`find_duplicates_report`].
+///
+/// Start from [`ScanOptions::new`] and chain only the settings that differ
+/// from the defaults. New settings are added as new methods, so code written
+/// against this type keeps compiling as the crate grows.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::{find_duplicates_with_options, HashAlgo, ScanOptions};
+/// use tempfile::tempdir;
+///
+/// let dir = tempdir().unwrap();
+/// let options = ScanOptions::new()
+///     .exclude(["**/.git/**", "*.tmp"])
+///     .min_size(4096)
+///     .hash_algo(HashAlgo::Blake3)
+///     .quiet(true);
+/// let report = find_duplicates_with_options(&[dir.path().to_path_buf()], &options).unwrap();
+/// assert!(report.is_empty());
+///
+/// // Or finish the chain directly.
+/// let report = ScanOptions::new().quiet(true).run(&[dir.path().to_path_buf()]).unwrap();
+/// assert!(report.is_empty());
+/// ```
+///
+/// [`find_duplicates_report`]: crate::find_duplicates_report
+#[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ScanOptions<'a> {
+    pub(crate) exclude: Vec<String>,
+    pub(crate) include: Vec<String>,
+    pub(crate) exclude_dirs: Vec<String>,
+    pub(crate) include_regex: Vec<String>,
+    pub(crate) exclude_regex: Vec<String>,
+    pub(crate) file_types: Vec<FileType>,
+    pub(crate) ignore_files: Vec<PathBuf>,
+    pub(crate) respect_gitignore: bool,
+    pub(crate) skip_hidden: bool,
+    pub(crate) min_size: Option<u64>,
+    pub(crate) max_size: Option<u64>,
+    pub(crate) hash_algo: HashAlgo,
+    pub(crate) quick_hash_bytes: u64,
+    pub(crate) quick_hash_sample: QuickHashSample,
+    pub(crate) paranoid: bool,
+    pub(crate) compare_pairs: bool,
+    pub(crate) ignore_image_metadata: bool,
+    pub(crate) audio_content: bool,
+    pub(crate) scan_archives: bool,
+    pub(crate) decompress: bool,
+    pub(crate) low_memory: bool,
+    pub(crate) threads: usize,
+    pub(crate) io_threads: usize,
+    pub(crate) serialize_hdd: bool,
+    pub(crate) max_read_rate: u64,
+    pub(crate) follow_symlinks: bool,
+    pub(crate) one_file_system: bool,
+    pub(crate) skip_files: Vec<PathBuf>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) name_match: NameMatch,
+    pub(crate) cache: Option<&'a HashCache>,
+    pub(crate) quiet: bool,
+    pub(crate) progress_json: bool,
+    pub(crate) progress: Option<Observer<'a>>,
+    pub(crate) cancel: Option<&'a AtomicBool>,
+}
+
+impl Default for ScanOptions<'_> {
+    fn default() -> Self {
+        Self {
+            exclude: Vec::new(),
+            include: Vec::new(),
+            exclude_dirs: Vec::new(),
+            include_regex: Vec::new(),
+            exclude_regex: Vec::new(),
+            file_types: Vec::new(),
+            ignore_files: Vec::new(),
+            respect_gitignore: false,
+            skip_hidden: false,
+            min_size: None,
+            max_size: None,
+            hash_algo: HashAlgo::default(),
+            quick_hash_bytes: DEFAULT_QUICK_HASH_BYTES,
+            quick_hash_sample: QuickHashSample::default(),
+            paranoid: false,
+            compare_pairs: false,
+            ignore_image_metadata: false,
+            audio_content: false,
+            scan_archives: false,
+            decompress: false,
+            low_memory: false,
+            threads: 0,
+            io_threads: 0,
+            serialize_hdd: true,
+            max_read_rate: 0,
+            follow_symlinks: false,
+            one_file_system: false,
+            skip_files: Vec::new(),
+            max_depth: None,
+            name_match: NameMatch::default(),
+            cache: None,
+            quiet: false,
+            progress_json: false,
+            progress: None,
+            cancel: None,
+        }
+    }
+}
+
+impl<'a> ScanOptions<'a> {
+    /// Returns the default options.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Skips paths matching any of these globs, relative to each scanned
+    /// directory. Matching directories are not descended into.
+    #[must_use]
+    pub fn exclude<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Collects only files matching at least one of these globs, relative to
+    /// each scanned directory; no patterns includes every file. Directories
+    /// are still descended into, and [`exclude`](Self::exclude) wins over
+    /// this.
+    #[must_use]
+    pub fn include<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.include = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Prunes whole directories from the walk. Each entry is either the path
+    /// of an existing directory, when it contains a path separator, or a
+    /// glob: one without a `/`, such as `node_modules`, matches a directory
+    /// name at any depth, and one with a `/` matches the path relative to
+    /// each scanned directory.
+    #[must_use]
+    pub fn exclude_dirs<I, S>(mut self, dirs: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude_dirs = dirs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Collects only files whose absolute path matches at least one of these
+    /// regular expressions, for selections globs cannot express. A pattern
+    /// matches anywhere in the path unless anchored with `^` or `$`.
+    #[must_use]
+    pub fn include_regex<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.include_regex = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Skips files whose absolute path matches any of these regular
+    /// expressions.
+    #[must_use]
+    pub fn exclude_regex<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude_regex = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Collects only files with an extension of one of these types; no types
+    /// collects every file. Combined with [`include`](Self::include), a file
+    /// must match both.
+    #[must_use]
+    pub fn file_types(mut self, types: impl IntoIterator<Item = FileType>) -> Self {
+        self.file_types = types.into_iter().collect();
+        self
+    }
+
+    /// Reads ignore rules from these files, in the syntax of `.gitignore`,
+    /// with patterns relative to each scanned directory. A `.dupeignore`
+    /// file in any directory of the walk is always read as well, with
+    /// patterns relative to its own directory and taking precedence over
+    /// these. Files that cannot be read are skipped with a warning.
+    #[must_use]
+    pub fn ignore_files<I, P>(mut self, files: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.ignore_files = files.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Also skips what git ignores: paths matched by `.gitignore` files
+    /// and each repository's `.git/info/exclude`, and `.git` directories
+    /// themselves, so build artifacts and vendored dependencies in source
+    /// trees are left out.
+    #[must_use]
+    pub fn respect_gitignore(mut self, respect: bool) -> Self {
+        self.respect_gitignore = respect;
+        self
+    }
+
+    /// Leaves out hidden files and directories: those whose name starts
+    /// with a dot and, on Windows, those with the hidden or system
+    /// attribute. Hidden entries are scanned by default. A scanned
+    /// directory is always walked, even if it is hidden itself.
+    #[must_use]
+    pub fn skip_hidden(mut self, skip: bool) -> Self {
+        self.skip_hidden = skip;
+        self
+    }
+
+    /// Ignores files smaller than this many bytes.
+    #[must_use]
+    pub fn min_size(mut self, bytes: impl Into<Option<u64>>) -> Self {
+        self.min_size = bytes.into();
+        self
+    }
+
+    /// Ignores files larger than this many bytes.
+    #[must_use]
+    pub fn max_size(mut self, bytes: impl Into<Option<u64>>) -> Self {
+        self.max_size = bytes.into();
+        self
+    }
+
+    /// Sets the algorithm used for the final full-content hash.
+    #[must_use]
+    pub fn hash_algo(mut self, hash_algo: HashAlgo) -> Self {
+        self.hash_algo = hash_algo;
+        self
+    }
+
+    /// Sets how many bytes the quick hash samples from each block of a file.
+    #[must_use]
+    pub fn quick_hash_bytes(mut self, bytes: u64) -> Self {
+        self.quick_hash_bytes = bytes;
+        self
+    }
+
+    /// Sets which blocks of a file the quick hash samples.
+    #[must_use]
+    pub fn quick_hash_sample(mut self, sample: QuickHashSample) -> Self {
+        self.quick_hash_sample = sample;
+        self
+    }
+
+    /// Compares the files of every group byte for byte after hashing, so
+    /// that only files with identical contents are reported even if two
+    /// differing files share a digest. This reads every candidate file a
+    /// second time; it matters most with [`HashAlgo::Xxh3`] or
+    /// [`HashAlgo::Md5`], or before deleting files.
+    #[must_use]
+    pub fn paranoid(mut self, paranoid: bool) -> Self {
+        self.paranoid = paranoid;
+        self
+    }
+
+    /// Compares the two files of a group byte for byte instead of hashing
+    /// them, whatever their size; small pairs are always compared. Files that
+    /// differ are only read up to their first difference, which makes large
+    /// files that share a size and quick hash but little else, such as disk
+    /// images, much faster to tell apart. Their hash is not stored in the
+    /// [`cache`](Self::cache), and their group's
+    /// [`hash`](crate::DuplicateGroup::hash) is a key rather than a digest.
+    #[must_use]
+    pub fn compare_pairs(mut self, compare: bool) -> Self {
+        self.compare_pairs = compare;
+        self
+    }
+
+    /// Compares PNG and JPEG images, recognised by their extension, by what
+    /// they show rather than byte for byte, so copies whose metadata differs,
+    /// such as EXIF dates, XMP ratings or text chunks, are still duplicates.
+    /// A PNG image is compared by its decoded pixels and a JPEG image by its
+    /// image data with the metadata segments left out, which matches copies
+    /// that were not re-encoded.
+    ///
+    /// Every image is read in full, whatever its size, since copies with
+    /// different metadata differ in size too. A file that cannot be decoded
+    /// is compared byte for byte with the other images. The groups of images
+    /// are not checked by [`paranoid`](Self::paranoid), their files may
+    /// differ in size, and their hashes are not stored in the
+    /// [`cache`](Self::cache).
+    #[must_use]
+    pub fn ignore_image_metadata(mut self, ignore: bool) -> Self {
+        self.ignore_image_metadata = ignore;
+        self
+    }
+
+    /// Compares MP3, FLAC and M4A files, recognised by their extension, by
+    /// their audio alone, so copies whose ID3 tags, Vorbis comments or
+    /// embedded artwork differ are still duplicates. Like
+    /// [`ignore_image_metadata`](Self::ignore_image_metadata), every such
+    /// file is read in full and its groups may mix sizes.
+    #[must_use]
+    pub fn audio_content(mut self, audio: bool) -> Self {
+        self.audio_content = audio;
+        self
+    }
+
+    /// Also compares the files inside zip, tar and `.tar.gz` archives, with
+    /// each other and with files outside them. Each is reported as the
+    /// archive's path, `!/` and its path inside, such as
+    /// `backup.zip!/docs/report.pdf`, so such paths cannot be deleted or
+    /// linked. Every archive is read in full, files inside are compared by
+    /// their full hash rather than byte for byte, and only the size limits
+    /// apply to them. Files outside archives that share a size with a file
+    /// inside one are hashed in full without the [`cache`](Self::cache).
+    #[must_use]
+    pub fn scan_archives(mut self, scan: bool) -> Self {
+        self.scan_archives = scan;
+        self
+    }
+
+    /// Compares `.gz`, `.bz2`, `.xz` and `.zst` files, recognised by their
+    /// extension, by their decompressed contents, so `log.txt.gz` is a
+    /// duplicate of `log.txt` holding the same text, and two copies
+    /// compressed differently are duplicates of each other. Bzip2, xz and
+    /// Zstandard files are decompressed by the `bzip2`, `xz` and `zstd`
+    /// commands, and are skipped with an error where those cannot be run.
+    ///
+    /// As with [`scan_archives`](Self::scan_archives), every compressed file
+    /// is decompressed in full and compared by its full hash, and files that
+    /// share a size with a decompressed one are hashed in full without the
+    /// [`cache`](Self::cache). A group has the decompressed size, and the
+    /// size limits apply to it.
+    #[must_use]
+    pub fn decompress(mut self, decompress: bool) -> Self {
+        self.decompress = decompress;
+        self
+    }
+
+    /// Keeps the list of files found on disk rather than in memory, for
+    /// scans of tens of millions of files. The list is written to the
+    /// system's temporary directory and read back in batches of files of
+    /// some sizes, each searched before the next is read. Files reached by
+    /// several paths cost one more metadata read each.
+    #[must_use]
+    pub fn low_memory(mut self, low_memory: bool) -> Self {
+        self.low_memory = low_memory;
+        self
+    }
+
+    /// Limits the scan to this many threads; `0` uses one per core.
+    #[must_use]
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Limits the stages that read file contents, the quick and full hashes,
+    /// to this many threads, so a scan of spinning disks or a busy file
+    /// server can keep few reads in flight while the rest of the scan uses
+    /// [`threads`](Self::threads). `0` uses the same threads as the rest of
+    /// the scan.
+    #[must_use]
+    pub fn io_threads(mut self, threads: usize) -> Self {
+        self.io_threads = threads;
+        self
+    }
+
+    /// Reads files on each spinning disk one at a time while hashing, as
+    /// parallel reads make a hard disk seek between files; solid-state
+    /// drives are still read in parallel. Disks are recognised on Linux
+    /// only. On by default.
+    #[must_use]
+    pub fn serialize_hdd(mut self, serialize: bool) -> Self {
+        self.serialize_hdd = serialize;
+        self
+    }
+
+    /// Limits how fast the hashing stages read, in bytes per second across
+    /// all threads, so a scan can run on a production server without
+    /// starving other work of disk bandwidth. Hashes served from the cache
+    /// read nothing and are not slowed down. `0`, the default, reads as fast
+    /// as the disks allow.
+    #[must_use]
+    pub fn max_read_rate(mut self, bytes_per_second: u64) -> Self {
+        self.max_read_rate = bytes_per_second;
+        self
+    }
+
+    /// Follows symbolic links to directories and files, reporting each file
+    /// at most once however many links lead to it. Links that point back to
+    /// an ancestor are skipped. Without this, links are skipped entirely.
+    #[must_use]
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Keeps the walk on the filesystem of each scanned directory, so it
+    /// does not wander into network mounts, snapshots or bind mounts below
+    /// it. Mount points themselves are skipped.
+    #[must_use]
+    pub fn one_file_system(mut self, one: bool) -> Self {
+        self.one_file_system = one;
+        self
+    }
+
+    /// Leaves these files out of the scan however they are reached, so a
+    /// program's own report, log or cache in a scanned directory is not
+    /// compared with the files around it. Files are recognised by identity
+    /// rather than name; those that do not exist when the scan starts are
+    /// ignored.
+    #[must_use]
+    pub fn skip_files<I, P>(mut self, files: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.skip_files = files.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Visits at most this many levels below each directory; `0` is the
+    /// directory itself and `None` is unlimited.
+    #[must_use]
+    pub fn max_depth(mut self, depth: impl Into<Option<usize>>) -> Self {
+        self.max_depth = depth.into();
+        self
+    }
+
+    /// Requires duplicates to share a file name as well as their content.
+    #[must_use]
+    pub fn name_match(mut self, name_match: NameMatch) -> Self {
+        self.name_match = name_match;
+        self
+    }
+
+    /// Reuses and updates full hashes stored in `cache`.
+    #[must_use]
+    pub fn cache(mut self, cache: impl Into<Option<&'a HashCache>>) -> Self {
+        self.cache = cache.into();
+        self
+    }
+
+    /// Hides progress bars and status lines.
+    #[must_use]
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Reports progress as JSON lines on stderr instead of progress bars.
+    #[must_use]
+    pub fn progress_json(mut self, progress_json: bool) -> Self {
+        self.progress_json = progress_json;
+        self
+    }
+
+    /// Sends progress and status lines to `observer` instead of drawing
+    /// progress bars and printing to stdout.
+    #[must_use]
+    pub fn progress(mut self, observer: &'a dyn ProgressObserver) -> Self {
+        self.progress = Some(Observer(observer));
+        self
+    }
+
+    /// Stops hashing as soon as `flag` is set, returning the groups found so far.
+    #[must_use]
+    pub fn cancel(mut self, flag: impl Into<Option<&'a AtomicBool>>) -> Self {
+        self.cancel = flag.into();
+        self
+    }
+
+    /// Scans `dirs` with these options; the same as
+    /// [`find_duplicates_with_options`], for callers who prefer to finish a
+    /// builder chain with a method.
+    ///
+    /// # Errors
+    /// Returns a [`GlobError`] if any of the glob patterns or regular
+    /// expressions is invalid.
+    pub fn run(&self, dirs: &[PathBuf]) -> Result<DuplicateReport, GlobError> {
+        find_duplicates_with_options(dirs, self)
+    }
+
+    /// Checks that every glob pattern and regular expression compiles,
+    /// without scanning anything.
+    ///
+    /// # Errors
+    /// Returns a [`GlobError`] naming the first invalid pattern.
+    pub fn validate(&self) -> Result<(), GlobError> {
+        crate::ScanConfig::new(self).map(drop)
+    }
+}
+
+/// How [`write_output_to`] and [`write_output_report`] lay out the text
+/// report, with defaults matching [`write_output`].
+///
+/// Like [`ScanOptions`], it is built by chaining only the settings that
+/// differ from the defaults, so new settings do not break existing code.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::{write_output_to, DuplicateReport, OutputOptions};
+///
+/// let mut buffer = Vec::new();
+/// let options = OutputOptions::new().verbose(true);
+/// write_output_to(&mut buffer, &DuplicateReport::default(), "20250101 12:00:00", &[], &options)
+///     .unwrap();
+/// assert!(String::from_utf8(buffer).unwrap().starts_with("Duplicate File Finder Report"));
+/// ```
+///
+/// [`write_output_to`]: crate::write_output_to
+/// [`write_output_report`]: crate::write_output_report
+/// [`write_output`]: crate::write_output
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    pub(crate) verbose: bool,
+}
+
+impl OutputOptions {
+    /// Options for the plain report.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lists each file's modification time, size and read-only state under
+    /// its path.
+    #[must_use]
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+}
@@ -0,0 +1,418 @@
+//! Decoding of PNG images into rows of pixels.
+//!
+//! Only what is needed to compare images by what they show is decoded: the
+//! header, the palette, the transparency and the image data. Other chunks,
+//! such as text, timestamps and colour profiles, are ignored, and chunk
+//! checksums are not checked.
+
+use crate::inflate::Inflate;
+use std::io::{self, Read};
+
+pub(crate) const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Where each of the seven passes of an interlaced image starts in the
+/// image, and how far apart its pixels are: x, y, step across and step down.
+const ADAM7: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid PNG image: {message}"),
+    )
+}
+
+/// Returns `true` if `data` starts like a PNG image.
+pub(crate) fn is_png(data: &[u8]) -> bool {
+    data.starts_with(&SIGNATURE)
+}
+
+/// A PNG image whose chunks have been read but not yet decompressed.
+pub(crate) struct Png<'a> {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    depth: u8,
+    color: u8,
+    interlaced: bool,
+    palette: &'a [u8],
+    /// The body of the `tRNS` chunk, which makes some colours transparent.
+    transparency: &'a [u8],
+    /// The compressed image data of every `IDAT` chunk, in order.
+    data: Vec<u8>,
+}
+
+/// One row of pixels, with its filter undone.
+pub(crate) struct Row<'a> {
+    /// The row of the image this is.
+    pub(crate) y: u32,
+    /// The column of the first pixel, and how far apart the pixels are: in
+    /// an interlaced image, each pass has a share of the pixels of a row.
+    pub(crate) x: u32,
+    pub(crate) step: u32,
+    /// How many pixels the row has.
+    pub(crate) len: u32,
+    /// The pixels, in the image's own format.
+    pub(crate) bytes: &'a [u8],
+}
+
+impl<'a> Png<'a> {
+    /// Reads the chunks of the PNG image in `data`.
+    pub(crate) fn parse(data: &'a [u8]) -> io::Result<Self> {
+        if !is_png(data) {
+            return Err(invalid("no PNG signature"));
+        }
+        let mut png = Self {
+            width: 0,
+            height: 0,
+            depth: 0,
+            color: 0,
+            interlaced: false,
+            palette: &[],
+            transparency: &[],
+            data: Vec::new(),
+        };
+        let mut rest = &data[SIGNATURE.len()..];
+        let mut seen_header = false;
+        loop {
+            // Some writers leave out the closing chunk.
+            if rest.is_empty() && seen_header {
+                break;
+            }
+            if rest.len() < 12 {
+                return Err(invalid("truncated chunk"));
+            }
+            let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]);
+            let len = usize::try_from(len).map_err(|_| invalid("chunk too long"))?;
+            let kind = &rest[4..8];
+            let body = rest
+                .get(8..8 + len)
+                .ok_or_else(|| invalid("truncated chunk"))?;
+            rest = rest.get(12 + len..).unwrap_or_default();
+            match kind {
+                b"IHDR" => {
+                    png.read_header(body)?;
+                    seen_header = true;
+                }
+                _ if !seen_header => return Err(invalid("no header")),
+                b"PLTE" => png.palette = body,
+                b"tRNS" => png.transparency = body,
+                b"IDAT" => png.data.extend_from_slice(body),
+                b"IEND" => break,
+                _ => {}
+            }
+        }
+        if png.color == 3 && png.palette.is_empty() {
+            return Err(invalid("no palette"));
+        }
+        Ok(png)
+    }
+
+    fn read_header(&mut self, body: &[u8]) -> io::Result<()> {
+        let [w0, w1, w2, w3, h0, h1, h2, h3, depth, color, compression, filter, interlace] = *body
+        else {
+            return Err(invalid("bad header length"));
+        };
+        self.width = u32::from_be_bytes([w0, w1, w2, w3]);
+        self.height = u32::from_be_bytes([h0, h1, h2, h3]);
+        self.depth = depth;
+        self.color = color;
+        self.interlaced = interlace == 1;
+        let depth_allowed = match color {
+            0 => matches!(depth, 1 | 2 | 4 | 8 | 16),
+            3 => matches!(depth, 1 | 2 | 4 | 8),
+            2 | 4 | 6 => matches!(depth, 8 | 16),
+            _ => false,
+        };
+        if !depth_allowed || compression != 0 || filter != 0 || interlace > 1 {
+            return Err(invalid("unsupported format"));
+        }
+        if self.width == 0 || self.height == 0 {
+            return Err(invalid("empty image"));
+        }
+        Ok(())
+    }
+
+    /// The number of samples in each pixel.
+    fn channels(&self) -> u32 {
+        match self.color {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    /// Decompresses the image, handing each row to `on_row` with its filter
+    /// undone. Interlaced images are handed over one pass at a time.
+    pub(crate) fn for_each_row(&self, on_row: &mut dyn FnMut(Row<'_>)) -> io::Result<()> {
+        let bits = u64::from(self.channels()) * u64::from(self.depth);
+        // Filters work on whole pixels or, below eight bits, whole bytes.
+        let distance = usize::try_from(bits.div_ceil(8)).unwrap_or(1);
+        let passes: &[_] = if self.interlaced {
+            &ADAM7
+        } else {
+            &[(0, 0, 1, 1)]
+        };
+        let mut inflate = Inflate::zlib(self.data.as_slice())?;
+        for &(x, y, step, step_down) in passes {
+            let len = self.width.saturating_sub(x).div_ceil(step);
+            let rows = self.height.saturating_sub(y).div_ceil(step_down);
+            if len == 0 || rows == 0 {
+                continue;
+            }
+            let row_bytes = usize::try_from((u64::from(len) * bits).div_ceil(8))
+                .map_err(|_| invalid("image too wide"))?;
+            let mut previous = vec![0; row_bytes];
+            let mut row = vec![0; row_bytes + 1];
+            for i in 0..rows {
+                inflate.read_exact(&mut row).map_err(|e| {
+                    if e.kind() == io::ErrorKind::UnexpectedEof {
+                        invalid("image data ends early")
+                    } else {
+                        e
+                    }
+                })?;
+                unfilter(row[0], &mut row[1..], &previous, distance)?;
+                on_row(Row {
+                    y: y + i * step_down,
+                    x,
+                    step,
+                    len,
+                    bytes: &row[1..],
+                });
+                previous.copy_from_slice(&row[1..]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands `on_bytes` everything that decides what the image shows, and
+    /// nothing else: its size and format, its palette and transparency, and
+    /// its rows of pixels, top to bottom and with any interlacing undone.
+    /// Two files holding the same pixels in the same format thus give the
+    /// same bytes, however they were compressed and whatever other chunks
+    /// they have.
+    pub(crate) fn for_each_content_part(&self, on_bytes: &mut dyn FnMut(&[u8])) -> io::Result<()> {
+        on_bytes(&self.width.to_be_bytes());
+        on_bytes(&self.height.to_be_bytes());
+        on_bytes(&[self.depth, self.color]);
+        for chunk in [self.palette, self.transparency] {
+            on_bytes(&u32::try_from(chunk.len()).unwrap_or(u32::MAX).to_be_bytes());
+            on_bytes(chunk);
+        }
+
+        let bits = usize::from(self.depth) * usize::try_from(self.channels()).unwrap_or(1);
+        let width = usize::try_from(self.width).map_err(|_| invalid("image too wide"))?;
+        let row_bytes = width
+            .checked_mul(bits)
+            .map(|row_bits| row_bits.div_ceil(8))
+            .ok_or_else(|| invalid("image too wide"))?;
+        // The bits after the last pixel of a row are left to the writer, so
+        // they are cleared.
+        let last_mask = match width * bits % 8 {
+            0 => 0xFF,
+            used => 0xFFu8 << (8 - used),
+        };
+        if !self.interlaced {
+            return self.for_each_row(&mut |row| {
+                if let Some((&last, bytes)) = row.bytes.split_last() {
+                    on_bytes(bytes);
+                    on_bytes(&[last & last_mask]);
+                }
+            });
+        }
+
+        // The passes of an interlaced image each hold some pixels of many
+        // rows, so the whole image is put back together first.
+        let height = usize::try_from(self.height).map_err(|_| invalid("image too tall"))?;
+        let mut image = vec![
+            0;
+            row_bytes
+                .checked_mul(height)
+                .ok_or_else(|| invalid("image too large"))?
+        ];
+        self.for_each_row(&mut |row| {
+            let start = usize::try_from(row.y).unwrap_or_default() * row_bytes;
+            let target = &mut image[start..start + row_bytes];
+            let (x, step) = (
+                usize::try_from(row.x).unwrap_or_default(),
+                usize::try_from(row.step).unwrap_or_default(),
+            );
+            for i in 0..usize::try_from(row.len).unwrap_or_default() {
+                copy_pixel(row.bytes, i, target, x + i * step, bits);
+            }
+        })?;
+        for row in image.chunks(row_bytes) {
+            on_bytes(row);
+        }
+        Ok(())
+    }
+
+    /// Returns the brightness, from 0 to 255, of pixel `i` of a row.
+    pub(crate) fn luma(&self, bytes: &[u8], i: usize) -> u8 {
+        let channels = usize::try_from(self.channels()).unwrap_or(1);
+        let sample = |channel: usize| -> u8 {
+            let index = i * channels + channel;
+            match self.depth {
+                8 => bytes[index],
+                // The high byte is precise enough for brightness.
+                16 => bytes[index * 2],
+                depth => {
+                    let bit = index * usize::from(depth);
+                    let mask = (1u8 << depth) - 1;
+                    let value = (bytes[bit / 8] >> (8 - usize::from(depth) - bit % 8)) & mask;
+                    if self.color == 3 {
+                        value
+                    } else {
+                        // Spread the levels of low-depth grey over 0 to 255.
+                        value * (255 / mask)
+                    }
+                }
+            }
+        };
+        match self.color {
+            0 | 4 => sample(0),
+            2 | 6 => luma(sample(0), sample(1), sample(2)),
+            _ => {
+                let index = usize::from(sample(0)) * 3;
+                match self.palette.get(index..index + 3) {
+                    Some(&[r, g, b]) => luma(r, g, b),
+                    _ => 0,
+                }
+            }
+        }
+    }
+}
+
+/// Copies pixel `from` of `source` to pixel `to` of `target`, both rows of
+/// pixels of `bits` bits each. `target` must be zero where the pixel goes.
+fn copy_pixel(source: &[u8], from: usize, target: &mut [u8], to: usize, bits: usize) {
+    if bits >= 8 {
+        let len = bits / 8;
+        target[to * len..(to + 1) * len].copy_from_slice(&source[from * len..(from + 1) * len]);
+        return;
+    }
+    let (from, to) = (from * bits, to * bits);
+    let mask = (1u8 << bits) - 1;
+    let value = (source[from / 8] >> (8 - bits - from % 8)) & mask;
+    target[to / 8] |= value << (8 - bits - to % 8);
+}
+
+/// The brightness of a colour, weighted as in ITU-R BT.601.
+pub(crate) fn luma(r: u8, g: u8, b: u8) -> u8 {
+    let weighted = 299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b);
+    u8::try_from(weighted / 1000).unwrap_or(u8::MAX)
+}
+
+/// Undoes the filter of a row, given the previous row with its filter undone
+/// and the `distance` in bytes between corresponding bytes of neighbouring
+/// pixels.
+fn unfilter(filter: u8, row: &mut [u8], previous: &[u8], distance: usize) -> io::Result<()> {
+    match filter {
+        0 => {}
+        1 => {
+            for i in distance..row.len() {
+                row[i] = row[i].wrapping_add(row[i - distance]);
+            }
+        }
+        2 => {
+            for (byte, &up) in row.iter_mut().zip(previous) {
+                *byte = byte.wrapping_add(up);
+            }
+        }
+        3 => {
+            for i in 0..row.len() {
+                let left = if i >= distance { row[i - distance] } else { 0 };
+                let average = u16::midpoint(u16::from(left), u16::from(previous[i]));
+                row[i] = row[i].wrapping_add(u8::try_from(average).unwrap_or_default());
+            }
+        }
+        4 => {
+            for i in 0..row.len() {
+                let (left, up_left) = if i >= distance {
+                    (row[i - distance], previous[i - distance])
+                } else {
+                    (0, 0)
+                };
+                row[i] = row[i].wrapping_add(paeth(left, previous[i], up_left));
+            }
+        }
+        _ => return Err(invalid("unknown filter")),
+    }
+    Ok(())
+}
+
+/// Predicts a byte from its neighbours to the left, above and above-left.
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let (a, b, c) = (i16::from(left), i16::from(up), i16::from(up_left));
+    let p = a + b - c;
+    let (pa, pb, pc) = ((p - a).abs(), (p - b).abs(), (p - c).abs());
+    if pa <= pb && pa <= pc {
+        left
+    } else if pb <= pc {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// Encodes a PNG image for tests, compressed with stored blocks. `gray`
+/// selects 8-bit grey rows with the Sub filter rather than unfiltered RGB.
+#[cfg(test)]
+pub(crate) fn encode(
+    width: usize,
+    height: usize,
+    gray: bool,
+    pixel: impl Fn(usize, usize) -> u8,
+) -> Vec<u8> {
+    let mut raw = Vec::new();
+    for y in 0..height {
+        raw.push(u8::from(gray));
+        let mut left = 0;
+        for x in 0..width {
+            let value = pixel(x, y);
+            if gray {
+                raw.push(value.wrapping_sub(left));
+                left = value;
+            } else {
+                raw.extend([value; 3]);
+            }
+        }
+    }
+    let mut zlib = vec![0x78, 0x01];
+    let mut chunks = raw.chunks(0xFFFF).peekable();
+    while let Some(chunk) = chunks.next() {
+        let len = u16::try_from(chunk.len()).expect("short block");
+        zlib.push(u8::from(chunks.peek().is_none()));
+        zlib.extend(len.to_le_bytes());
+        zlib.extend((!len).to_le_bytes());
+        zlib.extend_from_slice(chunk);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in &raw {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    zlib.extend(((b << 16) | a).to_be_bytes());
+
+    let mut header = Vec::new();
+    header.extend(u32::try_from(width).expect("small").to_be_bytes());
+    header.extend(u32::try_from(height).expect("small").to_be_bytes());
+    header.extend([8, if gray { 0 } else { 2 }, 0, 0, 0]);
+    let mut png = SIGNATURE.to_vec();
+    for (kind, body) in [(b"IHDR", header), (b"IDAT", zlib), (b"IEND", Vec::new())] {
+        png.extend(u32::try_from(body.len()).expect("small").to_be_bytes());
+        png.extend(kind);
+        png.extend(body);
+        // Chunk checksums are not checked.
+        png.extend([0; 4]);
+    }
+    png
+}
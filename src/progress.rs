@@ -0,0 +1,140 @@
+//! Progress reporting for frontends that draw their own display.
+//!
+//! By default a scan draws progress bars and prints status lines itself.
+//! Passing a [`ProgressObserver`] to [`ScanOptions::progress`] replaces both:
+//! nothing is written to stdout and every update goes to the observer.
+//!
+//! [`ScanOptions::progress`]: crate::ScanOptions::progress
+
+use std::fmt;
+
+/// A stage of the duplicate search, in the order the stages run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Grouping every file by size.
+    Size,
+    /// Hashing a sample of each file that shares its size with another.
+    QuickHash,
+    /// Hashing the whole of each file whose quick hash matched another.
+    FullHash,
+    /// Decoding each image for a search for similar images, which runs
+    /// instead of the other stages.
+    ImageHash,
+    /// Hashing what each image or audio file holds, without its metadata,
+    /// after the other stages.
+    MediaHash,
+    /// Hashing the files inside each archive, and later the files outside
+    /// archives that share a size with one of them or with a decompressed
+    /// file.
+    ArchiveHash,
+    /// Hashing the decompressed contents of each compressed file, after the
+    /// archives are read.
+    Decompress,
+    /// Sampling frames of each video for a search for similar videos, which
+    /// runs instead of the other stages.
+    VideoHash,
+    /// Reading each text file for a search for similar text files, which
+    /// runs instead of the other stages.
+    TextHash,
+    /// Splitting each file into chunks for a search for partial
+    /// duplicates, which runs instead of the other stages.
+    ChunkHash,
+}
+
+impl Stage {
+    /// Returns the stage's name as used in `--progress-json` events.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Size => "size",
+            Self::QuickHash => "quick_hash",
+            Self::FullHash => "full_hash",
+            Self::ImageHash => "image_hash",
+            Self::MediaHash => "media_hash",
+            Self::ArchiveHash => "archive_hash",
+            Self::Decompress => "decompress",
+            Self::VideoHash => "video_hash",
+            Self::TextHash => "text_hash",
+            Self::ChunkHash => "chunk_hash",
+        }
+    }
+}
+
+/// Receives progress updates from a scan.
+///
+/// Every method has an empty default, so implementors only override what they
+/// display. Stages that have nothing to do are skipped and report nothing.
+/// A [low-memory](crate::ScanOptions::low_memory) scan of many files runs
+/// the stages once for each batch of files, starting each of them again.
+/// Updates can come from the hashing threads, but they are delivered one at
+/// a time, so no two calls overlap.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::{ProgressObserver, ScanOptions, Stage};
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use tempfile::tempdir;
+///
+/// #[derive(Default)]
+/// struct Counter {
+///     sized: AtomicU64,
+/// }
+///
+/// impl ProgressObserver for Counter {
+///     fn progress(&self, stage: Stage, done: u64, _total: u64) {
+///         if stage == Stage::Size {
+///             self.sized.store(done, Ordering::Relaxed);
+///         }
+///     }
+/// }
+///
+/// let dir = tempdir().unwrap();
+/// std::fs::write(dir.path().join("a.txt"), b"same").unwrap();
+/// std::fs::write(dir.path().join("b.txt"), b"same").unwrap();
+/// let counter = Counter::default();
+/// let report = ScanOptions::new()
+///     .progress(&counter)
+///     .run(&[dir.path().to_path_buf()])
+///     .unwrap();
+/// assert_eq!(report.total_groups, 1);
+/// assert_eq!(counter.sized.load(Ordering::Relaxed), 2);
+/// ```
+pub trait ProgressObserver: Sync {
+    /// Called when `stage` starts, with the amount of work it has: files for
+    /// the size stage, groups of same-size files for the quick-hash stage and
+    /// bytes to hash for the full-hash stage.
+    fn stage_started(&self, stage: Stage, total: u64) {
+        let _ = (stage, total);
+    }
+
+    /// Called each time more of `stage`'s work is done; `done` never decreases.
+    fn progress(&self, stage: Stage, done: u64, total: u64) {
+        let _ = (stage, done, total);
+    }
+
+    /// Called when `stage` has finished its work.
+    fn stage_finished(&self, stage: Stage) {
+        let _ = stage;
+    }
+
+    /// Receives the status lines a scan would otherwise print, such as how
+    /// many files were found.
+    fn message(&self, text: &str) {
+        let _ = text;
+    }
+
+    /// Called once at the end of the scan with the number of groups found.
+    fn finished(&self, groups: usize) {
+        let _ = groups;
+    }
+}
+
+/// A borrowed observer that can sit in structs deriving `Debug`.
+#[derive(Clone, Copy)]
+pub(crate) struct Observer<'a>(pub(crate) &'a dyn ProgressObserver);
+
+impl fmt::Debug for Observer<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressObserver")
+    }
+}
@@ -0,0 +1,570 @@
+//! Minimal regular expressions used to filter paths by `--include-regex`
+//! and `--exclude-regex`.
+//!
+//! A pattern matches if it matches anywhere in the text, unless anchored.
+//! The supported syntax is:
+//!
+//! * `.` matches any character; `^` and `$` match the start and end.
+//! * `[abc]`, `[a-z]` and `[^a-z]` match a single character from (or not
+//!   from) a set.
+//! * `\d`, `\w` and `\s` match digits, word characters and whitespace, and
+//!   `\D`, `\W` and `\S` their opposites. `\` escapes any other punctuation.
+//! * `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}` repeat the item before them; a
+//!   trailing `?` making them lazy is accepted and changes nothing, since
+//!   only whether a path matches is ever asked.
+//! * `a|b` matches either side, and `(...)` or `(?:...)` groups.
+//! * A leading `(?i)` makes the whole pattern case-insensitive.
+//!
+//! Patterns are compiled to a state machine that is run over the text in a
+//! single pass, so matching time grows linearly with the path length
+//! whatever the pattern.
+
+use std::error::Error;
+use std::fmt;
+
+/// Repetition counts above this are rejected.
+const MAX_REPEAT: u32 = 1000;
+/// Compiled programs larger than this are rejected.
+const MAX_PROGRAM: usize = 100_000;
+
+/// An error produced when a regular expression given to
+/// [`ScanOptions::include_regex`](crate::ScanOptions::include_regex) or
+/// [`ScanOptions::exclude_regex`](crate::ScanOptions::exclude_regex) cannot
+/// be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegexError {
+    pattern: String,
+    reason: &'static str,
+}
+
+impl RegexError {
+    /// Returns the regular expression that failed to parse.
+    #[must_use]
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid regular expression '{}': {}",
+            self.pattern, self.reason
+        )
+    }
+}
+
+impl Error for RegexError {}
+
+/// A compiled regular expression.
+#[derive(Debug, Clone)]
+pub(crate) struct Regex {
+    program: Vec<Inst>,
+    ignore_case: bool,
+}
+
+/// A set of characters, as written in `[...]` or by an escape such as `\d`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Class {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+}
+
+impl Class {
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        let contains = |c: char| self.ranges.iter().any(|&(lo, hi)| (lo..=hi).contains(&c));
+        let found = if ignore_case {
+            contains(c) || c.to_lowercase().any(contains) || c.to_uppercase().any(contains)
+        } else {
+            contains(c)
+        };
+        found != self.negated
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    /// Continue at both targets.
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+impl Regex {
+    /// Compiles `pattern`.
+    pub(crate) fn new(pattern: &str) -> Result<Self, RegexError> {
+        let err = |reason| RegexError {
+            pattern: pattern.to_string(),
+            reason,
+        };
+        let (ignore_case, body) = match pattern.strip_prefix("(?i)") {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let mut parser = Parser {
+            chars: body.chars().collect(),
+            pos: 0,
+        };
+        let node = parser.alternation().map_err(err)?;
+        if parser.pos < parser.chars.len() {
+            return Err(err("unmatched `)`"));
+        }
+        let mut program = Vec::new();
+        compile(&node, &mut program).map_err(err)?;
+        program.push(Inst::Match);
+        if ignore_case {
+            for inst in &mut program {
+                if let Inst::Char(c) = inst {
+                    *c = fold(*c);
+                }
+            }
+        }
+        Ok(Self {
+            program,
+            ignore_case,
+        })
+    }
+
+    /// Returns `true` if the pattern matches anywhere in `text`.
+    pub(crate) fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        let len = self.program.len();
+        let mut current = Threads::new(len);
+        let mut next = Threads::new(len);
+        for pos in 0..=chars.len() {
+            // A match may start at any position.
+            if self.add(&mut current, 0, pos, chars.len()) {
+                return true;
+            }
+            let Some(&c) = chars.get(pos) else {
+                break;
+            };
+            let c = if self.ignore_case { fold(c) } else { c };
+            for i in 0..current.list.len() {
+                let pc = current.list[i];
+                let advances = match &self.program[pc] {
+                    Inst::Char(expected) => *expected == c,
+                    Inst::Any => true,
+                    Inst::Class(class) => class.matches(c, self.ignore_case),
+                    _ => false,
+                };
+                if advances && self.add(&mut next, pc + 1, pos + 1, chars.len()) {
+                    return true;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+        false
+    }
+
+    /// Adds the thread at `pc` and everything reachable from it without
+    /// consuming a character, returning `true` if one of them is a match.
+    fn add(&self, threads: &mut Threads, pc: usize, pos: usize, len: usize) -> bool {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if !threads.insert(pc) {
+                continue;
+            }
+            match self.program[pc] {
+                Inst::Match => return true,
+                Inst::Jump(to) => stack.push(to),
+                Inst::Split(first, second) => {
+                    stack.push(second);
+                    stack.push(first);
+                }
+                Inst::Start if pos == 0 => stack.push(pc + 1),
+                Inst::End if pos == len => stack.push(pc + 1),
+                _ => {}
+            }
+        }
+        false
+    }
+}
+
+/// The set of program positions reached at one text position.
+struct Threads {
+    list: Vec<usize>,
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Self {
+        Self {
+            list: Vec::new(),
+            seen: vec![false; len],
+        }
+    }
+
+    fn insert(&mut self, pc: usize) -> bool {
+        if self.seen[pc] {
+            return false;
+        }
+        self.seen[pc] = true;
+        self.list.push(pc);
+        true
+    }
+
+    fn clear(&mut self) {
+        for &pc in &self.list {
+            self.seen[pc] = false;
+        }
+        self.list.clear();
+    }
+}
+
+/// Folds a character to lower case for case-insensitive comparison.
+fn fold(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn alternation(&mut self) -> Result<Node, &'static str> {
+        let mut branches = vec![self.concatenation()?];
+        while self.eat('|') {
+            branches.push(self.concatenation()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.remove(0)
+        } else {
+            Node::Alternate(branches)
+        })
+    }
+
+    fn concatenation(&mut self) -> Result<Node, &'static str> {
+        let mut items = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            items.push(self.repetition(atom)?);
+        }
+        Ok(match items.len() {
+            0 => Node::Empty,
+            1 => items.remove(0),
+            _ => Node::Concat(items),
+        })
+    }
+
+    fn atom(&mut self) -> Result<Node, &'static str> {
+        let c = self.peek().ok_or("unexpected end of pattern")?;
+        self.pos += 1;
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => {
+                if self.eat('?') && !self.eat(':') {
+                    return Err("only `(?:` and a leading `(?i)` are supported");
+                }
+                let inner = self.alternation()?;
+                if !self.eat(')') {
+                    return Err("unclosed `(`");
+                }
+                inner
+            }
+            '[' => Node::Class(self.class()?),
+            '\\' => self.escape()?,
+            '*' | '+' | '?' => return Err("repetition with nothing to repeat"),
+            c => Node::Char(c),
+        })
+    }
+
+    fn escape(&mut self) -> Result<Node, &'static str> {
+        let c = self.peek().ok_or("dangling escape")?;
+        self.pos += 1;
+        if let Some(class) = class_escape(c) {
+            return Ok(Node::Class(class));
+        }
+        match c {
+            'n' => Ok(Node::Char('\n')),
+            't' => Ok(Node::Char('\t')),
+            c if c.is_alphanumeric() => Err("unknown escape"),
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    fn class(&mut self) -> Result<Class, &'static str> {
+        let negated = self.eat('^');
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.peek().ok_or("unclosed `[`")?;
+            self.pos += 1;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let lo = if c == '\\' {
+                let escaped = self.peek().ok_or("dangling escape")?;
+                self.pos += 1;
+                if let Some(class) = class_escape(escaped) {
+                    if class.negated {
+                        return Err("negated escapes cannot be used inside `[...]`");
+                    }
+                    ranges.extend(class.ranges);
+                    continue;
+                }
+                escaped
+            } else {
+                c
+            };
+            let is_range = self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']');
+            if is_range {
+                self.pos += 1;
+                let mut hi = self.peek().ok_or("unclosed `[`")?;
+                self.pos += 1;
+                if hi == '\\' {
+                    hi = self.peek().ok_or("dangling escape")?;
+                    self.pos += 1;
+                }
+                if hi < lo {
+                    return Err("range out of order");
+                }
+                ranges.push((lo, hi));
+            } else {
+                ranges.push((lo, lo));
+            }
+        }
+        Ok(Class { negated, ranges })
+    }
+
+    fn repetition(&mut self, atom: Node) -> Result<Node, &'static str> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let start = self.pos;
+                self.pos += 1;
+                if let Some(bounds) = self.bounds()? {
+                    // `bounds` leaves the closing brace for the shared step below.
+                    self.pos -= 1;
+                    bounds
+                } else {
+                    // A `{` that does not start a count is taken literally.
+                    self.pos = start;
+                    return Ok(atom);
+                }
+            }
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        self.eat('?');
+        if matches!(self.peek(), Some('*' | '+' | '?')) {
+            return Err("repetition of a repetition");
+        }
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+        })
+    }
+
+    /// Parses `n}`, `n,}` or `n,m}` after a `{`, leaving the position after
+    /// the `}`, or returns `None` if the text is not a count.
+    fn bounds(&mut self) -> Result<Option<(u32, Option<u32>)>, &'static str> {
+        let Some(min) = self.number()? else {
+            return Ok(None);
+        };
+        let max = if self.eat(',') {
+            self.number()?
+        } else {
+            Some(min)
+        };
+        if !self.eat('}') {
+            return Ok(None);
+        }
+        if max.is_some_and(|max| max < min) {
+            return Err("repetition range out of order");
+        }
+        Ok(Some((min, max)))
+    }
+
+    fn number(&mut self) -> Result<Option<u32>, &'static str> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Ok(None);
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        match digits.parse::<u32>() {
+            Ok(n) if n <= MAX_REPEAT => Ok(Some(n)),
+            _ => Err("repetition count is too large"),
+        }
+    }
+}
+
+/// Returns the class written as `\d`, `\w`, `\s` or their negations.
+fn class_escape(c: char) -> Option<Class> {
+    let ranges = match c.to_ascii_lowercase() {
+        'd' => vec![('0', '9')],
+        'w' => vec![('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')],
+        's' => vec![(' ', ' '), ('\t', '\r')],
+        _ => return None,
+    };
+    Some(Class {
+        negated: c.is_ascii_uppercase(),
+        ranges,
+    })
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) -> Result<(), &'static str> {
+    if program.len() > MAX_PROGRAM {
+        return Err("pattern is too large");
+    }
+    match node {
+        Node::Empty => {}
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Concat(items) => {
+            for item in items {
+                compile(item, program)?;
+            }
+        }
+        Node::Alternate(branches) => {
+            let mut jumps = Vec::new();
+            for (i, branch) in branches.iter().enumerate() {
+                if i + 1 < branches.len() {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(branch, program)?;
+                    jumps.push(program.len());
+                    program.push(Inst::Jump(0));
+                    let next = program.len();
+                    program[split] = Inst::Split(split + 1, next);
+                } else {
+                    compile(branch, program)?;
+                }
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jump(end);
+            }
+        }
+        Node::Repeat { node, min, max } => {
+            for _ in 0..*min {
+                compile(node, program)?;
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program)?;
+                    program.push(Inst::Jump(split));
+                    let end = program.len();
+                    program[split] = Inst::Split(split + 1, end);
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(program.len() + 1, 0));
+                        compile(node, program)?;
+                    }
+                    let end = program.len();
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).expect("valid regex").is_match(text)
+    }
+
+    #[test]
+    fn test_regex_matching() {
+        assert!(matches(
+            r"/20\d{2}-\d\d-\d\d/",
+            "/photos/2023-07-14/img.jpg"
+        ));
+        assert!(!matches(
+            r"/20\d{2}-\d\d-\d\d/",
+            "/photos/2023-7-14/img.jpg"
+        ));
+        assert!(matches(r"\.(jpe?g|png)$", "/a/b.jpeg"));
+        assert!(!matches(r"\.(jpe?g|png)$", "/a/b.jpeg.bak"));
+        assert!(matches(r"^/tmp/", "/tmp/x"));
+        assert!(!matches(r"^/tmp/", "/var/tmp/x"));
+        assert!(matches(r"(?i)\.JPG$", "holiday.jpg"));
+        assert!(matches(r"[^/]+\.log$", "logs/app.log"));
+        assert!(matches(r"a{2,3}b", "xaaab"));
+        assert!(!matches(r"^a{2,3}b", "ab"));
+        assert!(matches(r"(a*)*b", "aaab"));
+        assert!(matches(r"x{", "x{"));
+        // Nested repetition that would take exponential time when backtracking.
+        let pathological = format!("{}!", "a".repeat(40));
+        assert!(!matches(r"^(a+)+$", &pathological));
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        for bad in [
+            "(abc", "abc)", "[abc", "*a", r"\q", "a{3,2}", "a**", "(?P<x>a)",
+        ] {
+            let err = Regex::new(bad).expect_err(bad);
+            assert_eq!(err.pattern(), bad);
+            assert!(err.to_string().starts_with("invalid regular expression"));
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Directories on other machines, given as `sftp://user@host/path` roots.
+//!
+//! Rather than reading every remote file over the network, the remote
+//! machine hashes its own files: `ssh` runs this tool's `index create`
+//! there, which writes an [index](crate::index) to stdout. The index then
+//! takes the place of the directory, so only the files of this machine that
+//! share a size with a remote file are hashed here, and no file contents
+//! cross the network. The tool must be installed on the remote machine;
+//! `ssh` handles the login, with its usual keys, agent and configuration.
+
+use crate::cache::algo_name;
+use crate::index::{parse_index, Index};
+use crate::HashAlgo;
+use log::debug;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The command run on remote machines unless another is given.
+pub const DEFAULT_REMOTE_COMMAND: &str = "duplicate_file_finder";
+
+/// The schemes of remote roots. `ssh://` is accepted too, as the files are
+/// reached the same way.
+const SCHEMES: [&str; 2] = ["sftp://", "ssh://"];
+
+/// A directory on another machine, reached with `ssh`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteRoot {
+    /// Who to log in as, if not the user `ssh` picks.
+    pub user: Option<String>,
+    /// The machine's name or address.
+    pub host: String,
+    /// The port `ssh` connects to, if not its default.
+    pub port: Option<u16>,
+    /// The directory on the machine.
+    pub path: PathBuf,
+}
+
+/// An error returned by [`RemoteRoot::parse`] for malformed roots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseRemoteError {
+    input: String,
+    reason: &'static str,
+}
+
+impl fmt::Display for ParseRemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid remote directory '{}': {}",
+            self.input, self.reason
+        )
+    }
+}
+
+impl Error for ParseRemoteError {}
+
+/// Returns whether `path` names a remote directory rather than a local one.
+#[must_use]
+pub fn is_remote(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| SCHEMES.iter().any(|scheme| path.starts_with(scheme)))
+}
+
+impl RemoteRoot {
+    /// Parses a root such as `sftp://alice@nas:2222/data/photos`. The user
+    /// and port are optional; the path is absolute, and `sftp://nas/~/docs`
+    /// is below the user's home directory.
+    ///
+    /// # Errors
+    /// Returns a [`ParseRemoteError`] if `input` does not start with
+    /// `sftp://` or `ssh://`, or has no host or path.
+    ///
+    /// # Example
+    /// ```
+    /// use duplicate_file_finder::remote::RemoteRoot;
+    ///
+    /// let root = RemoteRoot::parse("sftp://alice@nas:2222/data").unwrap();
+    /// assert_eq!(root.user.as_deref(), Some("alice"));
+    /// assert_eq!(root.host, "nas");
+    /// assert_eq!(root.port, Some(2222));
+    /// assert!(RemoteRoot::parse("sftp://nas").is_err());
+    /// ```
+    pub fn parse(input: &str) -> Result<Self, ParseRemoteError> {
+        let error = |reason| ParseRemoteError {
+            input: input.to_string(),
+            reason,
+        };
+        let rest = SCHEMES
+            .iter()
+            .find_map(|scheme| input.strip_prefix(scheme))
+            .ok_or(error("expected sftp://[user@]host[:port]/path"))?;
+        let (authority, path) = rest
+            .find('/')
+            .map(|slash| rest.split_at(slash))
+            .ok_or(error("no path"))?;
+        let (user, host) = match authority.rsplit_once('@') {
+            Some((user, host)) if !user.is_empty() => (Some(user.to_string()), host),
+            Some(_) => return Err(error("empty user")),
+            None => (None, authority),
+        };
+        let (host, port) = match host.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port.parse().map_err(|_| error("invalid port"))?)),
+            None => (host, None),
+        };
+        if host.is_empty() {
+            return Err(error("no host"));
+        }
+        // A path starting with `/~` is relative to the user's home directory.
+        let path = path.strip_prefix("/~/").map_or(path, |home| home);
+        Ok(Self {
+            user,
+            host: host.to_string(),
+            port,
+            path: PathBuf::from(path),
+        })
+    }
+
+    /// Returns the `ssh` command that runs `remote_command index create` on
+    /// the machine, writing an index of the directory with `hash_algo` to
+    /// stdout.
+    #[must_use]
+    pub fn index_command(&self, remote_command: &str, hash_algo: HashAlgo) -> Command {
+        let mut command = Command::new("ssh");
+        if let Some(port) = self.port {
+            command.arg("-p").arg(port.to_string());
+        }
+        let destination = match &self.user {
+            Some(user) => format!("{user}@{}", self.host),
+            None => self.host.clone(),
+        };
+        // The remote shell splits the command again, so everything but the
+        // command itself is quoted.
+        let remote = format!(
+            "{remote_command} index create {} -o - --hash-algo {}",
+            shell_quote(&self.path.to_string_lossy()),
+            algo_name(hash_algo)
+        );
+        command.arg("--").arg(destination).arg(remote);
+        command
+    }
+
+    /// Has the machine index the directory with `hash_algo`, by
+    /// `remote_command`, and returns the index. Its files are labelled with
+    /// the host as given here.
+    ///
+    /// # Errors
+    /// Returns an error if `ssh` cannot be run, the login or the remote
+    /// command fails, in which case the error holds the first line it wrote
+    /// to stderr, or it does not write an index.
+    pub fn index(&self, remote_command: &str, hash_algo: HashAlgo) -> io::Result<Index> {
+        let mut command = self.index_command(remote_command, hash_algo);
+        debug!("Running {command:?}");
+        let output = command
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| io::Error::new(e.kind(), format!("cannot run ssh: {e}")))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = stderr.lines().next().unwrap_or("no details");
+            return Err(io::Error::other(format!(
+                "ssh to {} failed: {reason}",
+                self.host
+            )));
+        }
+        let text = String::from_utf8(output.stdout)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not an index"))?;
+        let mut index = parse_index(&text)?;
+        index.host.clone_from(&self.host);
+        Ok(index)
+    }
+}
+
+impl fmt::Display for RemoteRoot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sftp://")?;
+        if let Some(user) = &self.user {
+            write!(f, "{user}@")?;
+        }
+        f.write_str(&self.host)?;
+        if let Some(port) = self.port {
+            write!(f, ":{port}")?;
+        }
+        if self.path.is_relative() {
+            f.write_str("/~/")?;
+        }
+        write!(f, "{}", self.path.display())
+    }
+}
+
+/// Quotes `value` for a POSIX shell.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_command() {
+        let root = RemoteRoot::parse("sftp://nas/~/it's here").expect("valid root");
+        assert_eq!(root.user, None);
+        assert_eq!(root.path, Path::new("it's here"));
+        assert_eq!(root.to_string(), "sftp://nas/~/it's here");
+        let command = root.index_command("/opt/dff", HashAlgo::Blake3);
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(
+            args,
+            [
+                "--",
+                "nas",
+                r"/opt/dff index create 'it'\''s here' -o - --hash-algo blake3"
+            ]
+        );
+
+        let root = RemoteRoot::parse("ssh://bob@10.0.0.5:22/srv").expect("valid root");
+        assert_eq!(root.to_string(), "sftp://bob@10.0.0.5:22/srv");
+        let command = root.index_command(DEFAULT_REMOTE_COMMAND, HashAlgo::Sha256);
+        assert_eq!(
+            command.get_args().take(4).collect::<Vec<_>>(),
+            ["-p", "22", "--", "bob@10.0.0.5"]
+        );
+
+        for bad in [
+            "sftp://nas",
+            "sftp:///data",
+            "sftp://@nas/data",
+            "sftp://nas:x/data",
+            "/data",
+        ] {
+            assert!(RemoteRoot::parse(bad).is_err(), "{bad}");
+        }
+        assert!(is_remote(Path::new("sftp://nas/data")));
+        assert!(!is_remote(Path::new("data")));
+    }
+}
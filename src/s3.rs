@@ -0,0 +1,533 @@
+//! Objects in S3-compatible storage, given as `s3://bucket/prefix` roots.
+//!
+//! Every object below the prefix is listed with its size, and only the
+//! objects that may be duplicates are downloaded: those sharing a size with
+//! a local file or with another object. Their `ETag`s are not trusted to
+//! tell them apart, as only some are the MD5 of the contents: those of
+//! objects uploaded in parts, or encrypted with SSE-KMS or SSE-C, are not,
+//! and the listing does not say how an object was encrypted. Each
+//! object downloaded is hashed as it arrives, without being stored, into an
+//! [index](crate::index) with no host that takes the place of the bucket,
+//! so its objects are reported as `s3://bucket/key`.
+//!
+//! Requests are made and signed by `curl`, which must be on the `PATH`;
+//! the credentials reach it on its stdin, never on its command line.
+
+use crate::compressed::hash_reader;
+use crate::index::{Index, IndexEntry};
+use crate::notify::curl_quote;
+use crate::{
+    build_pool, collect_files, in_pool, into_errors, is_cancelled, record_error, Console,
+    ScanConfig, ScanOptions, Stage,
+};
+use chrono::Local;
+use log::debug;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+
+/// The region requests are signed for unless another is set.
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// A bucket, or the objects below a prefix in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S3Root {
+    pub bucket: String,
+    /// The start of the keys of the objects, or empty for the whole bucket.
+    pub prefix: String,
+}
+
+/// An error returned by [`S3Root::parse`] for malformed roots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseS3Error {
+    input: String,
+    reason: &'static str,
+}
+
+impl fmt::Display for ParseS3Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid S3 location '{}': {}", self.input, self.reason)
+    }
+}
+
+impl Error for ParseS3Error {}
+
+/// Returns whether `path` names objects in S3 rather than a local directory.
+#[must_use]
+pub fn is_s3(path: &Path) -> bool {
+    path.to_str().is_some_and(|path| path.starts_with("s3://"))
+}
+
+impl S3Root {
+    /// Parses a root such as `s3://backups/2024/`.
+    ///
+    /// # Errors
+    /// Returns a [`ParseS3Error`] if `input` does not start with `s3://` or
+    /// names no bucket.
+    ///
+    /// # Example
+    /// ```
+    /// use duplicate_file_finder::s3::S3Root;
+    ///
+    /// let root = S3Root::parse("s3://backups/2024/").unwrap();
+    /// assert_eq!(root.bucket, "backups");
+    /// assert_eq!(root.prefix, "2024/");
+    /// assert!(S3Root::parse("s3:///photos").is_err());
+    /// ```
+    pub fn parse(input: &str) -> Result<Self, ParseS3Error> {
+        let error = |reason| ParseS3Error {
+            input: input.to_string(),
+            reason,
+        };
+        let rest = input
+            .strip_prefix("s3://")
+            .ok_or(error("expected s3://bucket/prefix"))?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(error("no bucket"));
+        }
+        Ok(Self {
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for S3Root {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "s3://{}/{}", self.bucket, self.prefix)
+    }
+}
+
+/// Where the storage is and how to sign requests to it.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct S3Settings {
+    /// The service's URL, such as `http://localhost:9000` for `MinIO`.
+    /// Buckets are addressed by path below it.
+    pub endpoint: String,
+    /// The region requests are signed for.
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    /// The session token of temporary credentials.
+    pub session_token: Option<String>,
+}
+
+// The credentials are kept out of logs.
+impl fmt::Debug for S3Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3Settings")
+            .field("endpoint", &self.endpoint)
+            .field("region", &self.region)
+            .finish_non_exhaustive()
+    }
+}
+
+impl S3Settings {
+    /// Reads the settings from the environment variables the AWS tools
+    /// use: `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and, for
+    /// temporary credentials, `AWS_SESSION_TOKEN`; `AWS_REGION` or
+    /// `AWS_DEFAULT_REGION`, `us-east-1` by default; and `AWS_ENDPOINT_URL_S3`
+    /// or `AWS_ENDPOINT_URL` for storage other than AWS.
+    ///
+    /// # Errors
+    /// Returns an error if the access key or secret key is not set.
+    pub fn from_env() -> io::Result<Self> {
+        Self::from_vars(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> io::Result<Self> {
+        let required = |name: &str| {
+            var(name).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, format!("{name} is not set"))
+            })
+        };
+        let region = var("AWS_REGION")
+            .or_else(|| var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|| DEFAULT_REGION.to_string());
+        let endpoint = var("AWS_ENDPOINT_URL_S3")
+            .or_else(|| var("AWS_ENDPOINT_URL"))
+            .unwrap_or_else(|| format!("https://s3.{region}.amazonaws.com"));
+        Ok(Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            access_key: required("AWS_ACCESS_KEY_ID")?,
+            secret_key: required("AWS_SECRET_ACCESS_KEY")?,
+            session_token: var("AWS_SESSION_TOKEN"),
+            region,
+        })
+    }
+
+    /// Starts `curl` on a signed GET request for `url`, writing the
+    /// response to its stdout.
+    fn get(&self, url: &str) -> io::Result<Child> {
+        let mut config = format!(
+            "url = {}\nuser = {}\naws-sigv4 = {}\n",
+            curl_quote(url),
+            curl_quote(&format!("{}:{}", self.access_key, self.secret_key)),
+            curl_quote(&format!("aws:amz:{}:s3", self.region))
+        );
+        if let Some(token) = &self.session_token {
+            let _ = writeln!(
+                config,
+                "header = {}",
+                curl_quote(&format!("x-amz-security-token: {token}"))
+            );
+        }
+        debug!("Fetching {url}");
+        let mut child = Command::new("curl")
+            .args(["--silent", "--show-error", "--fail", "--config", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("cannot run curl: {e}")))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(config.as_bytes())?;
+        }
+        Ok(child)
+    }
+
+    /// Runs [`S3Settings::get`], handing the response to `read` once the
+    /// request has succeeded.
+    fn fetch<R>(
+        &self,
+        url: &str,
+        read: impl FnOnce(&mut dyn io::Read) -> io::Result<R>,
+    ) -> io::Result<R> {
+        let mut child = self.get(url)?;
+        let read = match child.stdout.take() {
+            Some(mut stdout) => read(&mut stdout),
+            None => Err(io::ErrorKind::BrokenPipe.into()),
+        };
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = stderr.lines().next().unwrap_or("no details");
+            return Err(io::Error::other(format!("curl failed: {reason}")));
+        }
+        read
+    }
+
+    fn bucket_url(&self, bucket: &str) -> String {
+        format!("{}/{}", self.endpoint, encode(bucket, false))
+    }
+}
+
+/// An object listed in a bucket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Object {
+    bucket: String,
+    key: String,
+    size: u64,
+}
+
+impl Object {
+    /// The path the object is reported by.
+    fn path(&self) -> PathBuf {
+        PathBuf::from(format!("s3://{}/{}", self.bucket, self.key))
+    }
+}
+
+/// Lists the objects of `root`, a page of up to a thousand at a time.
+fn list_objects(settings: &S3Settings, root: &S3Root) -> io::Result<Vec<Object>> {
+    let mut objects = Vec::new();
+    let mut token: Option<String> = None;
+    loop {
+        // The parameters are in the order the signature expects.
+        let mut url = format!("{}?", settings.bucket_url(&root.bucket));
+        if let Some(token) = &token {
+            let _ = write!(url, "continuation-token={}&", encode(token, false));
+        }
+        let _ = write!(url, "list-type=2&prefix={}", encode(&root.prefix, false));
+        let page = settings.fetch(&url, |reader| {
+            let mut page = String::new();
+            reader.read_to_string(&mut page)?;
+            Ok(page)
+        })?;
+        for contents in elements(&page, "Contents") {
+            let field = |tag| {
+                element(contents, tag)
+                    .map(unescape)
+                    .ok_or_else(|| invalid_listing(tag))
+            };
+            let key = field("Key")?;
+            // Keys ending in a slash mark folders made by some consoles.
+            if key.ends_with('/') {
+                continue;
+            }
+            objects.push(Object {
+                bucket: root.bucket.clone(),
+                size: field("Size")?
+                    .parse()
+                    .map_err(|_| invalid_listing("Size"))?,
+                key,
+            });
+        }
+        token = element(&page, "NextContinuationToken").map(unescape);
+        if element(&page, "IsTruncated") != Some("true") || token.is_none() {
+            return Ok(objects);
+        }
+    }
+}
+
+fn invalid_listing(tag: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected listing: missing {tag}"),
+    )
+}
+
+/// Returns the objects among `objects` that may be duplicates of another
+/// object or of a file of one of `local_sizes`.
+fn candidates(objects: Vec<Object>, local_sizes: &HashSet<u64>) -> Vec<Object> {
+    let mut by_size: HashMap<u64, Vec<Object>> = HashMap::new();
+    for object in objects {
+        by_size.entry(object.size).or_default().push(object);
+    }
+    by_size
+        .into_iter()
+        .filter(|(size, objects)| objects.len() > 1 || local_sizes.contains(size))
+        .flat_map(|(_, objects)| objects)
+        .collect()
+}
+
+/// Lists the objects of `roots` and downloads those that may be duplicates
+/// of each other or of a file in `dirs`, hashing them with the
+/// [`HashAlgo`](crate::HashAlgo) of `options`, into an index that can be
+/// passed on with the directories to
+/// [`find_duplicates_with_indexes`](crate::index::find_duplicates_with_indexes)
+/// or [`compare_with_indexes`](crate::compare::compare_with_indexes).
+///
+/// The index holds only the objects downloaded. Those that could not be
+/// are in its errors.
+///
+/// # Errors
+/// Returns an error if a pattern in `options` is invalid, or a bucket
+/// cannot be listed.
+pub fn index_buckets(
+    roots: &[S3Root],
+    dirs: &[PathBuf],
+    settings: &S3Settings,
+    options: &ScanOptions,
+) -> io::Result<Index> {
+    let config = ScanConfig::new(options)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    let console = Console::new(config.quiet, config.progress_json, config.progress);
+    let within_limits = |size: u64| {
+        config.min_size.is_none_or(|min| size >= min)
+            && config.max_size.is_none_or(|max| size <= max)
+    };
+    let mut objects = Vec::new();
+    for root in roots {
+        console.status(&format!("Listing {root}"));
+        let listed = list_objects(settings, root)
+            .map_err(|e| io::Error::new(e.kind(), format!("cannot list {root}: {e}")))?;
+        objects.extend(
+            listed
+                .into_iter()
+                .filter(|object| within_limits(object.size)),
+        );
+    }
+    let errors = Mutex::new(Vec::new());
+    let local_sizes: HashSet<u64> =
+        collect_files(dirs, &config.walk, config.cancel, &errors, &mut Vec::new())
+            .iter()
+            .map(|file| file.meta.len())
+            .collect();
+    let listed = objects.len();
+    let objects = candidates(objects, &local_sizes);
+    console.status(&format!(
+        "{listed} objects listed, {} to download",
+        objects.len()
+    ));
+
+    let total_bytes = objects
+        .iter()
+        .map(|object| object.size)
+        .fold(0, u64::saturating_add);
+    let progress = console.byte_bar(total_bytes, Stage::FullHash, "Downloading objects...");
+    let pool = build_pool(config.threads);
+    let io_pool = build_pool(config.io_threads);
+    // The errors of the walk were recorded by the scan of the directories.
+    let errors = Mutex::new(Vec::new());
+    let mut entries: Vec<IndexEntry> = in_pool(io_pool.as_ref().or(pool.as_ref()), || {
+        objects
+            .into_par_iter()
+            .filter_map(|object| {
+                if is_cancelled(config.cancel) {
+                    return None;
+                }
+                let url = format!(
+                    "{}/{}",
+                    settings.bucket_url(&object.bucket),
+                    encode(&object.key, true)
+                );
+                let hashed = settings.fetch(&url, |reader| hash_reader(reader, config.hash_algo));
+                config.pace(object.size);
+                progress.inc(object.size);
+                match hashed {
+                    Ok((size, hash)) => Some(IndexEntry {
+                        path: object.path(),
+                        size,
+                        hash,
+                    }),
+                    Err(e) => {
+                        record_error(&errors, &object.path(), e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    });
+    progress.finish_with_message("Objects downloaded.");
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(Index {
+        host: String::new(),
+        created: Local::now(),
+        hash_algo: config.hash_algo,
+        roots: roots
+            .iter()
+            .map(|root| PathBuf::from(root.to_string()))
+            .collect(),
+        entries,
+        errors: into_errors(errors),
+        interrupted: is_cancelled(config.cancel),
+    })
+}
+
+/// Percent-encodes `value` as the signature of a request expects, leaving
+/// slashes alone if `keep_slashes`.
+fn encode(value: &str, keep_slashes: bool) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(char::from(byte));
+            }
+            b'/' if keep_slashes => out.push('/'),
+            _ => {
+                let _ = write!(out, "%{byte:02X}");
+            }
+        }
+    }
+    out
+}
+
+/// Returns the text inside each `<tag>` element of `xml`, which must not
+/// nest.
+fn elements<'x>(xml: &'x str, tag: &str) -> impl Iterator<Item = &'x str> {
+    let (open, close) = (format!("<{tag}>"), format!("</{tag}>"));
+    let mut rest = xml;
+    std::iter::from_fn(move || {
+        let start = rest.find(&open)? + open.len();
+        let end = start + rest[start..].find(&close)?;
+        let text = &rest[start..end];
+        rest = &rest[end + close.len()..];
+        Some(text)
+    })
+}
+
+/// Returns the text inside the first `<tag>` element of `xml`.
+fn element<'x>(xml: &'x str, tag: &str) -> Option<&'x str> {
+    elements(xml, tag).next()
+}
+
+/// Replaces the entities of XML text with the characters they stand for.
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semicolon) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..semicolon];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        if let Some(c) = decoded {
+            out.push(c);
+            rest = &rest[semicolon + 1..];
+        } else {
+            out.push('&');
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing_and_candidates() {
+        let page = "<?xml version=\"1.0\"?><ListBucketResult xmlns=\"x\">\
+            <IsTruncated>true</IsTruncated>\
+            <Contents><Key>a &amp; b.txt</Key><ETag>&quot;AB12&quot;</ETag><Size>5</Size></Contents>\
+            <Contents><Key>c.txt</Key><ETag>\"ab12\"</ETag><Size>5</Size></Contents>\
+            <NextContinuationToken>next&#x2F;1</NextContinuationToken></ListBucketResult>";
+        let keys: Vec<String> = elements(page, "Contents")
+            .filter_map(|contents| element(contents, "Key").map(unescape))
+            .collect();
+        assert_eq!(keys, ["a & b.txt", "c.txt"]);
+        assert_eq!(
+            element(page, "NextContinuationToken")
+                .map(unescape)
+                .as_deref(),
+            Some("next/1")
+        );
+        assert_eq!(
+            encode("photos/2024 a+b.jpg", true),
+            "photos/2024%20a%2Bb.jpg"
+        );
+        assert_eq!(encode("a/b", false), "a%2Fb");
+
+        let object = |key: &str, size| Object {
+            bucket: "b".to_string(),
+            key: key.to_string(),
+            size,
+        };
+        let objects = vec![
+            object("same-1", 5),
+            object("same-2", 5),
+            object("sse-kms", 5),
+            object("alone", 6),
+            object("local", 8),
+        ];
+        let mut keys: Vec<String> = candidates(objects, &HashSet::from([8]))
+            .into_iter()
+            .map(|object| object.key)
+            .collect();
+        keys.sort();
+        assert_eq!(keys, ["local", "same-1", "same-2", "sse-kms"]);
+
+        let settings = S3Settings::from_vars(|name| match name {
+            "AWS_ACCESS_KEY_ID" => Some("key".to_string()),
+            "AWS_SECRET_ACCESS_KEY" => Some("secret".to_string()),
+            "AWS_REGION" => Some("eu-west-1".to_string()),
+            _ => None,
+        })
+        .expect("credentials set");
+        assert_eq!(settings.endpoint, "https://s3.eu-west-1.amazonaws.com");
+        assert!(!format!("{settings:?}").contains("secret"));
+        assert!(S3Settings::from_vars(|_| None).is_err());
+    }
+}
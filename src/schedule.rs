@@ -0,0 +1,279 @@
+//! Cron-style schedules, for `--schedule`.
+//!
+//! A schedule is five fields, as in a crontab: the minute, the hour, the day
+//! of the month, the month and the day of the week. Each field is `*`, a
+//! number, a range such as `1-5`, any of those followed by a step such as
+//! `*/15`, or a comma-separated list of them. Months and days of the week
+//! may be given by their first three letters, and Sunday is both `0` and
+//! `7`. When both the day of the month and the day of the week are
+//! restricted, a day matching either runs, as cron does. `@hourly`,
+//! `@daily`, `@weekly`, `@monthly` and `@yearly` stand for the usual
+//! schedules.
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, TimeZone, Timelike};
+use std::error::Error;
+use std::fmt;
+
+/// How many days ahead [`Schedule::next_after`] looks for a match. Every
+/// valid day of the month recurs within this span, even February 29th.
+const SEARCH_DAYS: i64 = 8 * 366;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// When a recurring scan runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    /// Bit `n` is set if the schedule runs in minute `n`.
+    minutes: u64,
+    hours: u32,
+    /// Bit `n` is set for day `n` of the month, from 1.
+    days: u32,
+    /// Bit `n` is set for month `n`, from 1.
+    months: u16,
+    /// Bit `n` is set for the day `n` days after Sunday.
+    weekdays: u8,
+    /// Whether the day of the month field was `*`.
+    any_day: bool,
+    /// Whether the day of the week field was `*`.
+    any_weekday: bool,
+}
+
+/// An error returned by [`Schedule::parse`] for malformed schedules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseScheduleError {
+    input: String,
+    reason: String,
+}
+
+impl fmt::Display for ParseScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid schedule '{}': {}", self.input, self.reason)
+    }
+}
+
+impl Error for ParseScheduleError {}
+
+impl Schedule {
+    /// Parses a schedule such as `"0 3 * * 0"`, which runs at 03:00 every
+    /// Sunday.
+    ///
+    /// # Errors
+    /// Returns a [`ParseScheduleError`] if there are not five fields, or a
+    /// field holds a value out of its range.
+    ///
+    /// # Example
+    /// ```
+    /// use duplicate_file_finder::schedule::Schedule;
+    ///
+    /// assert!(Schedule::parse("*/30 9-17 * * mon-fri").is_ok());
+    /// assert_eq!(Schedule::parse("@weekly"), Schedule::parse("0 0 * * 0"));
+    /// assert!(Schedule::parse("0 25 * * *").is_err());
+    /// ```
+    pub fn parse(input: &str) -> Result<Self, ParseScheduleError> {
+        let error = |reason: String| ParseScheduleError {
+            input: input.to_string(),
+            reason,
+        };
+        let expanded = match input.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(error(format!(
+                "expected 5 fields (minute hour day month weekday), found {}",
+                fields.len()
+            )));
+        };
+        let minutes = field(minute, 0, 59, &[]).map_err(|e| error(format!("minute: {e}")))?;
+        let hours = field(hour, 0, 23, &[]).map_err(|e| error(format!("hour: {e}")))?;
+        let days = field(day, 1, 31, &[]).map_err(|e| error(format!("day: {e}")))?;
+        let months = field(month, 1, 12, &MONTHS).map_err(|e| error(format!("month: {e}")))?;
+        let weekdays =
+            field(weekday, 0, 7, &WEEKDAYS).map_err(|e| error(format!("weekday: {e}")))?;
+        // Both 0 and 7 are Sunday.
+        let weekdays = (weekdays | weekdays >> 7) & 0x7f;
+        Ok(Self {
+            minutes,
+            hours: u32::try_from(hours).unwrap_or(u32::MAX),
+            days: u32::try_from(days).unwrap_or(u32::MAX),
+            months: u16::try_from(months).unwrap_or(u16::MAX),
+            weekdays: u8::try_from(weekdays).unwrap_or(u8::MAX),
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// Returns the first time after `time` at which the schedule runs, or
+    /// `None` if it never does, as for `0 0 30 2 *`.
+    ///
+    /// Times skipped by a change to daylight saving time are skipped by the
+    /// schedule too, and times repeated by the change back run once.
+    #[must_use]
+    pub fn next_after(&self, time: DateTime<Local>) -> Option<DateTime<Local>> {
+        let start = time + Duration::minutes(1);
+        let first_day = start.date_naive();
+        for offset in 0..SEARCH_DAYS {
+            let date = first_day + Duration::days(offset);
+            if !self.runs_on(date) {
+                continue;
+            }
+            let (from_hour, from_minute) = if offset == 0 {
+                (start.hour(), start.minute())
+            } else {
+                (0, 0)
+            };
+            for hour in from_hour..24 {
+                if self.hours & (1 << hour) == 0 {
+                    continue;
+                }
+                let first = if hour == from_hour { from_minute } else { 0 };
+                for minute in first..60 {
+                    if self.minutes & (1 << minute) == 0 {
+                        continue;
+                    }
+                    let naive = date.and_hms_opt(hour, minute, 0)?;
+                    if let Some(when) = Local.from_local_datetime(&naive).earliest() {
+                        return Some(when);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn runs_on(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (false, true) => day,
+            (true, false) => weekday,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+/// Parses one field, whose values run from `min` to `max` and may be
+/// named by `names`, starting from `min`, into a bit set.
+fn field(text: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |text: &str| -> Result<u32, String> {
+        if let Some(i) = names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(text))
+        {
+            return Ok(min + u32::try_from(i).unwrap_or(0));
+        }
+        let n = text
+            .parse::<u32>()
+            .map_err(|_| format!("'{text}' is not a number"))?;
+        if (min..=max).contains(&n) {
+            Ok(n)
+        } else {
+            Err(format!("{n} is outside {min}-{max}"))
+        }
+    };
+    let mut bits = 0;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("'{step}' is not a valid step")),
+            },
+            None => (part, 1),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (value(first)?, value(last)?),
+            // A single value with a step runs from it to the end.
+            None if part.contains('/') => (value(range)?, max),
+            None => {
+                let n = value(range)?;
+                (n, n)
+            }
+        };
+        if first > last {
+            return Err(format!("{first}-{last} runs backwards"));
+        }
+        for n in (first..=last).step_by(step as usize) {
+            bits |= 1 << n;
+        }
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> DateTime<Local> {
+        let naive =
+            chrono::NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").expect("valid time");
+        Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .expect("time exists")
+    }
+
+    fn next(schedule: &str, after: &str) -> String {
+        Schedule::parse(schedule)
+            .expect("valid schedule")
+            .next_after(at(after))
+            .expect("a next run")
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    }
+
+    #[test]
+    fn test_next_after() {
+        // 2024-06-05 is a Wednesday.
+        assert_eq!(next("0 3 * * 0", "2024-06-05 12:00"), "2024-06-09 03:00");
+        assert_eq!(next("0 3 * * sun", "2024-06-09 03:00"), "2024-06-16 03:00");
+        assert_eq!(next("*/15 * * * *", "2024-06-05 12:07"), "2024-06-05 12:15");
+        assert_eq!(
+            next("30 9-17/4 * * *", "2024-06-05 13:30"),
+            "2024-06-05 17:30"
+        );
+        assert_eq!(
+            next("0 0 1 jan,jul *", "2024-06-05 12:00"),
+            "2024-07-01 00:00"
+        );
+        assert_eq!(next("0 0 29 2 *", "2024-03-01 00:00"), "2028-02-29 00:00");
+        // Either the day of the month or the day of the week.
+        assert_eq!(next("0 0 13 * 5", "2024-06-05 12:00"), "2024-06-07 00:00");
+        assert_eq!(next("@monthly", "2024-06-05 12:00"), "2024-07-01 00:00");
+
+        let never = Schedule::parse("0 0 30 2 *").expect("valid schedule");
+        assert_eq!(never.next_after(at("2024-06-05 12:00")), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for bad in [
+            "0 3 * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "* * * foo *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert!(Schedule::parse(bad).is_err(), "{bad}");
+        }
+        let error = Schedule::parse("0 24 * * *").expect_err("hour out of range");
+        assert_eq!(
+            error.to_string(),
+            "invalid schedule '0 24 * * *': hour: 24 is outside 0-23"
+        );
+        assert_eq!(Schedule::parse("0 0 * * 7"), Schedule::parse("0 0 * * 0"));
+    }
+}
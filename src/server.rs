@@ -0,0 +1,978 @@
+//! A local HTTP server that runs scans for other programs, for the `serve`
+//! subcommand.
+//!
+//! GUIs and scripts can start scans, poll their progress, fetch their
+//! results and act on the duplicates found without running the command
+//! line tool and parsing what it prints. Requests and responses are JSON
+//! over HTTP/1.1, one request per connection:
+//!
+//! | Request | Response |
+//! |---|---|
+//! | `GET /` | The dashboard, a web page using the requests below |
+//! | `POST /scans` | Starts a scan; answers `201` with its `id` |
+//! | `GET /metrics` | Prometheus metrics of the scans finished, from [`Metrics`] |
+//! | `GET /scans` | Every scan, as `GET /scans/{id}` describes it |
+//! | `GET /scans/{id}` | The state of the scan and the progress of its current stage |
+//! | `GET /scans/{id}/report` | The duplicates found, in the `--format json` layout |
+//! | `GET /scans/{id}/preview?path=…` | The contents of an image in one of the groups found |
+//! | `POST /scans/{id}/actions` | Deletes, trashes or links the duplicates found |
+//! | `DELETE /scans/{id}` | Cancels the scan, keeping the groups confirmed so far |
+//!
+//! The body of `POST /scans` names the `directories` to scan and may set
+//! `exclude` and `include` to lists of globs, `min_size` and `max_size` in
+//! bytes, and `hash_algo` as `--hash-algo` does. The body of an action sets
+//! `action` to one of `delete`, `trash`, `hardlink`, `symlink` or `reflink`,
+//! `keep` as `--keep` does, and `confirm` to `true` to change files, which
+//! otherwise only describes what would be done. Setting `files` to a list of
+//! paths acts on those files alone, keeping the first file of each group
+//! that is not listed and still exists, in place of the one `keep` would
+//! choose. Confirmed
+//! actions other than `reflink` are recorded in the undo log, as on the
+//! command line.
+//!
+//! There is no authentication, so the server should only listen where
+//! every client is trusted. To keep web pages open in a browser from
+//! driving it, requests are refused unless their `Host` is `localhost` or
+//! an IP address, which defeats DNS rebinding, and requests that change
+//! anything must be sent as `application/json`, which a page on another
+//! origin cannot do without the server's consent.
+
+use crate::dashboard;
+use crate::dedupe::{
+    delete_duplicates, hardlink_duplicates, reflink_duplicates, symlink_duplicates,
+    trash_duplicates, DeleteSummary, KeepStrategy, LinkedFile,
+};
+use crate::json::{self, quote, Value};
+use crate::metrics::Metrics;
+use crate::undo::{self, UndoAction, UndoEntry};
+use crate::{
+    find_duplicates_with_options, write_output_json, DuplicateGroup, DuplicateReport, HashAlgo,
+    ProgressObserver, ScanConfig, ScanOptions, Stage,
+};
+use chrono::Local;
+use clap::ValueEnum;
+use log::{debug, error, info, warn};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// The largest request body accepted.
+const MAX_BODY: usize = 1024 * 1024;
+/// The most header lines accepted in a request.
+const MAX_HEADERS: usize = 100;
+/// How long a client may take to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// The extensions of the files `GET /scans/{id}/preview` serves, and the
+/// type each is served as. SVG is left out, as it can carry scripts.
+const PREVIEW_TYPES: [(&str, &str); 7] = [
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("bmp", "image/bmp"),
+    ("avif", "image/avif"),
+];
+
+/// Serves the API on `listener` until the process exits, recording
+/// confirmed actions in the undo log at `undo_log`.
+///
+/// Each connection is handled on its own thread, and each scan runs on its
+/// own thread, so a long scan never holds up other requests. Scans and
+/// their reports are kept in memory for as long as the server runs.
+///
+/// # Errors
+/// Returns an error if accepting connections fails for good.
+pub fn serve(listener: &TcpListener, undo_log: &Path) -> io::Result<()> {
+    let server = Server {
+        scans: Mutex::new(Vec::new()),
+        undo_log: undo_log.to_path_buf(),
+        metrics: Arc::new(Metrics::new()),
+    };
+    listen(listener, move |request| server.checked(request));
+    Ok(())
+}
+
+/// Serves `metrics` at `/metrics` on `listener` until the process exits,
+/// answering every other request with `404`.
+///
+/// # Errors
+/// Returns an error if accepting connections fails for good.
+pub fn serve_metrics(listener: &TcpListener, metrics: Arc<Metrics>) -> io::Result<()> {
+    listen(listener, move |request| {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/metrics") => metrics_response(&metrics),
+            (_, "/metrics") => Response::error(405, "method not allowed"),
+            _ => Response::error(404, "no such endpoint"),
+        }
+    });
+    Ok(())
+}
+
+/// Answers each connection to `listener` on its own thread with the
+/// response `handler` gives.
+fn listen(listener: &TcpListener, handler: impl Fn(&Request) -> Response + Send + Sync + 'static) {
+    let handler = Arc::new(handler);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                warn!("Failed to accept a connection: {e}");
+                continue;
+            }
+        };
+        let handler = Arc::clone(&handler);
+        std::thread::spawn(move || {
+            if let Err(e) = answer(stream, &*handler) {
+                debug!("Failed to answer a request: {e}");
+            }
+        });
+    }
+}
+
+struct Server {
+    /// Every scan started, in order; a scan's id is its position plus one.
+    scans: Mutex<Vec<Arc<Scan>>>,
+    undo_log: PathBuf,
+    metrics: Arc<Metrics>,
+}
+
+/// A scan started through the API.
+struct Scan {
+    id: usize,
+    dirs: Vec<PathBuf>,
+    hash_algo: HashAlgo,
+    cancel: AtomicBool,
+    progress: Mutex<Progress>,
+    state: Mutex<State>,
+}
+
+/// The stage a scan is in and how far along it is.
+#[derive(Default)]
+struct Progress {
+    stage: Option<Stage>,
+    done: u64,
+    total: u64,
+}
+
+enum State {
+    Running,
+    Finished {
+        report: Arc<DuplicateReport>,
+        start_time: String,
+    },
+}
+
+impl ProgressObserver for Scan {
+    fn stage_started(&self, stage: Stage, total: u64) {
+        *lock(&self.progress) = Progress {
+            stage: Some(stage),
+            done: 0,
+            total,
+        };
+    }
+
+    fn progress(&self, stage: Stage, done: u64, total: u64) {
+        *lock(&self.progress) = Progress {
+            stage: Some(stage),
+            done,
+            total,
+        };
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A request as far as the API needs it.
+struct Request {
+    method: String,
+    /// The path, without any query string.
+    path: String,
+    /// The query string, without the `?`.
+    query: String,
+    host: Option<String>,
+    content_type: Option<String>,
+    body: Vec<u8>,
+}
+
+/// A status code and a body, which is JSON unless `content_type` says
+/// otherwise.
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, body: String) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: body.into_bytes(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, format!("{{\"error\":{}}}", quote(message)))
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            201 => "Created",
+            202 => "Accepted",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            413 => "Payload Too Large",
+            415 => "Unsupported Media Type",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+/// Reads one request from `stream` and writes the response `handler`
+/// gives.
+fn answer(stream: TcpStream, handler: &dyn Fn(&Request) -> Response) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match read_request(&mut reader) {
+        Ok(request) => {
+            let response = handler(&request);
+            info!(
+                "{} {} answered {}",
+                request.method, request.path, response.status
+            );
+            response
+        }
+        Err(response) => response,
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+             X-Content-Type-Options: nosniff\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.reason(),
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+fn metrics_response(metrics: &Metrics) -> Response {
+    Response {
+        status: 200,
+        content_type: "text/plain; version=0.0.4",
+        body: metrics.render().into_bytes(),
+    }
+}
+
+impl Server {
+    /// Refuses requests a web page could have made, then routes the rest.
+    ///
+    /// Metrics are exempt from the `Host` check, as scrapers may address
+    /// the server by any name, and reading them changes nothing.
+    fn checked(&self, request: &Request) -> Response {
+        let metrics = request.method == "GET" && request.path == "/metrics";
+        if !metrics && !allowed_host(request.host.as_deref()) {
+            return Response::error(403, "the Host header must be localhost or an IP address");
+        }
+        let json = request
+            .content_type
+            .as_deref()
+            .and_then(|value| value.split(';').next())
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("application/json"));
+        if request.method != "GET" && !json {
+            return Response::error(415, "requests with a body must be sent as application/json");
+        }
+        self.route(request)
+    }
+
+    fn route(&self, request: &Request) -> Response {
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", [""]) => Response {
+                status: 200,
+                content_type: "text/html; charset=utf-8",
+                body: dashboard::PAGE.as_bytes().to_vec(),
+            },
+            ("GET", ["metrics"]) => metrics_response(&self.metrics),
+            ("GET", ["scans"]) => {
+                let scans = lock(&self.scans).clone();
+                let list: Vec<String> = scans.iter().map(|scan| scan.describe()).collect();
+                Response::json(200, format!("[{}]", list.join(",")))
+            }
+            ("POST", ["scans"]) => self.start(&request.body),
+            ("GET", ["scans", id]) => {
+                self.with_scan(id, |scan| Response::json(200, scan.describe()))
+            }
+            ("DELETE", ["scans", id]) => self.with_scan(id, |scan| {
+                scan.cancel.store(true, Ordering::Relaxed);
+                Response::json(202, scan.describe())
+            }),
+            ("GET", ["scans", id, "report"]) => self.with_scan(id, Scan::report),
+            ("GET", ["scans", id, "preview"]) => {
+                self.with_scan(id, |scan| scan.preview(&request.query))
+            }
+            ("POST", ["scans", id, "actions"]) => {
+                self.with_scan(id, |scan| scan.act(&request.body, &self.undo_log))
+            }
+            (
+                _,
+                ["" | "metrics" | "scans"]
+                | ["scans", _]
+                | ["scans", _, "report" | "preview" | "actions"],
+            ) => Response::error(405, "method not allowed"),
+            _ => Response::error(404, "no such endpoint"),
+        }
+    }
+
+    fn with_scan(&self, id: &str, answer: impl FnOnce(&Scan) -> Response) -> Response {
+        let scan = id
+            .parse::<usize>()
+            .ok()
+            .and_then(|id| lock(&self.scans).get(id.checked_sub(1)?).cloned());
+        match scan {
+            Some(scan) => answer(&scan),
+            None => Response::error(404, "no such scan"),
+        }
+    }
+
+    /// Starts the scan described by `body`.
+    fn start(&self, body: &[u8]) -> Response {
+        let (dirs, options) = match scan_options(body) {
+            Ok(parsed) => parsed,
+            Err(message) => return Response::error(400, &message),
+        };
+        let hash_algo = options.hash_algo;
+        let mut scans = lock(&self.scans);
+        let scan = Arc::new(Scan {
+            id: scans.len() + 1,
+            dirs,
+            hash_algo,
+            cancel: AtomicBool::new(false),
+            progress: Mutex::new(Progress::default()),
+            state: Mutex::new(State::Running),
+        });
+        scans.push(Arc::clone(&scan));
+        drop(scans);
+        info!("Scan {} started: {:?}", scan.id, scan.dirs);
+
+        let running = Arc::clone(&scan);
+        let metrics = Arc::clone(&self.metrics);
+        std::thread::spawn(move || {
+            let start_time = Local::now().format("%Y%m%d %H:%M:%S").to_string();
+            let started = Instant::now();
+            let options = options.progress(&*running).cancel(&running.cancel);
+            // The patterns were checked when the scan was requested.
+            let report = find_duplicates_with_options(&running.dirs, &options).unwrap_or_default();
+            metrics.record(&report, started.elapsed());
+            info!(
+                "Scan {} finished: {} duplicate groups",
+                running.id, report.total_groups
+            );
+            *lock(&running.state) = State::Finished {
+                report: Arc::new(report),
+                start_time,
+            };
+        });
+        Response::json(201, scan.describe())
+    }
+}
+
+/// Returns whether a request naming `host` in its `Host` header may be
+/// answered: a page served from a domain that has been pointed at this
+/// machine would name that domain.
+fn allowed_host(host: Option<&str>) -> bool {
+    let Some(host) = host else {
+        return false;
+    };
+    // Strip the port, minding the brackets of an IPv6 address.
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or(rest),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    name.eq_ignore_ascii_case("localhost") || name.parse::<IpAddr>().is_ok()
+}
+
+/// Reads a request, or returns the error response to send instead.
+fn read_request(reader: &mut impl BufRead) -> Result<Request, Response> {
+    let bad = |message: &str| Response::error(400, message);
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .map_err(|_| bad("unreadable request"))?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(bad("malformed request line"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), query.to_string());
+    let method = method.to_string();
+
+    let (mut host, mut content_type, mut length) = (None, None, 0);
+    for _ in 0..=MAX_HEADERS {
+        line.clear();
+        reader
+            .read_line(&mut line)
+            .map_err(|_| bad("unreadable headers"))?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            let mut body = vec![0; length];
+            reader
+                .read_exact(&mut body)
+                .map_err(|_| bad("body shorter than its Content-Length"))?;
+            return Ok(Request {
+                method,
+                path,
+                query,
+                host,
+                content_type,
+                body,
+            });
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(bad("malformed header"));
+        };
+        let value = value.trim().to_string();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "host" => host = Some(value),
+            "content-type" => content_type = Some(value),
+            "content-length" => {
+                length = value.parse().map_err(|_| bad("invalid Content-Length"))?;
+                if length > MAX_BODY {
+                    return Err(Response::error(413, "request body too large"));
+                }
+            }
+            "transfer-encoding" => return Err(bad("chunked bodies are not supported")),
+            _ => {}
+        }
+    }
+    Err(bad("too many headers"))
+}
+
+/// Parses the body of `POST /scans` into the directories to scan and the
+/// options to scan them with.
+fn scan_options(body: &[u8]) -> Result<(Vec<PathBuf>, ScanOptions<'static>), String> {
+    let text = std::str::from_utf8(body).map_err(|_| "the body is not UTF-8".to_string())?;
+    let value = json::parse(text).map_err(|e| format!("invalid JSON: {e}"))?;
+    let strings = |key: &str| -> Result<Vec<String>, String> {
+        match value.get(key) {
+            None | Some(Value::Null) => Ok(Vec::new()),
+            Some(list) => list
+                .as_array()
+                .and_then(|items| {
+                    items
+                        .iter()
+                        .map(|item| item.as_str().map(String::from))
+                        .collect()
+                })
+                .ok_or_else(|| format!("{key} must be a list of strings")),
+        }
+    };
+    let size = |key: &str| -> Result<Option<u64>, String> {
+        match value.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(size) => size
+                .as_u64()
+                .map(Some)
+                .ok_or_else(|| format!("{key} must be a number of bytes")),
+        }
+    };
+
+    let dirs: Vec<PathBuf> = strings("directories")?
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+    if dirs.is_empty() {
+        return Err("directories must list at least one directory".to_string());
+    }
+    if let Some(dir) = dirs.iter().find(|dir| !dir.is_dir()) {
+        return Err(format!("'{}' is not a valid directory", dir.display()));
+    }
+    let hash_algo = match value.get("hash_algo").and_then(Value::as_str) {
+        Some(name) => HashAlgo::from_str(name, true).map_err(|e| format!("hash_algo: {e}"))?,
+        None => HashAlgo::default(),
+    };
+    let options = ScanOptions::new()
+        .exclude(strings("exclude")?)
+        .include(strings("include")?)
+        .min_size(size("min_size")?)
+        .max_size(size("max_size")?)
+        .hash_algo(hash_algo)
+        .quiet(true);
+    ScanConfig::new(&options).map_err(|e| e.to_string())?;
+    Ok((dirs, options))
+}
+
+impl Scan {
+    /// Returns the state of the scan as a JSON object.
+    fn describe(&self) -> String {
+        let dirs: Vec<String> = self
+            .dirs
+            .iter()
+            .map(|dir| quote(&dir.to_string_lossy()))
+            .collect();
+        let mut out = format!("{{\"id\":{},\"directories\":[{}]", self.id, dirs.join(","));
+        match &*lock(&self.state) {
+            State::Running => {
+                let progress = lock(&self.progress);
+                let stage = progress
+                    .stage
+                    .map_or("null".to_string(), |s| quote(s.as_str()));
+                let _ = write!(
+                    out,
+                    ",\"state\":\"running\",\"stage\":{stage},\"done\":{},\"total\":{}",
+                    progress.done, progress.total
+                );
+            }
+            State::Finished { report, .. } => {
+                let state = if report.interrupted {
+                    "cancelled"
+                } else {
+                    "finished"
+                };
+                let _ = write!(
+                    out,
+                    ",\"state\":\"{state}\",\"groups\":{},\"reclaimable_bytes\":{},\"errors\":{}",
+                    report.total_groups,
+                    report.total_reclaimable_bytes,
+                    report.errors.len()
+                );
+            }
+        }
+        out.push('}');
+        out
+    }
+
+    /// Returns the report of a finished scan, or `None` while it runs.
+    fn finished(&self) -> Option<(Arc<DuplicateReport>, String)> {
+        match &*lock(&self.state) {
+            State::Running => None,
+            State::Finished { report, start_time } => {
+                Some((Arc::clone(report), start_time.clone()))
+            }
+        }
+    }
+
+    /// Serves the image named by the `path` parameter of `query`, which
+    /// must be one of the duplicates found, so the server never hands out
+    /// files the scan did not report.
+    fn preview(&self, query: &str) -> Response {
+        let Some((report, _)) = self.finished() else {
+            return Response::error(409, "the scan is still running");
+        };
+        let path = query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("path="))
+            .and_then(percent_decode)
+            .map(PathBuf::from);
+        let Some(path) =
+            path.filter(|path| report.groups.iter().any(|group| group.paths.contains(path)))
+        else {
+            return Response::error(404, "no such file among the duplicates found");
+        };
+        let extension = path.extension().and_then(OsStr::to_str).unwrap_or("");
+        let Some(&(_, content_type)) = PREVIEW_TYPES
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(extension))
+        else {
+            return Response::error(404, "only images can be previewed");
+        };
+        match std::fs::read(&path) {
+            Ok(body) => Response {
+                status: 200,
+                content_type,
+                body,
+            },
+            Err(e) => Response::error(404, &format!("cannot read {}: {e}", path.display())),
+        }
+    }
+
+    fn report(&self) -> Response {
+        let Some((report, start_time)) = self.finished() else {
+            return Response::error(409, "the scan is still running");
+        };
+        let mut body = Vec::new();
+        if let Err(e) = write_output_json(&mut body, &report, &start_time, &self.dirs) {
+            return Response::error(500, &e.to_string());
+        }
+        Response {
+            status: 200,
+            content_type: "application/json",
+            body,
+        }
+    }
+
+    /// Runs the action described by `body` on the duplicates found.
+    fn act(&self, body: &[u8], undo_log: &Path) -> Response {
+        let Some((report, _)) = self.finished() else {
+            return Response::error(409, "the scan is still running");
+        };
+        let value = match std::str::from_utf8(body)
+            .map_err(|e| e.to_string())
+            .and_then(json::parse)
+        {
+            Ok(value) => value,
+            Err(e) => return Response::error(400, &format!("invalid JSON: {e}")),
+        };
+        let mut keep = match value.get("keep").and_then(Value::as_str) {
+            Some(name) => match KeepStrategy::from_str(name, true) {
+                Ok(keep) => keep,
+                Err(e) => return Response::error(400, &format!("keep: {e}")),
+            },
+            None => KeepStrategy::default(),
+        };
+        let mut report = report;
+        if let Some(files) = value.get("files").filter(|files| **files != Value::Null) {
+            match selected(&report, files) {
+                Ok(selection) => report = Arc::new(selection),
+                Err(message) => return Response::error(400, &message),
+            }
+            // Each group of the selection starts with the file to keep.
+            keep = KeepStrategy::First;
+        }
+        let dry_run = !value
+            .get("confirm")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        let action = value.get("action").and_then(Value::as_str).unwrap_or("");
+
+        let (outcome, entries) = match action {
+            "delete" | "trash" => {
+                let summary = if action == "trash" {
+                    trash_duplicates(&report, keep, dry_run)
+                } else {
+                    delete_duplicates(&report, keep, dry_run)
+                };
+                let entries = undo::delete_entries(&summary, self.hash_algo);
+                (removed(&summary), entries)
+            }
+            "hardlink" => {
+                let summary = hardlink_duplicates(&report, dry_run);
+                let entries =
+                    undo::link_entries(UndoAction::Hardlink, &summary.linked, self.hash_algo);
+                let outcome = linked(&summary.linked, &summary.skipped, summary.reclaimed_bytes);
+                (outcome, entries)
+            }
+            "symlink" => {
+                let summary = symlink_duplicates(&report, dry_run);
+                let entries =
+                    undo::link_entries(UndoAction::Symlink, &summary.linked, self.hash_algo);
+                let outcome = linked(&summary.linked, &summary.skipped, summary.reclaimed_bytes);
+                (outcome, entries)
+            }
+            "reflink" => {
+                let summary = reflink_duplicates(&report, dry_run);
+                let outcome = linked(&summary.linked, &summary.skipped, summary.reclaimed_bytes);
+                (outcome, Vec::new())
+            }
+            _ => {
+                return Response::error(
+                    400,
+                    "action must be delete, trash, hardlink, symlink or reflink",
+                )
+            }
+        };
+        info!("Scan {}: {action} (dry run: {dry_run})", self.id);
+        let recorded = if dry_run || entries.is_empty() {
+            String::new()
+        } else {
+            record(undo_log, &entries)
+        };
+        Response::json(
+            200,
+            format!(
+                "{{\"action\":{},\"dry_run\":{dry_run},{outcome}{recorded}}}",
+                quote(action)
+            ),
+        )
+    }
+}
+
+/// Returns the part of `report` that acting on the paths listed in `files`
+/// touches: each group holding any of them, cut down to its first unlisted
+/// file that still exists followed by the listed ones.
+fn selected(report: &DuplicateReport, files: &Value) -> Result<DuplicateReport, String> {
+    let listed: HashSet<&OsStr> = files
+        .as_array()
+        .and_then(|items| {
+            items
+                .iter()
+                .map(|item| item.as_str().map(OsStr::new))
+                .collect()
+        })
+        .ok_or("files must be a list of paths")?;
+    let mut found = 0;
+    let mut groups = Vec::new();
+    for group in &report.groups {
+        let (chosen, others): (Vec<&PathBuf>, Vec<&PathBuf>) = group
+            .paths
+            .iter()
+            .partition(|path| listed.contains(path.as_os_str()));
+        if chosen.is_empty() {
+            continue;
+        }
+        // An earlier action may have removed some of the others.
+        let Some(&kept) = others.iter().find(|path| path.exists()) else {
+            return Err(format!(
+                "every remaining copy of {} is listed, so none would be kept",
+                group.paths[0].display()
+            ));
+        };
+        found += chosen.len();
+        groups.push(DuplicateGroup {
+            hash: group.hash.clone(),
+            size: group.size,
+            paths: std::iter::once(kept).chain(chosen).cloned().collect(),
+        });
+    }
+    if found < listed.len() {
+        return Err("files lists paths that are not in any group".to_string());
+    }
+    Ok(DuplicateReport {
+        groups,
+        roots: report.roots.clone(),
+        ..DuplicateReport::default()
+    })
+}
+
+/// Decodes the `%XX` escapes and `+` signs of a query string value.
+fn percent_decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+    while let Some(byte) = input.next() {
+        bytes.push(match byte {
+            b'+' => b' ',
+            b'%' => {
+                let hex = [input.next()?, input.next()?];
+                u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?
+            }
+            _ => byte,
+        });
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Appends `entries` to the undo log, returning a JSON member to add to
+/// the response if that failed.
+fn record(undo_log: &Path, entries: &[UndoEntry]) -> String {
+    match undo::record(undo_log, entries) {
+        Ok(_) => String::new(),
+        Err(e) => {
+            error!("Failed to write undo log {}: {e}", undo_log.display());
+            format!(",\"undo_error\":{}", quote(&e.to_string()))
+        }
+    }
+}
+
+/// Returns the members describing a deletion or trashing.
+fn removed(summary: &DeleteSummary) -> String {
+    let files: Vec<String> = summary
+        .deleted
+        .iter()
+        .map(|file| file_json(&file.path, &file.kept, file.size))
+        .collect();
+    format!(
+        "\"files\":[{}],\"failed\":[{}],\"bytes\":{}",
+        files.join(","),
+        failed_json(&summary.failed),
+        summary.freed_bytes
+    )
+}
+
+/// Returns the members describing a pass replacing files with links.
+fn linked(linked: &[LinkedFile], skipped: &[(PathBuf, String)], bytes: u64) -> String {
+    let files: Vec<String> = linked
+        .iter()
+        .map(|file| file_json(&file.path, &file.target, file.size))
+        .collect();
+    format!(
+        "\"files\":[{}],\"failed\":[{}],\"bytes\":{bytes}",
+        files.join(","),
+        failed_json(skipped)
+    )
+}
+
+fn file_json(path: &Path, kept: &Path, size: u64) -> String {
+    format!(
+        "{{\"path\":{},\"kept\":{},\"size\":{size}}}",
+        quote(&path.to_string_lossy()),
+        quote(&kept.to_string_lossy())
+    )
+}
+
+fn failed_json(failed: &[(PathBuf, String)]) -> String {
+    let failed: Vec<String> = failed
+        .iter()
+        .map(|(path, reason)| {
+            format!(
+                "{{\"path\":{},\"reason\":{}}}",
+                quote(&path.to_string_lossy()),
+                quote(reason)
+            )
+        })
+        .collect();
+    failed.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Read;
+    use tempfile::tempdir;
+
+    /// Sends a request to `addr`, returning the status and body.
+    fn send(addr: std::net::SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).expect("connect");
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: {addr}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .expect("send request");
+        let mut response = String::new();
+        stream.read_to_string(&mut response).expect("read response");
+        let status = response[9..12].parse().expect("status code");
+        let body = response.split_once("\r\n\r\n").expect("body").1.to_string();
+        (status, body)
+    }
+
+    #[test]
+    fn test_scan_and_act_over_http() {
+        let dir = tempdir().expect("create temp dir");
+        fs::write(dir.path().join("a.txt"), "same").expect("write file");
+        fs::write(dir.path().join("b.txt"), "same").expect("write file");
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let addr = listener.local_addr().expect("address");
+        let undo_log = dir.path().join("undo.jsonl");
+        let serving = undo_log.clone();
+        std::thread::spawn(move || serve(&listener, &serving));
+
+        let body = format!(
+            "{{\"directories\":[{}]}}",
+            quote(&dir.path().to_string_lossy())
+        );
+        let (status, created) = send(addr, "POST", "/scans", &body);
+        assert_eq!(status, 201, "{created}");
+        assert!(created.starts_with("{\"id\":1,"));
+        let mut state = String::new();
+        for _ in 0..100 {
+            state = send(addr, "GET", "/scans/1", "").1;
+            if !state.contains("\"running\"") {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert!(
+            state.contains("\"state\":\"finished\",\"groups\":1,"),
+            "{state}"
+        );
+        let (status, report) = send(addr, "GET", "/scans/1/report", "");
+        assert_eq!(status, 200);
+        assert!(report.contains("\"total_groups\": 1,"));
+
+        let action = r#"{"action":"delete","keep":"first"}"#;
+        let (status, outcome) = send(addr, "POST", "/scans/1/actions", action);
+        assert_eq!(status, 200, "{outcome}");
+        assert!(outcome.contains("\"dry_run\":true"));
+        assert!(dir.path().join("b.txt").exists());
+        let action = r#"{"action":"delete","confirm":true}"#;
+        let (status, outcome) = send(addr, "POST", "/scans/1/actions", action);
+        assert_eq!(status, 200, "{outcome}");
+        assert!(!dir.path().join("b.txt").exists());
+        assert!(undo_log.exists());
+
+        assert_eq!(send(addr, "GET", "/scans/2", "").0, 404);
+        assert_eq!(send(addr, "POST", "/scans", "{}").0, 400);
+        assert_eq!(send(addr, "PUT", "/scans", "").0, 405);
+    }
+
+    #[test]
+    fn test_dashboard_previews_and_selected_files() {
+        let dir = tempdir().expect("create temp dir");
+        for name in ["a.png", "b.png", "c.png"] {
+            fs::write(dir.path().join(name), "not really a png").expect("write file");
+        }
+        fs::write(dir.path().join("secret.txt"), "unique").expect("write file");
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let addr = listener.local_addr().expect("address");
+        let undo_log = dir.path().join("undo.jsonl");
+        std::thread::spawn(move || serve(&listener, &undo_log));
+
+        let (status, page) = send(addr, "GET", "/", "");
+        assert_eq!(status, 200);
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        let body = format!(
+            "{{\"directories\":[{}]}}",
+            quote(&dir.path().to_string_lossy())
+        );
+        assert_eq!(send(addr, "POST", "/scans", &body).0, 201);
+        while send(addr, "GET", "/scans/1", "").1.contains("\"running\"") {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        let query = |name: &str| {
+            let path = dir.path().join(name).to_string_lossy().replace('/', "%2F");
+            format!("/scans/1/preview?path={path}")
+        };
+        assert_eq!(
+            send(addr, "GET", &query("b.png"), ""),
+            (200, "not really a png".to_string())
+        );
+        assert_eq!(send(addr, "GET", &query("secret.txt"), "").0, 404);
+
+        let files = |names: &[&str]| {
+            let paths: Vec<String> = names
+                .iter()
+                .map(|name| quote(&dir.path().join(name).to_string_lossy()))
+                .collect();
+            format!(
+                "{{\"action\":\"delete\",\"confirm\":true,\"files\":[{}]}}",
+                paths.join(",")
+            )
+        };
+        let everything = files(&["a.png", "b.png", "c.png"]);
+        assert_eq!(send(addr, "POST", "/scans/1/actions", &everything).0, 400);
+        let (status, outcome) = send(addr, "POST", "/scans/1/actions", &files(&["a.png"]));
+        assert_eq!(status, 200, "{outcome}");
+        assert!(!dir.path().join("a.png").exists());
+        assert!(dir.path().join("b.png").exists());
+        assert!(dir.path().join("c.png").exists());
+    }
+
+    #[test]
+    fn test_refuses_requests_a_web_page_could_make() {
+        assert!(allowed_host(Some("localhost:7878")));
+        assert!(allowed_host(Some("127.0.0.1:7878")));
+        assert!(allowed_host(Some("[::1]:7878")));
+        assert!(!allowed_host(Some("evil.example:7878")));
+        assert!(!allowed_host(None));
+
+        let mut request = "POST /scans HTTP/1.1\r\nHost: localhost\r\nContent-Type: text/plain\r\n\
+                           Content-Length: 2\r\n\r\n{}"
+            .as_bytes();
+        let request = read_request(&mut request).ok().expect("valid request");
+        let server = Server {
+            scans: Mutex::new(Vec::new()),
+            undo_log: PathBuf::from("unused"),
+            metrics: Arc::new(Metrics::new()),
+        };
+        assert_eq!(server.checked(&request).status, 415);
+    }
+}
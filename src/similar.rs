@@ -0,0 +1,596 @@
+//! Detection of images that look alike without being byte-for-byte copies.
+//!
+//! The byte-exact search misses a photo that was resized, re-encoded or
+//! lightly edited. Here each PNG and JPEG image is instead reduced to a
+//! perceptual hash: the image is shrunk to a grid of 9×8 brightness values,
+//! and each of the 64 bits records whether a cell is brighter than its
+//! neighbour to the right (a difference hash, or dHash). Copies of one
+//! picture keep the same broad gradients, so their hashes differ in few bits,
+//! and two images whose hashes are at most a chosen number of bits apart are
+//! grouped together, along with anything similar to either of them.
+
+use crate::jpeg::{self, Blocks};
+use crate::media;
+use crate::png::{self, Png};
+use crate::{
+    build_pool, collect_files, format_size, in_pool, into_errors, is_cancelled, record_error,
+    Console, GlobError, ScanConfig, ScanOptions, Stage,
+};
+use chrono::Local;
+use log::debug;
+use rayon::prelude::*;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// How many bits apart the hashes of two images may be for them to count as
+/// similar by default. Resized and re-encoded copies are usually within a few
+/// bits; unrelated photos are around 32 apart.
+pub const DEFAULT_MAX_DISTANCE: u32 = 10;
+
+/// The grid an image is shrunk to before hashing: one column more than the
+/// bits in a row, so that each bit compares two cells.
+const GRID_COLS: usize = 9;
+const GRID_ROWS: usize = 8;
+
+/// An image that is part of a group of similar images.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimilarImage {
+    pub path: PathBuf,
+    /// The size of the image in pixels.
+    pub width: u32,
+    pub height: u32,
+    /// The size of the file in bytes.
+    pub size: u64,
+}
+
+/// A set of images that look alike.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimilarImageGroup {
+    /// The images, largest first by pixel count, then by file size, so that
+    /// the best copy to keep comes first.
+    pub images: Vec<SimilarImage>,
+    /// The most bits by which the hashes of any two of the images differ.
+    /// This can exceed the maximum distance of the search, as images are
+    /// grouped with anything similar to any of them.
+    pub distance: u32,
+}
+
+/// The result of a search for similar images.
+#[derive(Debug)]
+pub struct SimilarImages {
+    /// The directories searched.
+    pub roots: Vec<PathBuf>,
+    /// How many bits apart the hashes of two images could be for them to
+    /// count as similar.
+    pub max_distance: u32,
+    /// How many images were compared.
+    pub images: usize,
+    /// Groups of at least two similar images, ordered by the path of their
+    /// first image.
+    pub groups: Vec<SimilarImageGroup>,
+    /// Images that could not be read or decoded, with the error raised.
+    pub errors: Vec<(PathBuf, io::Error)>,
+    /// Whether the search was cancelled before every image was hashed.
+    pub interrupted: bool,
+}
+
+/// Finds groups of PNG and JPEG images in `dirs` whose perceptual hashes are
+/// at most `max_distance` bits apart.
+///
+/// Files are chosen as in any scan, by every filter of `options` that
+/// applies to the walk and by size, and then by their extension. Images
+/// coded in a way that is not supported, such as arithmetic-coded or 12-bit
+/// JPEG, are left out; see the log for which.
+///
+/// # Errors
+/// Returns an error if any pattern in `options` is invalid.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::similar::{find_similar_images, DEFAULT_MAX_DISTANCE};
+/// use duplicate_file_finder::ScanOptions;
+/// use tempfile::tempdir;
+///
+/// let dir = tempdir().unwrap();
+/// let options = ScanOptions::new().quiet(true);
+/// let similar =
+///     find_similar_images(&[dir.path().to_path_buf()], &options, DEFAULT_MAX_DISTANCE)
+///         .unwrap();
+/// assert!(similar.groups.is_empty());
+/// ```
+pub fn find_similar_images(
+    dirs: &[PathBuf],
+    options: &ScanOptions,
+    max_distance: u32,
+) -> Result<SimilarImages, GlobError> {
+    let config = ScanConfig::new(options)?;
+    let console = Console::new(config.quiet, config.progress_json, config.progress);
+    let errors = Mutex::new(Vec::new());
+    let files: Vec<_> = collect_files(dirs, &config.walk, config.cancel, &errors, &mut Vec::new())
+        .into_iter()
+        .filter(|file| {
+            let size = file.meta.len();
+            config.min_size.is_none_or(|min| size >= min)
+                && config.max_size.is_none_or(|max| size <= max)
+                && media::is_image(&file.path)
+        })
+        .collect();
+    console.status(&format!(
+        "{} images identified across {} directories",
+        files.len(),
+        dirs.len()
+    ));
+
+    let progress = console.bar(files.len() as u64, Stage::ImageHash, "Hashing images");
+    let pool = build_pool(config.threads);
+    let hashed: Vec<(SimilarImage, u64)> = in_pool(pool.as_ref(), || {
+        files
+            .into_par_iter()
+            .filter_map(|file| {
+                if is_cancelled(config.cancel) {
+                    return None;
+                }
+                let data = config.read(&file.path, || fs::read(&file.path));
+                progress.inc(1);
+                let hashed = data.and_then(|data| {
+                    config.pace(data.len() as u64);
+                    image_hash(&data)
+                });
+                match hashed {
+                    Ok(Some((hash, width, height))) => {
+                        let image = SimilarImage {
+                            size: file.meta.len(),
+                            path: file.path,
+                            width,
+                            height,
+                        };
+                        Some((image, hash))
+                    }
+                    Ok(None) => {
+                        debug!("Skipping {}: unsupported image coding", file.path.display());
+                        None
+                    }
+                    Err(e) => {
+                        record_error(&errors, &file.path, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    });
+    progress.finish_with_message("Images hashed");
+
+    let images = hashed.len();
+    let groups = group_similar(hashed, max_distance);
+    console.complete(groups.len());
+    Ok(SimilarImages {
+        roots: dirs.to_vec(),
+        max_distance,
+        images,
+        groups,
+        errors: into_errors(errors),
+        interrupted: is_cancelled(config.cancel),
+    })
+}
+
+/// Returns the perceptual hash and the size in pixels of the PNG or JPEG
+/// image in `data`, or `None` if it is in neither format or is coded in a
+/// way that is not supported.
+fn image_hash(data: &[u8]) -> io::Result<Option<(u64, u32, u32)>> {
+    if png::is_png(data) {
+        let png = Png::parse(data)?;
+        let mut grid = Grid::new(png.width, png.height);
+        png.for_each_row(&mut |row| {
+            let y = usize::try_from(row.y).unwrap_or_default();
+            for i in 0..usize::try_from(row.len).unwrap_or_default() {
+                let x = usize::try_from(row.x).unwrap_or_default()
+                    + i * usize::try_from(row.step).unwrap_or_default();
+                grid.add(x, y, i64::from(png.luma(row.bytes, i)));
+            }
+        })?;
+        return Ok(Some((grid.dhash(), png.width, png.height)));
+    }
+    if jpeg::is_jpeg(data) {
+        let Some(Blocks {
+            width,
+            height,
+            cols,
+            rows,
+            dc,
+        }) = jpeg::decode_blocks(data)?
+        else {
+            return Ok(None);
+        };
+        let mut grid = Grid::new(
+            u32::try_from(cols).unwrap_or(u32::MAX),
+            u32::try_from(rows).unwrap_or(u32::MAX),
+        );
+        for (i, &value) in dc.iter().enumerate() {
+            grid.add(i % cols, i / cols, i64::from(value));
+        }
+        return Ok(Some((grid.dhash(), width, height)));
+    }
+    Ok(None)
+}
+
+/// The average brightness of an image over each cell of a small grid.
+struct Grid {
+    width: usize,
+    height: usize,
+    /// The grid column of each column of pixels.
+    cols: Vec<usize>,
+    sums: Vec<i64>,
+    counts: Vec<u32>,
+}
+
+impl Grid {
+    fn new(width: u32, height: u32) -> Self {
+        let width = usize::try_from(width).unwrap_or_default().max(1);
+        let height = usize::try_from(height).unwrap_or_default().max(1);
+        Self {
+            width,
+            height,
+            cols: (0..width).map(|x| x * GRID_COLS / width).collect(),
+            sums: vec![0; GRID_COLS * GRID_ROWS],
+            counts: vec![0; GRID_COLS * GRID_ROWS],
+        }
+    }
+
+    /// Adds the brightness of the pixel at `x`, `y`.
+    fn add(&mut self, x: usize, y: usize, value: i64) {
+        let (Some(&col), true) = (self.cols.get(x), y < self.height) else {
+            return;
+        };
+        let cell = y * GRID_ROWS / self.height * GRID_COLS + col;
+        self.sums[cell] += value;
+        self.counts[cell] += 1;
+    }
+
+    /// Returns the average of the cell at `col`, `row`. In an image smaller
+    /// than the grid some cells have no pixels, and take the average of the
+    /// cell holding the pixel nearest their centre.
+    #[allow(clippy::cast_precision_loss)]
+    fn average(&self, col: usize, row: usize) -> f64 {
+        let cell = row * GRID_COLS + col;
+        if self.counts[cell] > 0 {
+            return self.sums[cell] as f64 / f64::from(self.counts[cell]);
+        }
+        let x = (2 * col + 1) * self.width / (2 * GRID_COLS);
+        let y = (2 * row + 1) * self.height / (2 * GRID_ROWS);
+        let nearest = y * GRID_ROWS / self.height * GRID_COLS + self.cols[x];
+        if self.counts[nearest] == 0 {
+            return 0.0;
+        }
+        self.sums[nearest] as f64 / f64::from(self.counts[nearest])
+    }
+
+    /// Returns the difference hash: a bit for each cell that is brighter
+    /// than its neighbour to the right.
+    fn dhash(&self) -> u64 {
+        let mut hash = 0;
+        for row in 0..GRID_ROWS {
+            for col in 0..GRID_COLS - 1 {
+                hash <<= 1;
+                if self.average(col, row) > self.average(col + 1, row) {
+                    hash |= 1;
+                }
+            }
+        }
+        hash
+    }
+}
+
+/// Groups images whose hashes are at most `max_distance` bits apart, and
+/// anything similar to those, leaving out images similar to nothing.
+fn group_similar(images: Vec<(SimilarImage, u64)>, max_distance: u32) -> Vec<SimilarImageGroup> {
+    let hashes: Vec<[u64; 1]> = images.iter().map(|&(_, hash)| [hash]).collect();
+    let mut images: Vec<Option<SimilarImage>> =
+        images.into_iter().map(|(image, _)| Some(image)).collect();
+    let mut groups: Vec<SimilarImageGroup> = cluster(&hashes, max_distance)
+        .into_iter()
+        .map(|(members, distance)| {
+            let mut images: Vec<SimilarImage> =
+                members.iter().filter_map(|&i| images[i].take()).collect();
+            images.sort_by(|a, b| {
+                let pixels = |i: &SimilarImage| u64::from(i.width) * u64::from(i.height);
+                pixels(b)
+                    .cmp(&pixels(a))
+                    .then(b.size.cmp(&a.size))
+                    .then_with(|| a.path.cmp(&b.path))
+            });
+            SimilarImageGroup { images, distance }
+        })
+        .collect();
+    groups.sort_by(|a, b| a.images[0].path.cmp(&b.images[0].path));
+    groups
+}
+
+/// Returns the number of bits by which two lists of hashes differ, adding
+/// up the differences of each pair of hashes.
+fn distance(a: &[u64], b: &[u64]) -> u32 {
+    a.iter().zip(b).map(|(a, b)| (a ^ b).count_ones()).sum()
+}
+
+/// Groups the items whose `hashes` are at most `max_distance` bits apart,
+/// and anything similar to those, returning the indexes of each group of
+/// at least two items with the most bits by which any two of them differ.
+/// An item's hashes may be several, such as one for each frame of a video.
+pub(crate) fn cluster<H: AsRef<[u64]>>(hashes: &[H], max_distance: u32) -> Vec<(Vec<usize>, u32)> {
+    let mut tree = BkTree {
+        hashes,
+        nodes: Vec::new(),
+    };
+    let mut parents: Vec<usize> = (0..hashes.len()).collect();
+    for (i, hash) in hashes.iter().enumerate() {
+        for j in tree.find(hash.as_ref(), max_distance) {
+            let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+            parents[a.max(b)] = a.min(b);
+        }
+        tree.insert(i);
+    }
+
+    let mut members: Vec<Vec<usize>> = Vec::new();
+    let mut group_of = vec![usize::MAX; hashes.len()];
+    for i in 0..hashes.len() {
+        let group = &mut group_of[root(&mut parents, i)];
+        if *group == usize::MAX {
+            *group = members.len();
+            members.push(Vec::new());
+        }
+        members[*group].push(i);
+    }
+    members
+        .into_iter()
+        .filter(|members| members.len() > 1)
+        .map(|members| {
+            let mut most = 0;
+            for (n, &a) in members.iter().enumerate() {
+                for &b in &members[n + 1..] {
+                    most = most.max(distance(hashes[a].as_ref(), hashes[b].as_ref()));
+                }
+            }
+            (members, most)
+        })
+        .collect()
+}
+
+/// Returns the representative of the set holding `i`, shortening the path
+/// to it on the way.
+fn root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+/// A BK-tree of the hashes of items, which finds every item within a
+/// distance of some hashes without comparing them with them all.
+///
+/// Each child of a node is kept with its distance from the node. By the
+/// triangle inequality, items within `d` of a query that is `q` from a node
+/// can only be under children between `q - d` and `q + d` from it.
+struct BkTree<'h, H> {
+    hashes: &'h [H],
+    /// The item of each node, and its children with their distances.
+    nodes: Vec<(usize, Vec<(u32, usize)>)>,
+}
+
+impl<H: AsRef<[u64]>> BkTree<'_, H> {
+    fn insert(&mut self, item: usize) {
+        let new = self.nodes.len();
+        self.nodes.push((item, Vec::new()));
+        if new == 0 {
+            return;
+        }
+        let hash = self.hashes[item].as_ref();
+        let mut node = 0;
+        loop {
+            let distance = distance(self.hashes[self.nodes[node].0].as_ref(), hash);
+            let child = self.nodes[node]
+                .1
+                .iter()
+                .find(|&&(d, _)| d == distance)
+                .map(|&(_, child)| child);
+            let Some(child) = child else {
+                self.nodes[node].1.push((distance, new));
+                return;
+            };
+            node = child;
+        }
+    }
+
+    /// Returns the items whose hashes are at most `max_distance` from `hash`.
+    fn find(&self, hash: &[u64], max_distance: u32) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut pending = if self.nodes.is_empty() {
+            Vec::new()
+        } else {
+            vec![0]
+        };
+        while let Some(node) = pending.pop() {
+            let (item, children) = &self.nodes[node];
+            let distance = distance(self.hashes[*item].as_ref(), hash);
+            if distance <= max_distance {
+                found.push(*item);
+            }
+            pending.extend(
+                children
+                    .iter()
+                    .filter(|&&(d, _)| d.abs_diff(distance) <= max_distance)
+                    .map(|&(_, child)| child),
+            );
+        }
+        found
+    }
+}
+
+/// Writes the groups of similar images as plain text, with the size of
+/// each image so that the best copy is easy to pick.
+///
+/// # Errors
+/// Returns any error raised by `writer`.
+pub fn write_similar_images_to<W: Write>(
+    writer: &mut W,
+    similar: &SimilarImages,
+    start_time: &str,
+) -> io::Result<()> {
+    let username = whoami::username();
+    let end_time = Local::now().format("%Y%m%d %H:%M:%S").to_string();
+
+    writeln!(writer, "Similar Images Report")?;
+    writeln!(writer, "Generated by: {username}")?;
+    writeln!(writer, "Start Time: {start_time}")?;
+    writeln!(writer, "End Time: {end_time}")?;
+    if similar.roots.len() == 1 {
+        writeln!(writer, "Base Directory: {}", similar.roots[0].display())?;
+    } else {
+        writeln!(writer, "Base Directories:")?;
+        for dir in &similar.roots {
+            writeln!(writer, " - {}", dir.display())?;
+        }
+    }
+    writeln!(writer)?;
+
+    if similar.interrupted {
+        writeln!(
+            writer,
+            "Scan Interrupted: the results below are incomplete."
+        )?;
+    }
+    writeln!(
+        writer,
+        "Images Compared: {} (similar when at most {} of 64 bits apart)",
+        similar.images, similar.max_distance
+    )?;
+    writeln!(writer, "Groups Of Similar Images: {}", similar.groups.len())?;
+    if !similar.errors.is_empty() {
+        writeln!(
+            writer,
+            "Images Skipped Due To Errors: {}",
+            similar.errors.len()
+        )?;
+    }
+    writeln!(writer)?;
+
+    for group in &similar.groups {
+        writeln!(
+            writer,
+            "{} images, up to {} bits apart:",
+            group.images.len(),
+            group.distance
+        )?;
+        for image in &group.images {
+            writeln!(
+                writer,
+                "{} ({}x{}, {})",
+                image.path.display(),
+                image.width,
+                image.height,
+                format_size(image.size)
+            )?;
+        }
+        writeln!(writer)?;
+    }
+
+    if !similar.errors.is_empty() {
+        writeln!(writer, "Skipped Files:")?;
+        for (path, error) in &similar.errors {
+            writeln!(writer, "{}: {error}", path.display())?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    const WIDTH: usize = 128;
+    const HEIGHT: usize = 96;
+
+    /// A picture of a bright spot left of centre, at any size.
+    fn shade(x: usize, y: usize, width: usize, height: usize) -> u8 {
+        let (u, v) = (x * 256 / width, y * 256 / height);
+        let dimming = u.abs_diff(80) + v.abs_diff(100) / 2;
+        u8::try_from(255usize.saturating_sub(dimming)).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_find_similar_images() {
+        let dir = tempdir().expect("create temp dir");
+        let path = |name: &str| dir.path().join(name);
+        let write = |name: &str, data: &[u8]| fs::write(path(name), data).expect("write file");
+        write(
+            "photo.png",
+            &png::encode(WIDTH, HEIGHT, false, |x, y| shade(x, y, WIDTH, HEIGHT)),
+        );
+        write(
+            "small.png",
+            &png::encode(WIDTH / 2, HEIGHT / 2, true, |x, y| {
+                shade(x, y, WIDTH / 2, HEIGHT / 2)
+            }),
+        );
+        write(
+            "photo.jpg",
+            &jpeg::encode_dc_only(WIDTH, HEIGHT, |x, y| shade(x, y, WIDTH, HEIGHT)),
+        );
+        write(
+            "mirrored.png",
+            &png::encode(WIDTH, HEIGHT, false, |x, y| {
+                shade(WIDTH - 1 - x, y, WIDTH, HEIGHT)
+            }),
+        );
+        write("broken.png", b"\x89PNG\r\n\x1a\nnot much else");
+        write("notes.txt", b"not an image");
+
+        let options = ScanOptions::new().quiet(true);
+        let similar =
+            find_similar_images(&[dir.path().to_path_buf()], &options, DEFAULT_MAX_DISTANCE)
+                .expect("valid globs");
+        assert_eq!(similar.images, 4);
+        assert_eq!(similar.errors.len(), 1);
+        assert_eq!(similar.errors[0].0, path("broken.png"));
+        assert_eq!(similar.groups.len(), 1);
+        let group = &similar.groups[0];
+        let paths: Vec<&Path> = group.images.iter().map(|i| i.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            [path("photo.png"), path("photo.jpg"), path("small.png")]
+        );
+        assert!(group.distance <= DEFAULT_MAX_DISTANCE);
+        assert_eq!((group.images[2].width, group.images[2].height), (64, 48));
+
+        let mut text = Vec::new();
+        write_similar_images_to(&mut text, &similar, "start").expect("write report");
+        let text = String::from_utf8(text).expect("UTF-8");
+        assert!(text.contains("Images Compared: 4 (similar when at most 10 of 64 bits apart)\n"));
+        assert!(text.contains(&format!("{} (64x48, ", path("small.png").display())));
+    }
+
+    #[test]
+    fn test_group_similar_chains_neighbours() {
+        let image = |name: &str| SimilarImage {
+            path: PathBuf::from(name),
+            width: 1,
+            height: 1,
+            size: 1,
+        };
+        let images = vec![
+            (image("a"), 0),
+            (image("c"), 0b11_1111),
+            (image("far"), u64::MAX),
+            (image("b"), 0b111),
+        ];
+        let groups = group_similar(images, 3);
+        assert_eq!(groups.len(), 1);
+        let names: Vec<_> = groups[0].images.iter().map(|i| i.path.clone()).collect();
+        assert_eq!(names, [PathBuf::from("a"), "b".into(), "c".into()]);
+        assert_eq!(groups[0].distance, 6);
+    }
+}
@@ -0,0 +1,222 @@
+//! Temporary on-disk lists of the files found by a low-memory scan.
+//!
+//! A scan normally holds the path of every file it finds in memory until the
+//! files have been grouped by size, which for tens of millions of files can
+//! take more memory than the machine has. A low-memory scan writes each file
+//! to a [`Spill`] instead, then reads them back in [`Batches`] small enough to
+//! group in memory. Files of one size always land in the same batch, so each
+//! batch can be searched for duplicates on its own.
+
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The fewest files that a scan is split into batches of.
+pub(crate) const BATCH_FILES: u64 = 1_000_000;
+
+/// The most batches a scan is split into, so that writing them never needs
+/// more open files than the system allows. Larger scans get larger batches.
+const MAX_BATCHES: u64 = 256;
+
+/// The sizes and paths of the files found so far, in the temporary
+/// directory.
+pub(crate) struct Spill {
+    file: TempFile,
+    writer: BufWriter<File>,
+    len: u64,
+}
+
+impl Spill {
+    /// Creates an empty spill file in the system's temporary directory.
+    pub(crate) fn new() -> io::Result<Self> {
+        let (file, writer) = TempFile::create()?;
+        Ok(Self {
+            file,
+            writer: BufWriter::new(writer),
+            len: 0,
+        })
+    }
+
+    /// Returns the path of the spill file, for error messages.
+    pub(crate) fn path(&self) -> &Path {
+        &self.file.0
+    }
+
+    /// Returns how many files have been written.
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Appends a file of `size` bytes at `path`.
+    pub(crate) fn push(&mut self, size: u64, path: &Path) -> io::Result<()> {
+        write_record(&mut self.writer, size, path)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Splits the files written into batches of about `batch_files` each,
+    /// keeping files of the same size together.
+    pub(crate) fn into_batches(self, batch_files: u64) -> io::Result<Batches> {
+        let Self { file, writer, len } = self;
+        writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        let count = len.div_ceil(batch_files.max(1)).clamp(1, MAX_BATCHES);
+        if count == 1 {
+            return Ok(Batches {
+                files: vec![file].into_iter(),
+            });
+        }
+
+        let mut buckets = Vec::new();
+        for _ in 0..count {
+            let (bucket, writer) = TempFile::create()?;
+            buckets.push((bucket, BufWriter::new(writer)));
+        }
+        let mut reader = BufReader::new(File::open(&file.0)?);
+        while let Some((size, path)) = read_record(&mut reader)? {
+            // Sizes are clustered, so they are mixed before choosing a batch.
+            let index = (size.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32) % count;
+            let index = usize::try_from(index).unwrap_or_default();
+            write_record(&mut buckets[index].1, size, &path)?;
+        }
+        let mut files = Vec::with_capacity(buckets.len());
+        for (bucket, writer) in buckets {
+            writer
+                .into_inner()
+                .map_err(io::IntoInnerError::into_error)?;
+            files.push(bucket);
+        }
+        Ok(Batches {
+            files: files.into_iter(),
+        })
+    }
+}
+
+/// The files of a [`Spill`], read back one batch at a time.
+pub(crate) struct Batches {
+    files: std::vec::IntoIter<TempFile>,
+}
+
+impl Batches {
+    /// Returns how many batches are left.
+    pub(crate) fn len(&self) -> usize {
+        self.files.len()
+    }
+}
+
+impl Iterator for Batches {
+    type Item = io::Result<Vec<(u64, PathBuf)>>;
+
+    /// Reads the sizes and paths of the next batch, in the order they were
+    /// written, and removes its temporary file.
+    fn next(&mut self) -> Option<Self::Item> {
+        let file = self.files.next()?;
+        Some(File::open(&file.0).and_then(|handle| {
+            let mut reader = BufReader::new(handle);
+            let mut batch = Vec::new();
+            while let Some(record) = read_record(&mut reader)? {
+                batch.push(record);
+            }
+            Ok(batch)
+        }))
+    }
+}
+
+/// Writes a record: the size, the length of the path and the path itself.
+fn write_record(writer: &mut impl Write, size: u64, path: &Path) -> io::Result<()> {
+    let bytes = path.as_os_str().as_encoded_bytes();
+    let len = u32::try_from(bytes.len()).map_err(|_| io::Error::other("path too long"))?;
+    writer.write_all(&size.to_le_bytes())?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+/// Reads the next record, or `None` at the end of the file.
+fn read_record(reader: &mut impl Read) -> io::Result<Option<(u64, PathBuf)>> {
+    let mut size = [0; 8];
+    match reader.read_exact(&mut size) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = usize::try_from(u32::from_le_bytes(len)).map_err(io::Error::other)?;
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    // SAFETY: the bytes were written by `write_record` from
+    // `OsStr::as_encoded_bytes` in this same process.
+    let path = unsafe { OsStr::from_encoded_bytes_unchecked(&bytes) };
+    Ok(Some((u64::from_le_bytes(size), PathBuf::from(path))))
+}
+
+/// A file in the temporary directory, removed when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    /// Creates a new, uniquely named file and opens it for writing.
+    fn create() -> io::Result<(Self, File)> {
+        static CREATED: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir();
+        loop {
+            let n = CREATED.fetch_add(1, Ordering::Relaxed);
+            let path = dir.join(format!(".dff-{}-{n}.spill", std::process::id()));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((Self(path), file)),
+                // Left behind by an earlier process with the same id.
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batches_keep_sizes_together() {
+        let mut spill = Spill::new().expect("create spill file");
+        let spill_path = spill.path().to_path_buf();
+        let mut written = Vec::new();
+        for i in 0..40u64 {
+            let path = PathBuf::from(format!("dir/file {i}\n.txt"));
+            spill.push(i % 7, &path).expect("write record");
+            written.push((i % 7, path));
+        }
+        assert_eq!(spill.len(), 40);
+
+        let batches = spill.into_batches(10).expect("split into batches");
+        assert_eq!(batches.len(), 4);
+        assert!(!spill_path.exists());
+        let batches: Vec<_> = batches.map(|b| b.expect("read batch")).collect();
+        let mut read: Vec<_> = batches.iter().flatten().cloned().collect();
+        read.sort();
+        written.sort();
+        assert_eq!(read, written);
+        for size in 0..7 {
+            let holding = batches
+                .iter()
+                .filter(|batch| batch.iter().any(|(s, _)| *s == size))
+                .count();
+            assert_eq!(holding, 1, "size {size} is in one batch");
+        }
+
+        let mut small = Spill::new().expect("create spill file");
+        small.push(3, Path::new("a")).expect("write record");
+        let mut batches = small.into_batches(10).expect("split into batches");
+        assert_eq!(batches.len(), 1);
+        let batch = batches.next().expect("one batch").expect("read batch");
+        assert_eq!(batch, [(3, PathBuf::from("a"))]);
+    }
+}
@@ -0,0 +1,859 @@
+//! Writing the report as an `SQLite` database for ad-hoc SQL queries.
+//!
+//! The database is written directly in the `SQLite` file format rather than
+//! through the `SQLite` library: every table is filled once, in rowid order, so
+//! each B-tree can be laid out bottom-up in a single pass with no need to
+//! rebalance. Pages are streamed to the writer as they fill, except for the
+//! first page, which holds the schema and is written last. The result is an
+//! ordinary database that `sqlite3` and every `SQLite` binding can open and
+//! modify.
+//!
+//! Reading a report back, to compare it with a later one, walks the table
+//! B-trees of any database in the `SQLite` file format, so a report that
+//! `sqlite3` has since modified or vacuumed can still be read.
+
+use crate::{DuplicateGroup, DuplicateReport, FileMetadata};
+use chrono::Local;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+const PAGE_SIZE: usize = 4096;
+/// The most payload a table leaf cell keeps on its own page.
+const MAX_LOCAL: usize = PAGE_SIZE - 35;
+/// The least payload kept locally once a cell spills to overflow pages.
+const MIN_LOCAL: usize = (PAGE_SIZE - 12) * 32 / 255 - 23;
+/// Payload bytes carried by each overflow page after its next-page pointer.
+const OVERFLOW_CAPACITY: usize = PAGE_SIZE - 4;
+/// Children per interior page, assuming the largest possible cells.
+const INTERIOR_FANOUT: usize = (PAGE_SIZE - 12) / (2 + 4 + 9) + 1;
+/// The library version recorded in the header, as `SQLITE_VERSION_NUMBER`.
+const SQLITE_VERSION: u32 = 3_046_000;
+
+const LEAF_TABLE: u8 = 0x0D;
+const INTERIOR_TABLE: u8 = 0x05;
+
+const SCHEMA: [(&str, &str); 5] = [
+    (
+        "scan",
+        "CREATE TABLE scan(id INTEGER PRIMARY KEY, generated_by TEXT, start_time TEXT, \
+         end_time TEXT, interrupted INTEGER, total_groups INTEGER, \
+         total_reclaimable_bytes INTEGER)",
+    ),
+    (
+        "base_dirs",
+        "CREATE TABLE base_dirs(id INTEGER PRIMARY KEY, path TEXT)",
+    ),
+    (
+        "duplicate_groups",
+        "CREATE TABLE duplicate_groups(id INTEGER PRIMARY KEY, hash TEXT, size INTEGER, \
+         file_count INTEGER, reclaimable_bytes INTEGER)",
+    ),
+    (
+        "files",
+        "CREATE TABLE files(id INTEGER PRIMARY KEY, \
+         group_id INTEGER REFERENCES duplicate_groups(id), path TEXT, modified INTEGER, \
+         readonly INTEGER, inode INTEGER)",
+    ),
+    (
+        "errors",
+        "CREATE TABLE errors(id INTEGER PRIMARY KEY, path TEXT, error TEXT)",
+    ),
+];
+
+/// Writes the report as an `SQLite` database.
+///
+/// The database has five tables:
+///
+/// * `scan` – a single row with `generated_by`, `start_time`, `end_time`,
+///   `interrupted`, `total_groups` and `total_reclaimable_bytes`.
+/// * `base_dirs` – the scanned directories, in the order they were given.
+/// * `duplicate_groups` – one row per group in report order, with its
+///   `hash`, `size` in bytes, `file_count` and `reclaimable_bytes`.
+/// * `files` – one row per duplicate file, with the `group_id` of its group,
+///   its `path`, its `modified` time in seconds since the Unix epoch,
+///   whether it is `readonly` (0 or 1), and its `inode`. Metadata that was
+///   not captured is `NULL`.
+/// * `errors` – the skipped files, with their `path` and `error`.
+///
+/// No indexes are created; add one on `files(group_id)` before running many
+/// joins over a large result set. Paths that are not valid UTF-8 are stored
+/// lossily.
+///
+/// # Errors
+/// Returns any I/O error raised by `writer`.
+///
+/// # Example
+/// ```
+/// use std::io::Cursor;
+/// use std::path::PathBuf;
+/// use duplicate_file_finder::{write_sqlite_to, DuplicateGroup, DuplicateReport};
+///
+/// let report = DuplicateReport::from_groups(vec![DuplicateGroup {
+///     hash: String::from("somehash"),
+///     size: 1024,
+///     paths: vec![PathBuf::from("/tmp/a.txt"), PathBuf::from("/tmp/b.txt")],
+/// }]);
+/// let mut database = Cursor::new(Vec::new());
+/// write_sqlite_to(&mut database, &report, "20240101 00:00:00", &[PathBuf::from("/tmp")])
+///     .unwrap();
+/// assert!(database.get_ref().starts_with(b"SQLite format 3\0"));
+/// ```
+pub fn write_sqlite_to<W: Write + Seek>(
+    writer: &mut W,
+    report: &DuplicateReport,
+    start_time: &str,
+    base_dirs: &[PathBuf],
+) -> io::Result<()> {
+    let end_time = Local::now().format("%Y%m%d %H:%M:%S").to_string();
+    let username = whoami::username();
+    let mut database = Database::new(writer)?;
+
+    let (name, sql) = SCHEMA[0];
+    database.table(
+        name,
+        sql,
+        [vec![
+            Value::Text(&username),
+            Value::Text(start_time),
+            Value::Text(&end_time),
+            Value::Integer(i64::from(report.interrupted)),
+            integer(report.total_groups as u64),
+            integer(report.total_reclaimable_bytes),
+        ]],
+    )?;
+
+    let (name, sql) = SCHEMA[1];
+    let dirs: Vec<String> = base_dirs
+        .iter()
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .collect();
+    database.table(name, sql, dirs.iter().map(|dir| vec![Value::Text(dir)]))?;
+
+    let (name, sql) = SCHEMA[2];
+    database.table(
+        name,
+        sql,
+        report.groups.iter().map(|group| {
+            vec![
+                Value::Text(&group.hash),
+                integer(group.size),
+                integer(group.paths.len() as u64),
+                integer(group.reclaimable_bytes()),
+            ]
+        }),
+    )?;
+
+    let (name, sql) = SCHEMA[3];
+    let files = report.groups.iter().enumerate().flat_map(|(index, group)| {
+        group.paths.iter().map(move |path| {
+            let mut row = vec![
+                integer(index as u64 + 1),
+                Value::Owned(path.to_string_lossy().into_owned()),
+            ];
+            row.extend(metadata_values(report.metadata.get(path)));
+            row
+        })
+    });
+    database.table(name, sql, files)?;
+
+    let (name, sql) = SCHEMA[4];
+    let errors: Vec<(String, String)> = report
+        .errors
+        .iter()
+        .map(|(path, error)| (path.to_string_lossy().into_owned(), error.to_string()))
+        .collect();
+    database.table(
+        name,
+        sql,
+        errors
+            .iter()
+            .map(|(path, error)| vec![Value::Text(path), Value::Text(error)]),
+    )?;
+
+    database.finish()
+}
+
+/// The `modified`, `readonly` and `inode` columns of a file.
+fn metadata_values(metadata: Option<&FileMetadata>) -> [Value<'static>; 3] {
+    let Some(metadata) = metadata else {
+        return [Value::Null, Value::Null, Value::Null];
+    };
+    let modified = metadata
+        .modified
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(Value::Null, |d| integer(d.as_secs()));
+    [
+        modified,
+        Value::Integer(i64::from(metadata.readonly)),
+        metadata.inode.map_or(Value::Null, integer),
+    ]
+}
+
+/// Stores an unsigned count as an `SQLite` integer, which is signed.
+fn integer(value: u64) -> Value<'static> {
+    i64::try_from(value).map_or(Value::Null, Value::Integer)
+}
+
+/// A column value in a row being written.
+enum Value<'a> {
+    Null,
+    Integer(i64),
+    Text(&'a str),
+    Owned(String),
+}
+
+/// A database being written, one table at a time.
+struct Database<'w, W: Write + Seek> {
+    writer: &'w mut W,
+    /// The number of the page the next write lands on, counting from 1.
+    next_page: u32,
+    /// The name, `CREATE TABLE` statement and root page of every table.
+    tables: Vec<(&'static str, &'static str, u32)>,
+}
+
+impl<'w, W: Write + Seek> Database<'w, W> {
+    fn new(writer: &'w mut W) -> io::Result<Self> {
+        writer.seek(SeekFrom::Start(0))?;
+        // Page 1 is filled in by `finish`, once the root pages are known.
+        writer.write_all(&[0; PAGE_SIZE])?;
+        Ok(Self {
+            writer,
+            next_page: 2,
+            tables: Vec::new(),
+        })
+    }
+
+    fn write_page(&mut self, page: &[u8; PAGE_SIZE]) -> io::Result<u32> {
+        self.writer.write_all(page)?;
+        let number = self.next_page;
+        self.next_page += 1;
+        Ok(number)
+    }
+
+    /// Writes a table whose first column is `id INTEGER PRIMARY KEY`, taking
+    /// the remaining columns of each row from `rows`. Rows are numbered from 1.
+    fn table<'a>(
+        &mut self,
+        name: &'static str,
+        sql: &'static str,
+        rows: impl IntoIterator<Item = Vec<Value<'a>>>,
+    ) -> io::Result<()> {
+        let mut leaf = Leaf::default();
+        let mut leaves = Vec::new();
+        let mut rowid = 0;
+        for mut row in rows {
+            rowid += 1;
+            // The `id` column aliases the rowid, so its value is stored as NULL.
+            row.insert(0, Value::Null);
+            let cell = self.cell(rowid, &record(&row))?;
+            if !leaf.fits(&cell) {
+                leaves.push((self.write_page(&leaf.page(0))?, rowid - 1));
+                leaf = Leaf::default();
+            }
+            leaf.cells.push(cell);
+        }
+        if leaves.is_empty() || !leaf.cells.is_empty() {
+            leaves.push((self.write_page(&leaf.page(0))?, rowid));
+        }
+        let root = self.interior_levels(leaves)?;
+        self.tables.push((name, sql, root));
+        Ok(())
+    }
+
+    /// Builds a leaf cell, writing any payload that does not fit on the leaf
+    /// to a chain of overflow pages.
+    fn cell(&mut self, rowid: i64, payload: &[u8]) -> io::Result<Vec<u8>> {
+        let mut cell = Vec::new();
+        put_varint(&mut cell, payload.len() as u64);
+        put_varint(&mut cell, rowid.cast_unsigned());
+        if payload.len() <= MAX_LOCAL {
+            cell.extend_from_slice(payload);
+            return Ok(cell);
+        }
+        let spill = MIN_LOCAL + (payload.len() - MIN_LOCAL) % OVERFLOW_CAPACITY;
+        let local = if spill <= MAX_LOCAL { spill } else { MIN_LOCAL };
+        cell.extend_from_slice(&payload[..local]);
+        // Overflow pages are written back to back, so each points at the next.
+        cell.extend_from_slice(&self.next_page.to_be_bytes());
+        let mut chunks = payload[local..].chunks(OVERFLOW_CAPACITY).peekable();
+        while let Some(chunk) = chunks.next() {
+            let mut page = [0; PAGE_SIZE];
+            if chunks.peek().is_some() {
+                page[..4].copy_from_slice(&(self.next_page + 1).to_be_bytes());
+            }
+            page[4..4 + chunk.len()].copy_from_slice(chunk);
+            self.write_page(&page)?;
+        }
+        Ok(cell)
+    }
+
+    /// Writes interior pages above `children`, each given as its page number
+    /// and largest rowid, and returns the root page.
+    fn interior_levels(&mut self, mut children: Vec<(u32, i64)>) -> io::Result<u32> {
+        while children.len() > 1 {
+            // Spread the children evenly so every page has at least one cell.
+            let pages = children.len().div_ceil(INTERIOR_FANOUT);
+            let mut parents = Vec::with_capacity(pages);
+            let mut rest = &children[..];
+            for remaining in (1..=pages).rev() {
+                let (these, after) = rest.split_at(rest.len().div_ceil(remaining));
+                rest = after;
+                let (&(right, max_rowid), cells) = these.split_last().expect("non-empty");
+                let mut page = [0; PAGE_SIZE];
+                page[0] = INTERIOR_TABLE;
+                page[8..12].copy_from_slice(&right.to_be_bytes());
+                let cells: Vec<Vec<u8>> = cells
+                    .iter()
+                    .map(|&(child, key)| {
+                        let mut cell = child.to_be_bytes().to_vec();
+                        put_varint(&mut cell, key.cast_unsigned());
+                        cell
+                    })
+                    .collect();
+                place_cells(&mut page, 0, 12, &cells);
+                parents.push((self.write_page(&page)?, max_rowid));
+            }
+            children = parents;
+        }
+        Ok(children[0].0)
+    }
+
+    /// Writes page 1: the file header and the schema table.
+    fn finish(self) -> io::Result<()> {
+        let mut schema = Leaf {
+            cells: Vec::new(),
+            offset: 100,
+        };
+        for (rowid, &(name, sql, root)) in (1..).zip(&self.tables) {
+            let payload = record(&[
+                Value::Text("table"),
+                Value::Text(name),
+                Value::Text(name),
+                Value::Integer(i64::from(root)),
+                Value::Text(sql),
+            ]);
+            let mut cell = Vec::new();
+            put_varint(&mut cell, payload.len() as u64);
+            put_varint(&mut cell, rowid);
+            cell.extend_from_slice(&payload);
+            assert!(schema.fits(&cell), "the schema fits on the first page");
+            schema.cells.push(cell);
+        }
+        let mut page = schema.page(100);
+        write_header(&mut page, self.next_page - 1);
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&page)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+}
+
+/// The cells of a table leaf page being filled.
+#[derive(Default)]
+struct Leaf {
+    cells: Vec<Vec<u8>>,
+    /// Where the page header starts: 100 on page 1, after the file header.
+    offset: usize,
+}
+
+impl Leaf {
+    /// Whether `cell` still fits, along with its entry in the pointer array.
+    fn fits(&self, cell: &[u8]) -> bool {
+        let used: usize = self.cells.iter().map(|c| c.len() + 2).sum();
+        self.offset + 8 + used + cell.len() + 2 <= PAGE_SIZE
+    }
+
+    fn page(&self, offset: usize) -> [u8; PAGE_SIZE] {
+        let mut page = [0; PAGE_SIZE];
+        page[offset] = LEAF_TABLE;
+        place_cells(&mut page, offset, 8, &self.cells);
+        page
+    }
+}
+
+/// Fills in the cell count, the cell pointer array and the cells of a B-tree
+/// page whose header of `header_len` bytes starts at `offset`. Cells are
+/// packed against the end of the page, first cell last.
+fn place_cells(page: &mut [u8; PAGE_SIZE], offset: usize, header_len: usize, cells: &[Vec<u8>]) {
+    let count = u16::try_from(cells.len()).expect("cells fit on a page");
+    page[offset + 3..offset + 5].copy_from_slice(&count.to_be_bytes());
+    let mut content = PAGE_SIZE;
+    for (i, cell) in cells.iter().enumerate() {
+        content -= cell.len();
+        page[content..content + cell.len()].copy_from_slice(cell);
+        let pointer = offset + header_len + 2 * i;
+        let start = u16::try_from(content).expect("offsets fit in 16 bits");
+        page[pointer..pointer + 2].copy_from_slice(&start.to_be_bytes());
+    }
+    // A page of 4096 bytes never needs the special value 0 for 65536.
+    let start = u16::try_from(content).expect("offsets fit in 16 bits");
+    page[offset + 5..offset + 7].copy_from_slice(&start.to_be_bytes());
+}
+
+/// Writes the 100-byte database header at the start of page 1.
+fn write_header(page: &mut [u8; PAGE_SIZE], page_count: u32) {
+    let size = u16::try_from(PAGE_SIZE).expect("page size fits in 16 bits");
+    page[..16].copy_from_slice(b"SQLite format 3\0");
+    page[16..18].copy_from_slice(&size.to_be_bytes());
+    // Legacy journalling for both the write and read format versions.
+    page[18] = 1;
+    page[19] = 1;
+    // Payload fractions, which must be 64, 32 and 32.
+    page[21] = 64;
+    page[22] = 32;
+    page[23] = 32;
+    // File change counter, matched by the version-valid-for number below.
+    page[24..28].copy_from_slice(&1u32.to_be_bytes());
+    page[28..32].copy_from_slice(&page_count.to_be_bytes());
+    // Schema cookie.
+    page[40..44].copy_from_slice(&1u32.to_be_bytes());
+    // Schema format 4, the current one.
+    page[44..48].copy_from_slice(&4u32.to_be_bytes());
+    // Text encoding: UTF-8.
+    page[56..60].copy_from_slice(&1u32.to_be_bytes());
+    page[92..96].copy_from_slice(&1u32.to_be_bytes());
+    page[96..100].copy_from_slice(&SQLITE_VERSION.to_be_bytes());
+}
+
+/// Encodes a row in the record format.
+fn record(values: &[Value<'_>]) -> Vec<u8> {
+    let mut types = Vec::new();
+    let mut body = Vec::new();
+    for value in values {
+        let serial_type = match value {
+            Value::Null => 0,
+            Value::Integer(0) => 8,
+            Value::Integer(1) => 9,
+            Value::Integer(n) => {
+                let (serial_type, len) = match n {
+                    -0x80..0x80 => (1, 1),
+                    -0x8000..0x8000 => (2, 2),
+                    -0x80_0000..0x80_0000 => (3, 3),
+                    -0x8000_0000..0x8000_0000 => (4, 4),
+                    -0x8000_0000_0000..0x8000_0000_0000 => (5, 6),
+                    _ => (6, 8),
+                };
+                body.extend_from_slice(&n.to_be_bytes()[8 - len..]);
+                serial_type
+            }
+            Value::Text(text) => {
+                body.extend_from_slice(text.as_bytes());
+                13 + 2 * text.len() as u64
+            }
+            Value::Owned(text) => {
+                body.extend_from_slice(text.as_bytes());
+                13 + 2 * text.len() as u64
+            }
+        };
+        put_varint(&mut types, serial_type);
+    }
+    // The header length counts its own varint.
+    let mut header_len = types.len() + 1;
+    while varint_len(header_len as u64) + types.len() != header_len {
+        header_len = varint_len(header_len as u64) + types.len();
+    }
+    let mut out = Vec::with_capacity(header_len + body.len());
+    put_varint(&mut out, header_len as u64);
+    out.extend_from_slice(&types);
+    out.extend_from_slice(&body);
+    out
+}
+
+fn varint_len(value: u64) -> usize {
+    let mut buf = Vec::new();
+    put_varint(&mut buf, value);
+    buf.len()
+}
+
+/// Appends `value` as an `SQLite` varint: big-endian groups of seven bits, with
+/// the high bit set on all but the last, and a full ninth byte for values
+/// wider than 56 bits.
+fn put_varint(out: &mut Vec<u8>, value: u64) {
+    if value >> 56 != 0 {
+        let mut bytes = [0; 9];
+        bytes[8] = value.to_le_bytes()[0];
+        let mut rest = value >> 8;
+        for byte in bytes[..8].iter_mut().rev() {
+            *byte = (rest & 0x7F) as u8 | 0x80;
+            rest >>= 7;
+        }
+        out.extend_from_slice(&bytes);
+        return;
+    }
+    let mut groups = Vec::with_capacity(8);
+    let mut rest = value;
+    loop {
+        groups.push((rest & 0x7F) as u8);
+        rest >>= 7;
+        if rest == 0 {
+            break;
+        }
+    }
+    let last = groups.len() - 1;
+    for (i, group) in groups.into_iter().rev().enumerate() {
+        out.push(if i == last { group } else { group | 0x80 });
+    }
+}
+
+/// Reads a report written by [`write_sqlite_to`] from the bytes of the
+/// database.
+///
+/// The groups, their files, the scanned directories, the skipped files and
+/// the totals are read; the metadata of the files is not.
+///
+/// # Errors
+/// Returns an error of kind [`io::ErrorKind::InvalidData`] if `data` is not
+/// an `SQLite` database holding the tables of a report.
+pub(crate) fn read_sqlite(data: &[u8]) -> io::Result<DuplicateReport> {
+    let database = Reader::new(data)?;
+    let schema = database.rows(1)?;
+    let root = |table: &str| {
+        schema
+            .iter()
+            .find(|(_, row)| {
+                matches!(row.first(), Some(Column::Text(kind)) if kind == "table")
+                    && matches!(row.get(1), Some(Column::Text(name)) if name == table)
+            })
+            .and_then(|(_, row)| row.get(3)?.integer())
+            .and_then(|root| u32::try_from(root).ok())
+            .ok_or_else(|| malformed(&format!("no {table} table")))
+    };
+
+    let scan = database.rows(root("scan")?)?;
+    let scan = scan.first().map_or(&[][..], |(_, row)| row.as_slice());
+    let column = |row: &[Column], index: usize| row.get(index).unwrap_or(&Column::Null).clone();
+
+    let mut groups: Vec<(i64, DuplicateGroup)> = database
+        .rows(root("duplicate_groups")?)?
+        .into_iter()
+        .map(|(id, row)| {
+            let group = DuplicateGroup {
+                hash: column(&row, 1).text().unwrap_or_default(),
+                size: column(&row, 2).integer().map_or(0, i64::cast_unsigned),
+                paths: Vec::new(),
+            };
+            (id, group)
+        })
+        .collect();
+    for (_, row) in database.rows(root("files")?)? {
+        let (Some(group_id), Some(path)) = (column(&row, 1).integer(), column(&row, 2).text())
+        else {
+            return Err(malformed("file without a group or path"));
+        };
+        let index = groups
+            .binary_search_by_key(&group_id, |(id, _)| *id)
+            .map_err(|_| malformed(&format!("file in unknown group {group_id}")))?;
+        groups[index].1.paths.push(PathBuf::from(path));
+    }
+
+    let mut report = DuplicateReport::from_groups(groups.into_iter().map(|(_, g)| g).collect());
+    report.interrupted = column(scan, 4).integer().is_some_and(|n| n != 0);
+    if let Some(total) = column(scan, 6).integer() {
+        report.total_reclaimable_bytes = total.cast_unsigned();
+    }
+    report.roots = database
+        .rows(root("base_dirs")?)?
+        .into_iter()
+        .filter_map(|(_, row)| column(&row, 1).text().map(PathBuf::from))
+        .collect();
+    report.errors = database
+        .rows(root("errors")?)?
+        .into_iter()
+        .filter_map(|(_, row)| {
+            let path = column(&row, 1).text()?;
+            let error = column(&row, 2).text().unwrap_or_default();
+            Some((PathBuf::from(path), io::Error::other(error)))
+        })
+        .collect();
+    Ok(report)
+}
+
+fn malformed(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("not a duplicate report database: {what}"),
+    )
+}
+
+/// A column value in a row being read.
+#[derive(Debug, Clone)]
+enum Column {
+    Null,
+    Integer(i64),
+    Text(String),
+    /// A float or blob, which no column of a report holds.
+    Other,
+}
+
+impl Column {
+    fn integer(&self) -> Option<i64> {
+        match self {
+            Self::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn text(self) -> Option<String> {
+        match self {
+            Self::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+/// A database being read, held in memory.
+struct Reader<'a> {
+    data: &'a [u8],
+    page_size: usize,
+    /// The bytes of each page available to B-tree content, excluding the
+    /// space reserved at the end of every page.
+    usable: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> io::Result<Self> {
+        if data.len() < 100 || !data.starts_with(b"SQLite format 3\0") {
+            return Err(malformed("not an SQLite database"));
+        }
+        let page_size = match u16::from_be_bytes([data[16], data[17]]) {
+            1 => 65536,
+            size => usize::from(size),
+        };
+        if !page_size.is_power_of_two() || page_size < 512 {
+            return Err(malformed("invalid page size"));
+        }
+        let usable = page_size - usize::from(data[20]);
+        if usable < 480 {
+            return Err(malformed("invalid reserved space"));
+        }
+        Ok(Self {
+            data,
+            page_size,
+            usable,
+        })
+    }
+
+    /// Returns page `number`, counting from 1.
+    fn page(&self, number: u32) -> io::Result<&'a [u8]> {
+        let start = (number as usize)
+            .checked_sub(1)
+            .ok_or_else(|| malformed("page 0"))?
+            * self.page_size;
+        self.data
+            .get(start..start + self.page_size)
+            .ok_or_else(|| malformed(&format!("page {number} past the end")))
+    }
+
+    /// Returns every row of the table whose B-tree starts at page `root`, with
+    /// its rowid, in rowid order.
+    fn rows(&self, root: u32) -> io::Result<Vec<(i64, Vec<Column>)>> {
+        let mut rows = Vec::new();
+        let mut pending = vec![root];
+        // A malformed file could link pages into a cycle; no tree visits
+        // more pages than the file holds.
+        let mut budget = self.data.len() / self.page_size;
+        while let Some(number) = pending.pop() {
+            budget = budget
+                .checked_sub(1)
+                .ok_or_else(|| malformed("B-tree cycle"))?;
+            let page = self.page(number)?;
+            let offset = if number == 1 { 100 } else { 0 };
+            let header = &page[offset..];
+            let count = usize::from(u16::from_be_bytes([header[3], header[4]]));
+            let header_len = match header[0] {
+                LEAF_TABLE => 8,
+                INTERIOR_TABLE => 12,
+                kind => return Err(malformed(&format!("page {number} has type {kind}"))),
+            };
+            let pointers = header
+                .get(header_len..header_len + 2 * count)
+                .ok_or_else(|| malformed("cell pointers past the page"))?;
+            let cells = pointers
+                .chunks_exact(2)
+                .map(|pointer| usize::from(u16::from_be_bytes([pointer[0], pointer[1]])));
+            if header[0] == INTERIOR_TABLE {
+                let mut children: Vec<u32> = Vec::with_capacity(count + 1);
+                for cell in cells {
+                    let child = page
+                        .get(cell..cell + 4)
+                        .ok_or_else(|| malformed("cell past the page"))?;
+                    children.push(u32::from_be_bytes(child.try_into().expect("4 bytes")));
+                }
+                children.push(u32::from_be_bytes(
+                    header[8..12].try_into().expect("4 bytes"),
+                ));
+                // Popped from the end, so push the rightmost child first.
+                pending.extend(children.into_iter().rev());
+                continue;
+            }
+            for cell in cells {
+                let mut at = cell;
+                let payload_len = read_varint(page, &mut at)?;
+                let rowid = read_varint(page, &mut at)?.cast_signed();
+                let payload = self.payload(page, at, payload_len, &mut budget)?;
+                rows.push((rowid, columns(&payload)?));
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Returns a cell's payload of `len` bytes starting at `at` on `page`,
+    /// following its overflow pages.
+    fn payload(&self, page: &[u8], at: usize, len: u64, budget: &mut usize) -> io::Result<Vec<u8>> {
+        let len = usize::try_from(len).map_err(|_| malformed("payload too large"))?;
+        let max_local = self.usable - 35;
+        let min_local = (self.usable - 12) * 32 / 255 - 23;
+        let local = if len <= max_local {
+            len
+        } else {
+            let spill = min_local + (len - min_local) % (self.usable - 4);
+            if spill <= max_local {
+                spill
+            } else {
+                min_local
+            }
+        };
+        let mut payload = page
+            .get(at..at + local)
+            .ok_or_else(|| malformed("payload past the page"))?
+            .to_vec();
+        if local == len {
+            return Ok(payload);
+        }
+        let next = page
+            .get(at + local..at + local + 4)
+            .ok_or_else(|| malformed("overflow pointer past the page"))?;
+        let mut next = u32::from_be_bytes(next.try_into().expect("4 bytes"));
+        while payload.len() < len {
+            *budget = budget
+                .checked_sub(1)
+                .ok_or_else(|| malformed("overflow cycle"))?;
+            let overflow = self.page(next)?;
+            let take = (len - payload.len()).min(self.usable - 4);
+            payload.extend_from_slice(&overflow[4..4 + take]);
+            next = u32::from_be_bytes(overflow[..4].try_into().expect("4 bytes"));
+        }
+        Ok(payload)
+    }
+}
+
+/// Decodes a row in the record format.
+fn columns(record: &[u8]) -> io::Result<Vec<Column>> {
+    let mut at = 0;
+    let header_len = usize::try_from(read_varint(record, &mut at)?)
+        .map_err(|_| malformed("record header too large"))?;
+    let mut body = header_len;
+    let mut columns = Vec::new();
+    while at < header_len {
+        let serial_type = read_varint(record, &mut at)?;
+        let len = match serial_type {
+            0 | 8 | 9 => 0,
+            1 => 1,
+            2 => 2,
+            3 => 3,
+            4 => 4,
+            5 => 6,
+            6 | 7 => 8,
+            10 | 11 => return Err(malformed("reserved serial type")),
+            _ => {
+                usize::try_from((serial_type - 12) / 2).map_err(|_| malformed("value too large"))?
+            }
+        };
+        let bytes = record
+            .get(body..body + len)
+            .ok_or_else(|| malformed("value past the record"))?;
+        body += len;
+        columns.push(match serial_type {
+            0 => Column::Null,
+            8 => Column::Integer(0),
+            9 => Column::Integer(1),
+            1..=6 => {
+                // Sign-extend the big-endian bytes to 64 bits.
+                let sign = if bytes[0] & 0x80 == 0 { 0 } else { 0xFF };
+                let mut wide = [sign; 8];
+                wide[8 - len..].copy_from_slice(bytes);
+                Column::Integer(i64::from_be_bytes(wide))
+            }
+            n if n >= 13 && n % 2 == 1 => Column::Text(String::from_utf8_lossy(bytes).into_owned()),
+            _ => Column::Other,
+        });
+    }
+    Ok(columns)
+}
+
+/// Reads an `SQLite` varint from `data` at `*at`, advancing past it.
+fn read_varint(data: &[u8], at: &mut usize) -> io::Result<u64> {
+    let mut value = 0u64;
+    for i in 0..9 {
+        let byte = *data
+            .get(*at)
+            .ok_or_else(|| malformed("varint past the end"))?;
+        *at += 1;
+        if i == 8 {
+            return Ok(value << 8 | u64::from(byte));
+        }
+        value = value << 7 | u64::from(byte & 0x7F);
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DuplicateGroup;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_put_varint() {
+        let encode = |value| {
+            let mut out = Vec::new();
+            put_varint(&mut out, value);
+            out
+        };
+        assert_eq!(encode(0), [0]);
+        assert_eq!(encode(127), [0x7F]);
+        assert_eq!(encode(128), [0x81, 0x00]);
+        assert_eq!(encode(300), [0x82, 0x2C]);
+        assert_eq!(encode(u64::MAX), [0xFF; 9]);
+    }
+
+    #[test]
+    fn test_write_sqlite_spans_pages() {
+        // Enough files for several leaves and an interior page, and a path
+        // long enough to need overflow pages.
+        let long = format!("/{}", "x".repeat(10_000));
+        let mut paths: Vec<PathBuf> = (0..500)
+            .map(|i| PathBuf::from(format!("/data/copies/file-{i:04}.bin")))
+            .collect();
+        paths.push(PathBuf::from(&long));
+        let report = DuplicateReport::from_groups(vec![DuplicateGroup {
+            hash: "abc".to_string(),
+            size: 10,
+            paths,
+        }]);
+
+        let mut database = Cursor::new(Vec::new());
+        write_sqlite_to(&mut database, &report, "start", &[PathBuf::from("/data")])
+            .expect("write database");
+        let bytes = database.into_inner();
+
+        assert_eq!(bytes.len() % PAGE_SIZE, 0);
+        let page_count = u32::from_be_bytes(bytes[28..32].try_into().expect("4 bytes"));
+        assert_eq!(page_count as usize, bytes.len() / PAGE_SIZE);
+        // The schema leaf on page 1 lists the five tables.
+        assert_eq!(bytes[100], LEAF_TABLE);
+        assert_eq!(u16::from_be_bytes([bytes[103], bytes[104]]), 5);
+        // The files table has an interior root above its leaves.
+        // Only the empty errors table follows the root of the files table.
+        let files_root = page_count as usize - 1;
+        assert_eq!(bytes[(files_root - 1) * PAGE_SIZE], INTERIOR_TABLE);
+        let text = String::from_utf8_lossy(&bytes);
+        assert!(text.contains("CREATE TABLE files("));
+        assert!(text.contains("/data/copies/file-0499.bin"));
+        assert!(text.contains(&"x".repeat(OVERFLOW_CAPACITY)));
+    }
+}
@@ -0,0 +1,405 @@
+//! Detection of text files that are mostly, but not exactly, the same, such
+//! as two copies of a config file or a document that have drifted apart.
+//!
+//! Each file is split into words, and every run of [`SHINGLE_WORDS`]
+//! consecutive words is a shingle. The share of shingles two files have in
+//! common (their Jaccard similarity) is estimated from a `MinHash` signature:
+//! for each of 128 hash functions, the smallest hash of any of the file's
+//! shingles. Two files agree in each signature slot with a probability
+//! equal to their similarity, so only the signatures are compared. To avoid
+//! comparing every pair, signatures are cut into bands and only files that
+//! agree on a whole band are compared, which finds pairs at least 30%
+//! similar with near certainty.
+
+use crate::{
+    build_pool, collect_files, format_size, in_pool, into_errors, is_cancelled, record_error,
+    Console, GlobError, ScanConfig, ScanOptions, Stage,
+};
+use chrono::Local;
+use log::debug;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::Hasher;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use twox_hash::XxHash3_64;
+
+/// The percentage of shingles two files must share by default to be
+/// reported as similar.
+pub const DEFAULT_MIN_SIMILARITY: u32 = 90;
+
+/// How many consecutive words make up a shingle.
+pub const SHINGLE_WORDS: usize = 3;
+
+/// How many hash functions make up a signature.
+const SIGNATURE_LEN: usize = 128;
+
+/// How many signature slots make up a band.
+const BAND_ROWS: usize = 2;
+
+type Signature = [u64; SIGNATURE_LEN];
+
+/// A text file with what is needed to compare it.
+struct Signed {
+    text: SimilarText,
+    /// The hash of the whole file, to tell exact copies apart.
+    digest: u64,
+    signature: Box<Signature>,
+}
+
+/// A text file that is part of a similar pair.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimilarText {
+    pub path: PathBuf,
+    /// The size of the file in bytes.
+    pub size: u64,
+}
+
+/// Two text files that share most of their shingles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimilarTextPair {
+    /// The two files, in path order.
+    pub files: [SimilarText; 2],
+    /// The estimated percentage of shingles the files share, rounded down.
+    /// Only files with the same contents are 100% similar.
+    pub similarity: u32,
+}
+
+/// The result of a search for similar text files.
+#[derive(Debug)]
+pub struct SimilarTexts {
+    /// The directories searched.
+    pub roots: Vec<PathBuf>,
+    /// The percentage of shingles two files had to share to be reported.
+    pub min_similarity: u32,
+    /// How many text files were compared.
+    pub files: usize,
+    /// The similar pairs, most similar first, then by path.
+    pub pairs: Vec<SimilarTextPair>,
+    /// Files that could not be read, with the error raised.
+    pub errors: Vec<(PathBuf, io::Error)>,
+    /// Whether the search was cancelled before every file was read.
+    pub interrupted: bool,
+}
+
+/// Finds pairs of text files in `dirs` that share at least `min_similarity`
+/// percent of their shingles.
+///
+/// Files are chosen as in any scan, by every filter of `options` that
+/// applies to the walk and by size. A file counts as text if it holds no
+/// NUL byte; others, and files without any words, are skipped. Each file is
+/// read in full, so setting [`ScanOptions::max_size`] keeps large binary
+/// files from slowing the search down.
+///
+/// # Errors
+/// Returns an error if any pattern in `options` is invalid.
+pub fn find_similar_texts(
+    dirs: &[PathBuf],
+    options: &ScanOptions,
+    min_similarity: u32,
+) -> Result<SimilarTexts, GlobError> {
+    let config = ScanConfig::new(options)?;
+    let console = Console::new(config.quiet, config.progress_json, config.progress);
+    let errors = Mutex::new(Vec::new());
+    let files: Vec<_> = collect_files(dirs, &config.walk, config.cancel, &errors, &mut Vec::new())
+        .into_iter()
+        .filter(|file| {
+            let size = file.meta.len();
+            size > 0
+                && config.min_size.is_none_or(|min| size >= min)
+                && config.max_size.is_none_or(|max| size <= max)
+        })
+        .collect();
+    console.status(&format!(
+        "{} files identified across {} directories",
+        files.len(),
+        dirs.len()
+    ));
+
+    let progress = console.bar(files.len() as u64, Stage::TextHash, "Reading text files");
+    let pool = build_pool(config.threads);
+    let signed: Vec<Signed> = in_pool(pool.as_ref(), || {
+        files
+            .into_par_iter()
+            .filter_map(|file| {
+                if is_cancelled(config.cancel) {
+                    return None;
+                }
+                let data = config.read(&file.path, || fs::read(&file.path));
+                progress.inc(1);
+                match data {
+                    Ok(data) => {
+                        config.pace(data.len() as u64);
+                        let Some(signature) = signature(&data) else {
+                            debug!("Skipping {}: not text", file.path.display());
+                            return None;
+                        };
+                        let text = SimilarText {
+                            size: file.meta.len(),
+                            path: file.path,
+                        };
+                        Some(Signed {
+                            text,
+                            digest: XxHash3_64::oneshot(&data),
+                            signature,
+                        })
+                    }
+                    Err(e) => {
+                        record_error(&errors, &file.path, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    });
+    progress.finish_with_message("Text files read");
+
+    let count = signed.len();
+    let pairs = similar_pairs(&signed, min_similarity);
+    console.complete(pairs.len());
+    Ok(SimilarTexts {
+        roots: dirs.to_vec(),
+        min_similarity,
+        files: count,
+        pairs,
+        errors: into_errors(errors),
+        interrupted: is_cancelled(config.cancel),
+    })
+}
+
+/// Returns the `MinHash` signature of the shingles of `data`, or `None` if it
+/// is not text or holds no words.
+fn signature(data: &[u8]) -> Option<Box<Signature>> {
+    if data.contains(&0) {
+        return None;
+    }
+    let text = String::from_utf8_lossy(data);
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.is_empty() {
+        return None;
+    }
+    // A file shorter than a shingle is a single shingle.
+    let size = SHINGLE_WORDS.min(words.len());
+    let mut signature = Box::new([u64::MAX; SIGNATURE_LEN]);
+    for shingle in words.windows(size) {
+        let mut hasher = XxHash3_64::new();
+        for word in shingle {
+            hasher.write(word.as_bytes());
+            hasher.write_u8(0);
+        }
+        let hash = hasher.finish();
+        for (i, slot) in signature.iter_mut().enumerate() {
+            *slot = (*slot).min(mix(hash ^ SEEDS[i]));
+        }
+    }
+    Some(signature)
+}
+
+/// The value each shingle hash is combined with before scrambling, one for
+/// each hash function of a signature.
+const SEEDS: Signature = {
+    let mut seeds = [0; SIGNATURE_LEN];
+    let mut i = 0;
+    while i < SIGNATURE_LEN {
+        seeds[i] = mix(i as u64 + 1);
+        i += 1;
+    }
+    seeds
+};
+
+/// Scrambles the bits of `x` (the `SplitMix64` finalizer), so that each seed
+/// gives an unrelated hash function.
+const fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+/// Returns the pairs of `files` whose signatures agree in at least
+/// `min_similarity` percent of their slots.
+fn similar_pairs(files: &[Signed], min_similarity: u32) -> Vec<SimilarTextPair> {
+    let mut buckets: HashMap<(usize, &[u64]), Vec<usize>> = HashMap::new();
+    for (i, file) in files.iter().enumerate() {
+        for (band, rows) in file.signature.chunks(BAND_ROWS).enumerate() {
+            buckets.entry((band, rows)).or_default().push(i);
+        }
+    }
+    let mut candidates = HashSet::new();
+    for members in buckets.values() {
+        for (n, &a) in members.iter().enumerate() {
+            for &b in &members[n + 1..] {
+                candidates.insert((a, b));
+            }
+        }
+    }
+
+    let mut pairs: Vec<SimilarTextPair> = candidates
+        .into_iter()
+        .filter_map(|(a, b)| {
+            let (a, b) = (&files[a], &files[b]);
+            let agreeing = a
+                .signature
+                .iter()
+                .zip(b.signature.iter())
+                .filter(|(x, y)| x == y)
+                .count();
+            let estimate = u32::try_from(agreeing * 100 / SIGNATURE_LEN).unwrap_or(100);
+            // Files that differ at all are at most 99% similar, however
+            // closely their signatures agree.
+            let similarity = if a.digest == b.digest {
+                100
+            } else {
+                estimate.min(99)
+            };
+            if similarity < min_similarity {
+                return None;
+            }
+            let mut texts = [a.text.clone(), b.text.clone()];
+            texts.sort_by(|x, y| x.path.cmp(&y.path));
+            Some(SimilarTextPair {
+                files: texts,
+                similarity,
+            })
+        })
+        .collect();
+    pairs.sort_by(|a, b| {
+        b.similarity
+            .cmp(&a.similarity)
+            .then_with(|| a.files[0].path.cmp(&b.files[0].path))
+            .then_with(|| a.files[1].path.cmp(&b.files[1].path))
+    });
+    pairs
+}
+
+/// Writes the pairs of similar text files as plain text, most similar
+/// first.
+///
+/// # Errors
+/// Returns any error raised by `writer`.
+pub fn write_similar_texts_to<W: Write>(
+    writer: &mut W,
+    similar: &SimilarTexts,
+    start_time: &str,
+) -> io::Result<()> {
+    let username = whoami::username();
+    let end_time = Local::now().format("%Y%m%d %H:%M:%S").to_string();
+
+    writeln!(writer, "Similar Text Files Report")?;
+    writeln!(writer, "Generated by: {username}")?;
+    writeln!(writer, "Start Time: {start_time}")?;
+    writeln!(writer, "End Time: {end_time}")?;
+    if similar.roots.len() == 1 {
+        writeln!(writer, "Base Directory: {}", similar.roots[0].display())?;
+    } else {
+        writeln!(writer, "Base Directories:")?;
+        for dir in &similar.roots {
+            writeln!(writer, " - {}", dir.display())?;
+        }
+    }
+    writeln!(writer)?;
+
+    if similar.interrupted {
+        writeln!(
+            writer,
+            "Scan Interrupted: the results below are incomplete."
+        )?;
+    }
+    writeln!(
+        writer,
+        "Text Files Compared: {} (similar when at least {}% of their {SHINGLE_WORDS}-word \
+         sequences are shared)",
+        similar.files, similar.min_similarity
+    )?;
+    writeln!(writer, "Similar Pairs: {}", similar.pairs.len())?;
+    if !similar.errors.is_empty() {
+        writeln!(
+            writer,
+            "Files Skipped Due To Errors: {}",
+            similar.errors.len()
+        )?;
+    }
+    writeln!(writer)?;
+
+    for pair in &similar.pairs {
+        writeln!(writer, "{}% similar:", pair.similarity)?;
+        for file in &pair.files {
+            writeln!(
+                writer,
+                "{} ({})",
+                file.path.display(),
+                format_size(file.size)
+            )?;
+        }
+        writeln!(writer)?;
+    }
+
+    if !similar.errors.is_empty() {
+        writeln!(writer, "Skipped Files:")?;
+        for (path, error) in &similar.errors {
+            writeln!(writer, "{}: {error}", path.display())?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(name: &str, body: &str) -> Signed {
+        Signed {
+            text: SimilarText {
+                path: PathBuf::from(name),
+                size: body.len() as u64,
+            },
+            digest: XxHash3_64::oneshot(body.as_bytes()),
+            signature: signature(body.as_bytes()).expect("text"),
+        }
+    }
+
+    #[test]
+    fn test_similar_pairs_estimates_shared_shingles() {
+        let words: Vec<String> = (0..400).map(|i| format!("word{i}")).collect();
+        let original = words.join(" ");
+        // Changing one word in forty removes about one shingle in thirteen.
+        let edited: Vec<String> = words
+            .iter()
+            .enumerate()
+            .map(|(i, w)| {
+                if i % 40 == 0 {
+                    "changed".into()
+                } else {
+                    w.clone()
+                }
+            })
+            .collect();
+        let unrelated: Vec<String> = (0..400).map(|i| format!("other{i}")).collect();
+        let files = [
+            text("b.conf", &original),
+            text("a.conf", &edited.join("\n")),
+            text("c.conf", &unrelated.join(" ")),
+        ];
+
+        let pairs = similar_pairs(&files, 70);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].files[0].path, PathBuf::from("a.conf"));
+        assert_eq!(pairs[0].files[1].path, PathBuf::from("b.conf"));
+        assert!(
+            (75..100).contains(&pairs[0].similarity),
+            "{}",
+            pairs[0].similarity
+        );
+        assert!(similar_pairs(&files, 100).is_empty());
+
+        assert!(signature(b"binary\0data").is_none());
+        assert!(signature(b" \n\t").is_none());
+        let short = [text("x", "just two"), text("y", "just  two\n")];
+        assert_eq!(similar_pairs(&short, 1)[0].similarity, 99);
+        let copies = [text("x", "just two"), text("y", "just two")];
+        assert_eq!(similar_pairs(&copies, 100)[0].similarity, 100);
+    }
+}
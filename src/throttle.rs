@@ -0,0 +1,76 @@
+//! A limit on how fast a scan reads files, so that it can run on a busy
+//! server without starving other work of disk bandwidth.
+//!
+//! Every read made while hashing is accounted for after the fact: the reader
+//! is given the next free slot of time long enough to read that much at the
+//! limit, and sleeps until the slot ends. Slots are handed out one after
+//! another across all threads, so the limit holds for the scan as a whole
+//! however many files are read at once.
+
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Paces reads to at most a number of bytes per second.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    bytes_per_second: f64,
+    /// When the time given to reads so far runs out.
+    busy_until: Mutex<Instant>,
+}
+
+impl Throttle {
+    /// Creates a limit of `bytes_per_second`, which must not be zero.
+    pub(crate) fn new(bytes_per_second: u64) -> Self {
+        Self {
+            #[allow(clippy::cast_precision_loss)]
+            bytes_per_second: bytes_per_second.max(1) as f64,
+            busy_until: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Accounts for `bytes` just read, sleeping until reading them would
+    /// have taken at the limit.
+    pub(crate) fn consume(&self, bytes: u64) {
+        if let Some(wait) = self.reserve(bytes, Instant::now()) {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// Reserves the time to read `bytes`, returning how long to wait at
+    /// `now` for it to pass.
+    fn reserve(&self, bytes: u64, now: Instant) -> Option<Duration> {
+        #[allow(clippy::cast_precision_loss)]
+        let time = Duration::from_secs_f64(bytes as f64 / self.bytes_per_second);
+        let mut busy_until = self
+            .busy_until
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        // Time spent not reading is not saved up for a later burst.
+        *busy_until = (*busy_until).max(now) + time;
+        Some(busy_until.duration_since(now)).filter(|wait| !wait.is_zero())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttle_paces_reads() {
+        let throttle = Throttle::new(1000);
+        let start = Instant::now();
+        assert_eq!(
+            throttle.reserve(500, start),
+            Some(Duration::from_millis(500))
+        );
+        // A second read waits for the first one's time as well.
+        assert_eq!(throttle.reserve(500, start), Some(Duration::from_secs(1)));
+        // After a pause, reading starts again from the present.
+        let later = start + Duration::from_secs(5);
+        assert_eq!(
+            throttle.reserve(250, later),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(throttle.reserve(0, later + Duration::from_secs(1)), None);
+    }
+}
@@ -0,0 +1,270 @@
+//! Moving files to the desktop trash instead of deleting them.
+//!
+//! On Linux and other Unix desktops this follows the freedesktop.org Trash
+//! specification, so trashed duplicates can be restored from the file
+//! manager: each file is renamed into the `files` directory of a trash and
+//! described by a `.trashinfo` file in `info` recording where it came from.
+//! Files on the home filesystem go to `$XDG_DATA_HOME/Trash`; files on other
+//! filesystems go to a `.Trash-$UID` directory at the top of their own
+//! filesystem, since a rename cannot cross filesystems and copying a file
+//! into the trash would not free any space. On macOS files are moved to
+//! `~/.Trash`. Other platforms are not supported yet.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Moves `path` to the current user's trash, returning where it now is.
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn move_to_trash(path: &Path) -> io::Result<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    let path = absolute(path)?;
+    let home = home_trash()?;
+    let dev = fs::symlink_metadata(&path)?.dev();
+    // The home trash may not exist yet; its nearest existing ancestor is on
+    // the same filesystem it will be.
+    let home_dev = home
+        .ancestors()
+        .find_map(|dir| fs::metadata(dir).ok())
+        .map(|meta| meta.dev());
+    if home_dev == Some(dev) {
+        return trash_into(&path, &home, None);
+    }
+    let top = mount_top(&path, dev);
+    // SAFETY: getuid has no preconditions and cannot fail.
+    let uid = unsafe { libc::getuid() };
+    trash_into(&path, &top.join(format!(".Trash-{uid}")), Some(&top))
+}
+
+/// Moves `path` to the current user's trash, returning where it now is.
+#[cfg(target_os = "macos")]
+pub(crate) fn move_to_trash(path: &Path) -> io::Result<PathBuf> {
+    let home = std::env::var_os("HOME")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+    let trash = Path::new(&home).join(".Trash");
+    let name = file_name(path)?.to_string_lossy().into_owned();
+    let mut target = trash.join(&name);
+    let mut n = 2;
+    while fs::symlink_metadata(&target).is_ok() {
+        target = trash.join(format!("{name} {n}"));
+        n += 1;
+    }
+    fs::rename(path, &target)?;
+    Ok(target)
+}
+
+/// Moves `path` to the current user's trash, returning where it now is.
+#[cfg(not(unix))]
+pub(crate) fn move_to_trash(_path: &Path) -> io::Result<PathBuf> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the trash is not supported on this platform",
+    ))
+}
+
+/// Moves a file returned by [`move_to_trash`] back to `original`, which
+/// must not exist, and removes its `.trashinfo` file if it has one.
+pub(crate) fn restore_from_trash(trashed: &Path, original: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(original).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "another file now exists at the original path",
+        ));
+    }
+    fs::rename(trashed, original)?;
+    if let Some(info) = info_file(trashed) {
+        let _ = fs::remove_file(info);
+    }
+    Ok(())
+}
+
+/// Returns the `.trashinfo` file describing a file in a freedesktop.org trash.
+fn info_file(trashed: &Path) -> Option<PathBuf> {
+    let files = trashed.parent()?;
+    if files.file_name()? != "files" {
+        return None;
+    }
+    let mut name = trashed.file_name()?.to_os_string();
+    name.push(".trashinfo");
+    Some(files.parent()?.join("info").join(name))
+}
+
+#[cfg(unix)]
+fn file_name(path: &Path) -> io::Result<&std::ffi::OsStr> {
+    path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))
+}
+
+/// Returns `path` made absolute without resolving a symbolic link at its end,
+/// so a trashed link is recorded under its own name.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn absolute(path: &Path) -> io::Result<PathBuf> {
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    Ok(fs::canonicalize(parent)?.join(file_name(path)?))
+}
+
+/// Returns `$XDG_DATA_HOME/Trash`, defaulting to `~/.local/share/Trash`.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn home_trash() -> io::Result<PathBuf> {
+    if let Some(data) = std::env::var_os("XDG_DATA_HOME").map(PathBuf::from) {
+        if data.is_absolute() {
+            return Ok(data.join("Trash"));
+        }
+    }
+    let home = std::env::var_os("HOME")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+    Ok(Path::new(&home).join(".local/share/Trash"))
+}
+
+/// Returns the top directory of the filesystem `dev` that holds `path`.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn mount_top(path: &Path, dev: u64) -> PathBuf {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut top = path.parent().unwrap_or(path);
+    for dir in path.ancestors().skip(2) {
+        match fs::metadata(dir) {
+            Ok(meta) if meta.dev() == dev => top = dir,
+            _ => break,
+        }
+    }
+    top.to_path_buf()
+}
+
+/// Moves `path` into `trash`, recording it relative to `top` when the trash
+/// belongs to another filesystem than the home directory.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn trash_into(path: &Path, trash: &Path, top: Option<&Path>) -> io::Result<PathBuf> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    let files = trash.join("files");
+    let info = trash.join("info");
+    for dir in [&files, &info] {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+    }
+    let original = top
+        .and_then(|top| path.strip_prefix(top).ok())
+        .unwrap_or(path);
+    let (info_path, trashed) = reserve(&files, &info, file_name(path)?, original)?;
+    if let Err(e) = fs::rename(path, &trashed) {
+        let _ = fs::remove_file(&info_path);
+        return Err(e);
+    }
+    Ok(trashed)
+}
+
+/// Claims a free name in the trash by creating its `.trashinfo` file, the
+/// atomic step the specification relies on, and returns the info file and
+/// the path the trashed file should be renamed to.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn reserve(
+    files: &Path,
+    info: &Path,
+    name: &std::ffi::OsStr,
+    original: &Path,
+) -> io::Result<(PathBuf, PathBuf)> {
+    use std::io::Write;
+
+    let contents = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(original),
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+    for n in 1u32.. {
+        let mut candidate = name.to_os_string();
+        if n > 1 {
+            candidate.push(format!(".{n}"));
+        }
+        let trashed = files.join(&candidate);
+        if fs::symlink_metadata(&trashed).is_ok() {
+            continue;
+        }
+        candidate.push(".trashinfo");
+        let info_path = info.join(candidate);
+        match fs::File::options()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(mut file) => {
+                if let Err(e) = file.write_all(contents.as_bytes()) {
+                    let _ = fs::remove_file(&info_path);
+                    return Err(e);
+                }
+                return Ok((info_path, trashed));
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::other("no free name left in the trash"))
+}
+
+/// Percent-encodes a path for the `Path=` key, leaving `/` and unreserved
+/// characters as they are.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn percent_encode(path: &Path) -> String {
+    use std::fmt::Write;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+#[cfg(all(test, unix, not(target_os = "macos")))]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_trash_into_writes_trash_info() {
+        let dir = tempdir().expect("create temp dir");
+        let trash = dir.path().join("Trash");
+        let first = dir.path().join("my photo.jpg");
+        fs::write(&first, "first").expect("write file");
+        let trashed = trash_into(&first, &trash, None).expect("trash file");
+        assert_eq!(trashed, trash.join("files/my photo.jpg"));
+        let nested = dir.path().join("nested");
+        fs::create_dir(&nested).expect("create dir");
+        let second = nested.join("my photo.jpg");
+        fs::write(&second, "second").expect("write file");
+        trash_into(&second, &trash, Some(dir.path())).expect("trash file");
+
+        assert!(!first.exists() && !second.exists());
+        let files = trash.join("files");
+        assert_eq!(
+            fs::read(files.join("my photo.jpg")).expect("read"),
+            b"first"
+        );
+        assert_eq!(
+            fs::read(files.join("my photo.jpg.2")).expect("read"),
+            b"second"
+        );
+        let info = fs::read_to_string(trash.join("info/my photo.jpg.trashinfo")).expect("read");
+        assert!(info.starts_with("[Trash Info]\n"));
+        assert!(info.contains(&format!("Path={}\n", percent_encode(&first))));
+        assert!(info.contains("my%20photo.jpg"));
+        // Trashes on other filesystems record paths relative to their top directory.
+        let info = fs::read_to_string(trash.join("info/my photo.jpg.2.trashinfo")).expect("read");
+        assert!(info.contains("Path=nested/my%20photo.jpg\n"));
+
+        restore_from_trash(&files.join("my photo.jpg.2"), &second).expect("restore file");
+        assert_eq!(fs::read(&second).expect("read"), b"second");
+        assert!(!trash.join("info/my photo.jpg.2.trashinfo").exists());
+        assert!(restore_from_trash(&files.join("my photo.jpg"), &second).is_err());
+    }
+}
@@ -0,0 +1,751 @@
+//! A journal of the files changed by destructive actions, and the restore
+//! pass that undoes the most recent one.
+//!
+//! Each confirmed delete, trash, hard link or symbolic link pass appends one
+//! line to the journal: a JSON object with the run's id and time and an entry
+//! for every changed file, giving the action, the path, the copy that was
+//! kept, the content hash and, for the trash, where the file was moved to.
+//! Every changed file had a kept copy with identical content, so every action
+//! can be undone: trashed files are moved back, and deleted or linked files
+//! are recreated by copying the kept copy, once its hash has been checked to
+//! still match, and given back the modification time and permissions the
+//! journal recorded for them. A restore appends a marker line naming the run it undid, so
+//! the next restore moves on to the run before it.
+//!
+//! Paths that are not valid UTF-8 cannot be written to the journal and are
+//! left out with a warning.
+
+use crate::cache::{algo_name, parse_algo};
+use crate::dedupe::{DeleteSummary, FileAttributes, LinkedFile};
+use crate::json::{self, Value};
+use crate::{full_hash, trash, HashAlgo};
+use chrono::Local;
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Version of the journal line format. Bump it whenever the layout changes.
+pub const UNDO_VERSION: u64 = 1;
+
+/// File name the command-line tool uses for its journal by default.
+pub const DEFAULT_UNDO_LOG: &str = "duplicate_finder_undo.jsonl";
+
+/// The change made to a file, which decides how it is undone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UndoAction {
+    /// The file was deleted; restoring copies the kept file back.
+    Delete,
+    /// The file was moved to the trash; restoring moves it back.
+    Trash,
+    /// The file was replaced by a hard link; restoring makes it a separate copy again.
+    Hardlink,
+    /// The file was replaced by a symbolic link; restoring makes it a regular file again.
+    Symlink,
+}
+
+impl UndoAction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Delete => "delete",
+            Self::Trash => "trash",
+            Self::Hardlink => "hardlink",
+            Self::Symlink => "symlink",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "delete" => Some(Self::Delete),
+            "trash" => Some(Self::Trash),
+            "hardlink" => Some(Self::Hardlink),
+            "symlink" => Some(Self::Symlink),
+            _ => None,
+        }
+    }
+}
+
+/// One changed file, as recorded in the journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndoEntry {
+    /// What was done to the file.
+    pub action: UndoAction,
+    /// The duplicate that was changed.
+    pub path: PathBuf,
+    /// The copy with the same content that was left in place.
+    pub kept: PathBuf,
+    /// Where a trashed file was moved to.
+    pub trashed_to: Option<PathBuf>,
+    /// The file's size in bytes.
+    pub size: u64,
+    /// The algorithm that produced `hash`.
+    pub hash_algo: HashAlgo,
+    /// The file's full-content hash.
+    pub hash: String,
+    /// The modification time and permissions the file had, if they could
+    /// be read before it was changed.
+    pub attributes: Option<FileAttributes>,
+}
+
+/// A run recorded in the journal that has not been restored yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndoOperation {
+    /// Identifies the run; the local time it was recorded.
+    pub id: String,
+    /// The files it changed, in the order they were changed.
+    pub entries: Vec<UndoEntry>,
+}
+
+/// Builds the journal entries for a confirmed [`delete_duplicates`] or
+/// [`trash_duplicates`] pass, hashing each kept copy with `hash_algo`.
+///
+/// Files whose kept copy cannot be read are left out with a warning, since
+/// they could not be restored anyway.
+///
+/// [`delete_duplicates`]: crate::dedupe::delete_duplicates
+/// [`trash_duplicates`]: crate::dedupe::trash_duplicates
+#[must_use]
+pub fn delete_entries(summary: &DeleteSummary, hash_algo: HashAlgo) -> Vec<UndoEntry> {
+    let action = if summary.trashed {
+        UndoAction::Trash
+    } else {
+        UndoAction::Delete
+    };
+    let mut hashes = KeptHashes::new(hash_algo);
+    summary
+        .deleted
+        .iter()
+        .filter_map(|file| {
+            Some(UndoEntry {
+                action,
+                path: file.path.clone(),
+                kept: file.kept.clone(),
+                trashed_to: file.trashed_to.clone(),
+                size: file.size,
+                hash_algo,
+                hash: hashes.get(&file.kept)?,
+                attributes: file.attributes,
+            })
+        })
+        .collect()
+}
+
+/// Builds the journal entries for a confirmed hard or symbolic link pass,
+/// hashing each kept copy with `hash_algo`. `action` must be
+/// [`UndoAction::Hardlink`] or [`UndoAction::Symlink`].
+#[must_use]
+pub fn link_entries(
+    action: UndoAction,
+    linked: &[LinkedFile],
+    hash_algo: HashAlgo,
+) -> Vec<UndoEntry> {
+    let mut hashes = KeptHashes::new(hash_algo);
+    linked
+        .iter()
+        .filter_map(|file| {
+            Some(UndoEntry {
+                action,
+                path: file.path.clone(),
+                kept: file.target.clone(),
+                trashed_to: None,
+                size: file.size,
+                hash_algo,
+                hash: hashes.get(&file.target)?,
+                attributes: file.attributes,
+            })
+        })
+        .collect()
+}
+
+/// Full hashes of kept copies, computed once per copy.
+///
+/// The kept copies are hashed again rather than trusting the report's
+/// hashes, which may have been made with another algorithm or before the
+/// copies were last changed.
+struct KeptHashes {
+    hash_algo: HashAlgo,
+    hashes: HashMap<PathBuf, Option<String>>,
+}
+
+impl KeptHashes {
+    fn new(hash_algo: HashAlgo) -> Self {
+        Self {
+            hash_algo,
+            hashes: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, kept: &Path) -> Option<String> {
+        let hash_algo = self.hash_algo;
+        self.hashes
+            .entry(kept.to_path_buf())
+            .or_insert_with(|| match full_hash(kept, hash_algo) {
+                Ok(hash) => Some(hash),
+                Err(e) => {
+                    warn!("Not journaling copies of {}: {e}", kept.display());
+                    None
+                }
+            })
+            .clone()
+    }
+}
+
+/// Appends a run changing `entries` to the journal at `journal`, creating it
+/// if needed, and returns the run's id. Nothing is written for an empty run.
+///
+/// # Errors
+/// Returns any I/O error raised while writing the journal.
+pub fn record(journal: &Path, entries: &[UndoEntry]) -> io::Result<Option<String>> {
+    if entries.is_empty() {
+        return Ok(None);
+    }
+    let now = Local::now();
+    let id = now.format("%Y%m%dT%H%M%S%.6f").to_string();
+    let mut line = format!(
+        "{{\"version\":{UNDO_VERSION},\"id\":{},\"time\":{},\"entries\":[",
+        json::quote(&id),
+        json::quote(&now.to_rfc3339()),
+    );
+    let mut written = 0;
+    for entry in entries {
+        let (Some(path), Some(kept)) = (entry.path.to_str(), entry.kept.to_str()) else {
+            warn!("Not journaling {}: path is not UTF-8", entry.path.display());
+            continue;
+        };
+        let trashed_to = match entry.trashed_to.as_deref().map(Path::to_str) {
+            None => "null".to_string(),
+            Some(Some(trashed)) => json::quote(trashed),
+            Some(None) => {
+                warn!("Not journaling {}: path is not UTF-8", entry.path.display());
+                continue;
+            }
+        };
+        let separator = if written == 0 { "" } else { "," };
+        let _ = write!(
+            line,
+            "{separator}{{\"action\":{},\"path\":{},\"kept\":{},\"trashed_to\":{trashed_to},\"size\":{},\"algo\":{},\"hash\":{}",
+            json::quote(entry.action.as_str()),
+            json::quote(path),
+            json::quote(kept),
+            entry.size,
+            json::quote(algo_name(entry.hash_algo)),
+            json::quote(&entry.hash),
+        );
+        if let Some(attributes) = &entry.attributes {
+            write_attributes(&mut line, attributes);
+        }
+        line.push('}');
+        written += 1;
+    }
+    line.push_str("]}\n");
+    append(journal, &line)?;
+    info!(
+        "Recorded {written} changed files as run {id} in {}",
+        journal.display()
+    );
+    Ok(Some(id))
+}
+
+/// Appends the members recording `attributes` to the entry being written in
+/// `line`: the modification time in nanoseconds since the Unix epoch, the
+/// Unix permission bits and whether the file was read-only.
+fn write_attributes(line: &mut String, attributes: &FileAttributes) {
+    let nanos = |time: SystemTime| {
+        let nanos = time.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+        u64::try_from(nanos).ok()
+    };
+    let number = |value: Option<u64>| value.map_or_else(|| "null".to_string(), |n| n.to_string());
+    let _ = write!(
+        line,
+        ",\"modified_ns\":{},\"mode\":{},\"readonly\":{}",
+        number(attributes.modified.and_then(nanos)),
+        number(attributes.mode.map(u64::from)),
+        attributes.readonly,
+    );
+}
+
+/// Returns the most recent run in the journal that has not been restored.
+///
+/// Lines that cannot be parsed, or that were written by another
+/// [`UNDO_VERSION`], are skipped with a warning.
+///
+/// # Errors
+/// Returns any I/O error raised while reading the journal, including the
+/// journal not existing.
+pub fn last_operation(journal: &Path) -> io::Result<Option<UndoOperation>> {
+    let text = fs::read_to_string(journal)?;
+    let mut operations = Vec::new();
+    let mut restored = HashSet::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_line(line) {
+            Ok(Line::Operation(operation)) => operations.push(operation),
+            Ok(Line::Restored(id)) => {
+                restored.insert(id);
+            }
+            Err(reason) => warn!(
+                "Skipping line {} of {}: {reason}",
+                number + 1,
+                journal.display()
+            ),
+        }
+    }
+    Ok(operations
+        .into_iter()
+        .rev()
+        .find(|operation| !restored.contains(&operation.id)))
+}
+
+/// The outcome of [`restore_last`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RestoreSummary {
+    /// Whether the run only reported what it would do.
+    pub dry_run: bool,
+    /// The run that was undone, or `None` if every run was already restored.
+    pub operation: Option<String>,
+    /// Files put back in place.
+    pub restored: Vec<PathBuf>,
+    /// Files that could not be put back, with the reason.
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl RestoreSummary {
+    /// Writes a human-readable description of the restore to `writer`.
+    ///
+    /// # Errors
+    /// Returns any I/O error raised by the writer.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let Some(id) = &self.operation else {
+            return writeln!(writer, "Nothing to restore.");
+        };
+        if self.dry_run {
+            writeln!(writer, "Restoring run {id} (dry run, no files changed):")?;
+        } else {
+            writeln!(writer, "Restored run {id}:")?;
+        }
+        for path in &self.restored {
+            writeln!(writer, "{}", path.display())?;
+        }
+        for (path, reason) in &self.failed {
+            writeln!(writer, "Failed {}: {reason}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Undoes the most recent unrestored run in the journal at `journal`.
+///
+/// Files are restored in the reverse order they were changed. A file that
+/// cannot be restored is recorded in the summary and the others are still
+/// processed; nothing is ever overwritten except a hard or symbolic link
+/// that the run created and that still points at the kept copy, so a link
+/// replaced since by another file is left alone. On systems other than Unix
+/// a hard link cannot be told apart from another file, so it is not
+/// restored while it exists. Unless `dry_run` is set, the run is then marked as
+/// restored, even if some files failed, so the next call moves on to the
+/// run before it.
+///
+/// # Errors
+/// Returns any I/O error raised while reading or appending to the journal.
+///
+/// # Example
+/// ```
+/// use duplicate_file_finder::dedupe::{delete_duplicates, KeepStrategy};
+/// use duplicate_file_finder::undo::{delete_entries, record, restore_last};
+/// use duplicate_file_finder::{find_duplicates_report, HashAlgo};
+/// use tempfile::tempdir;
+///
+/// fn demo() -> std::io::Result<()> {
+///     let dir = tempdir()?;
+///     std::fs::write(dir.path().join("a.txt"), b"same")?;
+///     std::fs::write(dir.path().join("b.txt"), b"same")?;
+///     let report = find_duplicates_report(dir.path());
+///     let summary = delete_duplicates(&report, KeepStrategy::First, false);
+///     let journal = dir.path().join("undo.jsonl");
+///     record(&journal, &delete_entries(&summary, HashAlgo::Sha256))?;
+///
+///     let restore = restore_last(&journal, false)?;
+///     assert_eq!(restore.restored.len(), 1);
+///     assert!(dir.path().join("b.txt").exists());
+///     Ok(())
+/// }
+/// # demo().unwrap();
+/// ```
+pub fn restore_last(journal: &Path, dry_run: bool) -> io::Result<RestoreSummary> {
+    let mut summary = RestoreSummary {
+        dry_run,
+        ..RestoreSummary::default()
+    };
+    let Some(operation) = last_operation(journal)? else {
+        return Ok(summary);
+    };
+
+    for entry in operation.entries.iter().rev() {
+        match restore_entry(entry, dry_run) {
+            Ok(()) => {
+                if !dry_run {
+                    info!("Restored {}", entry.path.display());
+                }
+                summary.restored.push(entry.path.clone());
+            }
+            Err(reason) => {
+                warn!("Cannot restore {}: {reason}", entry.path.display());
+                summary.failed.push((entry.path.clone(), reason));
+            }
+        }
+    }
+    if !dry_run {
+        append(
+            journal,
+            &format!(
+                "{{\"version\":{UNDO_VERSION},\"restored\":{}}}\n",
+                json::quote(&operation.id)
+            ),
+        )?;
+    }
+    summary.operation = Some(operation.id);
+    Ok(summary)
+}
+
+/// Puts one changed file back, or only checks that it could be.
+fn restore_entry(entry: &UndoEntry, dry_run: bool) -> Result<(), String> {
+    let occupied = fs::symlink_metadata(&entry.path).is_ok();
+    if entry.action == UndoAction::Trash {
+        let trashed = entry
+            .trashed_to
+            .as_deref()
+            .ok_or("no trash location was recorded")?;
+        if occupied {
+            return Err("another file now exists at the original path".to_string());
+        }
+        if fs::symlink_metadata(trashed).is_err() {
+            return Err(format!("{} is no longer in the trash", trashed.display()));
+        }
+        if dry_run {
+            return Ok(());
+        }
+        return trash::restore_from_trash(trashed, &entry.path).map_err(|e| e.to_string());
+    }
+
+    if occupied && !still_linked(entry) {
+        return Err("another file now exists at the original path".to_string());
+    }
+    let hash = full_hash(&entry.kept, entry.hash_algo)
+        .map_err(|e| format!("kept copy {} unreadable: {e}", entry.kept.display()))?;
+    if hash != entry.hash {
+        return Err(format!(
+            "kept copy {} has changed since the run",
+            entry.kept.display()
+        ));
+    }
+    if dry_run {
+        return Ok(());
+    }
+    copy_into_place(&entry.kept, &entry.path, entry.attributes.as_ref()).map_err(|e| e.to_string())
+}
+
+/// Returns whether the file at the entry's path is still the link the run
+/// made to the kept copy, and so may be replaced by a copy of it.
+fn still_linked(entry: &UndoEntry) -> bool {
+    match entry.action {
+        UndoAction::Hardlink => {
+            match (fs::symlink_metadata(&entry.path), fs::metadata(&entry.kept)) {
+                (Ok(link), Ok(kept)) => same_file(&link, &kept).unwrap_or(false),
+                _ => false,
+            }
+        }
+        UndoAction::Symlink => fs::read_link(&entry.path).is_ok_and(|target| target == entry.kept),
+        UndoAction::Delete | UndoAction::Trash => false,
+    }
+}
+
+/// Returns whether two files are the same file on disk, or `None` where
+/// that cannot be told.
+#[cfg(unix)]
+#[allow(clippy::unnecessary_wraps)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(a.dev() == b.dev() && a.ino() == b.ino())
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &fs::Metadata, _b: &fs::Metadata) -> Option<bool> {
+    None
+}
+
+/// Copies `source` to a temporary name next to `path`, gives the copy
+/// `attributes` if they were recorded, and renames it over `path`, so a link
+/// at `path` becomes an independent file in one step.
+fn copy_into_place(
+    source: &Path,
+    path: &Path,
+    attributes: Option<&FileAttributes>,
+) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(format!(".dff-restore-{}.tmp", std::process::id()));
+    let temp = PathBuf::from(temp);
+    fs::copy(source, &temp)?;
+    let restored = attributes.map_or(Ok(()), |attributes| set_attributes(&temp, attributes));
+    if let Err(e) = restored.and_then(|()| fs::rename(&temp, path)) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Gives the file at `path` the modification time and permissions of
+/// `attributes`.
+fn set_attributes(path: &Path, attributes: &FileAttributes) -> io::Result<()> {
+    let file = File::options().write(true).open(path)?;
+    if let Some(modified) = attributes.modified {
+        file.set_modified(modified)?;
+    }
+    let mut permissions = file.metadata()?.permissions();
+    match attributes.mode {
+        #[cfg(unix)]
+        Some(mode) => {
+            use std::os::unix::fs::PermissionsExt;
+            permissions.set_mode(mode);
+        }
+        _ => permissions.set_readonly(attributes.readonly),
+    }
+    file.set_permissions(permissions)
+}
+
+/// Appends `line` to the journal and flushes it to disk.
+fn append(journal: &Path, line: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(journal)?;
+    file.write_all(line.as_bytes())?;
+    file.sync_all()
+}
+
+enum Line {
+    Operation(UndoOperation),
+    Restored(String),
+}
+
+fn parse_line(line: &str) -> Result<Line, String> {
+    let doc = json::parse(line)?;
+    match doc.get("version").and_then(Value::as_u64) {
+        Some(UNDO_VERSION) => {}
+        Some(other) => return Err(format!("unsupported version {other}")),
+        None => return Err("missing version".to_string()),
+    }
+    if let Some(id) = doc.get("restored") {
+        let id = id.as_str().ok_or("invalid restored")?;
+        return Ok(Line::Restored(id.to_string()));
+    }
+
+    let id = doc
+        .get("id")
+        .and_then(Value::as_str)
+        .ok_or("missing id")?
+        .to_string();
+    let items = doc
+        .get("entries")
+        .and_then(Value::as_array)
+        .ok_or("missing entries")?;
+    let mut entries = Vec::with_capacity(items.len());
+    for item in items {
+        let field = |name: &str| item.get(name).ok_or(format!("entry without {name}"));
+        let string = |name: &str| field(name)?.as_str().ok_or(format!("invalid {name}"));
+
+        entries.push(UndoEntry {
+            action: UndoAction::parse(string("action")?).ok_or("unknown action")?,
+            path: PathBuf::from(string("path")?),
+            kept: PathBuf::from(string("kept")?),
+            trashed_to: item
+                .get("trashed_to")
+                .and_then(Value::as_str)
+                .map(PathBuf::from),
+            size: field("size")?.as_u64().ok_or("invalid size")?,
+            hash_algo: parse_algo(string("algo")?).ok_or("unknown algo")?,
+            hash: string("hash")?.to_string(),
+            attributes: parse_attributes(item)?,
+        });
+    }
+    Ok(Line::Operation(UndoOperation { id, entries }))
+}
+
+/// Reads the attributes [`write_attributes`] recorded for an entry, which
+/// runs recorded before they were journaled do not have.
+fn parse_attributes(item: &Value) -> Result<Option<FileAttributes>, String> {
+    let Some(readonly) = item.get("readonly") else {
+        return Ok(None);
+    };
+    let number = |name: &str| item.get(name).and_then(Value::as_u64);
+    Ok(Some(FileAttributes {
+        modified: number("modified_ns").map(|nanos| UNIX_EPOCH + Duration::from_nanos(nanos)),
+        readonly: readonly.as_bool().ok_or("invalid readonly")?,
+        mode: number("mode")
+            .map(|mode| u32::try_from(mode).map_err(|_| "invalid mode"))
+            .transpose()?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dedupe::{delete_duplicates, hardlink_duplicates, KeepStrategy};
+    use crate::find_duplicates_report;
+    use tempfile::tempdir;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restore_undoes_runs_in_reverse_order() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempdir().expect("create temp dir");
+        let data = dir.path().join("data");
+        fs::create_dir(&data).expect("create dir");
+        let file1 = data.join("file1.txt");
+        let file2 = data.join("file2.txt");
+        fs::write(&file1, "Duplicate content").expect("write file");
+        fs::write(&file2, "Duplicate content").expect("write file");
+        let journal = dir.path().join("undo.jsonl");
+        let report = find_duplicates_report(&data);
+
+        let linked = hardlink_duplicates(&report, false);
+        let entries = link_entries(UndoAction::Hardlink, &linked.linked, HashAlgo::Sha256);
+        let first = record(&journal, &entries).expect("record").expect("run id");
+        let deleted = delete_duplicates(&report, KeepStrategy::First, false);
+        let entries = delete_entries(&deleted, HashAlgo::Sha256);
+        let second = record(&journal, &entries).expect("record").expect("run id");
+        assert_ne!(first, second);
+        assert!(!file2.exists());
+        assert!(record(&journal, &[]).expect("record").is_none());
+
+        // A dry run checks the files but changes nothing.
+        let preview = restore_last(&journal, true).expect("restore");
+        assert_eq!(preview.operation.as_deref(), Some(second.as_str()));
+        assert_eq!(preview.restored, vec![file2.clone()]);
+        assert!(!file2.exists());
+
+        let undo_delete = restore_last(&journal, false).expect("restore");
+        assert_eq!(undo_delete.operation.as_deref(), Some(second.as_str()));
+        assert_eq!(fs::read(&file2).expect("read"), b"Duplicate content");
+
+        // The deletion is undone, so the hard link pass comes next. The
+        // restored copy is no longer the link, so it is left alone.
+        let undo_link = restore_last(&journal, false).expect("restore");
+        assert_eq!(undo_link.operation.as_deref(), Some(first.as_str()));
+        assert_eq!(undo_link.failed.len(), 1);
+        assert!(undo_link.failed[0].1.contains("another file"));
+        let (meta1, meta2) = (fs::metadata(&file1).unwrap(), fs::metadata(&file2).unwrap());
+        assert_ne!(meta1.ino(), meta2.ino());
+
+        let nothing = restore_last(&journal, false).expect("restore");
+        assert!(nothing.operation.is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restore_gives_back_modification_time_and_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().expect("create temp dir");
+        let data = dir.path().join("data");
+        fs::create_dir(&data).expect("create dir");
+        let kept = data.join("a.txt");
+        let removed = data.join("b.txt");
+        fs::write(&kept, "Duplicate content").expect("write file");
+        fs::write(&removed, "Duplicate content").expect("write file");
+        let past = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::options()
+            .write(true)
+            .open(&removed)
+            .and_then(|file| file.set_modified(past))
+            .expect("set mtime");
+        fs::set_permissions(&removed, fs::Permissions::from_mode(0o640)).expect("chmod");
+        let journal = dir.path().join("undo.jsonl");
+        let report = find_duplicates_report(&data);
+        let deleted = delete_duplicates(&report, KeepStrategy::First, false);
+        record(&journal, &delete_entries(&deleted, HashAlgo::Sha256)).expect("record");
+        assert!(!removed.exists());
+
+        let summary = restore_last(&journal, false).expect("restore");
+        assert!(summary.failed.is_empty(), "{:?}", summary.failed);
+        let meta = fs::metadata(&removed).expect("restored");
+        assert_eq!(meta.modified().expect("mtime"), past);
+        assert_eq!(meta.permissions().mode() & 0o777, 0o640);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restore_leaves_files_that_replaced_a_link() {
+        let dir = tempdir().expect("create temp dir");
+        let data = dir.path().join("data");
+        fs::create_dir(&data).expect("create dir");
+        let kept = data.join("a.txt");
+        let hard = data.join("b.txt");
+        let soft = data.join("c.txt");
+        for path in [&kept, &hard, &soft] {
+            fs::write(path, "Duplicate content").expect("write file");
+        }
+        let journal = dir.path().join("undo.jsonl");
+        let report = find_duplicates_report(&data);
+        let linked = hardlink_duplicates(&report, false);
+        record(
+            &journal,
+            &link_entries(UndoAction::Hardlink, &linked.linked, HashAlgo::Sha256),
+        )
+        .expect("record");
+        // Make c.txt a symbolic link to the kept copy in a second run.
+        fs::remove_file(&soft).expect("remove");
+        std::os::unix::fs::symlink(&kept, &soft).expect("symlink");
+        let entries = vec![UndoEntry {
+            action: UndoAction::Symlink,
+            path: soft.clone(),
+            kept: kept.clone(),
+            trashed_to: None,
+            size: 17,
+            hash_algo: HashAlgo::Sha256,
+            hash: full_hash(&kept, HashAlgo::Sha256).expect("hash"),
+            attributes: None,
+        }];
+        record(&journal, &entries).expect("record");
+
+        // Both links are replaced by unrelated files after the runs.
+        for path in [&hard, &soft] {
+            fs::remove_file(path).expect("remove");
+            fs::write(path, "Written since").expect("write file");
+        }
+        let undo_symlink = restore_last(&journal, false).expect("restore");
+        assert_eq!(undo_symlink.failed.len(), 1);
+        let undo_hardlink = restore_last(&journal, false).expect("restore");
+        assert!(undo_hardlink.restored.is_empty());
+        assert!(!undo_hardlink.failed.is_empty());
+        for path in [&hard, &soft] {
+            assert_eq!(fs::read(path).expect("read"), b"Written since");
+        }
+    }
+
+    #[test]
+    fn test_restore_refuses_changed_kept_copy() {
+        let dir = tempdir().expect("create temp dir");
+        let data = dir.path().join("data");
+        fs::create_dir(&data).expect("create dir");
+        let kept = data.join("a.txt");
+        let removed = data.join("b.txt");
+        fs::write(&kept, "Duplicate content").expect("write file");
+        fs::write(&removed, "Duplicate content").expect("write file");
+        let journal = dir.path().join("undo.jsonl");
+        let report = find_duplicates_report(&data);
+        let deleted = delete_duplicates(&report, KeepStrategy::First, false);
+        record(&journal, &delete_entries(&deleted, HashAlgo::Sha256)).expect("record");
+        fs::write(&kept, "Edited since").expect("write file");
+        // An unreadable line is skipped rather than failing the restore.
+        append(&journal, "not json\n").expect("append");
+
+        let summary = restore_last(&journal, false).expect("restore");
+        assert!(summary.restored.is_empty());
+        assert!(summary.failed[0].1.contains("has changed"));
+        assert!(!removed.exists());
+    }
+}
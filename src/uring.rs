@@ -0,0 +1,488 @@
+//! A minimal `io_uring` reader that batches the reads of the hashing stages
+//! on Linux.
+//!
+//! Hashing millions of small files issues millions of `read` calls, each
+//! waiting for the device before the next one is sent. An `io_uring` queues a
+//! whole batch of reads with one system call instead, so a fast solid-state
+//! drive sees many requests at once and the process makes far fewer calls. Only
+//! what plain reads at an offset need is implemented.
+//!
+//! The kernel writes into read buffers until their requests complete. If the
+//! ring fails while requests may still be in flight, the buffers of that
+//! batch are leaked rather than freed, and the ring is discarded.
+
+use log::debug;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+const IORING_OFF_SQ_RING: libc::off_t = 0;
+const IORING_OFF_CQ_RING: libc::off_t = 0x800_0000;
+const IORING_OFF_SQES: libc::off_t = 0x1000_0000;
+const IORING_FEAT_SINGLE_MMAP: u32 = 1;
+const IORING_ENTER_GETEVENTS: u32 = 1;
+const IORING_OP_READ: u8 = 22;
+
+/// Requests the ring holds at once; longer batches are queued.
+const RING_ENTRIES: u32 = 64;
+
+/// Set once the kernel refuses to create a ring, so later batches fall
+/// back to plain reads without asking again.
+static UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// Each hashing thread keeps one ring for the whole scan.
+    static RING: RefCell<Option<Ring>> = const { RefCell::new(None) };
+}
+
+/// Runs `batch` with this thread's ring, creating it on first use.
+///
+/// Returns `None` if `io_uring` cannot be used, or if the ring failed during
+/// the batch, in which case it is discarded and the caller should do the
+/// work with plain reads.
+pub(crate) fn with_ring<R>(batch: impl FnOnce(&mut Ring) -> io::Result<R>) -> Option<R> {
+    if UNAVAILABLE.load(Ordering::Relaxed) {
+        return None;
+    }
+    RING.with(|ring| {
+        let mut ring = ring.borrow_mut();
+        if ring.is_none() {
+            match Ring::new() {
+                Ok(new) => *ring = Some(new),
+                Err(e) => {
+                    debug!("Reading files without io_uring: {e}");
+                    UNAVAILABLE.store(true, Ordering::Relaxed);
+                    return None;
+                }
+            }
+        }
+        match batch(ring.as_mut()?) {
+            Ok(result) => Some(result),
+            Err(e) => {
+                debug!("Discarding io_uring after a failed batch: {e}");
+                *ring = None;
+                None
+            }
+        }
+    })
+}
+
+/// A read of up to `len` bytes from `fd`, starting at `offset`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ReadRequest {
+    pub(crate) fd: RawFd,
+    pub(crate) offset: u64,
+    pub(crate) len: usize,
+}
+
+#[repr(C)]
+#[derive(Default)]
+#[allow(dead_code)]
+struct SqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+#[allow(dead_code)]
+struct CqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+/// `struct io_uring_params`.
+#[repr(C)]
+#[derive(Default)]
+#[allow(dead_code)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqRingOffsets,
+    cq_off: CqRingOffsets,
+}
+
+/// `struct io_uring_sqe`, as used by a read.
+#[repr(C)]
+#[derive(Default)]
+#[allow(dead_code)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    rw_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    addr3: u64,
+    pad: u64,
+}
+
+/// `struct io_uring_cqe`.
+#[repr(C)]
+#[allow(dead_code)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+/// A region of the ring shared with the kernel, unmapped on drop.
+struct Mapping {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(fd: RawFd, len: usize, offset: libc::off_t) -> io::Result<Self> {
+        // SAFETY: a null hint lets the kernel pick the address, `fd` is an
+        // io_uring descriptor and `offset` one of its regions, and the result
+        // is checked before use.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd,
+                offset,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let ptr = NonNull::new(ptr.cast()).ok_or_else(|| io::Error::other("mmap returned null"))?;
+        Ok(Self { ptr, len })
+    }
+
+    /// Returns a pointer `offset` bytes into the region.
+    fn at<T>(&self, offset: u32) -> *mut T {
+        // SAFETY: the kernel's offsets all lie within the region it sized.
+        unsafe { self.ptr.as_ptr().add(offset as usize).cast() }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: `ptr` and `len` describe a mapping created by `new` that
+        // has not been unmapped yet.
+        unsafe {
+            libc::munmap(self.ptr.as_ptr().cast(), self.len);
+        }
+    }
+}
+
+/// An `io_uring` instance with its submission and completion queues.
+///
+/// The queues are shared with the kernel through raw pointers, so a ring
+/// stays on the thread that created it.
+pub(crate) struct Ring {
+    fd: OwnedFd,
+    sq_region: Mapping,
+    /// The completion queue's own region, unless it shares the submission
+    /// queue's.
+    cq_region: Option<Mapping>,
+    sqes: Mapping,
+    sq_entries: u32,
+    sq_mask: u32,
+    cq_mask: u32,
+    sq_off: SqRingOffsets,
+    cq_off: CqRingOffsets,
+}
+
+impl Ring {
+    fn new() -> io::Result<Self> {
+        let mut params = Params::default();
+        // SAFETY: `params` is a zeroed `io_uring_params` for the kernel to fill.
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_setup,
+                RING_ENTRIES,
+                std::ptr::addr_of_mut!(params),
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = RawFd::try_from(fd).map_err(io::Error::other)?;
+        // SAFETY: the kernel just returned this descriptor, and nothing else owns it.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * size_of::<u32>();
+        let cq_len = params.cq_off.cqes as usize + params.cq_entries as usize * size_of::<Cqe>();
+        let single = params.features & IORING_FEAT_SINGLE_MMAP != 0;
+        let sq_region = Mapping::new(
+            fd.as_raw_fd(),
+            if single { sq_len.max(cq_len) } else { sq_len },
+            IORING_OFF_SQ_RING,
+        )?;
+        let cq_region = if single {
+            None
+        } else {
+            Some(Mapping::new(fd.as_raw_fd(), cq_len, IORING_OFF_CQ_RING)?)
+        };
+        let sqes = Mapping::new(
+            fd.as_raw_fd(),
+            params.sq_entries as usize * size_of::<Sqe>(),
+            IORING_OFF_SQES,
+        )?;
+
+        // SAFETY: the masks are `u32`s inside the regions just mapped.
+        let (sq_mask, cq_mask) = unsafe {
+            let cq = cq_region.as_ref().unwrap_or(&sq_region);
+            (
+                *sq_region.at::<u32>(params.sq_off.ring_mask),
+                *cq.at::<u32>(params.cq_off.ring_mask),
+            )
+        };
+        Ok(Self {
+            fd,
+            sq_region,
+            cq_region,
+            sqes,
+            sq_entries: params.sq_entries,
+            sq_mask,
+            cq_mask,
+            sq_off: params.sq_off,
+            cq_off: params.cq_off,
+        })
+    }
+
+    fn cq_region(&self) -> &Mapping {
+        self.cq_region.as_ref().unwrap_or(&self.sq_region)
+    }
+
+    /// Performs every read, returning for each the bytes up to `len` read
+    /// before the end of the file, or the error the read failed with.
+    ///
+    /// # Errors
+    /// Returns an error if the ring itself fails; it must not be used again.
+    pub(crate) fn read_batch(
+        &mut self,
+        requests: &[ReadRequest],
+    ) -> io::Result<Vec<io::Result<Vec<u8>>>> {
+        let mut buffers: Vec<Vec<u8>> = requests.iter().map(|r| vec![0; r.len]).collect();
+        let mut filled = vec![0; requests.len()];
+        let mut failures: Vec<Option<io::Error>> = requests.iter().map(|_| None).collect();
+        let mut queue: VecDeque<usize> = (0..requests.len())
+            .filter(|&i| requests[i].len > 0)
+            .collect();
+        let mut in_flight = 0;
+        let mut unsubmitted = 0;
+
+        while !queue.is_empty() || in_flight > 0 {
+            while in_flight < self.sq_entries {
+                let Some(i) = queue.pop_front() else { break };
+                let request = requests[i];
+                self.push(
+                    request.fd,
+                    request.offset + filled[i] as u64,
+                    &mut buffers[i][filled[i]..],
+                    i as u64,
+                );
+                in_flight += 1;
+                unsubmitted += 1;
+            }
+            match self.enter(unsubmitted) {
+                Ok(submitted) => unsubmitted -= submitted,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    // Requests still in flight may write to the buffers.
+                    std::mem::forget(buffers);
+                    return Err(e);
+                }
+            }
+            while let Some(cqe) = self.pop() {
+                in_flight -= 1;
+                let Some(i) = usize::try_from(cqe.user_data)
+                    .ok()
+                    .filter(|&i| i < requests.len())
+                else {
+                    continue;
+                };
+                match usize::try_from(cqe.res) {
+                    // The end of the file.
+                    Ok(0) => {}
+                    Ok(read) => {
+                        filled[i] += read;
+                        if filled[i] < requests[i].len {
+                            queue.push_back(i);
+                        }
+                    }
+                    Err(_) => {
+                        let error = io::Error::from_raw_os_error(-cqe.res);
+                        if matches!(
+                            error.kind(),
+                            io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+                        ) {
+                            queue.push_back(i);
+                        } else {
+                            failures[i] = Some(error);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(buffers
+            .into_iter()
+            .zip(filled)
+            .zip(failures)
+            .map(|((mut buffer, filled), failure)| {
+                if let Some(e) = failure {
+                    return Err(e);
+                }
+                buffer.truncate(filled);
+                Ok(buffer)
+            })
+            .collect())
+    }
+
+    /// Queues a read into `buffer`, which must stay alive until the read completes.
+    fn push(&mut self, fd: RawFd, offset: u64, buffer: &mut [u8], user_data: u64) {
+        let tail = self.sq_region.at::<AtomicU32>(self.sq_off.tail);
+        // SAFETY: the tail and the array lie in the submission ring, and the
+        // entry at `index` is not in use: at most `sq_entries` requests are
+        // queued or in flight, and the kernel copies an entry on submission.
+        unsafe {
+            let tail = &*tail;
+            let current = tail.load(Ordering::Relaxed);
+            let index = current & self.sq_mask;
+            self.sqes.at::<Sqe>(0).add(index as usize).write(Sqe {
+                opcode: IORING_OP_READ,
+                fd,
+                off: offset,
+                addr: buffer.as_mut_ptr() as u64,
+                len: u32::try_from(buffer.len()).unwrap_or(u32::MAX),
+                user_data,
+                ..Sqe::default()
+            });
+            *self
+                .sq_region
+                .at::<u32>(self.sq_off.array)
+                .add(index as usize) = index;
+            tail.store(current.wrapping_add(1), Ordering::Release);
+        }
+    }
+
+    /// Submits `to_submit` queued requests and waits for at least one
+    /// completion, returning how many requests were submitted.
+    fn enter(&self, to_submit: u32) -> io::Result<u32> {
+        // SAFETY: the descriptor is an io_uring, and no signal mask is passed.
+        let submitted = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_enter,
+                self.fd.as_raw_fd(),
+                to_submit,
+                1u32,
+                IORING_ENTER_GETEVENTS,
+                std::ptr::null::<libc::sigset_t>(),
+                0usize,
+            )
+        };
+        if submitted < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        u32::try_from(submitted).map_err(io::Error::other)
+    }
+
+    /// Takes the next completion, if there is one.
+    fn pop(&mut self) -> Option<Cqe> {
+        let cq = self.cq_region();
+        // SAFETY: the head, tail and entries lie in the completion ring, and
+        // an entry between head and tail is fully written by the kernel
+        // before it publishes the tail.
+        unsafe {
+            let head = &*cq.at::<AtomicU32>(self.cq_off.head);
+            let tail = &*cq.at::<AtomicU32>(self.cq_off.tail);
+            let current = head.load(Ordering::Relaxed);
+            if current == tail.load(Ordering::Acquire) {
+                return None;
+            }
+            let index = (current & self.cq_mask) as usize;
+            let cqe = cq.at::<Cqe>(self.cq_off.cqes).add(index).read();
+            head.store(current.wrapping_add(1), Ordering::Release);
+            Some(cqe)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_ring_reads_batches() {
+        let dir = tempdir().expect("create temp dir");
+        let path = dir.path().join("data.bin");
+        let contents: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        fs::write(&path, &contents).expect("write file");
+        let file = File::open(&path).expect("open file");
+        let fd = file.as_raw_fd();
+
+        // More requests than the ring holds, one past the end of the file,
+        // and one with a bad descriptor.
+        let mut requests: Vec<ReadRequest> = (0..150)
+            .map(|i| ReadRequest {
+                fd,
+                offset: i * 50,
+                len: 100,
+            })
+            .collect();
+        requests.push(ReadRequest {
+            fd,
+            offset: 9_990,
+            len: 100,
+        });
+        requests.push(ReadRequest {
+            fd: -1,
+            offset: 0,
+            len: 10,
+        });
+
+        let Some(results) = with_ring(|ring| ring.read_batch(&requests)) else {
+            // The kernel may not offer io_uring, or forbid it.
+            return;
+        };
+        for (i, result) in results[..150].iter().enumerate() {
+            let start = i * 50;
+            assert_eq!(
+                result.as_ref().expect("read"),
+                &contents[start..start + 100]
+            );
+        }
+        assert_eq!(results[150].as_ref().expect("read"), &contents[9_990..]);
+        assert!(results[151].is_err());
+    }
+}
@@ -0,0 +1,459 @@
+//! Detection of videos that show the same footage, such as one recording
+//! saved in two containers or at two bitrates. This is experimental.
+//!
+//! Decoding video is left to `FFmpeg`: its `ffprobe` and `ffmpeg` commands
+//! must be on the `PATH`. A frame is taken at each of a few fixed points
+//! through every video, a tenth of the way in and then every fifth, each is
+//! shrunk to 9×8 brightness values and reduced to a 64-bit difference hash
+//! as for [similar images](crate::similar), and two videos are similar when
+//! their frames are, on average, at most a chosen number of bits apart.
+//! As the points are fractions of each video's length, copies only match if
+//! they have the same length: a trimmed copy does not.
+
+use crate::similar::cluster;
+use crate::{
+    build_pool, collect_files, format_size, in_pool, into_errors, is_cancelled, record_error,
+    Console, GlobError, ScanConfig, ScanOptions, Stage,
+};
+use chrono::Local;
+use log::debug;
+use rayon::prelude::*;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How many bits apart, on average, the sampled frames of two videos may be
+/// for them to count as similar by default.
+pub const DEFAULT_MAX_DISTANCE: u32 = 10;
+
+/// How many frames of each video are compared.
+const SAMPLES: usize = 5;
+
+/// The extensions, without the dot, of the videos that are compared.
+const EXTENSIONS: [&str; 13] = [
+    "mp4", "m4v", "mov", "mkv", "webm", "avi", "wmv", "flv", "mpg", "mpeg", "ts", "mts", "3gp",
+];
+
+/// A video that is part of a group of similar videos.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimilarVideo {
+    pub path: PathBuf,
+    /// The size of the picture in pixels.
+    pub width: u32,
+    pub height: u32,
+    /// How long the video plays.
+    pub duration: Duration,
+    /// The size of the file in bytes.
+    pub size: u64,
+}
+
+/// A set of videos that show the same footage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimilarVideoGroup {
+    /// The videos, largest picture first, then largest file, so that the
+    /// best copy to keep comes first.
+    pub videos: Vec<SimilarVideo>,
+    /// The most bits by which the frames of any two of the videos differ on
+    /// average, rounded up.
+    pub distance: u32,
+}
+
+/// The result of a search for similar videos.
+#[derive(Debug)]
+pub struct SimilarVideos {
+    /// The directories searched.
+    pub roots: Vec<PathBuf>,
+    /// How many bits apart, on average, the frames of two videos could be
+    /// for them to count as similar.
+    pub max_distance: u32,
+    /// How many videos were compared.
+    pub videos: usize,
+    /// Groups of at least two similar videos, ordered by the path of their
+    /// first video.
+    pub groups: Vec<SimilarVideoGroup>,
+    /// Videos that could not be read or decoded, with the error raised.
+    pub errors: Vec<(PathBuf, io::Error)>,
+    /// Whether the search was cancelled before every video was sampled.
+    pub interrupted: bool,
+}
+
+/// Returns `true` if the `ffprobe` and `ffmpeg` commands can be run.
+#[must_use]
+pub fn tools_available() -> bool {
+    ["ffprobe", "ffmpeg"].iter().all(|tool| {
+        Command::new(tool)
+            .arg("-version")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    })
+}
+
+/// Finds groups of videos in `dirs` whose sampled frames are on average at
+/// most `max_distance` bits apart.
+///
+/// Files are chosen as in any scan, by every filter of `options` that
+/// applies to the walk and by size, and then by their extension. A video
+/// that `ffmpeg` cannot read, or that cannot be run at all, is recorded in
+/// [`SimilarVideos::errors`]; check [`tools_available`] first to tell the
+/// two apart.
+///
+/// # Errors
+/// Returns an error if any pattern in `options` is invalid.
+pub fn find_similar_videos(
+    dirs: &[PathBuf],
+    options: &ScanOptions,
+    max_distance: u32,
+) -> Result<SimilarVideos, GlobError> {
+    let config = ScanConfig::new(options)?;
+    let console = Console::new(config.quiet, config.progress_json, config.progress);
+    let errors = Mutex::new(Vec::new());
+    let files: Vec<_> = collect_files(dirs, &config.walk, config.cancel, &errors, &mut Vec::new())
+        .into_iter()
+        .filter(|file| {
+            let size = file.meta.len();
+            config.min_size.is_none_or(|min| size >= min)
+                && config.max_size.is_none_or(|max| size <= max)
+                && is_video(&file.path)
+        })
+        .collect();
+    console.status(&format!(
+        "{} videos identified across {} directories",
+        files.len(),
+        dirs.len()
+    ));
+
+    let progress = console.bar(files.len() as u64, Stage::VideoHash, "Sampling videos");
+    let pool = build_pool(config.threads);
+    let sampled: Vec<(SimilarVideo, [u64; SAMPLES])> = in_pool(pool.as_ref(), || {
+        files
+            .into_par_iter()
+            .filter_map(|file| {
+                if is_cancelled(config.cancel) {
+                    return None;
+                }
+                let sampled = config.read(&file.path, || sample(&file.path));
+                progress.inc(1);
+                match sampled {
+                    Ok((width, height, duration, hashes)) => {
+                        let video = SimilarVideo {
+                            size: file.meta.len(),
+                            path: file.path,
+                            width,
+                            height,
+                            duration,
+                        };
+                        Some((video, hashes))
+                    }
+                    Err(e) => {
+                        record_error(&errors, &file.path, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    });
+    progress.finish_with_message("Videos sampled");
+
+    let count = sampled.len();
+    let groups = group_similar(sampled, max_distance);
+    console.complete(groups.len());
+    Ok(SimilarVideos {
+        roots: dirs.to_vec(),
+        max_distance,
+        videos: count,
+        groups,
+        errors: into_errors(errors),
+        interrupted: is_cancelled(config.cancel),
+    })
+}
+
+fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            EXTENSIONS
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(ext))
+        })
+}
+
+/// Returns the size and length of the video at `path` and the hash of each
+/// of its sampled frames.
+fn sample(path: &Path) -> io::Result<(u32, u32, Duration, [u64; SAMPLES])> {
+    let probe = run(
+        "ffprobe",
+        &[
+            "-v".as_ref(),
+            "error".as_ref(),
+            "-select_streams".as_ref(),
+            "v:0".as_ref(),
+            "-show_entries".as_ref(),
+            "stream=width,height:format=duration".as_ref(),
+            "-of".as_ref(),
+            "default=noprint_wrappers=1".as_ref(),
+            path.as_os_str(),
+        ],
+    )?;
+    let (width, height, seconds) = parse_probe(&String::from_utf8_lossy(&probe))?;
+    let mut hashes = [0; SAMPLES];
+    for (i, hash) in hashes.iter_mut().enumerate() {
+        #[allow(clippy::cast_precision_loss)]
+        let at = seconds * (2 * i + 1) as f64 / (2 * SAMPLES) as f64;
+        let at = format!("{at:.3}");
+        let frame = run(
+            "ffmpeg",
+            &[
+                "-nostdin".as_ref(),
+                "-v".as_ref(),
+                "error".as_ref(),
+                "-threads".as_ref(),
+                "1".as_ref(),
+                "-ss".as_ref(),
+                at.as_ref(),
+                "-i".as_ref(),
+                path.as_os_str(),
+                "-frames:v".as_ref(),
+                "1".as_ref(),
+                "-vf".as_ref(),
+                "scale=9:8:flags=area,format=gray".as_ref(),
+                "-f".as_ref(),
+                "rawvideo".as_ref(),
+                "-".as_ref(),
+            ],
+        )?;
+        *hash = dhash(&frame).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("no frame at {at}s"))
+        })?;
+    }
+    Ok((width, height, Duration::from_secs_f64(seconds), hashes))
+}
+
+/// Runs `tool` with `args`, returning what it wrote to stdout, or an error
+/// holding the first line it wrote to stderr if it failed.
+fn run(tool: &str, args: &[&std::ffi::OsStr]) -> io::Result<Vec<u8>> {
+    debug!("Running {tool} {args:?}");
+    let output = Command::new(tool)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot run {tool}: {e}")))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().next().unwrap_or("no details");
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{tool} failed: {reason}"),
+        ));
+    }
+    Ok(output.stdout)
+}
+
+/// Reads the width, height and length in seconds of a video from the
+/// `key=value` lines printed by `ffprobe`.
+fn parse_probe(output: &str) -> io::Result<(u32, u32, f64)> {
+    let (mut width, mut height, mut duration) = (None, None, None);
+    for line in output.lines() {
+        match line.trim().split_once('=') {
+            Some(("width", value)) => width = value.parse().ok(),
+            Some(("height", value)) => height = value.parse().ok(),
+            Some(("duration", value)) => duration = value.parse::<f64>().ok(),
+            _ => {}
+        }
+    }
+    match (width, height, duration) {
+        (Some(width), Some(height), Some(duration)) if duration.is_finite() && duration > 0.0 => {
+            Ok((width, height, duration))
+        }
+        (None, _, _) | (_, None, _) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no video stream",
+        )),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unknown duration",
+        )),
+    }
+}
+
+/// Returns the difference hash of a frame of 9×8 brightness values, or
+/// `None` if `pixels` is not one.
+fn dhash(pixels: &[u8]) -> Option<u64> {
+    if pixels.len() != 9 * 8 {
+        return None;
+    }
+    let mut hash = 0;
+    for row in pixels.chunks(9) {
+        for pair in row.windows(2) {
+            hash = hash << 1 | u64::from(pair[0] > pair[1]);
+        }
+    }
+    Some(hash)
+}
+
+/// Groups videos whose frames are on average at most `max_distance` bits
+/// apart, and anything similar to those.
+fn group_similar(
+    videos: Vec<(SimilarVideo, [u64; SAMPLES])>,
+    max_distance: u32,
+) -> Vec<SimilarVideoGroup> {
+    let hashes: Vec<[u64; SAMPLES]> = videos.iter().map(|&(_, hashes)| hashes).collect();
+    let mut videos: Vec<Option<SimilarVideo>> =
+        videos.into_iter().map(|(video, _)| Some(video)).collect();
+    let samples = u32::try_from(SAMPLES).unwrap_or(u32::MAX);
+    let mut groups: Vec<SimilarVideoGroup> = cluster(&hashes, max_distance.saturating_mul(samples))
+        .into_iter()
+        .map(|(members, distance)| {
+            let mut videos: Vec<SimilarVideo> =
+                members.iter().filter_map(|&i| videos[i].take()).collect();
+            videos.sort_by(|a, b| {
+                let pixels = |v: &SimilarVideo| u64::from(v.width) * u64::from(v.height);
+                pixels(b)
+                    .cmp(&pixels(a))
+                    .then(b.size.cmp(&a.size))
+                    .then_with(|| a.path.cmp(&b.path))
+            });
+            SimilarVideoGroup {
+                videos,
+                distance: distance.div_ceil(samples),
+            }
+        })
+        .collect();
+    groups.sort_by(|a, b| a.videos[0].path.cmp(&b.videos[0].path));
+    groups
+}
+
+/// Writes the groups of similar videos as plain text, with the size and
+/// length of each video so that the best copy is easy to pick.
+///
+/// # Errors
+/// Returns any error raised by `writer`.
+pub fn write_similar_videos_to<W: Write>(
+    writer: &mut W,
+    similar: &SimilarVideos,
+    start_time: &str,
+) -> io::Result<()> {
+    let username = whoami::username();
+    let end_time = Local::now().format("%Y%m%d %H:%M:%S").to_string();
+
+    writeln!(writer, "Similar Videos Report")?;
+    writeln!(writer, "Generated by: {username}")?;
+    writeln!(writer, "Start Time: {start_time}")?;
+    writeln!(writer, "End Time: {end_time}")?;
+    if similar.roots.len() == 1 {
+        writeln!(writer, "Base Directory: {}", similar.roots[0].display())?;
+    } else {
+        writeln!(writer, "Base Directories:")?;
+        for dir in &similar.roots {
+            writeln!(writer, " - {}", dir.display())?;
+        }
+    }
+    writeln!(writer)?;
+
+    if similar.interrupted {
+        writeln!(
+            writer,
+            "Scan Interrupted: the results below are incomplete."
+        )?;
+    }
+    writeln!(
+        writer,
+        "Videos Compared: {} (similar when frames are at most {} of 64 bits apart on average)",
+        similar.videos, similar.max_distance
+    )?;
+    writeln!(writer, "Groups Of Similar Videos: {}", similar.groups.len())?;
+    if !similar.errors.is_empty() {
+        writeln!(
+            writer,
+            "Videos Skipped Due To Errors: {}",
+            similar.errors.len()
+        )?;
+    }
+    writeln!(writer)?;
+
+    for group in &similar.groups {
+        writeln!(
+            writer,
+            "{} videos, up to {} bits apart:",
+            group.videos.len(),
+            group.distance
+        )?;
+        for video in &group.videos {
+            let seconds = video.duration.as_secs();
+            writeln!(
+                writer,
+                "{} ({}x{}, {}:{:02}, {})",
+                video.path.display(),
+                video.width,
+                video.height,
+                seconds / 60,
+                seconds % 60,
+                format_size(video.size)
+            )?;
+        }
+        writeln!(writer)?;
+    }
+
+    if !similar.errors.is_empty() {
+        writeln!(writer, "Skipped Files:")?;
+        for (path, error) in &similar.errors {
+            writeln!(writer, "{}: {error}", path.display())?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_probe() {
+        let output = "width=1920\nheight=1080\nduration=83.250000\n";
+        assert_eq!(parse_probe(output).expect("valid"), (1920, 1080, 83.25));
+        assert!(parse_probe("duration=12.5\n").is_err());
+        assert!(parse_probe("width=640\nheight=480\nduration=N/A\n").is_err());
+    }
+
+    #[test]
+    fn test_group_similar_videos() {
+        let video = |name: &str| SimilarVideo {
+            path: PathBuf::from(name),
+            width: 640,
+            height: 480,
+            duration: Duration::from_secs(90),
+            size: 1,
+        };
+        let frames = |bits: u64| [bits, 0, 0, 0, 0];
+        let ramp: Vec<u8> = (0..72).map(|i| 200 - i).collect();
+        assert_eq!(dhash(&ramp), Some(u64::MAX));
+        assert_eq!(dhash(&ramp[1..]), None);
+
+        // Twenty bits apart in one frame is four bits on average.
+        let groups = group_similar(
+            vec![
+                (video("a.mp4"), frames(0)),
+                (video("far.mp4"), [u64::MAX; SAMPLES]),
+                (video("b.mkv"), frames(0xF_FFFF)),
+            ],
+            4,
+        );
+        assert_eq!(groups.len(), 1);
+        let paths: Vec<_> = groups[0].videos.iter().map(|v| v.path.clone()).collect();
+        assert_eq!(paths, [PathBuf::from("a.mp4"), "b.mkv".into()]);
+        assert_eq!(groups[0].distance, 4);
+        assert!(group_similar(
+            vec![
+                (video("a.mp4"), frames(0)),
+                (video("b.mkv"), frames(0xF_FFFF))
+            ],
+            3
+        )
+        .is_empty());
+    }
+}
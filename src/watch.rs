@@ -0,0 +1,366 @@
+//! Waiting for files to change, so that a scan can be repeated as soon as
+//! its results go stale.
+//!
+//! On Linux every directory is watched with inotify. Elsewhere the
+//! directories are walked again every [`POLL_INTERVAL`] and the size and
+//! modification time of each file compared with the previous walk.
+
+use std::collections::HashSet;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How long the quiet period after a change must last by default before
+/// [`Watcher::wait`] returns, so that a burst of changes, such as a large
+/// file being copied, leads to a single scan.
+pub const DEFAULT_SETTLE: Duration = Duration::from_secs(2);
+
+/// How often the directories are walked where they cannot be watched.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often a watcher with nothing to report checks whether it was
+/// cancelled.
+const TICK: Duration = Duration::from_millis(250);
+
+/// Watches directory trees for files being created, modified, moved or
+/// deleted.
+pub struct Watcher {
+    backend: Backend,
+    /// Files whose changes are not reported: those the program writes
+    /// itself, such as its report and log.
+    ignored: HashSet<PathBuf>,
+}
+
+impl Watcher {
+    /// Starts watching `dirs` and every directory below them, ignoring
+    /// changes to the files in `ignored`.
+    ///
+    /// # Errors
+    /// Returns an error if a directory in `dirs` cannot be watched.
+    /// Subdirectories that cannot be are skipped.
+    pub fn new(dirs: &[PathBuf], ignored: impl IntoIterator<Item = PathBuf>) -> io::Result<Self> {
+        let dirs: Vec<PathBuf> = dirs.iter().map(absolute).collect();
+        Ok(Self {
+            backend: Backend::new(&dirs)?,
+            ignored: ignored.into_iter().map(|path| absolute(&path)).collect(),
+        })
+    }
+
+    /// Blocks until something has changed and then nothing more for
+    /// `settle`, returning the paths that changed, sorted, or an empty list
+    /// if `cancel` was set first.
+    ///
+    /// # Errors
+    /// Returns any error raised while reading the changes.
+    pub fn wait(&mut self, settle: Duration, cancel: &AtomicBool) -> io::Result<Vec<PathBuf>> {
+        let mut changed = HashSet::new();
+        loop {
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(Vec::new());
+            }
+            let timeout = if changed.is_empty() { TICK } else { settle };
+            let batch: Vec<PathBuf> = self
+                .backend
+                .next(timeout)?
+                .into_iter()
+                .filter(|path| !self.ignored.contains(path))
+                .collect();
+            if batch.is_empty() && !changed.is_empty() {
+                break;
+            }
+            changed.extend(batch);
+        }
+        let mut changed: Vec<PathBuf> = changed.into_iter().collect();
+        changed.sort();
+        Ok(changed)
+    }
+}
+
+fn absolute(path: &PathBuf) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.clone())
+}
+
+#[cfg(target_os = "linux")]
+use inotify::Inotify as Backend;
+
+#[cfg(not(target_os = "linux"))]
+use poll::Poll as Backend;
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use log::{debug, warn};
+    use std::collections::HashMap;
+    use std::ffi::{CString, OsStr};
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+    use walkdir::WalkDir;
+
+    /// The events that mean a file has changed.
+    const EVENTS: u32 = libc::IN_CLOSE_WRITE
+        | libc::IN_MODIFY
+        | libc::IN_ATTRIB
+        | libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO
+        | libc::IN_DELETE_SELF
+        | libc::IN_MOVE_SELF;
+
+    /// The size of the fixed part of each event read.
+    const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+
+    pub(super) struct Inotify {
+        fd: libc::c_int,
+        /// The directory each watch descriptor was added for.
+        watches: HashMap<libc::c_int, PathBuf>,
+        roots: Vec<PathBuf>,
+    }
+
+    impl Inotify {
+        pub(super) fn new(dirs: &[PathBuf]) -> io::Result<Self> {
+            // SAFETY: `inotify_init1` takes no pointers.
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut inotify = Self {
+                fd,
+                watches: HashMap::new(),
+                roots: dirs.to_vec(),
+            };
+            for dir in dirs {
+                inotify.add(dir)?;
+                inotify.add_tree(dir);
+            }
+            Ok(inotify)
+        }
+
+        /// Watches the directory `dir`.
+        fn add(&mut self, dir: &Path) -> io::Result<()> {
+            let path = CString::new(dir.as_os_str().as_bytes())?;
+            // SAFETY: `path` is a valid NUL-terminated string that outlives the call.
+            let wd = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), EVENTS) };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.watches.insert(wd, dir.to_path_buf());
+            Ok(())
+        }
+
+        /// Watches every directory below `dir`, skipping those that cannot
+        /// be.
+        fn add_tree(&mut self, dir: &Path) {
+            for entry in WalkDir::new(dir).min_depth(1).into_iter().flatten() {
+                if entry.file_type().is_dir() {
+                    if let Err(e) = self.add(entry.path()) {
+                        warn!("Cannot watch {}: {e}", entry.path().display());
+                    }
+                }
+            }
+        }
+
+        /// Returns the paths changed within `timeout`, returning as soon as
+        /// there are any.
+        pub(super) fn next(&mut self, timeout: Duration) -> io::Result<Vec<PathBuf>> {
+            let deadline = Instant::now() + timeout;
+            loop {
+                let left = deadline.saturating_duration_since(Instant::now());
+                let mut poll = libc::pollfd {
+                    fd: self.fd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                let millis = libc::c_int::try_from(left.as_millis()).unwrap_or(libc::c_int::MAX);
+                // SAFETY: `poll` points to one valid `pollfd`.
+                let ready = unsafe { libc::poll(&raw mut poll, 1, millis) };
+                if ready < 0 {
+                    let e = io::Error::last_os_error();
+                    if e.kind() == io::ErrorKind::Interrupted {
+                        // Ctrl-C; the caller checks whether to stop.
+                        return Ok(Vec::new());
+                    }
+                    return Err(e);
+                }
+                if ready == 0 {
+                    return Ok(Vec::new());
+                }
+                let changed = self.read()?;
+                if !changed.is_empty() || left.is_zero() {
+                    return Ok(changed);
+                }
+            }
+        }
+
+        /// Reads the pending events, returning the paths they are about.
+        fn read(&mut self) -> io::Result<Vec<PathBuf>> {
+            let mut buffer = vec![0u8; 64 * 1024];
+            let mut changed = Vec::new();
+            loop {
+                // SAFETY: `buffer` is valid for writes of its whole length.
+                let len = unsafe { libc::read(self.fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+                if len < 0 {
+                    let e = io::Error::last_os_error();
+                    return match e.kind() {
+                        io::ErrorKind::WouldBlock => Ok(changed),
+                        io::ErrorKind::Interrupted => continue,
+                        _ => Err(e),
+                    };
+                }
+                let len = usize::try_from(len).unwrap_or(0);
+                let mut offset = 0;
+                while offset + HEADER <= len {
+                    // SAFETY: the kernel wrote a whole event header at `offset`;
+                    // `read_unaligned` copes with the byte buffer's alignment.
+                    let event: libc::inotify_event =
+                        unsafe { std::ptr::read_unaligned(buffer.as_ptr().add(offset).cast()) };
+                    let name_len = event.len as usize;
+                    let name = &buffer[offset + HEADER..offset + HEADER + name_len];
+                    let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                    self.handle(&event, OsStr::from_bytes(name), &mut changed);
+                    offset += HEADER + name_len;
+                }
+            }
+        }
+
+        fn handle(
+            &mut self,
+            event: &libc::inotify_event,
+            name: &OsStr,
+            changed: &mut Vec<PathBuf>,
+        ) {
+            if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                debug!("inotify queue overflowed; reporting a change to every directory");
+                changed.extend(self.roots.iter().cloned());
+                return;
+            }
+            if event.mask & libc::IN_IGNORED != 0 {
+                self.watches.remove(&event.wd);
+                return;
+            }
+            let Some(dir) = self.watches.get(&event.wd) else {
+                return;
+            };
+            let path = if name.is_empty() {
+                dir.clone()
+            } else {
+                dir.join(name)
+            };
+            let new_dir = event.mask & libc::IN_ISDIR != 0
+                && event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0;
+            if new_dir {
+                // Files may have been added to it before it was watched.
+                if let Err(e) = self.add(&path) {
+                    warn!("Cannot watch {}: {e}", path.display());
+                }
+                self.add_tree(&path);
+            }
+            changed.push(path);
+        }
+    }
+
+    impl Drop for Inotify {
+        fn drop(&mut self) {
+            // SAFETY: `fd` is owned by this watcher and closed only here.
+            unsafe {
+                libc::close(self.fd);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod poll {
+    use super::POLL_INTERVAL;
+    use std::collections::HashMap;
+    use std::io;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+    use walkdir::WalkDir;
+
+    type Snapshot = HashMap<PathBuf, (u64, Option<SystemTime>)>;
+
+    pub(super) struct Poll {
+        roots: Vec<PathBuf>,
+        snapshot: Snapshot,
+    }
+
+    impl Poll {
+        pub(super) fn new(dirs: &[PathBuf]) -> io::Result<Self> {
+            for dir in dirs {
+                std::fs::read_dir(dir)?;
+            }
+            let mut poll = Self {
+                roots: dirs.to_vec(),
+                snapshot: Snapshot::new(),
+            };
+            poll.snapshot = poll.walk();
+            Ok(poll)
+        }
+
+        fn walk(&self) -> Snapshot {
+            self.roots
+                .iter()
+                .flat_map(|dir| WalkDir::new(dir).into_iter().flatten())
+                .filter_map(|entry| {
+                    let meta = entry.metadata().ok()?;
+                    Some((entry.into_path(), (meta.len(), meta.modified().ok())))
+                })
+                .collect()
+        }
+
+        /// Returns the paths changed since the last walk, walking again
+        /// after `timeout` or [`POLL_INTERVAL`], whichever is longer.
+        pub(super) fn next(&mut self, timeout: Duration) -> io::Result<Vec<PathBuf>> {
+            std::thread::sleep(timeout.max(POLL_INTERVAL));
+            let snapshot = self.walk();
+            let mut changed: Vec<PathBuf> = snapshot
+                .iter()
+                .filter(|&(path, state)| self.snapshot.get(path) != Some(state))
+                .map(|(path, _)| path.clone())
+                .collect();
+            changed.extend(
+                self.snapshot
+                    .keys()
+                    .filter(|path| !snapshot.contains_key(*path))
+                    .cloned(),
+            );
+            self.snapshot = snapshot;
+            Ok(changed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_watcher_reports_changes() {
+        let dir = tempdir().expect("create temp dir");
+        let root = dir.path().to_path_buf();
+        let ignored = root.join("report.txt");
+        let mut watcher =
+            Watcher::new(std::slice::from_ref(&root), [ignored.clone()]).expect("watch");
+        let cancel = AtomicBool::new(false);
+
+        fs::create_dir(root.join("new")).expect("create dir");
+        let settle = Duration::from_millis(100);
+        let changed = watcher.wait(settle, &cancel).expect("wait");
+        assert!(changed.contains(&absolute(&root.join("new"))));
+
+        // The new directory is watched too, and the ignored file is not.
+        fs::write(&ignored, "report").expect("write file");
+        fs::write(root.join("new/a.txt"), "a").expect("write file");
+        let changed = watcher.wait(settle, &cancel).expect("wait");
+        assert_eq!(changed, [absolute(&root.join("new/a.txt"))]);
+
+        cancel.store(true, Ordering::Relaxed);
+        assert!(watcher.wait(settle, &cancel).expect("wait").is_empty());
+    }
+}
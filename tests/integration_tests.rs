@@ -1,5 +1,5 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use tempfile::tempdir;
 use walkdir::WalkDir;
@@ -57,6 +57,26 @@ fn no_arguments_scans_current_directory() {
     assert!(report.exists());
 }
 
+#[test]
+fn rescans_leave_out_own_report_and_log() {
+    let tmp = tempdir().expect("create temp dir");
+    copy_dir_recursive(Path::new("resources"), tmp.path()).expect("copy resources");
+    let report = tmp.path().join("report.csv");
+
+    let output = run_with_args(tmp.path(), &["--format", "csv", "-o", "report.csv"]);
+    assert!(output.status.success());
+    // Until the new report is written, the old one matches this copy.
+    fs::copy(&report, tmp.path().join("backup.csv")).expect("copy report");
+
+    let output = run_with_args(tmp.path(), &["--format", "csv", "-o", "report.csv"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&report).expect("read report");
+    assert!(!content.contains("backup.csv"));
+    assert!(!content.contains("report.csv"));
+    assert!(!content.contains("duplicate_finder.log"));
+    assert!(content.contains("text_file (Copy).txt"));
+}
+
 #[test]
 fn output_file_argument_creates_file() {
     let tmp = tempdir().expect("create temp dir");
@@ -121,6 +141,82 @@ fn report_contains_expected_duplicates() {
     assert!(content.contains("1_GI-td9gs8D5OKZd19mAOqA.png"));
 }
 
+#[test]
+fn repeated_scans_write_identical_reports() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+    // Many groups of the same size, so their order cannot come from size alone.
+    for letter in ["q", "c", "x", "a", "m", "h", "t", "e"] {
+        for copy in ["2", "1", "3"] {
+            let dir = input_dir.join(format!("copy{copy}"));
+            fs::create_dir_all(&dir).expect("create dir");
+            fs::write(dir.join(format!("{letter}.txt")), letter.repeat(5)).expect("write file");
+        }
+    }
+    let dir_arg = input_dir.to_str().expect("valid UTF-8");
+    let scan = || {
+        let output = run_with_args(tmp.path(), &[dir_arg, "--output", "-"]);
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .expect("valid UTF-8")
+            .lines()
+            .filter(|line| !line.starts_with("Start Time:") && !line.starts_with("End Time:"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let first = scan();
+    for _ in 0..3 {
+        assert_eq!(scan(), first);
+    }
+    let small: Vec<&str> = first
+        .lines()
+        .filter(|line| line.ends_with(".txt") && line.contains("copy1"))
+        .collect();
+    let mut sorted = small.clone();
+    sorted.sort_unstable();
+    assert_eq!(small.len(), 8);
+    assert_eq!(small, sorted);
+}
+
+#[test]
+fn summary_prints_totals_instead_of_listing() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+    let dir_arg = input_dir.to_str().expect("valid UTF-8");
+
+    let output = run_with_args(tmp.path(), &[dir_arg, "--summary"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).expect("valid UTF-8");
+    assert!(stdout.starts_with("Duplicate File Finder Summary\n"));
+    assert!(stdout.contains("Duplicate Groups: 2\n"));
+    assert!(stdout.contains("Redundant Files: 3\n"));
+    assert!(stdout.contains("1. 1.20 MB in 3 files of 613.53 KB: "));
+    assert!(stdout.contains("2. 11 bytes in 2 files of 11 bytes: "));
+    // Only the first path of each group is shown.
+    assert!(!stdout.contains("text_file.txt"));
+    assert!(!tmp.path().join("duplicate_file_report.txt").exists());
+
+    let summary_file = tmp.path().join("summary.txt");
+    let output = run_with_args(
+        tmp.path(),
+        &[
+            dir_arg,
+            "--summary",
+            "--output",
+            summary_file.to_str().expect("valid UTF-8"),
+        ],
+    );
+    assert!(output.status.success());
+    let content = fs::read_to_string(&summary_file).expect("read summary");
+    assert_eq!(content, stdout);
+
+    let conflict = run_with_args(tmp.path(), &[dir_arg, "--summary", "--delete"]);
+    assert!(!conflict.status.success());
+}
+
 #[test]
 fn multiple_directories_scan() {
     let tmp = tempdir().expect("create temp dir");
@@ -144,3 +240,1634 @@ fn multiple_directories_scan() {
     assert!(content.contains(input_dir1.to_str().expect("valid UTF-8")));
     assert!(content.contains(input_dir2.to_str().expect("valid UTF-8")));
 }
+
+#[test]
+fn exclude_pattern_skips_matching_files() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[
+            input_dir.to_str().expect("valid UTF-8"),
+            "--exclude",
+            "*.txt",
+            "--exclude",
+            "**/a deeper folder/**",
+        ],
+    );
+    assert!(output.status.success());
+    let report = tmp.path().join("duplicate_file_report.txt");
+    let content = fs::read_to_string(report).expect("read report");
+    assert!(!content.contains("text_file.txt"));
+    assert!(!content.contains("a deeper folder"));
+    assert!(content.contains("1_GI-td9gs8D5OKZd19mAOqA (Copy).png"));
+}
+
+#[test]
+fn type_preset_scans_only_that_kind_of_file() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+    // Extensions are matched ignoring case.
+    fs::rename(
+        input_dir.join("1_GI-td9gs8D5OKZd19mAOqA.png"),
+        input_dir.join("upper.PNG"),
+    )
+    .expect("rename file");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[input_dir.to_str().expect("valid UTF-8"), "--type", "images"],
+    );
+    assert!(output.status.success());
+    let report = tmp.path().join("duplicate_file_report.txt");
+    let content = fs::read_to_string(report).expect("read report");
+    assert!(!content.contains("text_file.txt"));
+    assert!(content.contains("upper.PNG"));
+    assert!(content.contains("a deeper folder"));
+
+    let output = run_with_args(tmp.path(), &["--type", "spreadsheets"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn skip_hidden_leaves_out_dotfiles() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+    fs::rename(
+        input_dir.join("text_file (Copy).txt"),
+        input_dir.join(".text_file.txt"),
+    )
+    .expect("rename file");
+    fs::rename(input_dir.join("a deeper folder"), input_dir.join(".cache")).expect("rename dir");
+    let input = input_dir.to_str().expect("valid UTF-8");
+    let report = tmp.path().join("duplicate_file_report.txt");
+
+    let output = run_with_args(tmp.path(), &[input, "--skip-hidden"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&report).expect("read report");
+    assert!(!content.contains(".text_file.txt"));
+    assert!(!content.contains(".cache"));
+    assert!(content.contains("1_GI-td9gs8D5OKZd19mAOqA (Copy).png"));
+
+    // The later flag wins.
+    let output = run_with_args(tmp.path(), &[input, "--skip-hidden", "--include-hidden"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&report).expect("read report");
+    assert!(content.contains(".text_file.txt"));
+    assert!(content.contains(".cache"));
+}
+
+#[test]
+fn max_depth_limits_how_far_the_scan_descends() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[input_dir.to_str().expect("valid UTF-8"), "--max-depth", "1"],
+    );
+    assert!(output.status.success());
+    let report = tmp.path().join("duplicate_file_report.txt");
+    let content = fs::read_to_string(report).expect("read report");
+    assert!(!content.contains("a deeper folder"));
+    assert!(content.contains("1_GI-td9gs8D5OKZd19mAOqA (Copy).png"));
+    assert!(content.contains("text_file.txt"));
+}
+
+#[test]
+fn invalid_exclude_pattern_returns_error() {
+    let tmp = tempdir().expect("create temp dir");
+    let output = run_with_args(tmp.path(), &["--exclude", "[unclosed"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("invalid glob"));
+    assert!(!tmp.path().join("duplicate_file_report.txt").exists());
+}
+
+#[test]
+fn min_size_skips_small_files() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[
+            input_dir.to_str().expect("valid UTF-8"),
+            "--min-size",
+            "1KB",
+        ],
+    );
+    assert!(output.status.success());
+    let report = tmp.path().join("duplicate_file_report.txt");
+    let content = fs::read_to_string(report).expect("read report");
+    assert!(!content.contains("text_file.txt"));
+    assert!(content.contains("1_GI-td9gs8D5OKZd19mAOqA.png"));
+}
+
+#[cfg(unix)]
+#[test]
+fn hardlink_is_dry_run_without_confirm() {
+    use std::os::unix::fs::MetadataExt;
+
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+    let original = input_dir.join("text_file.txt");
+    let copy = input_dir.join("text_file (Copy).txt");
+    let ino = |p: &Path| fs::metadata(p).expect("metadata").ino();
+
+    let dir_arg = input_dir.to_str().expect("valid UTF-8");
+    let output = run_with_args(tmp.path(), &[dir_arg, "--hardlink"]);
+    assert!(output.status.success());
+    assert_ne!(ino(&original), ino(&copy));
+    let content =
+        fs::read_to_string(tmp.path().join("duplicate_file_report.txt")).expect("read report");
+    assert!(content.contains("Hard Links (dry run, no files changed):"));
+
+    let output = run_with_args(tmp.path(), &[dir_arg, "--hardlink", "--confirm"]);
+    assert!(output.status.success());
+    assert_eq!(ino(&original), ino(&copy));
+    let content =
+        fs::read_to_string(tmp.path().join("duplicate_file_report.txt")).expect("read report");
+    assert!(content.contains("Space Reclaimed:"));
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_is_dry_run_without_confirm() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+    let is_link = |p: &Path| {
+        fs::symlink_metadata(p)
+            .expect("metadata")
+            .file_type()
+            .is_symlink()
+    };
+    let linked = |report: &str| -> Vec<PathBuf> {
+        report
+            .lines()
+            .filter_map(|line| line.split_once(" -> "))
+            .map(|(path, _)| PathBuf::from(path))
+            .collect()
+    };
+
+    let dir_arg = input_dir.to_str().expect("valid UTF-8");
+    let output = run_with_args(tmp.path(), &[dir_arg, "--symlink"]);
+    assert!(output.status.success());
+    let content =
+        fs::read_to_string(tmp.path().join("duplicate_file_report.txt")).expect("read report");
+    assert!(content.contains("Symbolic Links (dry run, no files changed):"));
+    // One text copy and two of the three images.
+    assert_eq!(linked(&content).len(), 3);
+    assert!(linked(&content).iter().all(|p| !is_link(p)));
+
+    let output = run_with_args(tmp.path(), &[dir_arg, "--symlink", "--confirm"]);
+    assert!(output.status.success());
+    let content =
+        fs::read_to_string(tmp.path().join("duplicate_file_report.txt")).expect("read report");
+    assert!(content.contains("Space Reclaimed:"));
+    assert_eq!(linked(&content).len(), 3);
+    assert!(linked(&content).iter().all(|p| is_link(p)));
+
+    let conflict = run_with_args(tmp.path(), &[dir_arg, "--symlink", "--hardlink"]);
+    assert!(!conflict.status.success());
+}
+
+#[test]
+fn blake3_hash_algo_finds_same_duplicates() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[
+            input_dir.to_str().expect("valid UTF-8"),
+            "--hash-algo",
+            "blake3",
+        ],
+    );
+    assert!(output.status.success());
+    let report = tmp.path().join("duplicate_file_report.txt");
+    let content = fs::read_to_string(report).expect("read report");
+    assert!(content.contains("text_file (Copy).txt"));
+    assert!(content.contains("1_GI-td9gs8D5OKZd19mAOqA (Copy).png"));
+}
+
+#[test]
+fn fast_hash_algos_find_same_duplicates() {
+    for algo in ["xxh3", "md5"] {
+        let tmp = tempdir().expect("create temp dir");
+        let input_dir = tmp.path().join("data");
+        copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+        let output = run_with_args(
+            tmp.path(),
+            &[input_dir.to_str().expect("valid UTF-8"), "--hash", algo],
+        );
+        assert!(output.status.success(), "{algo}");
+        let report = tmp.path().join("duplicate_file_report.txt");
+        let content = fs::read_to_string(report).expect("read report");
+        assert!(content.contains("text_file (Copy).txt"), "{algo}");
+        assert!(
+            content.contains("1_GI-td9gs8D5OKZd19mAOqA (Copy).png"),
+            "{algo}"
+        );
+    }
+}
+
+#[test]
+fn paranoid_finds_same_duplicates() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[input_dir.to_str().expect("valid UTF-8"), "--paranoid"],
+    );
+    assert!(output.status.success());
+    let report = tmp.path().join("duplicate_file_report.txt");
+    let content = fs::read_to_string(report).expect("read report");
+    assert!(content.contains("text_file (Copy).txt"));
+    assert!(content.contains("1_GI-td9gs8D5OKZd19mAOqA (Copy).png"));
+}
+
+#[test]
+fn low_memory_finds_same_duplicates() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[input_dir.to_str().expect("valid UTF-8"), "--low-memory"],
+    );
+    assert!(output.status.success());
+    let report = tmp.path().join("duplicate_file_report.txt");
+    let content = fs::read_to_string(report).expect("read report");
+    assert!(content.contains("text_file (Copy).txt"));
+    assert!(content.contains("1_GI-td9gs8D5OKZd19mAOqA (Copy).png"));
+    assert!(content.contains("a deeper folder"));
+}
+
+#[test]
+fn against_reports_files_without_a_copy() {
+    let tmp = tempdir().expect("create temp dir");
+    let reference = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &reference).expect("copy resources");
+    let source = tmp.path().join("old");
+    fs::create_dir(&source).expect("create dir");
+    fs::copy("resources/text_file.txt", source.join("notes.txt")).expect("copy file");
+    fs::write(source.join("only here.txt"), "not backed up").expect("write file");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[
+            source.to_str().expect("valid UTF-8"),
+            "--against",
+            reference.to_str().expect("valid UTF-8"),
+        ],
+    );
+    assert!(output.status.success());
+    let report = tmp.path().join("comparison_report.txt");
+    let content = fs::read_to_string(report).expect("read report");
+    assert!(content.contains("Files With A Copy: 1\n"));
+    assert!(content.contains(&format!(
+        "Files Without A Copy:\n{}\n",
+        source.join("only here.txt").display()
+    )));
+    assert!(content.contains(&format!(
+        "{} => {}",
+        source.join("notes.txt").display(),
+        reference.join("text_file").display()
+    )));
+
+    let output = run_with_args(
+        tmp.path(),
+        &[
+            source.to_str().expect("valid UTF-8"),
+            "--against",
+            source.to_str().expect("valid UTF-8"),
+        ],
+    );
+    assert!(!output.status.success());
+}
+
+#[test]
+fn ignore_image_metadata_matches_retagged_images() {
+    let tmp = tempdir().expect("create temp dir");
+    copy_dir_recursive(Path::new("resources"), tmp.path()).expect("copy resources");
+    // The same image with a text chunk after the 33 bytes of signature and
+    // header, as an editor adding a caption would write it.
+    let png = fs::read("resources/1_GI-td9gs8D5OKZd19mAOqA.png").expect("read image");
+    let mut tagged = png[..33].to_vec();
+    tagged.extend(b"\0\0\0\x09tEXtTitle\0Cat\0\0\0\0");
+    tagged.extend_from_slice(&png[33..]);
+    fs::write(tmp.path().join("captioned.png"), tagged).expect("write image");
+
+    let output = run_with_args(tmp.path(), &["--ignore-image-metadata", "--summary"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Duplicate Groups: 2\n"), "{stdout}");
+    assert!(stdout.contains("Redundant Files: 4\n"), "{stdout}");
+
+    let output = run_with_args(tmp.path(), &["--summary"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Redundant Files: 3\n"), "{stdout}");
+}
+
+#[test]
+fn audio_content_matches_retagged_songs() {
+    let tmp = tempdir().expect("create temp dir");
+    let frames = b"\xFF\xFB\x90\x64not really audio, but framed like it";
+    for (name, title) in [("song.mp3", "Song"), ("song (retagged).mp3", "Song (Live)")] {
+        let tag = format!("TIT2{title}");
+        let mut data = b"ID3\x03\0\0\0\0\0".to_vec();
+        data.push(u8::try_from(tag.len()).expect("short tag"));
+        data.extend(tag.as_bytes());
+        data.extend_from_slice(frames);
+        fs::write(tmp.path().join(name), data).expect("write song");
+    }
+    fs::write(tmp.path().join("other.mp3"), b"\xFF\xFB\x90\x64other").expect("write song");
+
+    let output = run_with_args(tmp.path(), &["--audio-content", "--summary"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Duplicate Groups: 1\n"), "{stdout}");
+    assert!(stdout.contains("song (retagged).mp3"), "{stdout}");
+
+    let output = run_with_args(tmp.path(), &["--summary"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Duplicate Groups: 0\n"), "{stdout}");
+}
+
+#[test]
+fn images_similar_groups_look_alike_images() {
+    let tmp = tempdir().expect("create temp dir");
+    copy_dir_recursive(Path::new("resources"), tmp.path()).expect("copy resources");
+
+    let output = run_with_args(tmp.path(), &["--images-similar"]);
+    assert!(output.status.success());
+    let report = tmp.path().join("similar_images_report.txt");
+    let content = fs::read_to_string(report).expect("read report");
+    assert!(content.contains("Images Compared: 3 "));
+    assert!(content.contains("Groups Of Similar Images: 1\n"));
+    assert!(content.contains("3 images, up to 0 bits apart:\n"));
+    assert!(!content.contains("text_file"));
+
+    let output = run_with_args(tmp.path(), &["--images-similar", "--image-distance", "65"]);
+    assert!(!output.status.success());
+    let output = run_with_args(tmp.path(), &["--image-distance", "4"]);
+    assert!(!output.status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn videos_similar_groups_videos_with_matching_frames() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = tempdir().expect("create temp dir");
+    // Stand-ins for FFmpeg: every video is a minute long and each of its
+    // frames is its first 72 bytes.
+    let bin = tmp.path().join("bin");
+    fs::create_dir(&bin).expect("create bin dir");
+    let tools = [
+        (
+            "ffprobe",
+            "printf 'width=640\\nheight=480\\nduration=60.000000\\n'",
+        ),
+        (
+            "ffmpeg",
+            "[ \"$1\" = -version ] && exit 0\nwhile [ \"$1\" != -i ]; do shift; done\nhead -c 72 \"$2\"",
+        ),
+    ];
+    for (name, script) in tools {
+        let path = bin.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{script}\n")).expect("write tool");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).expect("make executable");
+    }
+    let data = tmp.path().join("data");
+    fs::create_dir(&data).expect("create data dir");
+    let frame: Vec<u8> = (0..72).map(|i| 200 - i).collect();
+    fs::write(data.join("clip.mp4"), &frame).expect("write video");
+    let mut reencoded = frame.clone();
+    // Brighter pixels in two rows flip two bits of every frame hash.
+    reencoded[4] = 250;
+    reencoded[13] = 250;
+    fs::write(data.join("clip.mkv"), &reencoded).expect("write video");
+    let reversed: Vec<u8> = frame.iter().rev().copied().collect();
+    fs::write(data.join("other.mp4"), reversed).expect("write video");
+    fs::write(data.join("notes.txt"), &frame).expect("write text");
+
+    let dir_arg = data.to_str().expect("valid UTF-8");
+    let path = format!("{}:/usr/bin:/bin", bin.display());
+    let output = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .env("PATH", &path)
+        .args([dir_arg, "--videos-similar"])
+        .output()
+        .expect("run binary");
+    assert!(output.status.success());
+    let report = tmp.path().join("similar_videos_report.txt");
+    let content = fs::read_to_string(report).expect("read report");
+    assert!(content.contains("Videos Compared: 3 "));
+    assert!(content.contains("Groups Of Similar Videos: 1\n"));
+    assert!(content.contains("2 videos, up to 2 bits apart:\n"));
+    assert!(content.contains("clip.mkv (640x480, 1:00, 72 bytes)"));
+    assert!(!content.contains("other.mp4"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .env("PATH", tmp.path().join("data"))
+        .args([dir_arg, "--videos-similar"])
+        .output()
+        .expect("run binary");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn text_similar_lists_drifted_copies() {
+    let tmp = tempdir().expect("create temp dir");
+    let data = tmp.path().join("data");
+    fs::create_dir(&data).expect("create data dir");
+    let settings: Vec<String> = (0..200).map(|i| format!("option_{i} = {i}")).collect();
+    fs::write(data.join("app.conf"), settings.join("\n")).expect("write config");
+    let mut drifted = settings.clone();
+    drifted[100] = "option_100 = changed".into();
+    fs::write(data.join("app.conf.bak"), drifted.join("\n")).expect("write config");
+    let other: Vec<String> = (0..200).map(|i| format!("key{i}: value{i}")).collect();
+    fs::write(data.join("other.yaml"), other.join("\n")).expect("write config");
+    fs::write(data.join("blob.bin"), b"option_1 = 1\0").expect("write binary");
+
+    let dir_arg = data.to_str().expect("valid UTF-8");
+    let output = run_with_args(tmp.path(), &[dir_arg, "--text-similar"]);
+    assert!(output.status.success());
+    let report = tmp.path().join("similar_texts_report.txt");
+    let content = fs::read_to_string(report).expect("read report");
+    assert!(content.contains("Text Files Compared: 3 "));
+    assert!(content.contains("Similar Pairs: 1\n"));
+    assert!(content.contains("99% similar:\n"));
+    assert!(content.contains("app.conf.bak ("));
+    assert!(!content.contains("other.yaml"));
+
+    let output = run_with_args(
+        tmp.path(),
+        &[dir_arg, "--text-similar", "--text-similarity", "0"],
+    );
+    assert!(!output.status.success());
+}
+
+#[test]
+fn partial_lists_files_sharing_chunks() {
+    let tmp = tempdir().expect("create temp dir");
+    let data = tmp.path().join("data");
+    fs::create_dir(&data).expect("create data dir");
+    let mut state = 0x9E37_79B9_u64;
+    let mut noise = |len: usize| -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state.to_le_bytes()[0]
+            })
+            .collect()
+    };
+    let log = noise(600 * 1024);
+    let mut grown = log.clone();
+    grown.extend(noise(400 * 1024));
+    fs::write(data.join("app.log"), &log).expect("write log");
+    fs::write(data.join("app.log.1"), &grown).expect("write log");
+    fs::write(data.join("other.bin"), noise(300 * 1024)).expect("write file");
+    fs::write(data.join("small.txt"), b"too small to chunk").expect("write file");
+
+    let dir_arg = data.to_str().expect("valid UTF-8");
+    let output = run_with_args(tmp.path(), &[dir_arg, "--partial", "--min-shared", "90"]);
+    assert!(output.status.success());
+    let report = tmp.path().join("partial_duplicates_report.txt");
+    let content = fs::read_to_string(report).expect("read report");
+    assert!(content.contains("Files Analysed: 3 "));
+    assert!(content.contains("Pairs Found: 1\n"));
+    assert!(content.contains("% of the smaller file):\n"));
+    assert!(content.contains("app.log.1 (1000.00 KB)\n"));
+    assert!(content.contains("Shareable By Chunk Deduplication: "));
+    assert!(!content.contains("other.bin"));
+
+    let output = run_with_args(tmp.path(), &[dir_arg, "--min-shared", "50"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn name_collisions_lists_diverged_copies() {
+    let tmp = tempdir().expect("create temp dir");
+    let data = tmp.path().join("data");
+    for sub in ["2023", "2024"] {
+        fs::create_dir_all(data.join(sub)).expect("create dir");
+        fs::write(data.join(sub).join("logo.png"), b"same logo").expect("write file");
+    }
+    fs::write(data.join("2023/budget.xlsx"), b"old figures").expect("write file");
+    fs::write(data.join("2024/budget.xlsx"), b"new figures").expect("write file");
+    fs::write(data.join("2024/Budget.XLSX"), b"newer figures").expect("write file");
+
+    let dir_arg = data.to_str().expect("valid UTF-8");
+    let output = run_with_args(tmp.path(), &[dir_arg, "--name-collisions"]);
+    assert!(output.status.success());
+    let report = tmp.path().join("name_collisions_report.txt");
+    let content = fs::read_to_string(&report).expect("read report");
+    assert!(content.contains("Files Examined: 5\n"));
+    assert!(content.contains("Names With Differing Contents: 1\n"));
+    assert!(content.contains("budget.xlsx (2 versions):\n"));
+    assert!(!content.contains("logo.png"));
+
+    let output = run_with_args(tmp.path(), &[dir_arg, "--name-collisions", "--ignore-case"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&report).expect("read report");
+    assert!(content.contains("(3 versions):\n"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn watch_rescans_after_changes() {
+    use std::time::{Duration, Instant};
+
+    let tmp = tempdir().expect("create temp dir");
+    let data = tmp.path().join("data");
+    fs::create_dir(&data).expect("create data dir");
+    fs::write(data.join("a.txt"), "same").expect("write file");
+    fs::write(data.join("b.txt"), "same").expect("write file");
+    let report = tmp.path().join("duplicate_file_report.txt");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args([data.to_str().expect("valid UTF-8"), "--watch"])
+        .spawn()
+        .expect("failed to run binary");
+    let wait_for = |done: &dyn Fn(&str) -> bool| {
+        let deadline = Instant::now() + Duration::from_secs(20);
+        while Instant::now() < deadline {
+            if fs::read_to_string(&report).is_ok_and(|content| done(&content)) {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        false
+    };
+
+    assert!(wait_for(&|report| report.contains("b.txt")));
+    fs::create_dir(data.join("new")).expect("create dir");
+    fs::write(data.join("new/c.txt"), "same").expect("write file");
+    assert!(wait_for(&|report| report.contains("c.txt")));
+    fs::remove_file(data.join("b.txt")).expect("remove file");
+    assert!(wait_for(
+        &|report| report.contains("c.txt") && !report.contains("b.txt")
+    ));
+
+    let pid = libc::pid_t::try_from(child.id()).expect("valid pid");
+    // SAFETY: `kill` takes no pointers.
+    assert_eq!(unsafe { libc::kill(pid, libc::SIGINT) }, 0);
+    let status = child.wait().expect("wait for watcher");
+    assert!(status.success());
+}
+
+#[test]
+fn scan_archives_matches_files_inside_archives() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+    let unique = fs::read("resources/unique_file.txt").expect("read file");
+    // A tar archive holding `docs/unique.txt`.
+    let mut header = [0u8; 512];
+    header[..15].copy_from_slice(b"docs/unique.txt");
+    header[124..135].copy_from_slice(format!("{:011o}", unique.len()).as_bytes());
+    header[156] = b'0';
+    header[148..156].fill(b' ');
+    let sum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+    header[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+    let mut tar = header.to_vec();
+    tar.extend_from_slice(&unique);
+    tar.resize(512 * 4, 0);
+    fs::write(input_dir.join("backup.tar"), tar).expect("write archive");
+
+    let dir_arg = input_dir.to_str().expect("valid UTF-8");
+    let output = run_with_args(tmp.path(), &[dir_arg]);
+    assert!(output.status.success());
+    let content =
+        fs::read_to_string(tmp.path().join("duplicate_file_report.txt")).expect("read report");
+    assert!(!content.contains("unique"));
+
+    let output = run_with_args(tmp.path(), &[dir_arg, "--scan-archives"]);
+    assert!(output.status.success());
+    let content =
+        fs::read_to_string(tmp.path().join("duplicate_file_report.txt")).expect("read report");
+    let archived = input_dir.join("backup.tar!/docs/unique.txt");
+    assert!(content.contains(&format!("{}\n", archived.display())));
+    assert!(content.contains("unique_file.txt"));
+
+    let output = run_with_args(tmp.path(), &[dir_arg, "--scan-archives", "--delete"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn decompress_matches_compressed_copies() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+    let unique = fs::read("resources/unique_file.txt").expect("read file");
+    // A gzip file holding the text in a single stored block.
+    let len = u16::try_from(unique.len()).expect("short file");
+    let mut gzip = vec![0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 0, 0xFF, 1];
+    gzip.extend_from_slice(&len.to_le_bytes());
+    gzip.extend_from_slice(&(!len).to_le_bytes());
+    gzip.extend_from_slice(&unique);
+    let crc = !unique.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    });
+    gzip.extend_from_slice(&crc.to_le_bytes());
+    gzip.extend_from_slice(&u32::from(len).to_le_bytes());
+    fs::write(input_dir.join("unique_file.txt.gz"), gzip).expect("write gzip");
+
+    let dir_arg = input_dir.to_str().expect("valid UTF-8");
+    let output = run_with_args(tmp.path(), &[dir_arg, "--decompress"]);
+    assert!(output.status.success());
+    let content =
+        fs::read_to_string(tmp.path().join("duplicate_file_report.txt")).expect("read report");
+    assert!(content.contains("unique_file.txt.gz\n"));
+    assert!(content.contains("unique_file.txt\n"));
+
+    let output = run_with_args(tmp.path(), &[dir_arg, "--decompress", "--hardlink"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn verify_backup_lists_missing_files() {
+    let tmp = tempdir().expect("create temp dir");
+    let source = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &source).expect("copy resources");
+    let backup = tmp.path().join("backup");
+    copy_dir_recursive(Path::new("resources"), &backup).expect("copy resources");
+    fs::remove_file(backup.join("unique_file.txt")).expect("remove file");
+    let (source_arg, backup_arg) = (
+        source.to_str().expect("valid UTF-8"),
+        backup.to_str().expect("valid UTF-8"),
+    );
+
+    let output = run_with_args(tmp.path(), &["verify-backup", source_arg, backup_arg]);
+    assert_eq!(output.status.code(), Some(2));
+    let missing = String::from_utf8(output.stdout).expect("UTF-8");
+    assert_eq!(
+        missing,
+        format!("{}\n", source.join("unique_file.txt").display())
+    );
+
+    fs::copy("resources/unique_file.txt", backup.join("renamed.txt")).expect("copy file");
+    let output = run_with_args(
+        tmp.path(),
+        &[
+            "verify-backup",
+            source_arg,
+            backup_arg,
+            "--cache",
+            "hashes.json",
+        ],
+    );
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(tmp.path().join("hashes.json").exists());
+}
+
+#[test]
+fn max_read_mbps_finds_same_duplicates() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[
+            input_dir.to_str().expect("valid UTF-8"),
+            "--max-read-mbps",
+            "100",
+        ],
+    );
+    assert!(output.status.success());
+    let report = tmp.path().join("duplicate_file_report.txt");
+    let content = fs::read_to_string(report).expect("read report");
+    assert!(content.contains("text_file (Copy).txt"));
+    assert!(content.contains("1_GI-td9gs8D5OKZd19mAOqA (Copy).png"));
+}
+
+#[test]
+fn non_terminal_stdout_is_quiet_but_logged() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    for args in [vec![], vec!["--quiet"]] {
+        let mut full_args = vec![input_dir.to_str().expect("valid UTF-8")];
+        full_args.extend(args);
+        let output = run_with_args(tmp.path(), &full_args);
+        assert!(output.status.success());
+        assert!(output.stdout.is_empty());
+    }
+
+    let log = fs::read_to_string(tmp.path().join("duplicate_finder.log")).expect("read log");
+    assert!(log.contains("files identified across 1 directories"));
+    assert!(tmp.path().join("duplicate_file_report.txt").exists());
+}
+
+#[test]
+fn output_dash_writes_report_to_stdout() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[input_dir.to_str().expect("valid UTF-8"), "--output", "-"],
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).expect("valid UTF-8");
+    assert!(stdout.starts_with("Duplicate File Finder Report"));
+    assert!(stdout.contains("text_file (Copy).txt"));
+    assert!(!tmp.path().join("-").exists());
+    assert!(!tmp.path().join("duplicate_file_report.txt").exists());
+}
+
+#[test]
+fn csv_format_writes_one_row_per_file() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[input_dir.to_str().expect("valid UTF-8"), "--format", "csv"],
+    );
+    assert!(output.status.success());
+
+    let csv = fs::read_to_string(tmp.path().join("duplicate_file_report.csv")).expect("read csv");
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("group_id,hash,size_bytes,path,mtime"));
+    let rows: Vec<&str> = lines.collect();
+    assert_eq!(rows.len(), 5);
+    // The PNG copies are the largest group, so they come first.
+    assert!(rows[..3]
+        .iter()
+        .all(|r| r.starts_with("1,") && r.contains(",628251,")));
+    assert!(rows[3..]
+        .iter()
+        .all(|r| r.starts_with("2,") && r.contains(",11,")));
+    // Every row ends with a modification time such as `2024-01-31 09:05:00`.
+    assert!(rows.iter().all(|r| {
+        let mtime = r.rsplit(',').next().unwrap_or_default();
+        mtime.len() == 19 && mtime.as_bytes()[10] == b' '
+    }));
+}
+
+#[test]
+fn one_file_system_finds_same_duplicates() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[
+            input_dir.to_str().expect("valid UTF-8"),
+            "--one-file-system",
+        ],
+    );
+    assert!(output.status.success());
+    let report = tmp.path().join("duplicate_file_report.txt");
+    let content = fs::read_to_string(report).expect("read report");
+    assert!(content.contains("text_file (Copy).txt"));
+    assert!(content.contains("a deeper folder"));
+}
+
+#[test]
+fn thread_limit_finds_same_duplicates() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[input_dir.to_str().expect("valid UTF-8"), "--threads", "1"],
+    );
+    assert!(output.status.success());
+    let report =
+        fs::read_to_string(tmp.path().join("duplicate_file_report.txt")).expect("read report");
+    assert!(report.contains("text_file (Copy).txt"));
+    assert!(report.contains("1_GI-td9gs8D5OKZd19mAOqA (Copy).png"));
+}
+
+#[test]
+fn cache_reuses_hashes_on_rescan() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+    let cache = tmp.path().join("hashes.json");
+    let args = [
+        input_dir.to_str().expect("valid UTF-8"),
+        "--cache",
+        cache.to_str().expect("valid UTF-8"),
+    ];
+
+    assert!(run_with_args(tmp.path(), &args).status.success());
+    let saved = fs::read_to_string(&cache).expect("read cache");
+    assert!(saved.starts_with("{\"version\":1,"));
+    assert!(saved.contains("1_GI-td9gs8D5OKZd19mAOqA (Copy).png"));
+
+    assert!(run_with_args(tmp.path(), &args).status.success());
+    let log = fs::read_to_string(tmp.path().join("duplicate_finder.log")).expect("read log");
+    assert!(log.contains("0 full hashes reused from the cache"));
+    // The two small text files are compared directly rather than hashed.
+    assert!(log.contains("3 full hashes reused from the cache"));
+}
+
+#[test]
+fn resume_reuses_checkpointed_hashes() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+    let input = input_dir.to_str().expect("valid UTF-8");
+
+    // A completed scan leaves no checkpoint behind.
+    assert!(run_with_args(tmp.path(), &[input]).status.success());
+    let checkpoint = tmp.path().join("duplicate_finder.checkpoint.json");
+    assert!(!checkpoint.exists());
+
+    // The checkpoint has the layout of a hash cache, so stand in for an
+    // interrupted scan with one.
+    let cache = tmp.path().join("hashes.json");
+    let cache_arg = cache.to_str().expect("valid UTF-8");
+    assert!(run_with_args(tmp.path(), &[input, "--cache", cache_arg])
+        .status
+        .success());
+    fs::rename(&cache, &checkpoint).expect("move cache");
+
+    let output = run_with_args(tmp.path(), &[input, "--resume"]);
+    assert!(output.status.success());
+    let log = fs::read_to_string(tmp.path().join("duplicate_finder.log")).expect("read log");
+    assert!(log.contains("3 full hashes reused from the checkpoint"));
+    assert!(!checkpoint.exists());
+    let report = fs::read_to_string(tmp.path().join("duplicate_file_report.txt")).expect("read");
+    assert!(report.contains("1_GI-td9gs8D5OKZd19mAOqA (Copy).png"));
+}
+
+#[test]
+fn delete_keeps_one_copy_per_group() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+    let dir_arg = input_dir.to_str().expect("valid UTF-8");
+    let nested_png = input_dir
+        .join("a deeper folder")
+        .join("1_GI-td9gs8D5OKZd19mAOqA.png");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[dir_arg, "--delete", "--keep", "shortest-path"],
+    );
+    assert!(output.status.success());
+    assert!(nested_png.exists());
+    let content =
+        fs::read_to_string(tmp.path().join("duplicate_file_report.txt")).expect("read report");
+    assert!(content.contains("Deleted Files (dry run, no files changed):"));
+
+    let output = run_with_args(
+        tmp.path(),
+        &[dir_arg, "--delete", "--keep", "shortest-path", "--confirm"],
+    );
+    assert!(output.status.success());
+    assert!(!nested_png.exists());
+    let remaining_txt = ["text_file.txt", "text_file (Copy).txt"]
+        .iter()
+        .filter(|name| input_dir.join(name).exists())
+        .count();
+    assert_eq!(remaining_txt, 1);
+    assert!(input_dir.join("unique_file.txt").exists());
+
+    let conflict = run_with_args(tmp.path(), &[dir_arg, "--delete", "--hardlink"]);
+    assert!(!conflict.status.success());
+}
+
+#[test]
+fn restore_undoes_last_delete() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+    let dir_arg = input_dir.to_str().expect("valid UTF-8");
+    let nested_png = input_dir
+        .join("a deeper folder")
+        .join("1_GI-td9gs8D5OKZd19mAOqA.png");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[dir_arg, "--delete", "--keep", "shortest-path", "--confirm"],
+    );
+    assert!(output.status.success());
+    assert!(!nested_png.exists());
+    assert!(tmp.path().join("duplicate_finder_undo.jsonl").exists());
+
+    let preview = run_with_args(tmp.path(), &["restore"]);
+    assert!(preview.status.success());
+    assert!(String::from_utf8_lossy(&preview.stdout).contains("3 files would be restored"));
+    assert!(!nested_png.exists());
+
+    let output = run_with_args(tmp.path(), &["restore", "--confirm"]);
+    assert!(output.status.success());
+    assert!(nested_png.exists());
+    assert!(input_dir.join("text_file.txt").exists());
+    assert!(input_dir.join("text_file (Copy).txt").exists());
+
+    let again = run_with_args(tmp.path(), &["restore", "--confirm"]);
+    assert!(String::from_utf8_lossy(&again.stdout).contains("Nothing to restore."));
+}
+
+#[test]
+fn dirs_flag_reports_duplicate_trees() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir.join("original"))
+        .expect("copy resources");
+    copy_dir_recursive(Path::new("resources"), &input_dir.join("backup")).expect("copy resources");
+    let dir_arg = input_dir.to_str().expect("valid UTF-8");
+
+    let output = run_with_args(tmp.path(), &[dir_arg, "--dirs"]);
+    assert!(output.status.success());
+    let content =
+        fs::read_to_string(tmp.path().join("duplicate_file_report.txt")).expect("read report");
+    let section = content
+        .split("Duplicate Directories:\n")
+        .nth(1)
+        .expect("directory section");
+    let first_group: Vec<&str> = section.lines().take(3).collect();
+    assert!(first_group[0].starts_with("Size: "));
+    assert!(first_group[1].ends_with("backup"));
+    assert!(first_group[2].ends_with("original"));
+}
+
+#[test]
+fn print0_lists_all_but_one_copy() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+    let dir_arg = input_dir.to_str().expect("valid UTF-8");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[dir_arg, "--print0", "--keep", "shortest-path"],
+    );
+    assert!(output.status.success());
+    assert!(!tmp.path().join("duplicate_file_report.txt").exists());
+    assert_eq!(output.stdout.last(), Some(&0));
+    let paths: Vec<&[u8]> = output.stdout[..output.stdout.len() - 1]
+        .split(|b| *b == 0)
+        .collect();
+    // One of the two text files and two of the three images.
+    assert_eq!(paths.len(), 3);
+    let nested = input_dir.join("a deeper folder");
+    assert!(paths
+        .iter()
+        .any(|p| p.starts_with(nested.to_str().expect("valid UTF-8").as_bytes())));
+
+    let conflict = run_with_args(tmp.path(), &[dir_arg, "--print0", "--delete"]);
+    assert!(!conflict.status.success());
+}
+
+#[test]
+fn progress_json_writes_stage_events_to_stderr() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[input_dir.to_str().expect("valid UTF-8"), "--progress-json"],
+    );
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).expect("valid UTF-8");
+    let events: Vec<&str> = stderr.lines().filter(|l| l.starts_with('{')).collect();
+    assert!(events.contains(&r#"{"stage":"size","done":0,"total":6}"#));
+    assert!(events.contains(&r#"{"stage":"size","done":6,"total":6}"#));
+    assert!(events
+        .iter()
+        .any(|e| e.starts_with(r#"{"stage":"quick_hash","#)));
+    assert!(events.contains(&r#"{"stage":"full_hash","done":1884775,"total":1884775}"#));
+    assert_eq!(events.last(), Some(&r#"{"event":"complete","groups":2}"#));
+}
+
+#[test]
+fn html_format_writes_self_contained_page() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[input_dir.to_str().expect("valid UTF-8"), "--format", "html"],
+    );
+    assert!(output.status.success());
+    let html =
+        fs::read_to_string(tmp.path().join("duplicate_file_report.html")).expect("read report");
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<li>Duplicate groups: 2</li>"));
+    assert!(html.contains("text_file%20%28Copy%29.txt\">"));
+    assert_eq!(html.matches("<a href=\"file://").count(), 5);
+    // The three PNG copies are previewed.
+    assert_eq!(html.matches("<img src=\"file://").count(), 3);
+}
+
+#[test]
+fn json_format_writes_groups_and_files() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[input_dir.to_str().expect("valid UTF-8"), "--format", "json"],
+    );
+    assert!(output.status.success());
+    let json =
+        fs::read_to_string(tmp.path().join("duplicate_file_report.json")).expect("read report");
+    assert!(json.starts_with("{\n  \"version\": 1,"));
+    assert!(json.contains("\"total_groups\": 2,"));
+    assert!(json.contains("\"size\": 628251,"));
+    assert!(json.contains("text_file (Copy).txt\", \"modified\": "));
+    assert_eq!(json.matches("{\"path\": ").count(), 5);
+    assert!(json.trim_end().ends_with("\"errors\": []\n}"));
+}
+
+#[test]
+fn closed_stdout_pipe_is_not_an_error() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args([input_dir.to_str().expect("valid UTF-8"), "--output", "-"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("failed to run binary");
+    // Close the read end before the report is written, as `| head -0` would.
+    drop(child.stdout.take());
+    let output = child.wait_with_output().expect("wait for binary");
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn sqlite_format_writes_database() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[
+            input_dir.to_str().expect("valid UTF-8"),
+            "--format",
+            "sqlite",
+        ],
+    );
+    assert!(output.status.success());
+    let database =
+        fs::read(tmp.path().join("duplicate_file_report.sqlite")).expect("read database");
+    assert!(database.starts_with(b"SQLite format 3\0"));
+    assert_eq!(database.len() % 4096, 0);
+    let text = String::from_utf8_lossy(&database);
+    assert!(text.contains("CREATE TABLE duplicate_groups("));
+    assert!(text.contains("text_file (Copy).txt"));
+
+    let stdout = run_with_args(
+        tmp.path(),
+        &[
+            input_dir.to_str().expect("valid UTF-8"),
+            "--format",
+            "sqlite",
+            "--output",
+            "-",
+        ],
+    );
+    assert!(stdout.status.success());
+    assert_eq!(stdout.stdout.len(), database.len());
+}
+
+#[test]
+fn fdupes_and_rmlint_formats() {
+    let tmp = tempdir().expect("create temp dir");
+    let input_dir = tmp.path().join("data");
+    copy_dir_recursive(Path::new("resources"), &input_dir).expect("copy resources");
+    let dir_arg = input_dir.to_str().expect("valid UTF-8");
+
+    let output = run_with_args(
+        tmp.path(),
+        &[dir_arg, "--format", "fdupes", "--output", "-"],
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).expect("valid UTF-8");
+    let groups: Vec<&str> = stdout.split_terminator("\n\n").collect();
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].lines().count(), 3);
+    assert!(groups[1].ends_with("text_file.txt"));
+
+    let output = run_with_args(tmp.path(), &[dir_arg, "--format", "rmlint"]);
+    assert!(output.status.success());
+    let json = fs::read_to_string(tmp.path().join("duplicate_file_report_rmlint.json"))
+        .expect("read report");
+    assert!(json.starts_with("[\n{\"description\": \"rmlint json-dump of lint files\""));
+    assert_eq!(json.matches("\"type\": \"duplicate_file\"").count(), 5);
+    assert_eq!(json.matches("\"is_original\": true").count(), 2);
+    assert!(json.contains("\"duplicates\": 3, \"duplicate_sets\": 2"));
+}
+
+#[test]
+fn empty_and_unique_inputs_skip_hashing() {
+    let tmp = tempdir().expect("create temp dir");
+    let empty_dir = tmp.path().join("empty");
+    fs::create_dir(&empty_dir).expect("create dir");
+    let output = run_with_args(
+        tmp.path(),
+        &[empty_dir.to_str().expect("valid UTF-8"), "--progress-json"],
+    );
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).expect("valid UTF-8");
+    assert_eq!(stderr.trim_end(), r#"{"event":"complete","groups":0}"#);
+
+    let unique_dir = tmp.path().join("unique");
+    fs::create_dir(&unique_dir).expect("create dir");
+    fs::write(unique_dir.join("a.txt"), "a").expect("write file");
+    fs::write(unique_dir.join("b.txt"), "bb").expect("write file");
+    let output = run_with_args(
+        tmp.path(),
+        &[unique_dir.to_str().expect("valid UTF-8"), "--progress-json"],
+    );
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).expect("valid UTF-8");
+    assert!(stderr.contains(r#"{"stage":"size","done":2,"total":2}"#));
+    assert!(!stderr.contains("quick_hash"));
+    assert!(!stderr.contains("full_hash"));
+    assert!(stderr.ends_with("{\"event\":\"complete\",\"groups\":0}\n"));
+    assert!(!tmp.path().join("duplicate_file_report.txt").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn notify_webhook_posts_summary() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = tempdir().expect("create temp dir");
+    // A stand-in for curl that records its arguments and the body posted.
+    let bin = tmp.path().join("bin");
+    fs::create_dir(&bin).expect("create bin dir");
+    let curl = bin.join("curl");
+    let log = tmp.path().join("posted");
+    let script = format!(
+        "#!/bin/sh\necho \"$@\" > '{0}'\ncat >> '{0}'\n",
+        log.display()
+    );
+    fs::write(&curl, script).expect("write tool");
+    fs::set_permissions(&curl, fs::Permissions::from_mode(0o755)).expect("make executable");
+    let data = tmp.path().join("data");
+    fs::create_dir(&data).expect("create data dir");
+    fs::write(data.join("a.txt"), "same").expect("write file");
+    fs::write(data.join("b.txt"), "same").expect("write file");
+
+    let path = format!("{}:/usr/bin:/bin", bin.display());
+    let output = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .env("PATH", &path)
+        .args(["data", "--notify-webhook", "https://hooks.example/T0"])
+        .output()
+        .expect("run binary");
+    assert!(output.status.success());
+    let posted = fs::read_to_string(&log).expect("read posted request");
+    let (args, body) = posted.split_once('\n').expect("arguments and body");
+    assert!(args.ends_with("-- https://hooks.example/T0"));
+    assert!(body.contains("\"duplicate_groups\":1,\"duplicate_files\":2,\"reclaimable_bytes\":4,"));
+    let report = tmp.path().join("duplicate_file_report.txt");
+    assert!(body.ends_with(&format!("\"report\":\"{}\"}}", report.display())));
+
+    // Without curl the scan still succeeds, with a warning.
+    fs::remove_file(&curl).expect("remove tool");
+    let output = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .env("PATH", &bin)
+        .args(["data", "--notify-webhook", "https://hooks.example/T0"])
+        .output()
+        .expect("run binary");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Warning: could not notify https://hooks.example/T0"));
+}
+
+#[test]
+fn email_to_checks_smtp_settings_before_scanning() {
+    let tmp = tempdir().expect("create temp dir");
+    fs::write(tmp.path().join("a.txt"), "same").expect("write file");
+    fs::write(tmp.path().join("b.txt"), "same").expect("write file");
+    let config = tmp.path().join("smtp.conf");
+    fs::write(&config, "url = smtp://mail.example:587\n").expect("write config");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .env_remove("DFF_SMTP_FROM")
+        .args(["--email-to", "me@example.com", "--smtp-config", "smtp.conf"])
+        .output()
+        .expect("run binary");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Error: cannot email the report: no sender set"));
+    assert!(!tmp.path().join("duplicate_file_report.txt").exists());
+}
+
+#[test]
+fn schedule_rejects_times_that_never_come() {
+    let tmp = tempdir().expect("create temp dir");
+    fs::write(tmp.path().join("a.txt"), "same").expect("write file");
+    fs::write(tmp.path().join("b.txt"), "same").expect("write file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args(["--schedule", "0 4 * * 8"])
+        .output()
+        .expect("run binary");
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("weekday: 8 is outside 0-7"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args(["--schedule", "0 4 31 4 *"])
+        .output()
+        .expect("run binary");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Error: the schedule never runs"));
+    assert!(!tmp.path().join("duplicate_file_report.txt").exists());
+}
+
+#[test]
+fn history_lists_completed_scans() {
+    let tmp = tempdir().expect("create temp dir");
+    let data = tmp.path().join("data");
+    fs::create_dir(&data).expect("create dir");
+    fs::write(data.join("a.txt"), "same").expect("write file");
+    fs::write(data.join("b.txt"), "same").expect("write file");
+
+    // No history is kept unless asked for.
+    let status = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args(["data", "--quiet"])
+        .status()
+        .expect("run binary");
+    assert!(status.success());
+    assert!(!fs::read_dir(tmp.path())
+        .expect("list dir")
+        .any(|entry| entry.expect("read entry").path().extension() == Some("jsonl".as_ref())));
+
+    for _ in 0..2 {
+        let status = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+            .current_dir(tmp.path())
+            .args(["data", "--quiet", "--history", "history.jsonl"])
+            .status()
+            .expect("run binary");
+        assert!(status.success());
+        fs::write(data.join("c.txt"), "same").expect("write file");
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args(["history", "--history", "history.jsonl"])
+        .output()
+        .expect("run binary");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("data (2 runs)"), "{stdout}");
+    assert!(stdout.contains("+4 bytes"), "{stdout}");
+}
+
+#[test]
+fn diff_compares_a_saved_report_with_a_new_scan() {
+    let tmp = tempdir().expect("create temp dir");
+    let data = tmp.path().join("data");
+    fs::create_dir(&data).expect("create dir");
+    fs::write(data.join("a.txt"), "same").expect("write file");
+    fs::write(data.join("b.txt"), "same").expect("write file");
+    fs::write(data.join("c.txt"), "other").expect("write file");
+    fs::write(data.join("d.txt"), "other").expect("write file");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args(["data", "--format", "json", "-o", "old.json", "--quiet"])
+        .status()
+        .expect("run binary");
+    assert!(status.success());
+    fs::remove_file(data.join("d.txt")).expect("remove file");
+    fs::write(data.join("e.txt"), "same").expect("write file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args(["diff", "old.json"])
+        .output()
+        .expect("run binary");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("New duplicate groups: 0\n"), "{stdout}");
+    assert!(stdout.contains("Resolved groups: 1\n"), "{stdout}");
+    assert!(stdout.contains("Changed groups: 1\n"), "{stdout}");
+    assert!(stdout.contains(&format!(
+        "    + {}\n",
+        Path::new("data").join("e.txt").display()
+    )));
+    assert!(
+        stdout.contains("Reclaimable space: 9 bytes -> 8 bytes (-1 bytes)"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn diff_scans_again_with_the_options_given_after_a_double_dash() {
+    let tmp = tempdir().expect("create temp dir");
+    let data = tmp.path().join("data");
+    fs::create_dir(&data).expect("create dir");
+    fs::write(data.join("a.txt"), "same").expect("write file");
+    fs::write(data.join("b.txt"), "same").expect("write file");
+    fs::write(data.join("c.tmp"), "same").expect("write file");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args([
+            "data",
+            "--exclude",
+            "*.tmp",
+            "--format",
+            "json",
+            "-o",
+            "old.json",
+            "--quiet",
+        ])
+        .status()
+        .expect("run binary");
+    assert!(status.success());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args(["diff", "old.json", "--", "--exclude", "*.tmp"])
+        .output()
+        .expect("run binary");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Changed groups: 0\n"), "{stdout}");
+    assert!(
+        stdout.contains("Reclaimable space: 4 bytes -> 4 bytes (no change)"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn index_stands_in_for_a_directory_of_another_machine() {
+    let tmp = tempdir().expect("create temp dir");
+    let (here, there) = (tmp.path().join("here"), tmp.path().join("there"));
+    fs::create_dir(&here).expect("create dir");
+    fs::create_dir(&there).expect("create dir");
+    fs::write(here.join("a.txt"), "copied").expect("write file");
+    fs::write(here.join("b.txt"), "kept apart").expect("write file");
+    fs::write(there.join("copy of a.txt"), "copied").expect("write file");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args(["index", "create", "there", "-o", "there.dff"])
+        .status()
+        .expect("run binary");
+    assert!(status.success());
+    fs::remove_dir_all(&there).expect("remove dir");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args(["-d", "here", "there.dff", "-o", "-"])
+        .output()
+        .expect("run binary");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(&Path::new("here").join("a.txt").display().to_string()));
+    // Files of the index are listed by host and their path there.
+    let copy = format!(":{}", there.join("copy of a.txt").display());
+    assert!(stdout.contains(&copy), "{stdout}");
+    assert!(!stdout.contains("b.txt"), "{stdout}");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args(["here", "--against", "there.dff", "-o", "comparison.txt"])
+        .status()
+        .expect("run binary");
+    assert!(status.success());
+    let comparison = fs::read_to_string(tmp.path().join("comparison.txt")).expect("read report");
+    let missing = comparison
+        .split("copy of a.txt")
+        .next()
+        .expect("a copy listed");
+    assert!(missing.contains("b.txt"), "{comparison}");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args(["here", "there.dff", "--delete"])
+        .output()
+        .expect("run binary");
+    assert!(!output.status.success());
+
+    // The error names the option as it is spelt on the command line.
+    let output = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args(["-d", "here", "there.dff", "--dirs"])
+        .output()
+        .expect("run binary");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("--dirs cannot be used with an index"),
+        "{stderr}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn sftp_directories_are_indexed_by_the_remote_machine() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = tempdir().expect("create temp dir");
+    let (here, there) = (tmp.path().join("here"), tmp.path().join("there"));
+    fs::create_dir(&here).expect("create dir");
+    fs::create_dir(&there).expect("create dir");
+    fs::write(here.join("a.txt"), "copied").expect("write file");
+    fs::write(there.join("copy of a.txt"), "copied").expect("write file");
+    // Stands in for ssh, running the remote command on this machine.
+    let bin = tmp.path().join("bin");
+    fs::create_dir(&bin).expect("create dir");
+    let ssh = bin.join("ssh");
+    fs::write(&ssh, "#!/bin/sh\nshift 2\nexec sh -c \"$1\"\n").expect("write script");
+    fs::set_permissions(&ssh, fs::Permissions::from_mode(0o755)).expect("make executable");
+    let path = format!(
+        "{}:{}",
+        bin.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let remote = format!("sftp://nas{}", there.display());
+    let output = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .env("PATH", path)
+        .args(["-d", "here", &remote, "-o", "-", "--remote-command"])
+        .arg(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .output()
+        .expect("run binary");
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(&format!("nas:{}", there.join("copy of a.txt").display())),
+        "{stdout}"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args(["-d", "here", "sftp://nas"])
+        .output()
+        .expect("run binary");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no path"));
+}
+
+#[cfg(unix)]
+#[test]
+fn s3_objects_are_downloaded_only_when_they_may_be_duplicates() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = tempdir().expect("create temp dir");
+    let here = tmp.path().join("here");
+    fs::create_dir(&here).expect("create dir");
+    fs::write(here.join("a.txt"), "copied").expect("write file");
+    // Stands in for curl, answering the listing and the one download
+    // expected; the object of a size nothing else has must not be asked for.
+    let bin = tmp.path().join("bin");
+    fs::create_dir(&bin).expect("create dir");
+    let curl = bin.join("curl");
+    fs::write(
+        &curl,
+        "#!/bin/sh\n\
+         url=$(sed -n 's/^url = \"\\(.*\\)\"$/\\1/p')\n\
+         case \"$url\" in\n\
+         http://s3.test/backups?list-type=2\\&prefix=photos%2F) printf '%s' \
+         '<ListBucketResult><IsTruncated>false</IsTruncated>\
+         <Contents><Key>photos/a copy.jpg</Key><Size>6</Size><ETag>\"e1\"</ETag></Contents>\
+         <Contents><Key>photos/big.bin</Key><Size>100</Size><ETag>\"e2\"</ETag></Contents>\
+         </ListBucketResult>' ;;\n\
+         http://s3.test/backups/photos/a%20copy.jpg) printf copied ;;\n\
+         *) echo \"curl: (22) unexpected $url\" >&2; exit 22 ;;\n\
+         esac\n",
+    )
+    .expect("write script");
+    fs::set_permissions(&curl, fs::Permissions::from_mode(0o755)).expect("make executable");
+    let path = format!(
+        "{}:{}",
+        bin.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let run = |with_credentials: bool| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"));
+        command
+            .current_dir(tmp.path())
+            .env("PATH", &path)
+            .env("AWS_ENDPOINT_URL", "http://s3.test")
+            .env_remove("AWS_ENDPOINT_URL_S3")
+            .env_remove("AWS_SESSION_TOKEN")
+            .args(["-d", "here", "s3://backups/photos/", "-o", "-"]);
+        if with_credentials {
+            command
+                .env("AWS_ACCESS_KEY_ID", "key")
+                .env("AWS_SECRET_ACCESS_KEY", "secret");
+        } else {
+            command.env_remove("AWS_ACCESS_KEY_ID");
+        }
+        command.output().expect("run binary")
+    };
+    let output = run(true);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("s3://backups/photos/a copy.jpg"),
+        "{stdout}"
+    );
+    assert!(!stdout.contains("big.bin"), "{stdout}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("skipped"), "{stderr}");
+
+    let output = run(false);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("AWS_ACCESS_KEY_ID"));
+}
+
+#[test]
+fn coordinator_merges_the_indexes_agents_send() {
+    let tmp = tempdir().expect("create temp dir");
+    let (first, second) = (tmp.path().join("first"), tmp.path().join("second"));
+    fs::create_dir(&first).expect("create dir");
+    fs::create_dir(&second).expect("create dir");
+    fs::write(first.join("a.txt"), "shared").expect("write file");
+    fs::write(second.join("b.txt"), "shared").expect("write file");
+    fs::write(second.join("c.txt"), "alone").expect("write file");
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("free port")
+        .to_string();
+
+    let coordinator = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+        .current_dir(tmp.path())
+        .args(["--coordinate", &addr, "--agents", "2", "-o", "-"])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("run coordinator");
+    for dir in [&first, &second] {
+        // The coordinator may not be listening yet.
+        let sent = (0..50).any(|_| {
+            let status = Command::new(env!("CARGO_BIN_EXE_duplicate_file_finder"))
+                .args(["agent", "--coordinator", &addr])
+                .arg(dir)
+                .stderr(std::process::Stdio::null())
+                .status()
+                .expect("run agent");
+            if !status.success() {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+            status.success()
+        });
+        assert!(sent, "agent for {} failed", dir.display());
+    }
+    let output = coordinator
+        .wait_with_output()
+        .expect("wait for coordinator");
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(&format!(":{}", first.join("a.txt").display())),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains(&format!(":{}", second.join("b.txt").display())),
+        "{stdout}"
+    );
+    assert!(!stdout.contains("c.txt"), "{stdout}");
+}